@@ -0,0 +1,2158 @@
+//! HLS and DASH playback client
+//!
+//! Both manifest flavours are parsed into one model: a set of renditions
+//! (variant streams / Representations) that each resolve to a list of
+//! segments. `AdaptiveClient` walks that list in order, fetching segments
+//! over HTTP, decrypting AES-128 HLS segments, reloading live playlists and
+//! MPDs as they grow, and picking the rendition for every video segment from
+//! measured throughput. A separate audio rendition (HLS `EXT-X-MEDIA`, DASH
+//! audio AdaptationSet) is fetched alongside and interleaved by media time.
+//!
+//! Segments come out as `SegmentData`; `SegmentData::packets` runs them
+//! through `TsDemuxer` or, with their init section, `Mp4Demuxer`.
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use quick_xml::events::{BytesStart, Event};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::time::{Duration, Instant};
+
+use crate::demuxer::UniversalPacket;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+/// Largest playlist/segment body we accept
+const MAX_BODY_SIZE: u64 = 256 * 1024 * 1024;
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    /// Extra request headers (Referer, Authorization, cookies, ...)
+    pub headers: Vec<(String, String)>,
+    pub timeout_secs: u64,
+    /// Retries after a connection error or 5xx
+    pub retries: u32,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("SLAIN/{}", crate::VERSION),
+            headers: Vec::new(),
+            timeout_secs: 10,
+            retries: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A fetched body and the URL it finally came from (after redirects), which
+/// relative URIs inside it resolve against
+pub struct HttpResponse {
+    pub url: String,
+    pub data: Vec<u8>,
+}
+
+pub struct HttpClient {
+    agent: ureq::Agent,
+    config: HttpConfig,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+            .user_agent(&config.user_agent)
+            .build();
+        Self { agent, config }
+    }
+
+    pub fn get(&self, url: &str, range: Option<ByteRange>) -> Result<HttpResponse, String> {
+        let mut attempt = 0;
+        loop {
+            match self.try_get(url, range) {
+                Ok(response) => return Ok(response),
+                Err((message, retryable)) => {
+                    if !retryable || attempt >= self.config.retries {
+                        return Err(message);
+                    }
+                    attempt += 1;
+                    tracing::debug!("Retrying {} ({}): {}", url, attempt, message);
+                    std::thread::sleep(Duration::from_millis(250 * attempt as u64));
+                }
+            }
+        }
+    }
+
+    pub fn get_text(&self, url: &str) -> Result<HttpResponse, String> {
+        self.get(url, None)
+    }
+
+    fn try_get(&self, url: &str, range: Option<ByteRange>) -> Result<HttpResponse, (String, bool)> {
+        let mut request = self.agent.get(url);
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+        if let Some(range) = range {
+            let last = range.offset + range.length.max(1) - 1;
+            request = request.set("Range", &format!("bytes={}-{}", range.offset, last));
+        }
+
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) => {
+                return Err((format!("HTTP {} for {}", code, url), code >= 500));
+            }
+            Err(e) => return Err((format!("Request failed for {}: {}", url, e), true)),
+        };
+        let final_url = response.get_url().to_string();
+        let partial = response.status() == 206;
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_BODY_SIZE)
+            .read_to_end(&mut data)
+            .map_err(|e| (format!("Read failed for {}: {}", url, e), true))?;
+
+        // Server ignored the Range header: cut the range out ourselves
+        if let (Some(range), false) = (range, partial) {
+            let start = (range.offset as usize).min(data.len());
+            let end = (start + range.length as usize).min(data.len());
+            data = data[start..end].to_vec();
+        }
+        Ok(HttpResponse {
+            url: final_url,
+            data,
+        })
+    }
+}
+
+fn resolve_url(base: &str, reference: &str) -> String {
+    url::Url::parse(base)
+        .and_then(|base| base.join(reference))
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| reference.to_string())
+}
+
+// ============================================================================
+// Manifest Model
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ManifestKind {
+    Hls,
+    Dash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SegmentFormat {
+    /// MPEG-TS segments
+    Ts,
+    /// Fragmented MP4 (CMAF) segments behind an init section
+    Fmp4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MediaKind {
+    /// Video (or muxed audio+video)
+    Main,
+    /// Separate audio rendition
+    Audio,
+}
+
+/// One selectable quality level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rendition {
+    pub id: String,
+    /// Peak bits per second as advertised
+    pub bandwidth: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f64>,
+    pub language: Option<String>,
+    /// HLS media playlist URL (the MPD URL for DASH)
+    pub uri: String,
+    /// HLS `AUDIO` group this variant plays with
+    pub audio_group: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentKey {
+    pub uri: String,
+    /// Explicit IV; otherwise the segment's media sequence number
+    pub iv: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InitSection {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+    pub key: Option<SegmentKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub uri: String,
+    /// Seconds
+    pub duration: f64,
+    /// HLS media sequence number / DASH segment number
+    pub sequence: u64,
+    pub byte_range: Option<ByteRange>,
+    pub key: Option<SegmentKey>,
+    pub init: Option<InitSection>,
+    pub discontinuity: bool,
+}
+
+/// Segments of one rendition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaPlaylist {
+    /// Seconds; how often a live playlist changes
+    pub target_duration: f64,
+    pub segments: Vec<Segment>,
+    /// No more segments will be added (VOD, or a finished live event)
+    pub ended: bool,
+    pub format: SegmentFormat,
+}
+
+// ============================================================================
+// HLS Parsing
+// ============================================================================
+
+/// Split an attribute list (`A=1,B="x,y",C=0x10`), unquoting values
+fn parse_attributes(list: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = list.trim();
+    while !rest.is_empty() {
+        let Some(eq) = rest.find('=') else { break };
+        let name = rest[..eq].trim().to_ascii_uppercase();
+        rest = &rest[eq + 1..];
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let value = &quoted[..end];
+            rest = quoted.get(end + 1..).unwrap_or("");
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = &rest[..end];
+            rest = &rest[end..];
+            value.trim()
+        };
+        attrs.insert(name, value.to_string());
+        rest = rest
+            .trim_start()
+            .strip_prefix(',')
+            .unwrap_or(rest)
+            .trim_start();
+    }
+    attrs
+}
+
+/// `length[@offset]`; without an offset the range follows the previous one
+fn parse_byte_range(value: &str, next_offset: u64) -> Option<ByteRange> {
+    let (length, offset) = match value.split_once('@') {
+        Some((length, offset)) => (length, offset.trim().parse().ok()?),
+        None => (value, next_offset),
+    };
+    Some(ByteRange {
+        offset,
+        length: length.trim().parse().ok()?,
+    })
+}
+
+fn parse_iv(value: &str) -> Option<[u8; 16]> {
+    let hex = value.trim_start_matches("0x").trim_start_matches("0X");
+    if hex.is_empty() || hex.len() > 32 {
+        return None;
+    }
+    let padded = format!("{:0>32}", hex);
+    let mut iv = [0u8; 16];
+    for (i, byte) in iv.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&padded[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(iv)
+}
+
+fn is_fmp4_uri(uri: &str) -> bool {
+    let path = uri
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(uri)
+        .to_ascii_lowercase();
+    [".m4s", ".mp4", ".m4v", ".m4a", ".cmfv", ".cmfa"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+/// Whether an HTTP(S) URL is an HLS playlist or DASH MPD, going by its
+/// extension or the content type it was announced with
+pub fn is_manifest_url(url: &str, content_type: Option<&str>) -> bool {
+    let url = url.to_ascii_lowercase();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return false;
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(&url);
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+    path.ends_with(".m3u8")
+        || path.ends_with(".mpd")
+        || content_type.contains("mpegurl")
+        || content_type.contains("dash+xml")
+}
+
+/// Whether an M3U8 is a master playlist (lists variants, not segments)
+pub fn is_hls_master(text: &str) -> bool {
+    text.contains("#EXT-X-STREAM-INF")
+}
+
+/// Variant streams and alternative audio renditions of a master playlist
+pub fn parse_hls_master(
+    text: &str,
+    base_url: &str,
+) -> Result<(Vec<Rendition>, Vec<Rendition>), String> {
+    if !text.trim_start().starts_with("#EXTM3U") {
+        return Err("Not an HLS playlist: missing #EXTM3U".to_string());
+    }
+    let mut variants = Vec::new();
+    let mut audio = Vec::new();
+    let mut pending: Option<HashMap<String, String>> = None;
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(list));
+        } else if let Some(list) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = parse_attributes(list);
+            if attrs.get("TYPE").map(String::as_str) != Some("AUDIO") {
+                continue;
+            }
+            // Audio without a URI is muxed into the variant streams
+            let Some(uri) = attrs.get("URI") else {
+                continue;
+            };
+            audio.push(Rendition {
+                id: attrs.get("NAME").cloned().unwrap_or_default(),
+                bandwidth: 0,
+                width: None,
+                height: None,
+                codecs: None,
+                frame_rate: None,
+                language: attrs.get("LANGUAGE").cloned(),
+                uri: resolve_url(base_url, uri),
+                audio_group: attrs.get("GROUP-ID").cloned(),
+            });
+            if attrs.get("DEFAULT").map(String::as_str) == Some("YES") {
+                // Defaults first so they win when nothing else is asked for
+                let default = audio.pop().unwrap();
+                let at = audio
+                    .iter()
+                    .position(|r| r.audio_group == default.audio_group)
+                    .unwrap_or(audio.len());
+                audio.insert(at, default);
+            }
+        } else if !line.starts_with('#') {
+            let Some(attrs) = pending.take() else {
+                continue;
+            };
+            let resolution = attrs.get("RESOLUTION").and_then(|r| {
+                let (w, h) = r.split_once(['x', 'X'])?;
+                Some((w.parse().ok()?, h.parse().ok()?))
+            });
+            variants.push(Rendition {
+                id: variants.len().to_string(),
+                bandwidth: attrs
+                    .get("BANDWIDTH")
+                    .and_then(|b| b.parse().ok())
+                    .ok_or_else(|| format!("Variant {} has no BANDWIDTH", line))?,
+                width: resolution.map(|(w, _)| w),
+                height: resolution.map(|(_, h)| h),
+                codecs: attrs.get("CODECS").cloned(),
+                frame_rate: attrs.get("FRAME-RATE").and_then(|f| f.parse().ok()),
+                language: None,
+                uri: resolve_url(base_url, line),
+                audio_group: attrs.get("AUDIO").cloned(),
+            });
+        }
+    }
+
+    if variants.is_empty() {
+        return Err("HLS master playlist has no variant streams".to_string());
+    }
+    Ok((variants, audio))
+}
+
+/// Segments of a media playlist
+pub fn parse_hls_media(text: &str, base_url: &str) -> Result<MediaPlaylist, String> {
+    if !text.trim_start().starts_with("#EXTM3U") {
+        return Err("Not an HLS playlist: missing #EXTM3U".to_string());
+    }
+    let mut playlist = MediaPlaylist {
+        target_duration: 10.0,
+        segments: Vec::new(),
+        ended: false,
+        format: SegmentFormat::Ts,
+    };
+    let mut sequence = 0u64;
+    let mut duration = None;
+    let mut byte_range = None;
+    let mut discontinuity = false;
+    let mut key: Option<SegmentKey> = None;
+    let mut init: Option<InitSection> = None;
+    // Where a BYTERANGE without offset continues, per URI
+    let mut range_ends: HashMap<String, u64> = HashMap::new();
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            playlist.target_duration = value.trim().parse().unwrap_or(10.0);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value.trim().parse().unwrap_or(0);
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let value = value.split(',').next().unwrap_or("").trim();
+            duration = Some(
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("Bad EXTINF: {}", line))?,
+            );
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            byte_range = Some(value.to_string());
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if line == "#EXT-X-ENDLIST" {
+            playlist.ended = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PLAYLIST-TYPE:") {
+            if value.trim() == "VOD" {
+                playlist.ended = true;
+            }
+        } else if let Some(list) = line.strip_prefix("#EXT-X-KEY:") {
+            let attrs = parse_attributes(list);
+            key = match attrs.get("METHOD").map(String::as_str) {
+                Some("NONE") => None,
+                Some("AES-128") => Some(SegmentKey {
+                    uri: resolve_url(base_url, attrs.get("URI").ok_or("AES-128 key without URI")?),
+                    iv: attrs.get("IV").and_then(|iv| parse_iv(iv)),
+                }),
+                Some(method) => return Err(format!("Unsupported HLS encryption: {}", method)),
+                None => return Err("EXT-X-KEY without METHOD".to_string()),
+            };
+        } else if let Some(list) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = parse_attributes(list);
+            init = Some(InitSection {
+                uri: resolve_url(base_url, attrs.get("URI").ok_or("EXT-X-MAP without URI")?),
+                byte_range: attrs.get("BYTERANGE").and_then(|r| parse_byte_range(r, 0)),
+                key: key.clone(),
+            });
+            playlist.format = SegmentFormat::Fmp4;
+        } else if !line.starts_with('#') {
+            let uri = resolve_url(base_url, line);
+            let byte_range = byte_range.take().and_then(|r| {
+                let next = range_ends.get(&uri).copied().unwrap_or(0);
+                parse_byte_range(&r, next)
+            });
+            if let Some(range) = byte_range {
+                range_ends.insert(uri.clone(), range.offset + range.length);
+            }
+            if is_fmp4_uri(&uri) {
+                playlist.format = SegmentFormat::Fmp4;
+            }
+            playlist.segments.push(Segment {
+                uri,
+                duration: duration
+                    .take()
+                    .ok_or_else(|| format!("{} has no EXTINF", line))?,
+                sequence,
+                byte_range,
+                key: key.clone(),
+                init: init.clone(),
+                discontinuity: std::mem::take(&mut discontinuity),
+            });
+            sequence += 1;
+        }
+    }
+    Ok(playlist)
+}
+
+// ============================================================================
+// DASH Parsing
+// ============================================================================
+
+/// Element with namespace prefixes stripped (also used for UPnP SOAP/XML)
+#[derive(Debug, Default)]
+pub(crate) struct XmlNode {
+    pub(crate) name: String,
+    pub(crate) attrs: HashMap<String, String>,
+    pub(crate) children: Vec<XmlNode>,
+    pub(crate) text: String,
+}
+
+impl XmlNode {
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.get(name).map(String::as_str)
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&XmlNode> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub(crate) fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlNode> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+fn xml_node(start: &BytesStart) -> Result<XmlNode, String> {
+    let mut node = XmlNode {
+        name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+        ..Default::default()
+    };
+    for attr in start.attributes() {
+        let attr = attr.map_err(|e| format!("XML attribute error: {}", e))?;
+        let value = attr
+            .unescape_value()
+            .map_err(|e| format!("XML attribute error: {}", e))?;
+        node.attrs.insert(
+            String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned(),
+            value.into_owned(),
+        );
+    }
+    Ok(node)
+}
+
+/// Small documents only (manifests): builds the whole tree
+pub(crate) fn parse_xml(text: &str) -> Result<XmlNode, String> {
+    let mut reader = quick_xml::Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+    let mut stack = vec![XmlNode::default()];
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| format!("XML error at {}: {}", reader.buffer_position(), e))?
+        {
+            Event::Start(start) => stack.push(xml_node(&start)?),
+            Event::Empty(start) => {
+                let node = xml_node(&start)?;
+                stack.last_mut().unwrap().children.push(node);
+            }
+            Event::End(_) => {
+                let node = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .ok_or("Unbalanced XML")?
+                    .children
+                    .push(node);
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape()
+                    .map_err(|e| format!("XML text error: {}", e))?;
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    stack
+        .pop()
+        .and_then(|root| root.children.into_iter().next())
+        .ok_or_else(|| "Empty XML document".to_string())
+}
+
+/// ISO 8601 duration (`PT1H2M3.5S`, `P1DT2H`) in seconds
+pub fn parse_iso_duration(value: &str) -> Option<f64> {
+    let rest = value.trim().strip_prefix('P')?;
+    let mut seconds = 0.0;
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            unit => {
+                let n: f64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match (unit, in_time) {
+                    ('Y', false) => 365.0 * 86400.0,
+                    ('M', false) => 30.0 * 86400.0,
+                    ('W', false) => 7.0 * 86400.0,
+                    ('D', false) => 86400.0,
+                    ('H', true) => 3600.0,
+                    ('M', true) => 60.0,
+                    ('S', true) => 1.0,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    number.is_empty().then_some(seconds)
+}
+
+/// Expand `$RepresentationID$`, `$Number%05d$`, `$Bandwidth$`, `$Time$`, `$$`
+fn expand_template(template: &str, id: &str, number: u64, bandwidth: u64, time: u64) -> String {
+    let mut out = String::with_capacity(template.len() + 16);
+    let mut parts = template.split('$');
+    out.push_str(parts.next().unwrap_or(""));
+    let mut in_identifier = true;
+    for part in parts {
+        if !in_identifier {
+            out.push_str(part);
+            in_identifier = true;
+            continue;
+        }
+        in_identifier = false;
+        let (name, format) = part.split_once('%').unwrap_or((part, ""));
+        let width = format
+            .trim_start_matches('0')
+            .trim_end_matches('d')
+            .parse::<usize>()
+            .unwrap_or(0);
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(id),
+            "Number" => out.push_str(&format!("{:0width$}", number, width = width)),
+            "Bandwidth" => out.push_str(&format!("{:0width$}", bandwidth, width = width)),
+            "Time" => out.push_str(&format!("{:0width$}", time, width = width)),
+            other => {
+                out.push('$');
+                out.push_str(other);
+                out.push('$');
+            }
+        }
+    }
+    out
+}
+
+/// A parsed MPD: renditions with their segments baked out
+#[derive(Debug, Clone)]
+pub struct DashManifest {
+    /// `type="dynamic"`: live, refetch every `minimum_update_period`
+    pub dynamic: bool,
+    pub minimum_update_period: Option<f64>,
+    pub video: Vec<(Rendition, MediaPlaylist)>,
+    pub audio: Vec<(Rendition, MediaPlaylist)>,
+}
+
+/// SegmentTemplate attributes, inherited AdaptationSet -> Representation
+#[derive(Clone, Default)]
+struct TemplateInfo<'a> {
+    media: Option<&'a str>,
+    initialization: Option<&'a str>,
+    timescale: Option<u64>,
+    duration: Option<u64>,
+    start_number: Option<u64>,
+    timeline: Option<&'a XmlNode>,
+}
+
+impl<'a> TemplateInfo<'a> {
+    fn merge(&self, node: Option<&'a XmlNode>) -> Self {
+        let Some(node) = node else {
+            return self.clone();
+        };
+        Self {
+            media: node.attr("media").or(self.media),
+            initialization: node.attr("initialization").or(self.initialization),
+            timescale: node
+                .attr("timescale")
+                .and_then(|v| v.parse().ok())
+                .or(self.timescale),
+            duration: node
+                .attr("duration")
+                .and_then(|v| v.parse().ok())
+                .or(self.duration),
+            start_number: node
+                .attr("startNumber")
+                .and_then(|v| v.parse().ok())
+                .or(self.start_number),
+            timeline: node.child("SegmentTimeline").or(self.timeline),
+        }
+    }
+}
+
+fn base_url_of(node: &XmlNode, base: &str) -> String {
+    match node.child("BaseURL") {
+        Some(child) if !child.text.trim().is_empty() => resolve_url(base, child.text.trim()),
+        _ => base.to_string(),
+    }
+}
+
+/// Parse an MPD. `now` places the live edge for number-based live templates.
+pub fn parse_mpd(
+    text: &str,
+    mpd_url: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<DashManifest, String> {
+    let mpd = parse_xml(text)?;
+    if mpd.name != "MPD" {
+        return Err("Not a DASH manifest: root element is not MPD".to_string());
+    }
+    let dynamic = mpd.attr("type") == Some("dynamic");
+    let base = base_url_of(&mpd, mpd_url);
+    let presentation_duration = mpd
+        .attr("mediaPresentationDuration")
+        .and_then(parse_iso_duration);
+    let availability_start = mpd
+        .attr("availabilityStartTime")
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&chrono::Utc));
+    let time_shift_depth = mpd
+        .attr("timeShiftBufferDepth")
+        .and_then(parse_iso_duration)
+        .unwrap_or(30.0);
+
+    // Live streams play the newest period, VOD starts at the first
+    let mut periods = mpd.children_named("Period");
+    let period = if dynamic {
+        periods.last()
+    } else {
+        periods.next()
+    }
+    .ok_or("MPD has no Period")?;
+    let period_base = base_url_of(period, &base);
+    let period_start = period
+        .attr("start")
+        .and_then(parse_iso_duration)
+        .unwrap_or(0.0);
+    let period_duration = period
+        .attr("duration")
+        .and_then(parse_iso_duration)
+        .or(presentation_duration.map(|d| d - period_start));
+    // Seconds since the period began, for live number-based templates
+    let live_elapsed = availability_start
+        .filter(|_| dynamic)
+        .map(|start| (now - start).num_milliseconds() as f64 / 1000.0 - period_start);
+
+    let mut manifest = DashManifest {
+        dynamic,
+        minimum_update_period: mpd.attr("minimumUpdatePeriod").and_then(parse_iso_duration),
+        video: Vec::new(),
+        audio: Vec::new(),
+    };
+
+    for set in period.children_named("AdaptationSet") {
+        let content = set
+            .attr("contentType")
+            .or(set.attr("mimeType"))
+            .unwrap_or("");
+        let set_base = base_url_of(set, &period_base);
+        let set_template = TemplateInfo::default().merge(set.child("SegmentTemplate"));
+
+        for rep in set.children_named("Representation") {
+            let id = rep.attr("id").unwrap_or("").to_string();
+            let mime = rep.attr("mimeType").or(set.attr("mimeType")).unwrap_or("");
+            let kind = if content.starts_with("audio") || mime.starts_with("audio") {
+                MediaKind::Audio
+            } else if content.starts_with("video") || mime.starts_with("video") {
+                MediaKind::Main
+            } else {
+                continue; // text tracks, thumbnails
+            };
+            let bandwidth = rep
+                .attr("bandwidth")
+                .and_then(|b| b.parse().ok())
+                .unwrap_or(0);
+            let rep_base = base_url_of(rep, &set_base);
+            let format = if mime.contains("mp2t") {
+                SegmentFormat::Ts
+            } else {
+                SegmentFormat::Fmp4
+            };
+            let template = set_template.merge(rep.child("SegmentTemplate"));
+
+            let segments = if template.media.is_some() {
+                template_segments(
+                    &template,
+                    &id,
+                    bandwidth,
+                    &rep_base,
+                    period_duration,
+                    live_elapsed,
+                    time_shift_depth,
+                )?
+            } else if let Some(list) = rep.child("SegmentList").or(set.child("SegmentList")) {
+                list_segments(list, &rep_base)
+            } else {
+                // SegmentBase / bare BaseURL: the whole file is one segment
+                vec![Segment {
+                    uri: rep_base.clone(),
+                    duration: period_duration.unwrap_or(0.0),
+                    sequence: 0,
+                    byte_range: None,
+                    key: None,
+                    init: None,
+                    discontinuity: false,
+                }]
+            };
+
+            let target_duration = segments.iter().map(|s| s.duration).fold(0.0, f64::max);
+            let frame_rate = rep
+                .attr("frameRate")
+                .or(set.attr("frameRate"))
+                .and_then(|f| match f.split_once('/') {
+                    Some((n, d)) => Some(n.parse::<f64>().ok()? / d.parse::<f64>().ok()?),
+                    None => f.parse().ok(),
+                });
+            let rendition = Rendition {
+                id,
+                bandwidth,
+                width: rep.attr("width").and_then(|w| w.parse().ok()),
+                height: rep.attr("height").and_then(|h| h.parse().ok()),
+                codecs: rep
+                    .attr("codecs")
+                    .or(set.attr("codecs"))
+                    .map(str::to_string),
+                frame_rate,
+                language: set.attr("lang").map(str::to_string),
+                uri: mpd_url.to_string(),
+                audio_group: None,
+            };
+            let playlist = MediaPlaylist {
+                target_duration: target_duration.max(1.0),
+                segments,
+                ended: !dynamic,
+                format,
+            };
+            match kind {
+                MediaKind::Main => manifest.video.push((rendition, playlist)),
+                MediaKind::Audio => manifest.audio.push((rendition, playlist)),
+            }
+        }
+    }
+
+    if manifest.video.is_empty() && manifest.audio.is_empty() {
+        return Err("MPD has no playable Representations".to_string());
+    }
+    manifest.video.sort_by_key(|(r, _)| r.bandwidth);
+    Ok(manifest)
+}
+
+fn template_segments(
+    template: &TemplateInfo,
+    id: &str,
+    bandwidth: u64,
+    base: &str,
+    period_duration: Option<f64>,
+    live_elapsed: Option<f64>,
+    time_shift_depth: f64,
+) -> Result<Vec<Segment>, String> {
+    let media = template.media.unwrap_or_default();
+    let timescale = template.timescale.unwrap_or(1).max(1);
+    let start_number = template.start_number.unwrap_or(1);
+    let init = template.initialization.map(|init| InitSection {
+        uri: resolve_url(base, &expand_template(init, id, 0, bandwidth, 0)),
+        byte_range: None,
+        key: None,
+    });
+    let segment = |number: u64, time: u64, duration: u64| Segment {
+        uri: resolve_url(base, &expand_template(media, id, number, bandwidth, time)),
+        duration: duration as f64 / timescale as f64,
+        sequence: number,
+        byte_range: None,
+        key: None,
+        init: init.clone(),
+        discontinuity: false,
+    };
+
+    let mut segments = Vec::new();
+    if let Some(timeline) = template.timeline {
+        let entries: Vec<&XmlNode> = timeline.children_named("S").collect();
+        let end_time = period_duration.map(|d| (d * timescale as f64) as u64);
+        let mut time = 0u64;
+        let mut number = start_number;
+        for (i, s) in entries.iter().enumerate() {
+            if let Some(t) = s.attr("t").and_then(|t| t.parse().ok()) {
+                time = t;
+            }
+            let duration: u64 = s
+                .attr("d")
+                .and_then(|d| d.parse().ok())
+                .filter(|&d| d > 0)
+                .ok_or("SegmentTimeline entry without a duration")?;
+            let repeat: i64 = s.attr("r").and_then(|r| r.parse().ok()).unwrap_or(0);
+            let count = if repeat >= 0 {
+                repeat as u64 + 1
+            } else {
+                // -1: repeat up to the next entry's start or the period end
+                let until = entries
+                    .get(i + 1)
+                    .and_then(|next| next.attr("t"))
+                    .and_then(|t| t.parse().ok())
+                    .or(end_time)
+                    .unwrap_or(time + duration);
+                until.saturating_sub(time).div_ceil(duration).max(1)
+            };
+            for _ in 0..count {
+                segments.push(segment(number, time, duration));
+                time += duration;
+                number += 1;
+            }
+        }
+    } else {
+        let duration = template
+            .duration
+            .filter(|&d| d > 0)
+            .ok_or("SegmentTemplate needs a duration or a SegmentTimeline")?;
+        let seconds = duration as f64 / timescale as f64;
+        let (first, count) = match live_elapsed {
+            // Live: segments that have finished, within the time-shift window
+            Some(elapsed) => {
+                let available = (elapsed / seconds).floor().max(0.0) as u64;
+                let window = (time_shift_depth / seconds).ceil().max(1.0) as u64;
+                let first = available.saturating_sub(window);
+                (first, available - first)
+            }
+            None => {
+                let total = period_duration.ok_or("Number-based template without a duration")?;
+                (0, (total / seconds).ceil() as u64)
+            }
+        };
+        for index in first..first + count {
+            segments.push(segment(start_number + index, index * duration, duration));
+        }
+    }
+    Ok(segments)
+}
+
+fn list_segments(list: &XmlNode, base: &str) -> Vec<Segment> {
+    let timescale: f64 = list
+        .attr("timescale")
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(1.0);
+    let duration = list
+        .attr("duration")
+        .and_then(|d| d.parse::<f64>().ok())
+        .unwrap_or(0.0)
+        / timescale;
+    let start_number = list
+        .attr("startNumber")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1);
+    let range = |value: Option<&str>| {
+        let (first, last) = value?.split_once('-')?;
+        let first: u64 = first.parse().ok()?;
+        let last: u64 = last.parse().ok()?;
+        Some(ByteRange {
+            offset: first,
+            length: last.checked_sub(first)? + 1,
+        })
+    };
+    let init = list.child("Initialization").map(|init| InitSection {
+        uri: resolve_url(base, init.attr("sourceURL").unwrap_or("")),
+        byte_range: range(init.attr("range")),
+        key: None,
+    });
+    list.children_named("SegmentURL")
+        .enumerate()
+        .map(|(i, url)| Segment {
+            uri: resolve_url(base, url.attr("media").unwrap_or("")),
+            duration,
+            sequence: start_number + i as u64,
+            byte_range: range(url.attr("mediaRange")),
+            key: None,
+            init: init.clone(),
+            discontinuity: false,
+        })
+        .collect()
+}
+
+// ============================================================================
+// Adaptive Bitrate
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbrConfig {
+    /// Estimate used before the first segment has been measured
+    pub start_bandwidth_bps: u64,
+    /// Fraction of the estimate a rendition may use
+    pub safety_factor: f64,
+    /// Half-lives, in seconds of media, of the fast and slow averages
+    pub fast_half_life_secs: f64,
+    pub slow_half_life_secs: f64,
+}
+
+impl Default for AbrConfig {
+    fn default() -> Self {
+        Self {
+            start_bandwidth_bps: 1_000_000,
+            safety_factor: 0.8,
+            fast_half_life_secs: 3.0,
+            slow_half_life_secs: 9.0,
+        }
+    }
+}
+
+/// Exponentially weighted moving average, weighted by seconds of media
+#[derive(Debug, Clone)]
+struct Ewma {
+    alpha_per_sec: f64,
+    estimate: f64,
+    total_weight: f64,
+}
+
+impl Ewma {
+    fn new(half_life_secs: f64) -> Self {
+        Self {
+            alpha_per_sec: 0.5f64.powf(1.0 / half_life_secs.max(0.1)),
+            estimate: 0.0,
+            total_weight: 0.0,
+        }
+    }
+
+    fn sample(&mut self, weight: f64, value: f64) {
+        let alpha = self.alpha_per_sec.powf(weight);
+        self.estimate = value * (1.0 - alpha) + alpha * self.estimate;
+        self.total_weight += weight;
+    }
+
+    /// Corrected for the zero the average started from
+    fn value(&self) -> f64 {
+        let zero_factor = 1.0 - self.alpha_per_sec.powf(self.total_weight);
+        self.estimate / zero_factor
+    }
+}
+
+/// Throughput estimate (the lower of a fast and a slow average, so drops
+/// are reacted to quickly and spikes slowly) and rendition choice
+#[derive(Debug, Clone)]
+pub struct AbrController {
+    config: AbrConfig,
+    fast: Ewma,
+    slow: Ewma,
+}
+
+impl AbrController {
+    pub fn new(config: AbrConfig) -> Self {
+        Self {
+            fast: Ewma::new(config.fast_half_life_secs),
+            slow: Ewma::new(config.slow_half_life_secs),
+            config,
+        }
+    }
+
+    /// Record a download of `bytes` that took `elapsed` and holds
+    /// `media_secs` of media
+    pub fn sample(&mut self, bytes: usize, elapsed: Duration, media_secs: f64) {
+        let secs = elapsed.as_secs_f64().max(0.001);
+        let bps = bytes as f64 * 8.0 / secs;
+        let weight = media_secs.max(0.1);
+        self.fast.sample(weight, bps);
+        self.slow.sample(weight, bps);
+    }
+
+    /// Bits per second
+    pub fn estimate(&self) -> u64 {
+        if self.fast.total_weight == 0.0 {
+            return self.config.start_bandwidth_bps;
+        }
+        self.fast.value().min(self.slow.value()) as u64
+    }
+
+    /// Highest rendition that fits the estimate, else the lowest
+    pub fn select(&self, renditions: &[Rendition]) -> usize {
+        let budget = self.estimate() as f64 * self.config.safety_factor;
+        let mut best: Option<usize> = None;
+        let mut lowest = 0;
+        for (i, rendition) in renditions.iter().enumerate() {
+            if rendition.bandwidth < renditions[lowest].bandwidth {
+                lowest = i;
+            }
+            let fits = rendition.bandwidth as f64 <= budget;
+            if fits && best.is_none_or(|b| rendition.bandwidth > renditions[b].bandwidth) {
+                best = Some(i);
+            }
+        }
+        best.unwrap_or(lowest)
+    }
+}
+
+// ============================================================================
+// Client
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AdaptiveConfig {
+    pub http: HttpConfig,
+    pub abr: AbrConfig,
+    /// Preferred language for a separate audio rendition
+    pub audio_language: Option<String>,
+    /// Give up on a live stream after this long without a new segment
+    /// (0 = three target durations)
+    pub live_stall_secs: u64,
+}
+
+/// One fetched (and decrypted) segment
+#[derive(Debug, Clone)]
+pub struct SegmentData {
+    pub track: MediaKind,
+    /// Index into the track's renditions
+    pub rendition: usize,
+    pub sequence: u64,
+    /// Seconds
+    pub duration: f64,
+    pub format: SegmentFormat,
+    /// Timestamps may jump: a rendition switch or an HLS discontinuity
+    pub discontinuity: bool,
+    /// fMP4 init section this segment decodes with
+    pub init: Option<Vec<u8>>,
+    pub data: Vec<u8>,
+}
+
+impl SegmentData {
+    /// Demux the segment into packets (TS stream index = PID, fMP4 = track)
+    pub fn packets(&self) -> Result<Vec<UniversalPacket>, String> {
+        let mut packets = Vec::new();
+        match self.format {
+            SegmentFormat::Ts => {
+                let mut demuxer = crate::ts_demux::TsDemuxer::new(Cursor::new(&self.data[..]))?;
+                while let Some(packet) = demuxer.read_packet() {
+                    packets.push(crate::demuxer::map_ts_packet(packet));
+                }
+            }
+            SegmentFormat::Fmp4 => {
+                let mut buffer = self.init.clone().unwrap_or_default();
+                buffer.extend_from_slice(&self.data);
+                let mut demuxer = crate::mp4_demux::mp4::Mp4Demuxer::new(Cursor::new(buffer))
+                    .map_err(|e| format!("Segment demux init: {}", e))?;
+                while let Some(packet) = demuxer.read_packet() {
+                    packets.push(crate::demuxer::map_mp4_packet(packet));
+                }
+            }
+        }
+        Ok(packets)
+    }
+}
+
+struct TrackState {
+    kind: MediaKind,
+    renditions: Vec<Rendition>,
+    current: usize,
+    playlist: MediaPlaylist,
+    /// Sequence number of the next segment to deliver
+    next_sequence: u64,
+    /// Media time delivered so far, for interleaving audio with video
+    media_time: f64,
+    /// Init section last handed out (uri, range, bytes)
+    init: Option<(InitSection, Vec<u8>)>,
+    switched: bool,
+    last_new_segment: Instant,
+    finished: bool,
+    /// DASH: segment lists of every rendition (HLS loads them on switch)
+    dash_playlists: Vec<MediaPlaylist>,
+}
+
+impl TrackState {
+    fn new(
+        kind: MediaKind,
+        renditions: Vec<Rendition>,
+        current: usize,
+        playlist: MediaPlaylist,
+    ) -> Self {
+        // Live: start three segments back from the edge
+        let start = if playlist.ended {
+            0
+        } else {
+            playlist.segments.len().saturating_sub(3)
+        };
+        let next_sequence = playlist.segments.get(start).map_or(0, |s| s.sequence);
+        Self {
+            kind,
+            renditions,
+            current,
+            playlist,
+            next_sequence,
+            media_time: 0.0,
+            init: None,
+            switched: false,
+            last_new_segment: Instant::now(),
+            finished: false,
+            dash_playlists: Vec::new(),
+        }
+    }
+
+    fn next_segment(&self) -> Option<&Segment> {
+        self.playlist
+            .segments
+            .iter()
+            .find(|s| s.sequence >= self.next_sequence)
+    }
+}
+
+pub struct AdaptiveClient {
+    http: HttpClient,
+    config: AdaptiveConfig,
+    kind: ManifestKind,
+    manifest_url: String,
+    abr: AbrController,
+    /// Rendition pinned by the user (None = ABR)
+    locked: Option<usize>,
+    main: TrackState,
+    audio: Option<TrackState>,
+    keys: HashMap<String, [u8; 16]>,
+    minimum_update_period: Option<f64>,
+    last_manifest_load: Instant,
+}
+
+impl AdaptiveClient {
+    /// Load an HLS playlist (master or media) or a DASH MPD
+    pub fn open(url: &str, config: AdaptiveConfig) -> Result<Self, String> {
+        let http = HttpClient::new(config.http.clone());
+        let abr = AbrController::new(config.abr.clone());
+        let response = http.get_text(url)?;
+        let text = String::from_utf8_lossy(&response.data).into_owned();
+        let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+
+        let (kind, main, audio, minimum_update_period) = if trimmed.starts_with("#EXTM3U") {
+            let (main, audio) = Self::open_hls(&http, &abr, trimmed, &response.url, &config)?;
+            (ManifestKind::Hls, main, audio, None)
+        } else if trimmed.starts_with('<') {
+            let manifest = parse_mpd(trimmed, &response.url, chrono::Utc::now())?;
+            let update = manifest.minimum_update_period;
+            let (main, audio) = Self::dash_tracks(manifest, &abr, &config, None)?;
+            (ManifestKind::Dash, main, audio, update)
+        } else {
+            return Err(format!("{} is neither an HLS playlist nor a DASH MPD", url));
+        };
+
+        tracing::info!(
+            "Adaptive stream {:?}: {} renditions, separate audio: {}, live: {}",
+            kind,
+            main.renditions.len(),
+            audio.is_some(),
+            !main.playlist.ended
+        );
+        Ok(Self {
+            http,
+            config,
+            kind,
+            manifest_url: response.url,
+            abr,
+            locked: None,
+            main,
+            audio,
+            keys: HashMap::new(),
+            minimum_update_period,
+            last_manifest_load: Instant::now(),
+        })
+    }
+
+    fn open_hls(
+        http: &HttpClient,
+        abr: &AbrController,
+        text: &str,
+        url: &str,
+        config: &AdaptiveConfig,
+    ) -> Result<(TrackState, Option<TrackState>), String> {
+        if !is_hls_master(text) {
+            let playlist = parse_hls_media(text, url)?;
+            let rendition = Rendition {
+                id: "0".to_string(),
+                bandwidth: 0,
+                width: None,
+                height: None,
+                codecs: None,
+                frame_rate: None,
+                language: None,
+                uri: url.to_string(),
+                audio_group: None,
+            };
+            return Ok((
+                TrackState::new(MediaKind::Main, vec![rendition], 0, playlist),
+                None,
+            ));
+        }
+
+        let (variants, audio) = parse_hls_master(text, url)?;
+        let current = abr.select(&variants);
+        let playlist = load_hls_media(http, &variants[current].uri)?;
+
+        // Audio from the group the variants play with, preferred language first
+        let group = variants[current].audio_group.clone();
+        let candidates: Vec<Rendition> = audio
+            .into_iter()
+            .filter(|r| group.is_some() && r.audio_group == group)
+            .collect();
+        let audio_track = match pick_audio(&candidates, config.audio_language.as_deref()) {
+            Some(index) => {
+                let playlist = load_hls_media(http, &candidates[index].uri)?;
+                Some(TrackState::new(
+                    MediaKind::Audio,
+                    candidates,
+                    index,
+                    playlist,
+                ))
+            }
+            None => None,
+        };
+        Ok((
+            TrackState::new(MediaKind::Main, variants, current, playlist),
+            audio_track,
+        ))
+    }
+
+    /// Build tracks from an MPD; `previous` keeps positions across a reload
+    fn dash_tracks(
+        manifest: DashManifest,
+        abr: &AbrController,
+        config: &AdaptiveConfig,
+        previous: Option<(&TrackState, Option<&TrackState>)>,
+    ) -> Result<(TrackState, Option<TrackState>), String> {
+        let (video, audio) = if manifest.video.is_empty() {
+            // Audio-only presentation: its renditions are the main track
+            (manifest.audio, Vec::new())
+        } else {
+            (manifest.video, manifest.audio)
+        };
+
+        let build = |kind: MediaKind,
+                     entries: Vec<(Rendition, MediaPlaylist)>,
+                     choose: &dyn Fn(&[Rendition]) -> usize,
+                     previous: Option<&TrackState>| {
+            let (renditions, playlists): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+            let current = previous
+                .and_then(|p| {
+                    renditions
+                        .iter()
+                        .position(|r| r.id == p.renditions[p.current].id)
+                })
+                .unwrap_or_else(|| choose(&renditions));
+            let mut track = TrackState::new(kind, renditions, current, playlists[current].clone());
+            if let Some(previous) = previous {
+                track.next_sequence = previous.next_sequence;
+                track.media_time = previous.media_time;
+                track.init = previous.init.clone();
+                track.last_new_segment = previous.last_new_segment;
+            }
+            (track, playlists)
+        };
+
+        let (mut main, main_playlists) = build(
+            MediaKind::Main,
+            video,
+            &|r| abr.select(r),
+            previous.map(|p| p.0),
+        );
+        main.dash_playlists = main_playlists;
+        let audio = if audio.is_empty() {
+            None
+        } else {
+            let language = config.audio_language.as_deref();
+            let (mut track, playlists) = build(
+                MediaKind::Audio,
+                audio,
+                &|r| pick_audio(r, language).unwrap_or(0),
+                previous.and_then(|p| p.1),
+            );
+            track.dash_playlists = playlists;
+            Some(track)
+        };
+        Ok((main, audio))
+    }
+
+    pub fn kind(&self) -> ManifestKind {
+        self.kind
+    }
+
+    pub fn manifest_url(&self) -> &str {
+        &self.manifest_url
+    }
+
+    pub fn renditions(&self) -> &[Rendition] {
+        &self.main.renditions
+    }
+
+    /// Rendition of the most recent main segment
+    pub fn current_rendition(&self) -> usize {
+        self.main.current
+    }
+
+    pub fn is_live(&self) -> bool {
+        !self.main.playlist.ended
+    }
+
+    /// Throughput estimate in bits per second
+    pub fn bandwidth_estimate(&self) -> u64 {
+        self.abr.estimate()
+    }
+
+    /// Pin a rendition (`None` hands control back to ABR)
+    pub fn set_rendition(&mut self, rendition: Option<usize>) -> Result<(), String> {
+        if let Some(index) = rendition {
+            if index >= self.main.renditions.len() {
+                return Err(format!("No rendition {}", index));
+            }
+        }
+        self.locked = rendition;
+        Ok(())
+    }
+
+    /// Next segment in presentation order, or `None` at the end of a VOD
+    /// (or finished live) stream. Blocks while waiting for live segments.
+    pub fn next_segment(&mut self) -> Result<Option<SegmentData>, String> {
+        loop {
+            // Keep the separate audio track level with the main one
+            let audio_due = self.audio.as_ref().is_some_and(|audio| {
+                !audio.finished && (self.main.finished || audio.media_time < self.main.media_time)
+            });
+            let kind = if audio_due {
+                MediaKind::Audio
+            } else if !self.main.finished {
+                MediaKind::Main
+            } else {
+                return Ok(None);
+            };
+
+            if kind == MediaKind::Main && self.main.renditions.len() > 1 {
+                let target = self
+                    .locked
+                    .unwrap_or_else(|| self.abr.select(&self.main.renditions));
+                if target != self.main.current {
+                    self.switch_main(target)?;
+                }
+            }
+            if let Some(segment) = self.next_from(kind)? {
+                return Ok(Some(segment));
+            }
+            // That track just ended; carry on with the other one
+        }
+    }
+
+    fn track_mut(&mut self, kind: MediaKind) -> &mut TrackState {
+        match kind {
+            MediaKind::Audio => self.audio.as_mut().unwrap_or(&mut self.main),
+            MediaKind::Main => &mut self.main,
+        }
+    }
+
+    fn switch_main(&mut self, target: usize) -> Result<(), String> {
+        let from = self.main.renditions[self.main.current].bandwidth;
+        let to = &self.main.renditions[target];
+        tracing::info!(
+            "ABR: switching {} -> {} bps (estimate {} bps)",
+            from,
+            to.bandwidth,
+            self.abr.estimate()
+        );
+        let playlist = match self.kind {
+            ManifestKind::Hls => load_hls_media(&self.http, &to.uri)?,
+            ManifestKind::Dash => self.main.dash_playlists[target].clone(),
+        };
+        self.main.playlist = playlist;
+        self.main.current = target;
+        self.main.switched = true;
+        Ok(())
+    }
+
+    fn next_from(&mut self, kind: MediaKind) -> Result<Option<SegmentData>, String> {
+        let stall_secs = self.config.live_stall_secs;
+        let segment = loop {
+            let track = self.track_mut(kind);
+            if let Some(segment) = track.next_segment() {
+                break segment.clone();
+            }
+            if track.playlist.ended {
+                track.finished = true;
+                return Ok(None);
+            }
+            let stall_limit = match stall_secs {
+                0 => track.playlist.target_duration * 3.0,
+                secs => secs as f64,
+            };
+            if track.last_new_segment.elapsed().as_secs_f64() > stall_limit {
+                return Err(format!(
+                    "Live stream stalled: no new segment for {:.0} s",
+                    stall_limit
+                ));
+            }
+            let before = track.playlist.segments.last().map(|s| s.sequence);
+            self.reload(kind)?;
+            let track = self.track_mut(kind);
+            let after = track.playlist.segments.last().map(|s| s.sequence);
+            if after > before {
+                track.last_new_segment = Instant::now();
+            } else if !track.playlist.ended {
+                // Nothing new yet: wait half a target duration (RFC 8216 6.3.4)
+                let wait = (track.playlist.target_duration / 2.0).clamp(0.1, 10.0);
+                std::thread::sleep(Duration::from_secs_f64(wait));
+            }
+        };
+
+        // Init section, fetched again only when it changes
+        let init = match &segment.init {
+            Some(section) => {
+                let cached = self
+                    .track_mut(kind)
+                    .init
+                    .as_ref()
+                    .filter(|(s, _)| s == section)
+                    .map(|(_, data)| data.clone());
+                match cached {
+                    Some(data) => Some(data),
+                    None => {
+                        let response = self.http.get(&section.uri, section.byte_range)?;
+                        let data = match &section.key {
+                            Some(key) => self.decrypt(key, segment.sequence, response.data)?,
+                            None => response.data,
+                        };
+                        self.track_mut(kind).init = Some((section.clone(), data.clone()));
+                        Some(data)
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let started = Instant::now();
+        let response = self.http.get(&segment.uri, segment.byte_range)?;
+        if kind == MediaKind::Main {
+            self.abr
+                .sample(response.data.len(), started.elapsed(), segment.duration);
+        }
+        let data = match &segment.key {
+            Some(key) => self.decrypt(key, segment.sequence, response.data)?,
+            None => response.data,
+        };
+
+        let track = self.track_mut(kind);
+        track.next_sequence = segment.sequence + 1;
+        track.media_time += segment.duration;
+        let discontinuity = segment.discontinuity || std::mem::take(&mut track.switched);
+        Ok(Some(SegmentData {
+            track: kind,
+            rendition: track.current,
+            sequence: segment.sequence,
+            duration: segment.duration,
+            format: track.playlist.format,
+            discontinuity,
+            init,
+            data,
+        }))
+    }
+
+    /// Refetch a live media playlist (HLS) or the whole MPD (DASH)
+    fn reload(&mut self, kind: MediaKind) -> Result<(), String> {
+        if self.kind == ManifestKind::Hls {
+            let track = self.track_mut(kind);
+            let uri = track.renditions[track.current].uri.clone();
+            let playlist = load_hls_media(&self.http, &uri)?;
+            self.track_mut(kind).playlist = playlist;
+            return Ok(());
+        }
+
+        if let Some(period) = self.minimum_update_period {
+            let wait = Duration::from_secs_f64(period.max(0.0))
+                .saturating_sub(self.last_manifest_load.elapsed());
+            std::thread::sleep(wait.min(Duration::from_secs(10)));
+        }
+        let response = self.http.get_text(&self.manifest_url)?;
+        let manifest = parse_mpd(
+            &String::from_utf8_lossy(&response.data),
+            &response.url,
+            chrono::Utc::now(),
+        )?;
+        self.last_manifest_load = Instant::now();
+        self.minimum_update_period = manifest.minimum_update_period;
+        let (main, audio) = Self::dash_tracks(
+            manifest,
+            &self.abr,
+            &self.config,
+            Some((&self.main, self.audio.as_ref())),
+        )?;
+        // Positions were carried over; only the segment lists are new
+        self.main.dash_playlists = main.dash_playlists;
+        self.main.playlist = main.playlist;
+        if let (Some(track), Some(fresh)) = (self.audio.as_mut(), audio) {
+            track.dash_playlists = fresh.dash_playlists;
+            track.playlist = fresh.playlist;
+        }
+        Ok(())
+    }
+
+    fn decrypt(
+        &mut self,
+        key: &SegmentKey,
+        sequence: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, String> {
+        let key_bytes = match self.keys.get(&key.uri) {
+            Some(bytes) => *bytes,
+            None => {
+                let response = self.http.get(&key.uri, None)?;
+                let bytes: [u8; 16] =
+                    response.data.as_slice().try_into().map_err(|_| {
+                        format!("AES-128 key is {} bytes, not 16", response.data.len())
+                    })?;
+                self.keys.insert(key.uri.clone(), bytes);
+                bytes
+            }
+        };
+        let mut iv = [0u8; 16];
+        match key.iv {
+            Some(explicit) => iv = explicit,
+            None => iv[8..].copy_from_slice(&sequence.to_be_bytes()),
+        }
+        Aes128CbcDec::new(&key_bytes.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&data)
+            .map_err(|_| "AES-128 decryption failed (wrong key or IV?)".to_string())
+    }
+}
+
+fn load_hls_media(http: &HttpClient, url: &str) -> Result<MediaPlaylist, String> {
+    let response = http.get_text(url)?;
+    parse_hls_media(&String::from_utf8_lossy(&response.data), &response.url)
+}
+
+/// Preferred-language audio rendition, else the first (defaults come first)
+fn pick_audio(renditions: &[Rendition], language: Option<&str>) -> Option<usize> {
+    if renditions.is_empty() {
+        return None;
+    }
+    language
+        .and_then(|lang| {
+            renditions.iter().position(|r| {
+                r.language
+                    .as_deref()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(lang) || l.starts_with(lang))
+            })
+        })
+        .or(Some(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::{MuxCodec, MuxPacket, MuxStream};
+    use crate::test_support::{http_server, respond};
+    use aes::cipher::BlockEncryptMut;
+    use std::sync::{Arc, Mutex};
+
+    type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// File server on an ephemeral port, with Range support
+    fn serve(files: Files) -> String {
+        http_server(move |request, stream| {
+            let range = request.headers.get("range").map(|value| {
+                let (first, last) = value.trim_start_matches("bytes=").split_once('-').unwrap();
+                (
+                    first.parse::<usize>().unwrap(),
+                    last.parse::<usize>().unwrap(),
+                )
+            });
+            let body = files.lock().unwrap().get(&request.path).cloned();
+            let (status, body) = match (body, range) {
+                (Some(body), Some((first, last))) => {
+                    ("206 Partial Content", body[first..=last].to_vec())
+                }
+                (Some(body), None) => ("200 OK", body),
+                (None, _) => ("404 Not Found", Vec::new()),
+            };
+            respond(stream, status, &[], &body);
+        })
+    }
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    fn encrypt(data: &[u8], iv: [u8; 16]) -> Vec<u8> {
+        cbc::Encryptor::<aes::Aes128>::new(&KEY.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(data)
+    }
+
+    fn sequence_iv(sequence: u64) -> [u8; 16] {
+        let mut iv = [0u8; 16];
+        iv[8..].copy_from_slice(&sequence.to_be_bytes());
+        iv
+    }
+
+    /// One second of H.264 as MPEG-TS: five frames, starting at `index` s
+    fn ts_segment(index: i64, frame_size: usize) -> Vec<u8> {
+        let sps = [0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f, 0xac];
+        let pps = [0, 0, 0, 1, 0x68, 0xee, 0x3c];
+        let stream = MuxStream::video(MuxCodec::H264, 320, 240)
+            .with_codec_private([&sps[..], &pps[..]].concat());
+        let mut muxer =
+            crate::ts_mux::TsMuxer::new(Vec::new(), vec![stream], Default::default()).unwrap();
+        for frame in 0..5i64 {
+            let mut data = vec![0, 0, 0, 1, if frame == 0 { 0x65 } else { 0x41 }];
+            data.resize(frame_size, 0x5A);
+            let time = index * 1_000_000 + frame * 200_000;
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: time,
+                    dts_us: Some(time),
+                    duration_us: Some(200_000),
+                    keyframe: frame == 0,
+                    data,
+                })
+                .unwrap();
+        }
+        muxer.finish().unwrap()
+    }
+
+    fn media_playlist(variant: &str, sequences: std::ops::Range<u64>, ended: bool) -> Vec<u8> {
+        let mut text = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:1\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            sequences.start
+        );
+        // The high variant carries an explicit IV, the low one uses the sequence
+        if variant == "high" {
+            text.push_str("#EXT-X-KEY:METHOD=AES-128,URI=\"/key.bin\",IV=0x000102030405060708090a0b0c0d0e0f\n");
+        } else {
+            text.push_str("#EXT-X-KEY:METHOD=AES-128,URI=\"../key.bin\"\n");
+        }
+        for sequence in sequences {
+            text.push_str(&format!("#EXTINF:1.0,\nseg{}.ts\n", sequence));
+        }
+        if ended {
+            text.push_str("#EXT-X-ENDLIST\n");
+        }
+        text.into_bytes()
+    }
+
+    fn publish_segments(files: &Files, sequences: std::ops::Range<u64>) {
+        let explicit_iv: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let mut files = files.lock().unwrap();
+        for sequence in sequences {
+            let low = ts_segment(sequence as i64, 400);
+            let high = ts_segment(sequence as i64, 4000);
+            files.insert(
+                format!("/low/seg{}.ts", sequence),
+                encrypt(&low, sequence_iv(sequence)),
+            );
+            files.insert(
+                format!("/high/seg{}.ts", sequence),
+                encrypt(&high, explicit_iv),
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_hls_playlists() {
+        let master = "#EXTM3U\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en\",URI=\"audio/en.m3u8\"\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Deutsch\",LANGUAGE=\"de\",DEFAULT=YES,URI=\"audio/de.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\",FRAME-RATE=29.970,AUDIO=\"aud\"\n\
+            720p/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=400000,AUDIO=\"aud\"\n\
+            http://cdn.example/360p.m3u8\n";
+        let (variants, audio) =
+            parse_hls_master(master, "http://example.com/live/master.m3u8").unwrap();
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].bandwidth, 1_280_000);
+        assert_eq!(
+            (variants[0].width, variants[0].height),
+            (Some(1280), Some(720))
+        );
+        assert_eq!(variants[0].codecs.as_deref(), Some("avc1.64001f,mp4a.40.2"));
+        assert_eq!(variants[0].frame_rate, Some(29.97));
+        assert_eq!(variants[0].uri, "http://example.com/live/720p/index.m3u8");
+        assert_eq!(variants[1].uri, "http://cdn.example/360p.m3u8");
+        // The DEFAULT rendition sorts first
+        assert_eq!(audio[0].language.as_deref(), Some("de"));
+        assert_eq!(pick_audio(&audio, Some("en")), Some(1));
+        assert_eq!(pick_audio(&audio, None), Some(0));
+
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:100\n\
+            #EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXTINF:6.0,\n#EXT-X-BYTERANGE:1000@720\nmain.mp4\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"key\",IV=0x1F\n\
+            #EXTINF:5.5,title\n#EXT-X-BYTERANGE:500\nmain.mp4\n\
+            #EXT-X-DISCONTINUITY\n#EXT-X-KEY:METHOD=NONE\n\
+            #EXTINF:4,\nother.m4s?token=1\n#EXT-X-ENDLIST\n";
+        let playlist = parse_hls_media(media, "http://example.com/v/index.m3u8").unwrap();
+        assert!(playlist.ended);
+        assert_eq!(playlist.format, SegmentFormat::Fmp4);
+        assert_eq!(playlist.target_duration, 6.0);
+        let segments = &playlist.segments;
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].sequence, 100);
+        assert_eq!(
+            segments[0].byte_range,
+            Some(ByteRange {
+                offset: 720,
+                length: 1000
+            })
+        );
+        assert_eq!(
+            segments[0].init.as_ref().unwrap().byte_range,
+            Some(ByteRange {
+                offset: 0,
+                length: 720
+            })
+        );
+        // A range without offset continues where the last one on that URI ended
+        assert_eq!(
+            segments[1].byte_range,
+            Some(ByteRange {
+                offset: 1720,
+                length: 500
+            })
+        );
+        let key = segments[1].key.as_ref().unwrap();
+        assert_eq!(key.uri, "http://example.com/v/key");
+        assert_eq!(key.iv.unwrap()[15], 0x1F);
+        assert!(segments[2].discontinuity && segments[2].key.is_none());
+
+        let sample_aes = "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"k\"\n#EXTINF:1,\na.ts\n";
+        assert!(parse_hls_media(sample_aes, "http://x/").is_err());
+    }
+
+    #[test]
+    fn test_manifest_urls() {
+        assert!(is_manifest_url(
+            "https://cdn.example/live/master.m3u8?token=1",
+            None
+        ));
+        assert!(is_manifest_url("HTTP://cdn.example/vod/Manifest.MPD", None));
+        assert!(is_manifest_url(
+            "http://panel:8080/live/42",
+            Some("application/vnd.apple.mpegurl")
+        ));
+        assert!(is_manifest_url(
+            "http://host/stream",
+            Some("application/dash+xml")
+        ));
+        assert!(!is_manifest_url("http://host/movie.mp4", Some("video/mp4")));
+        assert!(!is_manifest_url("/media/list.m3u8", None));
+    }
+
+    #[test]
+    fn test_parse_mpd_templates() {
+        assert_eq!(parse_iso_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_iso_duration("P1DT1S"), Some(86401.0));
+        assert_eq!(
+            expand_template("$RepresentationID$/$Number%05d$.m4s", "v1", 42, 0, 0),
+            "v1/00042.m4s"
+        );
+        assert_eq!(
+            expand_template("t$Time$-$$-$Bandwidth$", "", 0, 800, 9000),
+            "t9000-$-800"
+        );
+
+        let mpd = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate timescale="90000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Time$.m4s">
+        <SegmentTimeline><S t="0" d="180000" r="-1"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="hi" bandwidth="3000000" width="1920" height="1080" frameRate="30000/1001"/>
+      <Representation id="lo" bandwidth="800000" width="640" height="360"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio" mimeType="audio/mp4" lang="en">
+      <Representation id="a" bandwidth="128000">
+        <SegmentTemplate timescale="1000" duration="4000" startNumber="1" media="audio/$Number$.m4s" initialization="audio/init.mp4"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let manifest = parse_mpd(
+            mpd,
+            "http://example.com/show/manifest.mpd",
+            chrono::Utc::now(),
+        )
+        .unwrap();
+        assert!(!manifest.dynamic);
+        // Sorted by bandwidth, lowest first
+        let (lo, lo_playlist) = &manifest.video[0];
+        let (hi, _) = &manifest.video[1];
+        assert_eq!((lo.id.as_str(), hi.id.as_str()), ("lo", "hi"));
+        assert!((hi.frame_rate.unwrap() - 29.97).abs() < 0.01);
+        // r=-1 repeats up to the end of the period: 5 x 2 s
+        assert_eq!(lo_playlist.segments.len(), 5);
+        assert_eq!(
+            lo_playlist.segments[1].uri,
+            "http://example.com/show/media/lo/180000.m4s"
+        );
+        assert_eq!(lo_playlist.segments[1].duration, 2.0);
+        assert_eq!(
+            lo_playlist.segments[0].init.as_ref().unwrap().uri,
+            "http://example.com/show/media/lo/init.mp4"
+        );
+        let (audio, audio_playlist) = &manifest.audio[0];
+        assert_eq!(audio.language.as_deref(), Some("en"));
+        // 10 s in 4 s segments: 3, numbered from 1
+        let uris: Vec<&str> = audio_playlist
+            .segments
+            .iter()
+            .map(|s| s.uri.as_str())
+            .collect();
+        assert_eq!(uris.len(), 3);
+        assert!(uris[2].ends_with("media/audio/3.m4s"));
+
+        // Live, number-based: the edge follows the clock
+        let live = r#"<MPD type="dynamic" availabilityStartTime="2026-01-01T00:00:00Z" timeShiftBufferDepth="PT10S" minimumUpdatePeriod="PT2S">
+  <Period start="PT0S"><AdaptationSet mimeType="video/mp2t">
+    <Representation id="v" bandwidth="500000">
+      <SegmentTemplate duration="2" startNumber="0" media="seg-$Number$.ts"/>
+    </Representation>
+  </AdaptationSet></Period></MPD>"#;
+        let now = chrono::DateTime::parse_from_rfc3339("2026-01-01T00:01:00.5Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let manifest = parse_mpd(live, "http://example.com/live.mpd", now).unwrap();
+        assert!(manifest.dynamic);
+        assert_eq!(manifest.minimum_update_period, Some(2.0));
+        let playlist = &manifest.video[0].1;
+        assert_eq!(playlist.format, SegmentFormat::Ts);
+        assert!(!playlist.ended);
+        // 60.5 s in: segments 0..=29 are complete, the last 10 s are kept
+        let numbers: Vec<u64> = playlist.segments.iter().map(|s| s.sequence).collect();
+        assert_eq!(numbers, (25..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_abr_controller() {
+        let renditions: Vec<Rendition> = [400_000u64, 1_500_000, 4_000_000]
+            .iter()
+            .map(|&bandwidth| Rendition {
+                id: bandwidth.to_string(),
+                bandwidth,
+                width: None,
+                height: None,
+                codecs: None,
+                frame_rate: None,
+                language: None,
+                uri: String::new(),
+                audio_group: None,
+            })
+            .collect();
+        let mut abr = AbrController::new(AbrConfig::default());
+        // 1 Mbit/s start estimate x 0.8 safety: only the lowest fits
+        assert_eq!(abr.select(&renditions), 0);
+
+        // Steady 6 Mbit/s: top rendition
+        for _ in 0..4 {
+            abr.sample(1_500_000, Duration::from_secs(2), 4.0);
+        }
+        assert!((abr.estimate() as i64 - 6_000_000).abs() < 10_000);
+        assert_eq!(abr.select(&renditions), 2);
+
+        // A collapse to 1.5 Mbit/s drags the fast average down right away
+        abr.sample(750_000, Duration::from_secs(4), 4.0);
+        assert!(abr.estimate() < 3_500_000);
+        assert_eq!(abr.select(&renditions), 1);
+
+        // Nothing fits: lowest
+        let mut slow = AbrController::new(AbrConfig::default());
+        slow.sample(10_000, Duration::from_secs(1), 1.0);
+        assert_eq!(slow.select(&renditions), 0);
+    }
+
+    #[test]
+    fn test_hls_live_aes_abr() {
+        let files: Files = Arc::default();
+        let base = serve(files.clone());
+        {
+            let mut files = files.lock().unwrap();
+            files.insert(
+                "/master.m3u8".into(),
+                b"#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=100000\nlow/index.m3u8\n\
+                  #EXT-X-STREAM-INF:BANDWIDTH=1000000\nhigh/index.m3u8\n"
+                    .to_vec(),
+            );
+            files.insert("/key.bin".into(), KEY.to_vec());
+            files.insert("/low/index.m3u8".into(), media_playlist("low", 0..4, false));
+            files.insert(
+                "/high/index.m3u8".into(),
+                media_playlist("high", 0..4, false),
+            );
+        }
+        publish_segments(&files, 0..4);
+
+        let config = AdaptiveConfig {
+            abr: AbrConfig {
+                start_bandwidth_bps: 200_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut client = AdaptiveClient::open(&format!("{}/master.m3u8", base), config).unwrap();
+        assert_eq!(client.kind(), ManifestKind::Hls);
+        assert_eq!(client.renditions().len(), 2);
+        assert!(client.is_live());
+
+        // Live: starts three segments from the edge, on the low variant
+        let first = client.next_segment().unwrap().unwrap();
+        assert_eq!((first.sequence, first.rendition), (1, 0));
+        assert_eq!(first.format, SegmentFormat::Ts);
+        let packets = first.packets().unwrap();
+        assert_eq!(packets.len(), 5);
+        assert_eq!(packets[0].pts_us, Some(1_000_000 + 700_000));
+        // SPS and PPS in front of the keyframe
+        assert_eq!(packets[0].data.len(), 9 + 7 + 400);
+
+        // Localhost is fast: ABR moves up, flagging the switch
+        let second = client.next_segment().unwrap().unwrap();
+        assert_eq!((second.sequence, second.rendition), (2, 1));
+        assert!(second.discontinuity);
+        let packets = second.packets().unwrap();
+        assert_eq!(packets[1].pts_us, Some(2_200_000 + 700_000));
+        assert_eq!(packets[1].data.len(), 4000);
+        let third = client.next_segment().unwrap().unwrap();
+        assert_eq!((third.sequence, third.discontinuity), (3, false));
+
+        // The playlist grows and ends; the client reloads to find it
+        publish_segments(&files, 4..6);
+        {
+            let mut files = files.lock().unwrap();
+            files.insert("/low/index.m3u8".into(), media_playlist("low", 2..6, true));
+            files.insert(
+                "/high/index.m3u8".into(),
+                media_playlist("high", 2..6, true),
+            );
+        }
+        let sequences: Vec<u64> = std::iter::from_fn(|| client.next_segment().unwrap())
+            .map(|s| s.sequence)
+            .collect();
+        assert_eq!(sequences, [4, 5]);
+        assert!(!client.is_live());
+
+        // Pinning a rendition overrides ABR; a wrong key is an error
+        let mut pinned =
+            AdaptiveClient::open(&format!("{}/master.m3u8", base), AdaptiveConfig::default())
+                .unwrap();
+        pinned.set_rendition(Some(0)).unwrap();
+        assert!(pinned.set_rendition(Some(5)).is_err());
+        files.lock().unwrap().insert("/key.bin".into(), vec![0; 16]);
+        assert!(pinned.next_segment().is_err());
+    }
+
+    #[test]
+    fn test_dash_fmp4_with_audio() {
+        // Three 1 s fragments of video, split into init + media segments
+        let sps = [0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f, 0xac];
+        let pps = [0, 0, 0, 1, 0x68, 0xee, 0x3c];
+        let fragmented = |stream: MuxStream, packets: Vec<MuxPacket>| {
+            let config = crate::mp4_mux::Mp4MuxConfig {
+                fragment_duration_ms: Some(1000),
+                ..Default::default()
+            };
+            let mut muxer =
+                crate::mp4_mux::Mp4Muxer::new(Cursor::new(Vec::new()), vec![stream], config)
+                    .unwrap();
+            for packet in &packets {
+                muxer.write_packet(packet).unwrap();
+            }
+            let file = muxer.finish().unwrap().into_inner();
+            // Top-level boxes: ftyp moov | moof mdat | moof mdat ...
+            let mut boxes = Vec::new();
+            let mut pos = 0;
+            while pos + 8 <= file.len() {
+                let size = u32::from_be_bytes(file[pos..pos + 4].try_into().unwrap()) as usize;
+                boxes.push(&file[pos..pos + size]);
+                pos += size;
+            }
+            let init = [boxes[0], boxes[1]].concat();
+            let segments: Vec<Vec<u8>> = boxes[2..].chunks(2).map(|pair| pair.concat()).collect();
+            (init, segments)
+        };
+        let video = (0..75i64)
+            .map(|i| {
+                let keyframe = i % 25 == 0;
+                let mut data = Vec::new();
+                if keyframe {
+                    data.extend_from_slice(&sps);
+                    data.extend_from_slice(&pps);
+                }
+                data.extend_from_slice(&[0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }, i as u8]);
+                MuxPacket {
+                    stream: 0,
+                    pts_us: i * 40_000,
+                    dts_us: Some(i * 40_000),
+                    duration_us: Some(40_000),
+                    keyframe,
+                    data,
+                }
+            })
+            .collect();
+        let (video_init, video_segments) =
+            fragmented(MuxStream::video(MuxCodec::H264, 320, 240), video);
+        assert_eq!(video_segments.len(), 3);
+        let audio = (0..150i64)
+            .map(|i| MuxPacket {
+                stream: 0,
+                pts_us: i * 20_000,
+                dts_us: Some(i * 20_000),
+                duration_us: Some(20_000),
+                keyframe: i % 50 == 0,
+                data: vec![0x21, i as u8],
+            })
+            .collect();
+        let audio_stream =
+            MuxStream::audio(MuxCodec::AAC, 48_000, 2).with_codec_private(vec![0x11, 0x90]);
+        let (audio_init, audio_segments) = fragmented(audio_stream, audio);
+
+        let files: Files = Arc::default();
+        let base = serve(files.clone());
+        {
+            let mut files = files.lock().unwrap();
+            files.insert("/dash/v/init.mp4".into(), video_init);
+            files.insert("/dash/a/init.mp4".into(), audio_init);
+            for (i, segment) in video_segments.into_iter().enumerate() {
+                files.insert(format!("/dash/v/{:03}.m4s", i + 1), segment);
+            }
+            for (i, segment) in audio_segments.into_iter().enumerate() {
+                files.insert(format!("/dash/a/{:03}.m4s", i + 1), segment);
+            }
+            files.insert(
+                "/dash/manifest.mpd".into(),
+                br#"<MPD type="static" mediaPresentationDuration="PT3S"><Period>
+  <AdaptationSet mimeType="video/mp4">
+    <SegmentTemplate timescale="90000" duration="90000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Number%03d$.m4s"/>
+    <Representation id="v" bandwidth="300000" width="320" height="240"/>
+  </AdaptationSet>
+  <AdaptationSet mimeType="audio/mp4" lang="en">
+    <SegmentTemplate timescale="48000" duration="48000" initialization="a/init.mp4" media="a/$Number%03d$.m4s"/>
+    <Representation id="a" bandwidth="64000"/>
+  </AdaptationSet>
+</Period></MPD>"#
+                    .to_vec(),
+            );
+        }
+
+        let mut client = AdaptiveClient::open(
+            &format!("{}/dash/manifest.mpd", base),
+            AdaptiveConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(client.kind(), ManifestKind::Dash);
+        assert!(!client.is_live());
+
+        let mut order = Vec::new();
+        let mut video_pts = Vec::new();
+        let mut audio_pts = Vec::new();
+        while let Some(segment) = client.next_segment().unwrap() {
+            assert_eq!(segment.format, SegmentFormat::Fmp4);
+            order.push((segment.track, segment.sequence));
+            let packets = segment.packets().unwrap();
+            let pts = packets.iter().filter_map(|p| p.pts_us);
+            match segment.track {
+                MediaKind::Main => {
+                    assert!(packets[0].keyframe);
+                    video_pts.extend(pts);
+                }
+                MediaKind::Audio => audio_pts.extend(pts),
+            }
+        }
+        // Interleaved by media time, video first at each tie
+        assert_eq!(
+            order,
+            [
+                (MediaKind::Main, 1),
+                (MediaKind::Audio, 1),
+                (MediaKind::Main, 2),
+                (MediaKind::Audio, 2),
+                (MediaKind::Main, 3),
+                (MediaKind::Audio, 3),
+            ]
+        );
+        // Each fragment's tfdt places it on the shared timeline
+        assert_eq!(video_pts, (0..75).map(|i| i * 40_000).collect::<Vec<_>>());
+        assert_eq!(audio_pts, (0..150).map(|i| i * 20_000).collect::<Vec<_>>());
+    }
+}
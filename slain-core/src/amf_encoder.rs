@@ -889,16 +889,74 @@ impl Drop for AmfEncoder {
 
 use parking_lot::Mutex;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
 use once_cell::sync::Lazy;
 
+use crate::h264_utils;
+use crate::mp4_mux::{self, Mp4MuxConfig, Mp4Muxer};
+use crate::mux::{MuxCodec, MuxFormat, MuxPacket, MuxStream};
+
 static RECORDING_STATE: Lazy<Mutex<Option<RecordingSession>>> = Lazy::new(|| Mutex::new(None));
 
+enum RecordingOutput {
+    /// Annex B elementary stream, as the encoder produces it
+    Raw(File),
+    /// MP4 with avcC/hvcC built from the in-band parameter sets
+    Mp4(Box<Mp4Muxer<BufWriter<File>>>),
+}
+
 struct RecordingSession {
     encoder: AmfEncoder,
-    output_file: File,
+    output: RecordingOutput,
     frame_count: u64,
+    packets_written: u64,
+    frame_duration_us: i64,
+    hevc: bool,
+}
+
+impl RecordingSession {
+    fn write_output(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        match &mut self.output {
+            RecordingOutput::Raw(file) => file
+                .write_all(data)
+                .map_err(|e| format!("Write error: {}", e))?,
+            RecordingOutput::Mp4(muxer) => {
+                // Recordings run without B-frames, so output order is display order
+                let pts_us = self.packets_written as i64 * self.frame_duration_us;
+                muxer.write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us,
+                    dts_us: Some(pts_us),
+                    duration_us: Some(self.frame_duration_us),
+                    keyframe: h264_utils::annexb_has_idr(data, self.hevc),
+                    data: data.to_vec(),
+                })?
+            }
+        }
+        self.packets_written += 1;
+        Ok(())
+    }
+
+    /// Flush and close the output (MP4 gets its moov moved to the front)
+    fn finish_output(self) -> Result<(), String> {
+        match self.output {
+            RecordingOutput::Raw(mut file) => {
+                file.flush().map_err(|e| format!("Flush error: {}", e))
+            }
+            RecordingOutput::Mp4(muxer) => {
+                let mut file = muxer
+                    .finish()?
+                    .into_inner()
+                    .map_err(|e| format!("Flush error: {}", e))?;
+                mp4_mux::faststart(&mut file).map(|_| ())
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -950,38 +1008,84 @@ pub fn amf_start_recording(
         ..Default::default()
     };
 
+    let codec = match config.codec {
+        AmfCodec::H264Avc => MuxCodec::H264,
+        AmfCodec::H265Hevc => MuxCodec::H265,
+        AmfCodec::Av1 => MuxCodec::AV1,
+    };
+    let mp4 = MuxFormat::from_path(Path::new(&path)) == Some(MuxFormat::Mp4);
+    if mp4 && codec == MuxCodec::AV1 {
+        return Err("AV1 recordings can only be written as a raw stream".to_string());
+    }
+    let frame_duration_us = 1_000_000 / fps.max(1) as i64;
+
     let mut encoder = AmfEncoder::new(config)?;
     encoder.initialize()?;
 
-    let file = File::create(&path).map_err(|e| format!("Failed to create output file: {}", e))?;
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create output file: {}", e))?;
+    let output = if mp4 {
+        let mut stream = MuxStream::video(codec, width, height);
+        stream.frame_duration_us = Some(frame_duration_us as u64);
+        RecordingOutput::Mp4(Box::new(Mp4Muxer::new(
+            BufWriter::new(file),
+            vec![stream],
+            Mp4MuxConfig::default(),
+        )?))
+    } else {
+        RecordingOutput::Raw(file)
+    };
 
     *state = Some(RecordingSession {
         encoder,
-        output_file: file,
+        output,
         frame_count: 0,
+        packets_written: 0,
+        frame_duration_us,
+        hevc: codec == MuxCodec::H265,
     });
 
     Ok(format!("Recording started: {}", path))
 }
 
-pub fn amf_stop_recording() -> Result<serde_json::Value, String> {
+/// Encode one raw frame (in the configured surface format) into the recording
+pub fn amf_record_frame(frame_data: &[u8]) -> Result<(), String> {
     let mut state = RECORDING_STATE.lock();
+    let session = state.as_mut().ok_or("Not recording")?;
+
+    // AMF timestamps are in 100 ns units
+    let pts = session.frame_count as i64 * session.frame_duration_us * 10;
+    let output = session.encoder.encode_frame(frame_data, pts)?;
+    session.frame_count += 1;
+    session.write_output(&output)
+}
 
-    let session = state.take().ok_or("Not recording")?;
+pub fn amf_stop_recording() -> Result<serde_json::Value, String> {
+    let mut state = RECORDING_STATE.lock();
 
-    let mut encoder = session.encoder;
-    let mut file = session.output_file;
+    let mut session = state.take().ok_or("Not recording")?;
 
-    let remaining = encoder.flush()?;
+    let remaining = session.encoder.flush()?;
     for data in remaining {
-        file.write_all(&data)
-            .map_err(|e| format!("Write error: {}", e))?;
+        session.write_output(&data)?;
     }
 
-    let stats = encoder.get_stats();
+    let stats = session.encoder.get_stats();
+    let frames = session.frame_count;
+    let container = match session.output {
+        RecordingOutput::Raw(_) => "raw",
+        RecordingOutput::Mp4(_) => "mp4",
+    };
+    session.finish_output()?;
 
     Ok(serde_json::json!({
-        "frames": session.frame_count,
+        "frames": frames,
+        "container": container,
         "stats": stats,
     }))
 }
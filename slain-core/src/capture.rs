@@ -0,0 +1,822 @@
+//! Screenshots and lossless clip export
+//!
+//! Screenshots are taken at the source's native resolution, either straight
+//! from the decoder (pre-filter) or after the container's filter chain from
+//! `filter_pipeline` (post-filter), and written through `imaging::save_image`.
+//!
+//! Clip export copies compressed packets between two points into a new MKV,
+//! WebM or MP4 without re-encoding. The start snaps back to the video keyframe
+//! at or before A so the clip decodes cleanly; B is honoured to the packet.
+//! Timestamps are rebased so the clip starts at zero.
+
+use crate::filter_pipeline::{ContainerFormat, FilterRegistry, Frame, PixelFormat as FilterFormat};
+use crate::frame_interpolation::RgbFrame;
+use crate::hw_decode::{decode_file, DecodedFrame, FileDecodeOptions};
+use crate::imaging::save_image;
+use crate::mux::{MuxCodec, MuxFormat, MuxPacket, MuxStream, MuxTrackKind, UniversalMuxer};
+use crate::pixel_convert::{ColorSpace, PixelConverter, PixelFormat, VideoFrame};
+use image::{DynamicImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Packets of other streams may trail the end point by this much in decode
+/// order before the copy gives up waiting for the next video keyframe
+const END_MARGIN_US: i64 = 2_000_000;
+
+// ============================================================================
+// Screenshots
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScreenshotStage {
+    /// Decoder output, before any filters
+    PreFilter,
+    /// After the container's filter chain (what the player shows)
+    #[default]
+    PostFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenshotOptions {
+    pub stage: ScreenshotStage,
+    /// JPEG/WebP quality (PNG ignores it)
+    pub quality: u8,
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        Self {
+            stage: ScreenshotStage::PostFilter,
+            quality: 90,
+        }
+    }
+}
+
+/// Image formats a screenshot can be written as, by extension
+pub const SCREENSHOT_FORMATS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// Convert a decoder frame to packed RGB24 at its native size
+pub fn decoded_to_rgb(frame: DecodedFrame) -> Result<RgbFrame, String> {
+    use crate::hw_decode::PixelFormat as HwFormat;
+
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let format = match frame.format {
+        HwFormat::NV12 => PixelFormat::NV12,
+        HwFormat::P010 => PixelFormat::P010,
+        HwFormat::P016 => PixelFormat::P012,
+        HwFormat::YUV420 => PixelFormat::YUV420P,
+    };
+    let mut src = VideoFrame::new(width, height, format);
+    if frame.data.len() < src.data.len() {
+        return Err(format!(
+            "Decoded frame is {} bytes, expected {}",
+            frame.data.len(),
+            src.data.len()
+        ));
+    }
+    src.data = frame.data;
+    let mut dst = VideoFrame::new(width, height, PixelFormat::RGB24);
+    PixelConverter::new(format, PixelFormat::RGB24, width, height, ColorSpace::BT709)
+        .convert(&src, &mut dst)?;
+    RgbFrame::new(frame.width, frame.height, dst.data)
+}
+
+/// Decode `path` up to `time_ms` and return the frame showing at that time
+/// (the last frame if the file is shorter)
+pub fn grab_frame(path: &Path, time_ms: u64) -> Result<RgbFrame, String> {
+    let options = FileDecodeOptions {
+        start_ms: time_ms,
+        ..Default::default()
+    };
+    let mut last: Option<DecodedFrame> = None;
+    decode_file(path, options, |frame| {
+        let reached = frame.pts >= time_ms as i64;
+        // Keep the frame that is on screen at `time_ms`: the last one
+        // before it, unless this one lands exactly on it
+        if last.is_none() || !reached || frame.pts == time_ms as i64 {
+            last = Some(frame);
+        }
+        Ok(!reached)
+    })?;
+    let frame = last.ok_or_else(|| format!("No frames decoded from {:?}", path))?;
+    decoded_to_rgb(frame)
+}
+
+/// Run `frame` through the filter chain configured for `path`'s container
+pub fn apply_filters(frame: RgbFrame, path: &Path, time_ms: u64) -> RgbFrame {
+    let Some(container) = ContainerFormat::from_path(path) else {
+        return frame;
+    };
+    let mut chain = {
+        let registry = FilterRegistry::global().read();
+        let spec = registry.chain_spec_for(&container);
+        registry.build_chain(spec)
+    };
+    let (width, height) = (frame.width, frame.height);
+    let output = chain.process_frame(Frame {
+        data: frame.data,
+        width,
+        height,
+        format: FilterFormat::PlanarRgb,
+        pts_us: time_ms as i64 * 1000,
+    });
+    RgbFrame {
+        width,
+        height,
+        data: output.data,
+    }
+}
+
+/// Write an RGB frame as PNG, JPEG or WebP depending on `output`'s extension
+pub fn save_screenshot(frame: &RgbFrame, output: &Path, quality: u8) -> Result<(), String> {
+    let ext = output
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if !SCREENSHOT_FORMATS.contains(&ext.as_str()) {
+        return Err(format!("Unsupported screenshot format: {:?}", output));
+    }
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let image = RgbImage::from_raw(frame.width, frame.height, frame.data.clone())
+        .ok_or("RGB buffer does not match frame size")?;
+    save_image(&DynamicImage::ImageRgb8(image), output, Some(quality))
+}
+
+/// Decode, optionally filter, and save the frame at `time_ms` of `input`
+pub fn screenshot_file(
+    input: &Path,
+    time_ms: u64,
+    output: &Path,
+    options: &ScreenshotOptions,
+) -> Result<(), String> {
+    let mut frame = grab_frame(input, time_ms)?;
+    if options.stage == ScreenshotStage::PostFilter {
+        frame = apply_filters(frame, input, time_ms);
+    }
+    save_screenshot(&frame, output, options.quality)?;
+    tracing::info!(
+        "Saved {}x{} screenshot at {} ms to {:?}",
+        frame.width,
+        frame.height,
+        time_ms,
+        output
+    );
+    Ok(())
+}
+
+/// Pictures/SLAIN, falling back to the working directory
+pub fn default_screenshot_dir() -> PathBuf {
+    dirs::picture_dir()
+        .map(|p| p.join("SLAIN"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// `<dir>/<video stem>_HH-MM-SS.mmm.<ext>`
+pub fn screenshot_path(dir: &Path, video: &Path, time_ms: u64, ext: &str) -> PathBuf {
+    let stem = video
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("screenshot");
+    let secs = time_ms / 1000;
+    dir.join(format!(
+        "{}_{:02}-{:02}-{:02}.{:03}.{}",
+        stem,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60,
+        time_ms % 1000,
+        ext
+    ))
+}
+
+// ============================================================================
+// Clip Export
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipReport {
+    pub output: PathBuf,
+    pub requested_start_ms: u64,
+    /// Start after snapping back to a keyframe
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub streams: usize,
+    pub packets: u64,
+}
+
+/// Packet reader over an MKV or MP4 source, mapping the streams it keeps to
+/// output stream indices (also used by `hls_packager`)
+pub(crate) enum ClipSource {
+    Mkv {
+        demuxer: Box<crate::mkv::MkvDemuxer<BufReader<File>>>,
+        /// Track number -> output stream
+        map: HashMap<u64, usize>,
+    },
+    Mp4 {
+        demuxer: crate::mp4_demux::mp4::Mp4Demuxer<BufReader<File>>,
+        /// Stream index -> output stream
+        map: HashMap<u32, usize>,
+    },
+}
+
+impl ClipSource {
+    /// Open `path` and describe the streams `accept` keeps
+    pub(crate) fn open(
+        path: &Path,
+        accept: impl Fn(&MuxStream) -> bool,
+    ) -> Result<(Self, Vec<MuxStream>), String> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let file = File::open(path).map_err(|e| format!("Open error: {}", e))?;
+        let reader = BufReader::new(file);
+        let mut streams = Vec::new();
+
+        match ext.as_str() {
+            "mkv" | "mka" | "webm" => {
+                let info = crate::mkv::MkvParser::new().parse(path)?;
+                let mut map = HashMap::new();
+                for track in &info.tracks {
+                    let Some((number, stream)) = mkv_stream(track) else {
+                        continue;
+                    };
+                    if accept(&stream) {
+                        map.insert(number, streams.len());
+                        streams.push(stream);
+                    }
+                }
+                let demuxer = crate::mkv::MkvDemuxer::new(reader, info)?;
+                Ok((
+                    Self::Mkv {
+                        demuxer: Box::new(demuxer),
+                        map,
+                    },
+                    streams,
+                ))
+            }
+            "mp4" | "m4v" | "mov" => {
+                let demuxer = crate::mp4_demux::mp4::Mp4Demuxer::new(reader)
+                    .map_err(|e| format!("Demux init: {}", e))?;
+                let mut map = HashMap::new();
+                for (i, info) in demuxer.streams().iter().enumerate() {
+                    let Some(stream) = mp4_stream(&demuxer, i, info) else {
+                        continue;
+                    };
+                    if accept(&stream) {
+                        map.insert(info.index, streams.len());
+                        streams.push(stream);
+                    }
+                }
+                Ok((Self::Mp4 { demuxer, map }, streams))
+            }
+            other => Err(format!("Unsupported source container: {}", other)),
+        }
+    }
+
+    pub(crate) fn seek(&mut self, time_ms: u64) -> Result<(), String> {
+        match self {
+            Self::Mkv { demuxer, .. } => demuxer.seek(time_ms),
+            Self::Mp4 { demuxer, .. } => demuxer.seek(time_ms as i64 * 1000),
+        }
+    }
+
+    /// Next packet of a copied stream, in source microseconds
+    pub(crate) fn next_packet(&mut self) -> Option<MuxPacket> {
+        match self {
+            Self::Mkv { demuxer, map } => loop {
+                let packet = demuxer.read_packet()?;
+                let Some(&stream) = map.get(&packet.track_number) else {
+                    continue;
+                };
+                return Some(MuxPacket {
+                    stream,
+                    pts_us: packet.pts_ms * 1000,
+                    dts_us: None,
+                    duration_us: packet.duration_ms.map(|d| d * 1000),
+                    keyframe: packet.keyframe,
+                    data: packet.data,
+                });
+            },
+            Self::Mp4 { demuxer, map } => loop {
+                let packet = demuxer.read_packet()?;
+                let Some(&stream) = map.get(&packet.stream_index) else {
+                    continue;
+                };
+                return Some(MuxPacket {
+                    stream,
+                    pts_us: packet.pts,
+                    dts_us: Some(packet.dts),
+                    duration_us: (packet.duration > 0).then_some(packet.duration),
+                    keyframe: packet.keyframe,
+                    data: packet.data,
+                });
+            },
+        }
+    }
+}
+
+fn mkv_stream(track: &crate::mkv::MkvTrack) -> Option<(u64, MuxStream)> {
+    use crate::mkv::MkvTrack;
+
+    let (number, mut stream, name, language, default, forced) = match track {
+        MkvTrack::Video(v) => {
+            let codec = MuxCodec::from_mkv_codec_id(&v.codec_id)?;
+            let mut stream = MuxStream::video(codec, v.pixel_width, v.pixel_height);
+            stream.codec_private = v.codec_private.clone();
+            stream.frame_duration_us = v
+                .frame_rate
+                .filter(|fps| *fps > 0.0)
+                .map(|fps| (1_000_000.0 / fps).round() as u64);
+            (
+                v.track_number,
+                stream,
+                &v.name,
+                &v.language,
+                v.default,
+                v.forced,
+            )
+        }
+        MkvTrack::Audio(a) => {
+            let codec = MuxCodec::from_mkv_codec_id(&a.codec_id)?;
+            let mut stream = MuxStream::audio(codec, a.sample_rate as u32, a.channels as u16);
+            if let MuxTrackKind::Audio { bit_depth, .. } = &mut stream.kind {
+                *bit_depth = a.bit_depth.map(|b| b as u16);
+            }
+            stream.codec_private = a.codec_private.clone();
+            (
+                a.track_number,
+                stream,
+                &a.name,
+                &a.language,
+                a.default,
+                a.forced,
+            )
+        }
+        MkvTrack::Subtitle(s) => {
+            let codec = MuxCodec::from_mkv_codec_id(&s.codec_id)?;
+            let stream = MuxStream::subtitle(codec);
+            (
+                s.track_number,
+                stream,
+                &s.name,
+                &s.language,
+                s.default,
+                s.forced,
+            )
+        }
+        MkvTrack::Other(_) => return None,
+    };
+    stream.name = name.clone();
+    stream.language = language.clone();
+    stream.default = default;
+    stream.forced = forced;
+    Some((number, stream))
+}
+
+fn mp4_stream(
+    demuxer: &crate::mp4_demux::mp4::Mp4Demuxer<BufReader<File>>,
+    index: usize,
+    info: &crate::mp4_demux::StreamInfo,
+) -> Option<MuxStream> {
+    let codec = MuxCodec::from_mp4_codec(&info.codec)?;
+    let mut stream = if codec.is_video() {
+        let video = demuxer.video_info(index)?;
+        let mut stream = MuxStream::video(codec, video.width, video.height);
+        if video.fps_num > 0 && video.fps_den > 0 {
+            stream.frame_duration_us =
+                Some(video.fps_den as u64 * 1_000_000 / video.fps_num as u64);
+        }
+        stream
+    } else {
+        let audio = demuxer.audio_info(index)?;
+        let mut stream = MuxStream::audio(codec, audio.sample_rate, audio.channels as u16);
+        if let MuxTrackKind::Audio { bit_depth, .. } = &mut stream.kind {
+            *bit_depth = Some(audio.bits_per_sample as u16).filter(|b| *b > 0);
+        }
+        stream
+    }
+    .with_codec_private(info.extra_data.clone());
+    if let Some(language) = &info.language {
+        stream.language = language.clone();
+    }
+    stream.name = info.title.clone();
+    stream.default = info.default;
+    stream.forced = info.forced;
+    Some(stream)
+}
+
+/// Copy `start_ms..end_ms` of `input` into `output` (container chosen by
+/// extension). Streams the output container can't hold are left out.
+pub fn export_clip(
+    input: &Path,
+    output: &Path,
+    start_ms: u64,
+    end_ms: u64,
+) -> Result<ClipReport, String> {
+    if end_ms <= start_ms {
+        return Err(format!(
+            "Clip end ({} ms) must be after its start ({} ms)",
+            end_ms, start_ms
+        ));
+    }
+    let format = MuxFormat::from_path(output)
+        .ok_or_else(|| format!("Unsupported output container: {:?}", output))?;
+    let (mut source, streams) = ClipSource::open(input, |s| format.supports(s))?;
+    if streams.is_empty() {
+        return Err(format!(
+            "No streams in {:?} can be stored in {:?}",
+            input, output
+        ));
+    }
+    // Cuts follow the first video stream; audio-only sources cut anywhere
+    let anchor = streams.iter().position(|s| s.codec.is_video()).unwrap_or(0);
+    let stream_count = streams.len();
+    source.seek(start_ms)?;
+
+    let start_us = start_ms as i64 * 1000;
+    let end_us = end_ms as i64 * 1000;
+    let mut muxer = UniversalMuxer::create(output, streams)?;
+    let mut pending: Vec<MuxPacket> = Vec::new();
+    let mut candidate: Option<i64> = None;
+    let mut snap: Option<i64> = None;
+    let mut packets = 0u64;
+
+    let mut write = |muxer: &mut UniversalMuxer, mut packet: MuxPacket, origin: i64| {
+        packet.pts_us -= origin;
+        packet.dts_us = packet.dts_us.map(|dts| dts - origin);
+        packets += 1;
+        muxer.write_packet(&packet)
+    };
+    // The end is cut in decode order: anchor packets presenting at or after
+    // it are held until a later one shows whether they are references (a
+    // B-frame before the end still needs the P-frame it follows)
+    let mut held: Vec<MuxPacket> = Vec::new();
+    // Writes `packet` if it belongs in the clip; false once the clip is done
+    let mut push = |muxer: &mut UniversalMuxer, packet: MuxPacket, origin: i64| {
+        let is_anchor = packet.stream == anchor;
+        if packet.pts_us < end_us {
+            if is_anchor {
+                for reference in held.drain(..) {
+                    write(muxer, reference, origin)?;
+                }
+            }
+            write(muxer, packet, origin)?;
+            return Ok::<_, String>(true);
+        }
+        if is_anchor && packet.keyframe {
+            return Ok(false);
+        }
+        let done = packet.pts_us >= end_us + END_MARGIN_US;
+        if is_anchor {
+            held.push(packet);
+        }
+        Ok(!done)
+    };
+
+    while let Some(packet) = source.next_packet() {
+        let is_anchor_key = packet.stream == anchor && packet.keyframe;
+
+        let Some(origin) = snap else {
+            // Pre-roll: keep everything since the latest anchor keyframe at
+            // or before the start until the cut point is known
+            if is_anchor_key && (packet.pts_us <= start_us || candidate.is_none()) {
+                pending.clear();
+                candidate = Some(packet.pts_us);
+                let past_start = packet.pts_us > start_us;
+                pending.push(packet);
+                if !past_start {
+                    continue;
+                }
+            } else if is_anchor_key {
+                // First keyframe after the start: the GOP before it is the cut
+                pending.push(packet);
+            } else {
+                if candidate.is_some() {
+                    pending.push(packet);
+                }
+                continue;
+            }
+            let origin = candidate.unwrap_or(start_us);
+            snap = Some(origin);
+            let mut open = true;
+            for packet in pending.drain(..) {
+                if packet.stream != anchor && packet.pts_us < origin {
+                    continue;
+                }
+                if !push(&mut muxer, packet, origin)? {
+                    open = false;
+                    break;
+                }
+            }
+            if !open {
+                break;
+            }
+            continue;
+        };
+
+        if !push(&mut muxer, packet, origin)? {
+            break;
+        }
+    }
+
+    // Source ended during pre-roll (start inside the last GOP)
+    let origin = match snap {
+        Some(origin) => origin,
+        None => {
+            let origin = candidate.unwrap_or(start_us);
+            for packet in pending.drain(..) {
+                if packet.stream != anchor && packet.pts_us < origin {
+                    continue;
+                }
+                if !push(&mut muxer, packet, origin)? {
+                    break;
+                }
+            }
+            origin
+        }
+    };
+
+    if packets == 0 {
+        return Err(format!(
+            "No packets between {} ms and {} ms in {:?}",
+            start_ms, end_ms, input
+        ));
+    }
+    muxer.finish()?;
+
+    let report = ClipReport {
+        output: output.to_path_buf(),
+        requested_start_ms: start_ms,
+        start_ms: origin.max(0) as u64 / 1000,
+        end_ms,
+        streams: stream_count,
+        packets,
+    };
+    tracing::info!(
+        "Exported clip {}..{} ms ({} packets) to {:?}",
+        report.start_ms,
+        report.end_ms,
+        report.packets,
+        output
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4_demux::mp4::Mp4Demuxer;
+    use crate::mp4_mux::{Mp4MuxConfig, Mp4Muxer};
+
+    const FRAME_US: i64 = 40_000;
+    const AUDIO_US: i64 = 21_333;
+
+    /// 4 s of 25 fps "video" with a keyframe every 10 frames plus AAC-sized
+    /// audio packets, interleaved in decode order
+    fn write_source(path: &Path) {
+        let streams = vec![
+            MuxStream::video(MuxCodec::H264, 64, 48)
+                .with_codec_private(vec![1, 0x64, 0, 0x1f, 0xff, 0xe0, 0]),
+            MuxStream::audio(MuxCodec::AAC, 48000, 2).with_codec_private(vec![0x11, 0x90]),
+        ];
+        let file = std::io::BufWriter::new(File::create(path).unwrap());
+        let mut muxer = Mp4Muxer::new(file, streams, Mp4MuxConfig::default()).unwrap();
+        let mut audio_pts = 0i64;
+        for i in 0..100i64 {
+            let pts = i * FRAME_US;
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: pts,
+                    dts_us: Some(pts),
+                    duration_us: Some(FRAME_US),
+                    keyframe: i % 10 == 0,
+                    data: vec![i as u8; 32],
+                })
+                .unwrap();
+            while audio_pts < pts + FRAME_US {
+                muxer
+                    .write_packet(&MuxPacket {
+                        stream: 1,
+                        pts_us: audio_pts,
+                        dts_us: Some(audio_pts),
+                        duration_us: Some(AUDIO_US),
+                        keyframe: true,
+                        data: vec![0xAA; 8],
+                    })
+                    .unwrap();
+                audio_pts += AUDIO_US;
+            }
+        }
+        muxer.finish().unwrap();
+    }
+
+    fn read_all(path: &Path) -> Vec<crate::mp4_demux::Packet> {
+        let file = BufReader::new(File::open(path).unwrap());
+        let mut demuxer = Mp4Demuxer::new(file).unwrap();
+        std::iter::from_fn(|| demuxer.read_packet()).collect()
+    }
+
+    #[test]
+    fn test_clip_snaps_to_keyframe_and_rebases() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mp4");
+        let clip = dir.path().join("clip.mp4");
+        write_source(&source);
+
+        let report = export_clip(&source, &clip, 1000, 2000).unwrap();
+        assert_eq!(report.start_ms, 800);
+        assert_eq!(report.streams, 2);
+
+        let packets = read_all(&clip);
+        let video: Vec<_> = packets.iter().filter(|p| p.stream_index == 0).collect();
+        // Frames 20..50 (0.8 s up to, not including, 2.0 s)
+        assert_eq!(video.len(), 30);
+        assert_eq!(video[0].data, vec![20u8; 32]);
+        assert_eq!(video[0].pts, 0);
+        assert_eq!(video[29].pts, 29 * FRAME_US);
+        let keys: Vec<i64> = video.iter().filter(|p| p.keyframe).map(|p| p.pts).collect();
+        assert_eq!(keys, vec![0, 400_000, 800_000]);
+
+        let audio: Vec<_> = packets.iter().filter(|p| p.stream_index == 1).collect();
+        assert!(!audio.is_empty());
+        assert!(audio.iter().all(|p| p.pts < 1_200_000));
+    }
+
+    #[test]
+    fn test_clip_end_keeps_b_frame_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mp4");
+        let clip = dir.path().join("clip.mp4");
+
+        // I P B B P B B ... in decode order, presenting one frame late so
+        // pts never precedes dts; a keyframe every 12 frames
+        let streams = vec![MuxStream::video(MuxCodec::H264, 64, 48)
+            .with_codec_private(vec![1, 0x64, 0, 0x1f, 0xff, 0xe0, 0])];
+        let file = std::io::BufWriter::new(File::create(&source).unwrap());
+        let mut muxer = Mp4Muxer::new(file, streams, Mp4MuxConfig::default()).unwrap();
+        for k in 0..31i64 {
+            let display = match k {
+                0 => 0,
+                _ => 3 * ((k - 1) / 3) + [3, 1, 2][((k - 1) % 3) as usize],
+            };
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: (display + 1) * FRAME_US,
+                    dts_us: Some(k * FRAME_US),
+                    duration_us: Some(FRAME_US),
+                    keyframe: display % 12 == 0,
+                    data: vec![display as u8; 32],
+                })
+                .unwrap();
+        }
+        muxer.finish().unwrap();
+
+        // Frames 0..=2 present before the end; B-frames 1 and 2 follow P-frame
+        // 3 in decode order and need it
+        export_clip(&source, &clip, 0, 150).unwrap();
+        let order: Vec<u8> = read_all(&clip).iter().map(|p| p.data[0]).collect();
+        assert_eq!(order, vec![0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_mkv_seek_lands_on_keyframe() {
+        use crate::mkv::{MkvDemuxer, MkvParser};
+        use crate::test_support::write_h264_mkv;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mkv");
+        write_h264_mkv(&source, 50, 10);
+
+        let info = MkvParser::new().parse(&source).unwrap();
+        let mut demuxer = MkvDemuxer::open(&source, info).unwrap();
+        let video = demuxer.video_track().unwrap();
+        for (time_ms, keyframe_ms) in [(1030, 800), (1200, 1200), (0, 0), (399, 0)] {
+            demuxer.seek(time_ms).unwrap();
+            let packet = std::iter::from_fn(|| demuxer.read_packet())
+                .find(|p| p.track_number == video)
+                .unwrap();
+            assert!(packet.keyframe, "{} ms", time_ms);
+            assert_eq!(packet.pts_ms, keyframe_ms, "{} ms", time_ms);
+        }
+    }
+
+    #[test]
+    fn test_clip_from_mkv_source() {
+        use crate::mkv::{MkvDemuxer, MkvParser};
+        use crate::test_support::write_h264_mkv;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mkv");
+        let clip = dir.path().join("clip.mkv");
+        write_h264_mkv(&source, 100, 10);
+
+        let report = export_clip(&source, &clip, 1000, 2000).unwrap();
+        assert_eq!(report.start_ms, 800);
+        assert_eq!(report.streams, 2);
+
+        let info = MkvParser::new().parse(&clip).unwrap();
+        let mut demuxer = MkvDemuxer::open(&clip, info).unwrap();
+        let packets: Vec<_> = std::iter::from_fn(|| demuxer.read_packet()).collect();
+        let video: Vec<_> = packets.iter().filter(|p| p.track_number == 1).collect();
+        assert_eq!(video.len(), 30);
+        assert_eq!(video[29].pts_ms, 29 * 40);
+        let keys: Vec<i64> = video
+            .iter()
+            .filter(|p| p.keyframe)
+            .map(|p| p.pts_ms)
+            .collect();
+        assert_eq!(keys, vec![0, 400, 800]);
+        let audio: Vec<_> = packets.iter().filter(|p| p.track_number == 2).collect();
+        assert!(!audio.is_empty());
+        assert!(audio.iter().all(|p| p.pts_ms < 1200));
+    }
+
+    #[test]
+    fn test_grab_frame_from_mkv_source() {
+        use crate::test_support::{h264_frame_luma, write_h264_mkv};
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mkv");
+        write_h264_mkv(&source, 50, 10);
+
+        // Limited-range grey luma to full-range RGB
+        let grey = |index: usize| (h264_frame_luma(index) as f32 - 16.0) * 255.0 / 219.0;
+        for (time_ms, index) in [(0, 0), (1000, 25), (1030, 25), (1500, 37)] {
+            let frame = grab_frame(&source, time_ms).unwrap();
+            assert_eq!((frame.width, frame.height), (64, 48));
+            let centre = (24 * 64 + 32) * 3;
+            let value = frame.data[centre] as f32;
+            assert!(
+                (value - grey(index)).abs() < 2.5,
+                "{} ms: got {}, want frame {} ({})",
+                time_ms,
+                value,
+                index,
+                grey(index)
+            );
+        }
+
+        let shot = dir.path().join("shot.png");
+        screenshot_file(
+            &source,
+            1000,
+            &shot,
+            &ScreenshotOptions {
+                stage: ScreenshotStage::PreFilter,
+                quality: 90,
+            },
+        )
+        .unwrap();
+        let image = image::open(&shot).unwrap();
+        assert_eq!((image.width(), image.height()), (64, 48));
+    }
+
+    #[test]
+    fn test_clip_rejects_bad_range_and_container() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mp4");
+        write_source(&source);
+
+        assert!(export_clip(&source, &dir.path().join("a.mp4"), 2000, 1000).is_err());
+        assert!(export_clip(&source, &dir.path().join("a.avi"), 0, 1000).is_err());
+        // H.264/AAC can't go into WebM
+        assert!(export_clip(&source, &dir.path().join("a.webm"), 0, 1000).is_err());
+    }
+
+    #[test]
+    fn test_save_screenshot_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..32 * 18 * 3).map(|i| (i % 251) as u8).collect();
+        let frame = RgbFrame::new(32, 18, data).unwrap();
+
+        for ext in SCREENSHOT_FORMATS {
+            let path = dir.path().join("shots").join(format!("frame.{}", ext));
+            save_screenshot(&frame, &path, 85).unwrap();
+            let image = image::open(&path).unwrap();
+            assert_eq!((image.width(), image.height()), (32, 18));
+        }
+        assert!(save_screenshot(&frame, &dir.path().join("frame.bmp"), 85).is_err());
+    }
+
+    #[test]
+    fn test_screenshot_path() {
+        let path = screenshot_path(
+            Path::new("/shots"),
+            Path::new("/videos/movie.mkv"),
+            3_723_045,
+            "png",
+        );
+        assert_eq!(path, Path::new("/shots/movie_01-02-03.045.png"));
+    }
+}
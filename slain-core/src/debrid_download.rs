@@ -0,0 +1,1555 @@
+//! # Debrid Download Manager
+//!
+//! Persistent queue for downloading resolved debrid links to disk.
+//!
+//! - Multi-connection segmented HTTP downloads over `Range` requests
+//! - Resume after restart: segment progress is saved next to a `.part` file
+//! - Per-host connection limits and a global bandwidth cap
+//! - Checksum verification from `Digest`/`X-Checksum-*` headers or the caller
+//!
+//! Servers that ignore `Range` are downloaded over a single connection and
+//! restart from zero after an interruption.
+
+use crate::adaptive_stream::HttpConfig;
+use crate::debrid::ResolvedLink;
+use crate::http_utils::HostSlots;
+use base64::Engine;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Bytes read per request before progress and throttling are applied
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Minimum interval between progress saves while downloading
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// Limits that can be changed while downloads run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadLimits {
+    /// Downloads transferring at the same time
+    pub max_active: usize,
+    /// Connections (segments) per download
+    pub connections_per_download: usize,
+    /// Connections to one host across all downloads
+    pub per_host_connections: usize,
+    /// Total bytes per second, 0 = unlimited
+    pub bandwidth_limit: u64,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self {
+            max_active: 3,
+            connections_per_download: 4,
+            per_host_connections: 8,
+            bandwidth_limit: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadConfig {
+    pub download_dir: PathBuf,
+    pub limits: DownloadLimits,
+    /// Files are not split into segments smaller than this
+    pub min_segment_size: u64,
+    pub http: HttpConfig,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        let download_dir = dirs::download_dir().unwrap_or_else(|| {
+            let mut path = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+            path.push("SLAIN");
+            path.push("Downloads");
+            path
+        });
+        Self {
+            download_dir,
+            limits: DownloadLimits::default(),
+            min_segment_size: 4 * 1024 * 1024,
+            http: HttpConfig {
+                timeout_secs: 30,
+                retries: 5,
+                ..HttpConfig::default()
+            },
+        }
+    }
+}
+
+// ============================================================================
+// Checksums
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// Accepts `md5`, `sha1`/`sha-1`/`sha`, `sha256`/`sha-256`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Some(Self::Md5),
+            "sha" | "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            Self::Md5 => 32,
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+        }
+    }
+
+    /// Lowercase hex digest of a file
+    pub fn hash_file(self, path: &Path) -> Result<String, String> {
+        match self {
+            Self::Md5 => digest_file::<md5::Md5>(path),
+            Self::Sha1 => digest_file::<sha1::Sha1>(path),
+            Self::Sha256 => digest_file::<sha2::Sha256>(path),
+        }
+    }
+}
+
+fn digest_file<D: Digest>(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    /// Lowercase hex
+    pub value: String,
+}
+
+impl Checksum {
+    /// Parse `sha256:<hex>`, `md5=<hex>` or a bare hex digest (algorithm
+    /// picked by length)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (algorithm, value) = match text.split_once([':', '=']) {
+            Some((name, value)) => (
+                ChecksumAlgorithm::parse(name)
+                    .ok_or_else(|| format!("Unsupported checksum algorithm: {}", name))?,
+                value.trim(),
+            ),
+            None => {
+                let algorithm = match text.len() {
+                    32 => ChecksumAlgorithm::Md5,
+                    40 => ChecksumAlgorithm::Sha1,
+                    64 => ChecksumAlgorithm::Sha256,
+                    _ => return Err(format!("Unrecognized checksum: {}", text)),
+                };
+                (algorithm, text)
+            }
+        };
+        Self::from_hex(algorithm, value)
+    }
+
+    fn from_hex(algorithm: ChecksumAlgorithm, value: &str) -> Result<Self, String> {
+        if value.len() != algorithm.hex_len() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid {:?} checksum: {}", algorithm, value));
+        }
+        Ok(Self {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        })
+    }
+
+    fn from_base64(algorithm: ChecksumAlgorithm, value: &str) -> Option<Self> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(value.trim().trim_matches(':'))
+            .ok()?;
+        Self::from_hex(algorithm, &to_hex(&bytes)).ok()
+    }
+
+    /// Full-file checksum advertised in response headers, strongest first.
+    /// `Content-MD5` only describes the full file on a non-range response.
+    pub fn from_headers(header: impl Fn(&str) -> Option<String>, full_body: bool) -> Option<Self> {
+        let mut found = Vec::new();
+        // RFC 3230 `Digest: sha-256=<b64>` and RFC 9530 `Repr-Digest: sha-256=:<b64>:`
+        for name in ["Repr-Digest", "Digest"] {
+            for entry in header(name).unwrap_or_default().split(',') {
+                if let Some((algorithm, value)) = entry.split_once('=') {
+                    found.extend(
+                        ChecksumAlgorithm::parse(algorithm)
+                            .and_then(|algorithm| Self::from_base64(algorithm, value)),
+                    );
+                }
+            }
+        }
+        for (name, algorithm) in [
+            ("X-Checksum-Sha256", ChecksumAlgorithm::Sha256),
+            ("X-Checksum-Sha1", ChecksumAlgorithm::Sha1),
+            ("X-Checksum-Md5", ChecksumAlgorithm::Md5),
+        ] {
+            found.extend(
+                header(name).and_then(|value| Self::from_hex(algorithm, value.trim()).ok()),
+            );
+        }
+        if full_body {
+            found.extend(
+                header("Content-MD5")
+                    .and_then(|value| Self::from_base64(ChecksumAlgorithm::Md5, &value)),
+            );
+        }
+        found.into_iter().max_by_key(|c| c.algorithm.hex_len())
+    }
+}
+
+// ============================================================================
+// Download Types
+// ============================================================================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadRequest {
+    /// Direct download URL
+    pub url: String,
+    /// Defaults to the last URL path segment
+    pub filename: Option<String>,
+    /// Defaults to the configured download directory
+    pub dest_dir: Option<PathBuf>,
+    /// Verified after download; falls back to checksum headers
+    pub checksum: Option<Checksum>,
+    /// Extra request headers (cookies, Referer, ...)
+    pub headers: Vec<(String, String)>,
+    /// Hoster link the URL was resolved from
+    pub source_url: Option<String>,
+}
+
+impl DownloadRequest {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_resolved(link: &ResolvedLink) -> Self {
+        Self {
+            url: link.download_url.clone(),
+            filename: (!link.filename.is_empty()).then(|| link.filename.clone()),
+            source_url: Some(link.original_url.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DownloadState {
+    Queued,
+    Downloading,
+    Verifying,
+    Paused,
+    Completed,
+    Failed,
+}
+
+/// Byte range `[start, end)` fetched by one connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    start: u64,
+    /// Exclusive; `None` while the size is unknown
+    end: Option<u64>,
+    downloaded: u64,
+}
+
+impl Segment {
+    fn remaining(&self) -> Option<u64> {
+        self.end
+            .map(|end| end.saturating_sub(self.start + self.downloaded))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DownloadJob {
+    id: String,
+    url: String,
+    source_url: Option<String>,
+    filename: String,
+    /// Final location; data goes to `<path>.part` until verified
+    path: PathBuf,
+    headers: Vec<(String, String)>,
+    state: DownloadState,
+    total_size: Option<u64>,
+    resumable: bool,
+    segments: Vec<Segment>,
+    checksum: Option<Checksum>,
+    checksum_verified: Option<bool>,
+    error: Option<String>,
+    added_at: i64,
+    completed_at: Option<i64>,
+    #[serde(skip)]
+    speed_bps: u64,
+    #[serde(skip)]
+    meter: Option<(Instant, u64)>,
+}
+
+impl DownloadJob {
+    fn part_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".part");
+        path.into()
+    }
+
+    fn downloaded(&self) -> u64 {
+        self.segments.iter().map(|s| s.downloaded).sum()
+    }
+
+    fn progress(&self) -> DownloadProgress {
+        let downloaded = match self.state {
+            DownloadState::Completed => self.total_size.unwrap_or_else(|| self.downloaded()),
+            _ => self.downloaded(),
+        };
+        DownloadProgress {
+            id: self.id.clone(),
+            url: self.url.clone(),
+            source_url: self.source_url.clone(),
+            filename: self.filename.clone(),
+            path: self.path.clone(),
+            state: self.state,
+            downloaded,
+            total_size: self.total_size,
+            percent: self
+                .total_size
+                .filter(|&size| size > 0)
+                .map(|size| (downloaded as f64 / size as f64 * 100.0) as f32),
+            speed_bps: self.speed_bps,
+            connections: self.segments.len(),
+            resumable: self.resumable,
+            checksum: self.checksum.clone(),
+            checksum_verified: self.checksum_verified,
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// Snapshot of one queued download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadProgress {
+    pub id: String,
+    pub url: String,
+    pub source_url: Option<String>,
+    pub filename: String,
+    pub path: PathBuf,
+    pub state: DownloadState,
+    pub downloaded: u64,
+    pub total_size: Option<u64>,
+    pub percent: Option<f32>,
+    pub speed_bps: u64,
+    pub connections: usize,
+    pub resumable: bool,
+    pub checksum: Option<Checksum>,
+    pub checksum_verified: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// On-disk queue
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedDownloads {
+    limits: Option<DownloadLimits>,
+    jobs: Vec<DownloadJob>,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn filename_from_url(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let last = parsed.path_segments()?.next_back()?;
+    let name = urlencoding::decode(last).ok()?.to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').to_string();
+    if cleaned.is_empty() {
+        "download".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// `dir/name`, or `dir/name (n).ext` when that is taken on disk or in the queue
+fn unique_destination(dir: &Path, name: &str, taken: &[&Path]) -> PathBuf {
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    (0..)
+        .map(|n| match n {
+            0 => dir.join(name),
+            n => dir.join(format!("{} ({}){}", stem, n, ext)),
+        })
+        .find(|path| {
+            let mut part = path.clone().into_os_string();
+            part.push(".part");
+            !path.exists() && !Path::new(&part).exists() && !taken.contains(&path.as_path())
+        })
+        .unwrap_or_else(|| dir.join(name))
+}
+
+/// Split a file into at most `connections` segments of at least `min_size`
+fn plan_segments(
+    size: Option<u64>,
+    resumable: bool,
+    connections: usize,
+    min_size: u64,
+) -> Vec<Segment> {
+    match size {
+        Some(size) if resumable && size > 0 => {
+            let count = size
+                .div_ceil(min_size.max(1))
+                .clamp(1, connections.max(1) as u64);
+            let chunk = size.div_ceil(count);
+            (0..count)
+                .map(|i| Segment {
+                    start: i * chunk,
+                    end: Some(((i + 1) * chunk).min(size)),
+                    downloaded: 0,
+                })
+                .filter(|s| s.end > Some(s.start))
+                .collect()
+        }
+        _ => vec![Segment {
+            start: 0,
+            end: size,
+            downloaded: 0,
+        }],
+    }
+}
+
+/// Shared token bucket; workers block in `take` once over budget
+struct Throttle {
+    rate: AtomicU64,
+    budget: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            budget: Mutex::new((0.0, Instant::now())),
+        }
+    }
+
+    fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::SeqCst);
+        *self.budget.lock() = (0.0, Instant::now());
+    }
+
+    fn take(&self, bytes: usize) {
+        let rate = self.rate.load(Ordering::SeqCst);
+        if rate == 0 {
+            return;
+        }
+        let rate = rate as f64;
+        let wait = {
+            let mut budget = self.budget.lock();
+            let now = Instant::now();
+            // Allow a quarter second of burst after idling
+            budget.0 =
+                (budget.0 + now.duration_since(budget.1).as_secs_f64() * rate).min(rate / 4.0);
+            budget.1 = now;
+            budget.0 -= bytes as f64;
+            Duration::from_secs_f64((-budget.0).max(0.0) / rate)
+        };
+        std::thread::sleep(wait);
+    }
+}
+
+/// What a `Range: bytes=0-0` probe learned about the file
+struct RemoteFile {
+    size: Option<u64>,
+    resumable: bool,
+    checksum: Option<Checksum>,
+}
+
+fn build_agent(http: &HttpConfig) -> ureq::Agent {
+    let timeout = Duration::from_secs(http.timeout_secs.max(1));
+    ureq::AgentBuilder::new()
+        .timeout_connect(timeout)
+        .timeout_read(timeout)
+        .user_agent(&http.user_agent)
+        .build()
+}
+
+fn request(
+    agent: &ureq::Agent,
+    http: &HttpConfig,
+    url: &str,
+    range: Option<String>,
+) -> Result<ureq::Response, String> {
+    let mut request = agent.get(url);
+    for (name, value) in &http.headers {
+        request = request.set(name, value);
+    }
+    if let Some(range) = range {
+        request = request.set("Range", &range);
+    }
+    request.call().map_err(|e| match e {
+        ureq::Error::Status(code, _) => format!("HTTP {}", code),
+        e => format!("Request failed: {}", e),
+    })
+}
+
+fn probe_remote(agent: &ureq::Agent, http: &HttpConfig, url: &str) -> Result<RemoteFile, String> {
+    let response = request(agent, http, url, Some("bytes=0-0".to_string()))?;
+    let partial = response.status() == 206;
+    let header = |name: &str| response.header(name).map(str::to_string);
+    let checksum = Checksum::from_headers(header, !partial);
+    let size = if partial {
+        // Content-Range: bytes 0-0/12345
+        response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.trim().parse().ok())
+    } else {
+        response
+            .header("Content-Length")
+            .and_then(|len| len.trim().parse().ok())
+    };
+    Ok(RemoteFile {
+        size,
+        resumable: partial && size.is_some(),
+        checksum,
+    })
+}
+
+// ============================================================================
+// Download Manager
+// ============================================================================
+
+struct Shared {
+    config: DownloadConfig,
+    limits: Mutex<DownloadLimits>,
+    jobs: Mutex<Vec<DownloadJob>>,
+    /// Stop flags of jobs with a worker thread
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    hosts: HostSlots,
+    throttle: Throttle,
+    state_path: Option<PathBuf>,
+    save_lock: Mutex<()>,
+    last_save: Mutex<Instant>,
+    shutting_down: AtomicBool,
+}
+
+impl Shared {
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+        let _guard = self.save_lock.lock();
+        let json = {
+            let saved = SavedDownloads {
+                limits: Some(self.limits.lock().clone()),
+                jobs: self.jobs.lock().clone(),
+            };
+            serde_json::to_string_pretty(&saved).map_err(|e| e.to_string())?
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| format!("Failed to write download state: {}", e))?;
+        fs::rename(&tmp, path).map_err(|e| format!("Failed to write download state: {}", e))?;
+        *self.last_save.lock() = Instant::now();
+        Ok(())
+    }
+
+    fn save_logged(&self) {
+        if let Err(e) = self.save() {
+            tracing::warn!("Download state not saved: {}", e);
+        }
+    }
+
+    /// Record `bytes` written to a segment
+    fn advance(&self, id: &str, segment: usize, bytes: u64) {
+        {
+            let mut jobs = self.jobs.lock();
+            let Some(job) = jobs.iter_mut().find(|j| j.id == id) else {
+                return;
+            };
+            if let Some(seg) = job.segments.get_mut(segment) {
+                seg.downloaded += bytes;
+            }
+            let downloaded = job.downloaded();
+            match job.meter {
+                Some((since, at)) if since.elapsed() >= Duration::from_secs(1) => {
+                    job.speed_bps =
+                        ((downloaded - at) as f64 / since.elapsed().as_secs_f64()) as u64;
+                    job.meter = Some((Instant::now(), downloaded));
+                }
+                None => job.meter = Some((Instant::now(), downloaded)),
+                _ => {}
+            }
+        }
+        if self.last_save.lock().elapsed() >= SAVE_INTERVAL {
+            self.save_logged();
+        }
+    }
+
+    fn reset_segment(&self, id: &str, segment: usize) {
+        let mut jobs = self.jobs.lock();
+        if let Some(seg) = jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .and_then(|job| job.segments.get_mut(segment))
+        {
+            seg.downloaded = 0;
+        }
+    }
+
+    fn update<R>(&self, id: &str, f: impl FnOnce(&mut DownloadJob) -> R) -> Option<R> {
+        self.jobs.lock().iter_mut().find(|j| j.id == id).map(f)
+    }
+}
+
+/// Queue of downloads running on background threads. State survives
+/// restarts when opened with a path.
+pub struct DownloadManager {
+    shared: Arc<Shared>,
+}
+
+impl DownloadManager {
+    pub fn in_memory(config: DownloadConfig) -> Self {
+        Self::with_jobs(config, None, Vec::new())
+    }
+
+    /// Load the queue from `path` and restart interrupted downloads. Limits
+    /// saved in the file take precedence over `config.limits`.
+    pub fn open(path: &Path, mut config: DownloadConfig) -> Result<Self, String> {
+        let saved: SavedDownloads = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid download state {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedDownloads::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        if let Some(limits) = saved.limits {
+            config.limits = limits;
+        }
+        let mut jobs = saved.jobs;
+        for job in &mut jobs {
+            if matches!(
+                job.state,
+                DownloadState::Downloading | DownloadState::Verifying
+            ) {
+                job.state = DownloadState::Queued;
+            }
+            if job.state != DownloadState::Completed && !job.part_path().exists() {
+                job.segments.clear();
+            }
+        }
+        Ok(Self::with_jobs(config, Some(path.to_path_buf()), jobs))
+    }
+
+    fn with_jobs(
+        config: DownloadConfig,
+        state_path: Option<PathBuf>,
+        jobs: Vec<DownloadJob>,
+    ) -> Self {
+        let limits = config.limits.clone();
+        let shared = Arc::new(Shared {
+            hosts: HostSlots::new(limits.per_host_connections),
+            throttle: Throttle::new(limits.bandwidth_limit),
+            limits: Mutex::new(limits),
+            config,
+            jobs: Mutex::new(jobs),
+            running: Mutex::new(HashMap::new()),
+            state_path,
+            save_lock: Mutex::new(()),
+            last_save: Mutex::new(Instant::now()),
+            shutting_down: AtomicBool::new(false),
+        });
+        pump(&shared);
+        Self { shared }
+    }
+
+    /// Queue a download; returns its id
+    pub fn add(&self, request: DownloadRequest) -> Result<String, String> {
+        if !request.url.starts_with("http://") && !request.url.starts_with("https://") {
+            return Err(format!("Not an HTTP URL: {}", request.url));
+        }
+        let dir = request
+            .dest_dir
+            .clone()
+            .unwrap_or_else(|| self.shared.config.download_dir.clone());
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let filename = sanitize_filename(
+            &request
+                .filename
+                .clone()
+                .or_else(|| filename_from_url(&request.url))
+                .unwrap_or_default(),
+        );
+
+        let id = uuid::Uuid::new_v4().to_string();
+        {
+            let mut jobs = self.shared.jobs.lock();
+            let taken: Vec<&Path> = jobs
+                .iter()
+                .filter(|j| j.state != DownloadState::Completed)
+                .map(|j| j.path.as_path())
+                .collect();
+            let path = unique_destination(&dir, &filename, &taken);
+            tracing::info!("Queued download {} -> {}", request.url, path.display());
+            jobs.push(DownloadJob {
+                id: id.clone(),
+                url: request.url,
+                source_url: request.source_url,
+                filename: path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or(filename),
+                path,
+                headers: request.headers,
+                state: DownloadState::Queued,
+                total_size: None,
+                resumable: false,
+                segments: Vec::new(),
+                checksum: request.checksum,
+                checksum_verified: None,
+                error: None,
+                added_at: chrono::Utc::now().timestamp(),
+                completed_at: None,
+                speed_bps: 0,
+                meter: None,
+            });
+        }
+        self.shared.save()?;
+        pump(&self.shared);
+        Ok(id)
+    }
+
+    /// All downloads in queue order
+    pub fn list(&self) -> Vec<DownloadProgress> {
+        self.shared
+            .jobs
+            .lock()
+            .iter()
+            .map(DownloadJob::progress)
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<DownloadProgress> {
+        self.shared.update(id, |job| job.progress())
+    }
+
+    pub fn pause(&self, id: &str) -> Result<(), String> {
+        self.shared
+            .update(id, |job| match job.state {
+                DownloadState::Queued | DownloadState::Downloading => {
+                    job.state = DownloadState::Paused;
+                    Ok(())
+                }
+                state => Err(format!("Cannot pause a {:?} download", state)),
+            })
+            .ok_or_else(|| format!("Download not found: {}", id))??;
+        if let Some(stop) = self.shared.running.lock().get(id) {
+            stop.store(true, Ordering::SeqCst);
+        }
+        self.shared.save()
+    }
+
+    /// Requeue a paused or failed download
+    pub fn resume(&self, id: &str) -> Result<(), String> {
+        self.shared
+            .update(id, |job| match job.state {
+                DownloadState::Paused | DownloadState::Failed => {
+                    job.state = DownloadState::Queued;
+                    job.error = None;
+                    Ok(())
+                }
+                state => Err(format!("Cannot resume a {:?} download", state)),
+            })
+            .ok_or_else(|| format!("Download not found: {}", id))??;
+        self.shared.save()?;
+        pump(&self.shared);
+        Ok(())
+    }
+
+    /// Remove a download from the queue, deleting partial data. Completed
+    /// files are kept.
+    pub fn cancel(&self, id: &str) -> Result<(), String> {
+        let job = {
+            let mut jobs = self.shared.jobs.lock();
+            let index = jobs
+                .iter()
+                .position(|j| j.id == id)
+                .ok_or_else(|| format!("Download not found: {}", id))?;
+            jobs.remove(index)
+        };
+        match self.shared.running.lock().get(id) {
+            // The worker deletes the part file once it lets go of it
+            Some(stop) => stop.store(true, Ordering::SeqCst),
+            None => {
+                let _ = fs::remove_file(job.part_path());
+            }
+        }
+        self.shared.save()
+    }
+
+    /// Drop completed downloads from the list; returns how many
+    pub fn clear_finished(&self) -> usize {
+        let removed = {
+            let mut jobs = self.shared.jobs.lock();
+            let before = jobs.len();
+            jobs.retain(|j| j.state != DownloadState::Completed);
+            before - jobs.len()
+        };
+        self.shared.save_logged();
+        removed
+    }
+
+    pub fn limits(&self) -> DownloadLimits {
+        self.shared.limits.lock().clone()
+    }
+
+    /// Apply new limits. Bandwidth and host limits affect running
+    /// downloads immediately, connection counts apply from the next start.
+    pub fn set_limits(&self, limits: DownloadLimits) -> Result<(), String> {
+        self.shared.hosts.set_limit(limits.per_host_connections);
+        self.shared.throttle.set_rate(limits.bandwidth_limit);
+        *self.shared.limits.lock() = limits;
+        self.shared.save()?;
+        pump(&self.shared);
+        Ok(())
+    }
+
+    /// Stop all transfers, leaving them queued for the next `open`
+    pub fn shutdown(&self) {
+        self.shared.shutting_down.store(true, Ordering::SeqCst);
+        for stop in self.shared.running.lock().values() {
+            stop.store(true, Ordering::SeqCst);
+        }
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !self.shared.running.lock().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        self.shared.save_logged();
+    }
+}
+
+/// Start queued downloads while below `max_active`
+fn pump(shared: &Arc<Shared>) {
+    if shared.shutting_down.load(Ordering::SeqCst) {
+        return;
+    }
+    let max_active = shared.limits.lock().max_active.max(1);
+    let mut jobs = shared.jobs.lock();
+    let mut running = shared.running.lock();
+    for job in jobs.iter_mut() {
+        if running.len() >= max_active {
+            break;
+        }
+        // A paused job may still be winding down; its worker pumps again
+        if job.state != DownloadState::Queued || running.contains_key(&job.id) {
+            continue;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let shared = shared.clone();
+            let id = job.id.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("slain-download".to_string())
+                .spawn(move || run_job(shared, id, stop))
+        };
+        match worker {
+            Ok(_) => {
+                job.state = DownloadState::Downloading;
+                running.insert(job.id.clone(), stop);
+            }
+            Err(e) => {
+                job.state = DownloadState::Failed;
+                job.error = Some(format!("Failed to start download: {}", e));
+            }
+        }
+    }
+}
+
+fn run_job(shared: Arc<Shared>, id: String, stop: Arc<AtomicBool>) {
+    let part = shared.update(&id, |job| job.part_path());
+    let result = download(&shared, &id, &stop);
+    let cancelled = shared
+        .update(&id, |job| {
+            job.speed_bps = 0;
+            job.meter = None;
+            // Errors after a pause or shutdown leave the state alone
+            if let (Err(e), false) = (&result, stop.load(Ordering::SeqCst)) {
+                tracing::warn!("Download {} failed: {}", job.filename, e);
+                job.state = DownloadState::Failed;
+                job.error = Some(e.clone());
+            }
+        })
+        .is_none();
+    shared.running.lock().remove(&id);
+    if let Some(part) = part.filter(|_| cancelled) {
+        let _ = fs::remove_file(part);
+    }
+    shared.save_logged();
+    pump(&shared);
+}
+
+/// Transfer one job; returns once it completes, fails or is stopped
+fn download(shared: &Shared, id: &str, stop: &AtomicBool) -> Result<(), String> {
+    let Some((url, headers)) = shared.update(id, |job| (job.url.clone(), job.headers.clone()))
+    else {
+        return Ok(());
+    };
+    let mut http = shared.config.http.clone();
+    http.headers.extend(headers);
+    let agent = build_agent(&http);
+    let remote = {
+        let _slot = shared.hosts.acquire(&url);
+        probe_remote(&agent, &http, &url)?
+    };
+
+    let limits = shared.limits.lock().clone();
+    let plan = shared.update(id, |job| {
+        if job.checksum.is_none() {
+            job.checksum = remote.checksum.clone();
+        }
+        let reuse = remote.resumable
+            && job.resumable
+            && job.total_size == remote.size
+            && !job.segments.is_empty()
+            && job.part_path().exists();
+        if !reuse {
+            job.total_size = remote.size;
+            job.resumable = remote.resumable;
+            job.segments = plan_segments(
+                remote.size,
+                remote.resumable,
+                limits.connections_per_download,
+                shared.config.min_segment_size,
+            );
+        }
+        (reuse, job.part_path(), job.segments.clone(), job.resumable)
+    });
+    let Some((reuse, part, segments, resumable)) = plan else {
+        return Ok(());
+    };
+    if !reuse {
+        let file = File::create(&part)
+            .map_err(|e| format!("Failed to create {}: {}", part.display(), e))?;
+        if let Some(size) = remote.size {
+            file.set_len(size).map_err(|e| e.to_string())?;
+        }
+    } else {
+        tracing::info!(
+            "Resuming {} from {} bytes",
+            url,
+            segments.iter().map(|s| s.downloaded).sum::<u64>()
+        );
+    }
+    shared.save_logged();
+
+    let transfer = Transfer {
+        shared,
+        id,
+        url: &url,
+        http: &http,
+        agent: &agent,
+        part: &part,
+        resumable,
+        stop,
+        abort: AtomicBool::new(false),
+    };
+    let results: Vec<Result<(), String>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = segments
+            .into_iter()
+            .enumerate()
+            .filter(|(_, seg)| seg.remaining() != Some(0))
+            .map(|(index, seg)| {
+                let transfer = &transfer;
+                scope.spawn(move || transfer.fetch_segment(index, seg))
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|w| {
+                w.join()
+                    .unwrap_or_else(|_| Err("Download worker panicked".to_string()))
+            })
+            .collect()
+    });
+    if stop.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    if let Some(Err(e)) = results.into_iter().find(Result::is_err) {
+        return Err(e);
+    }
+    finish(shared, id, &part)
+}
+
+/// Verify the checksum and move the part file into place
+fn finish(shared: &Shared, id: &str, part: &Path) -> Result<(), String> {
+    let Some((checksum, path)) = shared.update(id, |job| {
+        job.state = DownloadState::Verifying;
+        (job.checksum.clone(), job.path.clone())
+    }) else {
+        return Ok(());
+    };
+    if let Some(expected) = checksum {
+        let actual = expected.algorithm.hash_file(part)?;
+        if actual != expected.value {
+            let _ = fs::remove_file(part);
+            shared.update(id, |job| {
+                job.segments.clear();
+                job.checksum_verified = Some(false);
+            });
+            return Err(format!(
+                "Checksum mismatch: expected {:?} {}, got {}",
+                expected.algorithm, expected.value, actual
+            ));
+        }
+        shared.update(id, |job| job.checksum_verified = Some(true));
+    }
+    fs::rename(part, &path)
+        .map_err(|e| format!("Failed to move {} into place: {}", path.display(), e))?;
+    tracing::info!("Download complete: {}", path.display());
+    shared.update(id, |job| {
+        job.state = DownloadState::Completed;
+        job.completed_at = Some(chrono::Utc::now().timestamp());
+        if job.total_size.is_none() {
+            job.total_size = Some(job.downloaded());
+        }
+    });
+    Ok(())
+}
+
+/// Per-job context shared by the segment workers
+struct Transfer<'a> {
+    shared: &'a Shared,
+    id: &'a str,
+    url: &'a str,
+    http: &'a HttpConfig,
+    agent: &'a ureq::Agent,
+    part: &'a Path,
+    resumable: bool,
+    stop: &'a AtomicBool,
+    /// Set when a segment gives up, so the others stop too
+    abort: AtomicBool,
+}
+
+impl Transfer<'_> {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst) || self.abort.load(Ordering::SeqCst)
+    }
+
+    /// Fetch one segment, retrying with backoff while progress is made
+    fn fetch_segment(&self, index: usize, mut seg: Segment) -> Result<(), String> {
+        let mut failures = 0;
+        loop {
+            if self.stopped() || seg.remaining() == Some(0) {
+                return Ok(());
+            }
+            if !self.resumable && seg.downloaded > 0 {
+                // Without ranges the only way to retry is from the start
+                self.shared.reset_segment(self.id, index);
+                seg.downloaded = 0;
+            }
+            let before = seg.downloaded;
+            match self.transfer(index, &mut seg) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if seg.downloaded > before {
+                        failures = 0;
+                    }
+                    failures += 1;
+                    if failures > self.http.retries {
+                        self.abort.store(true, Ordering::SeqCst);
+                        return Err(e);
+                    }
+                    tracing::debug!("Segment {} of {} failed ({}), retrying", index, self.url, e);
+                    std::thread::sleep(Duration::from_millis(250 * failures as u64));
+                }
+            }
+        }
+    }
+
+    fn transfer(&self, index: usize, seg: &mut Segment) -> Result<(), String> {
+        let _slot = self.shared.hosts.acquire(self.url);
+        if self.stopped() {
+            return Ok(());
+        }
+        let offset = seg.start + seg.downloaded;
+        let range = match (self.resumable, seg.end) {
+            (true, Some(end)) => Some(format!("bytes={}-{}", offset, end - 1)),
+            _ => None,
+        };
+        let response = request(self.agent, self.http, self.url, range.clone())?;
+        if range.is_some() && response.status() != 206 {
+            return Err("Server ignored the range request".to_string());
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.part)
+            .map_err(|e| format!("Failed to open {}: {}", self.part.display(), e))?;
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|e| e.to_string())?;
+        let mut reader = response.into_reader();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            if self.stopped() {
+                return Ok(());
+            }
+            let want = seg
+                .remaining()
+                .map_or(CHUNK_SIZE, |left| left.min(CHUNK_SIZE as u64) as usize);
+            if want == 0 {
+                return Ok(());
+            }
+            let n = reader
+                .read(&mut buf[..want])
+                .map_err(|e| format!("Read failed: {}", e))?;
+            if n == 0 {
+                return match seg.end {
+                    Some(_) => Err("Connection closed early".to_string()),
+                    None => Ok(()),
+                };
+            }
+            self.shared.throttle.take(n);
+            file.write_all(&buf[..n])
+                .map_err(|e| format!("Failed to write {}: {}", self.part.display(), e))?;
+            seg.downloaded += n as u64;
+            self.shared.advance(self.id, index, n as u64);
+        }
+    }
+}
+
+// ============================================================================
+// Public Rust API
+// ============================================================================
+
+use once_cell::sync::Lazy;
+
+fn default_state_path() -> PathBuf {
+    let mut path = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("SLAIN");
+    path.push("downloads.json");
+    path
+}
+
+static DOWNLOADS: Lazy<DownloadManager> = Lazy::new(|| {
+    DownloadManager::open(&default_state_path(), DownloadConfig::default()).unwrap_or_else(|e| {
+        tracing::warn!("Download queue not restored: {}", e);
+        DownloadManager::in_memory(DownloadConfig::default())
+    })
+});
+
+/// Queue a direct download; returns its id
+pub fn download_add(request: DownloadRequest) -> Result<String, String> {
+    DOWNLOADS.add(request)
+}
+
+/// Queue a link already resolved by `DebridManager::resolve`
+pub fn download_add_resolved(link: ResolvedLink) -> Result<String, String> {
+    DOWNLOADS.add(DownloadRequest::from_resolved(&link))
+}
+
+/// Resolve a hoster link through the configured debrid service and queue it
+pub async fn download_debrid_link(url: String) -> Result<String, String> {
+    let link = crate::debrid::debrid_resolve(url).await?;
+    download_add_resolved(link)
+}
+
+pub fn download_list() -> Vec<DownloadProgress> {
+    DOWNLOADS.list()
+}
+
+pub fn download_get(id: String) -> Option<DownloadProgress> {
+    DOWNLOADS.get(&id)
+}
+
+pub fn download_pause(id: String) -> Result<(), String> {
+    DOWNLOADS.pause(&id)
+}
+
+pub fn download_resume(id: String) -> Result<(), String> {
+    DOWNLOADS.resume(&id)
+}
+
+pub fn download_cancel(id: String) -> Result<(), String> {
+    DOWNLOADS.cancel(&id)
+}
+
+pub fn download_clear_finished() -> usize {
+    DOWNLOADS.clear_finished()
+}
+
+pub fn download_get_limits() -> DownloadLimits {
+    DOWNLOADS.limits()
+}
+
+pub fn download_set_limits(limits: DownloadLimits) -> Result<(), String> {
+    DOWNLOADS.set_limits(limits)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{http_server, write_head};
+    use std::sync::atomic::AtomicUsize;
+
+    struct TestServer {
+        base: String,
+        ranges: Arc<Mutex<Vec<String>>>,
+        served: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    /// Serves `data` at `/file.bin` with range support and a SHA-256
+    /// `Digest`, and at `/plain.bin` ignoring ranges
+    fn file_server(data: Vec<u8>) -> TestServer {
+        let digest = base64::engine::general_purpose::STANDARD.encode(sha2::Sha256::digest(&data));
+        let (ranges, served, peak) = (
+            Arc::new(Mutex::new(Vec::new())),
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(AtomicUsize::new(0)),
+        );
+        let active = AtomicUsize::new(0);
+        let base = {
+            let (ranges, served, peak) = (ranges.clone(), served.clone(), peak.clone());
+            http_server(move |request, stream| {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                let len = data.len();
+                let range = request.headers.get("range");
+                let (status, headers, body) = match (request.path.as_str(), range) {
+                    ("/file.bin", Some(range)) => {
+                        ranges.lock().push(range.clone());
+                        let (start, end) =
+                            range.trim_start_matches("bytes=").split_once('-').unwrap();
+                        let start: usize = start.parse().unwrap();
+                        let end: usize = end.parse::<usize>().unwrap().min(len - 1);
+                        (
+                            "206 Partial Content",
+                            vec![
+                                ("Content-Range", format!("bytes {}-{}/{}", start, end, len)),
+                                ("Digest", format!("sha-256={}", digest)),
+                            ],
+                            &data[start..=end],
+                        )
+                    }
+                    ("/file.bin", None) => (
+                        "200 OK",
+                        vec![("Digest", format!("sha-256={}", digest))],
+                        &data[..],
+                    ),
+                    ("/plain.bin", _) => ("200 OK", Vec::new(), &data[..]),
+                    _ => ("404 Not Found", Vec::new(), &[][..]),
+                };
+                let mut headers = headers;
+                headers.push(("Accept-Ranges", "bytes".to_string()));
+                // Counted inactive before the last chunk, so the client
+                // can't start its next request while this one still counts
+                let chunks: Vec<&[u8]> = body.chunks(8192).collect();
+                let mut counted = true;
+                let mut sent = write_head(stream, status, &headers, Some(body.len())).is_ok();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    if !sent {
+                        break;
+                    }
+                    if i + 1 == chunks.len() {
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        counted = false;
+                    } else if i > 0 {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    sent = stream.write_all(chunk).is_ok();
+                    if sent {
+                        served.fetch_add(chunk.len(), Ordering::SeqCst);
+                    }
+                }
+                if counted {
+                    active.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        };
+        TestServer {
+            base,
+            ranges,
+            served,
+            peak,
+        }
+    }
+
+    fn test_config(dir: &Path) -> DownloadConfig {
+        DownloadConfig {
+            download_dir: dir.to_path_buf(),
+            limits: DownloadLimits {
+                max_active: 2,
+                connections_per_download: 4,
+                per_host_connections: 2,
+                bandwidth_limit: 0,
+            },
+            min_segment_size: 64 * 1024,
+            http: HttpConfig {
+                timeout_secs: 5,
+                retries: 2,
+                ..HttpConfig::default()
+            },
+        }
+    }
+
+    fn wait_for(
+        manager: &DownloadManager,
+        id: &str,
+        done: impl Fn(&DownloadProgress) -> bool,
+    ) -> DownloadProgress {
+        let deadline = Instant::now() + Duration::from_secs(20);
+        loop {
+            let progress = manager.get(id).expect("download listed");
+            if done(&progress) {
+                return progress;
+            }
+            assert!(Instant::now() < deadline, "timed out: {:?}", progress);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn finished(progress: &DownloadProgress) -> bool {
+        matches!(
+            progress.state,
+            DownloadState::Completed | DownloadState::Failed
+        )
+    }
+
+    #[test]
+    fn segmented_download_verifies_checksum() {
+        let data = test_data(1024 * 1024);
+        let server = file_server(data.clone());
+        let dir = tempfile::tempdir().unwrap();
+        let manager = DownloadManager::in_memory(test_config(dir.path()));
+
+        let id = manager
+            .add(DownloadRequest::new(&format!("{}/file.bin", server.base)))
+            .unwrap();
+        let done = wait_for(&manager, &id, finished);
+        assert_eq!(done.state, DownloadState::Completed, "{:?}", done.error);
+        assert_eq!(done.connections, 4);
+        assert!(done.resumable);
+        assert_eq!(
+            done.checksum.as_ref().unwrap().algorithm,
+            ChecksumAlgorithm::Sha256
+        );
+        assert_eq!(done.checksum_verified, Some(true));
+        assert_eq!(done.path, dir.path().join("file.bin"));
+        assert_eq!(fs::read(&done.path).unwrap(), data);
+        assert!(!dir.path().join("file.bin.part").exists());
+        // Probe plus one range per segment, never more than two at a time
+        assert_eq!(server.ranges.lock().len(), 5);
+        assert!(server.peak.load(Ordering::SeqCst) <= 2);
+
+        // Range ignored: single connection
+        let mut plain = DownloadRequest::new(&format!("{}/plain.bin", server.base));
+        plain.filename = Some("plain video.bin".to_string());
+        let id = manager.add(plain).unwrap();
+        let done = wait_for(&manager, &id, finished);
+        assert_eq!(done.state, DownloadState::Completed, "{:?}", done.error);
+        assert!(!done.resumable);
+        assert_eq!(done.connections, 1);
+        assert_eq!(done.checksum_verified, None);
+        assert_eq!(fs::read(dir.path().join("plain video.bin")).unwrap(), data);
+
+        // Caller checksum wins over the header and a mismatch fails
+        let mut wrong = DownloadRequest::new(&format!("{}/file.bin", server.base));
+        wrong.checksum = Some(Checksum::parse(&format!("sha1:{}", "0".repeat(40))).unwrap());
+        let id = manager.add(wrong).unwrap();
+        let done = wait_for(&manager, &id, finished);
+        assert_eq!(done.state, DownloadState::Failed);
+        assert!(done.error.unwrap().contains("Checksum mismatch"));
+        assert_eq!(done.checksum_verified, Some(false));
+        assert_eq!(done.filename, "file (1).bin");
+        assert!(!done.path.exists());
+        assert!(!dir.path().join("file (1).bin.part").exists());
+
+        assert_eq!(manager.clear_finished(), 2);
+        assert_eq!(manager.list().len(), 1);
+        assert!(manager
+            .add(DownloadRequest::new("magnet:?xt=urn:btih:abc"))
+            .is_err());
+    }
+
+    #[test]
+    fn resumes_after_restart() {
+        let data = test_data(1024 * 1024);
+        let server = file_server(data.clone());
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join("downloads.json");
+        let mut config = test_config(dir.path());
+        config.limits.bandwidth_limit = 256 * 1024;
+
+        let first = DownloadManager::open(&state, config.clone()).unwrap();
+        let link = ResolvedLink {
+            original_url: "https://hoster.example/file/abc".to_string(),
+            download_url: format!("{}/file.bin", server.base),
+            stream_url: None,
+            filename: "Movie.2024.mkv".to_string(),
+            filesize: data.len() as u64,
+            host: "hoster.example".to_string(),
+            service: crate::debrid::DebridService::RealDebrid,
+            is_cached: true,
+            quality: None,
+            mime_type: None,
+            streamable: false,
+            transcode_available: false,
+            available_qualities: Vec::new(),
+        };
+        let id = first.add(DownloadRequest::from_resolved(&link)).unwrap();
+        wait_for(&first, &id, |p| p.downloaded >= 200 * 1024);
+        first.shutdown();
+        assert!(dir.path().join("Movie.2024.mkv.part").exists());
+        let served_before = server.served.load(Ordering::SeqCst);
+
+        // Saved limits come back with the queue
+        config.limits.bandwidth_limit = 0;
+        let second = DownloadManager::open(&state, config).unwrap();
+        assert_eq!(second.limits().bandwidth_limit, 256 * 1024);
+        second
+            .set_limits(DownloadLimits {
+                bandwidth_limit: 0,
+                ..second.limits()
+            })
+            .unwrap();
+        let done = wait_for(&second, &id, finished);
+        assert_eq!(done.state, DownloadState::Completed, "{:?}", done.error);
+        assert_eq!(
+            done.source_url.as_deref(),
+            Some("https://hoster.example/file/abc")
+        );
+        assert_eq!(done.checksum_verified, Some(true));
+        assert_eq!(fs::read(dir.path().join("Movie.2024.mkv")).unwrap(), data);
+        // Only the missing ranges were fetched again
+        let refetched = server.served.load(Ordering::SeqCst) - served_before;
+        assert!(
+            refetched < data.len() - 150 * 1024,
+            "refetched {}",
+            refetched
+        );
+    }
+
+    #[test]
+    fn bandwidth_cap_pause_and_cancel() {
+        assert!(Checksum::parse("sha256=").is_err());
+        assert!(Checksum::parse("crc32:cbf43926").is_err());
+        let md5 = Checksum::parse("D41D8CD98F00B204E9800998ECF8427E").unwrap();
+        assert_eq!(md5.algorithm, ChecksumAlgorithm::Md5);
+        assert_eq!(md5.value, "d41d8cd98f00b204e9800998ecf8427e");
+        let headers = |name: &str| match name {
+            "Digest" => Some("md5=1B2M2Y8AsgTpgAmY7PhCfg==, unixsum=30637".to_string()),
+            "Content-MD5" => Some("1B2M2Y8AsgTpgAmY7PhCfg==".to_string()),
+            _ => None,
+        };
+        assert_eq!(Checksum::from_headers(headers, false), Some(md5.clone()));
+        assert_eq!(Checksum::from_headers(|_| None, true), None);
+        assert_eq!(
+            Checksum::from_headers(
+                |n| (n == "Content-MD5").then(|| "1B2M2Y8AsgTpgAmY7PhCfg==".to_string()),
+                false
+            ),
+            None
+        );
+
+        let data = test_data(256 * 1024);
+        let server = file_server(data.clone());
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config(dir.path());
+        config.limits.bandwidth_limit = 128 * 1024;
+        let manager = DownloadManager::in_memory(config);
+
+        let started = Instant::now();
+        let id = manager
+            .add(DownloadRequest::new(&format!("{}/file.bin", server.base)))
+            .unwrap();
+        wait_for(&manager, &id, |p| p.downloaded >= 32 * 1024);
+        manager.pause(&id).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        let paused = manager.get(&id).unwrap();
+        assert_eq!(paused.state, DownloadState::Paused);
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(manager.get(&id).unwrap().downloaded, paused.downloaded);
+        assert!(manager.pause(&id).is_err());
+
+        manager.resume(&id).unwrap();
+        let done = wait_for(&manager, &id, finished);
+        assert_eq!(done.state, DownloadState::Completed, "{:?}", done.error);
+        assert_eq!(fs::read(&done.path).unwrap(), data);
+        // 256 KiB at 128 KiB/s, plus the pause
+        assert!(
+            started.elapsed() >= Duration::from_millis(2000),
+            "{:?}",
+            started.elapsed()
+        );
+
+        let id = manager
+            .add(DownloadRequest::new(&format!("{}/file.bin", server.base)))
+            .unwrap();
+        let active = wait_for(&manager, &id, |p| p.downloaded > 0);
+        manager.cancel(&id).unwrap();
+        assert!(manager.get(&id).is_none());
+        let mut part = active.path.into_os_string();
+        part.push(".part");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Path::new(&part).exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!Path::new(&part).exists());
+        assert!(manager.cancel(&id).is_err());
+    }
+}
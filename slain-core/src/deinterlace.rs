@@ -1,4 +1,5 @@
-//! CPU deinterlacing utilities for RGB frames.
+//! CPU deinterlacing: RGB utilities plus Yadif/Bwdif on planar YUV
+//! (8/10-bit) with a three-frame window and double-rate output.
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DeinterlaceMode {
@@ -37,18 +38,35 @@ impl Default for DeinterlaceConfig {
     }
 }
 
+/// Telecine pattern recognised from the recent comb history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pulldown {
+    /// Two adjacent combed frames in every five (NTSC film)
+    ThreeTwo,
+    /// Every frame combed, but progressive when woven with the previous
+    /// frame's other field (field-shifted film)
+    TwoTwo,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct InterlaceInfo {
     pub is_interlaced: bool,
     pub field_order: FieldOrder,
     pub confidence: f32,
     pub comb_factor: f32,
+    pub pulldown: Option<Pulldown>,
 }
 
 pub struct InterlaceDetector {
     avg_comb: f32,
     frame_count: u32,
     detected_order: FieldOrder,
+    /// Per-frame interlaced verdicts, newest in bit 0
+    comb_history: u32,
+    /// Per-frame "progressive when woven with the previous frame" verdicts
+    shifted_history: u32,
+    history_len: u32,
+    prev_luma: Option<Vec<u16>>,
 }
 
 impl InterlaceDetector {
@@ -57,51 +75,117 @@ impl InterlaceDetector {
             avg_comb: 0.0,
             frame_count: 0,
             detected_order: FieldOrder::Auto,
+            comb_history: 0,
+            shifted_history: 0,
+            history_len: 0,
+            prev_luma: None,
         }
     }
 
     pub fn analyze(&mut self, frame: &[u8], width: u32, height: u32) -> InterlaceInfo {
         let stride = width as usize * 3;
-        let comb_factor = self.calculate_comb_factor(frame, width, height, stride);
+        self.analyze_with(width, height, None, |x, y| {
+            let idx = y * stride + x * 3;
+            (idx + 2 < frame.len()).then(|| luma_at(frame, idx))
+        })
+    }
+
+    /// Analyze a luma plane (8-bit scale, one sample per pixel)
+    ///
+    /// Consecutive calls are treated as consecutive frames, which lets the
+    /// detector recognise 3:2 and 2:2 pulldown.
+    pub fn analyze_luma(&mut self, luma: &[u16], width: u32, height: u32) -> InterlaceInfo {
+        let stride = width as usize;
+        // Combing left after weaving either field with the previous frame's
+        // other field: low for field-shifted film, high for true interlace
+        let woven_comb = self
+            .prev_luma
+            .take()
+            .filter(|prev| prev.len() == luma.len())
+            .map(|prev| {
+                (0..2)
+                    .map(|parity| {
+                        self.calculate_comb_factor(width, height, &|x: usize, y: usize| {
+                            let source = if y % 2 == parity { &prev[..] } else { luma };
+                            source.get(y * stride + x).map(|&v| v as i32)
+                        })
+                    })
+                    .fold(f32::MAX, f32::min)
+            });
+        let info = self.analyze_with(width, height, woven_comb, |x, y| {
+            luma.get(y * stride + x).map(|&v| v as i32)
+        });
+        self.prev_luma = Some(luma.to_vec());
+        info
+    }
+
+    fn analyze_with(
+        &mut self,
+        width: u32,
+        height: u32,
+        woven_comb: Option<f32>,
+        luma: impl Fn(usize, usize) -> Option<i32>,
+    ) -> InterlaceInfo {
+        let comb_factor = self.calculate_comb_factor(width, height, &luma);
 
-        self.avg_comb = (self.avg_comb * self.frame_count as f32 + comb_factor)
-            / (self.frame_count + 1) as f32;
+        self.avg_comb =
+            (self.avg_comb * self.frame_count as f32 + comb_factor) / (self.frame_count + 1) as f32;
         self.frame_count += 1;
 
         if self.frame_count < 10 && self.detected_order == FieldOrder::Auto {
-            self.detected_order = self.detect_field_order(frame, width, height, stride);
+            self.detected_order = self.detect_field_order(width, height, &luma);
         }
 
         let is_interlaced = comb_factor > 15.0;
         let confidence = (comb_factor / 50.0).clamp(0.0, 1.0);
 
+        let shifted = is_interlaced && woven_comb.is_some_and(|comb| comb <= 15.0);
+        self.comb_history = (self.comb_history << 1) | is_interlaced as u32;
+        self.shifted_history = (self.shifted_history << 1) | shifted as u32;
+        self.history_len = (self.history_len + 1).min(32);
+
         InterlaceInfo {
             is_interlaced,
             field_order: self.detected_order,
             confidence,
             comb_factor,
+            pulldown: self.pulldown(),
+        }
+    }
+
+    /// 3:2 needs two identical cycles of five, 2:2 six shifted frames in a row
+    fn pulldown(&self) -> Option<Pulldown> {
+        if self.history_len >= 10 {
+            let cycle = self.comb_history & 0x1F;
+            let repeats = (self.comb_history >> 5) & 0x1F == cycle;
+            // The two frames woven from different film frames are neighbours
+            let adjacent = (0..5).any(|r| ((cycle >> r) | (cycle << (5 - r))) & 0x1F == 0b11);
+            if repeats && adjacent {
+                return Some(Pulldown::ThreeTwo);
+            }
+        }
+        if self.history_len >= 6 && self.shifted_history & 0x3F == 0x3F {
+            return Some(Pulldown::TwoTwo);
         }
+        None
     }
 
     fn calculate_comb_factor(
         &self,
-        frame: &[u8],
         width: u32,
         height: u32,
-        stride: usize,
+        luma: &impl Fn(usize, usize) -> Option<i32>,
     ) -> f32 {
         let mut comb_sum = 0i64;
         let mut pixel_count = 0u64;
 
-        for y in 1..(height - 1) as usize {
+        for y in 1..(height.max(1) - 1) as usize {
             for x in (0..width as usize).step_by(4) {
-                let idx = y * stride + x * 3;
-                if idx + stride >= frame.len() || idx < stride {
+                let (Some(above), Some(current), Some(below)) =
+                    (luma(x, y - 1), luma(x, y), luma(x, y + 1))
+                else {
                     continue;
-                }
-                let above = luma_at(frame, idx - stride);
-                let current = luma_at(frame, idx);
-                let below = luma_at(frame, idx + stride);
+                };
 
                 let comb = (2 * current - above - below).abs();
                 comb_sum += comb as i64;
@@ -118,24 +202,20 @@ impl InterlaceDetector {
 
     fn detect_field_order(
         &self,
-        frame: &[u8],
         width: u32,
         height: u32,
-        stride: usize,
+        luma: &impl Fn(usize, usize) -> Option<i32>,
     ) -> FieldOrder {
         let mut top_motion = 0i64;
         let mut bottom_motion = 0i64;
 
-        for y in 2..(height - 2) as usize {
+        for y in 2..height.saturating_sub(2) as usize {
             for x in (0..width as usize).step_by(8) {
-                let idx = y * stride + x * 3;
-                if idx + 2 * stride >= frame.len() || idx < 2 * stride {
+                let (Some(current), Some(two_up), Some(two_down)) =
+                    (luma(x, y), luma(x, y - 2), luma(x, y + 2))
+                else {
                     continue;
-                }
-
-                let current = luma_at(frame, idx);
-                let two_up = luma_at(frame, idx - 2 * stride);
-                let two_down = luma_at(frame, idx + 2 * stride);
+                };
 
                 let diff = (2 * current - two_up - two_down).abs() as i64;
 
@@ -160,6 +240,10 @@ impl InterlaceDetector {
         self.avg_comb = 0.0;
         self.frame_count = 0;
         self.detected_order = FieldOrder::Auto;
+        self.comb_history = 0;
+        self.shifted_history = 0;
+        self.history_len = 0;
+        self.prev_luma = None;
     }
 }
 
@@ -307,7 +391,7 @@ impl Deinterlacer {
         let height = self.height as usize;
         let stride = self.width as usize * 3;
 
-        let prev = self.prev_frame.as_ref().map(|p| p.as_slice()).unwrap_or(frame);
+        let prev = self.prev_frame.as_deref().unwrap_or(frame);
 
         let first_field = match field_order {
             FieldOrder::TopFirst | FieldOrder::Auto => 0,
@@ -329,7 +413,11 @@ impl Deinterlacer {
                     for x in 0..stride {
                         let idx = y * stride + x;
                         let c = frame[idx] as i32;
-                        let d = if y > 0 { frame[(y - 1) * stride + x] as i32 } else { c };
+                        let d = if y > 0 {
+                            frame[(y - 1) * stride + x] as i32
+                        } else {
+                            c
+                        };
                         let e = if y < height - 1 {
                             frame[(y + 1) * stride + x] as i32
                         } else {
@@ -337,7 +425,11 @@ impl Deinterlacer {
                         };
 
                         let p_c = prev[idx] as i32;
-                        let p_d = if y > 0 { prev[(y - 1) * stride + x] as i32 } else { p_c };
+                        let p_d = if y > 0 {
+                            prev[(y - 1) * stride + x] as i32
+                        } else {
+                            p_c
+                        };
                         let p_e = if y < height - 1 {
                             prev[(y + 1) * stride + x] as i32
                         } else {
@@ -376,6 +468,604 @@ impl Deinterlacer {
     }
 }
 
+impl FieldOrder {
+    /// Field order signalled by the codec/container (e.g. `lav::VideoFrame::{interlaced, tff}`).
+    /// `None` when the stream marks the picture as progressive.
+    pub fn from_stream_flags(interlaced: bool, top_field_first: bool) -> Option<Self> {
+        if !interlaced {
+            None
+        } else if top_field_first {
+            Some(Self::TopFirst)
+        } else {
+            Some(Self::BottomFirst)
+        }
+    }
+
+    /// Line parity (0 = even/top lines) of the field that comes first in time
+    pub(crate) fn first_parity(self) -> usize {
+        match self {
+            Self::TopFirst | Self::Auto => 0,
+            Self::BottomFirst => 1,
+        }
+    }
+}
+
+/// Planar YUV picture for the YUV deinterlacers. Samples are stored widened
+/// to u16 so 8-bit and 10-bit content share one code path.
+#[derive(Debug, Clone)]
+pub struct YuvPicture {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u32,
+    /// log2 chroma subsampling (horizontal, vertical): (1, 1) for 4:2:0
+    pub chroma_shift: (u32, u32),
+    pub planes: [Vec<u16>; 3],
+    /// Presentation timestamp in the stream's time base
+    pub pts: i64,
+    /// Picture duration in the same time base (0 = derive from the next pts)
+    pub duration: i64,
+    /// Interlacing signalled by the stream; `None` if the stream doesn't say
+    pub interlaced: Option<bool>,
+    /// Field order signalled by the stream (`Auto` if unknown)
+    pub field_order: FieldOrder,
+}
+
+impl YuvPicture {
+    pub fn new(width: u32, height: u32, bit_depth: u32, chroma_shift: (u32, u32)) -> Self {
+        let (cw, ch) = chroma_size(width, height, chroma_shift);
+        let luma = (width * height) as usize;
+        let chroma = (cw * ch) as usize;
+        Self {
+            width,
+            height,
+            bit_depth,
+            chroma_shift,
+            planes: [vec![0; luma], vec![0; chroma], vec![0; chroma]],
+            pts: 0,
+            duration: 0,
+            interlaced: None,
+            field_order: FieldOrder::Auto,
+        }
+    }
+
+    /// Width and height of plane `index`
+    pub fn plane_size(&self, index: usize) -> (u32, u32) {
+        if index == 0 {
+            (self.width, self.height)
+        } else {
+            chroma_size(self.width, self.height, self.chroma_shift)
+        }
+    }
+
+    /// Import any 8/10/12-bit planar or semi-planar YUV frame
+    pub fn from_pixel_frame(frame: &crate::pixel_convert::VideoFrame) -> Result<Self, String> {
+        let format = frame.format;
+        let (sub_x, sub_y) = format
+            .chroma_subsampling()
+            .ok_or_else(|| format!("{:?} is not a YUV format", format))?;
+        let depth = format.bit_depth();
+        let wide = format.bytes_per_sample() == 2;
+        // Semi-planar high bit depth formats are MSB-aligned
+        let shift = if format.is_semi_planar() && wide {
+            16 - depth
+        } else {
+            0
+        };
+        let read = |bytes: &[u8]| -> Vec<u16> {
+            if wide {
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]) >> shift)
+                    .collect()
+            } else {
+                bytes.iter().map(|&b| b as u16).collect()
+            }
+        };
+
+        let mut picture = Self::new(
+            frame.width as u32,
+            frame.height as u32,
+            depth,
+            (sub_x as u32, sub_y as u32),
+        );
+        picture.pts = frame.pts;
+        let luma = read(frame.plane(0));
+        let luma_len = picture.planes[0].len();
+        let chroma_len = picture.planes[1].len();
+        if luma.len() < luma_len {
+            return Err("Frame buffer too small".to_string());
+        }
+        picture.planes[0].copy_from_slice(&luma[..luma_len]);
+
+        if format.is_semi_planar() {
+            let uv = read(frame.plane(1));
+            if uv.len() < chroma_len * 2 {
+                return Err("Frame buffer too small".to_string());
+            }
+            for i in 0..chroma_len {
+                picture.planes[1][i] = uv[i * 2];
+                picture.planes[2][i] = uv[i * 2 + 1];
+            }
+        } else {
+            for p in 1..3 {
+                let samples = read(frame.plane(p));
+                if samples.len() < chroma_len {
+                    return Err("Frame buffer too small".to_string());
+                }
+                picture.planes[p].copy_from_slice(&samples[..chroma_len]);
+            }
+        }
+        Ok(picture)
+    }
+
+    /// Export into a frame of `format`, which must match the picture's
+    /// subsampling and bit depth
+    pub fn to_pixel_frame(
+        &self,
+        format: crate::pixel_convert::PixelFormat,
+    ) -> Result<crate::pixel_convert::VideoFrame, String> {
+        let sub = format
+            .chroma_subsampling()
+            .ok_or_else(|| format!("{:?} is not a YUV format", format))?;
+        if (sub.0 as u32, sub.1 as u32) != self.chroma_shift || format.bit_depth() != self.bit_depth
+        {
+            return Err(format!("{:?} does not match the picture layout", format));
+        }
+
+        let wide = format.bytes_per_sample() == 2;
+        let shift = if format.is_semi_planar() && wide {
+            16 - self.bit_depth
+        } else {
+            0
+        };
+        let write = |dst: &mut [u8], samples: &mut dyn Iterator<Item = u16>| {
+            if wide {
+                for (out, s) in dst.chunks_exact_mut(2).zip(samples) {
+                    out.copy_from_slice(&(s << shift).to_le_bytes());
+                }
+            } else {
+                for (out, s) in dst.iter_mut().zip(samples) {
+                    *out = s as u8;
+                }
+            }
+        };
+
+        let mut frame = crate::pixel_convert::VideoFrame::new(
+            self.width as usize,
+            self.height as usize,
+            format,
+        );
+        frame.pts = self.pts;
+        write(frame.plane_mut(0), &mut self.planes[0].iter().copied());
+        if format.is_semi_planar() {
+            let mut uv = self.planes[1]
+                .iter()
+                .zip(&self.planes[2])
+                .flat_map(|(&u, &v)| [u, v]);
+            write(frame.plane_mut(1), &mut uv);
+        } else {
+            for p in 1..3 {
+                write(frame.plane_mut(p), &mut self.planes[p].iter().copied());
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Import a decoded frame together with its interlacing flags
+    pub fn from_lav_frame(frame: &crate::lav::VideoFrame) -> Result<Self, String> {
+        use crate::lav::PixelFormat as LavFormat;
+        use crate::pixel_convert::PixelFormat;
+
+        let format = match frame.format {
+            LavFormat::I420 => PixelFormat::YUV420P,
+            LavFormat::I422 => PixelFormat::YUV422P,
+            LavFormat::I444 => PixelFormat::YUV444P,
+            LavFormat::Nv12 => PixelFormat::NV12,
+            LavFormat::P010 => PixelFormat::P010,
+            // MSB-aligned, so reading the top 12 bits is exact for 10/12-bit content
+            LavFormat::P016 => PixelFormat::P012,
+            other => return Err(format!("{:?} is not a YUV format", other)),
+        };
+        let mut px = crate::pixel_convert::VideoFrame::new(
+            frame.width as usize,
+            frame.height as usize,
+            format,
+        );
+        let len = px.data.len().min(frame.data.len());
+        px.data[..len].copy_from_slice(&frame.data[..len]);
+        px.pts = frame.pts;
+
+        let mut picture = Self::from_pixel_frame(&px)?;
+        picture.duration = frame.duration;
+        picture.interlaced = Some(frame.interlaced);
+        if let Some(order) = FieldOrder::from_stream_flags(frame.interlaced, frame.tff) {
+            picture.field_order = order;
+        }
+        Ok(picture)
+    }
+}
+
+fn chroma_size(width: u32, height: u32, shift: (u32, u32)) -> (u32, u32) {
+    (
+        (width + (1 << shift.0) - 1) >> shift.0,
+        (height + (1 << shift.1) - 1) >> shift.1,
+    )
+}
+
+/// Index of the line `offset` rows away from `y`, mirrored at the plane
+/// edges so the result keeps the parity of `y + offset`
+fn ref_line(y: usize, offset: isize, height: usize) -> usize {
+    let h = height as isize;
+    let mut i = y as isize + offset;
+    if i < 0 || i >= h {
+        i = y as isize - offset;
+    }
+    if i < 0 || i >= h {
+        let parity = (y as isize + offset).rem_euclid(2);
+        i = i.clamp(0, h - 1);
+        if i.rem_euclid(2) != parity {
+            i = if i + 1 < h { i + 1 } else { i - 1 };
+        }
+    }
+    i.clamp(0, h - 1) as usize
+}
+
+/// Bwdif filter coefficients (13-bit fixed point)
+const BWDIF_COEF_LF: [i32; 2] = [4309, 213];
+const BWDIF_COEF_HF: [i32; 3] = [5570, 3801, 1016];
+const BWDIF_COEF_SP: [i32; 2] = [5077, 981];
+
+/// One plane's worth of the three-frame window
+struct FieldWindow<'a> {
+    prev: &'a [u16],
+    cur: &'a [u16],
+    next: &'a [u16],
+    width: usize,
+    height: usize,
+    max: i32,
+}
+
+impl FieldWindow<'_> {
+    /// Rebuild the lines of `cur` whose parity differs from `keep_parity`.
+    /// `first_field` selects the temporal pair straddling the output instant:
+    /// (prev, cur) for the earlier field, (cur, next) for the later one.
+    fn filter(
+        &self,
+        dst: &mut [u16],
+        mode: DeinterlaceMode,
+        keep_parity: usize,
+        first_field: bool,
+    ) {
+        let (w, h) = (self.width, self.height);
+        let (prev2, next2) = if first_field {
+            (self.prev, self.cur)
+        } else {
+            (self.cur, self.next)
+        };
+
+        for y in 0..h {
+            let row = y * w;
+            if y % 2 == keep_parity || h < 2 {
+                dst[row..row + w].copy_from_slice(&self.cur[row..row + w]);
+                continue;
+            }
+
+            let line = |offset: isize| ref_line(y, offset, h) * w;
+            let (m1, p1) = (line(-1), line(1));
+            let (m2, p2) = (line(-2), line(2));
+            let (m3, p3) = (line(-3), line(3));
+            let (m4, p4) = (line(-4), line(4));
+
+            for x in 0..w {
+                let at = |plane: &[u16], line: usize, dx: isize| -> i32 {
+                    let xi = (x as isize + dx).clamp(0, w as isize - 1) as usize;
+                    plane[line + xi] as i32
+                };
+                let cur = self.cur;
+                let c = at(cur, m1, 0);
+                let e = at(cur, p1, 0);
+
+                let value = match mode {
+                    DeinterlaceMode::Bob => (c + e + 1) >> 1,
+                    _ => {
+                        let d = (at(prev2, row, 0) + at(next2, row, 0)) >> 1;
+                        let td0 = (at(prev2, row, 0) - at(next2, row, 0)).abs();
+                        let td1 = ((at(self.prev, m1, 0) - c).abs()
+                            + (at(self.prev, p1, 0) - e).abs())
+                            >> 1;
+                        let td2 = ((at(self.next, m1, 0) - c).abs()
+                            + (at(self.next, p1, 0) - e).abs())
+                            >> 1;
+                        let mut diff = (td0 >> 1).max(td1).max(td2);
+
+                        if mode == DeinterlaceMode::Bwdif {
+                            if diff == 0 {
+                                d
+                            } else {
+                                let b = ((at(prev2, m2, 0) + at(next2, m2, 0)) >> 1) - c;
+                                let f = ((at(prev2, p2, 0) + at(next2, p2, 0)) >> 1) - e;
+                                let (dc, de) = (d - c, d - e);
+                                let hi = de.max(dc).max(b.min(f));
+                                let lo = de.min(dc).min(b.max(f));
+                                diff = diff.max(lo).max(-hi);
+
+                                let outer = at(cur, m3, 0) + at(cur, p3, 0);
+                                let interpol = if (c - e).abs() > td0 {
+                                    let hf = BWDIF_COEF_HF[0]
+                                        * (at(prev2, row, 0) + at(next2, row, 0))
+                                        - BWDIF_COEF_HF[1]
+                                            * (at(prev2, m2, 0)
+                                                + at(next2, m2, 0)
+                                                + at(prev2, p2, 0)
+                                                + at(next2, p2, 0))
+                                        + BWDIF_COEF_HF[2]
+                                            * (at(prev2, m4, 0)
+                                                + at(next2, m4, 0)
+                                                + at(prev2, p4, 0)
+                                                + at(next2, p4, 0));
+                                    ((hf >> 2) + BWDIF_COEF_LF[0] * (c + e)
+                                        - BWDIF_COEF_LF[1] * outer)
+                                        >> 13
+                                } else {
+                                    (BWDIF_COEF_SP[0] * (c + e) - BWDIF_COEF_SP[1] * outer) >> 13
+                                };
+                                interpol.clamp(d - diff, d + diff)
+                            }
+                        } else {
+                            // Yadif: edge-directed spatial prediction...
+                            let mut spatial_pred = (c + e) >> 1;
+                            let mut spatial_score = (at(cur, m1, -1) - at(cur, p1, -1)).abs()
+                                + (c - e).abs()
+                                + (at(cur, m1, 1) - at(cur, p1, 1)).abs()
+                                - 1;
+                            for dir in [[-1isize, -2], [1, 2]] {
+                                for j in dir {
+                                    let score = (at(cur, m1, j - 1) - at(cur, p1, -j - 1)).abs()
+                                        + (at(cur, m1, j) - at(cur, p1, -j)).abs()
+                                        + (at(cur, m1, j + 1) - at(cur, p1, -j + 1)).abs();
+                                    if score >= spatial_score {
+                                        break;
+                                    }
+                                    spatial_score = score;
+                                    spatial_pred = (at(cur, m1, j) + at(cur, p1, -j)) >> 1;
+                                }
+                            }
+
+                            // ...bounded by the temporal prediction
+                            let b = (at(prev2, m2, 0) + at(next2, m2, 0)) >> 1;
+                            let f = (at(prev2, p2, 0) + at(next2, p2, 0)) >> 1;
+                            let hi = (d - e).max(d - c).max((b - c).min(f - e));
+                            let lo = (d - e).min(d - c).min((b - c).max(f - e));
+                            diff = diff.max(lo).max(-hi);
+                            spatial_pred.clamp(d - diff, d + diff)
+                        }
+                    }
+                };
+                dst[row + x] = value.clamp(0, self.max) as u16;
+            }
+        }
+    }
+
+    /// Vertical [1 2 1] blend of both fields
+    fn blend(&self, dst: &mut [u16]) {
+        let (w, h) = (self.width, self.height);
+        for y in 0..h {
+            let (above, below) = (y.saturating_sub(1), (y + 1).min(h - 1));
+            for x in 0..w {
+                let sum = self.cur[above * w + x] as u32
+                    + 2 * self.cur[y * w + x] as u32
+                    + self.cur[below * w + x] as u32;
+                dst[y * w + x] = ((sum + 2) / 4) as u16;
+            }
+        }
+    }
+}
+
+/// Three-frame-window Yadif/Bwdif deinterlacer for planar YUV.
+///
+/// Pictures go in with [`push`](Self::push); output is delayed by one picture
+/// because the filters look at the next frame. With `double_rate` every
+/// interlaced picture yields two outputs, one per field, with the second
+/// timestamped halfway to the next picture.
+pub struct YuvDeinterlacer {
+    config: DeinterlaceConfig,
+    detector: InterlaceDetector,
+    stream_order: Option<FieldOrder>,
+    prev: Option<YuvPicture>,
+    cur: Option<YuvPicture>,
+    last_duration: i64,
+}
+
+impl YuvDeinterlacer {
+    pub fn new(config: DeinterlaceConfig) -> Self {
+        Self {
+            config,
+            detector: InterlaceDetector::new(),
+            stream_order: None,
+            prev: None,
+            cur: None,
+            last_duration: 0,
+        }
+    }
+
+    /// Field order from container/codec headers, used when pictures don't carry their own
+    pub fn set_stream_field_order(&mut self, order: Option<FieldOrder>) {
+        self.stream_order = order;
+    }
+
+    /// Pictures held back before the first output
+    pub fn latency(&self) -> usize {
+        1
+    }
+
+    /// Feed the next decoded picture; returns the deinterlaced output of the previous one
+    pub fn push(&mut self, picture: YuvPicture) -> Vec<YuvPicture> {
+        let output = match self.cur.take() {
+            Some(cur) => {
+                let out = self.process(&cur, Some(&picture));
+                self.prev = Some(cur);
+                out
+            }
+            None => Vec::new(),
+        };
+        self.cur = Some(picture);
+        output
+    }
+
+    /// Drain the held picture at end of stream
+    pub fn flush(&mut self) -> Vec<YuvPicture> {
+        match self.cur.take() {
+            Some(cur) => {
+                let out = self.process(&cur, None);
+                self.prev = Some(cur);
+                out
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn process(&mut self, cur: &YuvPicture, next: Option<&YuvPicture>) -> Vec<YuvPicture> {
+        let duration = if cur.duration > 0 {
+            cur.duration
+        } else {
+            next.map(|n| n.pts - cur.pts)
+                .filter(|d| *d > 0)
+                .unwrap_or(self.last_duration)
+        };
+        self.last_duration = duration;
+
+        let shift = cur.bit_depth.saturating_sub(8);
+        let luma8: Vec<u16> = cur.planes[0].iter().map(|&v| v >> shift).collect();
+        let info = self.detector.analyze_luma(&luma8, cur.width, cur.height);
+
+        let flagged = cur.interlaced;
+        let should_deinterlace = match self.config.mode {
+            DeinterlaceMode::Off => false,
+            DeinterlaceMode::Auto => self.config.force || flagged.unwrap_or(info.is_interlaced),
+            _ => flagged != Some(false) || self.config.force,
+        };
+        if !should_deinterlace {
+            let mut out = cur.clone();
+            out.duration = duration;
+            return vec![out];
+        }
+
+        // Explicit config > per-picture flags > stream headers > detector
+        let order = match self.config.field_order {
+            FieldOrder::Auto => match cur.field_order {
+                FieldOrder::Auto => self.stream_order.unwrap_or(info.field_order),
+                flagged => flagged,
+            },
+            forced => forced,
+        };
+        let first = order.first_parity();
+
+        let mode = match self.config.mode {
+            DeinterlaceMode::Auto => DeinterlaceMode::Yadif,
+            other => other,
+        };
+        if mode == DeinterlaceMode::Weave {
+            let mut out = cur.clone();
+            out.duration = duration;
+            return vec![out];
+        }
+
+        let prev = self
+            .prev
+            .as_ref()
+            .filter(|p| same_layout(p, cur))
+            .unwrap_or(cur);
+        let next = next.filter(|n| same_layout(n, cur)).unwrap_or(cur);
+        let max = ((1u32 << cur.bit_depth.min(16)) - 1) as i32;
+
+        let fields = if self.config.double_rate && mode != DeinterlaceMode::Blend {
+            2
+        } else {
+            1
+        };
+        let field_duration = duration / fields as i64;
+
+        (0..fields)
+            .map(|field| {
+                let mut out = cur.clone();
+                out.pts = cur.pts + field_duration * field as i64;
+                out.duration = field_duration;
+                out.interlaced = Some(false);
+                out.field_order = FieldOrder::Auto;
+                let keep = (first + field) % 2;
+
+                for p in 0..3 {
+                    let (w, h) = cur.plane_size(p);
+                    let window = FieldWindow {
+                        prev: &prev.planes[p],
+                        cur: &cur.planes[p],
+                        next: &next.planes[p],
+                        width: w as usize,
+                        height: h as usize,
+                        max,
+                    };
+                    if mode == DeinterlaceMode::Blend {
+                        window.blend(&mut out.planes[p]);
+                    } else {
+                        window.filter(&mut out.planes[p], mode, keep, field == 0);
+                    }
+                }
+                out
+            })
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.prev = None;
+        self.cur = None;
+        self.last_duration = 0;
+        self.detector.reset();
+    }
+
+    pub fn config(&self) -> &DeinterlaceConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: DeinterlaceConfig) {
+        self.config = config;
+    }
+}
+
+/// Rebuild the lines of `cur` whose parity differs from `keep_parity` with a
+/// single Yadif/Bwdif pass, for callers that choose the field themselves (IVTC)
+pub(crate) fn interpolate_field(
+    prev: &YuvPicture,
+    cur: &YuvPicture,
+    next: &YuvPicture,
+    mode: DeinterlaceMode,
+    keep_parity: usize,
+) -> YuvPicture {
+    let prev = if same_layout(prev, cur) { prev } else { cur };
+    let next = if same_layout(next, cur) { next } else { cur };
+    let max = ((1u32 << cur.bit_depth.min(16)) - 1) as i32;
+    let mut out = cur.clone();
+    for p in 0..3 {
+        let (w, h) = cur.plane_size(p);
+        let window = FieldWindow {
+            prev: &prev.planes[p],
+            cur: &cur.planes[p],
+            next: &next.planes[p],
+            width: w as usize,
+            height: h as usize,
+            max,
+        };
+        window.filter(&mut out.planes[p], mode, keep_parity, true);
+    }
+    out
+}
+
+fn same_layout(a: &YuvPicture, b: &YuvPicture) -> bool {
+    a.width == b.width
+        && a.height == b.height
+        && a.bit_depth == b.bit_depth
+        && a.chroma_shift == b.chroma_shift
+}
+
 fn luma_at(frame: &[u8], idx: usize) -> i32 {
     if idx + 2 >= frame.len() {
         return 0;
@@ -413,6 +1103,46 @@ mod tests {
         assert!(info.comb_factor > 10.0);
     }
 
+    #[test]
+    fn detects_field_shifted_film() {
+        let (width, height) = (32u32, 32u32);
+        // Bar moving 5 px per film frame; each video frame weaves film frame
+        // n (top) with n - 1 (bottom)
+        let luma = |top: usize, bottom: usize| -> Vec<u16> {
+            (0..(width * height) as usize)
+                .map(|i| {
+                    let (x, y) = (i % width as usize, i / width as usize);
+                    let n = if y % 2 == 0 { top } else { bottom };
+                    if (x + 32 - (n * 5) % 32) % 32 < 8 {
+                        220
+                    } else {
+                        30
+                    }
+                })
+                .collect()
+        };
+
+        let mut detector = InterlaceDetector::new();
+        let verdicts: Vec<_> = (1..10)
+            .map(|n| {
+                detector
+                    .analyze_luma(&luma(n, n - 1), width, height)
+                    .pulldown
+            })
+            .collect();
+        assert!(verdicts[..6].iter().all(Option::is_none));
+        assert_eq!(verdicts[8], Some(Pulldown::TwoTwo));
+
+        // Fields from unrelated moments never weave cleanly
+        detector.reset();
+        let interlaced = (1..10)
+            .map(|n| detector.analyze_luma(&luma(2 * n, 2 * n + 1), width, height))
+            .last()
+            .unwrap();
+        assert!(interlaced.is_interlaced);
+        assert_eq!(interlaced.pulldown, None);
+    }
+
     #[test]
     fn bob_outputs_two_frames_when_double_rate() {
         let config = DeinterlaceConfig {
@@ -426,4 +1156,129 @@ mod tests {
         assert!(deinterlaced);
         assert_eq!(outputs.len(), 2);
     }
+
+    fn gradient_picture(width: u32, height: u32, bit_depth: u32, pts: i64) -> YuvPicture {
+        let mut picture = YuvPicture::new(width, height, bit_depth, (1, 1));
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                picture.planes[0][y * width as usize + x] = (y * 8 + x) as u16;
+            }
+        }
+        picture.planes[1].fill(1 << (bit_depth - 1));
+        picture.planes[2].fill(1 << (bit_depth - 1));
+        picture.pts = pts;
+        picture
+    }
+
+    #[test]
+    fn yuv_filters_preserve_static_gradient() {
+        for mode in [DeinterlaceMode::Yadif, DeinterlaceMode::Bwdif] {
+            let config = DeinterlaceConfig {
+                mode,
+                field_order: FieldOrder::TopFirst,
+                ..Default::default()
+            };
+            let mut deinterlacer = YuvDeinterlacer::new(config);
+            assert!(deinterlacer.push(gradient_picture(16, 16, 8, 0)).is_empty());
+            let out = deinterlacer.push(gradient_picture(16, 16, 8, 40));
+            assert_eq!(out.len(), 1);
+
+            let expected = gradient_picture(16, 16, 8, 0);
+            for (a, b) in out[0].planes[0].iter().zip(&expected.planes[0]) {
+                assert!((*a as i32 - *b as i32).abs() <= 1, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn double_rate_splits_timestamps_per_field() {
+        let config = DeinterlaceConfig {
+            mode: DeinterlaceMode::Bwdif,
+            field_order: FieldOrder::Auto,
+            double_rate: true,
+            ..Default::default()
+        };
+        let mut deinterlacer = YuvDeinterlacer::new(config);
+        deinterlacer.set_stream_field_order(Some(FieldOrder::BottomFirst));
+
+        let mut outputs = Vec::new();
+        for pts in [0, 40, 80] {
+            outputs.extend(deinterlacer.push(gradient_picture(8, 8, 10, pts)));
+        }
+        outputs.extend(deinterlacer.flush());
+
+        let pts: Vec<i64> = outputs.iter().map(|p| p.pts).collect();
+        assert_eq!(pts, vec![0, 20, 40, 60, 80, 100]);
+        assert!(outputs.iter().all(|p| p.duration == 20));
+    }
+
+    #[test]
+    fn kept_field_lines_come_through_untouched() {
+        // Strongly combed 10-bit picture with motion between frames
+        let combed = |pts: i64, offset: u16| {
+            let mut picture = YuvPicture::new(8, 8, 10, (1, 1));
+            for y in 0..8 {
+                let value = if y % 2 == 0 {
+                    1000 - offset
+                } else {
+                    20 + offset
+                };
+                picture.planes[0][y * 8..y * 8 + 8].fill(value);
+            }
+            picture.pts = pts;
+            picture.interlaced = Some(true);
+            picture.field_order = FieldOrder::BottomFirst;
+            picture
+        };
+
+        let config = DeinterlaceConfig {
+            mode: DeinterlaceMode::Yadif,
+            ..Default::default()
+        };
+        let mut deinterlacer = YuvDeinterlacer::new(config);
+        let source = combed(0, 0);
+        deinterlacer.push(source.clone());
+        // Output lags one picture: this yields the deinterlaced `source`
+        let out = deinterlacer.push(combed(40, 100)).remove(0);
+        deinterlacer.push(combed(80, 200));
+
+        let out2 = deinterlacer.flush().remove(0);
+        assert!(out2.planes[0].iter().all(|&v| v <= 1023));
+        for y in (1..8).step_by(2) {
+            assert_eq!(
+                out.planes[0][y * 8..y * 8 + 8],
+                source.planes[0][y * 8..y * 8 + 8]
+            );
+        }
+    }
+
+    #[test]
+    fn progressive_stream_flag_bypasses_auto_mode() {
+        let mut deinterlacer = YuvDeinterlacer::new(DeinterlaceConfig::default());
+        let mut picture = gradient_picture(8, 8, 8, 0);
+        picture.interlaced = Some(false);
+        // Combing that the detector alone would flag
+        for y in (0..8).step_by(2) {
+            picture.planes[0][y * 8..y * 8 + 8].fill(250);
+        }
+        deinterlacer.push(picture.clone());
+        let out = deinterlacer.flush();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].planes[0], picture.planes[0]);
+    }
+
+    #[test]
+    fn yuv_picture_round_trips_p010() {
+        let mut frame =
+            crate::pixel_convert::VideoFrame::new(4, 2, crate::pixel_convert::PixelFormat::P010);
+        for (i, chunk) in frame.data.chunks_exact_mut(2).enumerate() {
+            chunk.copy_from_slice(&(((i as u16 * 37) % 1024) << 6).to_le_bytes());
+        }
+        let picture = YuvPicture::from_pixel_frame(&frame).expect("import");
+        assert_eq!(picture.bit_depth, 10);
+        let back = picture
+            .to_pixel_frame(crate::pixel_convert::PixelFormat::P010)
+            .expect("export");
+        assert_eq!(back.data, frame.data);
+    }
 }
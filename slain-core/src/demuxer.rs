@@ -111,7 +111,7 @@ fn map_mkv_packet(packet: MkvPacket) -> UniversalPacket {
     }
 }
 
-fn map_mp4_packet(packet: Mp4Packet) -> UniversalPacket {
+pub(crate) fn map_mp4_packet(packet: Mp4Packet) -> UniversalPacket {
     UniversalPacket {
         stream_index: packet.stream_index,
         pts_us: Some(packet.pts),
@@ -131,7 +131,7 @@ fn map_avi_packet(packet: AviPacket) -> UniversalPacket {
     }
 }
 
-fn map_ts_packet(packet: TsPacket) -> UniversalPacket {
+pub(crate) fn map_ts_packet(packet: TsPacket) -> UniversalPacket {
     UniversalPacket {
         stream_index: packet.pid as u32,
         pts_us: packet.pts,
@@ -0,0 +1,1968 @@
+//! DLNA/UPnP MediaRenderer
+//!
+//! The inverse of `streaming::DlnaCaster`: phones and other control points
+//! can cast to SLAIN.
+//! - AVTransport, RenderingControl and ConnectionManager over SOAP
+//! - GENA eventing: SUBSCRIBE/renew/UNSUBSCRIBE and `LastChange` NOTIFYs
+//! - SSDP advertisement through `streaming::SsdpAdvertisement`
+//!
+//! The renderer doesn't play anything itself. Transport actions arrive as
+//! `RendererCommand`s on a channel the player drains, and the player reports
+//! its position and state back with `DlnaRenderer::update_playback`.
+
+use crate::adaptive_stream::parse_xml;
+use crate::dlna_server::{
+    connection_manager_scpd, device_description, scpd, soap_fault, soap_response, write_response,
+    xml_escape, HttpRequest, ScpdAction, ScpdArgument, UpnpError, CONNECTION_MANAGER,
+    INVALID_ACTION, INVALID_ARGS,
+};
+use crate::streaming::SsdpAdvertisement;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const RENDERING_CONTROL: &str = "urn:schemas-upnp-org:service:RenderingControl:1";
+
+/// Containers the player can open
+const SINK_MIME_TYPES: &[&str] = &[
+    "video/mp4",
+    "video/quicktime",
+    "video/x-matroska",
+    "video/webm",
+    "video/x-msvideo",
+    "video/avi",
+    "video/mp2t",
+    "video/vnd.dlna.mpeg-tts",
+];
+
+/// GENA subscriptions are granted for at most this long
+const MAX_SUBSCRIPTION_SECS: u64 = 1800;
+/// `LastChange` moderation: at most one event per service per interval
+const EVENT_INTERVAL: Duration = Duration::from_millis(200);
+
+const TRANSITION_NOT_AVAILABLE: UpnpError = UpnpError {
+    code: 701,
+    description: "Transition not available",
+};
+const NO_CONTENTS: UpnpError = UpnpError {
+    code: 702,
+    description: "No contents",
+};
+const SEEK_MODE_NOT_SUPPORTED: UpnpError = UpnpError {
+    code: 710,
+    description: "Seek mode not supported",
+};
+const ILLEGAL_SEEK_TARGET: UpnpError = UpnpError {
+    code: 711,
+    description: "Illegal seek target",
+};
+const UNSUPPORTED_PLAY_SPEED: UpnpError = UpnpError {
+    code: 717,
+    description: "Play speed not supported",
+};
+const INVALID_INSTANCE_ID: UpnpError = UpnpError {
+    code: 718,
+    description: "Invalid InstanceID",
+};
+
+// ============================================================================
+// Configuration & Player Interface
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlnaRendererConfig {
+    pub friendly_name: String,
+    /// HTTP port (0 picks a free one)
+    pub port: u16,
+    /// Device UUID; keep it stable so control points remember the renderer.
+    /// Generated when None.
+    pub uuid: Option<String>,
+    /// Announce on and answer SSDP searches from the multicast group
+    pub advertise: bool,
+    /// SSDP `max-age`; alive notifications repeat at half of it
+    pub max_age_secs: u32,
+}
+
+impl Default for DlnaRendererConfig {
+    fn default() -> Self {
+        Self {
+            friendly_name: "SLAIN Player".to_string(),
+            port: 0,
+            uuid: None,
+            advertise: true,
+            max_age_secs: 1800,
+        }
+    }
+}
+
+/// What a control point asked the player to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RendererCommand {
+    /// SetAVTransportURI: open the media but don't start it
+    Load {
+        uri: String,
+        title: Option<String>,
+        mime: Option<String>,
+    },
+    Play,
+    Pause,
+    /// Stop and rewind
+    Stop,
+    Seek {
+        position_ms: u64,
+    },
+    /// 0.0 - 1.0
+    SetVolume(f32),
+    SetMute(bool),
+}
+
+/// AVTransport `TransportState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AvTransportState {
+    Stopped,
+    Playing,
+    PausedPlayback,
+    Transitioning,
+    NoMediaPresent,
+}
+
+impl AvTransportState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stopped => "STOPPED",
+            Self::Playing => "PLAYING",
+            Self::PausedPlayback => "PAUSED_PLAYBACK",
+            Self::Transitioning => "TRANSITIONING",
+            Self::NoMediaPresent => "NO_MEDIA_PRESENT",
+        }
+    }
+}
+
+/// Playback as the player sees it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RendererPlayback {
+    pub state: AvTransportState,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+}
+
+// ============================================================================
+// Renderer State
+// ============================================================================
+
+struct RendererState {
+    transport_state: AvTransportState,
+    /// `OK` or `ERROR_OCCURRED`
+    transport_status: &'static str,
+    uri: String,
+    metadata: String,
+    position_ms: u64,
+    duration_ms: u64,
+    /// 0 - 100
+    volume: u8,
+    muted: bool,
+}
+
+impl RendererState {
+    fn new() -> Self {
+        Self {
+            transport_state: AvTransportState::NoMediaPresent,
+            transport_status: "OK",
+            uri: String::new(),
+            metadata: String::new(),
+            position_ms: 0,
+            duration_ms: 0,
+            volume: 100,
+            muted: false,
+        }
+    }
+
+    fn transport_actions(&self) -> &'static str {
+        match self.transport_state {
+            AvTransportState::NoMediaPresent => "",
+            AvTransportState::Stopped => "Play,Seek",
+            AvTransportState::Playing | AvTransportState::Transitioning => "Pause,Stop,Seek",
+            AvTransportState::PausedPlayback => "Play,Stop,Seek",
+        }
+    }
+
+    /// Evented AVTransport variables (positions are polled, not evented)
+    fn av_transport_variables(&self) -> Vec<(&'static str, String)> {
+        let has_media = !self.uri.is_empty();
+        vec![
+            ("TransportState", self.transport_state.as_str().to_string()),
+            ("TransportStatus", self.transport_status.to_string()),
+            ("TransportPlaySpeed", "1".to_string()),
+            (
+                "PlaybackStorageMedium",
+                if has_media { "NETWORK" } else { "NONE" }.to_string(),
+            ),
+            ("NumberOfTracks", (has_media as u8).to_string()),
+            ("CurrentTrack", (has_media as u8).to_string()),
+            ("CurrentTrackDuration", upnp_time(self.duration_ms)),
+            ("CurrentMediaDuration", upnp_time(self.duration_ms)),
+            ("CurrentTrackURI", self.uri.clone()),
+            ("AVTransportURI", self.uri.clone()),
+            ("CurrentTrackMetaData", self.metadata.clone()),
+            ("AVTransportURIMetaData", self.metadata.clone()),
+            (
+                "CurrentTransportActions",
+                self.transport_actions().to_string(),
+            ),
+        ]
+    }
+
+    fn rendering_control_variables(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Volume", self.volume.to_string()),
+            ("Mute", (self.muted as u8).to_string()),
+            ("PresetNameList", "FactoryDefaults".to_string()),
+        ]
+    }
+}
+
+/// `H:MM:SS`
+fn upnp_time(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// `H+:MM:SS[.F+]` or `H+:MM:SS[.F0/F1]` to milliseconds
+fn parse_upnp_time(text: &str) -> Option<u64> {
+    let (clock, fraction) = match text.trim().split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (text.trim(), None),
+    };
+    let mut parts = clock.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let hours: u64 = hours.strip_prefix('+').unwrap_or(hours).parse().ok()?;
+    let minutes: u64 = minutes.parse().ok().filter(|m| *m < 60)?;
+    let seconds: u64 = seconds.parse().ok().filter(|s| *s < 60)?;
+    let millis = match fraction {
+        None => 0,
+        Some(fraction) => match fraction.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator: u64 = numerator.parse().ok()?;
+                let denominator: u64 = denominator.parse().ok().filter(|d| *d > numerator)?;
+                numerator * 1000 / denominator
+            }
+            None => {
+                let digits: String = fraction.chars().take(3).collect();
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                format!("{:0<3}", digits).parse().ok()?
+            }
+        },
+    };
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+/// Title and MIME type from `CurrentURIMetaData` (DIDL-Lite)
+fn didl_title_and_mime(metadata: &str) -> (Option<String>, Option<String>) {
+    let Ok(didl) = parse_xml(metadata) else {
+        return (None, None);
+    };
+    let Some(item) = didl.child("item").or_else(|| didl.child("container")) else {
+        return (None, None);
+    };
+    let title = item
+        .child("title")
+        .map(|t| t.text.trim().to_string())
+        .filter(|t| !t.is_empty());
+    // protocolInfo is protocol:network:mime:extra
+    let mime = item
+        .child("res")
+        .and_then(|res| res.attr("protocolInfo"))
+        .and_then(|info| info.split(':').nth(2))
+        .filter(|mime| *mime != "*")
+        .map(str::to_string);
+    (title, mime)
+}
+
+/// `<Event>` body for a LastChange variable
+fn last_change(namespace: &str, variables: &[(&str, String)]) -> String {
+    let mut out = format!(r#"<Event xmlns="{}"><InstanceID val="0">"#, namespace);
+    for (name, value) in variables {
+        let channel = if matches!(*name, "Volume" | "Mute") {
+            r#" channel="Master""#
+        } else {
+            ""
+        };
+        let _ = write!(out, r#"<{}{} val="{}"/>"#, name, channel, xml_escape(value));
+    }
+    out.push_str("</InstanceID></Event>");
+    out
+}
+
+// ============================================================================
+// GENA Eventing
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Service {
+    AvTransport,
+    RenderingControl,
+    ConnectionManager,
+}
+
+impl Service {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "AVTransport" => Some(Self::AvTransport),
+            "RenderingControl" => Some(Self::RenderingControl),
+            "ConnectionManager" => Some(Self::ConnectionManager),
+            _ => None,
+        }
+    }
+}
+
+struct Subscription {
+    sid: String,
+    service: Service,
+    callbacks: Vec<String>,
+    expires: Instant,
+    /// SEQ of the next event; 0 is the initial event
+    seq: u32,
+}
+
+/// `Second-N` / `infinite`, clamped to what we grant
+fn subscription_timeout(header: Option<&String>) -> u64 {
+    header
+        .and_then(|t| t.trim().strip_prefix("Second-"))
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(MAX_SUBSCRIPTION_SECS)
+        .clamp(60, MAX_SUBSCRIPTION_SECS)
+}
+
+/// `<http://a/b><http://c/d>`
+fn parse_callbacks(header: &str) -> Vec<String> {
+    header
+        .split('<')
+        .filter_map(|part| part.split_once('>'))
+        .map(|(url, _)| url.trim().to_string())
+        .filter(|url| url.starts_with("http://"))
+        .collect()
+}
+
+fn property_set(properties: &[(&str, String)]) -> String {
+    let mut body = String::from(
+        r#"<?xml version="1.0"?><e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">"#,
+    );
+    for (name, value) in properties {
+        let _ = write!(
+            body,
+            "<e:property><{}>{}</{}></e:property>",
+            name,
+            xml_escape(value),
+            name
+        );
+    }
+    body.push_str("</e:propertyset>");
+    body
+}
+
+/// Deliver one event; tries each callback URL until one accepts it
+fn send_event(callbacks: &[String], sid: &str, seq: u32, body: &str) -> bool {
+    callbacks.iter().any(|callback| {
+        let result = (|| -> Result<bool, String> {
+            let url = url::Url::parse(callback).map_err(|e| e.to_string())?;
+            let host = url.host_str().ok_or("callback without host")?;
+            let port = url.port_or_known_default().unwrap_or(80);
+            let addr: SocketAddr = (host, port)
+                .to_socket_addrs()
+                .map_err(|e| e.to_string())?
+                .next()
+                .ok_or("callback host unresolved")?;
+            let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(2))
+                .map_err(|e| e.to_string())?;
+            stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+            stream.set_write_timeout(Some(Duration::from_secs(2))).ok();
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+            let request = format!(
+                "NOTIFY {} HTTP/1.1\r\nHOST: {}:{}\r\nCONTENT-TYPE: text/xml; charset=\"utf-8\"\r\nCONTENT-LENGTH: {}\r\nNT: upnp:event\r\nNTS: upnp:propchange\r\nSID: {}\r\nSEQ: {}\r\nConnection: close\r\n\r\n{}",
+                path,
+                host,
+                port,
+                body.len(),
+                sid,
+                seq,
+                body
+            );
+            stream
+                .write_all(request.as_bytes())
+                .map_err(|e| e.to_string())?;
+            let mut status = [0u8; 12];
+            stream.read_exact(&mut status).map_err(|e| e.to_string())?;
+            Ok(status.starts_with(b"HTTP/1.") && &status[9..10] == b"2")
+        })();
+        match result {
+            Ok(delivered) => delivered,
+            Err(e) => {
+                tracing::debug!("GENA NOTIFY to {} failed: {}", callback, e);
+                false
+            }
+        }
+    })
+}
+
+// ============================================================================
+// Services
+// ============================================================================
+
+/// Shared by the HTTP and event threads
+struct Shared {
+    state: Mutex<RendererState>,
+    subscriptions: Mutex<Vec<Subscription>>,
+    commands: mpsc::Sender<RendererCommand>,
+    friendly_name: String,
+    uuid: String,
+}
+
+impl Shared {
+    fn command(&self, command: RendererCommand) {
+        if self.commands.send(command).is_err() {
+            tracing::debug!("DLNA renderer: player is gone");
+        }
+    }
+}
+
+fn check_instance(args: &HashMap<String, String>) -> Result<(), UpnpError> {
+    match args.get("InstanceID").map(|id| id.trim()) {
+        Some("0") => Ok(()),
+        _ => Err(INVALID_INSTANCE_ID),
+    }
+}
+
+fn check_channel(args: &HashMap<String, String>) -> Result<(), UpnpError> {
+    match args.get("Channel").map(|c| c.trim()) {
+        Some("Master") => Ok(()),
+        _ => Err(INVALID_ARGS),
+    }
+}
+
+fn av_transport_action(
+    shared: &Shared,
+    action: &str,
+    args: &HashMap<String, String>,
+) -> Result<String, UpnpError> {
+    check_instance(args)?;
+    let arg = |name: &str| args.get(name).map(String::as_str).unwrap_or("");
+    let reply = |arguments: &[(&str, String)]| soap_response(AV_TRANSPORT, action, arguments);
+    let mut state = shared.state.lock();
+    match action {
+        "SetAVTransportURI" => {
+            let uri = arg("CurrentURI").trim().to_string();
+            if uri.is_empty() {
+                return Err(INVALID_ARGS);
+            }
+            let metadata = arg("CurrentURIMetaData").to_string();
+            let (title, mime) = didl_title_and_mime(&metadata);
+            tracing::info!("DLNA renderer: loading {}", uri);
+            state.uri = uri.clone();
+            state.metadata = metadata;
+            state.transport_state = AvTransportState::Stopped;
+            state.transport_status = "OK";
+            state.position_ms = 0;
+            state.duration_ms = 0;
+            shared.command(RendererCommand::Load { uri, title, mime });
+            Ok(reply(&[]))
+        }
+        "Play" => {
+            if arg("Speed").trim() != "1" {
+                return Err(UNSUPPORTED_PLAY_SPEED);
+            }
+            if state.uri.is_empty() {
+                return Err(NO_CONTENTS);
+            }
+            if state.transport_state != AvTransportState::Playing {
+                state.transport_state = AvTransportState::Transitioning;
+            }
+            shared.command(RendererCommand::Play);
+            Ok(reply(&[]))
+        }
+        "Pause" => {
+            if !matches!(
+                state.transport_state,
+                AvTransportState::Playing | AvTransportState::Transitioning
+            ) {
+                return Err(TRANSITION_NOT_AVAILABLE);
+            }
+            state.transport_state = AvTransportState::PausedPlayback;
+            shared.command(RendererCommand::Pause);
+            Ok(reply(&[]))
+        }
+        "Stop" => {
+            if state.transport_state == AvTransportState::NoMediaPresent {
+                return Err(TRANSITION_NOT_AVAILABLE);
+            }
+            state.transport_state = AvTransportState::Stopped;
+            state.position_ms = 0;
+            shared.command(RendererCommand::Stop);
+            Ok(reply(&[]))
+        }
+        "Seek" => {
+            if state.uri.is_empty() {
+                return Err(TRANSITION_NOT_AVAILABLE);
+            }
+            if !matches!(arg("Unit"), "REL_TIME" | "ABS_TIME") {
+                return Err(SEEK_MODE_NOT_SUPPORTED);
+            }
+            let position_ms = parse_upnp_time(arg("Target")).ok_or(ILLEGAL_SEEK_TARGET)?;
+            if state.duration_ms > 0 && position_ms > state.duration_ms {
+                return Err(ILLEGAL_SEEK_TARGET);
+            }
+            state.position_ms = position_ms;
+            shared.command(RendererCommand::Seek { position_ms });
+            Ok(reply(&[]))
+        }
+        "GetTransportInfo" => Ok(reply(&[
+            (
+                "CurrentTransportState",
+                state.transport_state.as_str().to_string(),
+            ),
+            ("CurrentTransportStatus", state.transport_status.to_string()),
+            ("CurrentSpeed", "1".to_string()),
+        ])),
+        "GetPositionInfo" => {
+            let has_media = !state.uri.is_empty();
+            let position = upnp_time(state.position_ms);
+            Ok(reply(&[
+                ("Track", (has_media as u8).to_string()),
+                ("TrackDuration", upnp_time(state.duration_ms)),
+                ("TrackMetaData", state.metadata.clone()),
+                ("TrackURI", state.uri.clone()),
+                ("RelTime", position.clone()),
+                ("AbsTime", position),
+                ("RelCount", i32::MAX.to_string()),
+                ("AbsCount", i32::MAX.to_string()),
+            ]))
+        }
+        "GetMediaInfo" => {
+            let has_media = !state.uri.is_empty();
+            Ok(reply(&[
+                ("NrTracks", (has_media as u8).to_string()),
+                ("MediaDuration", upnp_time(state.duration_ms)),
+                ("CurrentURI", state.uri.clone()),
+                ("CurrentURIMetaData", state.metadata.clone()),
+                ("NextURI", String::new()),
+                ("NextURIMetaData", String::new()),
+                (
+                    "PlayMedium",
+                    if has_media { "NETWORK" } else { "NONE" }.to_string(),
+                ),
+                ("RecordMedium", "NOT_IMPLEMENTED".to_string()),
+                ("WriteStatus", "NOT_IMPLEMENTED".to_string()),
+            ]))
+        }
+        "GetTransportSettings" => Ok(reply(&[
+            ("PlayMode", "NORMAL".to_string()),
+            ("RecQualityMode", "NOT_IMPLEMENTED".to_string()),
+        ])),
+        "GetDeviceCapabilities" => Ok(reply(&[
+            ("PlayMedia", "NETWORK".to_string()),
+            ("RecMedia", "NOT_IMPLEMENTED".to_string()),
+            ("RecQualityModes", "NOT_IMPLEMENTED".to_string()),
+        ])),
+        "GetCurrentTransportActions" => {
+            Ok(reply(&[("Actions", state.transport_actions().to_string())]))
+        }
+        _ => Err(INVALID_ACTION),
+    }
+}
+
+fn rendering_control_action(
+    shared: &Shared,
+    action: &str,
+    args: &HashMap<String, String>,
+) -> Result<String, UpnpError> {
+    check_instance(args)?;
+    let arg = |name: &str| args.get(name).map(String::as_str).unwrap_or("").trim();
+    let reply = |arguments: &[(&str, String)]| soap_response(RENDERING_CONTROL, action, arguments);
+    let mut state = shared.state.lock();
+    match action {
+        "GetVolume" => {
+            check_channel(args)?;
+            Ok(reply(&[("CurrentVolume", state.volume.to_string())]))
+        }
+        "SetVolume" => {
+            check_channel(args)?;
+            let volume: u8 = arg("DesiredVolume")
+                .parse()
+                .ok()
+                .filter(|v| *v <= 100)
+                .ok_or(INVALID_ARGS)?;
+            state.volume = volume;
+            shared.command(RendererCommand::SetVolume(volume as f32 / 100.0));
+            Ok(reply(&[]))
+        }
+        "GetMute" => {
+            check_channel(args)?;
+            Ok(reply(&[("CurrentMute", (state.muted as u8).to_string())]))
+        }
+        "SetMute" => {
+            check_channel(args)?;
+            let muted = match arg("DesiredMute") {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                _ => return Err(INVALID_ARGS),
+            };
+            state.muted = muted;
+            shared.command(RendererCommand::SetMute(muted));
+            Ok(reply(&[]))
+        }
+        "ListPresets" => Ok(reply(&[(
+            "CurrentPresetNameList",
+            "FactoryDefaults".to_string(),
+        )])),
+        "SelectPreset" => {
+            if arg("PresetName") != "FactoryDefaults" {
+                return Err(INVALID_ARGS);
+            }
+            state.volume = 100;
+            state.muted = false;
+            shared.command(RendererCommand::SetVolume(1.0));
+            shared.command(RendererCommand::SetMute(false));
+            Ok(reply(&[]))
+        }
+        _ => Err(INVALID_ACTION),
+    }
+}
+
+fn sink_protocol_info() -> String {
+    SINK_MIME_TYPES
+        .iter()
+        .map(|mime| format!("http-get:*:{}:*", mime))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn connection_manager_action(
+    action: &str,
+    args: &HashMap<String, String>,
+) -> Result<String, UpnpError> {
+    let reply = |arguments: &[(&str, String)]| soap_response(CONNECTION_MANAGER, action, arguments);
+    match action {
+        "GetProtocolInfo" => Ok(reply(&[
+            ("Source", String::new()),
+            ("Sink", sink_protocol_info()),
+        ])),
+        "GetCurrentConnectionIDs" => Ok(reply(&[("ConnectionIDs", "0".to_string())])),
+        "GetCurrentConnectionInfo" => {
+            if args.get("ConnectionID").map(|id| id.trim()) != Some("0") {
+                return Err(INVALID_ARGS);
+            }
+            Ok(reply(&[
+                ("RcsID", "0".to_string()),
+                ("AVTransportID", "0".to_string()),
+                ("ProtocolInfo", String::new()),
+                ("PeerConnectionManager", String::new()),
+                ("PeerConnectionID", "-1".to_string()),
+                ("Direction", "Input".to_string()),
+                ("Status", "OK".to_string()),
+            ]))
+        }
+        _ => Err(INVALID_ACTION),
+    }
+}
+
+fn av_transport_scpd() -> String {
+    let instance = ("InstanceID", false, "A_ARG_TYPE_InstanceID");
+    let out = |name: &'static str, variable: &'static str| (name, true, variable);
+    let actions: Vec<(&str, Vec<ScpdArgument>)> = vec![
+        (
+            "SetAVTransportURI",
+            vec![
+                instance,
+                ("CurrentURI", false, "AVTransportURI"),
+                ("CurrentURIMetaData", false, "AVTransportURIMetaData"),
+            ],
+        ),
+        (
+            "Play",
+            vec![instance, ("Speed", false, "TransportPlaySpeed")],
+        ),
+        ("Pause", vec![instance]),
+        ("Stop", vec![instance]),
+        (
+            "Seek",
+            vec![
+                instance,
+                ("Unit", false, "A_ARG_TYPE_SeekMode"),
+                ("Target", false, "A_ARG_TYPE_SeekTarget"),
+            ],
+        ),
+        (
+            "GetTransportInfo",
+            vec![
+                instance,
+                out("CurrentTransportState", "TransportState"),
+                out("CurrentTransportStatus", "TransportStatus"),
+                out("CurrentSpeed", "TransportPlaySpeed"),
+            ],
+        ),
+        (
+            "GetPositionInfo",
+            vec![
+                instance,
+                out("Track", "CurrentTrack"),
+                out("TrackDuration", "CurrentTrackDuration"),
+                out("TrackMetaData", "CurrentTrackMetaData"),
+                out("TrackURI", "CurrentTrackURI"),
+                out("RelTime", "RelativeTimePosition"),
+                out("AbsTime", "AbsoluteTimePosition"),
+                out("RelCount", "RelativeCounterPosition"),
+                out("AbsCount", "AbsoluteCounterPosition"),
+            ],
+        ),
+        (
+            "GetMediaInfo",
+            vec![
+                instance,
+                out("NrTracks", "NumberOfTracks"),
+                out("MediaDuration", "CurrentMediaDuration"),
+                out("CurrentURI", "AVTransportURI"),
+                out("CurrentURIMetaData", "AVTransportURIMetaData"),
+                out("NextURI", "NextAVTransportURI"),
+                out("NextURIMetaData", "NextAVTransportURIMetaData"),
+                out("PlayMedium", "PlaybackStorageMedium"),
+                out("RecordMedium", "RecordStorageMedium"),
+                out("WriteStatus", "RecordMediumWriteStatus"),
+            ],
+        ),
+        (
+            "GetTransportSettings",
+            vec![
+                instance,
+                out("PlayMode", "CurrentPlayMode"),
+                out("RecQualityMode", "CurrentRecordQualityMode"),
+            ],
+        ),
+        (
+            "GetDeviceCapabilities",
+            vec![
+                instance,
+                out("PlayMedia", "PossiblePlaybackStorageMedia"),
+                out("RecMedia", "PossibleRecordStorageMedia"),
+                out("RecQualityModes", "PossibleRecordQualityModes"),
+            ],
+        ),
+        (
+            "GetCurrentTransportActions",
+            vec![instance, out("Actions", "CurrentTransportActions")],
+        ),
+    ];
+    let actions: Vec<ScpdAction> = actions
+        .iter()
+        .map(|(name, arguments)| (*name, arguments.as_slice()))
+        .collect();
+    let string = |name: &'static str| (name, "string", &[][..]);
+    scpd(
+        &actions,
+        &[
+            (
+                "TransportState",
+                "string",
+                &[
+                    "STOPPED",
+                    "PLAYING",
+                    "PAUSED_PLAYBACK",
+                    "TRANSITIONING",
+                    "NO_MEDIA_PRESENT",
+                ],
+            ),
+            ("TransportStatus", "string", &["OK", "ERROR_OCCURRED"]),
+            ("TransportPlaySpeed", "string", &["1"]),
+            ("PlaybackStorageMedium", "string", &["NONE", "NETWORK"]),
+            string("RecordStorageMedium"),
+            string("PossiblePlaybackStorageMedia"),
+            string("PossibleRecordStorageMedia"),
+            ("CurrentPlayMode", "string", &["NORMAL"]),
+            string("RecordMediumWriteStatus"),
+            string("CurrentRecordQualityMode"),
+            string("PossibleRecordQualityModes"),
+            ("NumberOfTracks", "ui4", &[]),
+            ("CurrentTrack", "ui4", &[]),
+            string("CurrentTrackDuration"),
+            string("CurrentMediaDuration"),
+            string("CurrentTrackMetaData"),
+            string("CurrentTrackURI"),
+            string("AVTransportURI"),
+            string("AVTransportURIMetaData"),
+            string("NextAVTransportURI"),
+            string("NextAVTransportURIMetaData"),
+            string("RelativeTimePosition"),
+            string("AbsoluteTimePosition"),
+            ("RelativeCounterPosition", "i4", &[]),
+            ("AbsoluteCounterPosition", "i4", &[]),
+            string("CurrentTransportActions"),
+            string("LastChange"),
+            ("A_ARG_TYPE_SeekMode", "string", &["REL_TIME", "ABS_TIME"]),
+            string("A_ARG_TYPE_SeekTarget"),
+            ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+        ],
+    )
+}
+
+fn rendering_control_scpd() -> String {
+    let instance = ("InstanceID", false, "A_ARG_TYPE_InstanceID");
+    let channel = ("Channel", false, "A_ARG_TYPE_Channel");
+    scpd(
+        &[
+            (
+                "ListPresets",
+                &[instance, ("CurrentPresetNameList", true, "PresetNameList")],
+            ),
+            (
+                "SelectPreset",
+                &[instance, ("PresetName", false, "A_ARG_TYPE_PresetName")],
+            ),
+            (
+                "GetVolume",
+                &[instance, channel, ("CurrentVolume", true, "Volume")],
+            ),
+            (
+                "SetVolume",
+                &[instance, channel, ("DesiredVolume", false, "Volume")],
+            ),
+            (
+                "GetMute",
+                &[instance, channel, ("CurrentMute", true, "Mute")],
+            ),
+            (
+                "SetMute",
+                &[instance, channel, ("DesiredMute", false, "Mute")],
+            ),
+        ],
+        &[
+            ("PresetNameList", "string", &[]),
+            ("Volume", "ui2", &[]),
+            ("Mute", "boolean", &[]),
+            ("LastChange", "string", &[]),
+            ("A_ARG_TYPE_Channel", "string", &["Master"]),
+            ("A_ARG_TYPE_PresetName", "string", &["FactoryDefaults"]),
+            ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+        ],
+    )
+}
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+fn handle_connection(mut stream: TcpStream, shared: &Shared) {
+    stream.set_read_timeout(Some(Duration::from_secs(10))).ok();
+    let request = match HttpRequest::read(&mut stream) {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::debug!("DLNA renderer: bad request: {}", e);
+            return;
+        }
+    };
+    let head_only = request.method == "HEAD";
+    let path = request.path.split('?').next().unwrap_or("/");
+    let xml = "text/xml; charset=\"utf-8\"";
+    let document = match path {
+        "/description.xml" => Some(device_description(
+            DEVICE_TYPE,
+            &shared.friendly_name,
+            "SLAIN Player",
+            "DMR-1.50",
+            &shared.uuid,
+            &[
+                (AV_TRANSPORT, "AVTransport"),
+                (RENDERING_CONTROL, "RenderingControl"),
+                (CONNECTION_MANAGER, "ConnectionManager"),
+            ],
+        )),
+        "/AVTransport.xml" => Some(av_transport_scpd()),
+        "/RenderingControl.xml" => Some(rendering_control_scpd()),
+        "/ConnectionManager.xml" => Some(connection_manager_scpd()),
+        _ => None,
+    };
+
+    match (request.method.as_str(), document) {
+        ("GET" | "HEAD", Some(body)) => {
+            write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), head_only);
+        }
+        ("POST", _) if path.starts_with("/control/") => {
+            let body = String::from_utf8_lossy(&request.body);
+            let result = crate::dlna_server::parse_soap_request(&body)
+                .map_err(|_| INVALID_ACTION)
+                .and_then(|(action, args)| match &path["/control/".len()..] {
+                    "AVTransport" => av_transport_action(shared, &action, &args),
+                    "RenderingControl" => rendering_control_action(shared, &action, &args),
+                    "ConnectionManager" => connection_manager_action(&action, &args),
+                    _ => Err(INVALID_ACTION),
+                });
+            match result {
+                Ok(body) => write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), false),
+                Err(error) => {
+                    let body = soap_fault(error);
+                    write_response(
+                        &mut stream,
+                        "500 Internal Server Error",
+                        xml,
+                        &[],
+                        body.as_bytes(),
+                        false,
+                    );
+                }
+            }
+        }
+        ("SUBSCRIBE", _) if path.starts_with("/event/") => {
+            subscribe(&mut stream, shared, &request, &path["/event/".len()..]);
+        }
+        ("UNSUBSCRIBE", _) if path.starts_with("/event/") => {
+            let removed = request.headers.get("sid").is_some_and(|sid| {
+                let mut subscriptions = shared.subscriptions.lock();
+                let before = subscriptions.len();
+                subscriptions.retain(|s| &s.sid != sid);
+                subscriptions.len() != before
+            });
+            let status = if removed {
+                "200 OK"
+            } else {
+                "412 Precondition Failed"
+            };
+            write_response(&mut stream, status, "text/plain", &[], b"", false);
+        }
+        _ => write_response(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            &[],
+            b"",
+            head_only,
+        ),
+    }
+}
+
+fn subscribe(stream: &mut TcpStream, shared: &Shared, request: &HttpRequest, name: &str) {
+    let Some(service) = Service::from_name(name) else {
+        write_response(stream, "404 Not Found", "text/plain", &[], b"", false);
+        return;
+    };
+    let timeout = subscription_timeout(request.headers.get("timeout"));
+    let expires = Instant::now() + Duration::from_secs(timeout);
+    let headers = &request.headers;
+    let mut subscriptions = shared.subscriptions.lock();
+
+    let sid = match (
+        headers.get("sid"),
+        headers.get("callback"),
+        headers.get("nt"),
+    ) {
+        // Renewal
+        (Some(sid), None, None) => {
+            match subscriptions
+                .iter_mut()
+                .find(|s| &s.sid == sid && s.service == service)
+            {
+                Some(subscription) => {
+                    subscription.expires = expires;
+                    sid.clone()
+                }
+                None => {
+                    drop(subscriptions);
+                    write_response(
+                        stream,
+                        "412 Precondition Failed",
+                        "text/plain",
+                        &[],
+                        b"",
+                        false,
+                    );
+                    return;
+                }
+            }
+        }
+        (None, Some(callback), Some(nt)) if nt == "upnp:event" => {
+            let callbacks = parse_callbacks(callback);
+            if callbacks.is_empty() {
+                drop(subscriptions);
+                write_response(
+                    stream,
+                    "412 Precondition Failed",
+                    "text/plain",
+                    &[],
+                    b"",
+                    false,
+                );
+                return;
+            }
+            let sid = format!("uuid:{}", uuid::Uuid::new_v4());
+            subscriptions.push(Subscription {
+                sid: sid.clone(),
+                service,
+                callbacks,
+                expires,
+                seq: 0,
+            });
+            sid
+        }
+        (None, _, _) => {
+            drop(subscriptions);
+            write_response(
+                stream,
+                "412 Precondition Failed",
+                "text/plain",
+                &[],
+                b"",
+                false,
+            );
+            return;
+        }
+        _ => {
+            drop(subscriptions);
+            write_response(stream, "400 Bad Request", "text/plain", &[], b"", false);
+            return;
+        }
+    };
+    drop(subscriptions);
+    write_response(
+        stream,
+        "200 OK",
+        "text/plain",
+        &[("SID", sid), ("TIMEOUT", format!("Second-{}", timeout))],
+        b"",
+        false,
+    );
+}
+
+/// Sends initial events to new subscribers and `LastChange` events when
+/// evented state changes; drops expired subscriptions
+fn run_events(shared: &Shared, shutdown: &AtomicBool) {
+    let mut last_sent: HashMap<Service, Vec<(&'static str, String)>> = HashMap::new();
+    while !shutdown.load(Ordering::Relaxed) {
+        thread::sleep(EVENT_INTERVAL);
+
+        let (av_transport, rendering_control) = {
+            let state = shared.state.lock();
+            (
+                state.av_transport_variables(),
+                state.rendering_control_variables(),
+            )
+        };
+        let current = |service: Service| -> Vec<(&'static str, String)> {
+            match service {
+                Service::AvTransport => av_transport.clone(),
+                Service::RenderingControl => rendering_control.clone(),
+                Service::ConnectionManager => vec![
+                    ("SourceProtocolInfo", String::new()),
+                    ("SinkProtocolInfo", sink_protocol_info()),
+                    ("CurrentConnectionIDs", "0".to_string()),
+                ],
+            }
+        };
+        // Only what changed goes into LastChange
+        let mut changes: HashMap<Service, Vec<(&'static str, String)>> = HashMap::new();
+        for service in [Service::AvTransport, Service::RenderingControl] {
+            let now = current(service);
+            let previous = last_sent.insert(service, now.clone());
+            let changed: Vec<_> = match previous {
+                Some(previous) => now.into_iter().filter(|v| !previous.contains(v)).collect(),
+                None => Vec::new(),
+            };
+            if !changed.is_empty() {
+                changes.insert(service, changed);
+            }
+        }
+
+        let body = |service: Service, variables: &[(&'static str, String)]| match service {
+            Service::AvTransport => property_set(&[(
+                "LastChange",
+                last_change("urn:schemas-upnp-org:metadata-1-0/AVT/", variables),
+            )]),
+            Service::RenderingControl => property_set(&[(
+                "LastChange",
+                last_change("urn:schemas-upnp-org:metadata-1-0/RCS/", variables),
+            )]),
+            Service::ConnectionManager => property_set(variables),
+        };
+
+        // Build the deliveries under the lock, send without it
+        let mut deliveries = Vec::new();
+        {
+            let mut subscriptions = shared.subscriptions.lock();
+            let now = Instant::now();
+            subscriptions.retain(|s| s.expires > now);
+            for subscription in subscriptions.iter_mut() {
+                let message = if subscription.seq == 0 {
+                    body(subscription.service, &current(subscription.service))
+                } else if let Some(changed) = changes.get(&subscription.service) {
+                    body(subscription.service, changed)
+                } else {
+                    continue;
+                };
+                deliveries.push((
+                    subscription.callbacks.clone(),
+                    subscription.sid.clone(),
+                    subscription.seq,
+                    message,
+                ));
+                // SEQ wraps to 1; 0 is only ever the initial event
+                subscription.seq = subscription.seq.checked_add(1).unwrap_or(1);
+            }
+        }
+        for (callbacks, sid, seq, message) in deliveries {
+            send_event(&callbacks, &sid, seq, &message);
+        }
+    }
+}
+
+// ============================================================================
+// Media Fetching
+// ============================================================================
+
+/// Extension the player's container dispatch understands
+fn media_extension(uri: &str, mime: Option<&str>) -> &'static str {
+    let path = uri.split(['?', '#']).next().unwrap_or(uri);
+    let from_path = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    const KNOWN: &[&str] = &[
+        "mkv", "webm", "mp4", "m4v", "mov", "avi", "ts", "mts", "m2ts",
+    ];
+    if let Some(ext) = from_path.and_then(|ext| KNOWN.iter().find(|k| **k == ext)) {
+        return ext;
+    }
+    match mime.unwrap_or("") {
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "video/webm" => "webm",
+        "video/x-msvideo" | "video/avi" => "avi",
+        "video/mp2t" | "video/vnd.dlna.mpeg-tts" => "ts",
+        // The player tries Matroska for anything it doesn't recognise
+        _ => "mkv",
+    }
+}
+
+/// Largest remote media the renderer will download before giving up
+pub const MAX_FETCH_BYTES: u64 = 8 << 30;
+
+/// Distinguishes the partial files of concurrent fetches of one URI
+static FETCH_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// A renderer URI being made playable locally on its own thread
+///
+/// Cancelling stops the download at the next chunk and removes its partial
+/// file; starting a fetch evicts every other download from the cache, so it
+/// only ever holds the latest URI.
+pub struct MediaFetch {
+    cancel: Arc<AtomicBool>,
+    handle: JoinHandle<Result<std::path::PathBuf, String>>,
+}
+
+impl MediaFetch {
+    pub fn start(uri: String, mime: Option<String>) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                fetch_media(
+                    &uri,
+                    mime.as_deref(),
+                    &std::env::temp_dir().join("slain-dlna"),
+                    MAX_FETCH_BYTES,
+                    &cancel,
+                )
+            })
+        };
+        Self { cancel, handle }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop the download without waiting for its thread
+    pub fn cancel(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn join(self) -> Result<std::path::PathBuf, String> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err("Fetch panicked".to_string()))
+    }
+}
+
+/// Local path for a renderer URI: `file://` URIs and plain paths as-is,
+/// HTTP downloaded into `directory` first (blocking)
+fn fetch_media(
+    uri: &str,
+    mime: Option<&str>,
+    directory: &std::path::Path,
+    max_bytes: u64,
+    cancel: &AtomicBool,
+) -> Result<std::path::PathBuf, String> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        let url = url::Url::parse(uri).map_err(|e| format!("Invalid URI: {}", e))?;
+        return url
+            .to_file_path()
+            .map_err(|_| format!("Invalid file URI: {}", path));
+    }
+    if !uri.starts_with("http://") && !uri.starts_with("https://") {
+        let path = std::path::PathBuf::from(uri);
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!("Unsupported URI: {}", uri))
+        };
+    }
+
+    std::fs::create_dir_all(directory).map_err(|e| format!("Failed to create cache: {}", e))?;
+    let name = {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        uri.hash(&mut hasher);
+        format!("{:016x}.{}", hasher.finish(), media_extension(uri, mime))
+    };
+    let path = directory.join(&name);
+    // Superseded downloads (and partials of cancelled ones) go; a file still
+    // open for playback elsewhere may refuse, and is retried next time
+    if let Ok(entries) = std::fs::read_dir(directory) {
+        for entry in entries.flatten() {
+            if entry.file_name() != name.as_str() {
+                std::fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    tracing::info!("DLNA renderer: fetching {}", uri);
+    let response = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(10))
+        .timeout_read(Duration::from_secs(30))
+        .build()
+        .get(uri)
+        .call()
+        .map_err(|e| format!("Failed to fetch {}: {}", uri, e))?;
+    let too_large = || {
+        format!(
+            "{} is larger than the {} MiB fetch limit",
+            uri,
+            max_bytes >> 20
+        )
+    };
+    if response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok())
+        .is_some_and(|len| len > max_bytes)
+    {
+        return Err(too_large());
+    }
+
+    // Write under a temporary name so a failed download is never reused
+    let partial = path.with_extension(format!(
+        "{}.part",
+        FETCH_SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut file = std::fs::File::create(&partial)
+            .map_err(|e| format!("Failed to fetch {}: {}", uri, e))?;
+        let mut reader = response.into_reader();
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut total = 0u64;
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(format!("Fetch of {} cancelled", uri));
+            }
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(format!("Failed to fetch {}: {}", uri, e)),
+            };
+            total += n as u64;
+            if total > max_bytes {
+                return Err(too_large());
+            }
+            file.write_all(&buffer[..n])
+                .map_err(|e| format!("Failed to fetch {}: {}", uri, e))?;
+        }
+        drop(file);
+        std::fs::rename(&partial, &path).map_err(|e| format!("Failed to fetch {}: {}", uri, e))
+    })();
+    if let Err(e) = result {
+        std::fs::remove_file(&partial).ok();
+        return Err(e);
+    }
+    Ok(path)
+}
+
+// ============================================================================
+// Renderer
+// ============================================================================
+
+/// A running MediaRenderer; stops (and says byebye) when dropped
+pub struct DlnaRenderer {
+    shared: Arc<Shared>,
+    port: u16,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DlnaRenderer {
+    /// Start the renderer; the player drains the returned receiver
+    pub fn start(
+        config: DlnaRendererConfig,
+    ) -> Result<(Self, mpsc::Receiver<RendererCommand>), String> {
+        let uuid = config
+            .uuid
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let listener = TcpListener::bind(("0.0.0.0", config.port))
+            .map_err(|e| format!("Failed to bind DLNA renderer: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to bind DLNA renderer: {}", e))?
+            .port();
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to bind DLNA renderer: {}", e))?;
+
+        let (commands, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(RendererState::new()),
+            subscriptions: Mutex::new(Vec::new()),
+            commands,
+            friendly_name: config.friendly_name.clone(),
+            uuid: uuid.clone(),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut threads = Vec::new();
+
+        {
+            let shared = shared.clone();
+            let shutdown = shutdown.clone();
+            threads.push(thread::spawn(move || {
+                while !shutdown.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            // Accepted sockets inherit non-blocking mode on some platforms
+                            stream.set_nonblocking(false).ok();
+                            let shared = shared.clone();
+                            thread::spawn(move || handle_connection(stream, &shared));
+                        }
+                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(50));
+                        }
+                        Err(e) => {
+                            tracing::warn!("DLNA renderer accept failed: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }));
+        }
+        {
+            let shared = shared.clone();
+            let shutdown = shutdown.clone();
+            threads.push(thread::spawn(move || run_events(&shared, &shutdown)));
+        }
+
+        if config.advertise {
+            let advertised = local_ip_address::local_ip()
+                .map_err(|e| format!("No local IP: {}", e))
+                .and_then(|ip| {
+                    SsdpAdvertisement {
+                        uuid: uuid.clone(),
+                        device_type: DEVICE_TYPE,
+                        services: vec![AV_TRANSPORT, RENDERING_CONTROL, CONNECTION_MANAGER],
+                        location: format!("http://{}:{}/description.xml", ip, port),
+                        max_age: config.max_age_secs.max(60),
+                    }
+                    .spawn(shutdown.clone())
+                });
+            match advertised {
+                Ok(thread) => threads.push(thread),
+                Err(e) => tracing::warn!("DLNA renderer not advertised: {}", e),
+            }
+        }
+
+        tracing::info!(
+            "DLNA renderer \"{}\" on port {}",
+            config.friendly_name,
+            port
+        );
+        Ok((
+            Self {
+                shared,
+                port,
+                shutdown,
+                threads,
+            },
+            receiver,
+        ))
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.shared.uuid
+    }
+
+    /// Report the player's state; subscribers are evented on changes
+    pub fn update_playback(&self, playback: RendererPlayback) {
+        let mut state = self.shared.state.lock();
+        if state.uri.is_empty() {
+            return;
+        }
+        // Keep TRANSITIONING until the player has actually started
+        if !(state.transport_state == AvTransportState::Transitioning
+            && playback.state == AvTransportState::Stopped)
+        {
+            state.transport_state = playback.state;
+        }
+        state.position_ms = playback.position_ms;
+        state.duration_ms = playback.duration_ms;
+    }
+
+    /// Report volume changes made on the player itself
+    pub fn update_volume(&self, volume: f32, muted: bool) {
+        let mut state = self.shared.state.lock();
+        state.volume = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        state.muted = muted;
+    }
+
+    /// The player couldn't open or play the current URI
+    pub fn report_error(&self, message: &str) {
+        tracing::warn!("DLNA renderer: {}", message);
+        let mut state = self.shared.state.lock();
+        state.transport_state = AvTransportState::Stopped;
+        state.transport_status = "ERROR_OCCURRED";
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown_threads();
+    }
+
+    fn shutdown_threads(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DlnaRenderer {
+    fn drop(&mut self) {
+        self.shutdown_threads();
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dlna_server::{parse_soap_request, soap_envelope};
+    use crate::test_support::{http_server, respond};
+
+    fn start() -> (DlnaRenderer, mpsc::Receiver<RendererCommand>) {
+        DlnaRenderer::start(DlnaRendererConfig {
+            advertise: false,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// One raw HTTP exchange; returns (status, headers, body)
+    fn http(port: u16, request: &str) -> (u16, HashMap<String, String>, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap()[9..12].parse().unwrap();
+        let headers = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim().to_string()))
+            .collect();
+        (status, headers, body.to_string())
+    }
+
+    fn soap(
+        port: u16,
+        service: &str,
+        action: &str,
+        args: &[(&str, &str)],
+    ) -> Result<HashMap<String, String>, u16> {
+        let urn = format!("urn:schemas-upnp-org:service:{}:1", service);
+        let mut body = format!(r#"<u:{} xmlns:u="{}">"#, action, urn);
+        for (name, value) in args {
+            body.push_str(&format!("<{}>{}</{}>", name, xml_escape(value), name));
+        }
+        body.push_str(&format!("</u:{}>", action));
+        let body = soap_envelope(&body);
+        let (status, _, response) = http(
+            port,
+            &format!(
+                "POST /control/{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Type: text/xml\r\nSOAPACTION: \"{}#{}\"\r\nContent-Length: {}\r\n\r\n{}",
+                service,
+                port,
+                urn,
+                action,
+                body.len(),
+                body
+            ),
+        );
+        if status != 200 {
+            let code = parse_xml(&response)
+                .ok()
+                .and_then(|e| {
+                    let fault = e.child("Body")?.child("Fault")?;
+                    let code = fault
+                        .child("detail")?
+                        .child("UPnPError")?
+                        .child("errorCode")?;
+                    code.text.parse().ok()
+                })
+                .unwrap_or(status);
+            return Err(code);
+        }
+        Ok(parse_soap_request(&response).unwrap().1)
+    }
+
+    #[test]
+    fn test_time_and_metadata() {
+        assert_eq!(
+            media_extension("http://phone/v/clip.MP4?sig=1", None),
+            "mp4"
+        );
+        assert_eq!(
+            media_extension("http://phone/stream?id=4", Some("video/mp2t")),
+            "ts"
+        );
+        assert_eq!(media_extension("http://phone/stream", None), "mkv");
+
+        assert_eq!(upnp_time(3_725_900), "1:02:05");
+        assert_eq!(parse_upnp_time("1:02:05"), Some(3_725_000));
+        assert_eq!(parse_upnp_time("0:00:30.5"), Some(30_500));
+        assert_eq!(parse_upnp_time("00:01:00.250"), Some(60_250));
+        assert_eq!(parse_upnp_time("+10:00:00.1/4"), Some(36_000_250));
+        assert_eq!(parse_upnp_time("0:61:00"), None);
+        assert_eq!(parse_upnp_time("12"), None);
+
+        let metadata = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/"><item id="1" parentID="0" restricted="1"><dc:title>Big &amp; Bold</dc:title><res protocolInfo="http-get:*:video/x-matroska:*">http://phone/v.mkv</res></item></DIDL-Lite>"#;
+        assert_eq!(
+            didl_title_and_mime(metadata),
+            (
+                Some("Big & Bold".to_string()),
+                Some("video/x-matroska".to_string())
+            )
+        );
+        assert_eq!(didl_title_and_mime(""), (None, None));
+
+        let event = last_change(
+            "urn:schemas-upnp-org:metadata-1-0/RCS/",
+            &[
+                ("Volume", "40".to_string()),
+                ("PresetNameList", "A&B".to_string()),
+            ],
+        );
+        assert_eq!(
+            event,
+            r#"<Event xmlns="urn:schemas-upnp-org:metadata-1-0/RCS/"><InstanceID val="0"><Volume channel="Master" val="40"/><PresetNameList val="A&amp;B"/></InstanceID></Event>"#
+        );
+        assert_eq!(
+            parse_callbacks("<http://10.0.0.5:4000/cb><https://x/><http://10.0.0.5/b>"),
+            vec!["http://10.0.0.5:4000/cb", "http://10.0.0.5/b"]
+        );
+        assert_eq!(subscription_timeout(Some(&"Second-5".to_string())), 60);
+        assert_eq!(subscription_timeout(Some(&"infinite".to_string())), 1800);
+    }
+
+    #[test]
+    fn test_transport_and_rendering_control() {
+        let (renderer, commands) = start();
+        let port = renderer.port();
+
+        let (status, _, description) = http(
+            port,
+            "GET /description.xml HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        assert_eq!(status, 200);
+        let device = parse_xml(&description).unwrap();
+        let device = device.child("device").unwrap();
+        assert_eq!(device.child("deviceType").unwrap().text, DEVICE_TYPE);
+        assert_eq!(device.child("serviceList").unwrap().children.len(), 3);
+        let (_, _, scpd) = http(port, "GET /AVTransport.xml HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            parse_xml(&scpd)
+                .unwrap()
+                .child("actionList")
+                .unwrap()
+                .children
+                .len(),
+            11
+        );
+
+        let info = |field: &str| {
+            soap(
+                port,
+                "AVTransport",
+                "GetTransportInfo",
+                &[("InstanceID", "0")],
+            )
+            .unwrap()[field]
+                .clone()
+        };
+        assert_eq!(info("CurrentTransportState"), "NO_MEDIA_PRESENT");
+        assert_eq!(
+            soap(
+                port,
+                "AVTransport",
+                "Play",
+                &[("InstanceID", "0"), ("Speed", "1")]
+            ),
+            Err(702)
+        );
+
+        let metadata = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/"><item id="1" parentID="0" restricted="1"><dc:title>Clip</dc:title><res protocolInfo="http-get:*:video/mp4:*">http://phone:8080/clip.mp4</res></item></DIDL-Lite>"#;
+        soap(
+            port,
+            "AVTransport",
+            "SetAVTransportURI",
+            &[
+                ("InstanceID", "0"),
+                ("CurrentURI", "http://phone:8080/clip.mp4"),
+                ("CurrentURIMetaData", metadata),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            commands.recv_timeout(Duration::from_secs(1)).unwrap(),
+            RendererCommand::Load {
+                uri: "http://phone:8080/clip.mp4".to_string(),
+                title: Some("Clip".to_string()),
+                mime: Some("video/mp4".to_string()),
+            }
+        );
+        assert_eq!(info("CurrentTransportState"), "STOPPED");
+
+        soap(
+            port,
+            "AVTransport",
+            "Play",
+            &[("InstanceID", "0"), ("Speed", "1")],
+        )
+        .unwrap();
+        assert_eq!(commands.recv().unwrap(), RendererCommand::Play);
+        assert_eq!(info("CurrentTransportState"), "TRANSITIONING");
+        renderer.update_playback(RendererPlayback {
+            state: AvTransportState::Playing,
+            position_ms: 65_000,
+            duration_ms: 120_000,
+        });
+        let position = soap(
+            port,
+            "AVTransport",
+            "GetPositionInfo",
+            &[("InstanceID", "0")],
+        )
+        .unwrap();
+        assert_eq!(position["RelTime"], "0:01:05");
+        assert_eq!(position["TrackDuration"], "0:02:00");
+        assert_eq!(position["TrackURI"], "http://phone:8080/clip.mp4");
+        assert!(position["TrackMetaData"].contains("<dc:title>Clip</dc:title>"));
+
+        let seek = |unit: &str, target: &str| {
+            soap(
+                port,
+                "AVTransport",
+                "Seek",
+                &[("InstanceID", "0"), ("Unit", unit), ("Target", target)],
+            )
+            .map(|_| ())
+        };
+        seek("REL_TIME", "0:00:30.5").unwrap();
+        assert_eq!(
+            commands.recv().unwrap(),
+            RendererCommand::Seek {
+                position_ms: 30_500
+            }
+        );
+        assert_eq!(seek("TRACK_NR", "1"), Err(710));
+        assert_eq!(seek("REL_TIME", "1:00:00"), Err(711));
+
+        soap(port, "AVTransport", "Pause", &[("InstanceID", "0")]).unwrap();
+        assert_eq!(commands.recv().unwrap(), RendererCommand::Pause);
+        assert_eq!(
+            soap(
+                port,
+                "AVTransport",
+                "GetCurrentTransportActions",
+                &[("InstanceID", "0")]
+            )
+            .unwrap()["Actions"],
+            "Play,Stop,Seek"
+        );
+        soap(port, "AVTransport", "Stop", &[("InstanceID", "0")]).unwrap();
+        assert_eq!(commands.recv().unwrap(), RendererCommand::Stop);
+        assert_eq!(
+            soap(port, "AVTransport", "Pause", &[("InstanceID", "0")]),
+            Err(701)
+        );
+        assert_eq!(
+            soap(
+                port,
+                "AVTransport",
+                "GetTransportInfo",
+                &[("InstanceID", "3")]
+            ),
+            Err(718)
+        );
+        assert_eq!(
+            soap(port, "AVTransport", "Record", &[("InstanceID", "0")]),
+            Err(401)
+        );
+
+        let volume = |value: &str| {
+            soap(
+                port,
+                "RenderingControl",
+                "SetVolume",
+                &[
+                    ("InstanceID", "0"),
+                    ("Channel", "Master"),
+                    ("DesiredVolume", value),
+                ],
+            )
+            .map(|_| ())
+        };
+        volume("40").unwrap();
+        assert_eq!(commands.recv().unwrap(), RendererCommand::SetVolume(0.4));
+        assert_eq!(volume("150"), Err(402));
+        renderer.update_volume(0.25, true);
+        let get = soap(
+            port,
+            "RenderingControl",
+            "GetVolume",
+            &[("InstanceID", "0"), ("Channel", "Master")],
+        )
+        .unwrap();
+        assert_eq!(get["CurrentVolume"], "25");
+        soap(
+            port,
+            "RenderingControl",
+            "SetMute",
+            &[
+                ("InstanceID", "0"),
+                ("Channel", "Master"),
+                ("DesiredMute", "0"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(commands.recv().unwrap(), RendererCommand::SetMute(false));
+
+        let protocols = soap(port, "ConnectionManager", "GetProtocolInfo", &[]).unwrap();
+        assert!(protocols["Sink"].contains("http-get:*:video/x-matroska:*"));
+        renderer.stop();
+    }
+
+    #[test]
+    fn test_gena_events() {
+        let (renderer, _commands) = start();
+        let port = renderer.port();
+
+        // Control point's event sink
+        let (events_tx, events) = mpsc::channel();
+        let sink = http_server(move |request, stream| {
+            // Recorded before answering, so events arrive in delivery order
+            let body = String::from_utf8_lossy(&request.body).into_owned();
+            let _ = events_tx.send((request.method.clone(), request.headers.clone(), body));
+            respond(stream, "200 OK", &[], b"");
+        });
+
+        let (status, headers, _) = http(
+            port,
+            &format!(
+                "SUBSCRIBE /event/AVTransport HTTP/1.1\r\nHOST: 127.0.0.1:{}\r\nCALLBACK: <{}/avt>\r\nNT: upnp:event\r\nTIMEOUT: Second-300\r\n\r\n",
+                port, sink
+            ),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(headers["TIMEOUT"], "Second-300");
+        let sid = headers["SID"].clone();
+        assert!(sid.starts_with("uuid:"));
+
+        let next_event = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+        let (method, headers, body) = next_event();
+        assert_eq!(method, "NOTIFY");
+        assert_eq!(headers["sid"], sid);
+        assert_eq!(headers["seq"], "0");
+        assert_eq!(headers["nts"], "upnp:propchange");
+        let property_set = parse_xml(&body).unwrap();
+        let last_change = &property_set
+            .child("property")
+            .unwrap()
+            .child("LastChange")
+            .unwrap()
+            .text;
+        let event = parse_xml(last_change).unwrap();
+        let instance = event.child("InstanceID").unwrap();
+        assert_eq!(
+            instance.child("TransportState").unwrap().attr("val"),
+            Some("NO_MEDIA_PRESENT")
+        );
+        assert_eq!(instance.children.len(), 13);
+
+        // A change is evented with just the changed variables
+        soap(
+            port,
+            "AVTransport",
+            "SetAVTransportURI",
+            &[
+                ("InstanceID", "0"),
+                ("CurrentURI", "http://phone/a.mkv"),
+                ("CurrentURIMetaData", ""),
+            ],
+        )
+        .unwrap();
+        let (_, headers, body) = next_event();
+        assert_eq!(headers["seq"], "1");
+        let property_set = parse_xml(&body).unwrap();
+        let last_change = &property_set
+            .child("property")
+            .unwrap()
+            .child("LastChange")
+            .unwrap()
+            .text;
+        let instance = parse_xml(last_change).unwrap();
+        let instance = instance.child("InstanceID").unwrap();
+        assert_eq!(
+            instance.child("TransportState").unwrap().attr("val"),
+            Some("STOPPED")
+        );
+        assert_eq!(
+            instance.child("AVTransportURI").unwrap().attr("val"),
+            Some("http://phone/a.mkv")
+        );
+        assert!(instance.child("TransportPlaySpeed").is_none());
+
+        // Renew, bad renewals, unsubscribe
+        let request = |method: &str, headers: &str| {
+            http(
+                port,
+                &format!(
+                    "{} /event/AVTransport HTTP/1.1\r\nHOST: 127.0.0.1\r\n{}\r\n",
+                    method, headers
+                ),
+            )
+            .0
+        };
+        assert_eq!(
+            request(
+                "SUBSCRIBE",
+                &format!("SID: {}\r\nTIMEOUT: Second-1800\r\n", sid)
+            ),
+            200
+        );
+        assert_eq!(request("SUBSCRIBE", "SID: uuid:nope\r\n"), 412);
+        assert_eq!(
+            request(
+                "SUBSCRIBE",
+                &format!(
+                    "SID: {}\r\nNT: upnp:event\r\nCALLBACK: <http://x/>\r\n",
+                    sid
+                )
+            ),
+            400
+        );
+        assert_eq!(request("SUBSCRIBE", "NT: upnp:event\r\n"), 412);
+        assert_eq!(request("UNSUBSCRIBE", &format!("SID: {}\r\n", sid)), 200);
+        assert_eq!(request("UNSUBSCRIBE", &format!("SID: {}\r\n", sid)), 412);
+
+        renderer.update_volume(0.5, false);
+        renderer.update_playback(RendererPlayback {
+            state: AvTransportState::Playing,
+            position_ms: 1000,
+            duration_ms: 10_000,
+        });
+        assert!(events.recv_timeout(Duration::from_millis(600)).is_err());
+        renderer.stop();
+    }
+
+    #[test]
+    fn test_fetch_media() {
+        let body: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let server = {
+            let body = body.clone();
+            http_server(move |_, stream| respond(stream, "200 OK", &[], &body))
+        };
+        let cache = tempfile::tempdir().unwrap();
+        let uri = |name: &str| format!("{}/{}", server, name);
+        let cached = || std::fs::read_dir(cache.path()).unwrap().count();
+        let idle = AtomicBool::new(false);
+
+        let first = fetch_media(&uri("a.mp4"), None, cache.path(), MAX_FETCH_BYTES, &idle).unwrap();
+        assert_eq!(first.extension().unwrap(), "mp4");
+        assert_eq!(std::fs::read(&first).unwrap(), body);
+
+        // A new URI supersedes the old download
+        let second = fetch_media(&uri("b.ts"), None, cache.path(), MAX_FETCH_BYTES, &idle).unwrap();
+        assert!(!first.exists());
+        assert_eq!(std::fs::read(&second).unwrap(), body);
+        assert_eq!(cached(), 1);
+
+        // Over the cap, or cancelled: nothing is left behind
+        let err = fetch_media(&uri("c.mkv"), None, cache.path(), 100_000, &idle).unwrap_err();
+        assert!(err.contains("fetch limit"), "{}", err);
+        assert_eq!(cached(), 0);
+        let cancelled = AtomicBool::new(true);
+        let err = fetch_media(
+            &uri("d.mkv"),
+            None,
+            cache.path(),
+            MAX_FETCH_BYTES,
+            &cancelled,
+        )
+        .unwrap_err();
+        assert!(err.contains("cancelled"), "{}", err);
+        assert_eq!(cached(), 0);
+
+        assert_eq!(
+            fetch_media(
+                "/no/such/file.mkv",
+                None,
+                cache.path(),
+                MAX_FETCH_BYTES,
+                &idle
+            ),
+            Err("Unsupported URI: /no/such/file.mkv".to_string())
+        );
+    }
+}
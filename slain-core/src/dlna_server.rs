@@ -0,0 +1,2017 @@
+//! DLNA/UPnP MediaServer
+//!
+//! Lets TVs and other DLNA players browse and play the media library:
+//! - SSDP advertisement (NOTIFY alive/byebye) and M-SEARCH answers
+//! - Device, ContentDirectory and ConnectionManager descriptions
+//! - ContentDirectory Browse/Search over SOAP, answered with DIDL-Lite built
+//!   from `media_library::MediaItem`s
+//! - Ranged HTTP delivery with DLNA transfer-mode and content-feature headers
+//!
+//! Event subscriptions are accepted but no events are sent; control points
+//! fall back to polling `GetSystemUpdateID`.
+
+use crate::adaptive_stream::{parse_xml, XmlNode};
+use crate::media_library::{MediaItem, MediaType};
+use crate::streaming::{upnp_server_header, SsdpAdvertisement};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+pub(crate) const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+/// DLNA.ORG_FLAGS: streaming transfer, background transfer, connection
+/// stall, DLNA 1.5
+const DLNA_FLAGS_STREAMING: &str = "01700000000000000000000000000000";
+/// DLNA.ORG_FLAGS for images: interactive instead of streaming transfer
+const DLNA_FLAGS_INTERACTIVE: &str = "00F00000000000000000000000000000";
+
+const SEARCH_CAPABILITIES: &str = "@id,@parentID,dc:title,upnp:class,dc:date,upnp:genre";
+const SORT_CAPABILITIES: &str = "dc:title,dc:date,upnp:class";
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlnaServerConfig {
+    pub friendly_name: String,
+    /// HTTP port (0 picks a free one)
+    pub port: u16,
+    /// Device UUID; keep it stable so renderers remember the server.
+    /// Generated when None.
+    pub uuid: Option<String>,
+    /// Announce on and answer SSDP searches from the multicast group
+    pub advertise: bool,
+    /// SSDP `max-age`; alive notifications repeat at half of it
+    pub max_age_secs: u32,
+}
+
+impl Default for DlnaServerConfig {
+    fn default() -> Self {
+        Self {
+            friendly_name: "SLAIN Media Server".to_string(),
+            port: 0,
+            uuid: None,
+            advertise: true,
+            max_age_secs: 1800,
+        }
+    }
+}
+
+// ============================================================================
+// Content Directory
+// ============================================================================
+
+/// UPnP error returned in a SOAP fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpnpError {
+    pub code: u16,
+    pub description: &'static str,
+}
+
+pub(crate) const INVALID_ACTION: UpnpError = UpnpError {
+    code: 401,
+    description: "Invalid Action",
+};
+pub(crate) const INVALID_ARGS: UpnpError = UpnpError {
+    code: 402,
+    description: "Invalid Args",
+};
+const NO_SUCH_OBJECT: UpnpError = UpnpError {
+    code: 701,
+    description: "No such object",
+};
+const BAD_SEARCH_CRITERIA: UpnpError = UpnpError {
+    code: 708,
+    description: "Unsupported or invalid search criteria",
+};
+const BAD_SORT_CRITERIA: UpnpError = UpnpError {
+    code: 709,
+    description: "Unsupported or invalid sort criteria",
+};
+const NO_SUCH_CONTAINER: UpnpError = UpnpError {
+    code: 710,
+    description: "No such container",
+};
+
+#[derive(Debug, Clone)]
+enum ResourceLocation {
+    /// Served by us under `/media/`
+    File(PathBuf),
+    /// Handed to the renderer as-is
+    Remote(String),
+}
+
+#[derive(Debug, Clone)]
+struct Resource {
+    location: ResourceLocation,
+    mime: &'static str,
+    size: Option<u64>,
+    duration_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct CdsObject {
+    id: String,
+    parent_id: String,
+    title: String,
+    class: &'static str,
+    /// Child indices (containers only)
+    children: Option<Vec<usize>>,
+    date: Option<String>,
+    genres: Vec<String>,
+    description: Option<String>,
+    album_art: Option<String>,
+    director: Option<String>,
+    resource: Option<Resource>,
+}
+
+impl CdsObject {
+    fn container(id: &str, parent_id: &str, title: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            parent_id: parent_id.to_string(),
+            title: title.to_string(),
+            class: "object.container.storageFolder",
+            children: Some(Vec::new()),
+            date: None,
+            genres: Vec::new(),
+            description: None,
+            album_art: None,
+            director: None,
+            resource: None,
+        }
+    }
+
+    /// Values of a DIDL property, for search and sort
+    fn property(&self, name: &str) -> Vec<&str> {
+        match name {
+            "@id" => vec![self.id.as_str()],
+            "@parentID" => vec![self.parent_id.as_str()],
+            "dc:title" => vec![self.title.as_str()],
+            "upnp:class" => vec![self.class],
+            "dc:date" => self.date.as_deref().into_iter().collect(),
+            "upnp:genre" => self.genres.iter().map(String::as_str).collect(),
+            "dc:description" => self.description.as_deref().into_iter().collect(),
+            "upnp:director" => self.director.as_deref().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Top-level container and item class for each media type
+fn media_type_layout(media_type: MediaType) -> (&'static str, &'static str, &'static str) {
+    match media_type {
+        MediaType::Movie => ("movies", "Movies", "object.item.videoItem.movie"),
+        MediaType::TvShow | MediaType::Episode => ("tv", "TV Shows", "object.item.videoItem"),
+        MediaType::LiveTv => ("livetv", "Live TV", "object.item.videoItem.videoBroadcast"),
+        MediaType::Music => ("music", "Music", "object.item.audioItem.musicTrack"),
+        MediaType::Photo => ("photos", "Photos", "object.item.imageItem.photo"),
+    }
+}
+
+/// MIME type for a file name or URL path
+fn mime_for(name: &str) -> &'static str {
+    let name = name.split(['?', '#']).next().unwrap_or(name);
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        "mov" => "video/quicktime",
+        "wmv" => "video/x-ms-wmv",
+        "flv" => "video/x-flv",
+        "webm" => "video/webm",
+        "ts" | "mts" | "m2ts" => "video/mp2t",
+        "mpg" | "mpeg" | "vob" => "video/mpeg",
+        "3gp" => "video/3gpp",
+        "ogv" => "video/ogg",
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "m4a" | "aac" => "audio/mp4",
+        "ogg" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "m3u8" => "application/x-mpegURL",
+        _ => "video/mpeg",
+    }
+}
+
+/// `contentFeatures.dlna.org` / fourth protocolInfo field for our own files
+fn dlna_features(mime: &str) -> String {
+    let profile = match mime {
+        "audio/mpeg" => "DLNA.ORG_PN=MP3;",
+        "image/jpeg" => "DLNA.ORG_PN=JPEG_LRG;",
+        _ => "",
+    };
+    let flags = if mime.starts_with("image/") {
+        DLNA_FLAGS_INTERACTIVE
+    } else {
+        DLNA_FLAGS_STREAMING
+    };
+    // OP=01: byte range seeking; CI=0: not transcoded
+    format!(
+        "{}DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS={}",
+        profile, flags
+    )
+}
+
+/// `H:MM:SS.000`
+fn didl_duration(seconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}.000",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Result of Browse / Search
+#[derive(Debug, Clone)]
+pub struct BrowseResult {
+    /// DIDL-Lite document
+    pub didl: String,
+    pub number_returned: usize,
+    pub total_matches: usize,
+}
+
+/// The object tree: root "0", one container per media type, items below
+pub struct ContentDirectory {
+    objects: Vec<CdsObject>,
+    index: HashMap<String, usize>,
+    /// SystemUpdateID / container UpdateID
+    pub update_id: u32,
+}
+
+impl ContentDirectory {
+    pub fn new(items: &[MediaItem], update_id: u32) -> Self {
+        let mut objects = vec![CdsObject::container("0", "-1", "Root")];
+        let mut containers: Vec<(&'static str, usize)> = Vec::new();
+        let order = [
+            MediaType::Movie,
+            MediaType::TvShow,
+            MediaType::LiveTv,
+            MediaType::Music,
+            MediaType::Photo,
+        ];
+
+        let mut sorted: Vec<&MediaItem> = items.iter().collect();
+        sorted.sort_by_key(|item| {
+            let (container, ..) = media_type_layout(item.media_type);
+            order
+                .iter()
+                .position(|t| media_type_layout(*t).0 == container)
+        });
+        for item in sorted {
+            let Some(resource) = Self::resource(item) else {
+                tracing::debug!("DLNA: skipping {} (no playable source)", item.title);
+                continue;
+            };
+            let (container_id, container_title, class) = media_type_layout(item.media_type);
+            let container = match containers.iter().find(|(id, _)| *id == container_id) {
+                Some((_, index)) => *index,
+                None => {
+                    objects.push(CdsObject::container(container_id, "0", container_title));
+                    let index = objects.len() - 1;
+                    containers.push((container_id, index));
+                    if let Some(children) = &mut objects[0].children {
+                        children.push(index);
+                    }
+                    index
+                }
+            };
+
+            let metadata = item.metadata.as_ref();
+            let date = metadata
+                .and_then(|m| m.release_date.clone())
+                .or_else(|| item.year.map(|y| format!("{:04}-01-01", y)));
+            objects.push(CdsObject {
+                id: item.id.clone(),
+                parent_id: container_id.to_string(),
+                title: match item.year {
+                    Some(year) if item.media_type == MediaType::Movie => {
+                        format!("{} ({})", item.title, year)
+                    }
+                    _ => item.title.clone(),
+                },
+                class,
+                children: None,
+                date,
+                genres: metadata.map(|m| m.genres.clone()).unwrap_or_default(),
+                description: metadata.and_then(|m| m.overview.clone()),
+                album_art: metadata.and_then(|m| m.poster_url.clone()),
+                director: metadata.and_then(|m| m.director.clone()),
+                resource: Some(Resource {
+                    duration_secs: metadata
+                        .and_then(|m| m.runtime_minutes)
+                        .map(|m| m as u64 * 60),
+                    ..resource
+                }),
+            });
+            let index = objects.len() - 1;
+            if let Some(children) = &mut objects[container].children {
+                children.push(index);
+            }
+        }
+
+        let index = objects
+            .iter()
+            .enumerate()
+            .map(|(i, o)| (o.id.clone(), i))
+            .collect();
+        Self {
+            objects,
+            index,
+            update_id,
+        }
+    }
+
+    fn resource(item: &MediaItem) -> Option<Resource> {
+        if let Some(path) = item.path.as_deref() {
+            if let Ok(meta) = std::fs::metadata(path) {
+                return Some(Resource {
+                    location: ResourceLocation::File(PathBuf::from(path)),
+                    mime: mime_for(path),
+                    size: Some(meta.len()),
+                    duration_secs: None,
+                });
+            }
+        }
+        let url = item.stream_url.as_deref()?;
+        Some(Resource {
+            location: ResourceLocation::Remote(url.to_string()),
+            mime: mime_for(url),
+            size: None,
+            duration_secs: None,
+        })
+    }
+
+    /// Number of objects, including the root and containers
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.len() <= 1
+    }
+
+    fn get(&self, id: &str) -> Option<&CdsObject> {
+        self.index.get(id).map(|&i| &self.objects[i])
+    }
+
+    /// Local file behind an item (for `/media/` requests)
+    fn file(&self, id: &str) -> Option<(&Path, &'static str)> {
+        let resource = self.get(id)?.resource.as_ref()?;
+        match &resource.location {
+            ResourceLocation::File(path) => Some((path, resource.mime)),
+            ResourceLocation::Remote(_) => None,
+        }
+    }
+
+    /// ContentDirectory Browse. `base_url` is how the client reached us
+    /// (`http://host:port`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn browse(
+        &self,
+        object_id: &str,
+        metadata_only: bool,
+        filter: &str,
+        start: usize,
+        count: usize,
+        sort: &str,
+        base_url: &str,
+    ) -> Result<BrowseResult, UpnpError> {
+        let object = self.get(object_id).ok_or(NO_SUCH_OBJECT)?;
+        if metadata_only {
+            return Ok(BrowseResult {
+                didl: self.didl(&[object], filter, base_url),
+                number_returned: 1,
+                total_matches: 1,
+            });
+        }
+        let children: Vec<&CdsObject> = object
+            .children
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|&i| &self.objects[i])
+            .collect();
+        self.page(children, filter, start, count, sort, base_url)
+    }
+
+    /// ContentDirectory Search below `container_id`
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &self,
+        container_id: &str,
+        criteria: &str,
+        filter: &str,
+        start: usize,
+        count: usize,
+        sort: &str,
+        base_url: &str,
+    ) -> Result<BrowseResult, UpnpError> {
+        let container = self.get(container_id).ok_or(NO_SUCH_CONTAINER)?;
+        if container.children.is_none() {
+            return Err(NO_SUCH_CONTAINER);
+        }
+        let expr = SearchExpr::parse(criteria).ok_or(BAD_SEARCH_CRITERIA)?;
+
+        let mut matches = Vec::new();
+        let mut stack: Vec<usize> = container.children.clone().unwrap_or_default();
+        stack.reverse();
+        while let Some(i) = stack.pop() {
+            let object = &self.objects[i];
+            if expr.matches(object) {
+                matches.push(object);
+            }
+            if let Some(children) = &object.children {
+                stack.extend(children.iter().rev());
+            }
+        }
+        self.page(matches, filter, start, count, sort, base_url)
+    }
+
+    fn page(
+        &self,
+        mut objects: Vec<&CdsObject>,
+        filter: &str,
+        start: usize,
+        count: usize,
+        sort: &str,
+        base_url: &str,
+    ) -> Result<BrowseResult, UpnpError> {
+        let keys = parse_sort(sort)?;
+        if !keys.is_empty() {
+            objects.sort_by(|a, b| {
+                keys.iter()
+                    .map(|(property, ascending)| {
+                        let ordering = a
+                            .property(property)
+                            .first()
+                            .map(|v| v.to_lowercase())
+                            .cmp(&b.property(property).first().map(|v| v.to_lowercase()));
+                        if *ascending {
+                            ordering
+                        } else {
+                            ordering.reverse()
+                        }
+                    })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        let total_matches = objects.len();
+        // RequestedCount 0 means "all"
+        let count = if count == 0 { usize::MAX } else { count };
+        let page: Vec<&CdsObject> = objects.into_iter().skip(start).take(count).collect();
+        Ok(BrowseResult {
+            didl: self.didl(&page, filter, base_url),
+            number_returned: page.len(),
+            total_matches,
+        })
+    }
+
+    fn didl(&self, objects: &[&CdsObject], filter: &str, base_url: &str) -> String {
+        let wants = |property: &str| {
+            filter.trim() == "*"
+                || filter.split(',').any(|f| {
+                    let f = f.trim();
+                    f == property || f.split('@').next() == Some(property)
+                })
+        };
+        let mut out = String::from(
+            r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/" xmlns:dlna="urn:schemas-dlna-org:metadata-1-0/">"#,
+        );
+        for object in objects {
+            let id = xml_escape(&object.id);
+            let parent = xml_escape(&object.parent_id);
+            if let Some(children) = &object.children {
+                let _ = write!(
+                    out,
+                    r#"<container id="{}" parentID="{}" restricted="1" searchable="1" childCount="{}">"#,
+                    id,
+                    parent,
+                    children.len()
+                );
+            } else {
+                let _ = write!(
+                    out,
+                    r#"<item id="{}" parentID="{}" restricted="1">"#,
+                    id, parent
+                );
+            }
+            let _ = write!(out, "<dc:title>{}</dc:title>", xml_escape(&object.title));
+            let _ = write!(out, "<upnp:class>{}</upnp:class>", object.class);
+            if let Some(date) = object.date.as_deref().filter(|_| wants("dc:date")) {
+                let _ = write!(out, "<dc:date>{}</dc:date>", xml_escape(date));
+            }
+            if wants("upnp:genre") {
+                for genre in &object.genres {
+                    let _ = write!(out, "<upnp:genre>{}</upnp:genre>", xml_escape(genre));
+                }
+            }
+            if let Some(text) = object
+                .description
+                .as_deref()
+                .filter(|_| wants("dc:description"))
+            {
+                let _ = write!(out, "<dc:description>{}</dc:description>", xml_escape(text));
+            }
+            if let Some(name) = object
+                .director
+                .as_deref()
+                .filter(|_| wants("upnp:director"))
+            {
+                let _ = write!(out, "<upnp:director>{}</upnp:director>", xml_escape(name));
+            }
+            if let Some(url) = object
+                .album_art
+                .as_deref()
+                .filter(|_| wants("upnp:albumArtURI"))
+            {
+                let _ = write!(
+                    out,
+                    "<upnp:albumArtURI>{}</upnp:albumArtURI>",
+                    xml_escape(url)
+                );
+            }
+            if let Some(resource) = object.resource.as_ref().filter(|_| wants("res")) {
+                let (url, features) = match &resource.location {
+                    ResourceLocation::File(path) => (
+                        media_url(base_url, &object.id, path),
+                        dlna_features(resource.mime),
+                    ),
+                    ResourceLocation::Remote(url) => (url.clone(), "*".to_string()),
+                };
+                let _ = write!(
+                    out,
+                    r#"<res protocolInfo="http-get:*:{}:{}""#,
+                    resource.mime, features
+                );
+                if let Some(size) = resource.size.filter(|_| wants("res@size")) {
+                    let _ = write!(out, r#" size="{}""#, size);
+                }
+                if let Some(secs) = resource.duration_secs.filter(|_| wants("res@duration")) {
+                    let _ = write!(out, r#" duration="{}""#, didl_duration(secs));
+                }
+                let _ = write!(out, ">{}</res>", xml_escape(&url));
+            }
+            out.push_str(if object.children.is_some() {
+                "</container>"
+            } else {
+                "</item>"
+            });
+        }
+        out.push_str("</DIDL-Lite>");
+        out
+    }
+
+    /// Comma-separated protocolInfo of everything we serve
+    fn source_protocol_info(&self) -> String {
+        let mut mimes: Vec<&'static str> = self
+            .objects
+            .iter()
+            .filter_map(|o| o.resource.as_ref())
+            .filter(|r| matches!(r.location, ResourceLocation::File(_)))
+            .map(|r| r.mime)
+            .collect();
+        mimes.sort_unstable();
+        mimes.dedup();
+        mimes
+            .iter()
+            .map(|mime| format!("http-get:*:{}:{}", mime, dlna_features(mime)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// `/media/<id>.<ext>` - the extension helps renderers that sniff URLs
+fn media_url(base_url: &str, id: &str, path: &Path) -> String {
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    format!(
+        "{}/media/{}{}",
+        base_url,
+        urlencoding::encode(id),
+        extension
+    )
+}
+
+/// `+dc:title,-dc:date` -> [(property, ascending)]
+fn parse_sort(sort: &str) -> Result<Vec<(String, bool)>, UpnpError> {
+    sort.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|key| {
+            let (ascending, property) = match key.as_bytes()[0] {
+                b'+' => (true, &key[1..]),
+                b'-' => (false, &key[1..]),
+                _ => (true, key),
+            };
+            if SORT_CAPABILITIES.split(',').any(|p| p == property) {
+                Ok((property.to_string(), ascending))
+            } else {
+                Err(BAD_SORT_CRITERIA)
+            }
+        })
+        .collect()
+}
+
+// ============================================================================
+// Search Criteria
+// ============================================================================
+
+/// Parsed UPnP ContentDirectory search criteria
+#[derive(Debug, Clone, PartialEq)]
+enum SearchExpr {
+    All,
+    And(Box<SearchExpr>, Box<SearchExpr>),
+    Or(Box<SearchExpr>, Box<SearchExpr>),
+    Compare {
+        property: String,
+        op: String,
+        value: String,
+    },
+    Exists {
+        property: String,
+        exists: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SearchToken {
+    Open,
+    Close,
+    Word(String),
+    Quoted(String),
+}
+
+impl SearchExpr {
+    fn parse(criteria: &str) -> Option<Self> {
+        let criteria = criteria.trim();
+        if criteria == "*" || criteria.is_empty() {
+            return Some(Self::All);
+        }
+        let tokens = Self::tokenize(criteria)?;
+        let mut pos = 0;
+        let expr = Self::parse_or(&tokens, &mut pos)?;
+        (pos == tokens.len()).then_some(expr)
+    }
+
+    fn tokenize(text: &str) -> Option<Vec<SearchToken>> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '(' => {
+                    chars.next();
+                    tokens.push(SearchToken::Open);
+                }
+                ')' => {
+                    chars.next();
+                    tokens.push(SearchToken::Close);
+                }
+                '"' => {
+                    chars.next();
+                    let mut value = String::new();
+                    loop {
+                        match chars.next()? {
+                            '\\' => value.push(chars.next()?),
+                            '"' => break,
+                            c => value.push(c),
+                        }
+                    }
+                    tokens.push(SearchToken::Quoted(value));
+                }
+                '=' | '!' | '<' | '>' => {
+                    let mut op = String::new();
+                    while let Some(&c) = chars.peek().filter(|c| "=!<>".contains(**c)) {
+                        op.push(c);
+                        chars.next();
+                    }
+                    tokens.push(SearchToken::Word(op));
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&c) = chars
+                        .peek()
+                        .filter(|c| !c.is_whitespace() && !"()\"=!<>".contains(**c))
+                    {
+                        word.push(c);
+                        chars.next();
+                    }
+                    tokens.push(SearchToken::Word(word));
+                }
+            }
+        }
+        Some(tokens)
+    }
+
+    fn keyword(tokens: &[SearchToken], pos: usize, keyword: &str) -> bool {
+        matches!(tokens.get(pos), Some(SearchToken::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(tokens: &[SearchToken], pos: &mut usize) -> Option<Self> {
+        let mut left = Self::parse_and(tokens, pos)?;
+        while Self::keyword(tokens, *pos, "or") {
+            *pos += 1;
+            left = Self::Or(Box::new(left), Box::new(Self::parse_and(tokens, pos)?));
+        }
+        Some(left)
+    }
+
+    fn parse_and(tokens: &[SearchToken], pos: &mut usize) -> Option<Self> {
+        let mut left = Self::parse_factor(tokens, pos)?;
+        while Self::keyword(tokens, *pos, "and") {
+            *pos += 1;
+            left = Self::And(Box::new(left), Box::new(Self::parse_factor(tokens, pos)?));
+        }
+        Some(left)
+    }
+
+    fn parse_factor(tokens: &[SearchToken], pos: &mut usize) -> Option<Self> {
+        if tokens.get(*pos) == Some(&SearchToken::Open) {
+            *pos += 1;
+            let expr = Self::parse_or(tokens, pos)?;
+            if tokens.get(*pos) != Some(&SearchToken::Close) {
+                return None;
+            }
+            *pos += 1;
+            return Some(expr);
+        }
+        let (Some(SearchToken::Word(property)), Some(SearchToken::Word(op)), Some(value)) =
+            (tokens.get(*pos), tokens.get(*pos + 1), tokens.get(*pos + 2))
+        else {
+            return None;
+        };
+        *pos += 3;
+        let op = op.to_ascii_lowercase();
+        match (op.as_str(), value) {
+            ("exists", SearchToken::Word(b)) => Some(Self::Exists {
+                property: property.clone(),
+                exists: b.eq_ignore_ascii_case("true"),
+            }),
+            (
+                "=" | "!=" | "<" | "<=" | ">" | ">=" | "contains" | "doesnotcontain"
+                | "derivedfrom" | "startswith",
+                SearchToken::Quoted(value),
+            ) => Some(Self::Compare {
+                property: property.clone(),
+                op,
+                value: value.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, object: &CdsObject) -> bool {
+        match self {
+            Self::All => true,
+            Self::And(a, b) => a.matches(object) && b.matches(object),
+            Self::Or(a, b) => a.matches(object) || b.matches(object),
+            Self::Exists { property, exists } => object.property(property).is_empty() != *exists,
+            Self::Compare {
+                property,
+                op,
+                value,
+            } => {
+                let values = object.property(property);
+                let value = value.to_lowercase();
+                let negated = matches!(op.as_str(), "!=" | "doesnotcontain");
+                if values.is_empty() {
+                    return negated;
+                }
+                let test = |v: &str| {
+                    let v = v.to_lowercase();
+                    match op.as_str() {
+                        "=" | "!=" => v == value,
+                        "<" => v < value,
+                        "<=" => v <= value,
+                        ">" => v > value,
+                        ">=" => v >= value,
+                        "contains" | "doesnotcontain" => v.contains(&value),
+                        "startswith" => v.starts_with(&value),
+                        // Class hierarchy: "object.item" covers "object.item.videoItem"
+                        _ => v == value || v.starts_with(&format!("{}.", value)),
+                    }
+                };
+                if negated {
+                    !values.iter().any(|v| test(v))
+                } else {
+                    values.iter().any(|v| test(v))
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Descriptions & SOAP
+// ============================================================================
+
+/// Root device description; `services` are (service type, short name) and
+/// are served under `/<name>.xml`, `/control/<name>` and `/event/<name>`
+pub(crate) fn device_description(
+    device_type: &str,
+    friendly_name: &str,
+    model_name: &str,
+    dlna_doc: &str,
+    uuid: &str,
+    services: &[(&str, &str)],
+) -> String {
+    let mut service_list = String::new();
+    for (kind, name) in services {
+        let _ = write!(
+            service_list,
+            "<service><serviceType>{}</serviceType>\
+             <serviceId>urn:upnp-org:serviceId:{}</serviceId>\
+             <SCPDURL>/{}.xml</SCPDURL>\
+             <controlURL>/control/{}</controlURL>\
+             <eventSubURL>/event/{}</eventSubURL></service>",
+            kind, name, name, name, name
+        );
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:dlna="urn:schemas-dlna-org:device-1-0">
+<specVersion><major>1</major><minor>0</minor></specVersion>
+<device>
+<deviceType>{}</deviceType>
+<friendlyName>{}</friendlyName>
+<manufacturer>SLAIN</manufacturer>
+<modelName>{}</modelName>
+<modelNumber>{}</modelNumber>
+<UDN>uuid:{}</UDN>
+<dlna:X_DLNADOC>{}</dlna:X_DLNADOC>
+<serviceList>{}</serviceList>
+</device>
+</root>"#,
+        device_type,
+        xml_escape(friendly_name),
+        model_name,
+        crate::VERSION,
+        uuid,
+        dlna_doc,
+        service_list,
+    )
+}
+
+/// (argument, is_output, related state variable)
+pub(crate) type ScpdArgument<'a> = (&'a str, bool, &'a str);
+
+/// (action, arguments)
+pub(crate) type ScpdAction<'a> = (&'a str, &'a [ScpdArgument<'a>]);
+
+/// (state variable, data type, allowed values)
+pub(crate) type ScpdVariable<'a> = (&'a str, &'a str, &'a [&'a str]);
+
+pub(crate) fn scpd(actions: &[ScpdAction], variables: &[ScpdVariable]) -> String {
+    let mut out = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?><scpd xmlns="urn:schemas-upnp-org:service-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><actionList>"#,
+    );
+    for (name, arguments) in actions {
+        let _ = write!(out, "<action><name>{}</name><argumentList>", name);
+        for (argument, output, variable) in arguments.iter() {
+            let _ = write!(
+                out,
+                "<argument><name>{}</name><direction>{}</direction><relatedStateVariable>{}</relatedStateVariable></argument>",
+                argument,
+                if *output { "out" } else { "in" },
+                variable
+            );
+        }
+        out.push_str("</argumentList></action>");
+    }
+    out.push_str("</actionList><serviceStateTable>");
+    for (name, data_type, allowed) in variables {
+        let evented = matches!(
+            *name,
+            "SystemUpdateID"
+                | "LastChange"
+                | "SourceProtocolInfo"
+                | "SinkProtocolInfo"
+                | "CurrentConnectionIDs"
+        );
+        let events = if evented { "yes" } else { "no" };
+        let _ = write!(
+            out,
+            r#"<stateVariable sendEvents="{}"><name>{}</name><dataType>{}</dataType>"#,
+            events, name, data_type
+        );
+        if !allowed.is_empty() {
+            out.push_str("<allowedValueList>");
+            for value in allowed.iter() {
+                let _ = write!(out, "<allowedValue>{}</allowedValue>", value);
+            }
+            out.push_str("</allowedValueList>");
+        }
+        out.push_str("</stateVariable>");
+    }
+    out.push_str("</serviceStateTable></scpd>");
+    out
+}
+
+fn content_directory_scpd() -> String {
+    let query: &[(&str, bool, &str)] = &[
+        ("Filter", false, "A_ARG_TYPE_Filter"),
+        ("StartingIndex", false, "A_ARG_TYPE_Index"),
+        ("RequestedCount", false, "A_ARG_TYPE_Count"),
+        ("SortCriteria", false, "A_ARG_TYPE_SortCriteria"),
+        ("Result", true, "A_ARG_TYPE_Result"),
+        ("NumberReturned", true, "A_ARG_TYPE_Count"),
+        ("TotalMatches", true, "A_ARG_TYPE_Count"),
+        ("UpdateID", true, "A_ARG_TYPE_UpdateID"),
+    ];
+    let browse: Vec<_> = [
+        ("ObjectID", false, "A_ARG_TYPE_ObjectID"),
+        ("BrowseFlag", false, "A_ARG_TYPE_BrowseFlag"),
+    ]
+    .into_iter()
+    .chain(query.iter().copied())
+    .collect();
+    let search: Vec<_> = [
+        ("ContainerID", false, "A_ARG_TYPE_ObjectID"),
+        ("SearchCriteria", false, "A_ARG_TYPE_SearchCriteria"),
+    ]
+    .into_iter()
+    .chain(query.iter().copied())
+    .collect();
+    scpd(
+        &[
+            ("Browse", &browse),
+            ("Search", &search),
+            (
+                "GetSearchCapabilities",
+                &[("SearchCaps", true, "SearchCapabilities")],
+            ),
+            (
+                "GetSortCapabilities",
+                &[("SortCaps", true, "SortCapabilities")],
+            ),
+            ("GetSystemUpdateID", &[("Id", true, "SystemUpdateID")]),
+        ],
+        &[
+            ("A_ARG_TYPE_ObjectID", "string", &[]),
+            (
+                "A_ARG_TYPE_BrowseFlag",
+                "string",
+                &["BrowseMetadata", "BrowseDirectChildren"],
+            ),
+            ("A_ARG_TYPE_Filter", "string", &[]),
+            ("A_ARG_TYPE_SearchCriteria", "string", &[]),
+            ("A_ARG_TYPE_SortCriteria", "string", &[]),
+            ("A_ARG_TYPE_Index", "ui4", &[]),
+            ("A_ARG_TYPE_Count", "ui4", &[]),
+            ("A_ARG_TYPE_Result", "string", &[]),
+            ("A_ARG_TYPE_UpdateID", "ui4", &[]),
+            ("SearchCapabilities", "string", &[]),
+            ("SortCapabilities", "string", &[]),
+            ("SystemUpdateID", "ui4", &[]),
+        ],
+    )
+}
+
+pub(crate) fn connection_manager_scpd() -> String {
+    scpd(
+        &[
+            (
+                "GetProtocolInfo",
+                &[
+                    ("Source", true, "SourceProtocolInfo"),
+                    ("Sink", true, "SinkProtocolInfo"),
+                ],
+            ),
+            (
+                "GetCurrentConnectionIDs",
+                &[("ConnectionIDs", true, "CurrentConnectionIDs")],
+            ),
+            (
+                "GetCurrentConnectionInfo",
+                &[
+                    ("ConnectionID", false, "A_ARG_TYPE_ConnectionID"),
+                    ("RcsID", true, "A_ARG_TYPE_RcsID"),
+                    ("AVTransportID", true, "A_ARG_TYPE_AVTransportID"),
+                    ("ProtocolInfo", true, "A_ARG_TYPE_ProtocolInfo"),
+                    (
+                        "PeerConnectionManager",
+                        true,
+                        "A_ARG_TYPE_ConnectionManager",
+                    ),
+                    ("PeerConnectionID", true, "A_ARG_TYPE_ConnectionID"),
+                    ("Direction", true, "A_ARG_TYPE_Direction"),
+                    ("Status", true, "A_ARG_TYPE_ConnectionStatus"),
+                ],
+            ),
+        ],
+        &[
+            ("SourceProtocolInfo", "string", &[]),
+            ("SinkProtocolInfo", "string", &[]),
+            ("CurrentConnectionIDs", "string", &[]),
+            (
+                "A_ARG_TYPE_ConnectionStatus",
+                "string",
+                &[
+                    "OK",
+                    "ContentFormatMismatch",
+                    "InsufficientBandwidth",
+                    "UnreliableChannel",
+                    "Unknown",
+                ],
+            ),
+            ("A_ARG_TYPE_ConnectionManager", "string", &[]),
+            ("A_ARG_TYPE_Direction", "string", &["Input", "Output"]),
+            ("A_ARG_TYPE_ProtocolInfo", "string", &[]),
+            ("A_ARG_TYPE_ConnectionID", "i4", &[]),
+            ("A_ARG_TYPE_AVTransportID", "i4", &[]),
+            ("A_ARG_TYPE_RcsID", "i4", &[]),
+        ],
+    )
+}
+
+/// Action name and arguments of a SOAP request body
+pub(crate) fn parse_soap_request(body: &str) -> Result<(String, HashMap<String, String>), String> {
+    let envelope = parse_xml(body)?;
+    let action = envelope
+        .child("Body")
+        .and_then(|b| b.children.first())
+        .ok_or("SOAP request without an action")?;
+    let arguments = action
+        .children
+        .iter()
+        .map(|arg: &XmlNode| (arg.name.clone(), arg.text.clone()))
+        .collect();
+    Ok((action.name.clone(), arguments))
+}
+
+pub(crate) fn soap_envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>{}</s:Body></s:Envelope>"#,
+        body
+    )
+}
+
+pub(crate) fn soap_response(service: &str, action: &str, arguments: &[(&str, String)]) -> String {
+    let mut body = format!(r#"<u:{}Response xmlns:u="{}">"#, action, service);
+    for (name, value) in arguments {
+        let _ = write!(body, "<{}>{}</{}>", name, xml_escape(value), name);
+    }
+    let _ = write!(body, "</u:{}Response>", action);
+    soap_envelope(&body)
+}
+
+pub(crate) fn soap_fault(error: UpnpError) -> String {
+    soap_envelope(&format!(
+        r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault>"#,
+        error.code, error.description
+    ))
+}
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+/// One request per connection; header names are lowercased
+pub(crate) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn read(stream: &mut TcpStream) -> Result<Self, String> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        let header_end = loop {
+            if let Some(i) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i;
+            }
+            if data.len() > 64 * 1024 {
+                return Err("Request header too large".to_string());
+            }
+            let n = stream.read(&mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err("Connection closed".to_string());
+            }
+            data.extend_from_slice(&buf[..n]);
+        };
+        let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("").to_ascii_uppercase();
+        let path = request_line.next().unwrap_or("/").to_string();
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+
+        let length: usize = headers
+            .get("content-length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0);
+        if length > 1024 * 1024 {
+            return Err("Request body too large".to_string());
+        }
+        let mut body = data[header_end + 4..].to_vec();
+        while body.len() < length {
+            let n = stream.read(&mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        body.truncate(length);
+        Ok(Self {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+}
+
+pub(crate) fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    extra_headers: &[(&str, String)],
+    body: &[u8],
+    head_only: bool,
+) {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nServer: {}\r\n",
+        status,
+        content_type,
+        body.len(),
+        upnp_server_header()
+    );
+    for (name, value) in extra_headers {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    if stream.write_all(head.as_bytes()).is_ok() && !head_only {
+        stream.write_all(body).ok();
+    }
+}
+
+/// `bytes=a-b`, `bytes=a-`, `bytes=-n` against `size`; None when unsatisfiable
+fn parse_range(header: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = header
+        .trim()
+        .strip_prefix("bytes=")?
+        .split(',')
+        .next()?
+        .trim();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            (size.saturating_sub(n), size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+        ),
+    };
+    (start <= end && start < size).then_some((start, end))
+}
+
+/// Shared by the HTTP and SSDP threads
+struct ServerState {
+    directory: RwLock<ContentDirectory>,
+    friendly_name: String,
+    uuid: String,
+}
+
+fn handle_connection(mut stream: TcpStream, state: &ServerState) {
+    stream.set_read_timeout(Some(Duration::from_secs(10))).ok();
+    let request = match HttpRequest::read(&mut stream) {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::debug!("DLNA: bad request: {}", e);
+            return;
+        }
+    };
+    let head_only = request.method == "HEAD";
+    let path = request.path.split('?').next().unwrap_or("/");
+    let xml = "text/xml; charset=\"utf-8\"";
+
+    match (request.method.as_str(), path) {
+        ("GET" | "HEAD", "/description.xml") => {
+            let body = device_description(
+                DEVICE_TYPE,
+                &state.friendly_name,
+                "SLAIN Media Server",
+                "DMS-1.50",
+                &state.uuid,
+                &[
+                    (CONTENT_DIRECTORY, "ContentDirectory"),
+                    (CONNECTION_MANAGER, "ConnectionManager"),
+                ],
+            );
+            write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), head_only);
+        }
+        ("GET" | "HEAD", "/ContentDirectory.xml") => {
+            let body = content_directory_scpd();
+            write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), head_only);
+        }
+        ("GET" | "HEAD", "/ConnectionManager.xml") => {
+            let body = connection_manager_scpd();
+            write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), head_only);
+        }
+        ("POST", "/control/ContentDirectory" | "/control/ConnectionManager") => {
+            let base_url = match request.headers.get("host") {
+                Some(host) => format!("http://{}", host),
+                None => match stream.local_addr() {
+                    Ok(addr) => format!("http://{}", addr),
+                    Err(_) => return,
+                },
+            };
+            let body = String::from_utf8_lossy(&request.body);
+            let result = parse_soap_request(&body)
+                .map_err(|_| INVALID_ACTION)
+                .and_then(|(action, args)| {
+                    if path.ends_with("ContentDirectory") {
+                        content_directory_action(state, &action, &args, &base_url)
+                    } else {
+                        connection_manager_action(state, &action, &args)
+                    }
+                });
+            match result {
+                Ok(body) => write_response(&mut stream, "200 OK", xml, &[], body.as_bytes(), false),
+                Err(error) => {
+                    let body = soap_fault(error);
+                    write_response(
+                        &mut stream,
+                        "500 Internal Server Error",
+                        xml,
+                        &[],
+                        body.as_bytes(),
+                        false,
+                    );
+                }
+            }
+        }
+        ("SUBSCRIBE", p) if p.starts_with("/event/") => {
+            let timeout = request
+                .headers
+                .get("timeout")
+                .cloned()
+                .unwrap_or_else(|| "Second-1800".to_string());
+            let sid = request
+                .headers
+                .get("sid")
+                .cloned()
+                .unwrap_or_else(|| format!("uuid:{}", uuid::Uuid::new_v4()));
+            write_response(
+                &mut stream,
+                "200 OK",
+                "text/plain",
+                &[("SID", sid), ("TIMEOUT", timeout)],
+                b"",
+                false,
+            );
+        }
+        ("UNSUBSCRIBE", p) if p.starts_with("/event/") => {
+            write_response(&mut stream, "200 OK", "text/plain", &[], b"", false);
+        }
+        ("GET" | "HEAD", p) if p.starts_with("/media/") => {
+            serve_media(&mut stream, state, &request, &p["/media/".len()..]);
+        }
+        _ => write_response(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            &[],
+            b"",
+            head_only,
+        ),
+    }
+}
+
+fn content_directory_action(
+    state: &ServerState,
+    action: &str,
+    args: &HashMap<String, String>,
+    base_url: &str,
+) -> Result<String, UpnpError> {
+    let directory = state.directory.read();
+    let arg = |name: &str| args.get(name).map(String::as_str).unwrap_or("");
+    let number = |name: &str| -> Result<usize, UpnpError> {
+        match arg(name).trim() {
+            "" => Ok(0),
+            n => n.parse().map_err(|_| INVALID_ARGS),
+        }
+    };
+    let reply = |action: &str, result: BrowseResult| {
+        soap_response(
+            CONTENT_DIRECTORY,
+            action,
+            &[
+                ("Result", result.didl),
+                ("NumberReturned", result.number_returned.to_string()),
+                ("TotalMatches", result.total_matches.to_string()),
+                ("UpdateID", directory.update_id.to_string()),
+            ],
+        )
+    };
+    match action {
+        "Browse" => {
+            let metadata_only = match arg("BrowseFlag") {
+                "BrowseMetadata" => true,
+                "BrowseDirectChildren" => false,
+                _ => return Err(INVALID_ARGS),
+            };
+            let result = directory.browse(
+                arg("ObjectID"),
+                metadata_only,
+                arg("Filter"),
+                number("StartingIndex")?,
+                number("RequestedCount")?,
+                arg("SortCriteria"),
+                base_url,
+            )?;
+            Ok(reply("Browse", result))
+        }
+        "Search" => {
+            let result = directory.search(
+                arg("ContainerID"),
+                arg("SearchCriteria"),
+                arg("Filter"),
+                number("StartingIndex")?,
+                number("RequestedCount")?,
+                arg("SortCriteria"),
+                base_url,
+            )?;
+            Ok(reply("Search", result))
+        }
+        "GetSearchCapabilities" => Ok(soap_response(
+            CONTENT_DIRECTORY,
+            action,
+            &[("SearchCaps", SEARCH_CAPABILITIES.to_string())],
+        )),
+        "GetSortCapabilities" => Ok(soap_response(
+            CONTENT_DIRECTORY,
+            action,
+            &[("SortCaps", SORT_CAPABILITIES.to_string())],
+        )),
+        "GetSystemUpdateID" => Ok(soap_response(
+            CONTENT_DIRECTORY,
+            action,
+            &[("Id", directory.update_id.to_string())],
+        )),
+        _ => Err(INVALID_ACTION),
+    }
+}
+
+fn connection_manager_action(
+    state: &ServerState,
+    action: &str,
+    args: &HashMap<String, String>,
+) -> Result<String, UpnpError> {
+    match action {
+        "GetProtocolInfo" => Ok(soap_response(
+            CONNECTION_MANAGER,
+            action,
+            &[
+                ("Source", state.directory.read().source_protocol_info()),
+                ("Sink", String::new()),
+            ],
+        )),
+        "GetCurrentConnectionIDs" => Ok(soap_response(
+            CONNECTION_MANAGER,
+            action,
+            &[("ConnectionIDs", "0".to_string())],
+        )),
+        "GetCurrentConnectionInfo" => {
+            if args.get("ConnectionID").map(|id| id.trim()) != Some("0") {
+                return Err(INVALID_ARGS);
+            }
+            Ok(soap_response(
+                CONNECTION_MANAGER,
+                action,
+                &[
+                    ("RcsID", "-1".to_string()),
+                    ("AVTransportID", "-1".to_string()),
+                    ("ProtocolInfo", String::new()),
+                    ("PeerConnectionManager", String::new()),
+                    ("PeerConnectionID", "-1".to_string()),
+                    ("Direction", "Output".to_string()),
+                    ("Status", "OK".to_string()),
+                ],
+            ))
+        }
+        _ => Err(INVALID_ACTION),
+    }
+}
+
+fn serve_media(stream: &mut TcpStream, state: &ServerState, request: &HttpRequest, name: &str) {
+    let head_only = request.method == "HEAD";
+    let id = name.rsplit_once('.').map(|(id, _)| id).unwrap_or(name);
+    let id = urlencoding::decode(id)
+        .map(|s| s.into_owned())
+        .unwrap_or_default();
+    let Some((path, mime)) = state
+        .directory
+        .read()
+        .file(&id)
+        .map(|(p, m)| (p.to_path_buf(), m))
+    else {
+        write_response(stream, "404 Not Found", "text/plain", &[], b"", head_only);
+        return;
+    };
+    let Ok(mut file) = File::open(&path) else {
+        write_response(stream, "404 Not Found", "text/plain", &[], b"", head_only);
+        return;
+    };
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let transfer_mode = if mime.starts_with("image/") {
+        "Interactive"
+    } else {
+        "Streaming"
+    };
+    let mut headers = vec![
+        ("Accept-Ranges", "bytes".to_string()),
+        ("transferMode.dlna.org", transfer_mode.to_string()),
+        ("contentFeatures.dlna.org", dlna_features(mime)),
+    ];
+
+    let (status, start, end) = match request.headers.get("range") {
+        Some(range) => match parse_range(range, size) {
+            Some((start, end)) => {
+                headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, size)));
+                ("206 Partial Content", start, end)
+            }
+            None => {
+                headers.push(("Content-Range", format!("bytes */{}", size)));
+                let mut head = String::from("HTTP/1.1 416 Requested Range Not Satisfiable\r\n");
+                for (name, value) in &headers {
+                    let _ = write!(head, "{}: {}\r\n", name, value);
+                }
+                head.push_str("Content-Length: 0\r\nConnection: close\r\n\r\n");
+                stream.write_all(head.as_bytes()).ok();
+                return;
+            }
+        },
+        None => ("200 OK", 0, size.saturating_sub(1)),
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nServer: {}\r\n",
+        status,
+        mime,
+        length,
+        upnp_server_header()
+    );
+    for (name, value) in &headers {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    if stream.write_all(head.as_bytes()).is_err() || head_only {
+        return;
+    }
+    if file.seek(SeekFrom::Start(start)).is_err() {
+        return;
+    }
+    let mut remaining = length;
+    let mut buf = vec![0u8; 256 * 1024];
+    while remaining > 0 {
+        let want = remaining.min(buf.len() as u64) as usize;
+        match file.read(&mut buf[..want]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if stream.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+                remaining -= n as u64;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+/// A running MediaServer; stops (and says byebye) when dropped
+pub struct DlnaServer {
+    state: Arc<ServerState>,
+    port: u16,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DlnaServer {
+    /// Serve `items` and, if configured, announce the server over SSDP
+    pub fn start(config: DlnaServerConfig, items: &[MediaItem]) -> Result<Self, String> {
+        let uuid = config
+            .uuid
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let listener = TcpListener::bind(("0.0.0.0", config.port))
+            .map_err(|e| format!("Failed to bind DLNA server: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to bind DLNA server: {}", e))?
+            .port();
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to bind DLNA server: {}", e))?;
+
+        let state = Arc::new(ServerState {
+            directory: RwLock::new(ContentDirectory::new(items, 1)),
+            friendly_name: config.friendly_name.clone(),
+            uuid: uuid.clone(),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut threads = Vec::new();
+
+        {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            threads.push(thread::spawn(move || {
+                while !shutdown.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            // Accepted sockets inherit non-blocking mode on some platforms
+                            stream.set_nonblocking(false).ok();
+                            let state = state.clone();
+                            thread::spawn(move || handle_connection(stream, &state));
+                        }
+                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(50));
+                        }
+                        Err(e) => {
+                            tracing::warn!("DLNA server accept failed: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }));
+        }
+
+        if config.advertise {
+            let advertised = local_ip_address::local_ip()
+                .map_err(|e| format!("No local IP: {}", e))
+                .and_then(|ip| {
+                    SsdpAdvertisement {
+                        uuid: uuid.clone(),
+                        device_type: DEVICE_TYPE,
+                        services: vec![CONTENT_DIRECTORY, CONNECTION_MANAGER],
+                        location: format!("http://{}:{}/description.xml", ip, port),
+                        max_age: config.max_age_secs.max(60),
+                    }
+                    .spawn(shutdown.clone())
+                });
+            match advertised {
+                Ok(thread) => threads.push(thread),
+                Err(e) => tracing::warn!("DLNA server not advertised: {}", e),
+            }
+        }
+
+        tracing::info!(
+            "DLNA server \"{}\" on port {} ({} objects)",
+            config.friendly_name,
+            port,
+            state.directory.read().len()
+        );
+        Ok(Self {
+            state,
+            port,
+            shutdown,
+            threads,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.state.uuid
+    }
+
+    /// Replace the served items; renderers see a new SystemUpdateID
+    pub fn set_items(&self, items: &[MediaItem]) {
+        let mut directory = self.state.directory.write();
+        let update_id = directory.update_id.wrapping_add(1);
+        *directory = ContentDirectory::new(items, update_id);
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown_threads();
+    }
+
+    fn shutdown_threads(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DlnaServer {
+    fn drop(&mut self) {
+        self.shutdown_threads();
+    }
+}
+
+// ============================================================================
+// Public Rust API
+// ============================================================================
+
+static DLNA_SERVER: Lazy<Mutex<Option<DlnaServer>>> = Lazy::new(|| Mutex::new(None));
+
+/// Start (or restart) the MediaServer with `items`; returns the description URL
+pub async fn start_dlna_server(
+    friendly_name: String,
+    items: Vec<MediaItem>,
+) -> Result<String, String> {
+    if let Some(server) = DLNA_SERVER.lock().take() {
+        server.stop();
+    }
+    let config = DlnaServerConfig {
+        friendly_name,
+        ..Default::default()
+    };
+    let server = DlnaServer::start(config, &items)?;
+    let local_ip =
+        local_ip_address::local_ip().map_err(|e| format!("Failed to get local IP: {}", e))?;
+    let url = format!("http://{}:{}/description.xml", local_ip, server.port());
+    *DLNA_SERVER.lock() = Some(server);
+    Ok(url)
+}
+
+/// Update what the running server exposes
+pub async fn update_dlna_library(items: Vec<MediaItem>) -> Result<(), String> {
+    DLNA_SERVER
+        .lock()
+        .as_ref()
+        .ok_or("DLNA server not running")?
+        .set_items(&items);
+    Ok(())
+}
+
+pub async fn stop_dlna_server() -> Result<(), String> {
+    let server = DLNA_SERVER.lock().take().ok_or("DLNA server not running")?;
+    server.stop();
+    Ok(())
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media_library::{MediaMetadata, MediaSource};
+
+    fn item(id: &str, title: &str, media_type: MediaType, path: Option<&Path>) -> MediaItem {
+        MediaItem {
+            id: id.to_string(),
+            title: title.to_string(),
+            year: None,
+            media_type,
+            source: MediaSource::Local,
+            path: path.map(|p| p.to_string_lossy().into_owned()),
+            stream_url: None,
+            metadata: None,
+        }
+    }
+
+    fn library(dir: &Path) -> Vec<MediaItem> {
+        let file = |name: &str, len: usize| {
+            let path = dir.join(name);
+            std::fs::write(&path, (0..len).map(|i| i as u8).collect::<Vec<u8>>()).unwrap();
+            path
+        };
+        let mut star = item(
+            "m1",
+            "Star Voyage",
+            MediaType::Movie,
+            Some(&file("star.mkv", 1000)),
+        );
+        star.year = Some(1999);
+        star.metadata = Some(MediaMetadata {
+            tmdb_id: None,
+            imdb_id: None,
+            overview: Some("Ships & <stars>".to_string()),
+            poster_url: Some("http://img/poster.jpg".to_string()),
+            backdrop_url: None,
+            rating: None,
+            runtime_minutes: Some(125),
+            genres: vec!["Science Fiction".to_string()],
+            cast: Vec::new(),
+            director: Some("A. Director".to_string()),
+            release_date: Some("1999-05-01".to_string()),
+            trailer_url: None,
+        });
+        let mut remote = item("m2", "Another Film", MediaType::Movie, None);
+        remote.stream_url = Some("http://cdn.example/film.mp4?token=1".to_string());
+        vec![
+            star,
+            remote,
+            item(
+                "m3",
+                "Missing",
+                MediaType::Movie,
+                Some(&dir.join("gone.mp4")),
+            ),
+            item(
+                "e1",
+                "Starship S01E01",
+                MediaType::Episode,
+                Some(&file("ep.mp4", 500)),
+            ),
+            item("a1", "Song", MediaType::Music, Some(&file("song.mp3", 100))),
+        ]
+    }
+
+    #[test]
+    fn test_browse_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = ContentDirectory::new(&library(dir.path()), 7);
+        let base = "http://10.0.0.2:8200";
+
+        let root = directory.browse("0", false, "*", 0, 0, "", base).unwrap();
+        assert_eq!((root.number_returned, root.total_matches), (3, 3));
+        assert!(root.didl.contains(
+            r#"<container id="movies" parentID="0" restricted="1" searchable="1" childCount="2">"#
+        ));
+        assert!(root.didl.find("id=\"tv\"").unwrap() < root.didl.find("id=\"music\"").unwrap());
+
+        let movie = directory.browse("m1", true, "*", 0, 0, "", base).unwrap();
+        let didl = &movie.didl;
+        assert!(didl.contains("<dc:title>Star Voyage (1999)</dc:title>"));
+        assert!(didl.contains("<upnp:class>object.item.videoItem.movie</upnp:class>"));
+        assert!(didl.contains("<dc:date>1999-05-01</dc:date>"));
+        assert!(didl.contains("<dc:description>Ships &amp; &lt;stars&gt;</dc:description>"));
+        assert!(didl.contains(&format!(
+            r#"<res protocolInfo="http-get:*:video/x-matroska:DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS={}" size="1000" duration="2:05:00.000">http://10.0.0.2:8200/media/m1.mkv</res>"#,
+            DLNA_FLAGS_STREAMING
+        )));
+        // A filter leaves out optional properties
+        let filtered = directory
+            .browse("m1", true, "dc:title,res", 0, 0, "", base)
+            .unwrap();
+        assert!(!filtered.didl.contains("dc:date") && !filtered.didl.contains("size="));
+        assert!(filtered.didl.contains("/media/m1.mkv</res>"));
+
+        // Paging and sorting; the remote item keeps its own URL
+        let page = directory
+            .browse("movies", false, "*", 1, 1, "-dc:title", base)
+            .unwrap();
+        assert_eq!((page.number_returned, page.total_matches), (1, 2));
+        assert!(page.didl.contains("Another Film"));
+        assert!(page
+            .didl
+            .contains(">http://cdn.example/film.mp4?token=1</res>"));
+        assert!(page.didl.contains("http-get:*:video/mp4:*"));
+
+        let search = |criteria: &str| {
+            directory
+                .search("0", criteria, "*", 0, 0, "+dc:title", base)
+                .map(|r| r.total_matches)
+        };
+        assert_eq!(search("*"), Ok(7));
+        assert_eq!(
+            search(
+                r#"upnp:class derivedfrom "object.item.videoItem" and dc:title contains "STAR""#
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            search(
+                r#"(upnp:class = "object.item.audioItem.musicTrack" or upnp:genre = "science fiction") and @refID exists false"#
+            ),
+            Ok(2)
+        );
+        assert_eq!(search(r#"dc:date>="1999-01-01""#), Ok(1));
+        assert_eq!(
+            search(r#"dc:title doesNotContain "star" and upnp:class derivedfrom "object.item""#),
+            Ok(2)
+        );
+        assert_eq!(search(r#"dc:title contains"#), Err(BAD_SEARCH_CRITERIA));
+        assert_eq!(search(r#"(dc:title = "x""#), Err(BAD_SEARCH_CRITERIA));
+
+        assert_eq!(
+            directory
+                .browse("nope", false, "*", 0, 0, "", base)
+                .unwrap_err(),
+            NO_SUCH_OBJECT
+        );
+        assert_eq!(
+            directory
+                .search("m1", "*", "*", 0, 0, "", base)
+                .unwrap_err(),
+            NO_SUCH_CONTAINER
+        );
+        assert_eq!(
+            directory
+                .browse("0", false, "*", 0, 0, "+upnp:rating", base)
+                .unwrap_err(),
+            BAD_SORT_CRITERIA
+        );
+    }
+
+    #[test]
+    fn test_ssdp_messages() {
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n";
+        let advertisement = SsdpAdvertisement {
+            uuid: "abc".to_string(),
+            device_type: DEVICE_TYPE,
+            services: vec![CONTENT_DIRECTORY, CONNECTION_MANAGER],
+            location: "http://10.0.0.2:8200/description.xml".to_string(),
+            max_age: 1800,
+        };
+        let responses = |st: &str| advertisement.search_responses(&search.replace("{}", st));
+        assert_eq!(responses("ssdp:all").len(), 5);
+        let media_server = responses(DEVICE_TYPE);
+        assert_eq!(media_server.len(), 1);
+        assert!(media_server[0].starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(media_server[0].contains(&format!("USN: uuid:abc::{}\r\n", DEVICE_TYPE)));
+        assert!(media_server[0].contains("LOCATION: http://10.0.0.2:8200/description.xml\r\n"));
+        assert!(responses("uuid:abc")[0].contains("USN: uuid:abc\r\n"));
+        assert!(responses("urn:schemas-upnp-org:device:MediaRenderer:1").is_empty());
+        assert!(advertisement
+            .search_responses("NOTIFY * HTTP/1.1\r\n\r\n")
+            .is_empty());
+
+        let bye = advertisement.notify("upnp:rootdevice", "uuid:abc::upnp:rootdevice", false);
+        assert!(bye.contains("NTS: ssdp:byebye\r\n") && !bye.contains("LOCATION"));
+
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=90-", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=50-500", 100), Some((50, 99)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+    }
+
+    fn soap(action: &str, args: &[(&str, &str)]) -> String {
+        let mut body = format!(r#"<u:{} xmlns:u="{}">"#, action, CONTENT_DIRECTORY);
+        for (name, value) in args {
+            body.push_str(&format!("<{}>{}</{}>", name, xml_escape(value), name));
+        }
+        body.push_str(&format!("</u:{}>", action));
+        soap_envelope(&body)
+    }
+
+    #[test]
+    fn test_http_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DlnaServerConfig {
+            friendly_name: "Test & Co".to_string(),
+            advertise: false,
+            ..Default::default()
+        };
+        let server = DlnaServer::start(config, &library(dir.path())).unwrap();
+        let base = format!("http://127.0.0.1:{}", server.port());
+
+        let description = ureq::get(&format!("{}/description.xml", base))
+            .call()
+            .unwrap()
+            .into_string()
+            .unwrap();
+        let root = parse_xml(&description).unwrap();
+        let device = root.child("device").unwrap();
+        assert_eq!(device.child("friendlyName").unwrap().text, "Test & Co");
+        assert_eq!(
+            device.child("UDN").unwrap().text,
+            format!("uuid:{}", server.uuid())
+        );
+        let scpd = ureq::get(&format!("{}/ContentDirectory.xml", base))
+            .call()
+            .unwrap()
+            .into_string()
+            .unwrap();
+        assert!(
+            parse_xml(&scpd)
+                .unwrap()
+                .child("actionList")
+                .unwrap()
+                .children
+                .len()
+                == 5
+        );
+
+        let control = format!("{}/control/ContentDirectory", base);
+        let post = |body: String| match ureq::post(&control)
+            .set("Content-Type", "text/xml; charset=\"utf-8\"")
+            .set(
+                "SOAPACTION",
+                "\"urn:schemas-upnp-org:service:ContentDirectory:1#Browse\"",
+            )
+            .send_string(&body)
+        {
+            Ok(response) => (200, response.into_string().unwrap()),
+            Err(ureq::Error::Status(code, response)) => (code, response.into_string().unwrap()),
+            Err(e) => panic!("{}", e),
+        };
+        let (status, body) = post(soap(
+            "Browse",
+            &[
+                ("ObjectID", "tv"),
+                ("BrowseFlag", "BrowseDirectChildren"),
+                ("Filter", "*"),
+                ("StartingIndex", "0"),
+                ("RequestedCount", "10"),
+                ("SortCriteria", ""),
+            ],
+        ));
+        assert_eq!(status, 200);
+        let (action, values) = parse_soap_request(&body).unwrap();
+        assert_eq!(action, "BrowseResponse");
+        assert_eq!(values["NumberReturned"], "1");
+        assert_eq!(values["UpdateID"], "1");
+        let didl = parse_xml(&values["Result"]).unwrap();
+        let res = didl.child("item").unwrap().child("res").unwrap();
+        let media_url = res.text.clone();
+        assert_eq!(media_url, format!("{}/media/e1.mp4", base));
+        assert_eq!(res.attr("size"), Some("500"));
+
+        let (status, body) = post(soap(
+            "Browse",
+            &[("ObjectID", "zzz"), ("BrowseFlag", "BrowseMetadata")],
+        ));
+        assert_eq!(status, 500);
+        assert!(body.contains("<errorCode>701</errorCode>"));
+        let (status, body) = post(soap("DestroyObject", &[("ObjectID", "m1")]));
+        assert_eq!(status, 500);
+        assert!(body.contains("<errorCode>401</errorCode>"));
+
+        // Ranged delivery with DLNA headers
+        let response = ureq::get(&media_url)
+            .set("Range", "bytes=10-19")
+            .call()
+            .unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.header("Content-Range"), Some("bytes 10-19/500"));
+        assert_eq!(response.header("transferMode.dlna.org"), Some("Streaming"));
+        assert!(response
+            .header("contentFeatures.dlna.org")
+            .unwrap()
+            .contains("DLNA.ORG_OP=01"));
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, (10..20).collect::<Vec<u8>>());
+
+        let head = ureq::head(&media_url).call().unwrap();
+        assert_eq!(head.header("Content-Length"), Some("500"));
+        match ureq::get(&media_url).set("Range", "bytes=600-").call() {
+            Err(ureq::Error::Status(416, response)) => {
+                assert_eq!(response.header("Content-Range"), Some("bytes */500"));
+            }
+            other => panic!("expected 416, got {:?}", other.map(|r| r.status())),
+        }
+
+        // New library: bumped SystemUpdateID, old items gone
+        server.set_items(&library(dir.path())[..1]);
+        let (_, body) = post(soap("GetSystemUpdateID", &[]));
+        assert_eq!(parse_soap_request(&body).unwrap().1["Id"], "2");
+        assert!(matches!(
+            ureq::get(&media_url).call(),
+            Err(ureq::Error::Status(404, _))
+        ));
+        server.stop();
+    }
+}
@@ -0,0 +1,351 @@
+//! # Video Encode Module
+//!
+//! Unified interface for video encoders, the counterpart of `decode`.
+//! Encoders take NV12 frames and return Annex B access units with their
+//! presentation times, so callers can mux them without knowing the backend.
+//!
+//! ## Encoder Selection Priority:
+//! 1. AMF (AMD) - H.264 / H.265
+//! 2. OpenH264 (software) - H.264
+//!
+//! Encoders run without B-frames: packets come out in presentation order and
+//! their decode time equals their presentation time.
+
+use crate::amf_encoder::{
+    AmfCodec, AmfEncoder, AmfEncoderConfig, AmfProfile, AmfRateControl, AmfSurfaceFormat,
+};
+use crate::h264_utils;
+use crate::mux::MuxCodec;
+use crate::pixel_convert::{PixelFormat, VideoFrame};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncoderConfig {
+    pub codec: MuxCodec,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub bitrate_bps: u64,
+    /// Frames between keyframes (the first frame is always one)
+    pub gop_frames: u32,
+}
+
+/// One encoded access unit
+#[derive(Debug, Clone)]
+pub struct EncodedPacket {
+    /// Annex B, parameter sets in front of keyframes
+    pub data: Vec<u8>,
+    /// Presentation timestamp in microseconds
+    pub pts_us: i64,
+    pub keyframe: bool,
+}
+
+/// Encoder trait - implemented by all encoder backends
+pub trait Encoder: Send {
+    /// Get the codec this encoder produces
+    fn codec(&self) -> MuxCodec;
+
+    /// Encode an NV12 frame (`pts` in microseconds) of the configured size
+    fn encode(&mut self, frame: &VideoFrame) -> Result<Vec<EncodedPacket>, String>;
+
+    /// Drain frames still inside the encoder
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>, String>;
+
+    /// Get encoder name for debugging
+    fn name(&self) -> &str;
+}
+
+/// Create the best available encoder for `config`
+pub fn create_encoder(config: &EncoderConfig) -> Result<Box<dyn Encoder>, String> {
+    let mut tried = Vec::new();
+
+    if matches!(config.codec, MuxCodec::H264 | MuxCodec::H265) {
+        tried.push("AMF");
+        match AmfVideoEncoder::new(config) {
+            Ok(encoder) => return Ok(Box::new(encoder)),
+            Err(e) => tracing::debug!("AMF encoder unavailable: {}", e),
+        }
+    }
+
+    if config.codec == MuxCodec::H264 {
+        tried.push("OpenH264");
+        match OpenH264VideoEncoder::new(config) {
+            Ok(encoder) => return Ok(Box::new(encoder)),
+            Err(e) => tracing::debug!("OpenH264 encoder unavailable: {}", e),
+        }
+    }
+
+    Err(format!(
+        "No encoder available for {:?} (tried: {:?})",
+        config.codec, tried
+    ))
+}
+
+// ============================================================================
+// AMF Encoder
+// ============================================================================
+
+pub struct AmfVideoEncoder {
+    encoder: AmfEncoder,
+    codec: MuxCodec,
+    width: usize,
+    height: usize,
+    /// Presentation times of frames submitted but not yet returned
+    pending: VecDeque<i64>,
+}
+
+impl AmfVideoEncoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        let (codec, profile) = match config.codec {
+            MuxCodec::H264 => (AmfCodec::H264Avc, AmfProfile::H264High),
+            MuxCodec::H265 => (AmfCodec::H265Hevc, AmfProfile::H265Main),
+            other => return Err(format!("AMF cannot encode {:?}", other)),
+        };
+        // Rational frame rate with millisecond precision (23.976 -> 23976/1000)
+        let framerate_num = (config.frame_rate.max(1.0) * 1000.0).round() as u32;
+        let mut encoder = AmfEncoder::new(AmfEncoderConfig {
+            codec,
+            width: config.width,
+            height: config.height,
+            framerate_num,
+            framerate_den: 1000,
+            bitrate_bps: config.bitrate_bps,
+            max_bitrate_bps: config.bitrate_bps * 3 / 2,
+            gop_size: config.gop_frames.max(1),
+            b_frames: 0,
+            rate_control: AmfRateControl::Vbr,
+            profile,
+            color_format: AmfSurfaceFormat::Nv12,
+            low_latency: true,
+            ..Default::default()
+        })?;
+        encoder.initialize()?;
+
+        Ok(Self {
+            encoder,
+            codec: config.codec,
+            width: config.width as usize,
+            height: config.height as usize,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn packet(&mut self, data: Vec<u8>) -> Option<EncodedPacket> {
+        if data.is_empty() {
+            return None;
+        }
+        // No B-frames: output order is input order
+        let pts_us = self.pending.pop_front().unwrap_or_default();
+        let keyframe = h264_utils::annexb_has_idr(&data, self.codec == MuxCodec::H265);
+        Some(EncodedPacket {
+            data,
+            pts_us,
+            keyframe,
+        })
+    }
+}
+
+impl Encoder for AmfVideoEncoder {
+    fn codec(&self) -> MuxCodec {
+        self.codec
+    }
+
+    fn encode(&mut self, frame: &VideoFrame) -> Result<Vec<EncodedPacket>, String> {
+        if frame.format != PixelFormat::NV12
+            || frame.width != self.width
+            || frame.height != self.height
+        {
+            return Err(format!(
+                "AMF expects {}x{} NV12, got {}x{} {:?}",
+                self.width, self.height, frame.width, frame.height, frame.format
+            ));
+        }
+        self.pending.push_back(frame.pts);
+        let data = self.encoder.encode_frame(&frame.data, frame.pts)?;
+        Ok(self.packet(data).into_iter().collect())
+    }
+
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>, String> {
+        let outputs = self.encoder.flush()?;
+        Ok(outputs
+            .into_iter()
+            .filter_map(|data| self.packet(data))
+            .collect())
+    }
+
+    fn name(&self) -> &str {
+        "AMF"
+    }
+}
+
+// ============================================================================
+// OpenH264 Encoder (software)
+// ============================================================================
+
+pub struct OpenH264VideoEncoder {
+    encoder: openh264::encoder::Encoder,
+    width: usize,
+    height: usize,
+    gop_frames: u64,
+    frames: u64,
+    /// I420 copy of the current frame (OpenH264 doesn't take NV12)
+    i420: Vec<u8>,
+}
+
+impl OpenH264VideoEncoder {
+    pub fn new(config: &EncoderConfig) -> Result<Self, String> {
+        use openh264::encoder::{EncoderConfig as OpenH264Config, RateControlMode};
+        use openh264::OpenH264API;
+
+        if config.codec != MuxCodec::H264 {
+            return Err(format!("OpenH264 cannot encode {:?}", config.codec));
+        }
+        if !config.width.is_multiple_of(2) || !config.height.is_multiple_of(2) {
+            return Err(format!(
+                "OpenH264 needs even dimensions, got {}x{}",
+                config.width, config.height
+            ));
+        }
+        let settings = OpenH264Config::new()
+            .set_bitrate_bps(config.bitrate_bps.min(u32::MAX as u64) as u32)
+            .max_frame_rate(config.frame_rate.max(1.0) as f32)
+            .rate_control_mode(RateControlMode::Bitrate)
+            .enable_skip_frame(false);
+        let encoder =
+            openh264::encoder::Encoder::with_api_config(OpenH264API::from_source(), settings)
+                .map_err(|e| format!("OpenH264 init failed: {}", e))?;
+
+        Ok(Self {
+            encoder,
+            width: config.width as usize,
+            height: config.height as usize,
+            gop_frames: config.gop_frames.max(1) as u64,
+            frames: 0,
+            i420: Vec::new(),
+        })
+    }
+}
+
+impl Encoder for OpenH264VideoEncoder {
+    fn codec(&self) -> MuxCodec {
+        MuxCodec::H264
+    }
+
+    fn encode(&mut self, frame: &VideoFrame) -> Result<Vec<EncodedPacket>, String> {
+        use openh264::formats::YUVSlices;
+
+        let (width, height) = (self.width, self.height);
+        if frame.format != PixelFormat::NV12 || frame.width != width || frame.height != height {
+            return Err(format!(
+                "OpenH264 expects {}x{} NV12, got {}x{} {:?}",
+                width, height, frame.width, frame.height, frame.format
+            ));
+        }
+        let (y_stride, uv_stride) = (frame.linesize[0], frame.linesize[1]);
+        let uv_offset = y_stride * height;
+        if frame.data.len() < uv_offset + uv_stride * (height / 2) {
+            return Err(format!(
+                "NV12 frame is {} bytes, too short for {}x{}",
+                frame.data.len(),
+                width,
+                height
+            ));
+        }
+
+        // Split the interleaved chroma into U and V planes
+        let (chroma_width, chroma_height) = (width / 2, height / 2);
+        self.i420.clear();
+        for row in frame.data[..uv_offset].chunks(y_stride) {
+            self.i420.extend_from_slice(&row[..width]);
+        }
+        let uv_rows = || {
+            frame.data[uv_offset..]
+                .chunks(uv_stride)
+                .take(chroma_height)
+                .map(|row| &row[..chroma_width * 2])
+        };
+        for row in uv_rows() {
+            self.i420.extend(row.iter().step_by(2));
+        }
+        for row in uv_rows() {
+            self.i420.extend(row.iter().skip(1).step_by(2));
+        }
+        let (y, chroma) = self.i420.split_at(width * height);
+        let (u, v) = chroma.split_at(chroma_width * chroma_height);
+        let source = YUVSlices::new(
+            (y, u, v),
+            (width, height),
+            (width, chroma_width, chroma_width),
+        );
+
+        if self.frames.is_multiple_of(self.gop_frames) {
+            self.encoder.force_intra_frame();
+        }
+        self.frames += 1;
+        let data = self
+            .encoder
+            .encode(&source)
+            .map_err(|e| format!("OpenH264 encode failed: {}", e))?
+            .to_vec();
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![EncodedPacket {
+            keyframe: h264_utils::annexb_has_idr(&data, false),
+            data,
+            pts_us: frame.pts,
+        }])
+    }
+
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>, String> {
+        // Every frame comes straight back out of `encode`
+        Ok(Vec::new())
+    }
+
+    fn name(&self) -> &str {
+        "OpenH264"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openh264_encodes_nv12() {
+        let config = EncoderConfig {
+            codec: MuxCodec::H264,
+            width: 64,
+            height: 48,
+            frame_rate: 25.0,
+            bitrate_bps: 500_000,
+            gop_frames: 5,
+        };
+        let mut encoder = create_encoder(&config).unwrap();
+        let mut keyframes = Vec::new();
+        for i in 0..10i64 {
+            let mut frame = VideoFrame::new(64, 48, PixelFormat::NV12);
+            frame.data[..64 * 48].fill(16 + i as u8 * 8);
+            frame.pts = i * 40_000;
+            let packets = encoder.encode(&frame).unwrap();
+            assert_eq!(packets.len(), 1);
+            assert_eq!(packets[0].pts_us, frame.pts);
+            if packets[0].keyframe {
+                keyframes.push(i);
+            }
+        }
+        assert_eq!(keyframes, vec![0, 5]);
+
+        let mut odd = config.clone();
+        odd.width = 63;
+        assert!(OpenH264VideoEncoder::new(&odd).is_err());
+        odd.codec = MuxCodec::H265;
+        odd.width = 64;
+        assert!(OpenH264VideoEncoder::new(&odd).is_err());
+    }
+}
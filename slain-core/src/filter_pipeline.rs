@@ -65,6 +65,7 @@ pub enum CodecKind {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PixelFormat {
     Nv12,
+    P010,
     I420,
     Rgba,
     Bgra,
@@ -1,6 +1,13 @@
 //! Simple CPU frame interpolation utilities.
 //!
-//! This provides a reference implementation for blending RGB24 frames.
+//! This provides a reference implementation for blending RGB24 frames, plus a
+//! motion-compensated (MEMC) engine: hierarchical block matching with vector
+//! smoothing, forward and backward vector fields with occlusion handling, and
+//! scene-cut detection. `FrameRateConverter` drives it to turn 24 fps sources
+//! into 60/120 fps output timestamps. The compensation step has a GPU compute
+//! twin in `gpu_video_processor`.
+
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct RgbFrame {
@@ -254,6 +261,629 @@ fn clamp_i32(value: i32, min: i32, max: i32) -> i32 {
     }
 }
 
+// ============================================================================
+// Motion-Compensated Interpolation (MEMC)
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MemcConfig {
+    /// Block size at full resolution
+    pub block_size: usize,
+    /// Pyramid levels; each level halves the resolution and the block size
+    pub levels: usize,
+    /// Exhaustive search radius at the coarsest level (in that level's pixels)
+    pub search_radius: i32,
+    /// Refinement radius around predicted vectors at the finer levels
+    pub refine_radius: i32,
+    /// Vector median smoothing passes over the full-resolution field
+    pub smoothing_passes: usize,
+    /// Forward/backward vector disagreement (pixels) that marks a block occluded
+    pub occlusion_tolerance: i32,
+    /// Per-pixel luma mismatch above which the two sides are not blended
+    pub blend_threshold: f32,
+    /// Mean compensated luma error above which a frame pair is a scene cut
+    pub scene_cut_threshold: f32,
+}
+
+impl Default for MemcConfig {
+    fn default() -> Self {
+        Self {
+            block_size: 16,
+            levels: 3,
+            search_radius: 4,
+            refine_radius: 2,
+            smoothing_passes: 1,
+            occlusion_tolerance: 2,
+            blend_threshold: 24.0,
+            scene_cut_threshold: 20.0,
+        }
+    }
+}
+
+impl MemcConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.block_size < 2 {
+            return Err("block_size must be at least 2".to_string());
+        }
+        if self.levels == 0 {
+            return Err("levels must be at least 1".to_string());
+        }
+        if self.search_radius < 0 || self.refine_radius < 0 {
+            return Err("search radii must be >= 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Block motion vectors from a source frame to a target frame
+#[derive(Debug, Clone)]
+pub struct MotionField {
+    pub block_size: usize,
+    pub blocks_x: usize,
+    pub blocks_y: usize,
+    /// Displacement of each source block into the target frame
+    pub vectors: Vec<(i32, i32)>,
+    /// SAD of each block at its chosen vector
+    pub sad: Vec<u64>,
+    /// Blocks whose vector the opposite field does not confirm
+    pub occluded: Vec<bool>,
+}
+
+impl MotionField {
+    fn block_index(&self, x: usize, y: usize) -> usize {
+        let bx = (x / self.block_size).min(self.blocks_x - 1);
+        let by = (y / self.block_size).min(self.blocks_y - 1);
+        by * self.blocks_x + bx
+    }
+
+    /// Vector of the block covering pixel (x, y)
+    pub fn vector_at(&self, x: usize, y: usize) -> (i32, i32) {
+        self.vectors[self.block_index(x, y)]
+    }
+
+    pub fn is_occluded(&self, x: usize, y: usize) -> bool {
+        self.occluded[self.block_index(x, y)]
+    }
+}
+
+/// Bidirectional motion between two frames
+#[derive(Debug, Clone)]
+pub struct MotionFields {
+    /// prev → next
+    pub forward: MotionField,
+    /// next → prev
+    pub backward: MotionField,
+    /// Mean motion-compensated luma error of the forward field
+    pub error: f32,
+    /// The frames are unrelated; interpolation repeats the nearest frame
+    pub scene_cut: bool,
+}
+
+struct LumaPyramid {
+    levels: Vec<Vec<u8>>,
+    dims: Vec<(usize, usize)>,
+}
+
+impl LumaPyramid {
+    fn new(frame: &RgbFrame, levels: usize) -> Self {
+        let mut pyramid = Self {
+            levels: vec![rgb_to_luma(&frame.data)],
+            dims: vec![(frame.width as usize, frame.height as usize)],
+        };
+        while pyramid.levels.len() < levels {
+            let (w, h) = pyramid.dims[pyramid.dims.len() - 1];
+            if w < 4 || h < 4 {
+                break;
+            }
+            let src = &pyramid.levels[pyramid.levels.len() - 1];
+            let (dw, dh) = (w / 2, h / 2);
+            let mut dst = Vec::with_capacity(dw * dh);
+            for y in 0..dh {
+                for x in 0..dw {
+                    let i = 2 * y * w + 2 * x;
+                    let sum = src[i] as u32
+                        + src[i + 1] as u32
+                        + src[i + w] as u32
+                        + src[i + w + 1] as u32;
+                    dst.push(((sum + 2) / 4) as u8);
+                }
+            }
+            pyramid.levels.push(dst);
+            pyramid.dims.push((dw, dh));
+        }
+        pyramid
+    }
+}
+
+/// Hierarchical block matching from `src` to `dst`. The block grid is the same
+/// at every level (block size halves with resolution), so each block's vector
+/// seeds its own refinement one level down.
+fn estimate_field(src: &LumaPyramid, dst: &LumaPyramid, config: &MemcConfig) -> MotionField {
+    let (width, height) = src.dims[0];
+    let block_size = config.block_size;
+    let blocks_x = width.div_ceil(block_size);
+    let blocks_y = height.div_ceil(block_size);
+    let mut vectors = vec![(0i32, 0i32); blocks_x * blocks_y];
+    let mut sad = vec![0u64; blocks_x * blocks_y];
+
+    let levels = src.dims.len().min(dst.dims.len());
+    for level in (0..levels).rev() {
+        let (lw, lh) = src.dims[level];
+        let lbs = (block_size >> level).max(1);
+        let coarsest = level + 1 == levels;
+        let parent = vectors.clone();
+        let (src_luma, dst_luma) = (&src.levels[level], &dst.levels[level]);
+
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                let i = by * blocks_x + bx;
+                let (x0, y0) = (bx * lbs, by * lbs);
+                let bw = lbs.min(lw.saturating_sub(x0));
+                let bh = lbs.min(lh.saturating_sub(y0));
+
+                let mut candidates = vec![(0, 0)];
+                if !coarsest {
+                    let neighbours = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)];
+                    for (nx, ny) in neighbours {
+                        let (cx, cy) = (bx as i32 + nx, by as i32 + ny);
+                        if cx >= 0
+                            && cy >= 0
+                            && (cx as usize) < blocks_x
+                            && (cy as usize) < blocks_y
+                        {
+                            let (vx, vy) = parent[cy as usize * blocks_x + cx as usize];
+                            candidates.push((vx * 2, vy * 2));
+                        }
+                    }
+                }
+                if bw == 0 || bh == 0 {
+                    vectors[i] = candidates[candidates.len().min(2) - 1];
+                    sad[i] = 0;
+                    continue;
+                }
+
+                let cost = |(dx, dy): (i32, i32)| {
+                    let sad = block_sad(dst_luma, src_luma, lw, lh, x0, y0, bw, bh, dx, dy);
+                    (sad, dx.abs() + dy.abs())
+                };
+                let mut best = (u64::MAX, i32::MAX);
+                let mut best_vector = (0, 0);
+                for candidate in candidates {
+                    let score = cost(candidate);
+                    if score < best {
+                        best = score;
+                        best_vector = candidate;
+                    }
+                }
+
+                let radius = if coarsest {
+                    config.search_radius
+                } else {
+                    config.refine_radius
+                };
+                let centre = best_vector;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let candidate = (centre.0 + dx, centre.1 + dy);
+                        let score = cost(candidate);
+                        if score < best {
+                            best = score;
+                            best_vector = candidate;
+                        }
+                    }
+                }
+                vectors[i] = best_vector;
+                sad[i] = best.0;
+            }
+        }
+    }
+
+    let mut field = MotionField {
+        block_size,
+        blocks_x,
+        blocks_y,
+        vectors,
+        sad,
+        occluded: vec![false; blocks_x * blocks_y],
+    };
+    for _ in 0..config.smoothing_passes {
+        smooth_field(&mut field, &src.levels[0], &dst.levels[0], width, height);
+    }
+    field
+}
+
+/// 3x3 vector median filter. A block only takes the median when it matches
+/// nearly as well as its own vector, so real motion boundaries survive.
+fn smooth_field(field: &mut MotionField, src: &[u8], dst: &[u8], width: usize, height: usize) {
+    let (blocks_x, blocks_y, bs) = (field.blocks_x, field.blocks_y, field.block_size);
+    let mut vectors = field.vectors.clone();
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let mut xs = Vec::with_capacity(9);
+            let mut ys = Vec::with_capacity(9);
+            for ny in by.saturating_sub(1)..(by + 2).min(blocks_y) {
+                for nx in bx.saturating_sub(1)..(bx + 2).min(blocks_x) {
+                    let (vx, vy) = field.vectors[ny * blocks_x + nx];
+                    xs.push(vx);
+                    ys.push(vy);
+                }
+            }
+            xs.sort_unstable();
+            ys.sort_unstable();
+            let median = (xs[xs.len() / 2], ys[ys.len() / 2]);
+            let i = by * blocks_x + bx;
+            if median == field.vectors[i] {
+                continue;
+            }
+
+            let (x0, y0) = (bx * bs, by * bs);
+            let (bw, bh) = (bs.min(width - x0), bs.min(height - y0));
+            let sad = block_sad(dst, src, width, height, x0, y0, bw, bh, median.0, median.1);
+            let own = field.sad[i];
+            if sad <= own + own / 4 + (bw * bh) as u64 {
+                vectors[i] = median;
+                field.sad[i] = sad;
+            }
+        }
+    }
+    field.vectors = vectors;
+}
+
+/// Mark blocks whose vector does not lead back to them through `other`
+fn occlusion_mask(
+    field: &MotionField,
+    other: &MotionField,
+    width: usize,
+    height: usize,
+    tolerance: i32,
+) -> Vec<bool> {
+    let bs = field.block_size;
+    (0..field.vectors.len())
+        .map(|i| {
+            let (bx, by) = (i % field.blocks_x, i / field.blocks_x);
+            let (vx, vy) = field.vectors[i];
+            let cx = clamp_i32((bx * bs + bs / 2) as i32 + vx, 0, width as i32 - 1) as usize;
+            let cy = clamp_i32((by * bs + bs / 2) as i32 + vy, 0, height as i32 - 1) as usize;
+            let (ox, oy) = other.vector_at(cx, cy);
+            (vx + ox).abs() + (vy + oy).abs() > tolerance
+        })
+        .collect()
+}
+
+/// Estimate forward and backward motion between two frames
+pub fn estimate_motion_fields(
+    prev: &RgbFrame,
+    next: &RgbFrame,
+    config: &MemcConfig,
+) -> Result<MotionFields, String> {
+    if prev.width != next.width || prev.height != next.height {
+        return Err("Frame dimensions must match for interpolation".to_string());
+    }
+    if prev.width == 0 || prev.height == 0 {
+        return Err("Frame dimensions must be non-zero".to_string());
+    }
+    config.validate()?;
+
+    let width = prev.width as usize;
+    let height = prev.height as usize;
+    let prev_pyramid = LumaPyramid::new(prev, config.levels);
+    let next_pyramid = LumaPyramid::new(next, config.levels);
+
+    let mut forward = estimate_field(&prev_pyramid, &next_pyramid, config);
+    let mut backward = estimate_field(&next_pyramid, &prev_pyramid, config);
+    let forward_occluded = occlusion_mask(
+        &forward,
+        &backward,
+        width,
+        height,
+        config.occlusion_tolerance,
+    );
+    let backward_occluded = occlusion_mask(
+        &backward,
+        &forward,
+        width,
+        height,
+        config.occlusion_tolerance,
+    );
+    forward.occluded = forward_occluded;
+    backward.occluded = backward_occluded;
+
+    let error = forward.sad.iter().sum::<u64>() as f32 / (width * height) as f32;
+    Ok(MotionFields {
+        forward,
+        backward,
+        error,
+        scene_cut: error > config.scene_cut_threshold,
+    })
+}
+
+fn fetch_rgb(frame: &RgbFrame, x: i32, y: i32) -> [f32; 3] {
+    let cx = clamp_i32(x, 0, frame.width as i32 - 1) as usize;
+    let cy = clamp_i32(y, 0, frame.height as i32 - 1) as usize;
+    let i = (cy * frame.width as usize + cx) * 3;
+    [
+        frame.data[i] as f32,
+        frame.data[i + 1] as f32,
+        frame.data[i + 2] as f32,
+    ]
+}
+
+fn bilinear_rgb(frame: &RgbFrame, x: f32, y: f32) -> [f32; 3] {
+    let (bx, by) = (x.floor(), y.floor());
+    let (fx, fy) = (x - bx, y - by);
+    let (ix, iy) = (bx as i32, by as i32);
+    let lerp = |a: [f32; 3], b: [f32; 3], f: f32| [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * f);
+    let top = lerp(fetch_rgb(frame, ix, iy), fetch_rgb(frame, ix + 1, iy), fx);
+    let bottom = lerp(
+        fetch_rgb(frame, ix, iy + 1),
+        fetch_rgb(frame, ix + 1, iy + 1),
+        fx,
+    );
+    lerp(top, bottom, fy)
+}
+
+fn luma_f32(c: [f32; 3]) -> f32 {
+    0.299 * c[0] + 0.587 * c[1] + 0.114 * c[2]
+}
+
+/// Build the frame at phase `t` (0 = prev, 1 = next) along the motion
+/// trajectories. Each pixel tries the forward and the backward vector and
+/// keeps whichever lines the two frames up better; where neither does, the
+/// occlusion masks pick the one frame that actually shows the content.
+///
+/// Mirrors `SHADER_MEMC_INTERPOLATE` in `gpu_video_processor`.
+pub fn interpolate_memc(
+    prev: &RgbFrame,
+    next: &RgbFrame,
+    fields: &MotionFields,
+    t: f32,
+    config: &MemcConfig,
+) -> Result<RgbFrame, String> {
+    if prev.width != next.width || prev.height != next.height {
+        return Err("Frame dimensions must match for interpolation".to_string());
+    }
+    if prev.data.len() != next.data.len() {
+        return Err("Frame data sizes must match for interpolation".to_string());
+    }
+    if !(0.0..=1.0).contains(&t) {
+        return Err("t must be between 0.0 and 1.0".to_string());
+    }
+    if fields.scene_cut || t == 0.0 || t == 1.0 {
+        return Ok(if t < 0.5 { prev.clone() } else { next.clone() });
+    }
+
+    let width = prev.width as usize;
+    let height = prev.height as usize;
+    let mut output = Vec::with_capacity(prev.data.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (px, py) = (x as f32, y as f32);
+            let (fx, fy) = fields.forward.vector_at(x, y);
+            let (bx, by) = fields.backward.vector_at(x, y);
+            let forward = (fx as f32, fy as f32);
+            let backward = (-bx as f32, -by as f32);
+
+            let side = |(vx, vy): (f32, f32)| {
+                let p = bilinear_rgb(prev, px - t * vx, py - t * vy);
+                let n = bilinear_rgb(next, px + (1.0 - t) * vx, py + (1.0 - t) * vy);
+                (p, n, (luma_f32(p) - luma_f32(n)).abs())
+            };
+            let (pa, na, err_a) = side(forward);
+            let (pb, nb, err_b) = side(backward);
+            let (p, n, err) = if err_b < err_a {
+                (pb, nb, err_b)
+            } else {
+                (pa, na, err_a)
+            };
+
+            let covered = fields.forward.is_occluded(x, y);
+            let uncovered = fields.backward.is_occluded(x, y);
+            let color = if err <= config.blend_threshold {
+                [0, 1, 2].map(|c| p[c] + (n[c] - p[c]) * t)
+            } else if covered && !uncovered {
+                // Only the previous frame still shows this content
+                pa
+            } else if uncovered && !covered {
+                // Content revealed by the motion exists only in the next frame
+                nb
+            } else if t < 0.5 {
+                p
+            } else {
+                n
+            };
+            output.extend(color.map(|c| c.round().clamp(0.0, 255.0) as u8));
+        }
+    }
+
+    Ok(RgbFrame {
+        width: prev.width,
+        height: prev.height,
+        data: output,
+    })
+}
+
+// ============================================================================
+// Frame Rate Conversion
+// ============================================================================
+
+/// Source gaps longer than this are treated as discontinuities, not motion
+const MAX_INTERPOLATION_GAP_US: i64 = 250_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrameRateStats {
+    pub source_frames: u64,
+    pub output_frames: u64,
+    pub interpolated: u64,
+    pub scene_cuts: u64,
+}
+
+/// Resamples a timestamped frame stream onto a fixed output rate (24 → 60/120
+/// fps), synthesising in-between frames with MEMC.
+pub struct FrameRateConverter {
+    config: MemcConfig,
+    target_fps: f64,
+    use_gpu: bool,
+    prev: Option<(RgbFrame, i64)>,
+    origin_us: i64,
+    next_index: u64,
+    stats: FrameRateStats,
+}
+
+impl FrameRateConverter {
+    pub fn new(target_fps: f64, config: MemcConfig) -> Result<Self, String> {
+        if !(target_fps.is_finite() && target_fps > 0.0) {
+            return Err("target_fps must be positive".to_string());
+        }
+        config.validate()?;
+        Ok(Self {
+            config,
+            target_fps,
+            use_gpu: false,
+            prev: None,
+            origin_us: 0,
+            next_index: 0,
+            stats: FrameRateStats::default(),
+        })
+    }
+
+    /// Run the compensation step on the global GPU processor when one is
+    /// initialised (falls back to the CPU path otherwise)
+    pub fn with_gpu(mut self, use_gpu: bool) -> Self {
+        self.use_gpu = use_gpu;
+        self
+    }
+
+    pub fn target_fps(&self) -> f64 {
+        self.target_fps
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: f64) -> Result<(), String> {
+        if !(target_fps.is_finite() && target_fps > 0.0) {
+            return Err("target_fps must be positive".to_string());
+        }
+        self.target_fps = target_fps;
+        if let Some((_, pts)) = &self.prev {
+            self.origin_us = *pts;
+            self.next_index = 0;
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &MemcConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &FrameRateStats {
+        &self.stats
+    }
+
+    fn output_time(&self, index: u64) -> i64 {
+        self.origin_us + (index as f64 * 1_000_000.0 / self.target_fps).round() as i64
+    }
+
+    /// Feed the next source frame; returns the output frames (with their
+    /// presentation times in µs) that fall before it
+    pub fn push(&mut self, frame: RgbFrame, pts_us: i64) -> Result<Vec<(RgbFrame, i64)>, String> {
+        self.stats.source_frames += 1;
+        let Some((prev, prev_pts)) = self.prev.take() else {
+            self.origin_us = pts_us;
+            self.next_index = 0;
+            self.prev = Some((frame, pts_us));
+            return Ok(Vec::new());
+        };
+
+        let span = pts_us - prev_pts;
+        let mut output = Vec::new();
+        if span <= 0
+            || span > MAX_INTERPOLATION_GAP_US
+            || prev.width != frame.width
+            || prev.height != frame.height
+        {
+            // Seek, edit or resolution change: show the frame as-is and restart
+            // the output grid at the new one
+            let time = self.output_time(self.next_index).max(prev_pts);
+            output.push((prev, time));
+            self.stats.output_frames += 1;
+            self.origin_us = pts_us;
+            self.next_index = 0;
+            self.prev = Some((frame, pts_us));
+            return Ok(output);
+        }
+
+        let mut fields: Option<MotionFields> = None;
+        loop {
+            let time = self.output_time(self.next_index);
+            if time >= pts_us {
+                break;
+            }
+            self.next_index += 1;
+            if time < prev_pts {
+                continue;
+            }
+
+            let phase = (time - prev_pts) as f32 / span as f32;
+            let picture = if time == prev_pts {
+                prev.clone()
+            } else {
+                if fields.is_none() {
+                    let estimated = estimate_motion_fields(&prev, &frame, &self.config)?;
+                    if estimated.scene_cut {
+                        self.stats.scene_cuts += 1;
+                    }
+                    fields = Some(estimated);
+                }
+                let fields = fields.as_ref().ok_or("Motion fields missing")?;
+                if !fields.scene_cut {
+                    self.stats.interpolated += 1;
+                }
+                self.interpolate(&prev, &frame, fields, phase)?
+            };
+            output.push((picture, time));
+        }
+
+        self.stats.output_frames += output.len() as u64;
+        self.prev = Some((frame, pts_us));
+        Ok(output)
+    }
+
+    /// Emit the held last frame (end of stream)
+    pub fn flush(&mut self) -> Vec<(RgbFrame, i64)> {
+        let Some((frame, pts)) = self.prev.take() else {
+            return Vec::new();
+        };
+        let time = self.output_time(self.next_index).max(pts);
+        self.stats.output_frames += 1;
+        self.next_index = 0;
+        vec![(frame, time)]
+    }
+
+    /// Drop held state (seek); the output grid restarts at the next frame
+    pub fn reset(&mut self) {
+        self.prev = None;
+        self.next_index = 0;
+    }
+
+    fn interpolate(
+        &self,
+        prev: &RgbFrame,
+        next: &RgbFrame,
+        fields: &MotionFields,
+        t: f32,
+    ) -> Result<RgbFrame, String> {
+        if self.use_gpu && !fields.scene_cut {
+            if let Some(gpu) = crate::gpu_video_processor::gpu_processor().as_mut() {
+                let rgba = gpu.interpolate_memc(prev, next, fields, t, self.config.blend_threshold);
+                let data = rgba
+                    .chunks_exact(4)
+                    .flat_map(|px| [px[0], px[1], px[2]])
+                    .collect();
+                return RgbFrame::new(prev.width, prev.height, data);
+            }
+        }
+        interpolate_memc(prev, next, fields, t, &self.config)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,4 +915,110 @@ mod tests {
         let output = motion_compensated_blend(&prev, &next, config).expect("blend");
         assert_eq!(output.data, next.data);
     }
+
+    fn hash(x: i32, y: i32, seed: u32) -> u32 {
+        let mut h =
+            (x as u32).wrapping_mul(0x9E37_79B1) ^ (y as u32).wrapping_mul(0x85EB_CA77) ^ seed;
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2C1B_3C6D);
+        h ^ (h >> 12)
+    }
+
+    /// Deterministic value-noise texture over the whole plane (8 px cells
+    /// plus fine grain), so shifted views of it have no exposed edges
+    fn world(x: i32, y: i32, seed: u32) -> [u8; 3] {
+        let (cx, cy) = (x.div_euclid(8), y.div_euclid(8));
+        let (fx, fy) = (x.rem_euclid(8) as f32 / 8.0, y.rem_euclid(8) as f32 / 8.0);
+        [0u32, 8, 16].map(|shift| {
+            let corner = |dx, dy| ((hash(cx + dx, cy + dy, seed) >> shift) & 0xFF) as f32;
+            let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * fx;
+            let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * fx;
+            let grain = ((hash(x, y, seed ^ 0x5555) >> shift) & 0x1F) as f32 - 16.0;
+            (top + (bottom - top) * fy + grain).clamp(0.0, 255.0) as u8
+        })
+    }
+
+    fn view(width: u32, height: u32, offset: (f32, f32), seed: u32) -> RgbFrame {
+        let mut data = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                data.extend(world(x - offset.0 as i32, y - offset.1 as i32, seed));
+            }
+        }
+        RgbFrame::new(width, height, data).expect("view")
+    }
+
+    #[test]
+    fn hierarchical_search_finds_pan() {
+        let prev = view(64, 48, (0.0, 0.0), 1);
+        let next = view(64, 48, (6.0, 2.0), 1);
+        let fields = estimate_motion_fields(&prev, &next, &MemcConfig::default()).expect("fields");
+
+        assert!(!fields.scene_cut);
+        // Interior blocks see the whole pan
+        assert_eq!(fields.forward.vector_at(24, 24), (6, 2));
+        assert_eq!(fields.backward.vector_at(24, 24), (-6, -2));
+        assert!(!fields.forward.is_occluded(24, 24));
+    }
+
+    #[test]
+    fn memc_midpoint_tracks_motion_without_ghosting() {
+        let config = MemcConfig::default();
+        let prev = view(64, 48, (0.0, 0.0), 2);
+        let next = view(64, 48, (6.0, 2.0), 2);
+        let expected = view(64, 48, (3.0, 1.0), 2);
+        let fields = estimate_motion_fields(&prev, &next, &config).expect("fields");
+        let memc = interpolate_memc(&prev, &next, &fields, 0.5, &config).expect("memc");
+        let linear = interpolate_rgb(&prev, &next, 0.5).expect("linear");
+
+        let interior_error = |frame: &RgbFrame| {
+            let mut sum = 0u64;
+            for y in 8..40 {
+                for x in 8..56 {
+                    let i = (y * 64 + x) * 3;
+                    sum += frame.data[i].abs_diff(expected.data[i]) as u64;
+                }
+            }
+            sum as f32 / (32 * 48) as f32
+        };
+        assert!(interior_error(&memc) < 1.0);
+        assert!(interior_error(&linear) > 10.0);
+    }
+
+    #[test]
+    fn scene_cut_repeats_nearest_frame() {
+        let config = MemcConfig::default();
+        let prev = view(32, 32, (0.0, 0.0), 3);
+        // Different content in negative: nothing in `prev` can predict it
+        let mut next = view(32, 32, (0.0, 0.0), 4);
+        next.data.iter_mut().for_each(|v| *v = 255 - *v);
+        let fields = estimate_motion_fields(&prev, &next, &config).expect("fields");
+        assert!(fields.scene_cut);
+
+        let early = interpolate_memc(&prev, &next, &fields, 0.4, &config).expect("early");
+        let late = interpolate_memc(&prev, &next, &fields, 0.6, &config).expect("late");
+        assert_eq!(early.data, prev.data);
+        assert_eq!(late.data, next.data);
+    }
+
+    #[test]
+    fn converter_resamples_24_to_60() {
+        let mut converter =
+            FrameRateConverter::new(60.0, MemcConfig::default()).expect("converter");
+        let mut output = Vec::new();
+        for k in 0..5 {
+            let frame = view(32, 32, (k as f32 * 2.0, 0.0), 5);
+            output.extend(converter.push(frame, k * 41_667).expect("push"));
+        }
+
+        // Source span 0..166_668 µs holds eleven 60 fps slots
+        assert_eq!(output.len(), 11);
+        for (k, (_, pts)) in output.iter().enumerate() {
+            assert_eq!(*pts, (k as f64 * 1_000_000.0 / 60.0).round() as i64);
+        }
+        assert_eq!(output[0].0.data, view(32, 32, (0.0, 0.0), 5).data);
+        assert_eq!(converter.stats().interpolated, 10);
+        assert_eq!(converter.stats().scene_cuts, 0);
+        assert_eq!(converter.flush().len(), 1);
+    }
 }
@@ -7,10 +7,13 @@
 //! - Color correction (brightness, contrast, saturation, gamma)
 //! - Adaptive sharpening with edge detection
 //! - Temporal noise reduction
-//! - HDR tone mapping (Reinhard, ACES, Hable)
+//! - HDR tone mapping (BT.2390, Reinhard, ACES, Hable, Mobius) with PQ/HLG decode
+//!   and BT.2020→BT.709 gamut mapping (CPU reference in `hdr`)
+//! - Motion-compensated frame interpolation (vectors from `frame_interpolation`)
 //!
 //! This is the professional-grade video pipeline that makes SLAIN special.
 
+use crate::frame_interpolation::{MotionField, MotionFields, RgbFrame};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
@@ -231,9 +234,15 @@ struct Params {
     width: u32,
     height: u32,
     exposure: f32,        // Exposure adjustment
-    white_point: f32,     // White point for Reinhard
-    tonemap_mode: u32,    // 0=Reinhard, 1=ACES, 2=Hable
-    _pad: u32,
+    white_point: f32,     // Source peak relative to target (Reinhard/Hable/Mobius)
+    tonemap_mode: u32,    // 0=Reinhard, 1=ACES, 2=Hable, 3=BT.2390, 4=Mobius
+    transfer: u32,        // 0=linear, 1=PQ, 2=HLG
+    src_peak_nits: f32,
+    src_black_nits: f32,
+    target_peak_nits: f32,
+    gamut_mode: u32,      // 0=none, 1=clip, 2=desaturate (BT.2020 sources)
+    _pad0: u32,
+    _pad1: u32,
 }
 
 @group(0) @binding(0) var<uniform> params: Params;
@@ -267,13 +276,102 @@ fn hable_partial(x: vec3<f32>) -> vec3<f32> {
     return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
 }
 
+// Expects exposure already applied by the caller
 fn tonemap_hable(color: vec3<f32>) -> vec3<f32> {
-    let curr = hable_partial(color * params.exposure);
+    let curr = hable_partial(color);
     let white = vec3<f32>(params.white_point);
     let white_scale = vec3<f32>(1.0) / hable_partial(white);
     return curr * white_scale;
 }
 
+// SMPTE ST 2084 (PQ)
+fn pq_eotf(e: f32) -> f32 {
+    let p = pow(clamp(e, 0.0, 1.0), 1.0 / 78.84375);
+    let num = max(p - 0.8359375, 0.0);
+    let den = 18.8515625 - 18.6875 * p;
+    return 10000.0 * pow(num / den, 1.0 / 0.1593017578125);
+}
+
+fn pq_inverse_eotf(nits: f32) -> f32 {
+    let y = pow(clamp(nits / 10000.0, 0.0, 1.0), 0.1593017578125);
+    return pow((0.8359375 + 18.8515625 * y) / (1.0 + 18.6875 * y), 78.84375);
+}
+
+// ARIB STD-B67 (HLG) inverse OETF
+fn hlg_inverse_oetf(e: f32) -> f32 {
+    if e <= 0.5 {
+        return e * e / 3.0;
+    }
+    return (exp((e - 0.55991073) / 0.17883277) + 0.28466892) / 12.0;
+}
+
+// Decode signal to linear nits
+fn linearize(signal: vec3<f32>) -> vec3<f32> {
+    if params.transfer == 1u {
+        return vec3<f32>(pq_eotf(signal.r), pq_eotf(signal.g), pq_eotf(signal.b));
+    }
+    if params.transfer == 2u {
+        let scene = vec3<f32>(hlg_inverse_oetf(signal.r), hlg_inverse_oetf(signal.g), hlg_inverse_oetf(signal.b));
+        let y = dot(scene, vec3<f32>(0.2627, 0.6780, 0.0593));
+        // OOTF for a 1000 nit display (system gamma 1.2)
+        return scene * 1000.0 * pow(max(y, 1e-6), 0.2);
+    }
+    return signal * params.target_peak_nits;
+}
+
+// BT.2390 EETF (hermite knee in PQ space), returns display-relative 0..1
+fn tonemap_bt2390(nits: f32) -> f32 {
+    let lo = pq_inverse_eotf(params.src_black_nits);
+    let hi = pq_inverse_eotf(max(params.src_peak_nits, params.target_peak_nits));
+    let range = max(hi - lo, 1e-6);
+    let e1 = clamp((pq_inverse_eotf(nits) - lo) / range, 0.0, 1.0);
+    let max_lum = clamp((pq_inverse_eotf(params.target_peak_nits) - lo) / range, 0.0, 1.0);
+    let ks = 1.5 * max_lum - 0.5;
+    var e2 = e1;
+    if e1 >= ks && ks < 1.0 {
+        let t = (e1 - ks) / (1.0 - ks);
+        let t2 = t * t;
+        let t3 = t2 * t;
+        e2 = (2.0 * t3 - 3.0 * t2 + 1.0) * ks + (t3 - 2.0 * t2 + t) * (1.0 - ks) + (-2.0 * t3 + 3.0 * t2) * max_lum;
+    }
+    return clamp(pq_eotf(e2 * range + lo) / params.target_peak_nits, 0.0, 1.0);
+}
+
+// Mobius: linear up to j, smooth roll-off to the source peak
+fn tonemap_mobius(x: f32) -> f32 {
+    let j = 0.3;
+    let peak = params.white_point;
+    if x <= j || peak <= 1.0 {
+        return min(x, 1.0);
+    }
+    let a = -j * j * (peak - 1.0) / (j * j - 2.0 * j + peak);
+    let b = (j * j - 2.0 * j * peak + peak) / max(peak - 1.0, 1e-6);
+    return (b * b + 2.0 * b * j + j * j) / (b - a) * (x + a) / (x + b);
+}
+
+// BT.2020 -> BT.709 with optional desaturation into gamut
+fn gamut_map(rgb: vec3<f32>) -> vec3<f32> {
+    if params.gamut_mode == 0u {
+        return rgb;
+    }
+    let m = mat3x3<f32>(
+        vec3<f32>(1.6605, -0.1246, -0.0182),
+        vec3<f32>(-0.5876, 1.1329, -0.1006),
+        vec3<f32>(-0.0728, -0.0083, 1.1187)
+    );
+    let c = m * rgb;
+    if params.gamut_mode == 1u {
+        return clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    let y = clamp(dot(c, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    let lo = min(c.r, min(c.g, c.b));
+    let hi = max(c.r, max(c.g, c.b));
+    var t = 1.0;
+    if lo < 0.0 { t = min(t, y / (y - lo)); }
+    if hi > 1.0 { t = min(t, (1.0 - y) / (hi - y)); }
+    return clamp(vec3<f32>(y) + (c - vec3<f32>(y)) * clamp(t, 0.0, 1.0), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
 // Linear to sRGB gamma
 fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
     let cutoff = color < vec3<f32>(0.0031308);
@@ -301,15 +399,35 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         f32((packed >> 20u) & 0x3FFu) / 1023.0
     );
 
-    // Apply exposure
-    color = color * params.exposure;
-
-    // Tone mapping
-    switch params.tonemap_mode {
-        case 0u: { color = tonemap_reinhard(color); }
-        case 1u: { color = tonemap_aces(color); }
-        case 2u: { color = tonemap_hable(color); }
-        default: { color = tonemap_aces(color); }
+    if params.transfer == 0u {
+        // Legacy path: input is already linear, curve on RGB
+        color = color * params.exposure;
+        switch params.tonemap_mode {
+            case 0u: { color = tonemap_reinhard(color); }
+            case 1u: { color = tonemap_aces(color); }
+            case 2u: { color = tonemap_hable(color); }
+            case 4u: { color = vec3<f32>(tonemap_mobius(color.r), tonemap_mobius(color.g), tonemap_mobius(color.b)); }
+            default: { color = tonemap_aces(color); }
+        }
+    } else {
+        // HDR path: decode to nits and tone map luminance to keep hue stable
+        let nits = linearize(color) * params.exposure;
+        var luma_coeffs = vec3<f32>(0.2126, 0.7152, 0.0722);
+        if params.gamut_mode != 0u {
+            luma_coeffs = vec3<f32>(0.2627, 0.6780, 0.0593);
+        }
+        let y = dot(nits, luma_coeffs);
+        let x = y / params.target_peak_nits;
+        var mapped: f32;
+        switch params.tonemap_mode {
+            case 0u: { mapped = tonemap_reinhard(vec3<f32>(x)).r; }
+            case 1u: { mapped = tonemap_aces(vec3<f32>(x * 2.0 / max(params.white_point, 1.0))).r; }
+            case 2u: { mapped = tonemap_hable(vec3<f32>(x)).r; }
+            case 4u: { mapped = tonemap_mobius(x); }
+            default: { mapped = tonemap_bt2390(y); }
+        }
+        let scale = select(0.0, mapped / max(x, 1e-6), y > 1e-6);
+        color = gamut_map(nits / params.target_peak_nits * scale);
     }
 
     // Convert to sRGB gamma
@@ -323,6 +441,111 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 }
 "#;
 
+/// Motion-compensated frame interpolation (CPU twin: `frame_interpolation::interpolate_memc`)
+const SHADER_MEMC_INTERPOLATE: &str = r#"
+struct Params {
+    width: u32,
+    height: u32,
+    blocks_x: u32,
+    blocks_y: u32,
+    block_size: u32,
+    t: f32,               // Phase between prev (0) and next (1)
+    blend_threshold: f32, // Luma mismatch above which sides are not blended
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> prev_frame: array<u32>;  // RGBA8
+@group(0) @binding(2) var<storage, read> next_frame: array<u32>;  // RGBA8
+@group(0) @binding(3) var<storage, read> forward: array<vec4<i32>>;  // dx, dy, occluded, 0
+@group(0) @binding(4) var<storage, read> backward: array<vec4<i32>>;
+@group(0) @binding(5) var<storage, read_write> output: array<u32>;
+
+fn unpack_rgb(c: u32) -> vec3<f32> {
+    return vec3<f32>(f32(c & 0xFFu), f32((c >> 8u) & 0xFFu), f32((c >> 16u) & 0xFFu));
+}
+
+fn fetch(from_next: bool, x: i32, y: i32) -> vec3<f32> {
+    let cx = clamp(x, 0, i32(params.width) - 1);
+    let cy = clamp(y, 0, i32(params.height) - 1);
+    let idx = u32(cy) * params.width + u32(cx);
+    if from_next {
+        return unpack_rgb(next_frame[idx]);
+    }
+    return unpack_rgb(prev_frame[idx]);
+}
+
+fn bilinear(from_next: bool, pos: vec2<f32>) -> vec3<f32> {
+    let base = floor(pos);
+    let f = pos - base;
+    let x = i32(base.x);
+    let y = i32(base.y);
+    let top = mix(fetch(from_next, x, y), fetch(from_next, x + 1, y), f.x);
+    let bottom = mix(fetch(from_next, x, y + 1), fetch(from_next, x + 1, y + 1), f.x);
+    return mix(top, bottom, f.y);
+}
+
+fn luma(c: vec3<f32>) -> f32 {
+    return dot(c, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if x >= params.width || y >= params.height {
+        return;
+    }
+
+    let bx = min(x / params.block_size, params.blocks_x - 1u);
+    let by = min(y / params.block_size, params.blocks_y - 1u);
+    let block = by * params.blocks_x + bx;
+    let fwd = forward[block];
+    let bwd = backward[block];
+
+    let p = vec2<f32>(f32(x), f32(y));
+    let t = params.t;
+    let mf = vec2<f32>(f32(fwd.x), f32(fwd.y));
+    let mb = -vec2<f32>(f32(bwd.x), f32(bwd.y));
+
+    // Candidate trajectories from the forward and backward fields
+    let pa = bilinear(false, p - t * mf);
+    let na = bilinear(true, p + (1.0 - t) * mf);
+    let pb = bilinear(false, p - t * mb);
+    let nb = bilinear(true, p + (1.0 - t) * mb);
+    let err_a = abs(luma(pa) - luma(na));
+    let err_b = abs(luma(pb) - luma(nb));
+
+    var prev_side = pa;
+    var next_side = na;
+    var err = err_a;
+    if err_b < err_a {
+        prev_side = pb;
+        next_side = nb;
+        err = err_b;
+    }
+
+    let covered = fwd.z != 0;
+    let uncovered = bwd.z != 0;
+    var color: vec3<f32>;
+    if err <= params.blend_threshold {
+        color = mix(prev_side, next_side, t);
+    } else if covered && !uncovered {
+        color = pa;
+    } else if uncovered && !covered {
+        color = nb;
+    } else if t < 0.5 {
+        color = prev_side;
+    } else {
+        color = next_side;
+    }
+
+    let c = clamp(round(color), vec3<f32>(0.0), vec3<f32>(255.0));
+    output[y * params.width + x] = u32(c.r) | (u32(c.g) << 8u) | (u32(c.b) << 16u) | (255u << 24u);
+}
+"#;
+
 // ============================================================================
 // Video Processing Parameters
 // ============================================================================
@@ -360,10 +583,15 @@ impl Default for VideoParams {
 pub struct HdrParams {
     pub width: u32,
     pub height: u32,
-    pub exposure: f32,     // Default 1.0
-    pub white_point: f32,  // Default 4.0
-    pub tonemap_mode: u32, // 0=Reinhard, 1=ACES, 2=Hable
-    pub _pad: u32,
+    pub exposure: f32,         // Default 1.0
+    pub white_point: f32,      // Default 4.0 (source peak / target peak)
+    pub tonemap_mode: u32,     // ToneMapMode as u32
+    pub transfer: u32,         // 0=linear, 1=PQ, 2=HLG
+    pub src_peak_nits: f32,    // MaxCLL / mastering peak / detected peak
+    pub src_black_nits: f32,   // Mastering display minimum
+    pub target_peak_nits: f32, // SDR white, default 203 (BT.2408)
+    pub gamut_mode: u32,       // 0=none, 1=clip, 2=desaturate
+    pub _pad: [u32; 2],
 }
 
 impl Default for HdrParams {
@@ -374,16 +602,36 @@ impl Default for HdrParams {
             exposure: 1.0,
             white_point: 4.0,
             tonemap_mode: 1, // ACES
-            _pad: 0,
+            transfer: 0,
+            src_peak_nits: 1000.0,
+            src_black_nits: 0.0,
+            target_peak_nits: 203.0,
+            gamut_mode: 0,
+            _pad: [0; 2],
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MemcParams {
+    pub width: u32,
+    pub height: u32,
+    pub blocks_x: u32,
+    pub blocks_y: u32,
+    pub block_size: u32,
+    pub t: f32,
+    pub blend_threshold: f32,
+    pub _pad: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ToneMapMode {
     Reinhard = 0,
     Aces = 1,
     Hable = 2,
+    Bt2390 = 3,
+    Mobius = 4,
 }
 
 // ============================================================================
@@ -406,6 +654,10 @@ pub struct GpuVideoProcessor {
     hdr_pipeline: wgpu::ComputePipeline,
     hdr_bind_group_layout: wgpu::BindGroupLayout,
 
+    // MEMC interpolation pipeline
+    memc_pipeline: wgpu::ComputePipeline,
+    memc_bind_group_layout: wgpu::BindGroupLayout,
+
     // Current parameters
     params: VideoParams,
     hdr_params: HdrParams,
@@ -413,6 +665,7 @@ pub struct GpuVideoProcessor {
     // Reusable buffers
     params_buffer: wgpu::Buffer,
     hdr_params_buffer: wgpu::Buffer,
+    memc_params_buffer: wgpu::Buffer,
 
     // Stats
     frames_processed: u64,
@@ -662,6 +915,100 @@ impl GpuVideoProcessor {
             cache: None,
         });
 
+        // Create MEMC pipeline
+        let memc_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("memc_shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER_MEMC_INTERPOLATE.into()),
+        });
+
+        let memc_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("memc_bind_layout"),
+                entries: &[
+                    // Params uniform
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Previous frame
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Next frame
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Forward vectors
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Backward vectors
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Output
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let memc_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("memc_pipeline_layout"),
+            bind_group_layouts: &[&memc_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let memc_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("memc_pipeline"),
+            layout: Some(&memc_pipeline_layout),
+            module: &memc_shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
         // Create parameter buffers
         let params = VideoParams::default();
         let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -677,6 +1024,13 @@ impl GpuVideoProcessor {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let memc_params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("memc_params_buffer"),
+            size: std::mem::size_of::<MemcParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         Ok(Self {
             device,
             queue,
@@ -686,10 +1040,13 @@ impl GpuVideoProcessor {
             yuv420p_bind_group_layout,
             hdr_pipeline,
             hdr_bind_group_layout,
+            memc_pipeline,
+            memc_bind_group_layout,
             params,
             hdr_params,
             params_buffer,
             hdr_params_buffer,
+            memc_params_buffer,
             frames_processed: 0,
         })
     }
@@ -794,7 +1151,7 @@ impl GpuVideoProcessor {
             });
             pass.set_pipeline(&self.nv12_pipeline);
             pass.set_bind_group(0, &bind_group, &[]);
-            pass.dispatch_workgroups((width + 15) / 16, (height + 15) / 16, 1);
+            pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
         }
 
         encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
@@ -906,7 +1263,239 @@ impl GpuVideoProcessor {
             let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
             pass.set_pipeline(&self.yuv420p_pipeline);
             pass.set_bind_group(0, &bind_group, &[]);
-            pass.dispatch_workgroups((width + 15) / 16, (height + 15) / 16, 1);
+            pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+        }
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |r| {
+            tx.send(r).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv().unwrap().unwrap();
+
+        let data = buffer_slice.get_mapped_range();
+        let result = data.to_vec();
+        drop(data);
+        staging_buffer.unmap();
+
+        self.frames_processed += 1;
+        result
+    }
+
+    /// Tone map packed 10-bit RGB (R | G << 10 | B << 20) to RGBA8
+    ///
+    /// Uses the current `HdrParams`; build them with `hdr::ToneMapper::gpu_params`.
+    pub fn process_hdr(&mut self, rgb10: &[u32], width: u32, height: u32) -> Vec<u8> {
+        let mut params = self.hdr_params;
+        params.width = width;
+        params.height = height;
+        self.queue
+            .write_buffer(&self.hdr_params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let input_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("hdr_input_buffer"),
+                contents: bytemuck::cast_slice(rgb10),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        let output_size = (width * height * 4) as u64;
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hdr_output_buffer"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging_buffer"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hdr_bind_group"),
+            layout: &self.hdr_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.hdr_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.hdr_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+        }
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |r| {
+            tx.send(r).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv().unwrap().unwrap();
+
+        let data = buffer_slice.get_mapped_range();
+        let result = data.to_vec();
+        drop(data);
+        staging_buffer.unmap();
+
+        self.frames_processed += 1;
+        result
+    }
+
+    /// Motion-compensated interpolation of RGB24 frames at phase `t`, returning RGBA8
+    ///
+    /// Vectors come from `frame_interpolation::estimate_motion_fields`; the
+    /// per-pixel trajectory sampling, occlusion handling and blending run here.
+    pub fn interpolate_memc(
+        &mut self,
+        prev: &RgbFrame,
+        next: &RgbFrame,
+        fields: &MotionFields,
+        t: f32,
+        blend_threshold: f32,
+    ) -> Vec<u8> {
+        let width = prev.width;
+        let height = prev.height;
+        let forward = &fields.forward;
+        let params = MemcParams {
+            width,
+            height,
+            blocks_x: forward.blocks_x as u32,
+            blocks_y: forward.blocks_y as u32,
+            block_size: forward.block_size as u32,
+            t,
+            blend_threshold,
+            _pad: 0,
+        };
+        self.queue
+            .write_buffer(&self.memc_params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let pack_rgba = |frame: &RgbFrame| -> Vec<u32> {
+            frame
+                .data
+                .chunks_exact(3)
+                .map(|px| px[0] as u32 | (px[1] as u32) << 8 | (px[2] as u32) << 16 | 0xFF00_0000)
+                .collect()
+        };
+        let pack_vectors = |field: &MotionField| -> Vec<[i32; 4]> {
+            field
+                .vectors
+                .iter()
+                .zip(&field.occluded)
+                .map(|(&(dx, dy), &occluded)| [dx, dy, occluded as i32, 0])
+                .collect()
+        };
+
+        let prev_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("memc_prev_buffer"),
+                contents: bytemuck::cast_slice(&pack_rgba(prev)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        let next_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("memc_next_buffer"),
+                contents: bytemuck::cast_slice(&pack_rgba(next)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        let forward_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("memc_forward_buffer"),
+                contents: bytemuck::cast_slice(&pack_vectors(&fields.forward)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        let backward_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("memc_backward_buffer"),
+                contents: bytemuck::cast_slice(&pack_vectors(&fields.backward)),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        let output_size = (width * height * 4) as u64;
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("memc_output_buffer"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging_buffer"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("memc_bind_group"),
+            layout: &self.memc_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.memc_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: prev_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: next_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: forward_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: backward_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: output_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.memc_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
         }
         encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
         self.queue.submit(Some(encoder.finish()));
@@ -975,4 +1564,10 @@ mod tests {
         let p = HdrParams::default();
         assert_eq!(p.tonemap_mode, 1); // ACES
     }
+
+    #[test]
+    fn test_memc_params_layout() {
+        // Uniform buffers need 16-byte multiples
+        assert_eq!(std::mem::size_of::<MemcParams>() % 16, 0);
+    }
 }
@@ -2,6 +2,8 @@
 //!
 //! Handles conversion between AVCC (length-prefixed) and Annex B (start code) formats.
 //! MKV/MP4 use AVCC format, hardware decoders (NVDEC) expect Annex B.
+//! Encoders (AMF) emit Annex B, so the reverse direction builds avcC/hvcC
+//! records from in-band parameter sets for the MP4 muxer.
 
 /// Annex B start code (4-byte version)
 const ANNEX_B_START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
@@ -179,6 +181,549 @@ pub fn is_annexb(data: &[u8]) -> bool {
     false
 }
 
+/// Split an Annex B stream into NAL units (start codes removed)
+pub fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(s) = start {
+                nals.push(trim_trailing_zeros(&data[s..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        nals.push(trim_trailing_zeros(&data[s..]));
+    }
+
+    nals.retain(|nal| !nal.is_empty());
+    nals
+}
+
+/// Convert Annex B NAL units to AVCC format with 4-byte length prefixes
+pub fn annexb_to_avcc(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() + 16);
+    for nal in split_annexb(data) {
+        result.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        result.extend_from_slice(nal);
+    }
+    result
+}
+
+/// Whether an Annex B access unit contains an IDR (H.264) or IRAP (HEVC) picture
+pub fn annexb_has_idr(data: &[u8], hevc: bool) -> bool {
+    split_annexb(data).iter().any(|nal| {
+        if hevc {
+            (16..=23).contains(&((nal[0] >> 1) & 0x3F))
+        } else {
+            nal[0] & 0x1F == 5
+        }
+    })
+}
+
+/// Build avcC extradata from the SPS/PPS found in Annex B data
+///
+/// Inverse of [`parse_avcc_extradata`]; NAL length size is always 4
+pub fn build_avcc_extradata(annexb: &[u8]) -> Option<Vec<u8>> {
+    let mut sps: Vec<&[u8]> = Vec::new();
+    let mut pps: Vec<&[u8]> = Vec::new();
+    for nal in split_annexb(annexb) {
+        let list = match nal[0] & 0x1F {
+            7 => &mut sps,
+            8 => &mut pps,
+            _ => continue,
+        };
+        if !list.contains(&nal) {
+            list.push(nal);
+        }
+    }
+    let first_sps = sps.first().filter(|s| s.len() >= 4)?;
+    if pps.is_empty() {
+        return None;
+    }
+
+    let mut result = vec![
+        1,
+        first_sps[1], // profile
+        first_sps[2], // profile compat
+        first_sps[3], // level
+        0xFF,         // 0xFC | (nal_length_size - 1)
+        0xE0 | sps.len().min(31) as u8,
+    ];
+    for nal in sps.iter().take(31) {
+        result.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        result.extend_from_slice(nal);
+    }
+    result.push(pps.len().min(255) as u8);
+    for nal in pps.iter().take(255) {
+        result.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        result.extend_from_slice(nal);
+    }
+
+    Some(result)
+}
+
+/// Build hvcC extradata from the VPS/SPS/PPS found in Annex B data
+///
+/// Inverse of [`parse_hvcc_extradata`]; profile/tier/level come from the SPS,
+/// chroma is assumed 4:2:0 and bit depth follows the profile (Main10 = 10-bit)
+pub fn build_hvcc_extradata(annexb: &[u8]) -> Option<Vec<u8>> {
+    let mut arrays: [(u8, Vec<&[u8]>); 3] = [(32, Vec::new()), (33, Vec::new()), (34, Vec::new())];
+    for nal in split_annexb(annexb) {
+        if nal.len() < 3 {
+            continue;
+        }
+        let nal_type = (nal[0] >> 1) & 0x3F;
+        if let Some((_, list)) = arrays.iter_mut().find(|(t, _)| *t == nal_type) {
+            if !list.contains(&nal) {
+                list.push(nal);
+            }
+        }
+    }
+    if arrays.iter().any(|(_, list)| list.is_empty()) {
+        return None;
+    }
+
+    // SPS after the 2-byte NAL header: vps_id(4) max_sub_layers_minus1(3)
+    // temporal_id_nesting(1), then the 12-byte general profile_tier_level
+    let sps = remove_emulation_prevention(&arrays[1].1[0][2..]);
+    if sps.len() < 13 {
+        return None;
+    }
+    let max_sub_layers = ((sps[0] >> 1) & 0x07) + 1;
+    let temporal_id_nested = sps[0] & 0x01;
+    let profile_tier_level = &sps[1..13];
+    let bit_depth_minus8 = if profile_tier_level[0] & 0x1F == 2 {
+        2
+    } else {
+        0
+    };
+
+    let mut result = vec![1];
+    result.extend_from_slice(profile_tier_level);
+    result.extend_from_slice(&[
+        0xF0,
+        0x00,                    // min_spatial_segmentation_idc
+        0xFC,                    // parallelismType
+        0xFC | 1,                // chroma_format_idc 4:2:0
+        0xF8 | bit_depth_minus8, // luma
+        0xF8 | bit_depth_minus8, // chroma
+        0x00,
+        0x00, // avgFrameRate
+        (max_sub_layers << 3) | (temporal_id_nested << 2) | 0x03,
+        arrays.len() as u8,
+    ]);
+    for (nal_type, list) in &arrays {
+        result.push(0x80 | nal_type); // array_completeness
+        result.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        for nal in list {
+            result.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            result.extend_from_slice(nal);
+        }
+    }
+
+    Some(result)
+}
+
+/// Coded picture size (after cropping) from the first H.264 SPS in Annex B data
+pub fn h264_sps_dimensions(annexb: &[u8]) -> Option<(u32, u32)> {
+    let nal = split_annexb(annexb)
+        .into_iter()
+        .find(|nal| nal[0] & 0x1F == 7)?;
+    parse_sps(nal).map(|sps| (sps.width, sps.height))
+}
+
+/// colour_primaries and transfer_characteristics (ISO/IEC 23091-4) from the
+/// VUI of the first H.264 SPS in Annex B data, when the stream signals them
+pub fn h264_sps_colour(annexb: &[u8]) -> Option<(u32, u32)> {
+    let nal = split_annexb(annexb)
+        .into_iter()
+        .find(|nal| nal[0] & 0x1F == 7)?;
+    parse_sps(nal)?.vui.colour
+}
+
+/// Interlacing signalled by an H.264 access unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H264FieldInfo {
+    pub interlaced: bool,
+    pub top_field_first: bool,
+}
+
+impl H264FieldInfo {
+    const PROGRESSIVE: Self = Self {
+        interlaced: false,
+        top_field_first: false,
+    };
+
+    fn fields(top_field_first: bool) -> Self {
+        Self {
+            interlaced: true,
+            top_field_first,
+        }
+    }
+}
+
+/// Reads the interlacing of H.264 access units: the pic_timing SEI's
+/// pic_struct when the SPS enables it, otherwise the first slice's
+/// field_pic/bottom_field flags. The last SPS seen is kept, so feed every
+/// access unit (and the avcC parameter sets first).
+#[derive(Debug, Default)]
+pub struct H264FieldParser {
+    sps: Option<Sps>,
+}
+
+impl H264FieldParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interlacing of an Annex B access unit; `None` when it doesn't say
+    /// (no SPS yet, or an MBAFF frame without pic_struct)
+    pub fn parse(&mut self, annexb: &[u8]) -> Option<H264FieldInfo> {
+        let mut sei = None;
+        for nal in split_annexb(annexb) {
+            match nal[0] & 0x1F {
+                7 => {
+                    if let Some(sps) = parse_sps(nal) {
+                        self.sps = Some(sps);
+                    }
+                }
+                6 => {
+                    if let Some(sps) = self.sps.as_ref().filter(|sps| sps.vui.pic_struct_present) {
+                        let rbsp = remove_emulation_prevention(&nal[1..]);
+                        sei = sei.or_else(|| sei_pic_struct(&rbsp, sps));
+                    }
+                }
+                1 | 5 => {
+                    let sps = self.sps.as_ref()?;
+                    return sei.or_else(|| slice_field_info(&nal[1..], sps));
+                }
+                _ => {}
+            }
+        }
+        sei
+    }
+}
+
+/// The SPS fields the helpers above need
+#[derive(Debug, Clone)]
+struct Sps {
+    width: u32,
+    height: u32,
+    frame_mbs_only: bool,
+    separate_colour_plane: bool,
+    log2_max_frame_num: u32,
+    vui: Vui,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Vui {
+    /// colour_primaries, transfer_characteristics
+    colour: Option<(u32, u32)>,
+    /// cpb_removal_delay / dpb_output_delay sizes in pic_timing SEI, when
+    /// the VUI carries HRD parameters
+    hrd_delay_bits: Option<(u32, u32)>,
+    pic_struct_present: bool,
+}
+
+fn parse_sps(nal: &[u8]) -> Option<Sps> {
+    let rbsp = remove_emulation_prevention(nal.get(1..)?);
+    let mut bits = BitReader {
+        data: &rbsp,
+        pos: 0,
+    };
+
+    let profile_idc = bits.read(8)?;
+    bits.read(16)?; // constraint flags, level_idc
+    bits.read_ue()?; // seq_parameter_set_id
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = bits.read_ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = bits.read(1)? == 1;
+        }
+        bits.read_ue()?; // bit_depth_luma_minus8
+        bits.read_ue()?; // bit_depth_chroma_minus8
+        bits.read(1)?; // qpprime_y_zero_transform_bypass_flag
+        if bits.read(1)? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if bits.read(1)? == 1 {
+                    skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    let log2_max_frame_num = bits.read_ue()? + 4;
+    match bits.read_ue()? {
+        0 => {
+            bits.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            bits.read(1)?; // delta_pic_order_always_zero_flag
+            bits.read_se()?;
+            bits.read_se()?;
+            for _ in 0..bits.read_ue()? {
+                bits.read_se()?;
+            }
+        }
+        _ => {}
+    }
+    bits.read_ue()?; // max_num_ref_frames
+    bits.read(1)?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = bits.read_ue()? + 1;
+    let height_map_units = bits.read_ue()? + 1;
+    let frame_mbs_only = bits.read(1)?;
+    if frame_mbs_only == 0 {
+        bits.read(1)?; // mb_adaptive_frame_field_flag
+    }
+    bits.read(1)?; // direct_8x8_inference_flag
+    let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+    if bits.read(1)? == 1 {
+        left = bits.read_ue()?;
+        right = bits.read_ue()?;
+        top = bits.read_ue()?;
+        bottom = bits.read_ue()?;
+    }
+
+    // Crop units per ISO 14496-10 table 6-1
+    let (sub_width, sub_height) = match chroma_format_idc {
+        0 | 3 => (1, 1),
+        1 => (2, 2),
+        _ => (2, 1),
+    };
+    let crop_x = sub_width;
+    let crop_y = sub_height * (2 - frame_mbs_only);
+    let width = (width_mbs * 16).checked_sub(crop_x * (left + right))?;
+    let height =
+        ((2 - frame_mbs_only) * height_map_units * 16).checked_sub(crop_y * (top + bottom))?;
+
+    // A truncated VUI still leaves the picture size usable
+    let vui = match bits.read(1) {
+        Some(1) => parse_vui(&mut bits).unwrap_or_default(),
+        _ => Vui::default(),
+    };
+    Some(Sps {
+        width,
+        height,
+        frame_mbs_only: frame_mbs_only == 1,
+        separate_colour_plane,
+        log2_max_frame_num,
+        vui,
+    })
+}
+
+fn parse_vui(bits: &mut BitReader) -> Option<Vui> {
+    if bits.read(1)? == 1 && bits.read(8)? == 255 {
+        bits.read(32)?; // Extended_SAR sar_width, sar_height
+    }
+    if bits.read(1)? == 1 {
+        bits.read(1)?; // overscan_appropriate_flag
+    }
+    let mut colour = None;
+    if bits.read(1)? == 1 {
+        bits.read(4)?; // video_format, video_full_range_flag
+        if bits.read(1)? == 1 {
+            colour = Some((bits.read(8)?, bits.read(8)?));
+            bits.read(8)?; // matrix_coefficients
+        }
+    }
+    if bits.read(1)? == 1 {
+        bits.read_ue()?; // chroma_sample_loc_type_top_field
+        bits.read_ue()?; // chroma_sample_loc_type_bottom_field
+    }
+    if bits.read(1)? == 1 {
+        bits.read(32)?; // num_units_in_tick
+        bits.read(32)?; // time_scale
+        bits.read(1)?; // fixed_frame_rate_flag
+    }
+    let nal_hrd = match bits.read(1)? {
+        1 => Some(parse_hrd(bits)?),
+        _ => None,
+    };
+    let vcl_hrd = match bits.read(1)? {
+        1 => Some(parse_hrd(bits)?),
+        _ => None,
+    };
+    if nal_hrd.is_some() || vcl_hrd.is_some() {
+        bits.read(1)?; // low_delay_hrd_flag
+    }
+    Some(Vui {
+        colour,
+        hrd_delay_bits: nal_hrd.or(vcl_hrd),
+        pic_struct_present: bits.read(1)? == 1,
+    })
+}
+
+/// cpb_removal_delay and dpb_output_delay lengths from hrd_parameters()
+fn parse_hrd(bits: &mut BitReader) -> Option<(u32, u32)> {
+    let cpb_count = bits.read_ue()? + 1;
+    if cpb_count > 32 {
+        return None;
+    }
+    bits.read(8)?; // bit_rate_scale, cpb_size_scale
+    for _ in 0..cpb_count {
+        bits.read_ue()?; // bit_rate_value_minus1
+        bits.read_ue()?; // cpb_size_value_minus1
+        bits.read(1)?; // cbr_flag
+    }
+    bits.read(5)?; // initial_cpb_removal_delay_length_minus1
+    let cpb_removal_delay = bits.read(5)? + 1;
+    let dpb_output_delay = bits.read(5)? + 1;
+    bits.read(5)?; // time_offset_length
+    Some((cpb_removal_delay, dpb_output_delay))
+}
+
+/// Interlacing from the pic_struct of a pic_timing message in an SEI RBSP
+fn sei_pic_struct(mut rbsp: &[u8], sps: &Sps) -> Option<H264FieldInfo> {
+    let read_value = |data: &mut &[u8]| -> Option<usize> {
+        let mut value = 0;
+        loop {
+            let (&byte, rest) = data.split_first()?;
+            *data = rest;
+            value += byte as usize;
+            if byte != 0xFF {
+                return Some(value);
+            }
+        }
+    };
+    // Messages up to the rbsp_trailing_bits byte
+    while rbsp.len() > 1 {
+        let payload_type = read_value(&mut rbsp)?;
+        let payload_size = read_value(&mut rbsp)?;
+        if payload_size > rbsp.len() {
+            return None;
+        }
+        let (payload, rest) = rbsp.split_at(payload_size);
+        if payload_type == 1 {
+            let mut bits = BitReader {
+                data: payload,
+                pos: 0,
+            };
+            if let Some((cpb, dpb)) = sps.vui.hrd_delay_bits {
+                bits.read(cpb)?;
+                bits.read(dpb)?;
+            }
+            // Table D-1: frames (incl. doubled/tripled), fields and field pairs
+            return match bits.read(4)? {
+                0 | 7 | 8 => Some(H264FieldInfo::PROGRESSIVE),
+                1 | 3 | 5 => Some(H264FieldInfo::fields(true)),
+                2 | 4 | 6 => Some(H264FieldInfo::fields(false)),
+                _ => None,
+            };
+        }
+        rbsp = rest;
+    }
+    None
+}
+
+/// Interlacing from a slice header; field pictures say which field they
+/// are, and the first in the access unit leads
+fn slice_field_info(payload: &[u8], sps: &Sps) -> Option<H264FieldInfo> {
+    if sps.frame_mbs_only {
+        return Some(H264FieldInfo::PROGRESSIVE);
+    }
+    let rbsp = remove_emulation_prevention(payload);
+    let mut bits = BitReader {
+        data: &rbsp,
+        pos: 0,
+    };
+    bits.read_ue()?; // first_mb_in_slice
+    bits.read_ue()?; // slice_type
+    bits.read_ue()?; // pic_parameter_set_id
+    if sps.separate_colour_plane {
+        bits.read(2)?; // colour_plane_id
+    }
+    bits.read(sps.log2_max_frame_num)?; // frame_num
+    if bits.read(1)? == 0 {
+        // Frame picture (MBAFF): the field order isn't in the slice header
+        return None;
+    }
+    let bottom_field = bits.read(1)? == 1;
+    Some(H264FieldInfo::fields(!bottom_field))
+}
+
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> Option<()> {
+    let (mut last, mut next) = (8i32, 8i32);
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + bits.read_se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+/// MSB-first reader with Exp-Golomb codes, for parameter set RBSPs
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..count {
+            let byte = *self.data.get(self.pos / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.pos % 8)) & 1) as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.read(1)? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.read(zeros)?)
+    }
+
+    fn read_se(&mut self) -> Option<i32> {
+        let value = self.read_ue()?;
+        Some(if value & 1 == 1 {
+            value.div_ceil(2) as i32
+        } else {
+            -((value / 2) as i32)
+        })
+    }
+}
+
+/// Strip emulation prevention bytes (00 00 03 -> 00 00) from a NAL payload
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        result.push(b);
+    }
+    result
+}
+
+fn trim_trailing_zeros(nal: &[u8]) -> &[u8] {
+    let end = nal.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &nal[..end]
+}
+
 /// Read big-endian unsigned integer of variable size (1-4 bytes)
 fn read_be_uint(data: &[u8], size: usize) -> usize {
     let mut val = 0usize;
@@ -219,4 +764,243 @@ mod tests {
         let annexb_data2 = vec![0xAB, 0xCD, 0xEF, 0x00, 0x00, 0x00, 0x01, 0x67];
         assert!(is_annexb(&annexb_data2));
     }
+
+    #[test]
+    fn test_annexb_avcc_round_trip() {
+        let annexb = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x1f, 0xac, // SPS
+            0x00, 0x00, 0x01, 0x68, 0xee, 0x3c, 0x80, // PPS
+            0x00, 0x00, 0x00, 0x01, 0x65, 0x88, 0x84, // IDR slice
+        ];
+        assert_eq!(split_annexb(&annexb).len(), 3);
+        assert!(annexb_has_idr(&annexb, false));
+
+        let avcc = annexb_to_avcc(&annexb);
+        assert_eq!(&avcc[0..4], &[0, 0, 0, 5]);
+        assert_eq!(
+            split_annexb(&avcc_to_annexb(&avcc, 4)),
+            split_annexb(&annexb)
+        );
+
+        let extradata = build_avcc_extradata(&annexb).unwrap();
+        assert_eq!(&extradata[1..4], &[0x64, 0x00, 0x1f]);
+        let (params, nal_length_size) = parse_avcc_extradata(&extradata).unwrap();
+        assert_eq!(nal_length_size, 4);
+        assert_eq!(split_annexb(&params), split_annexb(&annexb)[..2].to_vec());
+    }
+
+    #[test]
+    fn test_h264_sps_dimensions() {
+        // x264 High profile 1080p: 1088 coded lines cropped by 8
+        let sps = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27,
+            0xe5, 0xc0, 0x44, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x3c,
+            0x60, 0xc6, 0x58,
+        ];
+        assert_eq!(h264_sps_dimensions(&sps), Some((1920, 1080)));
+        assert_eq!(h264_sps_dimensions(&[0, 0, 0, 1, 0x67, 0x64]), None);
+    }
+
+    /// Bit writer for hand-built parameter sets and slices
+    #[derive(Default)]
+    struct Bits {
+        bits: Vec<bool>,
+    }
+
+    impl Bits {
+        fn u(mut self, count: u32, value: u32) -> Self {
+            for i in (0..count).rev() {
+                self.bits.push(value >> i & 1 == 1);
+            }
+            self
+        }
+
+        fn ue(self, value: u32) -> Self {
+            let code = value + 1;
+            let len = 32 - code.leading_zeros();
+            self.u(len - 1, 0).u(len, code)
+        }
+
+        /// Annex B NAL unit with rbsp trailing bits and emulation prevention
+        fn nal(self, header: u8) -> Vec<u8> {
+            let mut bits = self.u(1, 1).bits;
+            while !bits.len().is_multiple_of(8) {
+                bits.push(false);
+            }
+            let mut nal = vec![0, 0, 0, 1, header];
+            let mut zeros = 0;
+            for chunk in bits.chunks(8) {
+                let byte = chunk.iter().fold(0u8, |acc, &b| acc << 1 | b as u8);
+                if zeros >= 2 && byte <= 3 {
+                    nal.push(3);
+                    zeros = 0;
+                }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                nal.push(byte);
+            }
+            nal
+        }
+    }
+
+    /// Main profile 1920x1080 SPS, field/MBAFF coded unless `frame_mbs_only`,
+    /// with NAL HRD delays of 24 bits and pic_struct when `pic_struct`
+    /// Main profile 1920x1080 SPS up to vui_parameters_present_flag
+    fn sps_header(frame_mbs_only: bool) -> Bits {
+        let bits = Bits::default()
+            .u(8, 77)
+            .u(16, 0x0028)
+            .ue(0)
+            .ue(0) // log2_max_frame_num_minus4
+            .ue(0)
+            .ue(0)
+            .ue(4)
+            .u(1, 0)
+            .ue(119);
+        if frame_mbs_only {
+            bits.ue(67).u(1, 1).u(1, 1).u(1, 1).ue(0).ue(0).ue(0).ue(4)
+        } else {
+            bits.ue(33)
+                .u(1, 0)
+                .u(1, 1)
+                .u(1, 1)
+                .u(1, 1)
+                .ue(0)
+                .ue(0)
+                .ue(0)
+                .ue(2)
+        }
+    }
+
+    fn sps(frame_mbs_only: bool, pic_struct: bool) -> Vec<u8> {
+        let bits = sps_header(frame_mbs_only);
+        let bits = if pic_struct {
+            bits.u(1, 1)
+                .u(5, 0) // no aspect, overscan, signal type, chroma loc, timing
+                .u(1, 1) // nal_hrd_parameters_present_flag
+                .ue(0)
+                .u(8, 0)
+                .ue(1000)
+                .ue(1000)
+                .u(1, 0)
+                .u(5, 23)
+                .u(5, 23)
+                .u(5, 23)
+                .u(5, 24)
+                .u(1, 0) // vcl_hrd_parameters_present_flag
+                .u(1, 0) // low_delay_hrd_flag
+                .u(1, 1) // pic_struct_present_flag
+                .u(1, 0)
+        } else {
+            bits.u(1, 0)
+        };
+        bits.nal(0x67)
+    }
+
+    /// SEI with a pic_timing message (payloadType 1, 7 bytes)
+    fn pic_timing(pic_struct: u32) -> Vec<u8> {
+        Bits::default()
+            .u(8, 1)
+            .u(8, 7)
+            .u(24, 5) // cpb_removal_delay
+            .u(24, 2) // dpb_output_delay
+            .u(4, pic_struct)
+            .u(4, 0)
+            .nal(0x06)
+    }
+
+    fn slice(field: Option<bool>) -> Vec<u8> {
+        let bits = Bits::default().ue(0).ue(7).ue(0).u(4, 0);
+        let bits = match field {
+            None => bits.u(1, 0),
+            Some(bottom) => bits.u(1, 1).u(1, bottom as u32),
+        };
+        bits.ue(0).nal(0x65)
+    }
+
+    #[test]
+    fn test_h264_field_parser() {
+        assert_eq!(h264_sps_dimensions(&sps(false, true)), Some((1920, 1080)));
+        assert_eq!(h264_sps_dimensions(&sps(true, false)), Some((1920, 1080)));
+
+        let access_unit = |parts: &[Vec<u8>]| parts.concat();
+        let top = H264FieldInfo::fields(true);
+        let bottom = H264FieldInfo::fields(false);
+
+        // Nothing to go on before an SPS
+        let mut parser = H264FieldParser::new();
+        assert_eq!(parser.parse(&slice(Some(false))), None);
+
+        // Field pictures: the first field in the access unit leads
+        assert_eq!(
+            parser.parse(&access_unit(&[sps(false, false), slice(Some(true))])),
+            Some(bottom)
+        );
+        assert_eq!(parser.parse(&slice(Some(false))), Some(top));
+        assert_eq!(parser.parse(&slice(None)), None);
+
+        // pic_struct wins over the slice flags once the SPS enables it
+        parser.parse(&sps(false, true));
+        assert_eq!(
+            parser.parse(&access_unit(&[pic_timing(4), slice(None)])),
+            Some(bottom)
+        );
+        assert_eq!(
+            parser.parse(&access_unit(&[pic_timing(3), slice(Some(true))])),
+            Some(top)
+        );
+        assert_eq!(
+            parser.parse(&access_unit(&[pic_timing(0), slice(None)])),
+            Some(H264FieldInfo::PROGRESSIVE)
+        );
+
+        // Frame-only streams are progressive
+        parser.parse(&sps(true, false));
+        assert_eq!(parser.parse(&slice(None)), Some(H264FieldInfo::PROGRESSIVE));
+    }
+
+    #[test]
+    fn test_h264_sps_colour() {
+        // BT.2020 primaries, PQ transfer, BT.2020 NCL matrix
+        let hdr10 = sps_header(true)
+            .u(1, 1)
+            .u(2, 0) // no aspect ratio or overscan info
+            .u(1, 1) // video_signal_type_present_flag
+            .u(3, 5) // unspecified video_format
+            .u(1, 0) // limited range
+            .u(1, 1) // colour_description_present_flag
+            .u(8, 9)
+            .u(8, 16)
+            .u(8, 9)
+            .u(6, 0) // chroma loc, timing, HRD, pic_struct, restrictions
+            .nal(0x67);
+        assert_eq!(h264_sps_colour(&hdr10), Some((9, 16)));
+        assert_eq!(h264_sps_dimensions(&hdr10), Some((1920, 1080)));
+
+        // No colour description, or no VUI at all
+        assert_eq!(h264_sps_colour(&sps(true, true)), None);
+        assert_eq!(h264_sps_colour(&sps(true, false)), None);
+    }
+
+    #[test]
+    fn test_build_hvcc_extradata() {
+        let vps = [0x40, 0x01, 0x0c, 0x01];
+        let sps = [
+            0x42, 0x01, 0x01, 0x02, 0x20, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x99, 0xa0,
+        ];
+        let pps = [0x44, 0x01, 0xc1, 0x72];
+        let mut annexb = Vec::new();
+        for nal in [&vps[..], &sps[..], &pps[..]] {
+            annexb.extend_from_slice(&[0, 0, 0, 1]);
+            annexb.extend_from_slice(nal);
+        }
+
+        let extradata = build_hvcc_extradata(&annexb).unwrap();
+        assert_eq!(extradata[1], 0x02); // Main10 profile_idc
+        assert_eq!(extradata[12], 0x99); // level 5.1
+        assert_eq!(extradata[17], 0xFA); // 10-bit luma
+        let (params, nal_length_size) = parse_hvcc_extradata(&extradata).unwrap();
+        assert_eq!(nal_length_size, 4);
+        assert_eq!(params, annexb);
+    }
 }
@@ -0,0 +1,910 @@
+//! # HDR - Transfer Functions, Tone Mapping and Gamut Mapping
+//!
+//! CPU reference for HDR10 (PQ) and HLG → SDR conversion. The compute shader
+//! in `gpu_video_processor` implements the same math; this module is what the
+//! tests run against and what stills / software decode fall back to.
+//!
+//! ```text
+//! R'G'B' (BT.2020) ─► EOTF (PQ / HLG+OOTF) ─► nits ─► tone curve ─►
+//!     BT.2020→BT.709 ─► gamut map ─► sRGB encode ─► 8-bit
+//! ```
+//!
+//! Source peak comes from (in order): dynamic per-frame detection, MaxCLL,
+//! mastering display max luminance, then a 1000 nit default.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::gpu_video_processor::{HdrParams, ToneMapMode};
+use crate::h264_utils::{h264_sps_colour, is_annexb, parse_avcc_extradata};
+use crate::lav::{
+    ColorSpace, HdrMetadata, HdrTransfer, MasteringDisplay, VideoCodec, VideoStreamInfo,
+};
+use crate::mkv::VideoTrack;
+use crate::pixel_convert::{self, f16_to_f32, PixelConverter, PixelFormat, VideoFrame};
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+/// BT.2408 reference (diffuse) white, mapped to SDR 1.0
+pub const SDR_WHITE_NITS: f32 = 203.0;
+/// PQ absolute peak
+pub const PQ_MAX_NITS: f32 = 10000.0;
+/// Nominal peak assumed for HLG and for PQ without metadata
+pub const DEFAULT_PEAK_NITS: f32 = 1000.0;
+
+// SMPTE ST 2084 constants
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+// ARIB STD-B67 constants
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 0.284_668_92;
+const HLG_C: f32 = 0.559_910_7;
+
+/// BT.2020 luminance coefficients
+const BT2020_LUMA: [f32; 3] = [0.2627, 0.6780, 0.0593];
+/// BT.709 luminance coefficients
+const BT709_LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Linear-light BT.2020 → BT.709 primaries (D65 both sides)
+pub const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+// ============================================================================
+// Transfer Functions
+// ============================================================================
+
+/// PQ signal (0..1) → absolute luminance in nits
+pub fn pq_eotf(signal: f32) -> f32 {
+    let e = signal.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    let num = (e - PQ_C1).max(0.0);
+    let den = PQ_C2 - PQ_C3 * e;
+    PQ_MAX_NITS * (num / den).powf(1.0 / PQ_M1)
+}
+
+/// Absolute luminance in nits → PQ signal (0..1)
+pub fn pq_inverse_eotf(nits: f32) -> f32 {
+    let y = (nits / PQ_MAX_NITS).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// HLG signal (0..1) → relative scene light (0..1)
+pub fn hlg_inverse_oetf(signal: f32) -> f32 {
+    let e = signal.clamp(0.0, 1.0);
+    if e <= 0.5 {
+        e * e / 3.0
+    } else {
+        (((e - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+/// HLG OOTF: scene light → display light in nits for a display of `peak_nits`
+pub fn hlg_ootf(scene: [f32; 3], peak_nits: f32) -> [f32; 3] {
+    let gamma = 1.2 + 0.42 * (peak_nits / 1000.0).log10();
+    let y = luminance(scene, BT2020_LUMA);
+    let scale = if y > 0.0 {
+        peak_nits * y.powf(gamma - 1.0)
+    } else {
+        0.0
+    };
+    [scene[0] * scale, scene[1] * scale, scene[2] * scale]
+}
+
+/// Linear (0..1) → sRGB encoded (0..1)
+pub fn srgb_encode(linear: f32) -> f32 {
+    let v = linear.clamp(0.0, 1.0);
+    if v < 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline]
+fn luminance(rgb: [f32; 3], coeffs: [f32; 3]) -> f32 {
+    rgb[0] * coeffs[0] + rgb[1] * coeffs[1] + rgb[2] * coeffs[2]
+}
+
+#[inline]
+fn mat3_mul(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+// ============================================================================
+// Source Detection
+// ============================================================================
+
+/// Everything tone mapping needs to know about an HDR source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HdrSourceInfo {
+    pub transfer: HdrTransfer,
+    /// Content uses BT.2020 primaries (needs gamut conversion)
+    pub bt2020: bool,
+    pub mastering_display: Option<MasteringDisplay>,
+    /// MaxCLL in nits (0 = unknown)
+    pub max_cll: u16,
+    /// MaxFALL in nits (0 = unknown)
+    pub max_fall: u16,
+}
+
+impl HdrSourceInfo {
+    pub fn from_metadata(meta: &HdrMetadata, bt2020: bool) -> Option<Self> {
+        if meta.transfer == HdrTransfer::Sdr {
+            return None;
+        }
+        Some(Self {
+            transfer: meta.transfer,
+            bt2020,
+            mastering_display: meta.mastering_display.clone(),
+            max_cll: meta.max_cll,
+            max_fall: meta.max_fall,
+        })
+    }
+
+    /// Detect HDR from demuxer stream info
+    ///
+    /// Uses explicit metadata when the demuxer found it, otherwise the
+    /// transfer characteristics in the H.264 VUI. Streams that don't signal
+    /// PQ or HLG stay SDR whatever their primaries and bit depth.
+    pub fn from_stream(info: &VideoStreamInfo) -> Option<Self> {
+        let bt2020 = info.color_space == ColorSpace::Bt2020;
+        if let Some(meta) = &info.hdr {
+            return Self::from_metadata(meta, bt2020);
+        }
+        let (primaries, transfer) = match info.codec {
+            VideoCodec::H264 if is_annexb(&info.codec_private) => {
+                h264_sps_colour(&info.codec_private)?
+            }
+            VideoCodec::H264 => {
+                let (annexb, _) = parse_avcc_extradata(&info.codec_private)?;
+                h264_sps_colour(&annexb)?
+            }
+            _ => return None,
+        };
+        let transfer = match transfer {
+            16 => HdrTransfer::Pq,
+            18 => HdrTransfer::Hlg,
+            _ => return None,
+        };
+        Some(Self {
+            transfer,
+            bt2020: bt2020 || primaries == 9,
+            mastering_display: None,
+            max_cll: 0,
+            max_fall: 0,
+        })
+    }
+
+    /// Detect HDR from a Matroska track's Colour element
+    ///
+    /// Transfer characteristics follow ISO/IEC 23091-4: 16 = PQ, 18 = HLG.
+    pub fn from_mkv_track(track: &VideoTrack) -> Option<Self> {
+        let color = track.color_space.as_ref();
+        let transfer = match color.and_then(|c| c.transfer_characteristics) {
+            Some(16) => HdrTransfer::Pq,
+            Some(18) => HdrTransfer::Hlg,
+            _ => match &track.hdr_info {
+                Some(info) if info.is_hdr => match info.hdr_format.as_deref() {
+                    Some(f) if f.eq_ignore_ascii_case("hlg") => HdrTransfer::Hlg,
+                    _ => HdrTransfer::Pq,
+                },
+                _ => return None,
+            },
+        };
+        let bt2020 = color.and_then(|c| c.primaries).is_none_or(|p| p == 9);
+        let (max_cll, max_fall) = track
+            .hdr_info
+            .as_ref()
+            .map(|h| {
+                (
+                    h.max_cll.unwrap_or(0).min(u16::MAX as u32) as u16,
+                    h.max_fall.unwrap_or(0).min(u16::MAX as u32) as u16,
+                )
+            })
+            .unwrap_or((0, 0));
+
+        Some(Self {
+            transfer,
+            bt2020,
+            mastering_display: None,
+            max_cll,
+            max_fall,
+        })
+    }
+
+    /// Probe the first video stream of a non-Matroska file (MP4, TS, AVI)
+    pub fn probe_file(path: &Path) -> Option<Self> {
+        let splitter = match crate::lav::LavSplitter::open(path) {
+            Ok(splitter) => splitter,
+            Err(e) => {
+                tracing::debug!("HDR probe of {:?} failed: {:?}", path, e);
+                return None;
+            }
+        };
+        splitter
+            .info()
+            .video_streams
+            .first()
+            .and_then(Self::from_stream)
+    }
+
+    /// Best static estimate of the brightest pixel in the content
+    pub fn content_peak_nits(&self) -> f32 {
+        if self.transfer == HdrTransfer::Hlg {
+            return DEFAULT_PEAK_NITS;
+        }
+        if self.max_cll > 0 {
+            return self.max_cll as f32;
+        }
+        match &self.mastering_display {
+            Some(md) if md.luminance_max > 0.0 => md.luminance_max,
+            _ => DEFAULT_PEAK_NITS,
+        }
+    }
+
+    /// Mastering display black level in nits
+    pub fn content_black_nits(&self) -> f32 {
+        self.mastering_display
+            .as_ref()
+            .map(|md| md.luminance_min.max(0.0))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Parse an HEVC/AVC mastering display colour volume SEI payload (type 137)
+pub fn parse_mastering_display_sei(payload: &[u8]) -> Option<MasteringDisplay> {
+    if payload.len() < 24 {
+        return None;
+    }
+    let u16_at = |i: usize| u16::from_be_bytes([payload[i], payload[i + 1]]) as f32;
+    let u32_at = |i: usize| {
+        u32::from_be_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]]) as f32
+    };
+
+    // SEI order is G, B, R in 0.00002 units
+    let g = [u16_at(0) * 0.00002, u16_at(2) * 0.00002];
+    let b = [u16_at(4) * 0.00002, u16_at(6) * 0.00002];
+    let r = [u16_at(8) * 0.00002, u16_at(10) * 0.00002];
+
+    Some(MasteringDisplay {
+        primaries: [r, g, b],
+        white_point: [u16_at(12) * 0.00002, u16_at(14) * 0.00002],
+        luminance_max: u32_at(16) * 0.0001,
+        luminance_min: u32_at(20) * 0.0001,
+    })
+}
+
+/// Parse a content light level SEI payload (type 144) into (MaxCLL, MaxFALL)
+pub fn parse_content_light_level_sei(payload: &[u8]) -> Option<(u16, u16)> {
+    if payload.len() < 4 {
+        return None;
+    }
+    Some((
+        u16::from_be_bytes([payload[0], payload[1]]),
+        u16::from_be_bytes([payload[2], payload[3]]),
+    ))
+}
+
+// ============================================================================
+// Dynamic Peak Detection
+// ============================================================================
+
+/// Per-frame peak luminance tracker with temporal smoothing
+///
+/// Peaks are smoothed so brightness doesn't pump, but the filter resets on
+/// scene changes (large jumps in average luminance) so cuts adapt instantly.
+#[derive(Debug, Clone)]
+pub struct PeakDetector {
+    /// Smoothing factor per frame (0 = frozen, 1 = no smoothing)
+    pub smoothing: f32,
+    /// Relative change in frame average (in PQ space) treated as a scene cut
+    pub scene_threshold: f32,
+    /// Percentile used as the frame peak (ignores specular outliers)
+    pub percentile: f32,
+    peak_nits: Option<f32>,
+    avg_pq: Option<f32>,
+}
+
+impl Default for PeakDetector {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.05,
+            scene_threshold: 0.15,
+            percentile: 0.999,
+            peak_nits: None,
+            avg_pq: None,
+        }
+    }
+}
+
+impl PeakDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one frame of per-pixel luminance (nits); returns the smoothed peak
+    pub fn update(&mut self, luma_nits: &[f32]) -> f32 {
+        if luma_nits.is_empty() {
+            return self.peak_nits.unwrap_or(DEFAULT_PEAK_NITS);
+        }
+
+        // Subsample large frames - percentile estimate doesn't need every pixel
+        let step = (luma_nits.len() / 65536).max(1);
+        let mut samples: Vec<f32> = luma_nits.iter().step_by(step).copied().collect();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let idx = ((samples.len() - 1) as f32 * self.percentile) as usize;
+        let frame_peak = samples[idx].max(SDR_WHITE_NITS);
+        let frame_avg_pq =
+            samples.iter().map(|&l| pq_inverse_eotf(l)).sum::<f32>() / samples.len() as f32;
+
+        let scene_cut = match self.avg_pq {
+            Some(prev) => (frame_avg_pq - prev).abs() > self.scene_threshold,
+            None => true,
+        };
+
+        let peak = match self.peak_nits {
+            Some(prev) if !scene_cut => prev + (frame_peak - prev) * self.smoothing,
+            _ => frame_peak,
+        };
+        let avg = match self.avg_pq {
+            Some(prev) if !scene_cut => prev + (frame_avg_pq - prev) * self.smoothing,
+            _ => frame_avg_pq,
+        };
+
+        self.peak_nits = Some(peak);
+        self.avg_pq = Some(avg);
+        peak
+    }
+
+    pub fn peak_nits(&self) -> Option<f32> {
+        self.peak_nits
+    }
+
+    pub fn reset(&mut self) {
+        self.peak_nits = None;
+        self.avg_pq = None;
+    }
+}
+
+// ============================================================================
+// Tone Mapping
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GamutMapping {
+    /// Hard clip out-of-gamut channels
+    Clip,
+    /// Desaturate toward luminance until in gamut (keeps hue)
+    #[default]
+    Desaturate,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapConfig {
+    pub mode: ToneMapMode,
+    /// Target display peak in nits (SDR 1.0 maps here)
+    pub target_peak_nits: f32,
+    pub gamut: GamutMapping,
+    /// Track per-frame peaks instead of trusting static metadata
+    pub dynamic_peak: bool,
+    /// Exposure multiplier applied before the curve
+    pub exposure: f32,
+}
+
+impl Default for ToneMapConfig {
+    fn default() -> Self {
+        Self {
+            mode: ToneMapMode::Bt2390,
+            target_peak_nits: SDR_WHITE_NITS,
+            gamut: GamutMapping::Desaturate,
+            dynamic_peak: true,
+            exposure: 1.0,
+        }
+    }
+}
+
+/// Map scene luminance `nits` to display-relative (0..1) for a target peak
+pub fn tone_map_luminance(
+    mode: ToneMapMode,
+    nits: f32,
+    src_peak: f32,
+    src_black: f32,
+    target_peak: f32,
+) -> f32 {
+    let src_peak = src_peak.max(target_peak);
+    let x = nits / target_peak;
+    let w = src_peak / target_peak;
+    let out = match mode {
+        ToneMapMode::Bt2390 => return bt2390_eetf(nits, src_peak, src_black, target_peak),
+        ToneMapMode::Reinhard => x * (1.0 + x / (w * w)) / (1.0 + x),
+        ToneMapMode::Hable => hable(x) / hable(w),
+        ToneMapMode::Mobius => mobius(x, w),
+        ToneMapMode::Aces => {
+            let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+            let s = x / w.max(1.0) * 2.0;
+            (s * (a * s + b)) / (s * (c * s + d) + e)
+        }
+    };
+    out.clamp(0.0, 1.0)
+}
+
+/// BT.2390 EETF (hermite knee in PQ space)
+fn bt2390_eetf(nits: f32, src_peak: f32, src_black: f32, target_peak: f32) -> f32 {
+    let src_lo = pq_inverse_eotf(src_black);
+    let src_hi = pq_inverse_eotf(src_peak);
+    let range = (src_hi - src_lo).max(1e-6);
+
+    let e1 = ((pq_inverse_eotf(nits) - src_lo) / range).clamp(0.0, 1.0);
+    let max_lum = ((pq_inverse_eotf(target_peak) - src_lo) / range).clamp(0.0, 1.0);
+    let ks = 1.5 * max_lum - 0.5;
+
+    let e2 = if e1 < ks || ks >= 1.0 {
+        e1
+    } else {
+        let t = (e1 - ks) / (1.0 - ks);
+        let t2 = t * t;
+        let t3 = t2 * t;
+        (2.0 * t3 - 3.0 * t2 + 1.0) * ks
+            + (t3 - 2.0 * t2 + t) * (1.0 - ks)
+            + (-2.0 * t3 + 3.0 * t2) * max_lum
+    };
+
+    (pq_eotf(e2 * range + src_lo) / target_peak).clamp(0.0, 1.0)
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+/// Mobius: linear below `j`, smooth roll-off to the source peak above it
+fn mobius(x: f32, peak: f32) -> f32 {
+    let j = 0.3f32;
+    if x <= j || peak <= 1.0 {
+        return x.min(1.0);
+    }
+    let a = -j * j * (peak - 1.0) / (j * j - 2.0 * j + peak);
+    let b = (j * j - 2.0 * j * peak + peak) / (peak - 1.0).max(1e-6);
+    (b * b + 2.0 * b * j + j * j) / (b - a) * (x + a) / (x + b)
+}
+
+/// Bring a linear BT.709 value back into [0, 1]
+fn gamut_map(rgb: [f32; 3], mode: GamutMapping) -> [f32; 3] {
+    match mode {
+        GamutMapping::Clip => [
+            rgb[0].clamp(0.0, 1.0),
+            rgb[1].clamp(0.0, 1.0),
+            rgb[2].clamp(0.0, 1.0),
+        ],
+        GamutMapping::Desaturate => {
+            let y = luminance(rgb, BT709_LUMA).clamp(0.0, 1.0);
+            let lo = rgb[0].min(rgb[1]).min(rgb[2]);
+            let hi = rgb[0].max(rgb[1]).max(rgb[2]);
+            let mut t = 1.0f32;
+            if lo < 0.0 {
+                t = t.min(y / (y - lo));
+            }
+            if hi > 1.0 {
+                t = t.min((1.0 - y) / (hi - y));
+            }
+            let t = t.clamp(0.0, 1.0);
+            [
+                (y + (rgb[0] - y) * t).clamp(0.0, 1.0),
+                (y + (rgb[1] - y) * t).clamp(0.0, 1.0),
+                (y + (rgb[2] - y) * t).clamp(0.0, 1.0),
+            ]
+        }
+    }
+}
+
+/// CPU HDR → SDR tone mapper
+pub struct ToneMapper {
+    pub source: HdrSourceInfo,
+    pub config: ToneMapConfig,
+    peak: PeakDetector,
+}
+
+impl ToneMapper {
+    pub fn new(source: HdrSourceInfo, config: ToneMapConfig) -> Self {
+        Self {
+            source,
+            config,
+            peak: PeakDetector::new(),
+        }
+    }
+
+    /// Peak currently used for the curve (dynamic if available, else static)
+    pub fn source_peak_nits(&self) -> f32 {
+        let static_peak = self.source.content_peak_nits();
+        match (self.config.dynamic_peak, self.peak.peak_nits()) {
+            // Never exceed what the metadata says is possible
+            (true, Some(p)) => p.min(static_peak.max(SDR_WHITE_NITS)),
+            _ => static_peak,
+        }
+    }
+
+    /// Decode a non-linear signal triplet to linear display light in nits
+    pub fn linearize(&self, signal: [f32; 3]) -> [f32; 3] {
+        match self.source.transfer {
+            HdrTransfer::Pq => [pq_eotf(signal[0]), pq_eotf(signal[1]), pq_eotf(signal[2])],
+            HdrTransfer::Hlg => {
+                let scene = [
+                    hlg_inverse_oetf(signal[0]),
+                    hlg_inverse_oetf(signal[1]),
+                    hlg_inverse_oetf(signal[2]),
+                ];
+                hlg_ootf(scene, DEFAULT_PEAK_NITS)
+            }
+            HdrTransfer::Sdr => [
+                signal[0] * SDR_WHITE_NITS,
+                signal[1] * SDR_WHITE_NITS,
+                signal[2] * SDR_WHITE_NITS,
+            ],
+        }
+    }
+
+    /// Tone map one pixel: non-linear HDR signal → sRGB encoded (0..1)
+    pub fn map_pixel(&self, signal: [f32; 3]) -> [f32; 3] {
+        self.map_linear(self.linearize(signal), self.source_peak_nits())
+    }
+
+    fn map_linear(&self, nits: [f32; 3], src_peak: f32) -> [f32; 3] {
+        let exposure = self.config.exposure;
+        let nits = [nits[0] * exposure, nits[1] * exposure, nits[2] * exposure];
+        let coeffs = if self.source.bt2020 {
+            BT2020_LUMA
+        } else {
+            BT709_LUMA
+        };
+
+        // Luminance-based mapping keeps hue and saturation stable
+        let y = luminance(nits, coeffs);
+        let target = self.config.target_peak_nits;
+        let scale = if y > 1e-6 {
+            tone_map_luminance(
+                self.config.mode,
+                y,
+                src_peak,
+                self.source.content_black_nits(),
+                target,
+            ) / (y / target)
+        } else {
+            0.0
+        };
+        let mut rgb = [
+            nits[0] / target * scale,
+            nits[1] / target * scale,
+            nits[2] / target * scale,
+        ];
+
+        if self.source.bt2020 {
+            rgb = mat3_mul(&BT2020_TO_BT709, rgb);
+        }
+        let rgb = gamut_map(rgb, self.config.gamut);
+        [
+            srgb_encode(rgb[0]),
+            srgb_encode(rgb[1]),
+            srgb_encode(rgb[2]),
+        ]
+    }
+
+    /// Tone map an interleaved RGB frame of non-linear signal values (0..1)
+    ///
+    /// Updates the dynamic peak detector first, then returns RGB24.
+    pub fn process_rgb_f32(&mut self, signal: &[f32]) -> Vec<u8> {
+        self.update_peak(signal);
+
+        let src_peak = self.source_peak_nits();
+        let mut out = Vec::with_capacity(signal.len());
+        for px in signal.chunks_exact(3) {
+            let mapped = self.map_linear(self.linearize([px[0], px[1], px[2]]), src_peak);
+            out.extend(mapped.iter().map(|v| (v * 255.0 + 0.5) as u8));
+        }
+        out
+    }
+
+    /// Feed one frame of non-linear RGB to the dynamic peak detector
+    ///
+    /// Only the pixels the detector would sample are linearized, so this is
+    /// cheap enough to run on the CPU ahead of the GPU pass.
+    pub fn update_peak(&mut self, signal: &[f32]) {
+        if !self.config.dynamic_peak {
+            return;
+        }
+        let coeffs = if self.source.bt2020 {
+            BT2020_LUMA
+        } else {
+            BT709_LUMA
+        };
+        let pixels = signal.len() / 3;
+        let step = (pixels / 65536).max(1);
+        let luma: Vec<f32> = signal
+            .chunks_exact(3)
+            .step_by(step)
+            .map(|px| luminance(self.linearize([px[0], px[1], px[2]]), coeffs))
+            .collect();
+        self.peak.update(&luma);
+    }
+
+    /// Tone map a decoded YUV frame (P010, 10/12-bit planar, NV12, ...) to RGB24
+    ///
+    /// Runs on the shared GPU processor when one is initialised, otherwise on
+    /// the CPU; the dynamic peak is tracked on the CPU either way.
+    pub fn process_yuv(&mut self, frame: &VideoFrame) -> Result<Vec<u8>, String> {
+        let (width, height) = (frame.width, frame.height);
+        let matrix = if self.source.bt2020 {
+            pixel_convert::ColorSpace::BT2020
+        } else {
+            pixel_convert::ColorSpace::BT709
+        };
+        let mut rgb = VideoFrame::new(width, height, PixelFormat::RGBA16F);
+        PixelConverter::new(frame.format, PixelFormat::RGBA16F, width, height, matrix)
+            .convert(frame, &mut rgb)?;
+        let signal: Vec<f32> = rgb
+            .data
+            .chunks_exact(8)
+            .flat_map(|px| {
+                (0..3).map(move |c| {
+                    f16_to_f32(u16::from_le_bytes([px[c * 2], px[c * 2 + 1]])).clamp(0.0, 1.0)
+                })
+            })
+            .collect();
+
+        if let Some(gpu) = crate::gpu_video_processor::gpu_processor().as_mut() {
+            self.update_peak(&signal);
+            gpu.set_hdr_params(self.gpu_params(width as u32, height as u32));
+            let packed: Vec<u32> = signal
+                .chunks_exact(3)
+                .map(|px| {
+                    let q = |v: f32| (v * 1023.0 + 0.5) as u32;
+                    q(px[0]) | (q(px[1]) << 10) | (q(px[2]) << 20)
+                })
+                .collect();
+            let rgba = gpu.process_hdr(&packed, width as u32, height as u32);
+            return Ok(rgba
+                .chunks_exact(4)
+                .flat_map(|px| [px[0], px[1], px[2]])
+                .collect());
+        }
+        Ok(self.process_rgb_f32(&signal))
+    }
+
+    /// Tone map planar-converted 16-bit RGB (e.g. 10-bit samples in u16)
+    pub fn process_rgb48(&mut self, data: &[u16], bit_depth: u8) -> Vec<u8> {
+        let max = ((1u32 << bit_depth.clamp(8, 16)) - 1) as f32;
+        let signal: Vec<f32> = data.iter().map(|&v| v as f32 / max).collect();
+        self.process_rgb_f32(&signal)
+    }
+
+    /// Forget dynamic peak history (call on seek)
+    pub fn reset(&mut self) {
+        self.peak.reset();
+    }
+
+    /// Uniform parameters for the GPU tone mapping pass
+    pub fn gpu_params(&self, width: u32, height: u32) -> HdrParams {
+        HdrParams {
+            width,
+            height,
+            exposure: self.config.exposure,
+            white_point: self.source_peak_nits() / self.config.target_peak_nits,
+            tonemap_mode: self.config.mode as u32,
+            transfer: match self.source.transfer {
+                HdrTransfer::Sdr => 0,
+                HdrTransfer::Pq => 1,
+                HdrTransfer::Hlg => 2,
+            },
+            src_peak_nits: self.source_peak_nits(),
+            src_black_nits: self.source.content_black_nits(),
+            target_peak_nits: self.config.target_peak_nits,
+            gamut_mode: match (self.source.bt2020, self.config.gamut) {
+                (false, _) => 0,
+                (true, GamutMapping::Clip) => 1,
+                (true, GamutMapping::Desaturate) => 2,
+            },
+            _pad: [0; 2],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pq_source() -> HdrSourceInfo {
+        HdrSourceInfo {
+            transfer: HdrTransfer::Pq,
+            bt2020: true,
+            mastering_display: Some(MasteringDisplay {
+                primaries: [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]],
+                white_point: [0.3127, 0.329],
+                luminance_min: 0.005,
+                luminance_max: 1000.0,
+            }),
+            max_cll: 0,
+            max_fall: 0,
+        }
+    }
+
+    #[test]
+    fn pq_round_trip() {
+        for nits in [0.1f32, 100.0, 203.0, 1000.0, 4000.0] {
+            let back = pq_eotf(pq_inverse_eotf(nits));
+            assert!((back - nits).abs() / nits < 1e-3, "{} -> {}", nits, back);
+        }
+        // 10000 nits is full scale
+        assert!((pq_inverse_eotf(PQ_MAX_NITS) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hlg_reference_white() {
+        // 75% HLG signal is ~26.5% scene light (BT.2408 reference white)
+        let scene = hlg_inverse_oetf(0.75);
+        assert!((scene - 0.265).abs() < 0.005, "{}", scene);
+        let display = hlg_ootf([scene; 3], 1000.0);
+        assert!((display[0] - 203.0).abs() < 5.0, "{}", display[0]);
+    }
+
+    #[test]
+    fn curves_are_monotonic_and_bounded() {
+        for mode in [
+            ToneMapMode::Bt2390,
+            ToneMapMode::Hable,
+            ToneMapMode::Reinhard,
+            ToneMapMode::Mobius,
+            ToneMapMode::Aces,
+        ] {
+            let mut prev = -1.0f32;
+            for i in 0..=100 {
+                let nits = i as f32 * 40.0;
+                let v = tone_map_luminance(mode, nits, 4000.0, 0.0, SDR_WHITE_NITS);
+                assert!((0.0..=1.0).contains(&v), "{:?} {}", mode, v);
+                assert!(v >= prev - 1e-5, "{:?} not monotonic at {}", mode, nits);
+                prev = v;
+            }
+        }
+    }
+
+    #[test]
+    fn bt2390_passes_through_below_knee() {
+        let v = tone_map_luminance(ToneMapMode::Bt2390, 20.0, 1000.0, 0.0, SDR_WHITE_NITS);
+        assert!(
+            (v * SDR_WHITE_NITS - 20.0).abs() < 0.5,
+            "{}",
+            v * SDR_WHITE_NITS
+        );
+        let top = tone_map_luminance(ToneMapMode::Bt2390, 1000.0, 1000.0, 0.0, SDR_WHITE_NITS);
+        assert!((top - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn desaturate_keeps_out_of_gamut_colors_in_range() {
+        // Saturated BT.2020 green is outside BT.709
+        let bt709 = mat3_mul(&BT2020_TO_BT709, [0.0, 0.8, 0.0]);
+        assert!(bt709[0] < 0.0);
+        let mapped = gamut_map(bt709, GamutMapping::Desaturate);
+        assert!(mapped.iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(mapped[1] > mapped[0] && mapped[1] > mapped[2]);
+    }
+
+    #[test]
+    fn dynamic_peak_tracks_and_resets_on_cut() {
+        let mut mapper = ToneMapper::new(pq_source(), ToneMapConfig::default());
+        let dim = vec![pq_inverse_eotf(100.0); 3 * 64];
+        mapper.process_rgb_f32(&dim);
+        assert!((mapper.source_peak_nits() - SDR_WHITE_NITS).abs() < 1.0);
+
+        // Bright frame is a scene cut: peak jumps straight to the new level
+        let bright = vec![pq_inverse_eotf(800.0); 3 * 64];
+        mapper.process_rgb_f32(&bright);
+        assert!(mapper.source_peak_nits() > 700.0);
+    }
+
+    #[test]
+    fn process_yuv_matches_pixel_path() {
+        let config = ToneMapConfig {
+            dynamic_peak: false,
+            ..Default::default()
+        };
+        let mut mapper = ToneMapper::new(pq_source(), config);
+
+        // Neutral grey at 400 nits, limited range, MSB-aligned P010
+        let code = (64.0 + 876.0 * pq_inverse_eotf(400.0)).round() as u16;
+        let mut frame = VideoFrame::new(16, 8, PixelFormat::P010);
+        for (i, sample) in frame.data.chunks_exact_mut(2).enumerate() {
+            let value = if i < 16 * 8 { code } else { 512 };
+            sample.copy_from_slice(&(value << 6).to_le_bytes());
+        }
+        let rgb = mapper.process_yuv(&frame).unwrap();
+        assert_eq!(rgb.len(), 16 * 8 * 3);
+
+        let signal = (code as f32 - 64.0) / 876.0;
+        let expected = mapper.map_pixel([signal; 3]);
+        for px in rgb.chunks_exact(3) {
+            for (c, &v) in px.iter().enumerate() {
+                let want = expected[c] * 255.0;
+                assert!((v as f32 - want).abs() <= 1.5, "{} vs {}", v, want);
+            }
+        }
+    }
+
+    #[test]
+    fn reference_white_maps_near_sdr_white() {
+        let config = ToneMapConfig {
+            dynamic_peak: false,
+            ..Default::default()
+        };
+        let mapper = ToneMapper::new(pq_source(), config);
+        let grey = pq_inverse_eotf(SDR_WHITE_NITS * 0.25);
+        let out = mapper.map_pixel([grey; 3]);
+        // Quarter of reference white sits below the knee, so it passes through
+        assert!((out[0] - srgb_encode(0.25)).abs() < 0.01, "{:?}", out);
+        assert!((out[0] - out[2]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn parse_sei_payloads() {
+        let mut md = Vec::new();
+        for v in [8500u16, 39850, 6550, 2300, 35400, 14600, 15635, 16450] {
+            md.extend_from_slice(&v.to_be_bytes());
+        }
+        md.extend_from_slice(&10_000_000u32.to_be_bytes());
+        md.extend_from_slice(&50u32.to_be_bytes());
+        let display = parse_mastering_display_sei(&md).expect("mdcv");
+        assert!((display.luminance_max - 1000.0).abs() < 0.01);
+        assert!((display.primaries[0][0] - 0.708).abs() < 1e-3);
+
+        let cll = parse_content_light_level_sei(&[0x03, 0xE8, 0x01, 0x90]).expect("cll");
+        assert_eq!(cll, (1000, 400));
+
+        let mut source = pq_source();
+        source.mastering_display = Some(display);
+        source.max_cll = cll.0;
+        assert_eq!(source.content_peak_nits(), 1000.0);
+    }
+
+    #[test]
+    fn stream_transfer_comes_from_vui() {
+        let stream = |color_space, bit_depth, codec_private: &[u8]| VideoStreamInfo {
+            index: 0,
+            codec: VideoCodec::H264,
+            width: 1920,
+            height: 1080,
+            frame_rate: 24.0,
+            par: 1.0,
+            bit_depth,
+            color_space,
+            hdr: None,
+            codec_private: codec_private.to_vec(),
+            language: None,
+            title: None,
+            is_default: true,
+            duration_us: 0,
+            bitrate: 0,
+        };
+        // SPS whose VUI signals BT.2020 primaries and PQ transfer
+        let pq_sps = [
+            0x00, 0x00, 0x00, 0x01, 0x67, 0x4d, 0x00, 0x28, 0xf2, 0x80, 0xf0, 0x04, 0x4f, 0xcb,
+            0x35, 0x09, 0x10, 0x09, 0x02,
+        ];
+
+        let source = HdrSourceInfo::from_stream(&stream(ColorSpace::Bt709, 8, &pq_sps)).unwrap();
+        assert_eq!(source.transfer, HdrTransfer::Pq);
+        assert!(source.bt2020);
+
+        // 10-bit BT.2020 alone says nothing about the transfer
+        assert!(HdrSourceInfo::from_stream(&stream(ColorSpace::Bt2020, 10, &[])).is_none());
+    }
+}
@@ -0,0 +1,1081 @@
+//! On-the-fly HLS packaging for the local streaming server
+//!
+//! A source (MKV/MP4) is indexed once: its video keyframes split it into
+//! segments of about the target duration, which gives a complete VOD playlist
+//! up front so players can seek anywhere. Segments are cut when requested,
+//! muxed as MPEG-TS or fragmented MP4 (a shared init segment plus `moof` +
+//! `mdat` media segments) and kept in a small cache. Timestamps stay those of
+//! the source, so segments line up whatever order they are fetched in.
+//!
+//! Lower renditions decode the source video through `hw_decode`, scale it
+//! with `pixel_convert` and re-encode it through `encode`; audio is copied.
+//! A rendition whose encoder can't be created is left out of the master
+//! playlist.
+//!
+//! Paths served by `HlsPackager::respond`:
+//! - `master.m3u8`
+//! - `<rendition>/index.m3u8`
+//! - `<rendition>/init.mp4` (fMP4 only)
+//! - `<rendition>/<n>.ts` or `<rendition>/<n>.m4s`
+
+use crate::capture::ClipSource;
+use crate::encode::{create_encoder, EncoderConfig};
+use crate::h264_utils;
+use crate::hw_decode::{self, DecoderConfig, HwCodec, HwDecoder};
+use crate::mp4_mux::{Mp4MuxConfig, Mp4Muxer};
+use crate::mux::{MuxCodec, MuxFormat, MuxPacket, MuxStream, MuxTrackKind};
+use crate::pixel_convert::{scale_frame, ChromaLocation, PixelFormat, ScaleFilter, VideoFrame};
+use crate::ts_mux::{self, TsMuxConfig, TsMuxer};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HlsSegmentFormat {
+    /// MPEG-TS segments (widest player support)
+    #[default]
+    Ts,
+    /// Fragmented MP4 segments after a shared init segment
+    Fmp4,
+}
+
+impl HlsSegmentFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Ts => "ts",
+            Self::Fmp4 => "m4s",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ts => "video/mp2t",
+            Self::Fmp4 => "video/mp4",
+        }
+    }
+
+    /// Container whose codec support decides which source streams are kept
+    fn mux_format(&self) -> MuxFormat {
+        match self {
+            Self::Ts => MuxFormat::Ts,
+            Self::Fmp4 => MuxFormat::Mp4,
+        }
+    }
+}
+
+/// A transcoded rendition below the source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsRenditionConfig {
+    /// Output height; the width follows the source aspect ratio
+    pub height: u32,
+    pub video_bitrate_bps: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsPackagerConfig {
+    /// Segments are cut at the first video keyframe at least this far into one
+    pub target_duration_secs: f64,
+    pub format: HlsSegmentFormat,
+    /// Transcoded renditions offered next to the copied source; ones at or
+    /// above the source height are skipped
+    pub renditions: Vec<HlsRenditionConfig>,
+    /// Video codec of transcoded renditions
+    pub transcode_codec: MuxCodec,
+    /// Generated segments kept in memory
+    pub cache_segments: usize,
+}
+
+impl Default for HlsPackagerConfig {
+    fn default() -> Self {
+        Self {
+            target_duration_secs: 4.0,
+            format: HlsSegmentFormat::Ts,
+            renditions: Vec::new(),
+            transcode_codec: MuxCodec::H264,
+            cache_segments: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsRenditionInfo {
+    /// Path component of the rendition's URLs ("source", "480p", ...)
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub bandwidth_bps: u64,
+    pub transcoded: bool,
+}
+
+// ============================================================================
+// Source Index
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+struct SegmentSpan {
+    start_us: i64,
+    duration_us: i64,
+}
+
+struct SourceIndex {
+    streams: Vec<MuxStream>,
+    /// Stream whose keyframes the cuts follow: the first video stream, else
+    /// the first stream (audio-only sources cut at any packet)
+    anchor: usize,
+    segments: Vec<SegmentSpan>,
+    /// Earliest decode time, the origin of fMP4 fragment times
+    decode_origin_us: i64,
+    /// Bit rate of the busiest segment, and of the audio overall
+    peak_bps: u64,
+    audio_bps: u64,
+}
+
+/// Video and audio streams the segment container can carry
+fn keeps_stream(format: HlsSegmentFormat, stream: &MuxStream) -> bool {
+    !matches!(stream.kind, MuxTrackKind::Subtitle) && format.mux_format().supports(stream)
+}
+
+impl SourceIndex {
+    /// Read through `path` once and place the cuts
+    fn build(path: &Path, format: HlsSegmentFormat, target_us: i64) -> Result<Self, String> {
+        let (mut source, streams) = ClipSource::open(path, |s| keeps_stream(format, s))?;
+        if streams.is_empty() {
+            return Err(format!("No streams in {:?} can be packaged for HLS", path));
+        }
+        let anchor = streams.iter().position(|s| s.codec.is_video()).unwrap_or(0);
+        let cut_anywhere = !streams[anchor].codec.is_video();
+
+        let mut starts: Vec<i64> = Vec::new();
+        let mut sizes: Vec<(i64, usize)> = Vec::new();
+        let mut decode_origin_us = i64::MAX;
+        let mut end_us = i64::MIN;
+        let mut audio_bytes = 0u64;
+        while let Some(packet) = source.next_packet() {
+            let stream = &streams[packet.stream];
+            decode_origin_us = decode_origin_us.min(packet.dts_us.unwrap_or(packet.pts_us));
+            let duration = packet
+                .duration_us
+                .or(stream.frame_duration_us.map(|d| d as i64))
+                .unwrap_or(0);
+            end_us = end_us.max(packet.pts_us + duration);
+            if packet.stream == anchor && (packet.keyframe || cut_anywhere) {
+                match starts.last() {
+                    Some(&last) if packet.pts_us - last < target_us => {}
+                    _ => starts.push(packet.pts_us),
+                }
+            }
+            if !stream.codec.is_video() {
+                audio_bytes += packet.data.len() as u64;
+            }
+            sizes.push((packet.pts_us, packet.data.len()));
+        }
+        if starts.is_empty() {
+            return Err(format!("No keyframes found in {:?}", path));
+        }
+        starts.sort_unstable();
+
+        let last = *starts.last().unwrap_or(&0);
+        let end_us = end_us.max(last + 1);
+        let segments: Vec<SegmentSpan> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start_us)| SegmentSpan {
+                start_us,
+                duration_us: starts.get(i + 1).copied().unwrap_or(end_us) - start_us,
+            })
+            .collect();
+
+        let mut index = Self {
+            streams,
+            anchor,
+            segments,
+            decode_origin_us,
+            peak_bps: 0,
+            audio_bps: 0,
+        };
+        let mut bytes = vec![0u64; index.segments.len()];
+        for (pts_us, len) in sizes {
+            bytes[index.segment_at(pts_us)] += len as u64;
+        }
+        index.peak_bps = bytes
+            .iter()
+            .zip(&index.segments)
+            .map(|(&b, span)| bits_per_second(b, span.duration_us))
+            .max()
+            .unwrap_or(0);
+        index.audio_bps = bits_per_second(audio_bytes, end_us - index.segments[0].start_us);
+        Ok(index)
+    }
+
+    /// Segment whose span holds `pts_us` (earlier times belong to the first)
+    fn segment_at(&self, pts_us: i64) -> usize {
+        self.segments
+            .partition_point(|s| s.start_us <= pts_us)
+            .saturating_sub(1)
+    }
+
+    fn video_size(&self) -> (u32, u32) {
+        match self.streams[self.anchor].kind {
+            MuxTrackKind::Video { width, height } => (width, height),
+            _ => (0, 0),
+        }
+    }
+
+    fn frame_rate(&self) -> f64 {
+        self.streams[self.anchor]
+            .frame_duration_us
+            .filter(|&d| d > 0)
+            .map(|d| 1_000_000.0 / d as f64)
+            .unwrap_or(30.0)
+    }
+}
+
+fn bits_per_second(bytes: u64, duration_us: i64) -> u64 {
+    if duration_us <= 0 {
+        return 0;
+    }
+    (bytes as f64 * 8.0 * 1_000_000.0 / duration_us as f64).round() as u64
+}
+
+// ============================================================================
+// Segment Reader
+// ============================================================================
+
+/// Sequential reader that sorts packets into segments. Packets read ahead
+/// for later segments are held back, so linear playback reads the source
+/// once; a jump reopens and seeks.
+struct SegmentReader {
+    source: ClipSource,
+    /// Segment a sequential read continues with
+    next: usize,
+    /// Segment the anchor stream is in (set by its keyframes)
+    anchor_segment: Option<usize>,
+    /// Furthest segment each stream has reached
+    reached: Vec<Option<usize>>,
+    carry: BTreeMap<usize, Vec<MuxPacket>>,
+    eof: bool,
+}
+
+impl SegmentReader {
+    fn open(
+        path: &Path,
+        format: HlsSegmentFormat,
+        index: &SourceIndex,
+        segment: usize,
+    ) -> Result<Self, String> {
+        let (mut source, streams) = ClipSource::open(path, |s| keeps_stream(format, s))?;
+        if streams.len() != index.streams.len() {
+            return Err(format!("{:?} changed since it was indexed", path));
+        }
+        let start_ms = index.segments[segment].start_us.max(0) as u64 / 1000;
+        source.seek(start_ms)?;
+        Ok(Self {
+            source,
+            next: segment,
+            anchor_segment: None,
+            reached: vec![None; streams.len()],
+            carry: BTreeMap::new(),
+            eof: false,
+        })
+    }
+
+    /// Packets of `segment` in file order
+    fn read(&mut self, index: &SourceIndex, segment: usize) -> Vec<MuxPacket> {
+        let cut_anywhere = !index.streams[index.anchor].codec.is_video();
+        loop {
+            // Done once every stream has moved past the segment; streams that
+            // stop early only hold things up until the anchor is a segment on
+            let done = self.eof
+                || self.reached.iter().all(|r| r.is_some_and(|s| s > segment))
+                || self.reached[index.anchor].is_some_and(|s| s > segment + 1);
+            if done {
+                break;
+            }
+            let Some(packet) = self.source.next_packet() else {
+                self.eof = true;
+                continue;
+            };
+            let target = if packet.stream == index.anchor {
+                if packet.keyframe || cut_anywhere {
+                    self.anchor_segment = Some(index.segment_at(packet.pts_us));
+                }
+                // Frames ahead of the first keyframe after a seek can't be used
+                match self.anchor_segment {
+                    Some(target) => target,
+                    None => continue,
+                }
+            } else {
+                index.segment_at(packet.pts_us)
+            };
+            let reached = &mut self.reached[packet.stream];
+            *reached = Some(reached.map_or(target, |r| r.max(target)));
+            if target >= segment {
+                self.carry.entry(target).or_default().push(packet);
+            }
+        }
+        let packets = self.carry.remove(&segment).unwrap_or_default();
+        self.carry = self.carry.split_off(&(segment + 1));
+        self.next = segment + 1;
+        packets
+    }
+}
+
+// ============================================================================
+// Transcoding
+// ============================================================================
+
+/// Feeds source video packets to `hw_decode` as Annex B, parameter sets first
+struct SegmentDecoder {
+    decoder: HwDecoder,
+    nal_length_size: Option<usize>,
+}
+
+impl SegmentDecoder {
+    fn new(stream: &MuxStream) -> Result<Self, String> {
+        let codec = match stream.codec {
+            MuxCodec::H264 => HwCodec::H264,
+            MuxCodec::H265 => HwCodec::H265,
+            MuxCodec::VP8 => HwCodec::VP8,
+            MuxCodec::VP9 => HwCodec::VP9,
+            MuxCodec::AV1 => HwCodec::AV1,
+            MuxCodec::MPEG2 => HwCodec::MPEG2,
+            other => return Err(format!("{:?} can't be decoded for transcoding", other)),
+        };
+        let (width, height) = match stream.kind {
+            MuxTrackKind::Video { width, height } => (width, height),
+            _ => return Err("Transcoding needs a video stream".to_string()),
+        };
+        let parsed = match (stream.codec, stream.codec_private.as_deref()) {
+            (MuxCodec::H264, Some(config)) if !h264_utils::is_annexb(config) => {
+                h264_utils::parse_avcc_extradata(config)
+            }
+            (MuxCodec::H265, Some(config)) if !h264_utils::is_annexb(config) => {
+                h264_utils::parse_hvcc_extradata(config)
+            }
+            _ => None,
+        };
+        let mut decoder = HwDecoder::new(DecoderConfig {
+            codec,
+            width,
+            height,
+            extra_data: stream.codec_private.clone(),
+            ..Default::default()
+        })?;
+        if let Some((parameter_sets, _)) = &parsed {
+            if let Err(e) = decoder.decode(parameter_sets, 0) {
+                tracing::warn!("SPS/PPS feed error (may be ok): {}", e);
+            }
+        }
+        Ok(Self {
+            decoder,
+            nal_length_size: parsed.map(|(_, size)| size),
+        })
+    }
+
+    fn decode(&mut self, packet: &MuxPacket) -> Result<Option<hw_decode::DecodedFrame>, String> {
+        match self.nal_length_size {
+            Some(size) => {
+                let annexb = ts_mux::length_prefixed_to_annexb(&packet.data, size);
+                self.decoder.decode(&annexb, packet.pts_us)
+            }
+            None => self.decoder.decode(&packet.data, packet.pts_us),
+        }
+    }
+}
+
+/// Copy a decoder frame (NV12 or I420, rows `pitch` apart) into a tight NV12
+/// frame
+fn to_nv12(frame: &hw_decode::DecodedFrame) -> Result<VideoFrame, String> {
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(format!("Odd frame size {}x{}", width, height));
+    }
+    let pitch = (frame.pitch as usize).max(width);
+    let mut out = VideoFrame::new(width, height, PixelFormat::NV12);
+    let row = |offset: usize, len: usize| -> Result<&[u8], String> {
+        frame
+            .data
+            .get(offset..offset + len)
+            .ok_or_else(|| "Truncated decoder frame".to_string())
+    };
+
+    for y in 0..height {
+        out.data[y * width..(y + 1) * width].copy_from_slice(row(y * pitch, width)?);
+    }
+    let chroma = &mut out.data[width * height..];
+    match frame.format {
+        hw_decode::PixelFormat::NV12 => {
+            let base = pitch * height;
+            for y in 0..height / 2 {
+                chroma[y * width..(y + 1) * width].copy_from_slice(row(base + y * pitch, width)?);
+            }
+        }
+        hw_decode::PixelFormat::YUV420 => {
+            let (chroma_width, chroma_pitch) = (width / 2, pitch / 2);
+            let u_base = pitch * height;
+            let v_base = u_base + chroma_pitch * height / 2;
+            for y in 0..height / 2 {
+                let u = row(u_base + y * chroma_pitch, chroma_width)?;
+                let v = row(v_base + y * chroma_pitch, chroma_width)?;
+                for x in 0..chroma_width {
+                    chroma[y * width + 2 * x] = u[x];
+                    chroma[y * width + 2 * x + 1] = v[x];
+                }
+            }
+        }
+        other => return Err(format!("{:?} frames can't be transcoded", other)),
+    }
+    Ok(out)
+}
+
+// ============================================================================
+// Packager
+// ============================================================================
+
+/// Content type and body of a served file
+pub type HlsResponse = (&'static str, Arc<Vec<u8>>);
+
+struct Rendition {
+    info: HlsRenditionInfo,
+    /// Streams as muxed (the video replaced when transcoded)
+    streams: Vec<MuxStream>,
+    transcode: Option<EncoderConfig>,
+    reader: Mutex<Option<SegmentReader>>,
+}
+
+pub struct HlsPackager {
+    path: PathBuf,
+    config: HlsPackagerConfig,
+    index: SourceIndex,
+    renditions: Vec<Rendition>,
+    /// Generated files by path, least recently used first
+    cache: Mutex<VecDeque<(String, Arc<Vec<u8>>)>>,
+}
+
+impl HlsPackager {
+    /// Index `path` and set up its renditions
+    pub fn open(path: &Path, config: HlsPackagerConfig) -> Result<Self, String> {
+        let target_us = (config.target_duration_secs.max(0.5) * 1_000_000.0) as i64;
+        let index = SourceIndex::build(path, config.format, target_us)?;
+        let (width, height) = index.video_size();
+
+        let mut renditions = vec![Rendition {
+            info: HlsRenditionInfo {
+                name: "source".to_string(),
+                width,
+                height,
+                bandwidth_bps: index.peak_bps,
+                transcoded: false,
+            },
+            streams: index.streams.clone(),
+            transcode: None,
+            reader: Mutex::new(None),
+        }];
+
+        let frame_rate = index.frame_rate();
+        for wanted in &config.renditions {
+            if height == 0 || wanted.height >= height {
+                tracing::info!(
+                    "HLS: skipping {}p rendition (source is {}p)",
+                    wanted.height,
+                    height
+                );
+                continue;
+            }
+            // Even dimensions for 4:2:0
+            let out_height = wanted.height & !1;
+            let out_width = ((width as u64 * out_height as u64 / height as u64) as u32 + 1) & !1;
+            let encoder = EncoderConfig {
+                codec: config.transcode_codec,
+                width: out_width,
+                height: out_height,
+                frame_rate,
+                bitrate_bps: wanted.video_bitrate_bps,
+                // Keyframes only where segments start
+                gop_frames: (frame_rate * config.target_duration_secs * 4.0).ceil() as u32,
+            };
+            if let Err(e) = create_encoder(&encoder) {
+                tracing::warn!("HLS: no {}p rendition: {}", out_height, e);
+                continue;
+            }
+            let mut streams = index.streams.clone();
+            let mut video = MuxStream::video(config.transcode_codec, out_width, out_height);
+            video.frame_duration_us = streams[index.anchor].frame_duration_us;
+            video.language = streams[index.anchor].language.clone();
+            streams[index.anchor] = video;
+
+            renditions.push(Rendition {
+                info: HlsRenditionInfo {
+                    name: format!("{}p", out_height),
+                    width: out_width,
+                    height: out_height,
+                    bandwidth_bps: wanted.video_bitrate_bps + index.audio_bps,
+                    transcoded: true,
+                },
+                streams,
+                transcode: Some(encoder),
+                reader: Mutex::new(None),
+            });
+        }
+
+        tracing::info!(
+            "HLS: {:?} -> {} segments, {} renditions",
+            path,
+            index.segments.len(),
+            renditions.len()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            config,
+            index,
+            renditions,
+            cache: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn renditions(&self) -> Vec<HlsRenditionInfo> {
+        self.renditions.iter().map(|r| r.info.clone()).collect()
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.index.segments.len()
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.index
+            .segments
+            .iter()
+            .map(|s| s.duration_us)
+            .sum::<i64>() as f64
+            / 1_000_000.0
+    }
+
+    fn version(&self) -> u32 {
+        match self.config.format {
+            HlsSegmentFormat::Ts => 3,
+            HlsSegmentFormat::Fmp4 => 7,
+        }
+    }
+
+    pub fn master_playlist(&self) -> String {
+        let mut out = format!(
+            "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+            self.version()
+        );
+        for rendition in &self.renditions {
+            let info = &rendition.info;
+            let _ = write!(
+                out,
+                "#EXT-X-STREAM-INF:BANDWIDTH={}",
+                info.bandwidth_bps.max(1)
+            );
+            if info.width > 0 && info.height > 0 {
+                let _ = write!(out, ",RESOLUTION={}x{}", info.width, info.height);
+            }
+            if let Some(codecs) = codecs_attribute(&rendition.streams) {
+                let _ = write!(out, ",CODECS=\"{}\"", codecs);
+            }
+            let _ = write!(out, "\n{}/index.m3u8\n", info.name);
+        }
+        out
+    }
+
+    /// VOD playlist of one rendition (index into `renditions()`)
+    pub fn media_playlist(&self, rendition: usize) -> Option<String> {
+        self.renditions.get(rendition)?;
+        let longest = self
+            .index
+            .segments
+            .iter()
+            .map(|s| s.duration_us)
+            .max()
+            .unwrap_or(0);
+        // Every EXTINF, rounded to the nearest second, must fit the target
+        let mut out = format!(
+            "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-TARGETDURATION:{}\n\
+             #EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+            self.version(),
+            (longest as f64 / 1_000_000.0).round().max(1.0) as u64
+        );
+        if self.config.format == HlsSegmentFormat::Fmp4 {
+            out.push_str("#EXT-X-MAP:URI=\"init.mp4\"\n");
+        }
+        let extension = self.config.format.extension();
+        for (i, span) in self.index.segments.iter().enumerate() {
+            let _ = write!(
+                out,
+                "#EXTINF:{:.3},\n{}.{}\n",
+                span.duration_us as f64 / 1_000_000.0,
+                i,
+                extension
+            );
+        }
+        out.push_str("#EXT-X-ENDLIST\n");
+        Some(out)
+    }
+
+    /// fMP4 init segment (`ftyp` + `moov`) of a rendition
+    pub fn init_segment(&self, rendition: usize) -> Result<Arc<Vec<u8>>, String> {
+        if self.config.format != HlsSegmentFormat::Fmp4 {
+            return Err("MPEG-TS renditions have no init segment".to_string());
+        }
+        let name = &self.rendition(rendition)?.info.name;
+        let key = format!("{}/init.mp4", name);
+        if let Some(data) = self.cached(&key) {
+            return Ok(data);
+        }
+        // The init segment comes out of muxing the first media segment
+        self.generate(rendition, 0)?;
+        self.cached(&key)
+            .ok_or_else(|| format!("No init segment for {}", name))
+    }
+
+    /// Media segment `segment` of a rendition
+    pub fn segment(&self, rendition: usize, segment: usize) -> Result<Arc<Vec<u8>>, String> {
+        let name = &self.rendition(rendition)?.info.name;
+        if segment >= self.index.segments.len() {
+            return Err(format!("No segment {} in {}", segment, name));
+        }
+        let key = format!("{}/{}.{}", name, segment, self.config.format.extension());
+        if let Some(data) = self.cached(&key) {
+            return Ok(data);
+        }
+        self.generate(rendition, segment)
+    }
+
+    /// Serve a request path below the packager root: content type and body,
+    /// or `None` when nothing lives there
+    pub fn respond(&self, path: &str) -> Result<Option<HlsResponse>, String> {
+        const PLAYLIST: &str = "application/vnd.apple.mpegurl";
+        let path = path.split(['?', '#']).next().unwrap_or("");
+        let path = path.trim_start_matches('/');
+        if path == "master.m3u8" {
+            return Ok(Some((
+                PLAYLIST,
+                Arc::new(self.master_playlist().into_bytes()),
+            )));
+        }
+        let Some((name, file)) = path.split_once('/') else {
+            return Ok(None);
+        };
+        let Some(rendition) = self.renditions.iter().position(|r| r.info.name == name) else {
+            return Ok(None);
+        };
+        if file == "index.m3u8" {
+            return Ok(self
+                .media_playlist(rendition)
+                .map(|text| (PLAYLIST, Arc::new(text.into_bytes()))));
+        }
+        if file == "init.mp4" && self.config.format == HlsSegmentFormat::Fmp4 {
+            return self
+                .init_segment(rendition)
+                .map(|data| Some(("video/mp4", data)));
+        }
+        let segment = file
+            .strip_suffix(self.config.format.extension())
+            .and_then(|stem| stem.strip_suffix('.'))
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n < self.index.segments.len());
+        match segment {
+            Some(n) => self
+                .segment(rendition, n)
+                .map(|data| Some((self.config.format.content_type(), data))),
+            None => Ok(None),
+        }
+    }
+
+    fn rendition(&self, index: usize) -> Result<&Rendition, String> {
+        self.renditions
+            .get(index)
+            .ok_or_else(|| format!("No rendition {}", index))
+    }
+
+    fn cached(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        let mut cache = self.cache.lock();
+        let position = cache.iter().position(|(k, _)| k == key)?;
+        let entry = cache.remove(position)?;
+        let data = entry.1.clone();
+        cache.push_back(entry);
+        Some(data)
+    }
+
+    fn store(&self, key: String, data: Arc<Vec<u8>>) {
+        let mut cache = self.cache.lock();
+        cache.retain(|(k, _)| *k != key);
+        cache.push_back((key, data));
+        while cache.len() > self.config.cache_segments.max(2) {
+            cache.pop_front();
+        }
+    }
+
+    /// Cut, (transcode,) mux and cache one segment
+    fn generate(&self, rendition: usize, segment: usize) -> Result<Arc<Vec<u8>>, String> {
+        let target = self.rendition(rendition)?;
+        let packets = {
+            let mut reader = target.reader.lock();
+            let sequential = reader
+                .as_ref()
+                .is_some_and(|r| r.next <= segment && segment <= r.next + 1);
+            if !sequential {
+                *reader = Some(SegmentReader::open(
+                    &self.path,
+                    self.config.format,
+                    &self.index,
+                    segment,
+                )?);
+            }
+            match reader.as_mut() {
+                Some(reader) => reader.read(&self.index, segment),
+                None => Vec::new(),
+            }
+        };
+        let packets = match &target.transcode {
+            Some(encoder) => self.transcode(encoder, packets)?,
+            None => packets,
+        };
+        if packets.is_empty() {
+            return Err(format!(
+                "Segment {} of {} has no packets",
+                segment, target.info.name
+            ));
+        }
+
+        let name = &target.info.name;
+        let media = match self.config.format {
+            HlsSegmentFormat::Ts => {
+                let mut muxer =
+                    TsMuxer::new(Vec::new(), target.streams.clone(), TsMuxConfig::default())?;
+                for packet in &packets {
+                    muxer.write_packet(packet)?;
+                }
+                muxer.finish()?
+            }
+            HlsSegmentFormat::Fmp4 => {
+                let config = Mp4MuxConfig {
+                    // One fragment per segment
+                    fragment_duration_ms: Some(u32::MAX),
+                    fragment_decode_origin_us: Some(self.index.decode_origin_us),
+                    ..Default::default()
+                };
+                let mut muxer =
+                    Mp4Muxer::new(Cursor::new(Vec::new()), target.streams.clone(), config)?;
+                for packet in &packets {
+                    muxer.write_packet(packet)?;
+                }
+                let (init, media) = split_init(&muxer.finish()?.into_inner());
+                self.store(format!("{}/init.mp4", name), Arc::new(init));
+                media
+            }
+        };
+
+        tracing::debug!(
+            "HLS: {} segment {} ({} packets, {} bytes)",
+            name,
+            segment,
+            packets.len(),
+            media.len()
+        );
+        let media = Arc::new(media);
+        let key = format!("{}/{}.{}", name, segment, self.config.format.extension());
+        self.store(key, media.clone());
+        Ok(media)
+    }
+
+    /// Re-encode the anchor video of a segment; other streams pass through
+    fn transcode(
+        &self,
+        config: &EncoderConfig,
+        packets: Vec<MuxPacket>,
+    ) -> Result<Vec<MuxPacket>, String> {
+        let anchor = self.index.anchor;
+        let (video, mut output): (Vec<MuxPacket>, Vec<MuxPacket>) =
+            packets.into_iter().partition(|p| p.stream == anchor);
+        if video.is_empty() {
+            return Ok(output);
+        }
+
+        let mut decoder = SegmentDecoder::new(&self.index.streams[anchor])?;
+        let mut frames = Vec::new();
+        for packet in &video {
+            frames.extend(decoder.decode(packet)?);
+        }
+        frames.extend(decoder.decoder.flush());
+
+        // Frames come out in display order, so their times are the sorted PTS
+        let mut times: Vec<i64> = video.iter().map(|p| p.pts_us).collect();
+        times.sort_unstable();
+        let frame_duration_us = (1_000_000.0 / config.frame_rate).round() as i64;
+
+        // A fresh encoder per segment starts it on a keyframe
+        let mut encoder = create_encoder(config)?;
+        let mut encoded = Vec::new();
+        for (frame, pts_us) in frames.iter().zip(times) {
+            let mut nv12 = to_nv12(frame)?;
+            if nv12.width != config.width as usize || nv12.height != config.height as usize {
+                nv12 = scale_frame(
+                    &nv12,
+                    config.width as usize,
+                    config.height as usize,
+                    ScaleFilter::Bilinear,
+                    ChromaLocation::Left,
+                )?;
+            }
+            nv12.pts = pts_us;
+            encoded.extend(encoder.encode(&nv12)?);
+        }
+        encoded.extend(encoder.flush()?);
+
+        output.extend(encoded.into_iter().map(|packet| MuxPacket {
+            stream: anchor,
+            pts_us: packet.pts_us,
+            dts_us: Some(packet.pts_us),
+            duration_us: Some(frame_duration_us),
+            keyframe: packet.keyframe,
+            data: packet.data,
+        }));
+        output.sort_by_key(|p| p.dts_us.unwrap_or(p.pts_us));
+        Ok(output)
+    }
+}
+
+/// `CODECS` value for a variant, when every stream's codec string is known
+fn codecs_attribute(streams: &[MuxStream]) -> Option<String> {
+    let mut codecs = Vec::new();
+    for stream in streams {
+        let config = stream.codec_private.as_deref().unwrap_or(&[]);
+        codecs.push(match stream.codec {
+            // avcC: version, profile, compatibility, level
+            MuxCodec::H264 if config.len() >= 4 && config[0] == 1 => {
+                format!("avc1.{:02X}{:02X}{:02X}", config[1], config[2], config[3])
+            }
+            MuxCodec::AAC => {
+                let object_type = config.first().map(|b| b >> 3).filter(|&t| t > 0);
+                format!("mp4a.40.{}", object_type.unwrap_or(2))
+            }
+            MuxCodec::MP3 => "mp4a.40.34".to_string(),
+            MuxCodec::AC3 => "ac-3".to_string(),
+            MuxCodec::EAC3 => "ec-3".to_string(),
+            _ => return None,
+        });
+    }
+    Some(codecs.join(","))
+}
+
+/// Split fragmented MP4 output into its init (`ftyp`, `moov`) and media
+/// (`moof`, `mdat`) boxes
+fn split_init(data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut init = Vec::new();
+    let mut media = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size32 = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let size = match size32 {
+            0 => data.len() - pos,
+            1 if pos + 16 <= data.len() => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&data[pos + 8..pos + 16]);
+                u64::from_be_bytes(large) as usize
+            }
+            n => n as usize,
+        };
+        if size < 8 || pos + size > data.len() {
+            break;
+        }
+        let target = match &data[pos + 4..pos + 8] {
+            b"ftyp" | b"moov" => &mut init,
+            _ => &mut media,
+        };
+        target.extend_from_slice(&data[pos..pos + size]);
+        pos += size;
+    }
+    (init, media)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive_stream::{AdaptiveClient, AdaptiveConfig};
+    use crate::streaming::LocalStreamServer;
+
+    const SPS: [u8; 6] = [0x67, 0x64, 0x00, 0x1F, 0xAC, 0xD9];
+    const PPS: [u8; 4] = [0x68, 0xEB, 0xE3, 0xCB];
+
+    /// 6 s MP4: 25 fps H.264 with a keyframe every second, 50 fps AAC
+    fn source(dir: &Path) -> PathBuf {
+        let mut avcc = vec![1, 0x64, 0x00, 0x1F, 0xFF, 0xE1];
+        avcc.extend_from_slice(&(SPS.len() as u16).to_be_bytes());
+        avcc.extend_from_slice(&SPS);
+        avcc.push(1);
+        avcc.extend_from_slice(&(PPS.len() as u16).to_be_bytes());
+        avcc.extend_from_slice(&PPS);
+        let mut video = MuxStream::video(MuxCodec::H264, 1280, 720).with_codec_private(avcc);
+        video.frame_duration_us = Some(40_000);
+        let streams = vec![
+            video,
+            MuxStream::audio(MuxCodec::AAC, 48000, 2).with_codec_private(vec![0x11, 0x90]),
+        ];
+
+        let path = dir.join("source.mp4");
+        let file = std::fs::File::create(&path).unwrap();
+        let mut muxer = Mp4Muxer::new(
+            std::io::BufWriter::new(file),
+            streams,
+            Mp4MuxConfig::default(),
+        )
+        .unwrap();
+        for i in 0..150i64 {
+            let keyframe = i % 25 == 0;
+            let size = if keyframe { 2000 } else { 300 + i as usize };
+            let mut data = (size as u32 + 1).to_be_bytes().to_vec();
+            data.push(if keyframe { 0x65 } else { 0x41 });
+            data.extend((0..size).map(|b| b as u8 | 0x10));
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: i * 40_000,
+                    dts_us: Some(i * 40_000),
+                    duration_us: Some(40_000),
+                    keyframe,
+                    data,
+                })
+                .unwrap();
+            for a in 0..2 {
+                let t = i * 40_000 + a * 20_000;
+                muxer
+                    .write_packet(&MuxPacket {
+                        stream: 1,
+                        pts_us: t,
+                        dts_us: Some(t),
+                        duration_us: Some(20_000),
+                        keyframe: true,
+                        data: vec![0x21; 120],
+                    })
+                    .unwrap();
+            }
+        }
+        muxer.finish().unwrap();
+        path
+    }
+
+    fn config(format: HlsSegmentFormat) -> HlsPackagerConfig {
+        HlsPackagerConfig {
+            target_duration_secs: 2.0,
+            format,
+            // Not below the 720p source: never offered
+            renditions: vec![HlsRenditionConfig {
+                height: 1080,
+                video_bitrate_bps: 8_000_000,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ts_segments_and_playlists() {
+        let dir = tempfile::tempdir().unwrap();
+        let packager =
+            HlsPackager::open(&source(dir.path()), config(HlsSegmentFormat::Ts)).unwrap();
+        assert_eq!(packager.segment_count(), 3);
+        assert!((packager.duration_secs() - 6.0).abs() < 0.01);
+
+        let renditions = packager.renditions();
+        assert_eq!(renditions.len(), 1);
+        assert_eq!((renditions[0].width, renditions[0].height), (1280, 720));
+
+        let master = packager.master_playlist();
+        assert!(master.contains("RESOLUTION=1280x720,CODECS=\"avc1.64001F,mp4a.40.2\""));
+        assert!(master.contains("\nsource/index.m3u8\n"));
+        let media = packager.media_playlist(0).unwrap();
+        assert_eq!(media.matches("#EXTINF:2.00").count(), 3);
+        assert!(media.contains("#EXT-X-TARGETDURATION:2\n"));
+        assert!(media.contains("\n2.ts\n#EXT-X-ENDLIST"));
+
+        // Out of order, as after a seek, then back to the start
+        let mut first_pts = Vec::new();
+        for n in [2usize, 0, 1] {
+            let (content_type, data) = packager
+                .respond(&format!("source/{}.ts", n))
+                .unwrap()
+                .unwrap();
+            assert_eq!(content_type, "video/mp2t");
+            let mut demuxer = crate::ts_demux::TsDemuxer::new(Cursor::new(&data[..])).unwrap();
+            let mut video = Vec::new();
+            let mut audio = 0;
+            while let Some(packet) = demuxer.read_packet() {
+                let packet = crate::demuxer::map_ts_packet(packet);
+                // TS packets are numbered by PID; video has the first one
+                if packet.stream_index == 0x100 {
+                    video.push((packet.pts_us.unwrap(), packet.keyframe));
+                } else {
+                    audio += 1;
+                }
+            }
+            assert_eq!(video.len(), 50, "segment {}", n);
+            assert_eq!(audio, 100, "segment {}", n);
+            assert!(video[0].1);
+            first_pts.push((n, video[0].0));
+        }
+        let base = first_pts.iter().find(|(n, _)| *n == 0).unwrap().1;
+        for (n, pts) in first_pts {
+            assert_eq!(pts - base, n as i64 * 2_000_000);
+        }
+
+        assert!(packager.respond("source/3.ts").unwrap().is_none());
+        assert!(packager.respond("other/index.m3u8").unwrap().is_none());
+        assert!(packager.init_segment(0).is_err());
+    }
+
+    #[test]
+    fn test_fmp4_over_local_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = source(dir.path());
+        let mut server = LocalStreamServer::new(0);
+        let url =
+            pollster::block_on(server.start_hls(path, config(HlsSegmentFormat::Fmp4))).unwrap();
+        let port = url
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .and_then(|host| host.rsplit(':').next())
+            .unwrap()
+            .to_string();
+        let local = format!("http://127.0.0.1:{}/hls/master.m3u8", port);
+
+        let mut client = AdaptiveClient::open(&local, AdaptiveConfig::default()).unwrap();
+        assert!(!client.is_live());
+        let mut video = Vec::new();
+        let mut audio = 0;
+        while let Some(segment) = client.next_segment().unwrap() {
+            assert!(segment.init.as_ref().is_some_and(|init| !init.is_empty()));
+            for packet in segment.packets().unwrap() {
+                if packet.stream_index == 0 {
+                    video.push((packet.pts_us.unwrap(), packet.keyframe));
+                } else {
+                    audio += 1;
+                }
+            }
+        }
+        pollster::block_on(server.stop());
+
+        assert_eq!(video.len(), 150);
+        assert_eq!(audio, 300);
+        // One timeline across the separately muxed segments
+        for (i, (pts, keyframe)) in video.iter().enumerate() {
+            assert_eq!(pts - video[0].0, i as i64 * 40_000);
+            assert_eq!(*keyframe, i % 25 == 0);
+        }
+    }
+}
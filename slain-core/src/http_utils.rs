@@ -0,0 +1,66 @@
+//! Helpers shared by the HTTP clients
+
+use parking_lot::{Condvar, Mutex};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// Per-Host Connection Limits
+// ============================================================================
+
+/// Connection slots per host
+pub struct HostSlots {
+    limit: AtomicUsize,
+    busy: Mutex<HashMap<String, usize>>,
+    freed: Condvar,
+}
+
+/// A held slot; dropping it frees the slot
+pub struct HostSlot<'a> {
+    slots: &'a HostSlots,
+    host: String,
+}
+
+impl HostSlots {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit.max(1)),
+            busy: Mutex::new(HashMap::new()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Change the limit; waiters re-check it at once
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit.max(1), Ordering::SeqCst);
+        self.freed.notify_all();
+    }
+
+    /// Block until `url`'s host has a free slot
+    pub fn acquire(&self, url: &str) -> HostSlot<'_> {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| {
+                let port = u.port_or_known_default().unwrap_or(0);
+                u.host_str().map(|host| format!("{}:{}", host, port))
+            })
+            .unwrap_or_default();
+        let mut busy = self.busy.lock();
+        while busy.get(&host).copied().unwrap_or(0) >= self.limit.load(Ordering::SeqCst) {
+            self.freed.wait(&mut busy);
+        }
+        *busy.entry(host.clone()).or_insert(0) += 1;
+        HostSlot { slots: self, host }
+    }
+}
+
+impl Drop for HostSlot<'_> {
+    fn drop(&mut self) {
+        let mut busy = self.slots.busy.lock();
+        if let Some(count) = busy.get_mut(&self.host) {
+            *count = count.saturating_sub(1);
+        }
+        drop(busy);
+        self.slots.freed.notify_all();
+    }
+}
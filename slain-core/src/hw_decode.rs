@@ -477,16 +477,19 @@ impl SoftwareDecoder {
         self.width = width as u32;
         self.height = height as u32;
 
-        // Get YUV plane data - OpenH264 returns packed data
-        let y_data = yuv.y();
-        let u_data = yuv.u();
-        let v_data = yuv.v();
-
-        // Build YUV420 planar buffer
-        let mut yuv_data = Vec::with_capacity(y_data.len() + u_data.len() + v_data.len());
-        yuv_data.extend_from_slice(y_data);
-        yuv_data.extend_from_slice(u_data);
-        yuv_data.extend_from_slice(v_data);
+        // OpenH264 planes are padded to their strides; build a tightly
+        // packed YUV420 planar buffer (pitch == width)
+        let (y_stride, u_stride, v_stride) = yuv.strides();
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let mut yuv_data = Vec::with_capacity(width * height + 2 * chroma_width * chroma_height);
+        for row in yuv.y().chunks(y_stride).take(height) {
+            yuv_data.extend_from_slice(&row[..width]);
+        }
+        for (plane, stride) in [(yuv.u(), u_stride), (yuv.v(), v_stride)] {
+            for row in plane.chunks(stride).take(chroma_height) {
+                yuv_data.extend_from_slice(&row[..chroma_width]);
+            }
+        }
 
         Ok(Some(DecodedFrame {
             pts,
@@ -575,6 +578,243 @@ pub fn decoder_capabilities() -> HashMap<String, serde_json::Value> {
     caps
 }
 
+// ============================================================================
+// File Decoding
+// ============================================================================
+
+/// Which packets `decode_file` hands to the decoder
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileDecodeOptions {
+    /// Only decode keyframes (trickplay, quick previews)
+    pub keyframes_only: bool,
+    /// With `keyframes_only`, skip keyframes closer than this to the last one decoded
+    pub min_interval_ms: u64,
+    /// Start from the keyframe before this time where the container can seek
+    /// (MP4); other containers decode from the beginning
+    pub start_ms: u64,
+}
+
+/// Demuxed video packet with its timestamp in ms
+struct FilePacket {
+    data: Vec<u8>,
+    pts_ms: i64,
+    keyframe: bool,
+}
+
+enum FileVideoSource {
+    Mkv {
+        demuxer: Box<crate::mkv::MkvDemuxer<std::io::BufReader<std::fs::File>>>,
+        track: u64,
+        /// NAL length size when H.264 arrives in AVCC form
+        avcc: Option<usize>,
+    },
+    Mp4 {
+        demuxer: crate::mp4_demux::mp4::Mp4Demuxer<std::io::BufReader<std::fs::File>>,
+        index: u32,
+    },
+}
+
+impl FileVideoSource {
+    /// Open the first video track and build a decoder for it. Any SPS/PPS
+    /// that must precede the first packet is returned alongside.
+    fn open(path: &std::path::Path) -> Result<(Self, DecoderConfig, Option<Vec<u8>>), String> {
+        use crate::mp4_demux::{CodecId, CodecType, VideoCodec};
+
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let file = std::fs::File::open(path).map_err(|e| format!("Open error: {}", e))?;
+        let reader = std::io::BufReader::new(file);
+
+        match ext.as_str() {
+            "mkv" | "mka" | "webm" => {
+                let info = crate::mkv::MkvParser::new().parse(path)?;
+                let demuxer = crate::mkv::MkvDemuxer::new(reader, info.clone())?;
+                let track = demuxer.video_track().ok_or("No video track found in MKV")?;
+                let video = info
+                    .tracks
+                    .iter()
+                    .find_map(|t| match t {
+                        crate::mkv::MkvTrack::Video(v) => Some(v.clone()),
+                        _ => None,
+                    })
+                    .ok_or("No video track found in MKV")?;
+                let codec = match video.codec_id.as_str() {
+                    "V_MPEG4/ISO/AVC" => HwCodec::H264,
+                    "V_MPEGH/ISO/HEVC" => HwCodec::H265,
+                    "V_VP8" => HwCodec::VP8,
+                    "V_VP9" => HwCodec::VP9,
+                    "V_AV1" => HwCodec::AV1,
+                    "V_MPEG2" => HwCodec::MPEG2,
+                    other => return Err(format!("Unsupported MKV codec: {}", other)),
+                };
+                let avcc = match (codec, &video.codec_private) {
+                    (HwCodec::H264, Some(extra)) => crate::h264_utils::parse_avcc_extradata(extra),
+                    _ => None,
+                };
+                let config = DecoderConfig {
+                    codec,
+                    width: video.pixel_width,
+                    height: video.pixel_height,
+                    preferred_backend: None,
+                    allow_software_fallback: true,
+                    extra_data: video.codec_private.clone(),
+                };
+                let (prefix, avcc) = match avcc {
+                    Some((sps_pps, size)) => (Some(sps_pps), Some(size)),
+                    None => (None, None),
+                };
+                Ok((
+                    Self::Mkv {
+                        demuxer: Box::new(demuxer),
+                        track,
+                        avcc,
+                    },
+                    config,
+                    prefix,
+                ))
+            }
+            "mp4" | "m4v" | "mov" => {
+                let demuxer = crate::mp4_demux::mp4::Mp4Demuxer::new(reader)
+                    .map_err(|e| format!("Demux init: {}", e))?;
+                let streams = demuxer.streams();
+                let (index, stream) = streams
+                    .iter()
+                    .enumerate()
+                    .find(|(_, s)| matches!(s.codec_type, CodecType::Video))
+                    .ok_or("No video stream found in MP4")?;
+                let codec = match &stream.codec {
+                    CodecId::Video(VideoCodec::H264) => HwCodec::H264,
+                    CodecId::Video(VideoCodec::H265) => HwCodec::H265,
+                    CodecId::Video(VideoCodec::VP8) => HwCodec::VP8,
+                    CodecId::Video(VideoCodec::VP9) => HwCodec::VP9,
+                    CodecId::Video(VideoCodec::AV1) => HwCodec::AV1,
+                    CodecId::Video(VideoCodec::MPEG2) => HwCodec::MPEG2,
+                    CodecId::Video(VideoCodec::VC1) => HwCodec::VC1,
+                    other => return Err(format!("Unsupported MP4 codec: {:?}", other)),
+                };
+                let (width, height) = demuxer
+                    .video_info(index)
+                    .map(|v| (v.width, v.height))
+                    .unwrap_or((1920, 1080));
+                let config = DecoderConfig {
+                    codec,
+                    width,
+                    height,
+                    preferred_backend: None,
+                    allow_software_fallback: true,
+                    extra_data: Some(stream.extra_data.clone()),
+                };
+                Ok((
+                    Self::Mp4 {
+                        demuxer,
+                        index: index as u32,
+                    },
+                    config,
+                    None,
+                ))
+            }
+            other => Err(format!("Unsupported container for decoding: {}", other)),
+        }
+    }
+
+    fn seek(&mut self, time_ms: u64) {
+        let result = match self {
+            Self::Mkv { demuxer, .. } => demuxer.seek(time_ms),
+            Self::Mp4 { demuxer, .. } => demuxer.seek(time_ms as i64 * 1000),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Seek to {} ms failed: {}", time_ms, e);
+        }
+    }
+
+    fn next_packet(&mut self) -> Option<FilePacket> {
+        match self {
+            Self::Mkv {
+                demuxer,
+                track,
+                avcc,
+            } => loop {
+                let packet = demuxer.read_packet()?;
+                if packet.track_number != *track {
+                    continue;
+                }
+                let data = match avcc {
+                    Some(size) if !crate::h264_utils::is_annexb(&packet.data) => {
+                        crate::h264_utils::avcc_to_annexb(&packet.data, *size)
+                    }
+                    _ => packet.data,
+                };
+                return Some(FilePacket {
+                    data,
+                    pts_ms: packet.pts_ms,
+                    keyframe: packet.keyframe,
+                });
+            },
+            Self::Mp4 { demuxer, index } => loop {
+                let packet = demuxer.read_packet()?;
+                if packet.stream_index != *index {
+                    continue;
+                }
+                // MP4 packet timestamps are in microseconds
+                return Some(FilePacket {
+                    data: packet.data,
+                    pts_ms: packet.pts / 1000,
+                    keyframe: packet.keyframe,
+                });
+            },
+        }
+    }
+}
+
+/// Demux an MKV/MP4 file and decode its first video track, calling `on_frame`
+/// with each frame (its `pts` is in ms). Return `Ok(false)` to stop early.
+pub fn decode_file<F>(
+    path: &std::path::Path,
+    options: FileDecodeOptions,
+    mut on_frame: F,
+) -> Result<(), String>
+where
+    F: FnMut(DecodedFrame) -> Result<bool, String>,
+{
+    let (mut source, config, prefix) = FileVideoSource::open(path)?;
+    let mut decoder = HwDecoder::new(config)?;
+    if let Some(sps_pps) = prefix {
+        if let Err(e) = decoder.decode(&sps_pps, 0) {
+            tracing::warn!("SPS/PPS feed error (may be ok): {}", e);
+        }
+    }
+
+    if options.start_ms > 0 {
+        source.seek(options.start_ms);
+    }
+
+    let mut last_fed: Option<i64> = None;
+    while let Some(packet) = source.next_packet() {
+        if options.keyframes_only {
+            let too_close =
+                last_fed.is_some_and(|last| packet.pts_ms < last + options.min_interval_ms as i64);
+            if !packet.keyframe || too_close {
+                continue;
+            }
+        }
+        last_fed = Some(packet.pts_ms);
+        if let Some(frame) = decoder.decode(&packet.data, packet.pts_ms)? {
+            if !on_frame(frame)? {
+                return Ok(());
+            }
+        }
+    }
+    for frame in decoder.flush() {
+        if !on_frame(frame)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
 // ============================================================================
 // Public Rust API
 // ============================================================================
@@ -694,4 +934,40 @@ mod tests {
         assert!(HwDecoderType::Nvdec.priority() < HwDecoderType::Software.priority());
         assert!(HwDecoderType::Amf.priority() < HwDecoderType::Vaapi.priority());
     }
+
+    #[test]
+    fn test_decode_file_mkv_timestamps() {
+        use crate::test_support::{write_h264_mkv, H264_FRAME_MS, H264_HEIGHT, H264_WIDTH};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("source.mkv");
+        write_h264_mkv(&path, 50, 10);
+
+        let mut all = Vec::new();
+        decode_file(&path, FileDecodeOptions::default(), |frame| {
+            assert_eq!(
+                (frame.width as usize, frame.height as usize),
+                (H264_WIDTH, H264_HEIGHT)
+            );
+            all.push(frame.pts);
+            Ok(true)
+        })
+        .unwrap();
+        let expected: Vec<i64> = (0..50).map(|i| i * H264_FRAME_MS).collect();
+        assert_eq!(all, expected);
+
+        // Trickplay-style keyframe sampling spaces frames by their real times
+        let options = FileDecodeOptions {
+            keyframes_only: true,
+            min_interval_ms: 700,
+            ..Default::default()
+        };
+        let mut keys = Vec::new();
+        decode_file(&path, options, |frame| {
+            keys.push(frame.pts);
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(keys, vec![0, 800, 1600]);
+    }
 }
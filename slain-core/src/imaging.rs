@@ -11,6 +11,8 @@ use image::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::lut::{self, Lut, LutInterpolation};
+
 // ============================================================================
 // Types
 // ============================================================================
@@ -91,6 +93,14 @@ pub struct ColorAdjustments {
     pub hue_rotate: f32, // Degrees
     pub invert: bool,
     pub grayscale: bool,
+    #[serde(default)]
+    pub lut_path: Option<String>, // .cube / .3dl
+    #[serde(default = "default_lut_strength")]
+    pub lut_strength: f32, // 0.0 to 1.0
+}
+
+fn default_lut_strength() -> f32 {
+    1.0
 }
 
 impl Default for ColorAdjustments {
@@ -102,6 +112,8 @@ impl Default for ColorAdjustments {
             hue_rotate: 0.0,
             invert: false,
             grayscale: false,
+            lut_path: None,
+            lut_strength: default_lut_strength(),
         }
     }
 }
@@ -351,6 +363,21 @@ pub fn adjust_colors(img: &DynamicImage, adjustments: &ColorAdjustments) -> Dyna
         result = rotate_hue(&result, adjustments.hue_rotate);
     }
 
+    // Apply LUT (grade sits on top of the basic corrections)
+    if let Some(path) = &adjustments.lut_path {
+        match lut::load_lut(path) {
+            Ok(table) => {
+                result = apply_lut(
+                    &result,
+                    &table,
+                    adjustments.lut_strength,
+                    LutInterpolation::Tetrahedral,
+                )
+            }
+            Err(e) => tracing::warn!("Skipping LUT {}: {}", path, e),
+        }
+    }
+
     // Invert
     if adjustments.invert {
         result.invert();
@@ -359,6 +386,17 @@ pub fn adjust_colors(img: &DynamicImage, adjustments: &ColorAdjustments) -> Dyna
     result
 }
 
+pub fn apply_lut(
+    img: &DynamicImage,
+    table: &Lut,
+    strength: f32,
+    interpolation: LutInterpolation,
+) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    table.apply_rgb8(&mut rgba, 4, strength, interpolation);
+    DynamicImage::ImageRgba8(rgba)
+}
+
 fn adjust_brightness_contrast(img: &DynamicImage, brightness: f32, contrast: f32) -> DynamicImage {
     let rgba = img.to_rgba8();
     let (width, height) = rgba.dimensions();
@@ -668,6 +706,8 @@ pub async fn image_adjust(
     hue_rotate: Option<f32>,
     grayscale: Option<bool>,
     invert: Option<bool>,
+    lut_path: Option<String>,
+    lut_strength: Option<f32>,
     quality: Option<u8>,
 ) -> Result<ImageInfo, String> {
     let img = load_image(&input_path)?;
@@ -679,6 +719,8 @@ pub async fn image_adjust(
         hue_rotate: hue_rotate.unwrap_or(0.0),
         grayscale: grayscale.unwrap_or(false),
         invert: invert.unwrap_or(false),
+        lut_path,
+        lut_strength: lut_strength.unwrap_or(1.0).clamp(0.0, 1.0),
     };
 
     let adjusted = adjust_colors(&img, &adjustments);
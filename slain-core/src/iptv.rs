@@ -3,14 +3,20 @@
 //! Features:
 //! - M3U/M3U8 playlist parsing
 //! - IPTV channel management
-//! - EPG (Electronic Program Guide) support
+//! - EPG (Electronic Program Guide): streaming XMLTV parsing (plain or
+//!   gzip'd), time-range and grid queries, fuzzy `tvg-id` matching
 //! - Live TV categories
-//! - Recording support
+//! - Catch-up (archive) URLs from the M3U `catchup` attributes
+//! - Xtream Codes and Stalker portals (see `iptv_providers`)
+//! - Stream health checks and failover (see `iptv_health`)
+//! - Recording and timeshift (see `iptv_recording`)
 
 use chrono::TimeZone;
-use regex::Regex;
+use flate2::read::GzDecoder;
+use quick_xml::events::{BytesStart, Event};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
 
 // ============================================================================
 // Channel Types
@@ -28,6 +34,9 @@ pub struct IptvChannel {
     pub language: Option<String>,
     pub is_favorite: bool,
     pub last_watched: Option<i64>,
+    /// Archive playback of past programs, when the provider keeps one
+    #[serde(default)]
+    pub catchup: Option<Catchup>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,11 +63,13 @@ pub fn parse_m3u(content: &str) -> Result<IptvPlaylist, String> {
     let mut groups = std::collections::HashSet::new();
     let mut lines = content.lines().peekable();
 
-    // Check for #EXTM3U header
+    // Check for #EXTM3U header; its catch-up attributes apply to every channel
+    let mut catchup_defaults = CatchupAttributes::default();
     if let Some(first) = lines.next() {
         if !first.trim().starts_with("#EXTM3U") {
             return Err("Invalid M3U file: missing #EXTM3U header".to_string());
         }
+        catchup_defaults = CatchupAttributes::parse(first);
     }
 
     let mut current_info: Option<ExtInf> = None;
@@ -90,6 +101,7 @@ pub fn parse_m3u(content: &str) -> Result<IptvPlaylist, String> {
                     language: info.tvg_language,
                     is_favorite: false,
                     last_watched: None,
+                    catchup: info.catchup.or(&catchup_defaults).build(),
                 });
             }
         }
@@ -115,6 +127,7 @@ struct ExtInf {
     tvg_language: Option<String>,
     group: Option<String>,
     logo: Option<String>,
+    catchup: CatchupAttributes,
 }
 
 fn parse_extinf(line: &str) -> Result<ExtInf, String> {
@@ -157,6 +170,7 @@ fn parse_extinf(line: &str) -> Result<ExtInf, String> {
         tvg_language,
         group,
         logo: tvg_logo,
+        catchup: CatchupAttributes::parse(attrs_part),
     })
 }
 
@@ -171,6 +185,281 @@ fn extract_attribute(text: &str, attr_name: &str) -> Option<String> {
     None
 }
 
+// ============================================================================
+// Catch-up
+// ============================================================================
+
+/// How a channel's archive URL is derived, as in the M3U `catchup` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CatchupMode {
+    /// `catchup-source` is the whole archive URL
+    Default,
+    /// `catchup-source` is appended to the live URL
+    Append,
+    /// `utc`/`lutc` query parameters on the live URL
+    Shift,
+    /// Flussonic archive paths (`index-<start>-<duration>.m3u8`,
+    /// `timeshift_abs-<start>.ts`)
+    Flussonic,
+    /// Xtream Codes `/timeshift/` URLs
+    Xc,
+}
+
+impl CatchupMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "default" => Some(Self::Default),
+            "append" => Some(Self::Append),
+            "shift" | "timeshift" => Some(Self::Shift),
+            "flussonic" | "flussonic-hls" | "flussonic-ts" | "fs" => Some(Self::Flussonic),
+            "xc" => Some(Self::Xc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catchup {
+    pub mode: CatchupMode,
+    /// URL template; `Shift`, `Flussonic` and `Xc` derive one from the live
+    /// URL when it is missing
+    pub source: Option<String>,
+    /// How far back the archive reaches
+    pub days: Option<u32>,
+}
+
+/// Raw catch-up attributes of an `#EXTINF` or `#EXTM3U` line
+#[derive(Debug, Default)]
+struct CatchupAttributes {
+    mode: Option<String>,
+    source: Option<String>,
+    days: Option<String>,
+}
+
+impl CatchupAttributes {
+    fn parse(attrs: &str) -> Self {
+        Self {
+            mode: extract_attribute(attrs, "catchup"),
+            source: extract_attribute(attrs, "catchup-source"),
+            days: extract_attribute(attrs, "catchup-days")
+                .or_else(|| extract_attribute(attrs, "tvg-rec"))
+                .or_else(|| extract_attribute(attrs, "timeshift")),
+        }
+    }
+
+    /// Channel attributes, falling back to the playlist-wide ones
+    fn or(self, defaults: &Self) -> Self {
+        Self {
+            mode: self.mode.or_else(|| defaults.mode.clone()),
+            source: self.source.or_else(|| defaults.source.clone()),
+            days: self.days.or_else(|| defaults.days.clone()),
+        }
+    }
+
+    fn build(self) -> Option<Catchup> {
+        let source = self.source.filter(|s| !s.trim().is_empty());
+        let mode = match self.mode.as_deref() {
+            Some(mode) => CatchupMode::parse(mode)?,
+            // A bare catchup-source is a complete template
+            None if source.is_some() => CatchupMode::Default,
+            None => return None,
+        };
+        Some(Catchup {
+            mode,
+            source,
+            days: self.days.and_then(|d| d.trim().parse().ok()),
+        })
+    }
+}
+
+/// Archive URL playing `start..end` (Unix seconds) of `channel`, given the
+/// current time `now`. Templates understand `${start}`/`{utc}`,
+/// `${end}`/`{utcend}`, `${now}`/`{lutc}`/`${timestamp}`, `${duration}`,
+/// `${offset}` (seconds since start; `{duration:60}` divides), the start
+/// time fields `{Y}` `{m}` `{d}` `{H}` `{M}` `{S}` and formatted times such
+/// as `${start:Y-m-d}`, all in UTC
+pub fn catchup_url(
+    channel: &IptvChannel,
+    start: i64,
+    end: i64,
+    now: i64,
+) -> Result<String, String> {
+    let catchup = channel
+        .catchup
+        .as_ref()
+        .ok_or_else(|| format!("{} has no catch-up", channel.name))?;
+    if end <= start {
+        return Err("Catch-up range is empty".to_string());
+    }
+    if start >= now {
+        return Err("Catch-up is only available for past programs".to_string());
+    }
+    if let Some(days) = catchup.days {
+        if start < now - days as i64 * 86_400 {
+            return Err(format!(
+                "{} keeps only {} days of archive",
+                channel.name, days
+            ));
+        }
+    }
+
+    let live = channel.stream_url.as_str();
+    let template = match (catchup.mode, &catchup.source) {
+        (CatchupMode::Append, Some(source)) => format!("{}{}", live, source),
+        (CatchupMode::Append | CatchupMode::Default, None) => {
+            return Err(format!("{} has no catchup-source", channel.name))
+        }
+        (_, Some(source)) => source.clone(),
+        (CatchupMode::Shift, None) => {
+            let separator = if live.contains('?') { '&' } else { '?' };
+            format!("{}{}utc={{utc}}&lutc={{lutc}}", live, separator)
+        }
+        (CatchupMode::Flussonic, None) => flussonic_template(live)?,
+        (CatchupMode::Xc, None) => xc_template(live)?,
+    };
+    Ok(fill_catchup_template(&template, start, end, now))
+}
+
+/// Archive URL of a past EPG program on `channel`
+pub fn program_catchup_url(channel: &IptvChannel, program: &EpgProgram) -> Result<String, String> {
+    catchup_url(
+        channel,
+        program.start_time,
+        program.end_time,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// `http://host/chan/index.m3u8?t=1` -> `http://host/chan/index-${start}-${duration}.m3u8?t=1`;
+/// `http://host/chan/mpegts?t=1` -> `http://host/chan/timeshift_abs-${start}.ts?t=1`
+fn flussonic_template(live: &str) -> Result<String, String> {
+    let (base, query) = match live.split_once('?') {
+        Some((base, query)) => (base, format!("?{}", query)),
+        None => (live, String::new()),
+    };
+    let (dir, file) = base
+        .rsplit_once('/')
+        .filter(|(dir, _)| dir.contains("://") && dir.matches('/').count() >= 3)
+        .ok_or_else(|| format!("Not a Flussonic stream URL: {}", live))?;
+
+    if file == "mpegts" {
+        return Ok(format!("{}/timeshift_abs-${{start}}.ts{}", dir, query));
+    }
+    match file.strip_suffix(".m3u8") {
+        Some(list) if !list.is_empty() => Ok(format!(
+            "{}/{}-${{start}}-${{duration}}.m3u8{}",
+            dir, list, query
+        )),
+        _ => Err(format!("Not a Flussonic stream URL: {}", live)),
+    }
+}
+
+/// `http://host/live/user/pass/101.ts` ->
+/// `http://host/timeshift/user/pass/{duration:60}/{Y}-{m}-{d}:{H}-{M}/101.ts`
+fn xc_template(live: &str) -> Result<String, String> {
+    let invalid = || format!("Not an Xtream Codes stream URL: {}", live);
+    let url = url::Url::parse(live).map_err(|_| invalid())?;
+    let mut segments: Vec<&str> = url
+        .path_segments()
+        .ok_or_else(invalid)?
+        .filter(|s| !s.is_empty())
+        .collect();
+    if segments.len() < 3 {
+        return Err(invalid());
+    }
+    let file = segments.pop().unwrap_or_default();
+    let password = segments.pop().unwrap_or_default();
+    let username = segments.pop().unwrap_or_default();
+    if segments.last() == Some(&"live") {
+        segments.pop();
+    }
+    let (id, extension) = match file.rsplit_once('.') {
+        Some((id, ext)) if ext.eq_ignore_ascii_case("m3u8") => (id, "m3u8"),
+        Some((id, _)) => (id, "ts"),
+        None => (file, "ts"),
+    };
+
+    let origin = url.origin().ascii_serialization();
+    let prefix: String = segments.iter().map(|s| format!("/{}", s)).collect();
+    Ok(format!(
+        "{}{}/timeshift/{}/{}/{{duration:60}}/{{Y}}-{{m}}-{{d}}:{{H}}-{{M}}/{}.{}",
+        origin, prefix, username, password, id, extension
+    ))
+}
+
+/// Replace the catch-up placeholders of `template`; unknown ones are kept
+fn fill_catchup_template(template: &str, start: i64, end: i64, now: i64) -> String {
+    let format_time = |time: i64, format: &str| -> String {
+        let Some(time) = chrono::Utc.timestamp_opt(time, 0).single() else {
+            return String::new();
+        };
+        let mut out = String::new();
+        for c in format.chars() {
+            match c {
+                'Y' => out.push_str(&time.format("%Y").to_string()),
+                'm' => out.push_str(&time.format("%m").to_string()),
+                'd' => out.push_str(&time.format("%d").to_string()),
+                'H' => out.push_str(&time.format("%H").to_string()),
+                'M' => out.push_str(&time.format("%M").to_string()),
+                'S' => out.push_str(&time.format("%S").to_string()),
+                other => out.push(other),
+            }
+        }
+        out
+    };
+    let value = |name: &str, arg: Option<&str>| -> Option<String> {
+        let time = match name {
+            "utc" | "start" => Some(start),
+            "utcend" | "end" => Some(end),
+            "lutc" | "now" | "timestamp" => Some(now),
+            _ => None,
+        };
+        if let Some(time) = time {
+            return Some(match arg {
+                Some(format) => format_time(time, format),
+                None => time.to_string(),
+            });
+        }
+        let seconds = match name {
+            "duration" => end - start,
+            "offset" => now - start,
+            "Y" | "m" | "d" | "H" | "M" | "S" if arg.is_none() => {
+                return Some(format_time(start, name))
+            }
+            _ => return None,
+        };
+        let divider = match arg {
+            Some(arg) => arg.trim().parse::<i64>().ok().filter(|d| *d > 0)?,
+            None => 1,
+        };
+        Some((seconds / divider).to_string())
+    };
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let dollar = open > 0 && rest.as_bytes()[open - 1] == b'$';
+        let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        let inner = &rest[open + 1..close];
+        let (name, arg) = match inner.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (inner, None),
+        };
+        match value(name, arg) {
+            Some(value) => {
+                out.push_str(&rest[..if dollar { open - 1 } else { open }]);
+                out.push_str(&value);
+            }
+            None => out.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
 // ============================================================================
 // EPG (Electronic Program Guide)
 // ============================================================================
@@ -179,82 +468,500 @@ fn extract_attribute(text: &str, attr_name: &str) -> Option<String> {
 pub struct EpgProgram {
     pub channel_id: String,
     pub title: String,
+    #[serde(default)]
+    pub sub_title: Option<String>,
     pub description: Option<String>,
     pub start_time: i64,
     pub end_time: i64,
     pub category: Option<String>,
+    /// `S01E05` from `xmltv_ns` numbering, else the on-screen form
+    #[serde(default)]
+    pub episode: Option<String>,
+    #[serde(default)]
+    pub rating: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// XMLTV `<channel>` metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EpgChannel {
+    pub id: String,
+    pub display_names: Vec<String>,
     pub icon: Option<String>,
+    pub url: Option<String>,
 }
 
+/// One channel's row of an EPG grid
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpgGridRow {
+    pub channel_id: String,
+    pub name: String,
+    pub programs: Vec<EpgProgram>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EpgData {
+    /// Programs per guide channel, sorted by start and non-overlapping
     pub channels: HashMap<String, Vec<EpgProgram>>,
+    #[serde(default)]
+    pub channel_info: HashMap<String, EpgChannel>,
     pub last_updated: i64,
 }
 
-/// Parse XMLTV format EPG
-pub fn parse_xmltv(content: &str) -> Result<EpgData, String> {
-    let mut channels: HashMap<String, Vec<EpgProgram>> = HashMap::new();
-    let program_re = Regex::new(
-        r#"<programme[^>]*channel="(?P<channel>[^"]+)"[^>]*start="(?P<start>[^"]+)"[^>]*stop="(?P<stop>[^"]+)"[^>]*>(?s:.*?)</programme>"#,
-    )
-    .map_err(|e| format!("XMLTV regex error: {}", e))?;
-    let title_re = Regex::new(r#"<title[^>]*>(?P<title>[^<]+)</title>"#)
-        .map_err(|e| format!("XMLTV regex error: {}", e))?;
-    let desc_re = Regex::new(r#"<desc[^>]*>(?P<desc>[^<]+)</desc>"#)
-        .map_err(|e| format!("XMLTV regex error: {}", e))?;
-    let category_re = Regex::new(r#"<category[^>]*>(?P<cat>[^<]+)</category>"#)
-        .map_err(|e| format!("XMLTV regex error: {}", e))?;
-    let icon_re = Regex::new(r#"<icon[^>]*src="(?P<src>[^"]+)""#)
-        .map_err(|e| format!("XMLTV regex error: {}", e))?;
-
-    for caps in program_re.captures_iter(content) {
-        let channel_id = caps
-            .name("channel")
-            .map(|m| m.as_str())
-            .unwrap_or("")
-            .to_string();
-        let start_time = parse_xmltv_time(caps.name("start").map(|m| m.as_str()).unwrap_or(""))?;
-        let end_time = parse_xmltv_time(caps.name("stop").map(|m| m.as_str()).unwrap_or(""))?;
-        let block = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-        let title = title_re
-            .captures(block)
-            .and_then(|c| c.name("title").map(|m| m.as_str().to_string()))
-            .unwrap_or_else(|| "Unknown".to_string());
-        let description = desc_re
-            .captures(block)
-            .and_then(|c| c.name("desc").map(|m| m.as_str().to_string()));
-        let category = category_re
-            .captures(block)
-            .and_then(|c| c.name("cat").map(|m| m.as_str().to_string()));
-        let icon = icon_re
-            .captures(block)
-            .and_then(|c| c.name("src").map(|m| m.as_str().to_string()));
+/// End time of a programme without `stop` until the index closes it
+const OPEN_END: i64 = i64::MAX;
+
+impl EpgData {
+    /// Sort each channel and make the programs non-overlapping, so time
+    /// range lookups can bisect on both start and end
+    pub(crate) fn build_index(&mut self) {
+        for programs in self.channels.values_mut() {
+            // Stable sort, so a repeated start time keeps the last entry
+            programs.sort_by_key(|p| p.start_time);
+            programs.reverse();
+            programs.dedup_by_key(|p| p.start_time);
+            programs.reverse();
+
+            // Open-ended programmes run until the next; overlaps (guide
+            // errors) are cut at the next start
+            for i in 1..programs.len() {
+                let next_start = programs[i].start_time;
+                let previous = &mut programs[i - 1];
+                previous.end_time = previous.end_time.min(next_start);
+            }
+            programs.retain(|p| p.end_time > p.start_time && p.end_time != OPEN_END);
+        }
+        self.channels.retain(|_, programs| !programs.is_empty());
+    }
+
+    /// Programs on `channel_id` overlapping `from..to` (Unix seconds)
+    pub fn programs_between(&self, channel_id: &str, from: i64, to: i64) -> &[EpgProgram] {
+        let Some(programs) = self.channels.get(channel_id) else {
+            return &[];
+        };
+        let first = programs.partition_point(|p| p.end_time <= from);
+        let last = programs.partition_point(|p| p.start_time < to);
+        &programs[first..last.max(first)]
+    }
+
+    /// Program airing on `channel_id` at `time`
+    pub fn program_at(&self, channel_id: &str, time: i64) -> Option<&EpgProgram> {
+        self.programs_between(channel_id, time, time + 1).first()
+    }
+
+    /// First display name, falling back to the ID
+    pub fn channel_name<'a>(&'a self, channel_id: &'a str) -> &'a str {
+        self.channel_info
+            .get(channel_id)
+            .and_then(|info| info.display_names.first())
+            .map_or(channel_id, |name| name.as_str())
+    }
+
+    /// Every guide channel with programs in `from..to`, ordered by name
+    pub fn grid(&self, from: i64, to: i64) -> Vec<EpgGridRow> {
+        let mut rows: Vec<EpgGridRow> = self
+            .channels
+            .keys()
+            .filter_map(|id| {
+                let programs = self.programs_between(id, from, to);
+                (!programs.is_empty()).then(|| EpgGridRow {
+                    channel_id: id.clone(),
+                    name: self.channel_name(id).to_string(),
+                    programs: programs.to_vec(),
+                })
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.channel_id.cmp(&b.channel_id))
+        });
+        rows
+    }
+
+    /// Guide channel for a playlist entry, see [`EpgData::match_channels`]
+    pub fn match_channel(&self, channel: &IptvChannel) -> Option<&str> {
+        self.match_channels(std::slice::from_ref(channel))
+            .pop()
+            .flatten()
+    }
+
+    /// Guide channel for each playlist entry: the `tvg-id` as is, then IDs
+    /// and display names compared loosely (case, punctuation, quality tags
+    /// and country suffixes ignored), then the closest similar name
+    pub fn match_channels(&self, channels: &[IptvChannel]) -> Vec<Option<&str>> {
+        let mut ids: Vec<&str> = self
+            .channels
+            .keys()
+            .chain(self.channel_info.keys())
+            .map(|id| id.as_str())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        // Normalized key -> guide ID; IDs first, so they win over names
+        let mut keys: HashMap<String, &str> = HashMap::new();
+        let mut lowercase: HashMap<String, &str> = HashMap::new();
+        for &id in &ids {
+            lowercase.entry(id.to_lowercase()).or_insert(id);
+            keys.entry(channel_key(id)).or_insert(id);
+        }
+        for &id in &ids {
+            for name in self
+                .channel_info
+                .get(id)
+                .iter()
+                .flat_map(|i| &i.display_names)
+            {
+                keys.entry(channel_key(name)).or_insert(id);
+            }
+        }
+        keys.remove("");
+        let mut candidates: Vec<(&String, &str)> = keys.iter().map(|(k, &id)| (k, id)).collect();
+        candidates.sort_unstable();
 
         channels
-            .entry(channel_id.clone())
-            .or_default()
-            .push(EpgProgram {
-                channel_id,
-                title,
-                description,
-                start_time,
-                end_time,
-                category,
-                icon,
-            });
+            .iter()
+            .map(|channel| {
+                if let Some(tvg_id) = channel.epg_id.as_deref().map(str::trim) {
+                    if let Some(&id) = ids.iter().find(|&&id| id == tvg_id) {
+                        return Some(id);
+                    }
+                    if let Some(&id) = lowercase.get(&tvg_id.to_lowercase()) {
+                        return Some(id);
+                    }
+                    if let Some(&id) = keys.get(&channel_key(tvg_id)) {
+                        return Some(id);
+                    }
+                }
+                let name = channel_key(&channel.name);
+                if let Some(&id) = keys.get(&name) {
+                    return Some(id);
+                }
+                if name.chars().count() < 3 {
+                    return None;
+                }
+                candidates
+                    .iter()
+                    .map(|(key, id)| (bigram_similarity(&name, key), *id))
+                    .filter(|(score, _)| *score >= 0.8)
+                    .max_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(_, id)| id)
+            })
+            .collect()
     }
+}
 
-    Ok(EpgData {
-        channels,
-        last_updated: chrono::Utc::now().timestamp(),
-    })
+/// Lowercase alphanumerics of a channel ID or name, without a country
+/// suffix (`BBCOne.uk`), provider prefix (`UK: `, `US | `) or quality tags
+/// (`HD`, `4K`, ...)
+pub(crate) fn channel_key(name: &str) -> String {
+    const TAGS: &[&str] = &[
+        "hd", "fhd", "uhd", "sd", "4k", "8k", "hevc", "h265", "1080p", "720p", "hq",
+    ];
+
+    let mut name = name.trim().to_lowercase();
+    if let Some((head, tail)) = name.split_once([':', '|']) {
+        let head = head.trim();
+        if (2..=3).contains(&head.len()) && head.chars().all(|c| c.is_ascii_alphabetic()) {
+            name = tail.to_string();
+        }
+    }
+    if let Some((head, tail)) = name.rsplit_once('.') {
+        if (2..=3).contains(&tail.len()) && tail.chars().all(|c| c.is_ascii_alphabetic()) {
+            name = head.to_string();
+        }
+    }
+    name.replace('&', " and ")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && !TAGS.contains(token))
+        .collect()
+}
+
+/// Sørensen–Dice coefficient over character bigrams
+fn bigram_similarity(a: &str, b: &str) -> f64 {
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut pairs: Vec<_> = chars.windows(2).map(|w| (w[0], w[1])).collect();
+        pairs.sort_unstable();
+        pairs
+    };
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    // Multiset intersection of the two sorted lists
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    2.0 * common as f64 / (a.len() + b.len()) as f64
+}
+
+// ============================================================================
+// XMLTV Parser
+// ============================================================================
+
+/// Parse an XMLTV document held in memory
+pub fn parse_xmltv(content: &str) -> Result<EpgData, String> {
+    parse_xmltv_stream(content.as_bytes())
+}
+
+/// Parse XMLTV incrementally from `reader`, gzip'd or not, without holding
+/// the document in memory
+pub fn read_xmltv<R: Read>(reader: R) -> Result<EpgData, String> {
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read EPG: {}", e))?
+        .starts_with(&[0x1F, 0x8B]);
+    if gzipped {
+        parse_xmltv_stream(BufReader::new(GzDecoder::new(reader)))
+    } else {
+        parse_xmltv_stream(reader)
+    }
+}
+
+fn parse_xmltv_stream<R: BufRead>(reader: R) -> Result<EpgData, String> {
+    let mut reader = quick_xml::Reader::from_reader(reader);
+    reader.config_mut().trim_text(true);
+    let mut builder = XmltvBuilder::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| format!("XMLTV error at {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(start) => {
+                let name = element_name(&start);
+                builder.open(&name, &start, &path);
+                path.push(name);
+                text.clear();
+            }
+            Event::Empty(start) => {
+                let name = element_name(&start);
+                builder.open(&name, &start, &path);
+                builder.close(&name, &path, "");
+            }
+            Event::Text(raw) => text.push_str(&xml_text(&raw)),
+            Event::CData(raw) => text.push_str(&String::from_utf8_lossy(&raw)),
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                builder.close(&name, &path, text.trim());
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if builder.skipped > 0 {
+        tracing::debug!(
+            "XMLTV: skipped {} programmes with bad times",
+            builder.skipped
+        );
+    }
+    let mut epg = builder.epg;
+    epg.build_index();
+    epg.last_updated = chrono::Utc::now().timestamp();
+    Ok(epg)
+}
+
+fn element_name(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.local_name().as_ref()).into_owned()
+}
+
+fn attribute(start: &BytesStart, name: &str) -> Option<String> {
+    start
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name.as_bytes())
+        .map(|attr| xml_text(&attr.value))
+}
+
+/// Unescaped text; guides in other encodings or with HTML entities are
+/// taken as-is rather than failing the whole document
+fn xml_text(raw: &[u8]) -> String {
+    let text = String::from_utf8_lossy(raw);
+    match quick_xml::escape::unescape(&text) {
+        Ok(unescaped) => unescaped.into_owned(),
+        Err(_) => text.into_owned(),
+    }
+}
+
+/// Collects `<channel>` and `<programme>` elements as the parser streams by
+#[derive(Default)]
+struct XmltvBuilder {
+    epg: EpgData,
+    channel: Option<EpgChannel>,
+    program: Option<EpgProgram>,
+    episode_system: Option<String>,
+    skipped: usize,
+}
+
+impl XmltvBuilder {
+    fn open(&mut self, name: &str, start: &BytesStart, path: &[String]) {
+        let parent = path.last().map(String::as_str);
+        match (name, parent) {
+            ("channel", Some("tv")) => {
+                self.channel = Some(EpgChannel {
+                    id: attribute(start, "id").unwrap_or_default(),
+                    ..Default::default()
+                });
+            }
+            ("programme", Some("tv")) => {
+                let start_time = attribute(start, "start").map(|t| parse_xmltv_time(&t));
+                let end_time = match attribute(start, "stop") {
+                    Some(stop) => parse_xmltv_time(&stop),
+                    None => Ok(OPEN_END),
+                };
+                self.program = match (start_time, end_time) {
+                    (Some(Ok(start_time)), Ok(end_time)) => Some(EpgProgram {
+                        channel_id: attribute(start, "channel").unwrap_or_default(),
+                        title: String::new(),
+                        sub_title: None,
+                        description: None,
+                        start_time,
+                        end_time,
+                        category: None,
+                        episode: None,
+                        rating: None,
+                        icon: None,
+                    }),
+                    _ => {
+                        self.skipped += 1;
+                        None
+                    }
+                };
+            }
+            ("icon", Some("channel")) => {
+                if let Some(channel) = self.channel.as_mut() {
+                    channel.icon = channel.icon.take().or(attribute(start, "src"));
+                }
+            }
+            ("icon", Some("programme")) => {
+                if let Some(program) = self.program.as_mut() {
+                    program.icon = program.icon.take().or(attribute(start, "src"));
+                }
+            }
+            ("episode-num", Some("programme")) => {
+                self.episode_system = attribute(start, "system");
+            }
+            _ => {}
+        }
+    }
+
+    /// `path` no longer includes `name`
+    fn close(&mut self, name: &str, path: &[String], text: &str) {
+        let parent = path.last().map(String::as_str);
+        let set = |field: &mut Option<String>| {
+            if field.is_none() && !text.is_empty() {
+                *field = Some(text.to_string());
+            }
+        };
+
+        match (name, parent) {
+            ("channel", Some("tv")) => {
+                if let Some(channel) = self.channel.take().filter(|c| !c.id.is_empty()) {
+                    self.epg.channel_info.insert(channel.id.clone(), channel);
+                }
+            }
+            ("display-name", Some("channel")) => {
+                if let Some(channel) = self.channel.as_mut() {
+                    if !text.is_empty() && !channel.display_names.iter().any(|n| n == text) {
+                        channel.display_names.push(text.to_string());
+                    }
+                }
+            }
+            ("url", Some("channel")) => {
+                if let Some(channel) = self.channel.as_mut() {
+                    set(&mut channel.url);
+                }
+            }
+            ("programme", Some("tv")) => {
+                if let Some(mut program) = self.program.take() {
+                    if program.title.is_empty() {
+                        program.title = "Unknown".to_string();
+                    }
+                    self.epg
+                        .channels
+                        .entry(program.channel_id.clone())
+                        .or_default()
+                        .push(program);
+                }
+            }
+            (_, Some("programme")) => {
+                let Some(program) = self.program.as_mut() else {
+                    return;
+                };
+                match name {
+                    // The first of several translations wins
+                    "title" if program.title.is_empty() => program.title = text.to_string(),
+                    "sub-title" => set(&mut program.sub_title),
+                    "desc" => set(&mut program.description),
+                    "category" => set(&mut program.category),
+                    "episode-num" => match self.episode_system.take().as_deref() {
+                        Some("xmltv_ns") => {
+                            if let Some(episode) = format_xmltv_ns(text) {
+                                program.episode = Some(episode);
+                            }
+                        }
+                        Some("onscreen") => set(&mut program.episode),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            ("value", Some("rating")) => {
+                let in_programme = path.len() >= 2 && path[path.len() - 2] == "programme";
+                if let Some(program) = self.program.as_mut().filter(|_| in_programme) {
+                    set(&mut program.rating);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `xmltv_ns` numbering (`season.episode.part`, zero-based, each optionally
+/// `/total`) as `S01E05`
+fn format_xmltv_ns(value: &str) -> Option<String> {
+    let mut parts = value.split('.').map(|part| {
+        part.split('/')
+            .next()
+            .and_then(|n| n.trim().parse::<u32>().ok())
+            .map(|n| n + 1)
+    });
+    let season = parts.next().flatten();
+    let episode = parts.next().flatten();
+    match (season, episode) {
+        (Some(s), Some(e)) => Some(format!("S{:02}E{:02}", s, e)),
+        (None, Some(e)) => Some(format!("E{:02}", e)),
+        (Some(s), None) => Some(format!("S{:02}", s)),
+        (None, None) => None,
+    }
 }
 
 fn parse_xmltv_time(raw: &str) -> Result<i64, String> {
-    // Format: YYYYMMDDHHMMSS + optional timezone, we parse first 14 digits.
-    let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
-    if digits.len() < 14 {
+    // Format: YYYYMMDDHHMM[SS] then an optional "+HHMM"/"-HHMM" offset
+    // (none means UTC)
+    let raw = raw.trim();
+    let digit_count = raw.bytes().take_while(u8::is_ascii_digit).count();
+    let digits = &raw[..digit_count];
+    if digits.len() < 12 {
         return Err("Invalid XMLTV time format".to_string());
     }
     let year: i32 = digits[0..4].parse().map_err(|_| "Invalid year")?;
@@ -262,22 +969,39 @@ fn parse_xmltv_time(raw: &str) -> Result<i64, String> {
     let day: u32 = digits[6..8].parse().map_err(|_| "Invalid day")?;
     let hour: u32 = digits[8..10].parse().map_err(|_| "Invalid hour")?;
     let minute: u32 = digits[10..12].parse().map_err(|_| "Invalid minute")?;
-    let second: u32 = digits[12..14].parse().map_err(|_| "Invalid second")?;
+    let second: u32 = match digits.get(12..14) {
+        Some(s) => s.parse().map_err(|_| "Invalid second")?,
+        None => 0,
+    };
     let dt = chrono::NaiveDate::from_ymd_opt(year, month, day)
         .ok_or("Invalid date")?
         .and_hms_opt(hour, minute, second)
         .ok_or("Invalid time")?;
-    Ok(chrono::Utc.from_utc_datetime(&dt).timestamp())
+
+    let zone = raw[digit_count..].trim();
+    let offset = match zone.as_bytes().first() {
+        Some(sign @ (b'+' | b'-')) if zone.len() >= 5 => {
+            let zone = zone
+                .get(..5)
+                .filter(|zone| zone.is_ascii())
+                .ok_or("Invalid time zone")?;
+            let hours: i64 = zone[1..3].parse().map_err(|_| "Invalid time zone")?;
+            let minutes: i64 = zone[3..5].parse().map_err(|_| "Invalid time zone")?;
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => 0,
+    };
+    Ok(chrono::Utc.from_utc_datetime(&dt).timestamp() - offset)
 }
 
 /// Get current program for a channel
 pub fn get_current_program<'a>(epg: &'a EpgData, channel_id: &str) -> Option<&'a EpgProgram> {
-    let now = chrono::Utc::now().timestamp();
-
-    epg.channels
-        .get(channel_id)?
-        .iter()
-        .find(|p| p.start_time <= now && p.end_time > now)
+    epg.program_at(channel_id, chrono::Utc::now().timestamp())
 }
 
 /// Get upcoming programs for a channel
@@ -291,11 +1015,8 @@ pub fn get_upcoming_programs<'a>(
     epg.channels
         .get(channel_id)
         .map(|programs| {
-            programs
-                .iter()
-                .filter(|p| p.start_time >= now)
-                .take(limit)
-                .collect()
+            let first = programs.partition_point(|p| p.start_time < now);
+            programs[first..].iter().take(limit).collect()
         })
         .unwrap_or_default()
 }
@@ -353,17 +1074,69 @@ impl IptvManager {
 
     /// Load EPG from URL
     pub async fn load_epg_url(&mut self, url: &str) -> Result<(), String> {
+        // Kept as bytes (often still gzip'd): the parser streams from them
         let content = reqwest::get(url)
             .await
             .map_err(|e| format!("Failed to fetch EPG: {}", e))?
-            .text()
+            .bytes()
             .await
             .map_err(|e| format!("Failed to read EPG: {}", e))?;
 
-        self.epg_data = Some(parse_xmltv(&content)?);
+        self.epg_data = Some(read_xmltv(&content[..])?);
+        Ok(())
+    }
+
+    /// Load EPG from an XMLTV file (`.xml` or `.xml.gz`)
+    pub fn load_epg_file(&mut self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("Failed to open EPG: {}", e))?;
+        self.epg_data = Some(read_xmltv(file)?);
         Ok(())
     }
 
+    /// Add an already-built playlist (Xtream Codes, Stalker portals)
+    pub fn add_playlist(&mut self, playlist: IptvPlaylist) {
+        self.playlists.push(playlist);
+    }
+
+    /// Merge guide data into the loaded EPG; channels present in both are
+    /// replaced by the incoming data
+    pub fn merge_epg(&mut self, epg: EpgData) {
+        match &mut self.epg_data {
+            Some(current) => {
+                current.channels.extend(epg.channels);
+                current.channel_info.extend(epg.channel_info);
+                current.last_updated = current.last_updated.max(epg.last_updated);
+            }
+            None => self.epg_data = Some(epg),
+        }
+    }
+
+    pub fn epg(&self) -> Option<&EpgData> {
+        self.epg_data.as_ref()
+    }
+
+    /// EPG grid over the playlist channels for `from..to`, in playlist
+    /// order; channels without guide data get an empty row
+    pub fn epg_grid(&self, from: i64, to: i64) -> Vec<EpgGridRow> {
+        let channels: Vec<IptvChannel> = self.get_all_channels().into_iter().cloned().collect();
+        let matches = match &self.epg_data {
+            Some(epg) => epg.match_channels(&channels),
+            None => vec![None; channels.len()],
+        };
+        channels
+            .iter()
+            .zip(matches)
+            .map(|(channel, guide_id)| EpgGridRow {
+                channel_id: channel.id.clone(),
+                name: channel.name.clone(),
+                programs: match (&self.epg_data, guide_id) {
+                    (Some(epg), Some(id)) => epg.programs_between(id, from, to).to_vec(),
+                    _ => Vec::new(),
+                },
+            })
+            .collect()
+    }
+
     /// Get all channels
     pub fn get_all_channels(&self) -> Vec<&IptvChannel> {
         self.playlists
@@ -372,6 +1145,16 @@ impl IptvManager {
             .collect()
     }
 
+    /// Archive URL for `start..end` (Unix seconds) on a loaded channel
+    pub fn catchup_url(&self, channel_id: &str, start: i64, end: i64) -> Result<String, String> {
+        let channel = self
+            .get_all_channels()
+            .into_iter()
+            .find(|c| c.id == channel_id)
+            .ok_or_else(|| format!("Channel not found: {}", channel_id))?;
+        catchup_url(channel, start, end, chrono::Utc::now().timestamp())
+    }
+
     /// Get channels by group
     pub fn get_channels_by_group(&self, group: &str) -> Vec<&IptvChannel> {
         self.playlists
@@ -441,6 +1224,40 @@ pub fn load_iptv_playlist_file(path: String) -> Result<(), String> {
     manager.load_playlist_file(&path)
 }
 
+pub fn load_iptv_epg_file(path: String) -> Result<(), String> {
+    let mut manager = IPTV_MANAGER.lock().map_err(|e| e.to_string())?;
+    manager.load_epg_file(&path)
+}
+
+pub fn add_iptv_playlist(playlist: IptvPlaylist) -> Result<(), String> {
+    let mut manager = IPTV_MANAGER.lock().map_err(|e| e.to_string())?;
+    manager.add_playlist(playlist);
+    Ok(())
+}
+
+pub fn merge_iptv_epg(epg: EpgData) -> Result<(), String> {
+    let mut manager = IPTV_MANAGER.lock().map_err(|e| e.to_string())?;
+    manager.merge_epg(epg);
+    Ok(())
+}
+
+/// Programs on every playlist channel between `from` and `to` (Unix seconds)
+pub fn get_iptv_epg_grid(from: i64, to: i64) -> Vec<EpgGridRow> {
+    let manager = match IPTV_MANAGER.lock() {
+        Ok(m) => m,
+        Err(_) => return Vec::new(),
+    };
+
+    manager.epg_grid(from, to)
+}
+
+/// Archive URL to play a past program (`start..end`, Unix seconds) of a
+/// channel from the guide
+pub fn get_iptv_catchup_url(channel_id: String, start: i64, end: i64) -> Result<String, String> {
+    let manager = IPTV_MANAGER.lock().map_err(|e| e.to_string())?;
+    manager.catchup_url(&channel_id, start, end)
+}
+
 pub fn get_iptv_channels() -> Vec<IptvChannel> {
     let manager = match IPTV_MANAGER.lock() {
         Ok(m) => m,
@@ -526,6 +1343,57 @@ mod tests {
         assert!(err.contains("#EXTM3U"));
     }
 
+    #[test]
+    fn parse_m3u_catchup() {
+        let content = r#"#EXTM3U catchup="shift" catchup-days="2"
+#EXTINF:-1 tvg-id="a",Shifted
+http://host/a.ts
+#EXTINF:-1 catchup="flussonic" catchup-days="7",Fluss HLS
+http://fs.example/chan/index.m3u8?token=abc
+#EXTINF:-1 catchup="fs",Fluss TS
+http://fs.example/chan/mpegts?token=abc
+#EXTINF:-1 catchup="append" catchup-source="?start=${start}&end=${end}&d={duration:60}",Appended
+http://host/b
+#EXTINF:-1 catchup="default" catchup-source="http://arch/c/{Y}/{m}/{d}/{H}{M}{S}-${duration}.ts?now=${now}&off={offset:60}&day=${start:Y-m-d}&x={unknown}",Templated
+http://host/c.ts
+#EXTINF:-1 tvg-rec="3" catchup="xc",Xtream
+http://panel:8080/user/pass/42
+#EXTINF:-1 catchup="vod",Unsupported
+http://host/d.ts
+"#;
+        let playlist = parse_m3u(content).expect("parse m3u");
+        let channels = &playlist.channels;
+        let shifted = channels[0].catchup.as_ref().expect("header default");
+        assert_eq!((shifted.mode, shifted.days), (CatchupMode::Shift, Some(2)));
+        assert_eq!(channels[1].catchup.as_ref().unwrap().days, Some(7));
+        assert_eq!(channels[5].catchup.as_ref().unwrap().mode, CatchupMode::Xc);
+        assert_eq!(channels[5].catchup.as_ref().unwrap().days, Some(3));
+        assert!(channels[6].catchup.is_none());
+
+        // 2024-01-01 12:00 UTC for an hour, watched about a day later
+        let (start, end, now) = (1704110400, 1704114000, 1704200000);
+        let urls: Vec<String> = channels[..6]
+            .iter()
+            .map(|c| catchup_url(c, start, end, now).expect(&c.name))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "http://host/a.ts?utc=1704110400&lutc=1704200000",
+                "http://fs.example/chan/index-1704110400-3600.m3u8?token=abc",
+                "http://fs.example/chan/timeshift_abs-1704110400.ts?token=abc",
+                "http://host/b?start=1704110400&end=1704114000&d=60",
+                "http://arch/c/2024/01/01/120000-3600.ts?now=1704200000&off=1493&day=2024-01-01&x={unknown}",
+                "http://panel:8080/timeshift/user/pass/60/2024-01-01:12-00/42.ts",
+            ]
+        );
+
+        // Outside the archive window, in the future, or without catch-up
+        assert!(catchup_url(&channels[0], now - 3 * 86_400, now - 3 * 86_400 + 60, now).is_err());
+        assert!(catchup_url(&channels[1], now + 60, now + 120, now).is_err());
+        assert!(catchup_url(&channels[6], start, end, now).is_err());
+    }
+
     #[test]
     fn manager_grouping_and_favorites() {
         let now = SystemTime::now()
@@ -584,4 +1452,143 @@ http://example.com/news1\n";
         assert_eq!(program.icon.as_deref(), Some("http://example.com/icon.png"));
         assert!(program.start_time < program.end_time);
     }
+
+    #[test]
+    fn parse_xmltv_streaming_details() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!DOCTYPE tv SYSTEM "xmltv.dtd">
+            <tv generator-info-name="test">
+              <channel id="BBCOne.uk">
+                <display-name lang="en">BBC One</display-name>
+                <display-name>BBC 1</display-name>
+                <icon src="http://example.com/bbc1.png"/>
+                <url>http://bbc.co.uk</url>
+              </channel>
+              <programme channel="BBCOne.uk" start="20240101200000 +0100" stop="20240101210000 +0100">
+                <title lang="en">Doctor Who</title>
+                <title lang="cy">Doctor Who (Cymraeg)</title>
+                <sub-title>Rose &amp; the Doctor</sub-title>
+                <desc><![CDATA[A <b>shop</b> girl.]]></desc>
+                <episode-num system="onscreen">S1 E1</episode-num>
+                <episode-num system="xmltv_ns">0.0/13.</episode-num>
+                <rating system="BBFC"><value>PG</value><icon src="http://example.com/pg.png"/></rating>
+              </programme>
+              <programme channel="BBCOne.uk" start="20240101210000 +0100">
+                <title>News at Ten</title>
+              </programme>
+              <programme channel="BBCOne.uk" start="20240101213000 +0100" stop="20240101220000 +0100">
+                <title>Weather</title>
+              </programme>
+              <programme channel="BBCOne.uk" start="garbage" stop="20240101220000 +0100">
+                <title>Broken</title>
+              </programme>
+              <programme channel="BBCOne.uk" start="20240101220000 +1é0">
+                <title>Broken zone</title>
+              </programme>
+            </tv>"#;
+
+        // Same guide gzip'd
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, xml.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        for epg in [
+            read_xmltv(xml.as_bytes()).unwrap(),
+            read_xmltv(&gzipped[..]).unwrap(),
+        ] {
+            let info = &epg.channel_info["BBCOne.uk"];
+            assert_eq!(info.display_names, vec!["BBC One", "BBC 1"]);
+            assert_eq!(info.icon.as_deref(), Some("http://example.com/bbc1.png"));
+            assert_eq!(info.url.as_deref(), Some("http://bbc.co.uk"));
+
+            let programs = &epg.channels["BBCOne.uk"];
+            assert_eq!(programs.len(), 3);
+            let doctor = &programs[0];
+            assert_eq!(doctor.title, "Doctor Who");
+            assert_eq!(doctor.sub_title.as_deref(), Some("Rose & the Doctor"));
+            assert_eq!(doctor.description.as_deref(), Some("A <b>shop</b> girl."));
+            assert_eq!(doctor.episode.as_deref(), Some("S01E01"));
+            assert_eq!(doctor.rating.as_deref(), Some("PG"));
+            assert_eq!(doctor.icon, None);
+            // 20:00 +0100 is 19:00 UTC
+            assert_eq!(doctor.start_time, 1704135600);
+            assert_eq!(doctor.end_time - doctor.start_time, 3600);
+            // No stop: runs until the next programme
+            assert_eq!(programs[1].end_time, programs[2].start_time);
+        }
+    }
+
+    #[test]
+    fn epg_grid_and_channel_matching() {
+        let xml = r#"<tv>
+              <channel id="BBCOne.uk"><display-name>BBC One</display-name></channel>
+              <channel id="cnn.us"><display-name>CNN International</display-name></channel>
+              <channel id="Eurosport1.fr"><display-name>Eurosport 1</display-name></channel>
+              <programme channel="BBCOne.uk" start="202401011900 +0000" stop="202401012000 +0000"><title>A</title></programme>
+              <programme channel="BBCOne.uk" start="202401012000 +0000" stop="202401012130 +0000"><title>B</title></programme>
+              <programme channel="BBCOne.uk" start="202401012130 +0000" stop="202401012330 +0000"><title>C</title></programme>
+              <programme channel="cnn.us" start="202401012200 +0000" stop="202401012300 +0000"><title>D</title></programme>
+              <programme channel="cnn.us" start="202401020000 +0000" stop="202401020100 +0000"><title>E</title></programme>
+            </tv>"#;
+        let epg = parse_xmltv(xml).unwrap();
+        let at = |h: i64, m: i64| 1704067200 + h * 3600 + m * 60;
+
+        let titles = |programs: &[EpgProgram]| -> Vec<String> {
+            programs.iter().map(|p| p.title.clone()).collect()
+        };
+        assert_eq!(
+            titles(epg.programs_between("BBCOne.uk", at(20, 0), at(23, 0))),
+            ["B", "C"]
+        );
+        assert_eq!(epg.program_at("BBCOne.uk", at(19, 59)).unwrap().title, "A");
+        assert!(epg.program_at("cnn.us", at(21, 0)).is_none());
+
+        let grid = epg.grid(at(20, 0), at(23, 0));
+        let rows: Vec<(&str, Vec<String>)> = grid
+            .iter()
+            .map(|row| (row.name.as_str(), titles(&row.programs)))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("BBC One", vec!["B".to_string(), "C".to_string()]),
+                ("CNN International", vec!["D".to_string()]),
+            ]
+        );
+
+        let channel = |name: &str, tvg_id: Option<&str>| IptvChannel {
+            id: name.to_string(),
+            name: name.to_string(),
+            stream_url: String::new(),
+            logo_url: None,
+            group: None,
+            epg_id: tvg_id.map(str::to_string),
+            country: None,
+            language: None,
+            is_favorite: false,
+            last_watched: None,
+            catchup: None,
+        };
+        let playlist = [
+            channel("Anything", Some("BBCOne.uk")),
+            channel("CNN", Some("CNN.US")),
+            channel("BBC One HD", None),
+            channel("UK: BBC One FHD", Some("bbc1")),
+            channel("Eurosport-1 4K", None),
+            channel("Eurosprt 1", None),
+            channel("Cartoon Network", Some("cartoon.us")),
+        ];
+        assert_eq!(
+            epg.match_channels(&playlist),
+            vec![
+                Some("BBCOne.uk"),
+                Some("cnn.us"),
+                Some("BBCOne.uk"),
+                Some("BBCOne.uk"),
+                Some("Eurosport1.fr"),
+                Some("Eurosport1.fr"),
+                None,
+            ]
+        );
+    }
 }
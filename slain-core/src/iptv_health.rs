@@ -0,0 +1,1115 @@
+//! IPTV Stream Health
+//!
+//! Keeps dead links out of the channel list:
+//! - Concurrent prober: HTTP status, content type, MPEG-TS sync or HLS
+//!   playlist validity, and time to the first video keyframe, with a global
+//!   request rate and a per-host connection limit (panels often allow one
+//!   or two connections per account)
+//! - Probe history per URL, kept in a JSON file, with a verdict that only
+//!   calls a stream dead after repeated failures
+//! - Channel list filtering that hides or demotes dead entries
+//! - Failover between the URLs that carry the same channel (duplicate
+//!   entries, other playlists), used by timeshift playback
+
+use crate::adaptive_stream::{
+    is_hls_master, parse_hls_master, parse_hls_media, ByteRange, HttpConfig, SegmentFormat,
+};
+use crate::http_utils::HostSlots;
+use crate::iptv::{channel_key, IptvChannel};
+use crate::iptv_recording::{
+    is_random_access, packet_pid, pat_pmt_pids, payload_unit_start, TS_PACKET_SIZE, TS_SYNC_BYTE,
+};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Probes remembered per URL
+const HISTORY_LEN: usize = 10;
+
+/// Consecutive failed probes before a stream counts as dead
+const DEAD_AFTER: usize = 3;
+
+/// Largest playlist read while probing
+const MAX_PLAYLIST_BYTES: u64 = 1024 * 1024;
+
+/// Bytes of each video PES searched for a keyframe NAL
+const PES_SCAN_BYTES: usize = 4096;
+
+// ============================================================================
+// Probe Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthStatus {
+    /// Plays: valid stream with a keyframe in good time
+    Healthy,
+    /// Answers, but slow to start, without keyframes or in an unknown format
+    Degraded,
+    Dead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamKind {
+    MpegTs,
+    Hls,
+    Other,
+    /// Never got far enough to tell
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub url: String,
+    /// Unix seconds
+    pub checked_at: i64,
+    pub status: HealthStatus,
+    pub kind: StreamKind,
+    pub http_status: Option<u16>,
+    pub content_type: Option<String>,
+    /// Until the response headers arrived
+    pub response_ms: Option<u64>,
+    /// Until the first video keyframe arrived (through the playlists for HLS)
+    pub keyframe_ms: Option<u64>,
+    /// Why the stream is dead or degraded
+    pub detail: Option<String>,
+}
+
+impl ProbeResult {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            checked_at: chrono::Utc::now().timestamp(),
+            status: HealthStatus::Dead,
+            kind: StreamKind::Unknown,
+            http_status: None,
+            content_type: None,
+            response_ms: None,
+            keyframe_ms: None,
+            detail: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeConfig {
+    pub http: HttpConfig,
+    /// Probes running at once
+    pub concurrency: usize,
+    /// Probes running at once against one host
+    pub per_host: usize,
+    /// Probe starts per second across all workers (0 = unlimited)
+    pub requests_per_sec: f64,
+    /// Bytes read from a stream while waiting for a keyframe
+    pub max_probe_bytes: usize,
+    /// Give up waiting for a keyframe after this long
+    pub keyframe_timeout_ms: u64,
+    /// A first keyframe later than this marks the stream degraded
+    pub slow_keyframe_ms: u64,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            http: HttpConfig::default(),
+            concurrency: 8,
+            per_host: 2,
+            requests_per_sec: 5.0,
+            max_probe_bytes: 4 * 1024 * 1024,
+            keyframe_timeout_ms: 8000,
+            slow_keyframe_ms: 4000,
+        }
+    }
+}
+
+// ============================================================================
+// Keyframe Scanner
+// ============================================================================
+
+/// Follows PAT -> PMT -> first video stream through an MPEG-TS byte stream
+/// fed in arbitrary pieces, until that stream shows a keyframe
+#[derive(Default)]
+struct KeyframeScanner {
+    pending: Vec<u8>,
+    synced: bool,
+    pmt_pids: Vec<u16>,
+    /// PID and stream_type of the video stream
+    video: Option<(u16, u8)>,
+    /// Head of the current video PES
+    pes: Vec<u8>,
+}
+
+/// Offset of three sync bytes a packet apart
+fn find_sync(data: &[u8]) -> Option<usize> {
+    (0..data.len().saturating_sub(2 * TS_PACKET_SIZE)).find(|&i| {
+        data[i] == TS_SYNC_BYTE
+            && data[i + TS_PACKET_SIZE] == TS_SYNC_BYTE
+            && data[i + 2 * TS_PACKET_SIZE] == TS_SYNC_BYTE
+    })
+}
+
+fn ts_payload(packet: &[u8]) -> &[u8] {
+    if packet[3] & 0x10 == 0 {
+        return &[];
+    }
+    let mut offset = 4;
+    if packet[3] & 0x20 != 0 {
+        offset += 1 + packet[4] as usize;
+    }
+    packet.get(offset..).unwrap_or_default()
+}
+
+/// First video elementary stream of a single-packet PMT
+fn pmt_video_stream(packet: &[u8]) -> Option<(u16, u8)> {
+    let payload = ts_payload(packet);
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    if section.len() < 12 || section[0] != 0x02 {
+        return None;
+    }
+    let section_length = (((section[1] & 0x0F) as usize) << 8) | section[2] as usize;
+    let end = (3 + section_length).saturating_sub(4).min(section.len());
+    let program_info_length = (((section[10] & 0x0F) as usize) << 8) | section[11] as usize;
+
+    let mut offset = 12 + program_info_length;
+    while offset + 5 <= end {
+        let stream_type = section[offset];
+        let pid = (((section[offset + 1] & 0x1F) as u16) << 8) | section[offset + 2] as u16;
+        let es_info_length =
+            (((section[offset + 3] & 0x0F) as usize) << 8) | section[offset + 4] as usize;
+        // MPEG-1/2, H.264, HEVC
+        if matches!(stream_type, 0x01 | 0x02 | 0x1B | 0x24) {
+            return Some((pid, stream_type));
+        }
+        offset += 5 + es_info_length;
+    }
+    None
+}
+
+/// IDR/IRAP NAL (H.264, HEVC) or sequence header (MPEG-2) in a PES head
+fn has_keyframe_start(data: &[u8], stream_type: u8) -> bool {
+    data.windows(4)
+        .filter(|w| w[0] == 0 && w[1] == 0 && w[2] == 1)
+        .any(|w| match stream_type {
+            0x1B => w[3] & 0x1F == 5,
+            0x24 => (16..=21).contains(&((w[3] >> 1) & 0x3F)),
+            _ => w[3] == 0xB3,
+        })
+}
+
+impl KeyframeScanner {
+    /// Feed more bytes; true once a keyframe has been seen
+    fn push(&mut self, data: &[u8]) -> bool {
+        self.pending.extend_from_slice(data);
+        loop {
+            if !self.synced {
+                match find_sync(&self.pending) {
+                    Some(start) => {
+                        self.pending.drain(..start);
+                        self.synced = true;
+                    }
+                    None => {
+                        // Keep enough to find a sync spanning the next piece
+                        let keep = self.pending.len().min(3 * TS_PACKET_SIZE);
+                        self.pending.drain(..self.pending.len() - keep);
+                        return false;
+                    }
+                }
+            }
+
+            let mut offset = 0;
+            while offset + TS_PACKET_SIZE <= self.pending.len() {
+                if self.pending[offset] != TS_SYNC_BYTE {
+                    self.synced = false;
+                    break;
+                }
+                let packet = self.pending[offset..offset + TS_PACKET_SIZE].to_vec();
+                offset += TS_PACKET_SIZE;
+                if self.packet(&packet) {
+                    self.pending.drain(..offset);
+                    return true;
+                }
+            }
+            self.pending.drain(..offset);
+            if self.synced {
+                return false;
+            }
+        }
+    }
+
+    fn packet(&mut self, packet: &[u8]) -> bool {
+        let pid = packet_pid(packet);
+        let start = payload_unit_start(packet);
+        if pid == 0 && start {
+            self.pmt_pids = pat_pmt_pids(packet);
+            return false;
+        }
+        if self.video.is_none() && start && self.pmt_pids.contains(&pid) {
+            self.video = pmt_video_stream(packet);
+            return false;
+        }
+        let Some((video_pid, stream_type)) = self.video else {
+            return false;
+        };
+        if pid != video_pid {
+            return false;
+        }
+        if is_random_access(packet) {
+            return true;
+        }
+        if start {
+            self.pes.clear();
+        }
+        if self.pes.len() >= PES_SCAN_BYTES {
+            return false;
+        }
+        // Re-scan the tail too, for a start code split across packets
+        let from = self.pes.len().saturating_sub(3);
+        self.pes.extend_from_slice(ts_payload(packet));
+        has_keyframe_start(&self.pes[from..], stream_type)
+    }
+}
+
+// ============================================================================
+// Prober
+// ============================================================================
+
+fn probe_agent(http: &HttpConfig) -> ureq::Agent {
+    let timeout = Duration::from_secs(http.timeout_secs.max(1));
+    ureq::AgentBuilder::new()
+        .timeout_connect(timeout)
+        .timeout_read(timeout)
+        .user_agent(&http.user_agent)
+        .build()
+}
+
+fn send(
+    agent: &ureq::Agent,
+    http: &HttpConfig,
+    url: &str,
+    range: Option<ByteRange>,
+) -> Result<ureq::Response, (Option<u16>, String)> {
+    let mut request = agent.get(url);
+    for (name, value) in &http.headers {
+        request = request.set(name, value);
+    }
+    if let Some(range) = range {
+        let last = range.offset + range.length.max(1) - 1;
+        request = request.set("Range", &format!("bytes={}-{}", range.offset, last));
+    }
+    match request.call() {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(code, _)) => Err((Some(code), format!("HTTP {}", code))),
+        Err(e) => Err((None, format!("Connection failed: {}", e))),
+    }
+}
+
+/// Body (up to `limit` bytes) and final URL of a playlist or segment
+fn fetch(
+    agent: &ureq::Agent,
+    http: &HttpConfig,
+    url: &str,
+    range: Option<ByteRange>,
+    limit: u64,
+) -> Result<(String, Vec<u8>), String> {
+    let response = send(agent, http, url, range).map_err(|(_, e)| format!("{}: {}", url, e))?;
+    let final_url = response.get_url().to_string();
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(limit)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Read failed for {}: {}", url, e))?;
+    Ok((final_url, data))
+}
+
+/// Probe one stream URL
+pub fn probe_stream(url: &str, config: &ProbeConfig) -> ProbeResult {
+    probe_with(&probe_agent(&config.http), url, config)
+}
+
+fn probe_with(agent: &ureq::Agent, url: &str, config: &ProbeConfig) -> ProbeResult {
+    let started = Instant::now();
+    let mut result = ProbeResult::new(url);
+    match probe_inner(agent, url, config, started, &mut result) {
+        Ok(status) => result.status = status,
+        Err(e) => {
+            result.status = HealthStatus::Dead;
+            result.detail = Some(e);
+        }
+    }
+    tracing::debug!("Probed {}: {:?}", url, result.status);
+    result
+}
+
+fn probe_inner(
+    agent: &ureq::Agent,
+    url: &str,
+    config: &ProbeConfig,
+    started: Instant,
+    result: &mut ProbeResult,
+) -> Result<HealthStatus, String> {
+    let response = send(agent, &config.http, url, None).map_err(|(code, e)| {
+        result.http_status = code;
+        e
+    })?;
+    result.http_status = Some(response.status());
+    result.response_ms = Some(started.elapsed().as_millis() as u64);
+    result.content_type = response.header("Content-Type").map(str::to_string);
+    let final_url = response.get_url().to_string();
+    let is_playlist_type = result
+        .content_type
+        .as_deref()
+        .is_some_and(|t| t.to_ascii_lowercase().contains("mpegurl"));
+
+    let mut reader = response.into_reader();
+    let mut buf = vec![0u8; 64 * 1024];
+    // Enough of the body to recognise a playlist
+    let mut n = 0;
+    while n < 16 {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) => return Err(format!("Read failed: {}", e)),
+        }
+    }
+    if n == 0 {
+        return Err("Empty response".to_string());
+    }
+    let head = &buf[..n];
+
+    let text_start = String::from_utf8_lossy(&head[..n.min(64)]);
+    if is_playlist_type
+        || text_start
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with("#EXTM3U")
+    {
+        result.kind = StreamKind::Hls;
+        let mut body = head.to_vec();
+        reader
+            .take(MAX_PLAYLIST_BYTES)
+            .read_to_end(&mut body)
+            .map_err(|e| format!("Read failed: {}", e))?;
+        let text = String::from_utf8_lossy(&body);
+        return probe_hls(agent, &text, &final_url, config, started, result);
+    }
+
+    // Plain stream: read until a keyframe, the byte budget or the deadline
+    let deadline = Duration::from_millis(config.keyframe_timeout_ms);
+    let mut scanner = KeyframeScanner::default();
+    let mut found = scanner.push(head);
+    let mut total = n;
+    while !found && total < config.max_probe_bytes && started.elapsed() < deadline {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("Read failed: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+        found = scanner.push(&buf[..n]);
+    }
+
+    if !scanner.synced {
+        result.kind = StreamKind::Other;
+        result.detail = Some("Not an MPEG-TS or HLS stream".to_string());
+        return Ok(HealthStatus::Degraded);
+    }
+    result.kind = StreamKind::MpegTs;
+    Ok(keyframe_status(found, config, started, result))
+}
+
+fn keyframe_status(
+    found: bool,
+    config: &ProbeConfig,
+    started: Instant,
+    result: &mut ProbeResult,
+) -> HealthStatus {
+    if !found {
+        result.detail = Some("No video keyframe received".to_string());
+        return HealthStatus::Degraded;
+    }
+    let elapsed = started.elapsed().as_millis() as u64;
+    result.keyframe_ms = Some(elapsed);
+    if elapsed > config.slow_keyframe_ms {
+        result.detail = Some(format!("First keyframe after {} ms", elapsed));
+        return HealthStatus::Degraded;
+    }
+    HealthStatus::Healthy
+}
+
+/// Validate the playlist chain down to one segment and look into it
+fn probe_hls(
+    agent: &ureq::Agent,
+    text: &str,
+    url: &str,
+    config: &ProbeConfig,
+    started: Instant,
+    result: &mut ProbeResult,
+) -> Result<HealthStatus, String> {
+    let (media_text, media_url) = if is_hls_master(text) {
+        let (variants, _) = parse_hls_master(text, url)?;
+        // The cheapest variant is enough to tell whether the channel is up
+        let variant = variants
+            .iter()
+            .min_by_key(|v| v.bandwidth)
+            .ok_or_else(|| "Master playlist lists no variants".to_string())?;
+        let (final_url, body) = fetch(agent, &config.http, &variant.uri, None, MAX_PLAYLIST_BYTES)?;
+        (String::from_utf8_lossy(&body).into_owned(), final_url)
+    } else {
+        (text.to_string(), url.to_string())
+    };
+
+    let playlist = parse_hls_media(&media_text, &media_url)?;
+    // Live: the newest segment is the one least likely to have expired
+    let segment = if playlist.ended {
+        playlist.segments.first()
+    } else {
+        playlist.segments.last()
+    }
+    .ok_or_else(|| "Playlist has no segments".to_string())?;
+
+    let limit = config.max_probe_bytes as u64;
+    let (_, data) = fetch(agent, &config.http, &segment.uri, segment.byte_range, limit)?;
+    if data.is_empty() {
+        return Err("Empty segment".to_string());
+    }
+    // Encrypted and fMP4 segments are only checked for reachability
+    if segment.key.is_some() || playlist.format == SegmentFormat::Fmp4 {
+        return Ok(HealthStatus::Healthy);
+    }
+
+    let mut scanner = KeyframeScanner::default();
+    let found = scanner.push(&data);
+    if !scanner.synced {
+        return Err("Segment is not MPEG-TS".to_string());
+    }
+    Ok(keyframe_status(found, config, started, result))
+}
+
+/// Spaces probe starts evenly across all workers
+struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(per_sec: f64) -> Self {
+        Self {
+            interval: (per_sec > 0.0).then(|| Duration::from_secs_f64(1.0 / per_sec)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            let mut next = self.next.lock();
+            let slot = (*next).max(Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        std::thread::sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Probe many URLs concurrently; results are in input order
+pub fn probe_streams(urls: &[String], config: &ProbeConfig) -> Vec<ProbeResult> {
+    let agent = probe_agent(&config.http);
+    let limiter = RateLimiter::new(config.requests_per_sec);
+    let hosts = HostSlots::new(config.per_host);
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<ProbeResult>>> = Mutex::new(vec![None; urls.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..config.concurrency.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(url) = urls.get(index) else {
+                    break;
+                };
+                let _slot = hosts.acquire(url);
+                limiter.wait();
+                let result = probe_with(&agent, url, config);
+                results.lock()[index] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().into_iter().flatten().collect()
+}
+
+// ============================================================================
+// Health History
+// ============================================================================
+
+/// Probe history per stream URL, optionally backed by a JSON file
+#[derive(Debug, Default)]
+pub struct HealthStore {
+    path: Option<PathBuf>,
+    history: HashMap<String, VecDeque<ProbeResult>>,
+}
+
+impl HealthStore {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load the history kept at `path` (empty if the file doesn't exist)
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let history = if path.exists() {
+            let content =
+                fs::read_to_string(path).map_err(|e| format!("Failed to read health: {}", e))?;
+            serde_json::from_str(&content).map_err(|e| format!("Invalid health file: {}", e))?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            history,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string(&self.history).map_err(|e| e.to_string())?;
+        // Write-then-rename so a crash never leaves half a file
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, json).map_err(|e| e.to_string())?;
+        fs::rename(&temp, path).map_err(|e| e.to_string())
+    }
+
+    pub fn record(&mut self, result: ProbeResult) {
+        let history = self.history.entry(result.url.clone()).or_default();
+        history.push_back(result);
+        while history.len() > HISTORY_LEN {
+            history.pop_front();
+        }
+    }
+
+    /// Oldest first
+    pub fn history(&self, url: &str) -> Vec<ProbeResult> {
+        self.history
+            .get(url)
+            .map(|h| h.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// `None` until probed. A single failure only degrades a stream; it is
+    /// dead after `DEAD_AFTER` failures in a row.
+    pub fn verdict(&self, url: &str) -> Option<HealthStatus> {
+        let history = self.history.get(url)?;
+        let last = history.back()?;
+        if last.status != HealthStatus::Dead {
+            return Some(last.status);
+        }
+        let failures = history
+            .iter()
+            .rev()
+            .take_while(|r| r.status == HealthStatus::Dead)
+            .count();
+        Some(if failures >= DEAD_AFTER {
+            HealthStatus::Dead
+        } else {
+            HealthStatus::Degraded
+        })
+    }
+
+    /// 0.0 (always dead) to 1.0 (always healthy), recent probes weighing
+    /// most; 0.5 for unprobed streams
+    pub fn score(&self, url: &str) -> f64 {
+        let Some(history) = self.history.get(url).filter(|h| !h.is_empty()) else {
+            return 0.5;
+        };
+        let (mut total, mut weights) = (0.0, 0.0);
+        let mut weight = 1.0;
+        for result in history.iter().rev() {
+            total += weight
+                * match result.status {
+                    HealthStatus::Healthy => 1.0,
+                    HealthStatus::Degraded => 0.5,
+                    HealthStatus::Dead => 0.0,
+                };
+            weights += weight;
+            weight *= 0.7;
+        }
+        total / weights
+    }
+
+    /// Forget streams no longer in any playlist
+    pub fn retain_urls(&mut self, urls: &[String]) {
+        self.history.retain(|url, _| urls.contains(url));
+    }
+}
+
+// ============================================================================
+// Channel Filtering & Failover
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeadChannelPolicy {
+    Show,
+    /// Move dead channels to the end, otherwise keeping the order
+    Demote,
+    Hide,
+}
+
+fn is_dead(store: &HealthStore, url: &str) -> bool {
+    store.verdict(url) == Some(HealthStatus::Dead)
+}
+
+pub fn apply_health_policy(
+    channels: Vec<IptvChannel>,
+    store: &HealthStore,
+    policy: DeadChannelPolicy,
+) -> Vec<IptvChannel> {
+    match policy {
+        DeadChannelPolicy::Show => channels,
+        DeadChannelPolicy::Hide => channels
+            .into_iter()
+            .filter(|c| !is_dead(store, &c.stream_url))
+            .collect(),
+        DeadChannelPolicy::Demote => {
+            let (dead, alive): (Vec<_>, Vec<_>) = channels
+                .into_iter()
+                .partition(|c| is_dead(store, &c.stream_url));
+            alive.into_iter().chain(dead).collect()
+        }
+    }
+}
+
+/// URLs of other entries carrying the same channel: the same `tvg-id`, or
+/// the same name once provider prefixes and quality tags are ignored
+pub fn alternate_urls(channel: &IptvChannel, channels: &[IptvChannel]) -> Vec<String> {
+    let epg_id = channel
+        .epg_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|id| !id.is_empty());
+    let key = channel_key(&channel.name);
+
+    let mut urls: Vec<String> = Vec::new();
+    for other in channels {
+        if other.stream_url == channel.stream_url || urls.contains(&other.stream_url) {
+            continue;
+        }
+        let same_id = epg_id.is_some() && other.epg_id.as_deref().map(str::trim) == epg_id;
+        let same_name = !key.is_empty() && channel_key(&other.name) == key;
+        if same_id || same_name {
+            urls.push(other.stream_url.clone());
+        }
+    }
+    urls
+}
+
+/// Candidate URLs for one channel, tried in order. A round ends when every
+/// candidate has failed since the last one that delivered data.
+#[derive(Debug, Clone)]
+pub struct StreamFailover {
+    candidates: Vec<String>,
+    current: usize,
+    failures: usize,
+}
+
+impl StreamFailover {
+    pub fn new(primary: &str, alternates: Vec<String>) -> Self {
+        let mut candidates = vec![primary.to_string()];
+        for url in alternates {
+            if !candidates.contains(&url) {
+                candidates.push(url);
+            }
+        }
+        Self {
+            candidates,
+            current: 0,
+            failures: 0,
+        }
+    }
+
+    /// The channel's own URL and its alternates, best health first (the
+    /// channel's own URL wins ties)
+    pub fn for_channel(
+        channel: &IptvChannel,
+        channels: &[IptvChannel],
+        store: &HealthStore,
+    ) -> Self {
+        let mut failover = Self::new(&channel.stream_url, alternate_urls(channel, channels));
+        let rank = |url: &String| match store.verdict(url) {
+            Some(HealthStatus::Healthy) | None => 0,
+            Some(HealthStatus::Degraded) => 1,
+            Some(HealthStatus::Dead) => 2,
+        };
+        failover.candidates.sort_by_key(rank);
+        failover
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub fn current(&self) -> &str {
+        &self.candidates[self.current]
+    }
+
+    /// The current URL failed: the next one to try, or `None` once all
+    /// have failed in this round
+    pub fn fail(&mut self) -> Option<&str> {
+        self.failures += 1;
+        if self.failures >= self.candidates.len() {
+            return None;
+        }
+        self.current = (self.current + 1) % self.candidates.len();
+        Some(self.current())
+    }
+
+    /// The current URL delivered data
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+    }
+}
+
+// ============================================================================
+// Public Rust API
+// ============================================================================
+
+use once_cell::sync::Lazy;
+
+fn default_store_path() -> PathBuf {
+    let mut path = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("SLAIN");
+    path.push("iptv_health.json");
+    path
+}
+
+static HEALTH_STORE: Lazy<Mutex<HealthStore>> = Lazy::new(|| {
+    let store = HealthStore::open(&default_store_path()).unwrap_or_else(|e| {
+        tracing::warn!("Stream health history unavailable: {}", e);
+        HealthStore::in_memory()
+    });
+    Mutex::new(store)
+});
+
+/// Probe every loaded channel's stream and record the results. Blocks
+/// until all probes finish.
+pub fn probe_iptv_channels() -> Result<Vec<ProbeResult>, String> {
+    let mut urls: Vec<String> = crate::iptv::get_iptv_channels()
+        .into_iter()
+        .map(|c| c.stream_url)
+        .collect();
+    urls.sort();
+    urls.dedup();
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let results = probe_streams(&urls, &ProbeConfig::default());
+    let mut store = HEALTH_STORE.lock();
+    store.retain_urls(&urls);
+    for result in &results {
+        store.record(result.clone());
+    }
+    store.save()?;
+    Ok(results)
+}
+
+/// Probe history of one stream URL, oldest first
+pub fn get_iptv_stream_health(url: String) -> Vec<ProbeResult> {
+    HEALTH_STORE.lock().history(&url)
+}
+
+/// Loaded channels with dead ones hidden or moved to the end
+pub fn get_iptv_channels_by_health(policy: DeadChannelPolicy) -> Vec<IptvChannel> {
+    apply_health_policy(
+        crate::iptv::get_iptv_channels(),
+        &HEALTH_STORE.lock(),
+        policy,
+    )
+}
+
+/// Timeshift a loaded channel, failing over to its alternate URLs
+pub fn start_iptv_channel_timeshift(channel_id: String) -> Result<(), String> {
+    let channels = crate::iptv::get_iptv_channels();
+    let channel = channels
+        .iter()
+        .find(|c| c.id == channel_id)
+        .ok_or_else(|| format!("Channel not found: {}", channel_id))?;
+    let failover = StreamFailover::for_channel(channel, &channels, &HEALTH_STORE.lock());
+    crate::iptv_recording::start_iptv_timeshift_failover(failover)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iptv_recording::{TimeshiftBuffer, TimeshiftConfig};
+    use crate::test_support::{http_server, respond, ts_stream};
+    use std::net::TcpListener;
+
+    fn stream_server() -> String {
+        let live = ts_stream(50, true);
+        let no_keyframes = ts_stream(50, false);
+        http_server(move |request, stream| {
+            let (status, content_type, body) = match request.path.as_str() {
+                "/live.ts" => ("200 OK", "video/mp2t", live.clone()),
+                "/nokey.ts" => ("200 OK", "video/mp2t", no_keyframes.clone()),
+                "/page" => ("200 OK", "text/html", b"<html>Not here</html>".to_vec()),
+                "/master.m3u8" => (
+                    "200 OK",
+                    "application/vnd.apple.mpegurl",
+                    b"#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=3000000\nhigh.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=800000\nlow.m3u8\n".to_vec(),
+                ),
+                "/low.m3u8" => (
+                    "200 OK",
+                    "application/vnd.apple.mpegurl",
+                    b"#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MEDIA-SEQUENCE:7\n#EXTINF:2.0,\nseg7.ts\n#EXTINF:2.0,\nlive.ts\n".to_vec(),
+                ),
+                // Extensionless, served as text
+                "/empty" => (
+                    "200 OK",
+                    "text/plain",
+                    b"#EXTM3U\n#EXT-X-TARGETDURATION:2\n".to_vec(),
+                ),
+                _ => ("404 Not Found", "text/plain", b"Not Found".to_vec()),
+            };
+            respond(
+                stream,
+                status,
+                &[("Content-Type", content_type.to_string())],
+                &body,
+            );
+        })
+    }
+
+    fn channel(id: &str, name: &str, url: &str, epg_id: Option<&str>) -> IptvChannel {
+        IptvChannel {
+            id: id.to_string(),
+            name: name.to_string(),
+            stream_url: url.to_string(),
+            logo_url: None,
+            group: None,
+            epg_id: epg_id.map(str::to_string),
+            country: None,
+            language: None,
+            is_favorite: false,
+            last_watched: None,
+            catchup: None,
+        }
+    }
+
+    #[test]
+    fn probe_classifies_streams() {
+        let server = stream_server();
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/gone.ts", listener.local_addr().unwrap())
+        };
+        let urls: Vec<String> = [
+            "/live.ts",
+            "/nokey.ts",
+            "/page",
+            "/missing.ts",
+            "/master.m3u8",
+            "/empty",
+        ]
+        .iter()
+        .map(|path| format!("{}{}", server, path))
+        .chain([closed])
+        .collect();
+        let config = ProbeConfig {
+            concurrency: 4,
+            per_host: 2,
+            requests_per_sec: 40.0,
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let results = probe_streams(&urls, &config);
+        // Seven starts, 25 ms apart
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(results.len(), urls.len());
+        for (result, url) in results.iter().zip(&urls) {
+            assert_eq!(&result.url, url);
+        }
+
+        let live = &results[0];
+        assert_eq!(
+            (live.status, live.kind),
+            (HealthStatus::Healthy, StreamKind::MpegTs)
+        );
+        assert_eq!(live.http_status, Some(200));
+        assert_eq!(live.content_type.as_deref(), Some("video/mp2t"));
+        assert!(live.keyframe_ms.is_some() && live.response_ms.is_some());
+
+        assert_eq!(
+            (results[1].status, results[1].kind),
+            (HealthStatus::Degraded, StreamKind::MpegTs)
+        );
+        assert_eq!(results[1].keyframe_ms, None);
+        assert_eq!(
+            (results[2].status, results[2].kind),
+            (HealthStatus::Degraded, StreamKind::Other)
+        );
+        assert_eq!(
+            (results[3].status, results[3].http_status),
+            (HealthStatus::Dead, Some(404))
+        );
+
+        // Master -> lowest variant -> newest segment
+        let hls = &results[4];
+        assert_eq!(
+            (hls.status, hls.kind),
+            (HealthStatus::Healthy, StreamKind::Hls),
+            "{:?}",
+            hls
+        );
+        assert!(hls.keyframe_ms.is_some());
+
+        let empty = &results[5];
+        assert_eq!(
+            (empty.status, empty.kind),
+            (HealthStatus::Dead, StreamKind::Hls)
+        );
+        assert!(empty.detail.as_deref().unwrap().contains("no segments"));
+
+        assert_eq!(
+            (results[6].status, results[6].http_status),
+            (HealthStatus::Dead, None)
+        );
+    }
+
+    #[test]
+    fn health_history_and_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("health.json");
+        let mut store = HealthStore::open(&path).unwrap();
+
+        let probe = |url: &str, status| ProbeResult {
+            status,
+            ..ProbeResult::new(url)
+        };
+        store.record(probe("http://a/1", HealthStatus::Healthy));
+        for _ in 0..2 {
+            store.record(probe("http://b/1", HealthStatus::Dead));
+        }
+        // Two failures are not enough to write a stream off
+        assert_eq!(store.verdict("http://b/1"), Some(HealthStatus::Degraded));
+        store.record(probe("http://b/1", HealthStatus::Dead));
+        assert_eq!(store.verdict("http://b/1"), Some(HealthStatus::Dead));
+        assert_eq!(store.verdict("http://c/1"), None);
+        for _ in 0..HISTORY_LEN {
+            store.record(probe("http://a/1", HealthStatus::Degraded));
+        }
+        assert_eq!(store.history("http://a/1").len(), HISTORY_LEN);
+        assert!(store.score("http://a/1") > store.score("http://b/1"));
+        store.save().unwrap();
+
+        let store = HealthStore::open(&path).unwrap();
+        assert_eq!(store.verdict("http://b/1"), Some(HealthStatus::Dead));
+        assert_eq!(store.verdict("http://a/1"), Some(HealthStatus::Degraded));
+
+        let channels = vec![
+            channel("1", "UK: News HD", "http://b/1", Some("news.uk")),
+            channel("2", "Sport", "http://a/1", None),
+            channel("3", "News", "http://c/1", None),
+            channel("4", "Movies", "http://d/1", Some("news.uk")),
+        ];
+        let ids = |list: Vec<IptvChannel>| list.into_iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(apply_health_policy(
+                channels.clone(),
+                &store,
+                DeadChannelPolicy::Hide
+            )),
+            vec!["2", "3", "4"]
+        );
+        assert_eq!(
+            ids(apply_health_policy(
+                channels.clone(),
+                &store,
+                DeadChannelPolicy::Demote
+            )),
+            vec!["2", "3", "4", "1"]
+        );
+        assert_eq!(
+            ids(apply_health_policy(
+                channels.clone(),
+                &store,
+                DeadChannelPolicy::Show
+            ))
+            .len(),
+            4
+        );
+
+        // Same name modulo prefix/quality, and same tvg-id
+        assert_eq!(
+            alternate_urls(&channels[0], &channels),
+            vec!["http://c/1", "http://d/1"]
+        );
+        assert!(alternate_urls(&channels[1], &channels).is_empty());
+
+        // The dead primary goes last
+        let failover = StreamFailover::for_channel(&channels[0], &channels, &store);
+        assert_eq!(
+            failover.candidates(),
+            ["http://c/1", "http://d/1", "http://b/1"]
+        );
+    }
+
+    #[test]
+    fn failover_rounds_and_timeshift() {
+        let mut failover =
+            StreamFailover::new("a", vec!["b".to_string(), "a".to_string(), "c".to_string()]);
+        assert_eq!(failover.candidates(), ["a", "b", "c"]);
+        assert_eq!(failover.fail(), Some("b"));
+        failover.succeeded();
+        assert_eq!(failover.fail(), Some("c"));
+        assert_eq!(failover.fail(), Some("a"));
+        assert_eq!(failover.fail(), None);
+        assert_eq!(StreamFailover::new("a", Vec::new()).fail(), None);
+
+        // The first URL is down; timeshift carries on from the second
+        let server = stream_server();
+        let dir = tempfile::tempdir().unwrap();
+        let config = TimeshiftConfig {
+            dir: dir.path().to_path_buf(),
+            segment_secs: 1.0,
+            ..Default::default()
+        };
+        let failover = StreamFailover::new(
+            &format!("{}/missing.ts", server),
+            vec![format!("{}/live.ts", server)],
+        );
+        let buffer = TimeshiftBuffer::start_with_failover(failover, config).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while buffer.status().live && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        // live.ts ends, the missing URL is retried once more, then it stops
+        let status = buffer.status();
+        assert!(!status.live);
+        assert!(status.bytes > 0, "{:?}", status);
+        assert_eq!(status.source_url, Some(format!("{}/live.ts", server)));
+        assert!(
+            status.error.as_deref().unwrap().contains("404"),
+            "{:?}",
+            status
+        );
+        buffer.stop();
+    }
+}
@@ -0,0 +1,1378 @@
+//! IPTV Provider Portals
+//!
+//! Clients for the two common panel APIs behind IPTV subscriptions, so an
+//! account can be used without exporting an M3U first:
+//! - Xtream Codes (`player_api.php`): live, VOD and series catalogs, account
+//!   info, short EPG and the full `xmltv.php` guide
+//! - Stalker/Ministra middleware (MAG portals): MAC handshake with token
+//!   renewal, channels, paged VOD/series catalogs, `create_link` resolution
+//!   and the portal EPG
+//!
+//! Both produce the `iptv` types (`IptvPlaylist`, `IptvChannel`, `EpgData`)
+//! so provider channels sit alongside M3U ones in the `IptvManager`.
+
+use crate::iptv::{
+    read_xmltv, Catchup, CatchupMode, EpgChannel, EpgData, EpgProgram, IptvChannel, IptvPlaylist,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Upper bound on catalog pages fetched from a Stalker portal
+const MAX_PORTAL_PAGES: u32 = 500;
+
+// ============================================================================
+// Catalog Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderCategory {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderAccount {
+    pub username: String,
+    pub status: String,
+    /// Unix seconds; `None` for unlimited subscriptions
+    pub expires_at: Option<i64>,
+    pub is_trial: bool,
+    pub active_connections: Option<u32>,
+    pub max_connections: Option<u32>,
+    /// Live output formats the panel allows (`ts`, `m3u8`, `rtmp`)
+    pub output_formats: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VodItem {
+    pub id: String,
+    pub name: String,
+    pub category_id: Option<String>,
+    /// Direct URL for Xtream; the portal `cmd` for Stalker (see
+    /// `StalkerClient::resolve_vod`)
+    pub stream_url: String,
+    pub container: Option<String>,
+    pub poster: Option<String>,
+    pub plot: Option<String>,
+    pub year: Option<u32>,
+    pub rating: Option<f64>,
+    pub added: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesItem {
+    pub id: String,
+    pub name: String,
+    pub category_id: Option<String>,
+    pub poster: Option<String>,
+    pub plot: Option<String>,
+    pub year: Option<u32>,
+    pub rating: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesEpisode {
+    pub id: String,
+    pub series_id: String,
+    pub season: u32,
+    pub episode: u32,
+    pub title: String,
+    /// Direct URL for Xtream; the season `cmd` for Stalker (see
+    /// `StalkerClient::resolve_episode`)
+    pub stream_url: String,
+    pub container: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub plot: Option<String>,
+}
+
+// ============================================================================
+// JSON Helpers
+// ============================================================================
+//
+// Panels are loose with types: ids, counts and timestamps arrive as numbers
+// or strings depending on the vendor and version, lists sometimes as
+// objects keyed by id.
+
+fn json_str(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn json_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => {
+            let s = s.trim();
+            s.parse()
+                .ok()
+                .or_else(|| s.parse::<f64>().ok().map(|f| f as i64))
+        }
+        _ => None,
+    }
+}
+
+fn json_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn json_list(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(map) => map.values().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Leading four-digit year of `"2019"` or `"2019-05-01"`
+fn json_year(value: &Value) -> Option<u32> {
+    let text = json_str(value)?;
+    let digits: String = text.chars().take(4).collect();
+    digits.parse().ok().filter(|y| (1880..=2200).contains(y))
+}
+
+/// Xtream short-EPG text is base64; fall back to the raw value when a
+/// panel sends it plain
+fn decode_base64_text(value: &Value) -> Option<String> {
+    let raw = json_str(value)?;
+    match base64::engine::general_purpose::STANDARD.decode(raw.as_bytes()) {
+        Ok(bytes) => Some(String::from_utf8_lossy(&bytes).trim().to_string()),
+        Err(_) => Some(raw),
+    }
+    .filter(|s| !s.is_empty())
+}
+
+fn absolute_url(value: &Value) -> Option<String> {
+    json_str(value).filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+fn http_client(user_agent: &str) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(user_agent)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn parse_base_url(input: &str) -> Result<url::Url, String> {
+    let input = input.trim();
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("http://{}", input)
+    };
+    let url = url::Url::parse(&with_scheme).map_err(|e| format!("Invalid URL: {}", e))?;
+    if url.host_str().is_none() {
+        return Err(format!("Invalid URL: {}", input));
+    }
+    Ok(url)
+}
+
+/// `scheme://host[:port]`
+fn url_origin(url: &url::Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+fn groups_in_order(channels: &[IptvChannel]) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::new();
+    for group in channels.iter().filter_map(|c| c.group.as_ref()) {
+        if !groups.contains(group) {
+            groups.push(group.clone());
+        }
+    }
+    groups
+}
+
+// ============================================================================
+// Xtream Codes
+// ============================================================================
+
+pub struct XtreamClient {
+    server: String,
+    username: String,
+    password: String,
+    live_format: String,
+    client: reqwest::Client,
+}
+
+impl XtreamClient {
+    /// `server` is the panel base (`http://host:8080`); a trailing
+    /// `player_api.php`/`get.php` is ignored
+    pub fn new(server: &str, username: &str, password: &str) -> Result<Self, String> {
+        let url = parse_base_url(server)?;
+        let path = url.path().trim_end_matches('/');
+        let prefix = [
+            "/player_api.php",
+            "/get.php",
+            "/xmltv.php",
+            "/panel_api.php",
+        ]
+        .iter()
+        .find_map(|endpoint| path.strip_suffix(endpoint))
+        .unwrap_or(path);
+
+        Ok(Self {
+            server: format!("{}{}", url_origin(&url), prefix),
+            username: username.to_string(),
+            password: password.to_string(),
+            live_format: "ts".to_string(),
+            client: http_client("SLAIN/1.0")?,
+        })
+    }
+
+    /// Recognise an Xtream M3U export (`get.php?username=..&password=..`)
+    pub fn from_m3u_url(url: &str) -> Option<Self> {
+        let parsed = url::Url::parse(url.trim()).ok()?;
+        if !parsed.path().ends_with("/get.php") {
+            return None;
+        }
+        let query: HashMap<_, _> = parsed.query_pairs().collect();
+        let username = query.get("username")?;
+        let password = query.get("password")?;
+        let mut client = Self::new(url, username, password).ok()?;
+        if let Some(output) = query.get("output").filter(|o| !o.is_empty()) {
+            client.live_format = output.to_string();
+        }
+        Some(client)
+    }
+
+    /// Container for live URLs (`ts` by default, or `m3u8` for HLS)
+    pub fn with_live_format(mut self, format: &str) -> Self {
+        self.live_format = format.trim_start_matches('.').to_string();
+        self
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// The panel's own M3U export of this account
+    pub fn m3u_url(&self) -> String {
+        format!(
+            "{}/get.php?username={}&password={}&type=m3u_plus&output={}",
+            self.server,
+            urlencoding::encode(&self.username),
+            urlencoding::encode(&self.password),
+            self.live_format
+        )
+    }
+
+    fn stream_url(&self, kind: &str, id: &str, extension: &str) -> String {
+        format!(
+            "{}/{}/{}/{}/{}.{}",
+            self.server,
+            kind,
+            urlencoding::encode(&self.username),
+            urlencoding::encode(&self.password),
+            id,
+            extension
+        )
+    }
+
+    pub fn live_url(&self, stream_id: &str) -> String {
+        self.stream_url("live", stream_id, &self.live_format)
+    }
+
+    pub fn movie_url(&self, stream_id: &str, container: &str) -> String {
+        self.stream_url("movie", stream_id, container)
+    }
+
+    pub fn episode_url(&self, episode_id: &str, container: &str) -> String {
+        self.stream_url("series", episode_id, container)
+    }
+
+    async fn api(&self, params: &[(&str, &str)]) -> Result<Value, String> {
+        let mut query = vec![
+            ("username", self.username.as_str()),
+            ("password", self.password.as_str()),
+        ];
+        query.extend_from_slice(params);
+
+        let response = self
+            .client
+            .get(format!("{}/player_api.php", self.server))
+            .query(&query)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Xtream API error: {}", response.status()));
+        }
+
+        let data = response
+            .json::<Value>()
+            .await
+            .map_err(|e| format!("Parse failed: {}", e))?;
+
+        // Bad credentials come back as a 200 with just `user_info.auth = 0`
+        if json_i64(&data["user_info"]["auth"]) == Some(0) {
+            return Err("Xtream login failed: invalid username or password".to_string());
+        }
+
+        Ok(data)
+    }
+
+    async fn action(&self, action: &str, extra: &[(&str, &str)]) -> Result<Value, String> {
+        let mut params = vec![("action", action)];
+        params.extend_from_slice(extra);
+        self.api(&params).await
+    }
+
+    pub async fn account(&self) -> Result<ProviderAccount, String> {
+        let data = self.api(&[]).await?;
+        let info = &data["user_info"];
+        if info.is_null() {
+            return Err("Xtream API returned no user_info".to_string());
+        }
+
+        Ok(ProviderAccount {
+            username: json_str(&info["username"]).unwrap_or_else(|| self.username.clone()),
+            status: json_str(&info["status"]).unwrap_or_else(|| "Unknown".to_string()),
+            expires_at: json_i64(&info["exp_date"]).filter(|t| *t > 0),
+            is_trial: json_i64(&info["is_trial"]) == Some(1),
+            active_connections: json_i64(&info["active_cons"]).map(|n| n as u32),
+            max_connections: json_i64(&info["max_connections"]).map(|n| n as u32),
+            output_formats: json_list(&info["allowed_output_formats"])
+                .into_iter()
+                .filter_map(json_str)
+                .collect(),
+        })
+    }
+
+    async fn categories(&self, action: &str) -> Result<Vec<ProviderCategory>, String> {
+        let data = self.action(action, &[]).await?;
+        Ok(json_list(&data)
+            .into_iter()
+            .filter_map(|c| {
+                Some(ProviderCategory {
+                    id: json_str(&c["category_id"])?,
+                    name: json_str(&c["category_name"]).unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    pub async fn live_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("get_live_categories").await
+    }
+
+    pub async fn vod_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("get_vod_categories").await
+    }
+
+    pub async fn series_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("get_series_categories").await
+    }
+
+    /// Live channels as a playlist; channel ids are `xtream:<stream_id>`
+    /// and `epg_id` is the panel's XMLTV channel id
+    pub async fn playlist(&self) -> Result<IptvPlaylist, String> {
+        let categories: HashMap<String, String> = self
+            .live_categories()
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c.name))
+            .collect();
+        let streams = self.action("get_live_streams", &[]).await?;
+
+        let channels: Vec<IptvChannel> = json_list(&streams)
+            .into_iter()
+            .filter_map(|s| {
+                let stream_id = json_str(&s["stream_id"])?;
+                Some(IptvChannel {
+                    id: format!("xtream:{}", stream_id),
+                    name: json_str(&s["name"]).unwrap_or_else(|| stream_id.clone()),
+                    stream_url: self.live_url(&stream_id),
+                    logo_url: absolute_url(&s["stream_icon"]),
+                    group: json_str(&s["category_id"]).and_then(|id| categories.get(&id).cloned()),
+                    epg_id: json_str(&s["epg_channel_id"]),
+                    country: None,
+                    language: None,
+                    is_favorite: false,
+                    last_watched: None,
+                    catchup: (json_i64(&s["tv_archive"]) == Some(1)).then(|| Catchup {
+                        mode: CatchupMode::Xc,
+                        source: None,
+                        days: json_i64(&s["tv_archive_duration"]).map(|d| d as u32),
+                    }),
+                })
+            })
+            .collect();
+
+        tracing::debug!("Xtream {}: {} live channels", self.server, channels.len());
+
+        Ok(IptvPlaylist {
+            name: format!("Xtream: {}", self.server),
+            source_url: Some(self.m3u_url()),
+            groups: groups_in_order(&channels),
+            channels,
+            last_updated: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    pub async fn vod_streams(&self, category_id: Option<&str>) -> Result<Vec<VodItem>, String> {
+        let extra: Vec<(&str, &str)> = category_id
+            .map(|id| ("category_id", id))
+            .into_iter()
+            .collect();
+        let data = self.action("get_vod_streams", &extra).await?;
+
+        Ok(json_list(&data)
+            .into_iter()
+            .filter_map(|v| {
+                let id = json_str(&v["stream_id"])?;
+                let container =
+                    json_str(&v["container_extension"]).unwrap_or_else(|| "mp4".to_string());
+                Some(VodItem {
+                    name: json_str(&v["name"]).unwrap_or_else(|| id.clone()),
+                    category_id: json_str(&v["category_id"]),
+                    stream_url: self.movie_url(&id, &container),
+                    container: Some(container),
+                    poster: absolute_url(&v["stream_icon"]),
+                    plot: json_str(&v["plot"]),
+                    year: json_year(&v["year"]).or_else(|| json_year(&v["releasedate"])),
+                    rating: json_f64(&v["rating"]).filter(|r| *r > 0.0),
+                    added: json_i64(&v["added"]),
+                    id,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn series(&self, category_id: Option<&str>) -> Result<Vec<SeriesItem>, String> {
+        let extra: Vec<(&str, &str)> = category_id
+            .map(|id| ("category_id", id))
+            .into_iter()
+            .collect();
+        let data = self.action("get_series", &extra).await?;
+
+        Ok(json_list(&data)
+            .into_iter()
+            .filter_map(|s| {
+                let id = json_str(&s["series_id"])?;
+                Some(SeriesItem {
+                    name: json_str(&s["name"]).unwrap_or_else(|| id.clone()),
+                    category_id: json_str(&s["category_id"]),
+                    poster: absolute_url(&s["cover"]),
+                    plot: json_str(&s["plot"]),
+                    year: json_year(&s["year"]).or_else(|| json_year(&s["releaseDate"])),
+                    rating: json_f64(&s["rating"]).filter(|r| *r > 0.0),
+                    id,
+                })
+            })
+            .collect())
+    }
+
+    /// All episodes of a series, ordered by season then episode
+    pub async fn series_episodes(&self, series_id: &str) -> Result<Vec<SeriesEpisode>, String> {
+        let data = self
+            .action("get_series_info", &[("series_id", series_id)])
+            .await?;
+
+        // `episodes` is `{"1": [..], "2": [..]}`, or a list of season lists
+        let mut episodes = Vec::new();
+        for season_list in json_list(&data["episodes"]) {
+            for e in json_list(season_list) {
+                let Some(id) = json_str(&e["id"]) else {
+                    continue;
+                };
+                let container =
+                    json_str(&e["container_extension"]).unwrap_or_else(|| "mp4".to_string());
+                let info = &e["info"];
+                episodes.push(SeriesEpisode {
+                    series_id: series_id.to_string(),
+                    season: json_i64(&e["season"]).unwrap_or(1) as u32,
+                    episode: json_i64(&e["episode_num"]).unwrap_or(0) as u32,
+                    title: json_str(&e["title"]).unwrap_or_else(|| id.clone()),
+                    stream_url: self.episode_url(&id, &container),
+                    container: Some(container),
+                    duration_secs: json_i64(&info["duration_secs"]).map(|d| d as u64),
+                    plot: json_str(&info["plot"]),
+                    id,
+                });
+            }
+        }
+        episodes.sort_by_key(|e| (e.season, e.episode));
+        Ok(episodes)
+    }
+
+    /// Next `limit` programmes of one live stream, keyed by its XMLTV id
+    pub async fn short_epg(
+        &self,
+        stream_id: &str,
+        limit: usize,
+    ) -> Result<Vec<EpgProgram>, String> {
+        let limit = limit.to_string();
+        let data = self
+            .action(
+                "get_short_epg",
+                &[("stream_id", stream_id), ("limit", limit.as_str())],
+            )
+            .await?;
+
+        let mut programs: Vec<EpgProgram> = json_list(&data["epg_listings"])
+            .into_iter()
+            .filter_map(|p| {
+                Some(EpgProgram {
+                    channel_id: json_str(&p["channel_id"])
+                        .or_else(|| json_str(&p["epg_id"]))
+                        .unwrap_or_else(|| stream_id.to_string()),
+                    title: decode_base64_text(&p["title"])?,
+                    sub_title: None,
+                    description: decode_base64_text(&p["description"]),
+                    start_time: json_i64(&p["start_timestamp"])?,
+                    end_time: json_i64(&p["stop_timestamp"])?,
+                    category: None,
+                    episode: None,
+                    rating: None,
+                    icon: None,
+                })
+            })
+            .collect();
+        programs.sort_by_key(|p| p.start_time);
+        Ok(programs)
+    }
+
+    /// Full guide from the panel's `xmltv.php`
+    pub async fn epg(&self) -> Result<EpgData, String> {
+        let response = self
+            .client
+            .get(format!("{}/xmltv.php", self.server))
+            .query(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch EPG: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Xtream EPG error: {}", response.status()));
+        }
+
+        let content = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read EPG: {}", e))?;
+        read_xmltv(&content[..])
+    }
+}
+
+// ============================================================================
+// Stalker / Ministra Portal
+// ============================================================================
+
+const STALKER_USER_AGENT: &str = "Mozilla/5.0 (QtEmbedded; U; Linux; C) AppleWebKit/533.3 (KHTML, like Gecko) MAG200 stbapp ver: 2 rev: 250 Safari/533.3";
+
+pub struct StalkerClient {
+    api_url: String,
+    portal_url: String,
+    mac: String,
+    client: reqwest::Client,
+    token: parking_lot::Mutex<Option<String>>,
+}
+
+/// Portal `cmd`s look like `ffmpeg http://host/ch/1_`; players want the URL
+fn strip_cmd(cmd: &str) -> String {
+    let cmd = cmd.trim();
+    cmd.split_once(' ')
+        .filter(|(prefix, rest)| !prefix.contains("://") && rest.contains("://"))
+        .map(|(_, rest)| rest.trim().to_string())
+        .unwrap_or_else(|| cmd.to_string())
+}
+
+/// The `cmd` form `create_link` expects back
+fn portal_cmd(stream_url: &str) -> String {
+    if stream_url.contains("://") {
+        format!("ffmpeg {}", stream_url)
+    } else {
+        stream_url.to_string()
+    }
+}
+
+fn normalize_mac(mac: &str) -> Result<String, String> {
+    let mac = mac.trim().to_uppercase().replace('-', ":");
+    let valid = mac.len() == 17
+        && mac.split(':').count() == 6
+        && mac
+            .split(':')
+            .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()));
+    if valid {
+        Ok(mac)
+    } else {
+        Err(format!("Invalid MAC address: {}", mac))
+    }
+}
+
+impl StalkerClient {
+    /// `portal_url` is what a MAG box is given (`http://host/c/`,
+    /// `http://host/stalker_portal/c/`) or the API endpoint itself
+    pub fn new(portal_url: &str, mac: &str) -> Result<Self, String> {
+        let url = parse_base_url(portal_url)?;
+        let origin = url_origin(&url);
+        let path = url.path().trim_end_matches('/');
+
+        let api_url = if path.ends_with("/load.php") || path.ends_with("/portal.php") {
+            format!("{}{}", origin, path)
+        } else if let Some(index) = path.find("/stalker_portal") {
+            format!(
+                "{}{}/stalker_portal/server/load.php",
+                origin,
+                &path[..index]
+            )
+        } else if let Some(prefix) = path.strip_suffix("/c") {
+            format!("{}{}/portal.php", origin, prefix)
+        } else {
+            format!("{}{}/stalker_portal/server/load.php", origin, path)
+        };
+
+        Ok(Self {
+            api_url,
+            portal_url: url.to_string(),
+            mac: normalize_mac(mac)?,
+            client: http_client(STALKER_USER_AGENT)?,
+            token: parking_lot::Mutex::new(None),
+        })
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    pub fn mac(&self) -> &str {
+        &self.mac
+    }
+
+    /// One raw request; `Ok(None)` when the portal rejected the token
+    async fn send(&self, params: &[(&str, &str)], token: &str) -> Result<Option<Value>, String> {
+        let mut query = params.to_vec();
+        query.push(("JsHttpRequest", "1-xml"));
+
+        let mut request = self
+            .client
+            .get(&self.api_url)
+            .query(&query)
+            .header("X-User-Agent", "Model: MAG250; Link: WiFi")
+            .header("Referer", &self.portal_url)
+            .header(
+                "Cookie",
+                format!(
+                    "mac={}; stb_lang=en; timezone=UTC",
+                    urlencoding::encode(&self.mac)
+                ),
+            );
+        if !token.is_empty() {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("Portal error: {}", status));
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        if body.contains("Authorization failed") {
+            return Ok(None);
+        }
+        let data: Value = serde_json::from_str(&body)
+            .map_err(|e| format!("Portal returned invalid JSON: {}", e))?;
+        Ok(Some(data["js"].clone()))
+    }
+
+    /// Get a fresh token and register the device profile
+    pub async fn handshake(&self) -> Result<String, String> {
+        let js = self
+            .send(
+                &[("type", "stb"), ("action", "handshake"), ("token", "")],
+                "",
+            )
+            .await?
+            .ok_or_else(|| "Portal rejected the handshake".to_string())?;
+        let token = json_str(&js["token"])
+            .ok_or_else(|| "Portal handshake returned no token".to_string())?;
+
+        // Many portals refuse catalog calls until the profile was fetched
+        let profile = self
+            .send(
+                &[
+                    ("type", "stb"),
+                    ("action", "get_profile"),
+                    ("hd", "1"),
+                    ("stb_type", "MAG250"),
+                ],
+                &token,
+            )
+            .await;
+        match profile {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(format!("Portal rejected MAC {}", self.mac)),
+            Err(e) => tracing::debug!("Stalker get_profile failed: {}", e),
+        }
+
+        *self.token.lock() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Authenticated call; an expired token is renewed once
+    async fn call(&self, params: &[(&str, &str)]) -> Result<Value, String> {
+        let cached = self.token.lock().clone();
+        let token = match cached {
+            Some(token) => token,
+            None => self.handshake().await?,
+        };
+        if let Some(js) = self.send(params, &token).await? {
+            return Ok(js);
+        }
+
+        tracing::debug!("Stalker token expired, repeating handshake");
+        let token = self.handshake().await?;
+        self.send(params, &token)
+            .await?
+            .ok_or_else(|| "Portal authorization failed".to_string())
+    }
+
+    async fn categories(&self, kind: &str, action: &str) -> Result<Vec<ProviderCategory>, String> {
+        let js = self.call(&[("type", kind), ("action", action)]).await?;
+        Ok(json_list(&js)
+            .into_iter()
+            .filter_map(|c| {
+                let id = json_str(&c["id"])?;
+                // `*` is the portal's synthetic "All" entry
+                (id != "*").then(|| ProviderCategory {
+                    id,
+                    name: json_str(&c["title"]).unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// All pages of a `get_ordered_list` catalog
+    async fn ordered_list(&self, kind: &str, extra: &[(&str, &str)]) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        for page in 1..=MAX_PORTAL_PAGES {
+            let page = page.to_string();
+            let mut params = vec![
+                ("type", kind),
+                ("action", "get_ordered_list"),
+                ("p", page.as_str()),
+            ];
+            params.extend_from_slice(extra);
+            let js = self.call(&params).await?;
+
+            let data = json_list(&js["data"]);
+            if data.is_empty() {
+                break;
+            }
+            items.extend(data.into_iter().cloned());
+
+            let total = json_i64(&js["total_items"]).unwrap_or(0) as usize;
+            if items.len() >= total {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    pub async fn live_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("itv", "get_genres").await
+    }
+
+    pub async fn vod_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("vod", "get_categories").await
+    }
+
+    pub async fn series_categories(&self) -> Result<Vec<ProviderCategory>, String> {
+        self.categories("series", "get_categories").await
+    }
+
+    /// Live channels as a playlist; ids and `epg_id` are `stalker:<id>`.
+    /// Portals often hand out per-session links, so play channels through
+    /// `resolve_channel`
+    pub async fn playlist(&self) -> Result<IptvPlaylist, String> {
+        let genres: HashMap<String, String> = self
+            .live_categories()
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c.name))
+            .collect();
+        let js = self
+            .call(&[("type", "itv"), ("action", "get_all_channels")])
+            .await?;
+
+        let channels: Vec<IptvChannel> = json_list(&js["data"])
+            .into_iter()
+            .filter_map(|c| {
+                let id = format!("stalker:{}", json_str(&c["id"])?);
+                Some(IptvChannel {
+                    name: json_str(&c["name"]).unwrap_or_else(|| id.clone()),
+                    stream_url: strip_cmd(&json_str(&c["cmd"])?),
+                    logo_url: absolute_url(&c["logo"]),
+                    group: json_str(&c["tv_genre_id"]).and_then(|g| genres.get(&g).cloned()),
+                    epg_id: Some(id.clone()),
+                    country: None,
+                    language: None,
+                    is_favorite: false,
+                    last_watched: None,
+                    // Portal archives play through `tv_archive` links, not URL templates
+                    catchup: None,
+                    id,
+                })
+            })
+            .collect();
+
+        tracing::debug!("Stalker {}: {} live channels", self.api_url, channels.len());
+
+        Ok(IptvPlaylist {
+            name: format!("Stalker: {}", self.portal_url),
+            source_url: Some(self.portal_url.clone()),
+            groups: groups_in_order(&channels),
+            channels,
+            last_updated: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    async fn create_link(
+        &self,
+        kind: &str,
+        cmd: &str,
+        series: Option<u32>,
+    ) -> Result<String, String> {
+        let series = series.map(|n| n.to_string());
+        let mut params = vec![("type", kind), ("action", "create_link"), ("cmd", cmd)];
+        if let Some(series) = &series {
+            params.push(("series", series.as_str()));
+        }
+        let js = self.call(&params).await?;
+        json_str(&js["cmd"])
+            .map(|cmd| strip_cmd(&cmd))
+            .filter(|url| url.contains("://"))
+            .ok_or_else(|| "Portal returned no stream link".to_string())
+    }
+
+    /// Playable URL for a channel from `playlist`
+    pub async fn resolve_channel(&self, channel: &IptvChannel) -> Result<String, String> {
+        self.create_link("itv", &portal_cmd(&channel.stream_url), None)
+            .await
+    }
+
+    pub async fn vod_streams(&self, category_id: &str) -> Result<Vec<VodItem>, String> {
+        let items = self
+            .ordered_list("vod", &[("category", category_id), ("sortby", "added")])
+            .await?;
+
+        Ok(items
+            .iter()
+            .filter_map(|v| {
+                let id = json_str(&v["id"])?;
+                Some(VodItem {
+                    name: json_str(&v["name"]).unwrap_or_else(|| id.clone()),
+                    category_id: json_str(&v["category_id"]),
+                    stream_url: strip_cmd(&json_str(&v["cmd"])?),
+                    container: None,
+                    poster: absolute_url(&v["screenshot_uri"]),
+                    plot: json_str(&v["description"]),
+                    year: json_year(&v["year"]),
+                    rating: json_f64(&v["rating_imdb"]).filter(|r| *r > 0.0),
+                    added: None,
+                    id,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn resolve_vod(&self, item: &VodItem) -> Result<String, String> {
+        self.create_link("vod", &portal_cmd(&item.stream_url), None)
+            .await
+    }
+
+    pub async fn series(&self, category_id: &str) -> Result<Vec<SeriesItem>, String> {
+        let items = self
+            .ordered_list("series", &[("category", category_id), ("sortby", "added")])
+            .await?;
+
+        Ok(items
+            .iter()
+            .filter_map(|s| {
+                let id = json_str(&s["id"])?;
+                Some(SeriesItem {
+                    name: json_str(&s["name"]).unwrap_or_else(|| id.clone()),
+                    category_id: json_str(&s["category_id"]),
+                    poster: absolute_url(&s["screenshot_uri"]),
+                    plot: json_str(&s["description"]),
+                    year: json_year(&s["year"]),
+                    rating: json_f64(&s["rating_imdb"]).filter(|r| *r > 0.0),
+                    id,
+                })
+            })
+            .collect())
+    }
+
+    /// Episodes of a series: each season entry carries one `cmd` plus the
+    /// list of episode numbers it serves
+    pub async fn series_episodes(&self, series_id: &str) -> Result<Vec<SeriesEpisode>, String> {
+        let seasons = self
+            .ordered_list(
+                "series",
+                &[
+                    ("movie_id", series_id),
+                    ("season_id", "0"),
+                    ("episode_id", "0"),
+                ],
+            )
+            .await?;
+
+        let mut episodes = Vec::new();
+        for (index, season) in seasons.iter().enumerate() {
+            let (Some(season_id), Some(cmd)) = (json_str(&season["id"]), json_str(&season["cmd"]))
+            else {
+                continue;
+            };
+            let name = json_str(&season["name"]).unwrap_or_default();
+            let number = name
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|part| !part.is_empty())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(index as u32 + 1);
+
+            for episode in json_list(&season["series"])
+                .into_iter()
+                .filter_map(json_i64)
+            {
+                episodes.push(SeriesEpisode {
+                    id: format!("{}:{}", season_id, episode),
+                    series_id: series_id.to_string(),
+                    season: number,
+                    episode: episode as u32,
+                    title: format!("{} - Episode {}", name, episode),
+                    stream_url: strip_cmd(&cmd),
+                    container: None,
+                    duration_secs: None,
+                    plot: None,
+                });
+            }
+        }
+        episodes.sort_by_key(|e| (e.season, e.episode));
+        Ok(episodes)
+    }
+
+    pub async fn resolve_episode(&self, episode: &SeriesEpisode) -> Result<String, String> {
+        self.create_link(
+            "vod",
+            &portal_cmd(&episode.stream_url),
+            Some(episode.episode),
+        )
+        .await
+    }
+
+    /// Guide for the next `hours`, keyed like the playlist's `epg_id`
+    pub async fn epg(&self, hours: u32) -> Result<EpgData, String> {
+        let period = hours.max(1).to_string();
+        let js = self
+            .call(&[
+                ("type", "itv"),
+                ("action", "get_epg_info"),
+                ("period", period.as_str()),
+            ])
+            .await?;
+
+        let mut epg = EpgData::default();
+        if let Value::Object(channels) = &js["data"] {
+            for (channel, programs) in channels {
+                let channel_id = format!("stalker:{}", channel);
+                let programs: Vec<EpgProgram> = json_list(programs)
+                    .into_iter()
+                    .filter_map(|p| {
+                        Some(EpgProgram {
+                            channel_id: channel_id.clone(),
+                            title: json_str(&p["name"])?,
+                            sub_title: None,
+                            description: json_str(&p["descr"]),
+                            start_time: json_i64(&p["start_timestamp"])?,
+                            end_time: json_i64(&p["stop_timestamp"])?,
+                            category: json_str(&p["category"]),
+                            episode: None,
+                            rating: None,
+                            icon: None,
+                        })
+                    })
+                    .collect();
+                epg.channel_info.insert(
+                    channel_id.clone(),
+                    EpgChannel {
+                        id: channel_id.clone(),
+                        ..Default::default()
+                    },
+                );
+                epg.channels.insert(channel_id, programs);
+            }
+        }
+        epg.build_index();
+        epg.last_updated = chrono::Utc::now().timestamp();
+        Ok(epg)
+    }
+}
+
+// ============================================================================
+// Public Rust API
+// ============================================================================
+
+/// Hours of Stalker guide fetched with the channel list
+const STALKER_EPG_HOURS: u32 = 24;
+
+/// Load an Xtream Codes account's live channels and guide into the IPTV
+/// manager; returns the number of channels added
+pub async fn load_iptv_xtream(
+    server: String,
+    username: String,
+    password: String,
+) -> Result<usize, String> {
+    let client = XtreamClient::new(&server, &username, &password)?;
+    let playlist = client.playlist().await?;
+    let count = playlist.channels.len();
+
+    // The guide is optional: plenty of panels serve an empty or broken one
+    match client.epg().await {
+        Ok(epg) => crate::iptv::merge_iptv_epg(epg)?,
+        Err(e) => tracing::warn!("Xtream EPG unavailable: {}", e),
+    }
+    crate::iptv::add_iptv_playlist(playlist)?;
+    Ok(count)
+}
+
+/// Load a Stalker portal's live channels and guide into the IPTV manager;
+/// returns the number of channels added
+pub async fn load_iptv_stalker(portal_url: String, mac: String) -> Result<usize, String> {
+    let client = StalkerClient::new(&portal_url, &mac)?;
+    let playlist = client.playlist().await?;
+    let count = playlist.channels.len();
+
+    match client.epg(STALKER_EPG_HOURS).await {
+        Ok(epg) => crate::iptv::merge_iptv_epg(epg)?,
+        Err(e) => tracing::warn!("Stalker EPG unavailable: {}", e),
+    }
+    crate::iptv::add_iptv_playlist(playlist)?;
+    Ok(count)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dlna_server::HttpRequest;
+    use crate::test_support::{http_server, respond};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// `http_server` answering JSON: `route(request)` returns the status
+    /// and body
+    fn json_server<F>(route: F) -> String
+    where
+        F: Fn(&HttpRequest) -> (&'static str, String) + Send + Sync + 'static,
+    {
+        http_server(move |request, stream| {
+            let (status, body) = route(request);
+            let content_type = [("Content-Type", "application/json".to_string())];
+            respond(stream, status, &content_type, body.as_bytes());
+        })
+    }
+
+    fn query_param(path: &str, key: &str) -> Option<String> {
+        let url = url::Url::parse(&format!("http://x{}", path)).ok()?;
+        let value = url.query_pairs().find(|(k, _)| k == key)?.1.to_string();
+        Some(value)
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn xtream_catalogs_and_epg() {
+        let server = json_server(|request| {
+            let path = request.path.as_str();
+            if path.starts_with("/xmltv.php") {
+                let xml = r#"<tv><channel id="news.uk"><display-name>News</display-name></channel><programme channel="news.uk" start="20240101120000 +0000" stop="20240101130000 +0000"><title>Headlines</title></programme></tv>"#;
+                return ("200 OK", xml.to_string());
+            }
+            if query_param(path, "password").as_deref() != Some("secret") {
+                return ("200 OK", r#"{"user_info":{"auth":0}}"#.to_string());
+            }
+            let body = match query_param(path, "action").as_deref() {
+                None => {
+                    r#"{"user_info":{"username":"alice","auth":1,"status":"Active","exp_date":"1893456000","is_trial":"0","active_cons":"0","max_connections":"2","allowed_output_formats":["m3u8","ts"]},"server_info":{}}"#
+                }
+                Some("get_live_categories") => {
+                    r#"[{"category_id":"1","category_name":"News"},{"category_id":2,"category_name":"Sports"}]"#
+                }
+                Some("get_live_streams") => {
+                    r#"[{"num":1,"name":"News 24","stream_id":101,"stream_icon":"http://logo/news.png","epg_channel_id":"news.uk","category_id":"1","tv_archive":1,"tv_archive_duration":"3"},{"num":2,"name":"Sport HD","stream_id":"102","stream_icon":"","epg_channel_id":null,"category_id":"2"}]"#
+                }
+                Some("get_vod_categories") => r#"[{"category_id":"10","category_name":"Movies"}]"#,
+                Some("get_vod_streams") => {
+                    r#"[{"name":"A Film","stream_id":501,"container_extension":"mkv","rating":"7.5","added":"1700000000","category_id":"10","stream_icon":"http://img/a.jpg"}]"#
+                }
+                Some("get_series") => {
+                    r#"[{"name":"A Show","series_id":77,"cover":"http://img/s.jpg","releaseDate":"2019-05-01","rating":"8","category_id":"20"}]"#
+                }
+                Some("get_series_info") => {
+                    r#"{"seasons":[],"info":{},"episodes":{"2":[{"id":"9002","episode_num":"1","title":"S2E1","container_extension":"mp4","season":2,"info":{"duration_secs":1500}}],"1":[{"id":"9001","episode_num":1,"title":"S1E1","container_extension":"mkv","season":1,"info":{"plot":"Pilot"}}]}}"#
+                }
+                Some("get_short_epg") => {
+                    r#"{"epg_listings":[{"id":"1","epg_id":"news.uk","title":"SGVhZGxpbmVz","description":"VG9wIHN0b3JpZXM=","start_timestamp":"1704110400","stop_timestamp":"1704114000"}]}"#
+                }
+                _ => "[]",
+            };
+            ("200 OK", body.to_string())
+        });
+
+        block_on(async {
+            let client =
+                XtreamClient::new(&format!("{}/player_api.php", server), "alice", "secret")
+                    .unwrap();
+            assert_eq!(client.server(), server);
+
+            let account = client.account().await.unwrap();
+            assert_eq!(account.status, "Active");
+            assert_eq!(account.expires_at, Some(1893456000));
+            assert_eq!(account.max_connections, Some(2));
+            assert_eq!(account.output_formats, vec!["m3u8", "ts"]);
+
+            let playlist = client.playlist().await.unwrap();
+            assert_eq!(playlist.channels.len(), 2);
+            let news = &playlist.channels[0];
+            assert_eq!(news.id, "xtream:101");
+            assert_eq!(
+                news.stream_url,
+                format!("{}/live/alice/secret/101.ts", server)
+            );
+            assert_eq!(news.group.as_deref(), Some("News"));
+            assert_eq!(news.epg_id.as_deref(), Some("news.uk"));
+            assert_eq!(playlist.channels[1].logo_url, None);
+            assert_eq!(news.catchup.as_ref().unwrap().days, Some(3));
+            assert!(playlist.channels[1].catchup.is_none());
+            // 2024-01-01 12:00 UTC, one hour
+            assert_eq!(
+                crate::iptv::catchup_url(news, 1704110400, 1704114000, 1704200000).unwrap(),
+                format!(
+                    "{}/timeshift/alice/secret/60/2024-01-01:12-00/101.ts",
+                    server
+                )
+            );
+            assert_eq!(playlist.groups, vec!["News", "Sports"]);
+
+            let vod = client.vod_streams(Some("10")).await.unwrap();
+            assert_eq!(
+                vod[0].stream_url,
+                format!("{}/movie/alice/secret/501.mkv", server)
+            );
+            assert_eq!(vod[0].rating, Some(7.5));
+            assert_eq!(vod[0].added, Some(1700000000));
+
+            let series = client.series(None).await.unwrap();
+            assert_eq!(series[0].id, "77");
+            assert_eq!(series[0].year, Some(2019));
+
+            let episodes = client.series_episodes("77").await.unwrap();
+            assert_eq!(episodes.len(), 2);
+            assert_eq!((episodes[0].season, episodes[0].episode), (1, 1));
+            assert_eq!(
+                episodes[0].stream_url,
+                format!("{}/series/alice/secret/9001.mkv", server)
+            );
+            assert_eq!(episodes[1].duration_secs, Some(1500));
+
+            let short = client.short_epg("101", 4).await.unwrap();
+            assert_eq!(short[0].title, "Headlines");
+            assert_eq!(short[0].description.as_deref(), Some("Top stories"));
+            assert_eq!(short[0].channel_id, "news.uk");
+
+            let epg = client.epg().await.unwrap();
+            assert_eq!(epg.match_channel(news), Some("news.uk"));
+            assert_eq!(
+                epg.program_at("news.uk", 1704110400 + 60).unwrap().title,
+                "Headlines"
+            );
+
+            let wrong = XtreamClient::new(&server, "alice", "nope").unwrap();
+            assert!(wrong.account().await.unwrap_err().contains("login failed"));
+        });
+    }
+
+    #[test]
+    fn stalker_portal_flow() {
+        let handshakes = Arc::new(AtomicUsize::new(0));
+        let counter = handshakes.clone();
+        let server = json_server(move |request| {
+            let path = request.path.as_str();
+            let header = |name: &str| request.headers.get(name).map_or("", String::as_str);
+            assert!(
+                path.starts_with("/stalker_portal/server/load.php"),
+                "{}",
+                path
+            );
+            assert!(header("cookie").contains("mac=00%3A1A%3A79%3A12%3A34%3A56"));
+            let action = query_param(path, "action").unwrap_or_default();
+            if action == "handshake" {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                return ("200 OK", format!(r#"{{"js":{{"token":"T{}"}}}}"#, n));
+            }
+            // The first token "expires" after the profile call
+            let current = format!("Bearer T{}", counter.load(Ordering::SeqCst));
+            let auth = header("authorization");
+            if auth != current || (auth == "Bearer T1" && action != "get_profile") {
+                return ("401 Unauthorized", "Authorization failed.".to_string());
+            }
+            let kind = query_param(path, "type").unwrap_or_default();
+            let body = match (kind.as_str(), action.as_str()) {
+                ("stb", "get_profile") => r#"{"js":{"id":1,"status":0}}"#.to_string(),
+                ("itv", "get_genres") => r#"{"js":[{"id":"*","title":"All"},{"id":"3","title":"Movies"}]}"#.to_string(),
+                ("itv", "get_all_channels") => r#"{"js":{"total_items":1,"data":[{"id":"7","name":"Cinema 1","cmd":"ffmpeg http://localhost/ch/7_","tv_genre_id":"3","logo":"7.png"}]}}"#.to_string(),
+                ("itv", "create_link") => {
+                    assert_eq!(query_param(path, "cmd").as_deref(), Some("ffmpeg http://localhost/ch/7_"));
+                    r#"{"js":{"cmd":"ffmpeg http://cdn.example/live/7.ts?token=abc"}}"#.to_string()
+                }
+                ("itv", "get_epg_info") => r#"{"js":{"data":{"7":[{"name":"Late Film","descr":"Drama","start_timestamp":1704110400,"stop_timestamp":"1704117600"}]}}}"#.to_string(),
+                ("vod", "get_ordered_list") => {
+                    let page: usize = query_param(path, "p").unwrap().parse().unwrap();
+                    format!(
+                        r#"{{"js":{{"total_items":3,"max_page_items":2,"data":[{}]}}}}"#,
+                        (1..=3)
+                            .skip((page - 1) * 2)
+                            .take(2)
+                            .map(|i| format!(r#"{{"id":"{}","name":"Movie {}","cmd":"/media/{}.mpg","year":"2001"}}"#, i, i, i))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    )
+                }
+                ("vod", "create_link") => {
+                    let cmd = query_param(path, "cmd").unwrap();
+                    let series = query_param(path, "series").map(|s| format!("?ep={}", s)).unwrap_or_default();
+                    format!(r#"{{"js":{{"cmd":"ffmpeg http://cdn.example/vod{}{}"}}}}"#, cmd.replace("ffmpeg http://localhost", ""), series)
+                }
+                ("series", "get_ordered_list") => {
+                    assert_eq!(query_param(path, "movie_id").as_deref(), Some("42"));
+                    r#"{"js":{"total_items":2,"data":[{"id":"42:2","name":"Season 2","cmd":"ffmpeg http://localhost/s/42_2","series":[1]},{"id":"42:1","name":"Season 1","cmd":"ffmpeg http://localhost/s/42_1","series":[1,2]}]}}"#.to_string()
+                }
+                _ => r#"{"js":[]}"#.to_string(),
+            };
+            ("200 OK", body)
+        });
+
+        block_on(async {
+            let client = StalkerClient::new(
+                &format!("{}/stalker_portal/c/", server),
+                "00-1a-79-12-34-56",
+            )
+            .unwrap();
+            assert_eq!(client.mac(), "00:1A:79:12:34:56");
+
+            // T1 is rejected after the profile call, so this re-handshakes
+            let playlist = client.playlist().await.unwrap();
+            assert_eq!(handshakes.load(Ordering::SeqCst), 2);
+            let channel = &playlist.channels[0];
+            assert_eq!(channel.id, "stalker:7");
+            assert_eq!(channel.stream_url, "http://localhost/ch/7_");
+            assert_eq!(channel.group.as_deref(), Some("Movies"));
+            assert_eq!(channel.logo_url, None);
+            assert_eq!(
+                client.resolve_channel(channel).await.unwrap(),
+                "http://cdn.example/live/7.ts?token=abc"
+            );
+
+            let epg = client.epg(6).await.unwrap();
+            let program = epg.program_at("stalker:7", 1704110400 + 60).unwrap();
+            assert_eq!(program.title, "Late Film");
+            assert_eq!(program.end_time, 1704117600);
+            assert_eq!(epg.match_channel(channel), Some("stalker:7"));
+
+            let vod = client.vod_streams("1").await.unwrap();
+            assert_eq!(vod.len(), 3);
+            assert_eq!(vod[2].year, Some(2001));
+            assert_eq!(
+                client.resolve_vod(&vod[0]).await.unwrap(),
+                "http://cdn.example/vod/media/1.mpg"
+            );
+
+            let episodes = client.series_episodes("42").await.unwrap();
+            let order: Vec<(u32, u32)> = episodes.iter().map(|e| (e.season, e.episode)).collect();
+            assert_eq!(order, vec![(1, 1), (1, 2), (2, 1)]);
+            assert_eq!(
+                client.resolve_episode(&episodes[1]).await.unwrap(),
+                "http://cdn.example/vod/s/42_1?ep=2"
+            );
+            assert_eq!(handshakes.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn provider_urls_and_coercion() {
+        let client = XtreamClient::from_m3u_url(
+            "http://panel.example:8080/get.php?username=bob&password=p%40ss&type=m3u_plus&output=m3u8",
+        )
+        .unwrap();
+        assert_eq!(client.server(), "http://panel.example:8080");
+        assert_eq!(
+            client.live_url("5"),
+            "http://panel.example:8080/live/bob/p%40ss/5.m3u8"
+        );
+        assert!(XtreamClient::from_m3u_url("http://example.com/list.m3u").is_none());
+
+        let portal = |url: &str| StalkerClient::new(url, "00:1A:79:00:00:01").unwrap();
+        assert_eq!(portal("http://host/c/").api_url(), "http://host/portal.php");
+        assert_eq!(
+            portal("host:88/stalker_portal/c/").api_url(),
+            "http://host:88/stalker_portal/server/load.php"
+        );
+        assert_eq!(
+            portal("http://host/stalker_portal/server/load.php").api_url(),
+            "http://host/stalker_portal/server/load.php"
+        );
+        assert!(StalkerClient::new("http://host/c/", "00:1A:79").is_err());
+
+        assert_eq!(strip_cmd("ffmpeg http://h/1"), "http://h/1");
+        assert_eq!(strip_cmd("auto http://h/1"), "http://h/1");
+        assert_eq!(strip_cmd("/media/1.mpg"), "/media/1.mpg");
+        assert_eq!(portal_cmd("http://h/1"), "ffmpeg http://h/1");
+
+        let value: Value = serde_json::json!({"a": "12", "b": 3.0, "c": "", "d": "7.5", "e": null});
+        assert_eq!(json_i64(&value["a"]), Some(12));
+        assert_eq!(json_i64(&value["b"]), Some(3));
+        assert_eq!(json_str(&value["c"]), None);
+        assert_eq!(json_str(&value["b"]).as_deref(), Some("3.0"));
+        assert_eq!(json_f64(&value["d"]), Some(7.5));
+        assert_eq!(json_i64(&value["e"]), None);
+        assert_eq!(
+            decode_base64_text(&serde_json::json!("plain text!")).as_deref(),
+            Some("plain text!")
+        );
+    }
+}
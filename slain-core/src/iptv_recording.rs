@@ -0,0 +1,1831 @@
+//! IPTV Recording & Timeshift
+//!
+//! The recording half of `iptv`:
+//! - Timeshift: pause and rewind a live channel, backed by a bounded on-disk
+//!   ring of MPEG-TS segments
+//! - Scheduled recordings from EPG entries (with padding), written as TS or
+//!   remuxed to MKV once the window closes
+//! - The schedule lives in a JSON file, so it survives restarts. A recording
+//!   cut short by a shutdown picks up again (appending to the same TS) if
+//!   its window is still open
+//!
+//! Live sources are HLS playlists (through `AdaptiveClient`, TS segments
+//! only) or plain HTTP MPEG-TS streams. Timeshift moves on to a channel's
+//! alternate URLs when its stream fails (see `iptv_health`).
+
+use crate::adaptive_stream::{
+    AdaptiveClient, AdaptiveConfig, HttpConfig, MediaKind, SegmentFormat,
+};
+use crate::h264_utils;
+use crate::iptv::{EpgProgram, IptvChannel};
+use crate::iptv_health::StreamFailover;
+use crate::mux::{MuxCodec, MuxPacket, MuxStream, UniversalMuxer};
+use crate::ts_demux::{StreamCodec, TsDemuxer};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub(crate) const TS_PACKET_SIZE: usize = 188;
+pub(crate) const TS_SYNC_BYTE: u8 = 0x47;
+
+/// Bytes asked of an HTTP stream per read
+const HTTP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Wait between reconnects while a recording window is open
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// 2^33 ticks of the 90 kHz clock, in microseconds
+const PTS_WRAP_US: i64 = (1 << 33) * 1_000_000 / 90_000;
+
+// ============================================================================
+// Live Sources
+// ============================================================================
+
+/// A run of whole TS packets from a live source
+struct LiveChunk {
+    packets: Vec<u8>,
+    /// Media seconds covered, when the source knows (HLS segments)
+    duration: Option<f64>,
+}
+
+enum LiveSource {
+    Hls(Box<AdaptiveClient>),
+    Http {
+        reader: Box<dyn Read + Send>,
+        pending: Vec<u8>,
+    },
+}
+
+fn is_hls_url(url: &str) -> bool {
+    let path = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(url)
+        .to_ascii_lowercase();
+    path.ends_with(".m3u8") || path.ends_with(".m3u")
+}
+
+impl LiveSource {
+    fn open(url: &str, http: &HttpConfig) -> Result<Self, String> {
+        let open_hls = || {
+            let config = AdaptiveConfig {
+                http: http.clone(),
+                ..Default::default()
+            };
+            AdaptiveClient::open(url, config).map(|client| Self::Hls(Box::new(client)))
+        };
+        if is_hls_url(url) {
+            return open_hls();
+        }
+
+        let timeout = Duration::from_secs(http.timeout_secs.max(1));
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(timeout)
+            .timeout_read(timeout)
+            .user_agent(&http.user_agent)
+            .build();
+        let mut request = agent.get(url);
+        for (name, value) in &http.headers {
+            request = request.set(name, value);
+        }
+        let response = request
+            .call()
+            .map_err(|e| format!("Failed to open stream: {}", e))?;
+        // Extensionless playlist URLs are common on IPTV panels
+        if response
+            .content_type()
+            .to_ascii_lowercase()
+            .contains("mpegurl")
+        {
+            drop(response);
+            return open_hls();
+        }
+        Ok(Self::Http {
+            reader: Box::new(response.into_reader()),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Next chunk, or `None` once the source ends. Blocks while live.
+    fn next_chunk(&mut self) -> Result<Option<LiveChunk>, String> {
+        match self {
+            Self::Hls(client) => loop {
+                let Some(segment) = client.next_segment()? else {
+                    return Ok(None);
+                };
+                if segment.track != MediaKind::Main {
+                    tracing::debug!("Timeshift: skipping separate audio segment");
+                    continue;
+                }
+                if segment.format != SegmentFormat::Ts {
+                    return Err("Only MPEG-TS HLS streams can be recorded".to_string());
+                }
+                return Ok(Some(LiveChunk {
+                    packets: segment.data,
+                    duration: Some(segment.duration),
+                }));
+            },
+            Self::Http { reader, pending } => {
+                let mut buf = vec![0u8; HTTP_CHUNK_SIZE];
+                loop {
+                    let n = reader
+                        .read(&mut buf)
+                        .map_err(|e| format!("Stream read error: {}", e))?;
+                    if n == 0 {
+                        return Ok(None);
+                    }
+                    pending.extend_from_slice(&buf[..n]);
+
+                    // Resync: a sync byte with another one a packet later
+                    let start = (0..pending.len()).find(|&i| {
+                        pending[i] == TS_SYNC_BYTE
+                            && pending
+                                .get(i + TS_PACKET_SIZE)
+                                .is_none_or(|&b| b == TS_SYNC_BYTE)
+                    });
+                    match start {
+                        Some(start) => {
+                            pending.drain(..start);
+                        }
+                        None => {
+                            pending.clear();
+                            continue;
+                        }
+                    }
+                    let whole = pending.len() / TS_PACKET_SIZE * TS_PACKET_SIZE;
+                    if whole > 0 {
+                        let packets: Vec<u8> = pending.drain(..whole).collect();
+                        return Ok(Some(LiveChunk {
+                            packets,
+                            duration: None,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// TS Packet Helpers
+// ============================================================================
+
+pub(crate) fn packet_pid(packet: &[u8]) -> u16 {
+    (((packet[1] & 0x1F) as u16) << 8) | packet[2] as u16
+}
+
+pub(crate) fn payload_unit_start(packet: &[u8]) -> bool {
+    packet[1] & 0x40 != 0
+}
+
+/// random_access_indicator in the adaptation field
+pub(crate) fn is_random_access(packet: &[u8]) -> bool {
+    packet[3] & 0x20 != 0 && packet[4] > 0 && packet[5] & 0x40 != 0
+}
+
+/// PMT PIDs listed in a single-packet PAT
+pub(crate) fn pat_pmt_pids(packet: &[u8]) -> Vec<u16> {
+    let mut offset = 4;
+    if packet[3] & 0x20 != 0 {
+        offset += 1 + packet[4] as usize;
+    }
+    let Some(&pointer) = packet.get(offset) else {
+        return Vec::new();
+    };
+    let Some(section) = packet.get(offset + 1 + pointer as usize..) else {
+        return Vec::new();
+    };
+    if section.len() < 8 {
+        return Vec::new();
+    }
+    let section_length = (((section[1] & 0x0F) as usize) << 8) | section[2] as usize;
+    // Program loop runs from after the 8-byte header up to the CRC
+    let end = (3 + section_length).saturating_sub(4).min(section.len());
+    section
+        .get(8..end)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .filter(|entry| entry[0] != 0 || entry[1] != 0)
+        .map(|entry| (((entry[2] & 0x1F) as u16) << 8) | entry[3] as u16)
+        .collect()
+}
+
+/// Latest PAT and PMTs, repeated at the head of every timeshift segment so
+/// each one demuxes on its own
+#[derive(Default)]
+struct PsiCache {
+    pat: Option<Vec<u8>>,
+    pmt_pids: Vec<u16>,
+    pmts: Vec<(u16, Vec<u8>)>,
+}
+
+impl PsiCache {
+    fn observe(&mut self, packet: &[u8]) {
+        if !payload_unit_start(packet) {
+            return;
+        }
+        let pid = packet_pid(packet);
+        if pid == 0 {
+            self.pmt_pids = pat_pmt_pids(packet);
+            self.pmts.retain(|(pid, _)| self.pmt_pids.contains(pid));
+            self.pat = Some(packet.to_vec());
+        } else if self.pmt_pids.contains(&pid) {
+            match self.pmts.iter_mut().find(|(p, _)| *p == pid) {
+                Some((_, pmt)) => *pmt = packet.to_vec(),
+                None => self.pmts.push((pid, packet.to_vec())),
+            }
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = self.pat.clone().unwrap_or_default();
+        if !header.is_empty() {
+            for (_, pmt) in &self.pmts {
+                header.extend_from_slice(pmt);
+            }
+        }
+        header
+    }
+}
+
+// ============================================================================
+// Timeshift Buffer
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeshiftConfig {
+    /// Directory for the segment files (old `seg_*.ts` files are cleared)
+    pub dir: PathBuf,
+    /// Target segment length; cuts wait for a random access point
+    pub segment_secs: f64,
+    /// How far back the buffer reaches
+    pub max_secs: f64,
+    /// Disk cap in bytes (0 = bounded by `max_secs` only)
+    pub max_bytes: u64,
+    pub http: HttpConfig,
+}
+
+impl Default for TimeshiftConfig {
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir().join("slain-timeshift"),
+            segment_secs: 2.0,
+            max_secs: 30.0 * 60.0,
+            max_bytes: 0,
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeshiftStatus {
+    /// Seconds of stream on disk
+    pub buffered_secs: f64,
+    pub segments: usize,
+    pub bytes: u64,
+    /// Still receiving (false once the source ended or failed)
+    pub live: bool,
+    pub error: Option<String>,
+    /// URL currently feeding the buffer (changes on failover)
+    #[serde(default)]
+    pub source_url: Option<String>,
+}
+
+struct RingSegment {
+    sequence: u64,
+    path: PathBuf,
+    /// Seconds since the buffer started
+    start: f64,
+    duration: f64,
+    bytes: u64,
+    complete: bool,
+}
+
+#[derive(Default)]
+struct Ring {
+    segments: VecDeque<RingSegment>,
+    next_sequence: u64,
+    finished: bool,
+    error: Option<String>,
+    source_url: Option<String>,
+}
+
+impl Ring {
+    fn live_edge(&self) -> f64 {
+        self.segments
+            .back()
+            .map_or(0.0, |segment| segment.start + segment.duration)
+    }
+}
+
+struct RingShared {
+    ring: Mutex<Ring>,
+    changed: Condvar,
+    running: AtomicBool,
+}
+
+/// Writes incoming packets into the ring, cutting and evicting segments
+struct RingWriter {
+    config: TimeshiftConfig,
+    shared: Arc<RingShared>,
+    psi: PsiCache,
+    file: Option<File>,
+    /// Timeline position of the open segment
+    segment_start: Option<f64>,
+}
+
+impl RingWriter {
+    fn new(config: TimeshiftConfig, shared: Arc<RingShared>) -> Self {
+        Self {
+            config,
+            shared,
+            psi: PsiCache::default(),
+            file: None,
+            segment_start: None,
+        }
+    }
+
+    /// Append packets covering `start..end` seconds of the timeline
+    fn write(&mut self, packets: &[u8], start: f64, end: f64) -> Result<(), String> {
+        let mut pending: Vec<u8> = Vec::with_capacity(packets.len());
+        for packet in packets.chunks_exact(TS_PACKET_SIZE) {
+            self.psi.observe(packet);
+
+            let cut = match self.segment_start {
+                None => true,
+                Some(segment_start) => {
+                    let elapsed = start - segment_start;
+                    elapsed >= self.config.segment_secs
+                        && (is_random_access(packet) || elapsed >= 2.0 * self.config.segment_secs)
+                }
+            };
+            if cut {
+                self.flush(&mut pending, start)?;
+                self.open_segment(start)?;
+            }
+            pending.extend_from_slice(packet);
+        }
+        self.flush(&mut pending, end)
+    }
+
+    /// Write out `pending` and extend the open segment up to `time`
+    fn flush(&mut self, pending: &mut Vec<u8>, time: f64) -> Result<(), String> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(pending)
+            .map_err(|e| format!("Timeshift write error: {}", e))?;
+        let mut ring = self.shared.ring.lock();
+        if let Some(segment) = ring.segments.back_mut() {
+            segment.bytes += pending.len() as u64;
+            segment.duration = (time - segment.start).max(segment.duration);
+        }
+        pending.clear();
+        drop(ring);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    fn open_segment(&mut self, start: f64) -> Result<(), String> {
+        let header = self.psi.header();
+        let mut ring = self.shared.ring.lock();
+        if let Some(previous) = ring.segments.back_mut() {
+            previous.complete = true;
+        }
+        let sequence = ring.next_sequence;
+        ring.next_sequence += 1;
+        let path = self.config.dir.join(format!("seg_{}.ts", sequence));
+        let mut file =
+            File::create(&path).map_err(|e| format!("Timeshift segment error: {}", e))?;
+        file.write_all(&header)
+            .map_err(|e| format!("Timeshift write error: {}", e))?;
+        ring.segments.push_back(RingSegment {
+            sequence,
+            path,
+            start,
+            duration: 0.0,
+            bytes: header.len() as u64,
+            complete: false,
+        });
+        self.file = Some(file);
+        self.segment_start = Some(start);
+
+        // Evict from the back of the window, never the open segment
+        loop {
+            let buffered = ring.live_edge() - ring.segments.front().map_or(0.0, |s| s.start);
+            let bytes: u64 = ring.segments.iter().map(|s| s.bytes).sum();
+            let over = buffered > self.config.max_secs
+                || (self.config.max_bytes > 0 && bytes > self.config.max_bytes);
+            if ring.segments.len() < 2 || !over {
+                break;
+            }
+            let Some(oldest) = ring.segments.pop_front() else {
+                break;
+            };
+            if let Err(e) = fs::remove_file(&oldest.path) {
+                tracing::debug!("Timeshift: couldn't remove {:?}: {}", oldest.path, e);
+            }
+        }
+        drop(ring);
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    fn finish(&mut self, error: Option<String>) {
+        self.file = None;
+        let mut ring = self.shared.ring.lock();
+        if let Some(segment) = ring.segments.back_mut() {
+            segment.complete = true;
+        }
+        ring.finished = true;
+        ring.error = error;
+        drop(ring);
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Open the current candidate, moving down the list until one connects
+fn open_failover(failover: &mut StreamFailover, http: &HttpConfig) -> Result<LiveSource, String> {
+    loop {
+        match LiveSource::open(failover.current(), http) {
+            Ok(source) => return Ok(source),
+            Err(e) => {
+                tracing::warn!("Stream {} failed: {}", failover.current(), e);
+                if failover.fail().is_none() {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+fn run_ring_writer(
+    mut source: LiveSource,
+    mut failover: StreamFailover,
+    http: HttpConfig,
+    mut writer: RingWriter,
+) {
+    let started = Instant::now();
+    let mut clock = 0.0;
+    let mut receiving = false;
+    let result = loop {
+        if !writer.shared.running.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+        let outcome = match source.next_chunk() {
+            Ok(Some(chunk)) => Ok(chunk),
+            Ok(None) => Err(None),
+            Err(e) => Err(Some(e)),
+        };
+        match outcome {
+            Ok(chunk) => {
+                if !receiving {
+                    receiving = true;
+                    failover.succeeded();
+                }
+                // HTTP streams arrive in real time; HLS says how long it is
+                let end = match chunk.duration {
+                    Some(duration) => clock + duration,
+                    None => started.elapsed().as_secs_f64().max(clock),
+                };
+                if let Err(e) = writer.write(&chunk.packets, clock, end) {
+                    break Err(e);
+                }
+                clock = end;
+            }
+            // Source ended or broke: carry on from the next candidate
+            Err(error) => {
+                if let Some(e) = &error {
+                    tracing::warn!("Stream {} failed: {}", failover.current(), e);
+                }
+                if failover.fail().is_none() {
+                    break error.map_or(Ok(()), Err);
+                }
+                match open_failover(&mut failover, &http) {
+                    Ok(next) => {
+                        tracing::info!("Timeshift failing over to {}", failover.current());
+                        writer.shared.ring.lock().source_url = Some(failover.current().to_string());
+                        source = next;
+                        receiving = false;
+                    }
+                    Err(e) => break Err(e),
+                }
+            }
+        }
+    };
+    if let Err(e) = &result {
+        tracing::warn!("Timeshift stopped: {}", e);
+    }
+    writer.finish(result.err());
+}
+
+/// Live channel buffered to disk so it can be paused and rewound. Readers
+/// trail the live edge by as much as the ring holds.
+pub struct TimeshiftBuffer {
+    shared: Arc<RingShared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TimeshiftBuffer {
+    /// Connect to `url` and start buffering
+    pub fn start(url: &str, config: TimeshiftConfig) -> Result<Self, String> {
+        Self::start_with_failover(StreamFailover::new(url, Vec::new()), config)
+    }
+
+    /// Like `start`, but when the stream fails or ends, buffering continues
+    /// from the next candidate URL of `failover`
+    pub fn start_with_failover(
+        mut failover: StreamFailover,
+        config: TimeshiftConfig,
+    ) -> Result<Self, String> {
+        prepare_ring_dir(&config.dir)?;
+        let source = open_failover(&mut failover, &config.http)?;
+        let shared = Arc::new(RingShared {
+            ring: Mutex::new(Ring {
+                source_url: Some(failover.current().to_string()),
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            running: AtomicBool::new(true),
+        });
+        let http = config.http.clone();
+        let writer = RingWriter::new(config, shared.clone());
+        let thread = thread::Builder::new()
+            .name("iptv-timeshift".to_string())
+            .spawn(move || run_ring_writer(source, failover, http, writer))
+            .map_err(|e| format!("Failed to start timeshift: {}", e))?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn status(&self) -> TimeshiftStatus {
+        let ring = self.shared.ring.lock();
+        TimeshiftStatus {
+            buffered_secs: ring.live_edge() - ring.segments.front().map_or(0.0, |s| s.start),
+            segments: ring.segments.len(),
+            bytes: ring.segments.iter().map(|s| s.bytes).sum(),
+            live: !ring.finished,
+            error: ring.error.clone(),
+            source_url: ring.source_url.clone(),
+        }
+    }
+
+    /// Reader starting `behind_live_secs` back from the live edge (clamped to
+    /// what's buffered), at the segment boundary at or before that point
+    pub fn reader(&self, behind_live_secs: f64) -> TimeshiftReader {
+        let ring = self.shared.ring.lock();
+        let target = ring.live_edge() - behind_live_secs.max(0.0);
+        let sequence = ring
+            .segments
+            .iter()
+            .find(|s| s.start + s.duration > target)
+            .or(ring.segments.back())
+            .map_or(ring.next_sequence, |s| s.sequence);
+        TimeshiftReader {
+            shared: self.shared.clone(),
+            sequence,
+            offset: 0,
+            file: None,
+        }
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            // The writer may be blocked on the network; it exits on its own
+            if thread.is_finished() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for TimeshiftBuffer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn prepare_ring_dir(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with("seg_") && name.ends_with(".ts") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Byte stream of the buffered channel from some point behind live. Blocks
+/// at the live edge; a reader that falls out of the ring skips to the
+/// oldest segment still on disk.
+pub struct TimeshiftReader {
+    shared: Arc<RingShared>,
+    sequence: u64,
+    offset: u64,
+    file: Option<(u64, File)>,
+}
+
+impl TimeshiftReader {
+    /// Seconds between the read position and the live edge
+    pub fn behind_live_secs(&self) -> f64 {
+        let ring = self.shared.ring.lock();
+        let position = match ring.segments.iter().find(|s| s.sequence >= self.sequence) {
+            Some(s) if s.sequence == self.sequence && s.bytes > 0 => {
+                s.start + s.duration * self.offset as f64 / s.bytes as f64
+            }
+            Some(s) => s.start,
+            None => ring.live_edge(),
+        };
+        (ring.live_edge() - position).max(0.0)
+    }
+}
+
+impl Read for TimeshiftReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut ring = self.shared.ring.lock();
+        loop {
+            if let Some(front) = ring.segments.front() {
+                if self.sequence < front.sequence {
+                    tracing::debug!("Timeshift reader fell out of the ring; skipping ahead");
+                    self.sequence = front.sequence;
+                    self.offset = 0;
+                    self.file = None;
+                }
+                let index = (self.sequence - front.sequence) as usize;
+                if let Some(segment) = ring.segments.get(index) {
+                    if self.offset < segment.bytes {
+                        // Holding the lock keeps the segment from being evicted
+                        if self.file.as_ref().map(|(sequence, _)| *sequence) != Some(self.sequence)
+                        {
+                            let mut file = File::open(&segment.path)?;
+                            file.seek(SeekFrom::Start(self.offset))?;
+                            self.file = Some((self.sequence, file));
+                        }
+                        let available = (segment.bytes - self.offset).min(buf.len() as u64);
+                        let (_, file) = self.file.as_mut().expect("segment opened above");
+                        let n = file.read(&mut buf[..available as usize])?;
+                        if n > 0 {
+                            self.offset += n as u64;
+                            return Ok(n);
+                        }
+                    } else if segment.complete {
+                        self.sequence += 1;
+                        self.offset = 0;
+                        self.file = None;
+                        continue;
+                    }
+                }
+            }
+            if ring.finished {
+                return match &ring.error {
+                    Some(error) => Err(io::Error::other(error.clone())),
+                    None => Ok(0),
+                };
+            }
+            if !self.shared.running.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+            self.shared
+                .changed
+                .wait_for(&mut ring, Duration::from_millis(500));
+        }
+    }
+}
+
+// ============================================================================
+// Scheduled Recordings
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RecordingFormat {
+    /// The stream as received
+    #[default]
+    Ts,
+    /// Remuxed to Matroska after the window closes
+    Mkv,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ts => "ts",
+            Self::Mkv => "mkv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordingStatus {
+    Scheduled,
+    Recording,
+    Completed { path: PathBuf, bytes: u64 },
+    Failed(String),
+    Cancelled,
+}
+
+impl RecordingStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            Self::Completed { .. } | Self::Failed(_) | Self::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRecording {
+    pub id: String,
+    pub channel_id: String,
+    pub channel_name: String,
+    pub stream_url: String,
+    pub title: String,
+    /// Unix seconds, as in `EpgProgram`
+    pub start_time: i64,
+    pub end_time: i64,
+    pub padding_before_secs: i64,
+    pub padding_after_secs: i64,
+    pub format: RecordingFormat,
+    pub status: RecordingStatus,
+}
+
+impl ScheduledRecording {
+    /// Recording of an EPG entry, starting `padding_before_secs` early and
+    /// running `padding_after_secs` over
+    pub fn from_program(
+        channel: &IptvChannel,
+        program: &EpgProgram,
+        padding_before_secs: i64,
+        padding_after_secs: i64,
+        format: RecordingFormat,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            channel_id: channel.id.clone(),
+            channel_name: channel.name.clone(),
+            stream_url: channel.stream_url.clone(),
+            title: program.title.clone(),
+            start_time: program.start_time,
+            end_time: program.end_time,
+            padding_before_secs: padding_before_secs.max(0),
+            padding_after_secs: padding_after_secs.max(0),
+            format,
+            status: RecordingStatus::Scheduled,
+        }
+    }
+
+    /// Capture window with padding applied (Unix seconds)
+    pub fn window(&self) -> (i64, i64) {
+        (
+            self.start_time - self.padding_before_secs,
+            self.end_time + self.padding_after_secs,
+        )
+    }
+
+    /// `<channel> - <title> - <date time>`, safe for file names
+    fn file_stem(&self) -> String {
+        use chrono::TimeZone;
+
+        let when = chrono::Local
+            .timestamp_opt(self.start_time, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H%M").to_string())
+            .unwrap_or_else(|| self.start_time.to_string());
+        format!("{} - {} - {}", self.channel_name, self.title, when)
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingSchedulerConfig {
+    /// JSON file the schedule is kept in
+    pub state_path: PathBuf,
+    pub output_dir: PathBuf,
+    pub http: HttpConfig,
+    /// How often the background thread checks the schedule
+    pub poll_interval_ms: u64,
+}
+
+impl Default for RecordingSchedulerConfig {
+    fn default() -> Self {
+        let mut state_path = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+        state_path.push("SLAIN");
+        state_path.push("iptv_recordings.json");
+        Self {
+            state_path,
+            output_dir: dirs::video_dir()
+                .map(|p| p.join("SLAIN Recordings"))
+                .unwrap_or_else(|| PathBuf::from(".")),
+            http: HttpConfig::default(),
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+fn now_unix() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+struct ActiveRecording {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    recordings: Vec<ScheduledRecording>,
+    active: HashMap<String, ActiveRecording>,
+}
+
+struct SchedulerInner {
+    config: RecordingSchedulerConfig,
+    state: Mutex<SchedulerState>,
+    running: AtomicBool,
+}
+
+impl SchedulerInner {
+    fn save(&self, recordings: &[ScheduledRecording]) {
+        if let Err(e) = save_schedule(&self.config.state_path, recordings) {
+            tracing::warn!("Failed to save recording schedule: {}", e);
+        }
+    }
+}
+
+fn load_schedule(path: &Path) -> Result<Vec<ScheduledRecording>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read schedule: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid schedule file: {}", e))
+}
+
+fn save_schedule(path: &Path, recordings: &[ScheduledRecording]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(recordings).map_err(|e| e.to_string())?;
+    // Write-then-rename so a crash never leaves half a schedule
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json).map_err(|e| e.to_string())?;
+    fs::rename(&temp, path).map_err(|e| e.to_string())
+}
+
+/// EPG-driven recorder. `tick` starts recordings whose window has opened;
+/// `start` runs it on a background thread.
+pub struct RecordingScheduler {
+    inner: Arc<SchedulerInner>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl RecordingScheduler {
+    /// Load the saved schedule. Recordings interrupted by a shutdown resume
+    /// on the next tick if their window is still open.
+    pub fn open(config: RecordingSchedulerConfig) -> Result<Self, String> {
+        let mut recordings = load_schedule(&config.state_path)?;
+        let now = now_unix();
+        for recording in &mut recordings {
+            if recording.status == RecordingStatus::Recording {
+                recording.status = if recording.window().1 > now {
+                    tracing::info!("Resuming interrupted recording \"{}\"", recording.title);
+                    RecordingStatus::Scheduled
+                } else {
+                    RecordingStatus::Failed("Interrupted by shutdown".to_string())
+                };
+            }
+        }
+        let inner = Arc::new(SchedulerInner {
+            config,
+            state: Mutex::new(SchedulerState {
+                recordings,
+                active: HashMap::new(),
+            }),
+            running: AtomicBool::new(true),
+        });
+        inner.save(&inner.state.lock().recordings);
+        Ok(Self {
+            inner,
+            ticker: None,
+        })
+    }
+
+    /// Run `tick` every `poll_interval_ms` until `stop`
+    pub fn start(&mut self) -> Result<(), String> {
+        if self.ticker.is_some() {
+            return Ok(());
+        }
+        let inner = self.inner.clone();
+        let interval = Duration::from_millis(self.inner.config.poll_interval_ms.max(50));
+        let ticker = thread::Builder::new()
+            .name("iptv-scheduler".to_string())
+            .spawn(move || {
+                while inner.running.load(Ordering::SeqCst) {
+                    tick(&inner, now_unix());
+                    thread::sleep(interval);
+                }
+            })
+            .map_err(|e| format!("Failed to start scheduler: {}", e))?;
+        self.ticker = Some(ticker);
+        Ok(())
+    }
+
+    /// Add a recording; rejects empty windows, windows already over and
+    /// overlaps with another pending recording on the same channel
+    pub fn schedule(&self, mut recording: ScheduledRecording) -> Result<String, String> {
+        let (start, end) = recording.window();
+        if end <= start {
+            return Err("Recording ends before it starts".to_string());
+        }
+        if end <= now_unix() {
+            return Err(format!("\"{}\" has already aired", recording.title));
+        }
+        let mut state = self.inner.state.lock();
+        if let Some(other) = state.recordings.iter().find(|other| {
+            let (other_start, other_end) = other.window();
+            !other.status.is_finished()
+                && other.channel_id == recording.channel_id
+                && start < other_end
+                && other_start < end
+        }) {
+            return Err(format!(
+                "Overlaps \"{}\" on {}",
+                other.title, other.channel_name
+            ));
+        }
+        recording.status = RecordingStatus::Scheduled;
+        let id = recording.id.clone();
+        state.recordings.push(recording);
+        self.inner.save(&state.recordings);
+        Ok(id)
+    }
+
+    /// Cancel a pending or running recording (a partial TS is kept)
+    pub fn cancel(&self, id: &str) -> Result<(), String> {
+        let mut state = self.inner.state.lock();
+        let recording = state
+            .recordings
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| format!("No recording {}", id))?;
+        if recording.status.is_finished() {
+            return Err(format!("\"{}\" already finished", recording.title));
+        }
+        recording.status = RecordingStatus::Cancelled;
+        if let Some(active) = state.active.get(id) {
+            active.stop.store(true, Ordering::SeqCst);
+        }
+        self.inner.save(&state.recordings);
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<ScheduledRecording> {
+        self.inner.state.lock().recordings.clone()
+    }
+
+    /// Start due recordings and fail missed ones as of `now` (Unix seconds)
+    pub fn tick(&self, now: i64) {
+        tick(&self.inner, now);
+    }
+
+    /// Stop the scheduler. Running recordings stay marked as recording so
+    /// the next `open` resumes them.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.running.store(false, Ordering::SeqCst);
+        let active: Vec<ActiveRecording> = {
+            let mut state = self.inner.state.lock();
+            state.active.drain().map(|(_, active)| active).collect()
+        };
+        for recording in &active {
+            recording.stop.store(true, Ordering::SeqCst);
+        }
+        for recording in active {
+            let _ = recording.thread.join();
+        }
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
+
+impl Drop for RecordingScheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn tick(inner: &Arc<SchedulerInner>, now: i64) {
+    if !inner.running.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut state = inner.state.lock();
+    state
+        .active
+        .retain(|_, active| !active.thread.is_finished());
+
+    let mut changed = false;
+    let mut due = Vec::new();
+    for recording in &mut state.recordings {
+        if recording.status != RecordingStatus::Scheduled {
+            continue;
+        }
+        let (start, end) = recording.window();
+        if end <= now {
+            recording.status =
+                RecordingStatus::Failed("Missed: not running during the window".into());
+            changed = true;
+        } else if start <= now {
+            recording.status = RecordingStatus::Recording;
+            due.push(recording.clone());
+            changed = true;
+        }
+    }
+    for recording in due {
+        tracing::info!(
+            "Recording \"{}\" on {}",
+            recording.title,
+            recording.channel_name
+        );
+        let stop = Arc::new(AtomicBool::new(false));
+        let id = recording.id.clone();
+        let spawned = {
+            let inner = inner.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("iptv-recording".to_string())
+                .spawn(move || run_recording(inner, recording, stop))
+        };
+        match spawned {
+            Ok(thread) => {
+                state.active.insert(id, ActiveRecording { stop, thread });
+            }
+            Err(e) => {
+                if let Some(r) = state.recordings.iter_mut().find(|r| r.id == id) {
+                    r.status = RecordingStatus::Failed(format!("Failed to start: {}", e));
+                }
+            }
+        }
+    }
+    if changed {
+        inner.save(&state.recordings);
+    }
+}
+
+fn run_recording(inner: Arc<SchedulerInner>, recording: ScheduledRecording, stop: Arc<AtomicBool>) {
+    let stem = recording.file_stem();
+    let ts_path = inner.config.output_dir.join(format!("{}.ts", stem));
+    let (_, until) = recording.window();
+    let result = capture_live(
+        &recording.stream_url,
+        &inner.config.http,
+        &ts_path,
+        until,
+        &stop,
+    );
+
+    // Shutdown leaves the entry as Recording for the next start; a cancel
+    // already marked it
+    if !inner.running.load(Ordering::SeqCst) || stop.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let status = match result {
+        Ok(bytes) => match recording.format {
+            RecordingFormat::Ts => RecordingStatus::Completed {
+                path: ts_path,
+                bytes,
+            },
+            RecordingFormat::Mkv => {
+                let mkv_path = inner.config.output_dir.join(format!("{}.mkv", stem));
+                match remux_ts_to_mkv(&ts_path, &mkv_path) {
+                    Ok(()) => {
+                        let _ = fs::remove_file(&ts_path);
+                        let bytes = fs::metadata(&mkv_path).map_or(0, |m| m.len());
+                        RecordingStatus::Completed {
+                            path: mkv_path,
+                            bytes,
+                        }
+                    }
+                    Err(e) => {
+                        // The TS is still a good recording
+                        tracing::warn!("MKV remux of \"{}\" failed: {}", recording.title, e);
+                        let _ = fs::remove_file(&mkv_path);
+                        RecordingStatus::Completed {
+                            path: ts_path,
+                            bytes,
+                        }
+                    }
+                }
+            }
+        },
+        Err(e) => RecordingStatus::Failed(e),
+    };
+    tracing::info!("Recording \"{}\" finished: {:?}", recording.title, status);
+
+    let mut state = inner.state.lock();
+    if let Some(entry) = state.recordings.iter_mut().find(|r| r.id == recording.id) {
+        if entry.status == RecordingStatus::Recording {
+            entry.status = status;
+        }
+    }
+    inner.save(&state.recordings);
+}
+
+/// Append the live stream to `path` until `until` (Unix seconds) or `stop`,
+/// reconnecting whenever the source drops. Returns the file size.
+fn capture_live(
+    url: &str,
+    http: &HttpConfig,
+    path: &Path,
+    until: i64,
+    stop: &AtomicBool,
+) -> Result<u64, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut written = file.metadata().map_or(0, |m| m.len());
+    let mut last_error = None;
+
+    let open = || !stop.load(Ordering::SeqCst) && now_unix() < until;
+    while open() {
+        match LiveSource::open(url, http) {
+            Ok(mut source) => {
+                while open() {
+                    match source.next_chunk() {
+                        Ok(Some(chunk)) => {
+                            file.write_all(&chunk.packets)
+                                .map_err(|e| format!("Recording write error: {}", e))?;
+                            written += chunk.packets.len() as u64;
+                        }
+                        Ok(None) => {
+                            tracing::debug!("Recording source ended; reconnecting");
+                            break;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Recording source error: {}", e);
+                            last_error = Some(e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Recording connect failed: {}", e);
+                last_error = Some(e);
+            }
+        }
+        // Interruptible pause before reconnecting
+        let resume = Instant::now() + RECONNECT_DELAY;
+        while open() && Instant::now() < resume {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    file.flush()
+        .map_err(|e| format!("Recording write error: {}", e))?;
+
+    if written == 0 {
+        return Err(last_error.unwrap_or_else(|| "No data received".to_string()));
+    }
+    Ok(written)
+}
+
+// ============================================================================
+// TS -> MKV Remux
+// ============================================================================
+
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Framing {
+    /// Annex B H.264/HEVC to length-prefixed
+    AnnexB {
+        hevc: bool,
+    },
+    /// ADTS AAC split into raw frames
+    Adts {
+        sample_rate: u32,
+    },
+    Copy,
+}
+
+struct RemuxTrack {
+    pid: u16,
+    framing: Framing,
+    /// First usable timestamp (µs), for the common origin
+    first_us: i64,
+    /// PTS wrap compensation
+    wrap_offset: i64,
+    last_us: Option<i64>,
+    started: bool,
+}
+
+impl RemuxTrack {
+    fn unwrap(&mut self, raw: i64) -> i64 {
+        let mut value = raw + self.wrap_offset;
+        if let Some(last) = self.last_us {
+            if value < last - PTS_WRAP_US / 2 {
+                self.wrap_offset += PTS_WRAP_US;
+                value += PTS_WRAP_US;
+            }
+        }
+        self.last_us = Some(value);
+        value
+    }
+}
+
+/// Picture size from an MPEG-2 sequence header
+fn mpeg2_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let start = data.windows(4).position(|w| w == [0, 0, 1, 0xB3])? + 4;
+    let header = data.get(start..start + 3)?;
+    let width = ((header[0] as u32) << 4) | (header[1] as u32 >> 4);
+    let height = (((header[1] & 0x0F) as u32) << 8) | header[2] as u32;
+    Some((width, height))
+}
+
+/// The track a TS stream becomes, once its first usable packet is seen
+fn remux_setup(codec: StreamCodec, data: &[u8], keyframe: bool) -> Option<(MuxStream, Framing)> {
+    Some(match codec {
+        StreamCodec::H264 => {
+            let avcc = h264_utils::build_avcc_extradata(data)?;
+            let (width, height) = h264_utils::h264_sps_dimensions(data).unwrap_or((0, 0));
+            (
+                MuxStream::video(MuxCodec::H264, width, height).with_codec_private(avcc),
+                Framing::AnnexB { hevc: false },
+            )
+        }
+        StreamCodec::H265 => {
+            // HEVC SPS isn't parsed: the size is left to the decoder
+            let hvcc = h264_utils::build_hvcc_extradata(data)?;
+            (
+                MuxStream::video(MuxCodec::H265, 0, 0).with_codec_private(hvcc),
+                Framing::AnnexB { hevc: true },
+            )
+        }
+        StreamCodec::MPEG2Video => {
+            if !keyframe {
+                return None;
+            }
+            let (width, height) = mpeg2_dimensions(data)?;
+            (
+                MuxStream::video(MuxCodec::MPEG2, width, height),
+                Framing::Copy,
+            )
+        }
+        StreamCodec::AAC => {
+            let (asc, _) = crate::rtmp::split_adts(data)?;
+            let rate_index = (((asc[0] & 0x07) << 1) | (asc[1] >> 7)) as usize;
+            let sample_rate = *AAC_SAMPLE_RATES.get(rate_index)?;
+            let channels = ((asc[1] >> 3) & 0x0F).max(1) as u16;
+            (
+                MuxStream::audio(MuxCodec::AAC, sample_rate, channels)
+                    .with_codec_private(asc.to_vec()),
+                Framing::Adts { sample_rate },
+            )
+        }
+        StreamCodec::AC3 => (MuxStream::audio(MuxCodec::AC3, 48000, 2), Framing::Copy),
+        StreamCodec::EAC3 => (MuxStream::audio(MuxCodec::EAC3, 48000, 2), Framing::Copy),
+        StreamCodec::MP3 => (MuxStream::audio(MuxCodec::MP3, 48000, 2), Framing::Copy),
+        _ => return None,
+    })
+}
+
+/// Length of each ADTS frame in a PES payload
+fn adts_frames(data: &[u8]) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 7 && rest[0] == 0xFF && rest[1] & 0xF0 == 0xF0 {
+        let length = (((rest[3] & 0x03) as usize) << 11)
+            | ((rest[4] as usize) << 3)
+            | (rest[5] as usize >> 5);
+        if length < 7 || length > rest.len() {
+            break;
+        }
+        frames.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+    frames
+}
+
+fn open_ts(path: &Path) -> Result<TsDemuxer<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    TsDemuxer::new(BufReader::new(file))
+}
+
+/// Remux a recorded TS into Matroska. H.264/HEVC get avcC/hvcC records from
+/// the first keyframe's parameter sets, ADTS AAC is split into raw frames,
+/// AC-3/E-AC-3/MP3 and MPEG-2 video are copied; other streams are dropped.
+pub fn remux_ts_to_mkv(input: &Path, output: &Path) -> Result<(), String> {
+    // Pass 1: set up each stream from its first usable packet
+    let mut demuxer = open_ts(input)?;
+    let mut pending: HashMap<u16, StreamCodec> = demuxer
+        .info()
+        .streams
+        .iter()
+        .map(|stream| (stream.pid, stream.codec))
+        .collect();
+    let mut tracks: Vec<RemuxTrack> = Vec::new();
+    let mut streams: Vec<MuxStream> = Vec::new();
+    while !pending.is_empty() {
+        let Some(packet) = demuxer.read_packet() else {
+            break;
+        };
+        let Some(&codec) = pending.get(&packet.pid) else {
+            continue;
+        };
+        let Some(time) = packet.dts.or(packet.pts) else {
+            continue;
+        };
+        if let Some((stream, framing)) = remux_setup(codec, &packet.data, packet.keyframe) {
+            pending.remove(&packet.pid);
+            tracks.push(RemuxTrack {
+                pid: packet.pid,
+                framing,
+                first_us: time,
+                wrap_offset: 0,
+                last_us: None,
+                started: false,
+            });
+            streams.push(stream);
+        }
+    }
+    for (pid, codec) in pending {
+        tracing::debug!("Remux: leaving out PID {} ({:?})", pid, codec);
+    }
+    if tracks.is_empty() {
+        return Err("No remuxable streams in the recording".to_string());
+    }
+    let origin = tracks.iter().map(|t| t.first_us).min().unwrap_or(0);
+
+    // Pass 2: copy from each stream's first usable packet on
+    let mut demuxer = open_ts(input)?;
+    let mut muxer = UniversalMuxer::create(output, streams)?;
+    while let Some(packet) = demuxer.read_packet() {
+        let Some(index) = tracks.iter().position(|t| t.pid == packet.pid) else {
+            continue;
+        };
+        let track = &mut tracks[index];
+        let (Some(pts), dts) = (packet.pts.or(packet.dts), packet.dts) else {
+            continue;
+        };
+        let pts = track.unwrap(pts) - origin;
+        let dts = dts.map(|dts| dts + track.wrap_offset - origin);
+        if !track.started {
+            if dts.unwrap_or(pts) + origin < track.first_us {
+                continue;
+            }
+            track.started = true;
+        }
+        if pts < 0 {
+            continue;
+        }
+
+        match track.framing {
+            Framing::AnnexB { hevc } => {
+                muxer.write_packet(&MuxPacket {
+                    stream: index,
+                    pts_us: pts,
+                    dts_us: dts,
+                    duration_us: None,
+                    keyframe: h264_utils::annexb_has_idr(&packet.data, hevc),
+                    data: h264_utils::annexb_to_avcc(&packet.data),
+                })?;
+            }
+            Framing::Adts { sample_rate } => {
+                let frame_us = 1024 * 1_000_000 / sample_rate as i64;
+                for (i, frame) in adts_frames(&packet.data).into_iter().enumerate() {
+                    let Some((_, raw)) = crate::rtmp::split_adts(frame) else {
+                        continue;
+                    };
+                    let time = pts + i as i64 * frame_us;
+                    muxer.write_packet(&MuxPacket {
+                        stream: index,
+                        pts_us: time,
+                        dts_us: Some(time),
+                        duration_us: Some(frame_us),
+                        keyframe: true,
+                        data: raw.to_vec(),
+                    })?;
+                }
+            }
+            Framing::Copy => {
+                muxer.write_packet(&MuxPacket {
+                    stream: index,
+                    pts_us: pts,
+                    dts_us: dts,
+                    duration_us: None,
+                    keyframe: packet.keyframe,
+                    data: packet.data,
+                })?;
+            }
+        }
+    }
+    muxer.finish()
+}
+
+// ============================================================================
+// Public Rust API
+// ============================================================================
+
+use once_cell::sync::Lazy;
+
+static TIMESHIFT: Lazy<Mutex<Option<TimeshiftBuffer>>> = Lazy::new(|| Mutex::new(None));
+static RECORDING_SCHEDULER: Lazy<Mutex<Option<RecordingScheduler>>> =
+    Lazy::new(|| Mutex::new(None));
+
+/// Start (or restart) timeshifting `stream_url`
+pub fn start_iptv_timeshift(stream_url: String) -> Result<(), String> {
+    start_iptv_timeshift_failover(StreamFailover::new(&stream_url, Vec::new()))
+}
+
+/// Start (or restart) timeshifting, failing over between candidate URLs
+pub fn start_iptv_timeshift_failover(failover: StreamFailover) -> Result<(), String> {
+    if let Some(buffer) = TIMESHIFT.lock().take() {
+        buffer.stop();
+    }
+    let buffer = TimeshiftBuffer::start_with_failover(failover, TimeshiftConfig::default())?;
+    *TIMESHIFT.lock() = Some(buffer);
+    Ok(())
+}
+
+pub fn iptv_timeshift_status() -> Option<TimeshiftStatus> {
+    TIMESHIFT.lock().as_ref().map(|buffer| buffer.status())
+}
+
+/// Reader `behind_live_secs` back from live on the running timeshift
+pub fn iptv_timeshift_reader(behind_live_secs: f64) -> Result<TimeshiftReader, String> {
+    TIMESHIFT
+        .lock()
+        .as_ref()
+        .map(|buffer| buffer.reader(behind_live_secs))
+        .ok_or_else(|| "Timeshift not running".to_string())
+}
+
+pub fn stop_iptv_timeshift() -> Result<(), String> {
+    let buffer = TIMESHIFT.lock().take().ok_or("Timeshift not running")?;
+    buffer.stop();
+    Ok(())
+}
+
+/// Load the saved schedule and start recording in the background
+pub fn start_iptv_recording_scheduler() -> Result<(), String> {
+    let mut guard = RECORDING_SCHEDULER.lock();
+    if guard.is_some() {
+        return Ok(());
+    }
+    let mut scheduler = RecordingScheduler::open(RecordingSchedulerConfig::default())?;
+    scheduler.start()?;
+    *guard = Some(scheduler);
+    Ok(())
+}
+
+pub fn schedule_iptv_recording(
+    channel: IptvChannel,
+    program: EpgProgram,
+    padding_before_secs: i64,
+    padding_after_secs: i64,
+    format: RecordingFormat,
+) -> Result<String, String> {
+    let recording = ScheduledRecording::from_program(
+        &channel,
+        &program,
+        padding_before_secs,
+        padding_after_secs,
+        format,
+    );
+    RECORDING_SCHEDULER
+        .lock()
+        .as_ref()
+        .ok_or("Recording scheduler not running")?
+        .schedule(recording)
+}
+
+pub fn cancel_iptv_recording(id: String) -> Result<(), String> {
+    RECORDING_SCHEDULER
+        .lock()
+        .as_ref()
+        .ok_or("Recording scheduler not running")?
+        .cancel(&id)
+}
+
+pub fn list_iptv_recordings() -> Vec<ScheduledRecording> {
+    RECORDING_SCHEDULER
+        .lock()
+        .as_ref()
+        .map(|scheduler| scheduler.list())
+        .unwrap_or_default()
+}
+
+pub fn stop_iptv_recording_scheduler() -> Result<(), String> {
+    let scheduler = RECORDING_SCHEDULER
+        .lock()
+        .take()
+        .ok_or("Recording scheduler not running")?;
+    scheduler.stop();
+    Ok(())
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{http_server, ts_stream, write_head};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_timeshift_ring() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TimeshiftConfig {
+            dir: dir.path().to_path_buf(),
+            segment_secs: 1.0,
+            max_secs: 3.0,
+            ..Default::default()
+        };
+        prepare_ring_dir(&config.dir).unwrap();
+        let shared = Arc::new(RingShared {
+            ring: Mutex::new(Ring::default()),
+            changed: Condvar::new(),
+            running: AtomicBool::new(true),
+        });
+        let buffer = TimeshiftBuffer {
+            shared: shared.clone(),
+            thread: None,
+        };
+        let mut writer = RingWriter::new(config, shared);
+
+        // 10 s fed as 0.5 s chunks: keyframes every second
+        let stream = ts_stream(250, true);
+        let chunk = stream.len() / 20 / TS_PACKET_SIZE * TS_PACKET_SIZE;
+        let mut reader = None;
+        for (i, packets) in stream.chunks(chunk).enumerate() {
+            let start = i as f64 * 0.5;
+            writer.write(packets, start, start + 0.5).unwrap();
+            if i == 3 {
+                // Paused 2 s in: this reader falls out of the ring
+                reader = Some(buffer.reader(1.0));
+            }
+        }
+        let status = buffer.status();
+        assert!(status.buffered_secs <= 5.0, "{:?}", status);
+        assert!(status.segments >= 3, "{:?}", status);
+        let on_disk = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(on_disk, status.segments);
+
+        // Every segment demuxes on its own thanks to the repeated PAT/PMT
+        let first = fs::read(dir.path().join(format!("seg_{}.ts", shared_front(&buffer)))).unwrap();
+        let demuxer = TsDemuxer::new(io::Cursor::new(first)).unwrap();
+        assert_eq!(demuxer.info().streams.len(), 2);
+
+        // The paused reader skips to the oldest segment and reads to the end
+        let mut reader = reader.unwrap();
+        assert!(reader.behind_live_secs() >= status.buffered_secs - 0.01);
+        writer.finish(None);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data.len() as u64, buffer.status().bytes);
+        assert_eq!(data[0], TS_SYNC_BYTE);
+
+        let mut live = buffer.reader(0.0);
+        assert!(live.behind_live_secs() < 2.0);
+        let mut tail = Vec::new();
+        live.read_to_end(&mut tail).unwrap();
+        assert!(!tail.is_empty() && tail.len() < data.len());
+    }
+
+    fn shared_front(buffer: &TimeshiftBuffer) -> u64 {
+        buffer.shared.ring.lock().segments.front().unwrap().sequence
+    }
+
+    #[test]
+    fn test_scheduler_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RecordingSchedulerConfig {
+            state_path: dir.path().join("schedule.json"),
+            output_dir: dir.path().join("out"),
+            http: HttpConfig::default(),
+            poll_interval_ms: 100,
+        };
+        let channel = IptvChannel {
+            id: "news".to_string(),
+            name: "News 24".to_string(),
+            stream_url: "http://127.0.0.1:1/news.ts".to_string(),
+            logo_url: None,
+            group: None,
+            epg_id: None,
+            country: None,
+            language: None,
+            is_favorite: false,
+            last_watched: None,
+            catchup: None,
+        };
+        let now = now_unix();
+        let program = |title: &str, start: i64, end: i64| EpgProgram {
+            channel_id: "news".to_string(),
+            title: title.to_string(),
+            sub_title: None,
+            description: None,
+            start_time: start,
+            end_time: end,
+            category: None,
+            episode: None,
+            rating: None,
+            icon: None,
+        };
+
+        let scheduler = RecordingScheduler::open(config.clone()).unwrap();
+        let evening = ScheduledRecording::from_program(
+            &channel,
+            &program("Evening News", now + 3600, now + 5400),
+            120,
+            300,
+            RecordingFormat::Mkv,
+        );
+        assert_eq!(evening.window(), (now + 3480, now + 5700));
+        let id = scheduler.schedule(evening).unwrap();
+        let overlap = ScheduledRecording::from_program(
+            &channel,
+            &program("Weather", now + 5500, now + 6000),
+            0,
+            0,
+            RecordingFormat::Ts,
+        );
+        assert!(scheduler
+            .schedule(overlap)
+            .unwrap_err()
+            .contains("Evening News"));
+        let aired = program("Morning", now - 7200, now - 3600);
+        assert!(scheduler
+            .schedule(ScheduledRecording::from_program(
+                &channel,
+                &aired,
+                0,
+                0,
+                RecordingFormat::Ts
+            ))
+            .is_err());
+        let late = ScheduledRecording::from_program(
+            &channel,
+            &program("Late Show", now + 7200, now + 9000),
+            0,
+            0,
+            RecordingFormat::Ts,
+        );
+        let late_id = scheduler.schedule(late).unwrap();
+        scheduler.cancel(&late_id).unwrap();
+        drop(scheduler);
+
+        // An entry left as Recording by a crash after its window closed
+        let mut saved = load_schedule(&config.state_path).unwrap();
+        assert_eq!(saved.len(), 2);
+        let mut crashed = saved[0].clone();
+        crashed.id = "crashed".to_string();
+        crashed.start_time = now - 600;
+        crashed.end_time = now - 300;
+        crashed.padding_after_secs = 0;
+        crashed.status = RecordingStatus::Recording;
+        saved.push(crashed);
+        save_schedule(&config.state_path, &saved).unwrap();
+
+        let scheduler = RecordingScheduler::open(config).unwrap();
+        let list = scheduler.list();
+        let status = |id: &str| list.iter().find(|r| r.id == id).unwrap().status.clone();
+        assert_eq!(status(&id), RecordingStatus::Scheduled);
+        assert_eq!(status(&late_id), RecordingStatus::Cancelled);
+        assert!(matches!(status("crashed"), RecordingStatus::Failed(_)));
+
+        // Skipping past the window without running fails it as missed
+        scheduler.tick(now + 6000);
+        assert!(matches!(
+            scheduler.list().iter().find(|r| r.id == id).unwrap().status,
+            RecordingStatus::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_record_and_remux() {
+        let stream = ts_stream(100, true);
+        let connections = AtomicUsize::new(0);
+        let base = http_server(move |_, connection| {
+            if connections.fetch_add(1, Ordering::SeqCst) > 0 {
+                let _ = write_head(connection, "503 Busy", &[], Some(0));
+                return;
+            }
+            let content_type = [("Content-Type", "video/mp2t".to_string())];
+            if write_head(connection, "200 OK", &content_type, None).is_ok() {
+                // Four seconds of stream, then the "live" feed stalls
+                let _ = connection.write_all(&stream);
+                thread::sleep(Duration::from_secs(5));
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = RecordingSchedulerConfig {
+            state_path: dir.path().join("schedule.json"),
+            output_dir: dir.path().join("out"),
+            http: HttpConfig {
+                timeout_secs: 1,
+                ..Default::default()
+            },
+            poll_interval_ms: 100,
+        };
+        let mut scheduler = RecordingScheduler::open(config).unwrap();
+        let now = now_unix();
+        let recording = ScheduledRecording {
+            id: "rec".to_string(),
+            channel_id: "sports".to_string(),
+            channel_name: "Sports: HD".to_string(),
+            stream_url: format!("{}/live.ts", base),
+            title: "Match".to_string(),
+            start_time: now,
+            end_time: now + 2,
+            padding_before_secs: 0,
+            padding_after_secs: 0,
+            format: RecordingFormat::Mkv,
+            status: RecordingStatus::Scheduled,
+        };
+        scheduler.schedule(recording).unwrap();
+        scheduler.start().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(20);
+        let path = loop {
+            let status = scheduler.list()[0].status.clone();
+            match status {
+                RecordingStatus::Completed { path, .. } => break path,
+                RecordingStatus::Failed(e) => panic!("recording failed: {}", e),
+                _ => {}
+            }
+            assert!(Instant::now() < deadline, "recording didn't finish");
+            thread::sleep(Duration::from_millis(100));
+        };
+        scheduler.stop();
+
+        assert_eq!(path.extension().unwrap(), "mkv");
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("Sports_ HD - Match"));
+        assert!(!path.with_extension("ts").exists());
+
+        let info = crate::mkv::MkvParser::new().parse(&path).unwrap();
+        assert_eq!(info.tracks.len(), 2);
+        let mut demuxer =
+            crate::mkv::MkvDemuxer::new(BufReader::new(File::open(&path).unwrap()), info).unwrap();
+        let mut video = 0;
+        let mut audio = 0;
+        while let Some(packet) = demuxer.read_packet() {
+            if packet.track_number == 1 {
+                // Length-prefixed, not Annex B
+                let first = u32::from_be_bytes(packet.data[..4].try_into().unwrap()) as usize;
+                assert!(first + 4 <= packet.data.len());
+                assert!(matches!(packet.data[4] & 0x1F, 1 | 7));
+                video += 1;
+            } else {
+                assert_eq!(packet.data.len(), 60);
+                audio += 1;
+            }
+        }
+        assert_eq!(video, 100);
+        assert_eq!(audio, 100);
+    }
+}
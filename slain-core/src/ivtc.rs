@@ -0,0 +1,561 @@
+//! # Inverse Telecine
+//!
+//! Field matching plus decimation for 3:2 and 2:2 pulldown.
+//!
+//! Telecined NTSC film repeats fields in a 3:2 pattern: of every five
+//! interlaced frames, two are woven from fields of different film frames and
+//! one repeats a film frame. Matching each frame's first field with the best
+//! partner field (from the previous, current or next frame) rebuilds the
+//! progressive film frames, and dropping the duplicate in each cycle of five
+//! restores 23.976p with evenly spaced timestamps.
+//!
+//! 2:2 pulldown (PAL film, 29.97p-in-i broadcasts) only needs field matching;
+//! frames that no match can make progressive fall back to Yadif.
+
+use crate::deinterlace::{interpolate_field, DeinterlaceMode, FieldOrder, YuvPicture};
+use serde::{Deserialize, Serialize};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Which frame supplied the partner field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldMatch {
+    Previous,
+    Current,
+    Next,
+}
+
+/// Detected source cadence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cadence {
+    /// Not enough evidence yet
+    Unknown,
+    /// Already progressive, frames pass through
+    Progressive,
+    /// Field-shifted progressive content, matched but not decimated
+    Pulldown22,
+    /// Locked 3:2 pulldown, one frame in five dropped
+    Pulldown32,
+    /// True interlaced video, frames deinterlaced
+    Interlaced,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IvtcConfig {
+    /// Field order of the telecined stream (`Auto` = top field first)
+    pub field_order: FieldOrder,
+    /// Decimation cycle length (5 for 3:2 pulldown)
+    pub cycle: usize,
+    /// Fraction of combed luma samples above which a match counts as combed
+    pub comb_threshold: f32,
+    /// A frame is a duplicate when its difference to the previous frame is
+    /// below this fraction of the cycle's average difference
+    pub duplicate_ratio: f32,
+    /// Consecutive cycles with the same drop position needed to lock
+    pub lock_cycles: u32,
+    /// Deinterlace frames that no field match makes progressive
+    pub deinterlace_combed: bool,
+}
+
+impl Default for IvtcConfig {
+    fn default() -> Self {
+        Self {
+            field_order: FieldOrder::Auto,
+            cycle: 5,
+            comb_threshold: 0.01,
+            duplicate_ratio: 0.35,
+            lock_cycles: 2,
+            deinterlace_combed: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IvtcStats {
+    pub frames_in: u64,
+    pub frames_out: u64,
+    pub dropped: u64,
+    pub combed: u64,
+    pub cadence_breaks: u64,
+    pub matched_previous: u64,
+    pub matched_current: u64,
+    pub matched_next: u64,
+}
+
+/// Frame after field matching, waiting for decimation
+struct MatchedFrame {
+    picture: YuvPicture,
+    /// Mean absolute luma difference to the previous matched frame (8-bit scale)
+    diff: f32,
+    field_match: FieldMatch,
+    combed: bool,
+}
+
+/// Luma difference below which two frames are considered identical
+const NOISE_FLOOR: f32 = 0.5;
+
+// ============================================================================
+// IVTC
+// ============================================================================
+
+pub struct Ivtc {
+    config: IvtcConfig,
+    prev: Option<YuvPicture>,
+    cur: Option<YuvPicture>,
+    last_luma: Option<Vec<u16>>,
+    last_duration: i64,
+    cycle: Vec<MatchedFrame>,
+    drop_phase: Option<usize>,
+    lock_count: u32,
+    cadence: Cadence,
+    stats: IvtcStats,
+}
+
+impl Ivtc {
+    pub fn new(config: IvtcConfig) -> Self {
+        Self {
+            config: IvtcConfig {
+                cycle: config.cycle.max(2),
+                ..config
+            },
+            prev: None,
+            cur: None,
+            last_luma: None,
+            last_duration: 0,
+            cycle: Vec::new(),
+            drop_phase: None,
+            lock_count: 0,
+            cadence: Cadence::Unknown,
+            stats: IvtcStats::default(),
+        }
+    }
+
+    /// Feed the next decoded (telecined) picture. Output is produced a cycle
+    /// at a time, so this returns either nothing or a whole cycle's frames.
+    pub fn push(&mut self, picture: YuvPicture) -> Vec<YuvPicture> {
+        self.stats.frames_in += 1;
+        let mut output = Vec::new();
+        if let Some(cur) = self.cur.take() {
+            self.match_frame(&cur, Some(&picture));
+            self.prev = Some(cur);
+            if self.cycle.len() >= self.config.cycle {
+                output = self.decimate();
+            }
+        }
+        self.cur = Some(picture);
+        output
+    }
+
+    /// Drain held frames at end of stream or before a seek
+    pub fn flush(&mut self) -> Vec<YuvPicture> {
+        if let Some(cur) = self.cur.take() {
+            self.match_frame(&cur, None);
+            self.prev = Some(cur);
+        }
+        if self.cycle.is_empty() {
+            Vec::new()
+        } else {
+            self.decimate()
+        }
+    }
+
+    /// Forget all history (seek); the cadence is re-acquired from scratch
+    pub fn reset(&mut self) {
+        self.prev = None;
+        self.cur = None;
+        self.last_luma = None;
+        self.last_duration = 0;
+        self.cycle.clear();
+        self.drop_phase = None;
+        self.lock_count = 0;
+        self.cadence = Cadence::Unknown;
+    }
+
+    pub fn cadence(&self) -> Cadence {
+        self.cadence
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock_count >= self.config.lock_cycles
+    }
+
+    pub fn stats(&self) -> &IvtcStats {
+        &self.stats
+    }
+
+    pub fn config(&self) -> &IvtcConfig {
+        &self.config
+    }
+
+    // ------------------------------------------------------------------------
+    // Field matching
+    // ------------------------------------------------------------------------
+
+    fn match_frame(&mut self, cur: &YuvPicture, next: Option<&YuvPicture>) {
+        let duration = if cur.duration > 0 {
+            cur.duration
+        } else {
+            next.map(|n| n.pts - cur.pts)
+                .filter(|d| *d > 0)
+                .unwrap_or(self.last_duration)
+        };
+        self.last_duration = duration;
+
+        let keep = match self.config.field_order {
+            FieldOrder::Auto => cur.field_order.first_parity(),
+            order => order.first_parity(),
+        };
+        let prev = self.prev.as_ref().filter(|p| same_size(p, cur));
+        let next = next.filter(|n| same_size(n, cur));
+
+        let shift = cur.bit_depth.saturating_sub(8);
+        let score = |partner: &YuvPicture| comb_metric(cur, partner, keep, shift);
+        let mc = score(cur);
+        let mp = prev.map(score).unwrap_or(f32::MAX);
+        let mn = next.map(score).unwrap_or(f32::MAX);
+
+        // Prefer the current frame's own field unless another is clearly cleaner
+        let (field_match, partner, metric) = if mp < mc * 0.7 && mp <= mn {
+            (FieldMatch::Previous, prev.unwrap_or(cur), mp)
+        } else if mn < mc * 0.7 {
+            (FieldMatch::Next, next.unwrap_or(cur), mn)
+        } else {
+            (FieldMatch::Current, cur, mc)
+        };
+        match field_match {
+            FieldMatch::Previous => self.stats.matched_previous += 1,
+            FieldMatch::Current => self.stats.matched_current += 1,
+            FieldMatch::Next => self.stats.matched_next += 1,
+        }
+
+        let combed = metric > self.config.comb_threshold;
+        let mut picture = if combed && self.config.deinterlace_combed {
+            self.stats.combed += 1;
+            interpolate_field(
+                prev.unwrap_or(cur),
+                cur,
+                next.unwrap_or(cur),
+                DeinterlaceMode::Yadif,
+                keep,
+            )
+        } else {
+            weave(cur, partner, keep)
+        };
+        picture.duration = duration;
+        picture.interlaced = Some(false);
+
+        let luma: Vec<u16> = picture.planes[0].iter().map(|&v| v >> shift).collect();
+        let diff = match &self.last_luma {
+            Some(last) if last.len() == luma.len() => mean_abs_diff(last, &luma),
+            _ => f32::MAX,
+        };
+        self.last_luma = Some(luma);
+
+        self.cycle.push(MatchedFrame {
+            picture,
+            diff,
+            field_match,
+            combed,
+        });
+    }
+
+    // ------------------------------------------------------------------------
+    // Decimation
+    // ------------------------------------------------------------------------
+
+    fn decimate(&mut self) -> Vec<YuvPicture> {
+        let frames = std::mem::take(&mut self.cycle);
+        let full_cycle = frames.len() == self.config.cycle;
+        let locked = self.is_locked();
+
+        let (min_idx, min_diff) = frames
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (i, f.diff))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, f32::MAX));
+        let others: Vec<f32> = frames
+            .iter()
+            .enumerate()
+            .filter(|(i, f)| *i != min_idx && f.diff.is_finite() && f.diff < f32::MAX)
+            .map(|(_, f)| f.diff)
+            .collect();
+        let others_mean = if others.is_empty() {
+            0.0
+        } else {
+            others.iter().sum::<f32>() / others.len() as f32
+        };
+
+        let clear_duplicate = full_cycle
+            && others_mean >= NOISE_FLOOR * 2.0
+            && min_diff < others_mean * self.config.duplicate_ratio;
+        let static_scene = others_mean < NOISE_FLOOR * 2.0;
+
+        let drop = if clear_duplicate {
+            if self.drop_phase == Some(min_idx) {
+                self.lock_count += 1;
+            } else {
+                if locked {
+                    // Edit moved the cadence: re-acquire at the new phase
+                    self.stats.cadence_breaks += 1;
+                }
+                self.drop_phase = Some(min_idx);
+                self.lock_count = 1;
+            }
+            Some(min_idx)
+        } else if locked && static_scene {
+            // No motion to see the duplicate by; trust the locked phase
+            self.drop_phase.filter(|phase| *phase < frames.len())
+        } else {
+            if locked && full_cycle {
+                self.stats.cadence_breaks += 1;
+            }
+            if full_cycle && !static_scene {
+                self.drop_phase = None;
+                self.lock_count = 0;
+            }
+            None
+        };
+
+        let combed = frames.iter().filter(|f| f.combed).count();
+        let shifted = frames
+            .iter()
+            .filter(|f| f.field_match != FieldMatch::Current)
+            .count();
+        self.cadence = if self.is_locked() {
+            Cadence::Pulldown32
+        } else if clear_duplicate {
+            Cadence::Unknown
+        } else if combed * 2 > frames.len() {
+            Cadence::Interlaced
+        } else if shifted * 2 > frames.len() {
+            Cadence::Pulldown22
+        } else if static_scene {
+            self.cadence
+        } else {
+            Cadence::Progressive
+        };
+
+        self.retime(frames, drop)
+    }
+
+    /// Drop `drop` (if any) and spread the rest evenly over the cycle's span
+    fn retime(&mut self, frames: Vec<MatchedFrame>, drop: Option<usize>) -> Vec<YuvPicture> {
+        let start = frames.first().map(|f| f.picture.pts).unwrap_or(0);
+        let span: i64 = frames.iter().map(|f| f.picture.duration).sum();
+        let count = frames.len();
+
+        let Some(drop) = drop.filter(|_| count > 1) else {
+            self.stats.frames_out += count as u64;
+            return frames.into_iter().map(|f| f.picture).collect();
+        };
+
+        self.stats.dropped += 1;
+        let kept = (count - 1) as i64;
+        let output: Vec<YuvPicture> = frames
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| *i != drop)
+            .map(|(_, f)| f.picture)
+            .enumerate()
+            .map(|(k, mut picture)| {
+                let k = k as i64;
+                picture.pts = start + span * k / kept;
+                picture.duration = start + span * (k + 1) / kept - picture.pts;
+                picture
+            })
+            .collect();
+        self.stats.frames_out += output.len() as u64;
+        output
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn same_size(a: &YuvPicture, b: &YuvPicture) -> bool {
+    a.width == b.width
+        && a.height == b.height
+        && a.bit_depth == b.bit_depth
+        && a.chroma_shift == b.chroma_shift
+}
+
+/// Combine the `keep` field lines of `cur` with the other field of `partner`
+fn weave(cur: &YuvPicture, partner: &YuvPicture, keep: usize) -> YuvPicture {
+    let mut out = cur.clone();
+    for p in 0..3 {
+        let (w, h) = cur.plane_size(p);
+        let w = w as usize;
+        for y in (0..h as usize).filter(|y| y % 2 != keep) {
+            out.planes[p][y * w..(y + 1) * w]
+                .copy_from_slice(&partner.planes[p][y * w..(y + 1) * w]);
+        }
+    }
+    out
+}
+
+/// Fraction of luma samples that comb when `cur`'s kept field is woven with
+/// `partner`'s other field: a sample combs when it differs from both vertical
+/// neighbours in the same direction by more than the threshold.
+fn comb_metric(cur: &YuvPicture, partner: &YuvPicture, keep: usize, shift: u32) -> f32 {
+    const THRESHOLD: i32 = 10;
+    let w = cur.width as usize;
+    let h = cur.height as usize;
+    if h < 3 || w == 0 {
+        return 0.0;
+    }
+
+    let sample = |x: usize, y: usize| -> i32 {
+        let plane = if y % 2 == keep {
+            &cur.planes[0]
+        } else {
+            &partner.planes[0]
+        };
+        (plane[y * w + x] >> shift) as i32
+    };
+
+    let mut combed = 0u64;
+    let mut total = 0u64;
+    for y in 1..h - 1 {
+        for x in (0..w).step_by(2) {
+            let (a, b, c) = (sample(x, y - 1), sample(x, y), sample(x, y + 1));
+            if (a - b) * (c - b) > THRESHOLD * THRESHOLD {
+                combed += 1;
+            }
+            total += 1;
+        }
+    }
+    combed as f32 / total.max(1) as f32
+}
+
+fn mean_abs_diff(a: &[u16], b: &[u16]) -> f32 {
+    let sum: u64 = a
+        .iter()
+        .zip(b)
+        .step_by(2)
+        .map(|(&x, &y)| (x as i32 - y as i32).unsigned_abs() as u64)
+        .sum();
+    sum as f32 / a.len().div_ceil(2).max(1) as f32
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 32;
+    const H: u32 = 32;
+
+    /// Progressive film frame `n`: a bright bar that moves 3 px per frame
+    fn film_frame(n: usize) -> YuvPicture {
+        let mut picture = YuvPicture::new(W, H, 8, (1, 1));
+        for y in 0..H as usize {
+            for x in 0..W as usize {
+                let bar = (x + W as usize - (n * 3) % W as usize) % W as usize;
+                picture.planes[0][y * W as usize + x] = if bar < 8 { 220 } else { 30 };
+            }
+        }
+        picture.planes[1].fill(128);
+        picture.planes[2].fill(128);
+        picture
+    }
+
+    /// Telecine film frames 3:2 (top field first): AA BB BC CD DD
+    fn telecine(frames: usize) -> Vec<YuvPicture> {
+        let film: Vec<YuvPicture> = (0..frames).map(film_frame).collect();
+        // (top source, bottom source) per video frame in each cycle of 4 film frames
+        let pattern = [(0, 0), (1, 1), (1, 2), (2, 3), (3, 3)];
+        let mut video = Vec::new();
+        for cycle in 0..frames / 4 {
+            for (top, bottom) in pattern {
+                let mut frame = weave(&film[cycle * 4 + top], &film[cycle * 4 + bottom], 0);
+                frame.pts = video.len() as i64 * 33_367;
+                frame.field_order = FieldOrder::TopFirst;
+                video.push(frame);
+            }
+        }
+        video
+    }
+
+    fn run(ivtc: &mut Ivtc, input: Vec<YuvPicture>) -> Vec<YuvPicture> {
+        let mut output = Vec::new();
+        for picture in input {
+            output.extend(ivtc.push(picture));
+        }
+        output.extend(ivtc.flush());
+        output
+    }
+
+    #[test]
+    fn recovers_film_frames_from_32_pulldown() {
+        let mut ivtc = Ivtc::new(IvtcConfig::default());
+        let output = run(&mut ivtc, telecine(16));
+
+        assert_eq!(ivtc.cadence(), Cadence::Pulldown32);
+        assert_eq!(ivtc.stats().dropped, 4);
+        assert_eq!(output.len(), 16);
+        for (n, picture) in output.iter().enumerate() {
+            assert_eq!(
+                picture.planes[0],
+                film_frame(n).planes[0],
+                "film frame {}",
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn decimated_timestamps_are_evenly_spaced() {
+        let mut ivtc = Ivtc::new(IvtcConfig::default());
+        let output = run(&mut ivtc, telecine(12));
+
+        // Five 29.97 fps frames become four 23.976 fps frames
+        let cycle_span = 5 * 33_367;
+        for (k, picture) in output.iter().take(8).enumerate() {
+            let expected = (k / 4) as i64 * cycle_span + cycle_span * (k % 4) as i64 / 4;
+            assert_eq!(picture.pts, expected);
+            assert!((picture.duration - cycle_span / 4).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn progressive_input_passes_through() {
+        let mut ivtc = Ivtc::new(IvtcConfig::default());
+        let input: Vec<YuvPicture> = (0..10)
+            .map(|n| {
+                let mut frame = film_frame(n);
+                frame.pts = n as i64 * 40_000;
+                frame
+            })
+            .collect();
+        let output = run(&mut ivtc, input);
+
+        assert_eq!(output.len(), 10);
+        assert_eq!(ivtc.stats().dropped, 0);
+        assert_eq!(ivtc.cadence(), Cadence::Progressive);
+        assert_eq!(output[3].pts, 120_000);
+    }
+
+    #[test]
+    fn cadence_break_is_reacquired() {
+        let mut ivtc = Ivtc::new(IvtcConfig::default());
+        let mut input = telecine(12);
+        // An edit cuts two frames out of the middle cycle
+        input.drain(6..8);
+        let mut tail = telecine(8);
+        for (i, frame) in tail.iter_mut().enumerate() {
+            frame.pts = (input.len() + i) as i64 * 33_367;
+        }
+        input.extend(tail);
+
+        run(&mut ivtc, input);
+        assert!(ivtc.is_locked());
+        assert_eq!(ivtc.cadence(), Cadence::Pulldown32);
+    }
+}
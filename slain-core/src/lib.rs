@@ -23,6 +23,7 @@ pub mod vbios;
 pub mod amf_decode;
 pub mod amf_encoder;
 pub mod decode;
+pub mod encode;
 pub mod h264_utils;
 pub mod hw_decode;
 pub mod nvdec;
@@ -38,6 +39,14 @@ pub mod mkv;
 pub mod mp4_demux;
 pub mod ts_demux;
 
+// ============================================================================
+// Container Muxers
+// ============================================================================
+pub mod mkv_mux;
+pub mod mp4_mux;
+pub mod mux;
+pub mod ts_mux;
+
 // ============================================================================
 // DirectShow Integration (Windows)
 // ============================================================================
@@ -48,7 +57,12 @@ pub mod dshow;
 // Video Processing Pipeline
 // ============================================================================
 pub mod deinterlace;
+pub mod ivtc;
 pub mod frame_interpolation;
+pub mod scene_detect;
+pub mod trickplay;
+pub mod capture;
+pub mod gpu_video_processor;
 pub mod video_pipeline;
 pub mod vapoursynth_bridge;
 pub mod potplayer_compat;
@@ -61,7 +75,9 @@ pub mod audio;
 pub mod camera;
 pub mod filter_pipeline;
 pub mod frame_queue;
+pub mod hdr;
 pub mod imaging;
+pub mod lut;
 pub mod pixel_convert;
 pub mod subtitles;
 pub mod video_filters;
@@ -69,9 +85,20 @@ pub mod video_filters;
 // ============================================================================
 // Streaming / Network
 // ============================================================================
+pub mod adaptive_stream;
+pub mod cast;
 pub mod debrid;
+pub mod debrid_download;
+pub mod dlna_renderer;
+pub mod dlna_server;
+pub mod hls_packager;
+pub mod http_utils;
 pub mod iptv;
+pub mod iptv_health;
+pub mod iptv_providers;
+pub mod iptv_recording;
 pub mod protocol;
+pub mod rtmp;
 pub mod streaming;
 
 // ============================================================================
@@ -120,6 +147,12 @@ pub mod gpu_benchmark;
 // ============================================================================
 pub mod security_audit;
 
+// ============================================================================
+// Test Fixtures
+// ============================================================================
+#[cfg(test)]
+mod test_support;
+
 // ============================================================================
 // Version
 // ============================================================================
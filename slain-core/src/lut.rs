@@ -0,0 +1,586 @@
+//! # Color LUTs - 1D/3D Lookup Tables
+//!
+//! Loads color grading LUTs handed over by colorists and applies them on the
+//! CPU (stills, reference path) or uploads them for the GPU filter pass.
+//!
+//! **Formats:**
+//! - Adobe/Resolve `.cube` (`LUT_1D_SIZE`, `LUT_3D_SIZE`, `DOMAIN_MIN/MAX`)
+//! - Autodesk/Lustre `.3dl` (integer mesh, 10/12/16-bit output depth)
+//!
+//! **Interpolation:**
+//! - Trilinear (8 taps)
+//! - Tetrahedral (4 taps, preserves neutrals better)
+//!
+//! 1D LUTs can be baked into a 3D lattice so the GPU only needs one code path.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Largest lattice we accept (65^3 is the biggest size seen in practice)
+pub const MAX_3D_SIZE: usize = 256;
+/// Largest 1D table we accept
+pub const MAX_1D_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LutKind {
+    /// Per-channel curves
+    OneD,
+    /// RGB lattice
+    ThreeD,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LutInterpolation {
+    Trilinear,
+    #[default]
+    Tetrahedral,
+}
+
+/// A parsed lookup table with normalized output values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lut {
+    pub title: Option<String>,
+    pub kind: LutKind,
+    /// Entries per axis (1D) or lattice edge length (3D)
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    /// 1D: `size` entries. 3D: `size^3` entries, red varies fastest.
+    pub table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    /// Identity 3D LUT (useful for tests and as a neutral fallback)
+    pub fn identity(size: usize) -> Self {
+        let size = size.max(2);
+        let scale = 1.0 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                }
+            }
+        }
+        Self {
+            title: None,
+            kind: LutKind::ThreeD,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        }
+    }
+
+    /// Look up a lattice point (3D LUTs only)
+    #[inline]
+    fn lattice(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[(b * self.size + g) * self.size + r]
+    }
+
+    /// Map an input value into table coordinates `[0, size - 1]`
+    #[inline]
+    fn to_index(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let mut out = [0.0; 3];
+        for c in 0..3 {
+            let range = self.domain_max[c] - self.domain_min[c];
+            let n = if range.abs() > f32::EPSILON {
+                (rgb[c] - self.domain_min[c]) / range
+            } else {
+                0.0
+            };
+            out[c] = (n * max).clamp(0.0, max);
+        }
+        out
+    }
+
+    /// Apply the LUT to a single RGB triplet
+    pub fn sample(&self, rgb: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+        match self.kind {
+            LutKind::OneD => self.sample_1d(rgb),
+            LutKind::ThreeD => match interpolation {
+                LutInterpolation::Trilinear => self.sample_trilinear(rgb),
+                LutInterpolation::Tetrahedral => self.sample_tetrahedral(rgb),
+            },
+        }
+    }
+
+    fn sample_1d(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let idx = self.to_index(rgb);
+        let mut out = [0.0; 3];
+        for c in 0..3 {
+            let i0 = idx[c].floor() as usize;
+            let i1 = (i0 + 1).min(self.size - 1);
+            let f = idx[c] - i0 as f32;
+            out[c] = self.table[i0][c] + (self.table[i1][c] - self.table[i0][c]) * f;
+        }
+        out
+    }
+
+    /// Trilinear interpolation between the 8 surrounding lattice points
+    pub fn sample_trilinear(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = self.to_index(rgb);
+        let last = self.size - 1;
+        let (r0, g0, b0) = (x as usize, y as usize, z as usize);
+        let (r1, g1, b1) = ((r0 + 1).min(last), (g0 + 1).min(last), (b0 + 1).min(last));
+        let (fr, fg, fb) = (x - r0 as f32, y - g0 as f32, z - b0 as f32);
+
+        let c000 = self.lattice(r0, g0, b0);
+        let c100 = self.lattice(r1, g0, b0);
+        let c010 = self.lattice(r0, g1, b0);
+        let c110 = self.lattice(r1, g1, b0);
+        let c001 = self.lattice(r0, g0, b1);
+        let c101 = self.lattice(r1, g0, b1);
+        let c011 = self.lattice(r0, g1, b1);
+        let c111 = self.lattice(r1, g1, b1);
+
+        let mut out = [0.0; 3];
+        for c in 0..3 {
+            let c00 = c000[c] + (c100[c] - c000[c]) * fr;
+            let c10 = c010[c] + (c110[c] - c010[c]) * fr;
+            let c01 = c001[c] + (c101[c] - c001[c]) * fr;
+            let c11 = c011[c] + (c111[c] - c011[c]) * fr;
+            let c0 = c00 + (c10 - c00) * fg;
+            let c1 = c01 + (c11 - c01) * fg;
+            out[c] = c0 + (c1 - c0) * fb;
+        }
+        out
+    }
+
+    /// Tetrahedral interpolation (splits the cube into 6 tetrahedra)
+    pub fn sample_tetrahedral(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = self.to_index(rgb);
+        let last = self.size - 1;
+        let (r0, g0, b0) = (x as usize, y as usize, z as usize);
+        let (r1, g1, b1) = ((r0 + 1).min(last), (g0 + 1).min(last), (b0 + 1).min(last));
+        let (fr, fg, fb) = (x - r0 as f32, y - g0 as f32, z - b0 as f32);
+
+        let c000 = self.lattice(r0, g0, b0);
+        let c111 = self.lattice(r1, g1, b1);
+
+        // Pick the two intermediate vertices and weights for this tetrahedron
+        let (v1, v2, w0, w1, w2, w3) = if fr > fg {
+            if fg > fb {
+                (
+                    self.lattice(r1, g0, b0),
+                    self.lattice(r1, g1, b0),
+                    1.0 - fr,
+                    fr - fg,
+                    fg - fb,
+                    fb,
+                )
+            } else if fr > fb {
+                (
+                    self.lattice(r1, g0, b0),
+                    self.lattice(r1, g0, b1),
+                    1.0 - fr,
+                    fr - fb,
+                    fb - fg,
+                    fg,
+                )
+            } else {
+                (
+                    self.lattice(r0, g0, b1),
+                    self.lattice(r1, g0, b1),
+                    1.0 - fb,
+                    fb - fr,
+                    fr - fg,
+                    fg,
+                )
+            }
+        } else if fb > fg {
+            (
+                self.lattice(r0, g0, b1),
+                self.lattice(r0, g1, b1),
+                1.0 - fb,
+                fb - fg,
+                fg - fr,
+                fr,
+            )
+        } else if fb > fr {
+            (
+                self.lattice(r0, g1, b0),
+                self.lattice(r0, g1, b1),
+                1.0 - fg,
+                fg - fb,
+                fb - fr,
+                fr,
+            )
+        } else {
+            (
+                self.lattice(r0, g1, b0),
+                self.lattice(r1, g1, b0),
+                1.0 - fg,
+                fg - fr,
+                fr - fb,
+                fb,
+            )
+        };
+
+        let mut out = [0.0; 3];
+        for c in 0..3 {
+            out[c] = w0 * c000[c] + w1 * v1[c] + w2 * v2[c] + w3 * c111[c];
+        }
+        out
+    }
+
+    /// Bake into a 3D lattice of the given size (1D LUTs and resampling)
+    pub fn to_3d(&self, size: usize, interpolation: LutInterpolation) -> Lut {
+        if self.kind == LutKind::ThreeD && self.size == size {
+            return self.clone();
+        }
+        let size = size.clamp(2, MAX_3D_SIZE);
+        let mut table = Vec::with_capacity(size * size * size);
+        let steps = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let mut input = [0.0; 3];
+                    for (c, v) in [r, g, b].into_iter().enumerate() {
+                        let n = v as f32 / steps;
+                        input[c] =
+                            self.domain_min[c] + n * (self.domain_max[c] - self.domain_min[c]);
+                    }
+                    table.push(self.sample(input, interpolation));
+                }
+            }
+        }
+        Lut {
+            title: self.title.clone(),
+            kind: LutKind::ThreeD,
+            size,
+            domain_min: self.domain_min,
+            domain_max: self.domain_max,
+            table,
+        }
+    }
+
+    /// Flatten to RGBA f32 texels (red fastest) for a 3D texture upload
+    pub fn to_rgba_f32(&self) -> Vec<f32> {
+        let mut out = Vec::with_capacity(self.table.len() * 4);
+        for v in &self.table {
+            out.extend_from_slice(&[v[0], v[1], v[2], 1.0]);
+        }
+        out
+    }
+
+    /// Apply to interleaved 8-bit pixels in place, blending by `strength`
+    ///
+    /// `channels` is 3 for RGB24 or 4 for RGBA (alpha is left untouched).
+    pub fn apply_rgb8(
+        &self,
+        pixels: &mut [u8],
+        channels: usize,
+        strength: f32,
+        interpolation: LutInterpolation,
+    ) {
+        if channels < 3 {
+            return;
+        }
+        let strength = strength.clamp(0.0, 1.0);
+        for px in pixels.chunks_exact_mut(channels) {
+            let src = [
+                px[0] as f32 / 255.0,
+                px[1] as f32 / 255.0,
+                px[2] as f32 / 255.0,
+            ];
+            let dst = self.sample(src, interpolation);
+            for c in 0..3 {
+                let v = src[c] + (dst[c] - src[c]) * strength;
+                px[c] = (v * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// .cube Parser
+// ============================================================================
+
+/// Parse an Adobe `.cube` file (1D or 3D)
+pub fn parse_cube(content: &str) -> Result<Lut, String> {
+    let mut title = None;
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut domain_min = [0.0f32; 3];
+    let mut domain_max = [1.0f32; 3];
+    let mut table = Vec::new();
+
+    for (line_no, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let keyword = parts.next().unwrap_or_default();
+        match keyword {
+            "TITLE" => {
+                let rest = line["TITLE".len()..].trim();
+                title = Some(rest.trim_matches('"').to_string());
+            }
+            "LUT_1D_SIZE" => size_1d = Some(parse_size(parts.next(), line_no)?),
+            "LUT_3D_SIZE" => size_3d = Some(parse_size(parts.next(), line_no)?),
+            "DOMAIN_MIN" => domain_min = parse_triplet(parts, line_no)?,
+            "DOMAIN_MAX" => domain_max = parse_triplet(parts, line_no)?,
+            // Resolve-specific keywords we don't need
+            "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                let lo = parse_float(parts.next(), line_no)?;
+                let hi = parse_float(parts.next(), line_no)?;
+                domain_min = [lo; 3];
+                domain_max = [hi; 3];
+            }
+            _ if keyword.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') => {
+                table.push(parse_triplet(line.split_whitespace(), line_no)?);
+            }
+            // Unknown keywords are allowed by the spec and ignored
+            _ => {}
+        }
+    }
+
+    let (kind, size, expected) = match (size_1d, size_3d) {
+        (Some(_), Some(_)) => return Err("Both LUT_1D_SIZE and LUT_3D_SIZE present".to_string()),
+        (Some(n), None) => {
+            if !(2..=MAX_1D_SIZE).contains(&n) {
+                return Err(format!("Unsupported LUT_1D_SIZE {}", n));
+            }
+            (LutKind::OneD, n, n)
+        }
+        (None, Some(n)) => {
+            if !(2..=MAX_3D_SIZE).contains(&n) {
+                return Err(format!("Unsupported LUT_3D_SIZE {}", n));
+            }
+            (LutKind::ThreeD, n, n * n * n)
+        }
+        (None, None) => return Err("Missing LUT_1D_SIZE or LUT_3D_SIZE".to_string()),
+    };
+
+    if table.len() != expected {
+        return Err(format!(
+            "Expected {} table entries, found {}",
+            expected,
+            table.len()
+        ));
+    }
+
+    for c in 0..3 {
+        if domain_max[c] <= domain_min[c] {
+            return Err("DOMAIN_MAX must be greater than DOMAIN_MIN".to_string());
+        }
+    }
+
+    Ok(Lut {
+        title,
+        kind,
+        size,
+        domain_min,
+        domain_max,
+        table,
+    })
+}
+
+fn parse_size(token: Option<&str>, line_no: usize) -> Result<usize, String> {
+    token
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| format!("Invalid size on line {}", line_no + 1))
+}
+
+fn parse_float(token: Option<&str>, line_no: usize) -> Result<f32, String> {
+    token
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| format!("Invalid number on line {}", line_no + 1))
+}
+
+fn parse_triplet<'a>(
+    mut parts: impl Iterator<Item = &'a str>,
+    line_no: usize,
+) -> Result<[f32; 3], String> {
+    Ok([
+        parse_float(parts.next(), line_no)?,
+        parse_float(parts.next(), line_no)?,
+        parse_float(parts.next(), line_no)?,
+    ])
+}
+
+// ============================================================================
+// .3dl Parser
+// ============================================================================
+
+/// Parse an Autodesk `.3dl` file
+///
+/// The first numeric line is the input mesh (e.g. `0 64 128 ... 1023`);
+/// every following line is an integer RGB triplet with blue varying fastest.
+/// Output bit depth is inferred from the largest value in the table.
+pub fn parse_3dl(content: &str) -> Result<Lut, String> {
+    let mut mesh: Option<Vec<f32>> = None;
+    let mut rows: Vec<[f32; 3]> = Vec::new();
+
+    for (line_no, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(|c: char| c.is_alphabetic())
+        {
+            // Lustre headers like "3DMESH" / "Mesh 4 12" carry nothing we need
+            continue;
+        }
+
+        let values: Vec<f32> = line
+            .split_whitespace()
+            .map(|t| t.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid number on line {}", line_no + 1))?;
+
+        if mesh.is_none() && rows.is_empty() && values.len() != 3 {
+            mesh = Some(values);
+        } else if values.len() == 3 {
+            rows.push([values[0], values[1], values[2]]);
+        } else {
+            return Err(format!("Malformed row on line {}", line_no + 1));
+        }
+    }
+
+    let size = match &mesh {
+        Some(m) => m.len(),
+        None => (rows.len() as f64).cbrt().round() as usize,
+    };
+    if !(2..=MAX_3D_SIZE).contains(&size) || rows.len() != size * size * size {
+        return Err(format!("Expected {}^3 entries, found {}", size, rows.len()));
+    }
+
+    let max_value = rows
+        .iter()
+        .flat_map(|r| r.iter().copied())
+        .fold(0.0f32, f32::max);
+    let scale = match max_value {
+        v if v <= 1.0 => 1.0,
+        v if v <= 1023.0 => 1023.0,
+        v if v <= 4095.0 => 4095.0,
+        _ => 65535.0,
+    };
+
+    // .3dl is blue-fastest; reorder to red-fastest
+    let mut table = vec![[0.0f32; 3]; rows.len()];
+    for r in 0..size {
+        for g in 0..size {
+            for b in 0..size {
+                let src = rows[(r * size + g) * size + b];
+                table[(b * size + g) * size + r] = [src[0] / scale, src[1] / scale, src[2] / scale];
+            }
+        }
+    }
+
+    Ok(Lut {
+        title: None,
+        kind: LutKind::ThreeD,
+        size,
+        domain_min: [0.0; 3],
+        domain_max: [1.0; 3],
+        table,
+    })
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+/// Load a LUT from disk, choosing the parser by extension
+pub fn load_lut<P: AsRef<Path>>(path: P) -> Result<Lut, String> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read LUT {}: {}", path.display(), e))?;
+
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "cube" => parse_cube(&content),
+        "3dl" => parse_3dl(&content),
+        _ => Err(format!("Unsupported LUT format: .{}", ext)),
+    }
+}
+
+pub fn supported_lut_formats() -> Vec<&'static str> {
+    vec!["cube", "3dl"]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for c in 0..3 {
+            assert!((a[c] - b[c]).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn parse_cube_3d_with_domain() {
+        let content = "TITLE \"Test\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n\
+                       0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        let lut = parse_cube(content).expect("parse cube");
+        assert_eq!(lut.title.as_deref(), Some("Test"));
+        assert_eq!(lut.kind, LutKind::ThreeD);
+        assert_eq!(lut.size, 2);
+        // Domain is 0..2, so an input of 1.0 sits in the middle of the lattice
+        assert_close(
+            lut.sample([1.0, 1.0, 1.0], LutInterpolation::Trilinear),
+            [0.5, 0.5, 0.5],
+        );
+    }
+
+    #[test]
+    fn parse_cube_1d_inverts() {
+        let content = "LUT_1D_SIZE 2\n1 1 1\n0 0 0\n";
+        let lut = parse_cube(content).expect("parse cube");
+        assert_eq!(lut.kind, LutKind::OneD);
+        assert_close(
+            lut.sample([0.25, 0.5, 1.0], LutInterpolation::Tetrahedral),
+            [0.75, 0.5, 0.0],
+        );
+        let baked = lut.to_3d(5, LutInterpolation::Trilinear);
+        assert_close(
+            baked.sample([0.25, 0.5, 1.0], LutInterpolation::Tetrahedral),
+            [0.75, 0.5, 0.0],
+        );
+    }
+
+    #[test]
+    fn parse_cube_rejects_wrong_entry_count() {
+        let err = parse_cube("LUT_3D_SIZE 2\n0 0 0\n").unwrap_err();
+        assert!(err.contains("Expected 8"));
+    }
+
+    #[test]
+    fn parse_3dl_reorders_to_red_fastest() {
+        let mut content = String::from("0 1023\n");
+        for r in 0..2 {
+            for g in 0..2 {
+                for b in 0..2 {
+                    content.push_str(&format!("{} {} {}\n", r * 1023, g * 1023, b * 1023));
+                }
+            }
+        }
+        let lut = parse_3dl(&content).expect("parse 3dl");
+        assert_eq!(lut.size, 2);
+        assert_close(lut.table[1], [1.0, 0.0, 0.0]);
+        assert_close(lut.table[4], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn identity_interpolation_is_lossless() {
+        let lut = Lut::identity(17);
+        let rgb = [0.13, 0.57, 0.91];
+        assert_close(lut.sample(rgb, LutInterpolation::Trilinear), rgb);
+        assert_close(lut.sample(rgb, LutInterpolation::Tetrahedral), rgb);
+
+        let mut pixels = vec![10u8, 128, 250, 255];
+        lut.apply_rgb8(&mut pixels, 4, 1.0, LutInterpolation::Tetrahedral);
+        assert_eq!(pixels, vec![10, 128, 250, 255]);
+    }
+}
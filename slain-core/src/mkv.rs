@@ -1,11 +1,13 @@
 // MKV (Matroska/WebM) demuxer using matroska-demuxer crate
 // Provides track info and frame packet reading
 
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Arc;
 
 use matroska_demuxer::{Frame, MatroskaFile, TrackEntry, TrackType};
 
@@ -69,6 +71,7 @@ pub struct AudioTrack {
     pub track_uid: u64,
     pub codec_id: String,
     pub codec_name: Option<String>,
+    pub codec_private: Option<Vec<u8>>,
     pub name: Option<String>,
     pub language: String,
     pub enabled: bool,
@@ -215,6 +218,7 @@ fn convert_track(track: &TrackEntry) -> MkvTrack {
                     track_uid,
                     codec_id,
                     codec_name: None,
+                    codec_private,
                     name,
                     language,
                     enabled,
@@ -282,13 +286,13 @@ impl MkvParser {
 
         let mkv = MatroskaFile::open(file).map_err(|e| format!("Failed to parse MKV: {:?}", e))?;
 
-        // Get duration in nanoseconds, convert to ms
-        let duration_ns = mkv.info().duration().unwrap_or(0.0) as u64;
-        let duration_ms = duration_ns / 1_000_000;
-
-        // Get timecode scale
+        // Get timecode scale (ns per tick)
         let timecode_scale = mkv.info().timestamp_scale().get();
 
+        // Duration is in ticks, convert to ms
+        let duration_ticks = mkv.info().duration().unwrap_or(0.0);
+        let duration_ms = (duration_ticks * timecode_scale as f64 / 1_000_000.0) as u64;
+
         // Convert tracks
         let tracks: Vec<MkvTrack> = mkv.tracks().iter().map(convert_track).collect();
 
@@ -297,6 +301,16 @@ impl MkvParser {
         let muxing_app = Some(mkv.info().muxing_app().to_string());
         let writing_app = Some(mkv.info().writing_app().to_string());
 
+        // Cues, chapters and attachments come from our own segment walk
+        let index = File::open(path)
+            .map(std::io::BufReader::new)
+            .map_err(|e| e.to_string())
+            .and_then(|mut reader| parser::read_segment_index(&mut reader))
+            .unwrap_or_else(|e| {
+                tracing::warn!("MKV index scan failed for {:?}: {}", path, e);
+                parser::SegmentIndex::default()
+            });
+
         Ok(MkvInfo {
             file_path: path.to_string_lossy().to_string(),
             file_size,
@@ -307,13 +321,31 @@ impl MkvParser {
             date_utc: None,
             timecode_scale,
             tracks,
-            chapters: Vec::new(),
-            attachments: Vec::new(),
+            chapters: index.chapters,
+            attachments: index.attachments,
             tags: HashMap::new(),
-            has_cues: false,
-            cues: Vec::new(),
+            has_cues: !index.cues.is_empty(),
+            cues: index.cues,
         })
     }
+
+    /// Read an attachment's file data
+    pub fn read_attachment<P: AsRef<Path>>(
+        &self,
+        path: P,
+        attachment: &MkvAttachment,
+    ) -> Result<Vec<u8>, String> {
+        use std::io::SeekFrom;
+
+        let mut file =
+            File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
+        file.seek(SeekFrom::Start(attachment.data_offset))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        let mut data = vec![0u8; attachment.size as usize];
+        file.read_exact(&mut data)
+            .map_err(|e| format!("Failed to read attachment {}: {}", attachment.filename, e))?;
+        Ok(data)
+    }
 }
 
 // ============================================================================
@@ -330,11 +362,40 @@ pub struct MkvPacket {
     pub data: Vec<u8>,
 }
 
+/// Reader handle shared with `MatroskaFile`, so a seek can reopen the file
+/// and place it at a cue's cluster
+struct SharedReader<R>(Arc<Mutex<R>>);
+
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<R: Read> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.lock().read(buf)
+    }
+}
+
+impl<R: Seek> Seek for SharedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.lock().seek(pos)
+    }
+}
+
 /// MKV demuxer for reading frame packets
 pub struct MkvDemuxer<R: Read + Seek> {
-    mkv: MatroskaFile<R>,
+    mkv: MatroskaFile<SharedReader<R>>,
+    reader: SharedReader<R>,
+    /// Where the Segment's data starts; cue positions are relative to it
+    segment_offset: u64,
     frame: Frame,
+    /// Packets read ahead by a seek, returned before the file continues
+    buffered: VecDeque<MkvPacket>,
     info: MkvInfo,
+    /// Nanoseconds per block timestamp tick
+    timestamp_scale: u64,
     video_track: Option<u64>,
     audio_track: Option<u64>,
 }
@@ -350,7 +411,10 @@ impl MkvDemuxer<File> {
 impl<R: Read + Seek> MkvDemuxer<R> {
     /// Create from reader
     pub fn new(reader: R, info: MkvInfo) -> Result<Self, String> {
-        let mkv = MatroskaFile::open(reader).map_err(|e| format!("Failed to open MKV: {:?}", e))?;
+        let reader = SharedReader(Arc::new(Mutex::new(reader)));
+        let segment_offset = parser::segment_data_offset(&mut reader.clone())?;
+        let mkv = Self::open_matroska(&reader)?;
+        let timestamp_scale = mkv.info().timestamp_scale().get();
 
         // Find video and audio tracks
         let mut video_track = None;
@@ -367,13 +431,26 @@ impl<R: Read + Seek> MkvDemuxer<R> {
 
         Ok(Self {
             mkv,
+            reader,
+            segment_offset,
             frame: Frame::default(),
+            buffered: VecDeque::new(),
             info,
+            timestamp_scale,
             video_track,
             audio_track,
         })
     }
 
+    /// Parse the headers from the start; leaves the reader at the first cluster
+    fn open_matroska(reader: &SharedReader<R>) -> Result<MatroskaFile<SharedReader<R>>, String> {
+        reader
+            .clone()
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        MatroskaFile::open(reader.clone()).map_err(|e| format!("Failed to open MKV: {:?}", e))
+    }
+
     /// Get media info
     pub fn info(&self) -> &MkvInfo {
         &self.info
@@ -391,16 +468,19 @@ impl<R: Read + Seek> MkvDemuxer<R> {
 
     /// Read next packet
     pub fn read_packet(&mut self) -> Option<MkvPacket> {
+        if let Some(packet) = self.buffered.pop_front() {
+            return Some(packet);
+        }
         match self.mkv.next_frame(&mut self.frame) {
             Ok(true) => {
-                // Timestamp is in nanoseconds
-                let pts_ns = self.frame.timestamp as i64;
-                let pts_ms = pts_ns / 1_000_000;
+                // Timestamps are in TimestampScale ticks (1 ms by default)
+                let pts_ms = self.ticks_to_ms(self.frame.timestamp);
+                let duration_ms = self.frame.duration.map(|d| self.ticks_to_ms(d));
 
                 Some(MkvPacket {
                     track_number: self.frame.track as u64,
                     pts_ms,
-                    duration_ms: None,
+                    duration_ms,
                     keyframe: self.frame.is_keyframe.unwrap_or(false),
                     data: self.frame.data.clone(),
                 })
@@ -413,8 +493,51 @@ impl<R: Read + Seek> MkvDemuxer<R> {
         }
     }
 
-    /// Seek (not implemented - matroska-demuxer doesn't support seeking)
-    pub fn seek(&mut self, _time_ms: u64) -> Result<(), String> {
+    fn ticks_to_ms(&self, ticks: u64) -> i64 {
+        (ticks as u128 * self.timestamp_scale as u128 / 1_000_000) as i64
+    }
+
+    /// Seek to the last keyframe at or before `time_ms`
+    ///
+    /// Starts from the nearest preceding cue point of the video track (or the
+    /// first cluster without Cues) and reads ahead to the keyframe, so the
+    /// next packet read can be decoded on its own. matroska-demuxer's own
+    /// seek isn't used: it resolves CueRelativePosition against the first
+    /// cluster and lands on any block rather than a keyframe.
+    pub fn seek(&mut self, time_ms: u64) -> Result<(), String> {
+        let track = self.video_track.or(self.audio_track);
+        let cluster = self
+            .info
+            .cues
+            .iter()
+            .filter(|cue| Some(cue.track) == track && cue.time_ms <= time_ms)
+            .max_by_key(|cue| cue.time_ms)
+            .map(|cue| self.segment_offset + cue.cluster_position);
+
+        self.mkv = Self::open_matroska(&self.reader)?;
+        if let Some(position) = cluster {
+            self.reader
+                .clone()
+                .seek(SeekFrom::Start(position))
+                .map_err(|e| format!("Seek error: {}", e))?;
+        }
+        self.buffered.clear();
+
+        // Keep everything from the last keyframe up to the target
+        let mut ahead = VecDeque::new();
+        while let Some(packet) = self.read_packet() {
+            if Some(packet.track_number) == track {
+                if packet.pts_ms > time_ms as i64 {
+                    ahead.push_back(packet);
+                    break;
+                }
+                if packet.keyframe {
+                    ahead.clear();
+                }
+            }
+            ahead.push_back(packet);
+        }
+        self.buffered = ahead;
         Ok(())
     }
 }
@@ -1,6 +1,54 @@
 //! Minimal MKV/EBML parsing helpers.
+//!
+//! Besides the vint reader this walks a Segment's top level for the parts
+//! matroska-demuxer doesn't expose: Cues, Chapters and Attachments. The
+//! SeekHead is followed when present so files with cues at the end don't need
+//! a walk over every Cluster.
 
 use bytes::Buf;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{CuePoint, MkvAttachment, MkvChapter};
+
+const EBML: u32 = 0x1A45_DFA3;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const CLUSTER: u32 = 0x1F43_B675;
+
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+const CUE_RELATIVE_POSITION: u32 = 0xF0;
+const CUE_DURATION: u32 = 0xB2;
+
+const CHAPTERS: u32 = 0x1043_A770;
+const EDITION_ENTRY: u32 = 0x45B9;
+const CHAPTER_ATOM: u32 = 0xB6;
+const CHAPTER_UID: u32 = 0x73C4;
+const CHAPTER_STRING_UID: u32 = 0x5654;
+const CHAPTER_TIME_START: u32 = 0x91;
+const CHAPTER_TIME_END: u32 = 0x92;
+const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+const CHAPTER_DISPLAY: u32 = 0x80;
+const CHAP_STRING: u32 = 0x85;
+const CHAP_LANGUAGE: u32 = 0x437C;
+
+const ATTACHMENTS: u32 = 0x1941_A469;
+const ATTACHED_FILE: u32 = 0x61A7;
+const FILE_DESCRIPTION: u32 = 0x467E;
+const FILE_NAME: u32 = 0x466E;
+const FILE_MEDIA_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465C;
+const FILE_UID: u32 = 0x46AE;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Vint {
@@ -37,6 +85,381 @@ pub fn read_vint<B: Buf>(buf: &mut B) -> Result<Vint, String> {
     Ok(Vint { length, value })
 }
 
+// ============================================================================
+// Segment Index
+// ============================================================================
+
+/// Segment-level metadata outside Info/Tracks
+#[derive(Debug, Clone, Default)]
+pub struct SegmentIndex {
+    pub cues: Vec<CuePoint>,
+    pub chapters: Vec<MkvChapter>,
+    pub attachments: Vec<MkvAttachment>,
+}
+
+struct ElementHeader {
+    id: u32,
+    /// None for "unknown" (live-written) sizes
+    size: Option<u64>,
+    data_pos: u64,
+}
+
+fn read_element_header<R: Read + Seek>(reader: &mut R) -> Result<ElementHeader, String> {
+    let mut raw = [0u8; 8];
+    reader
+        .read_exact(&mut raw[..1])
+        .map_err(|e| format!("Read error: {}", e))?;
+    let id_len = raw[0].leading_zeros() as usize + 1;
+    if id_len > 4 {
+        return Err("Invalid element ID".to_string());
+    }
+    reader
+        .read_exact(&mut raw[1..id_len])
+        .map_err(|e| format!("Read error: {}", e))?;
+    let id = raw[..id_len]
+        .iter()
+        .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+
+    reader
+        .read_exact(&mut raw[..1])
+        .map_err(|e| format!("Read error: {}", e))?;
+    let size_len = raw[0].leading_zeros() as usize + 1;
+    if size_len > 8 {
+        return Err("Invalid element size".to_string());
+    }
+    reader
+        .read_exact(&mut raw[1..size_len])
+        .map_err(|e| format!("Read error: {}", e))?;
+    let size = read_vint(&mut &raw[..size_len])?;
+    let unknown = size.value == (1u64 << (7 * size.length)) - 1;
+
+    let data_pos = reader
+        .stream_position()
+        .map_err(|e| format!("Position error: {}", e))?;
+    Ok(ElementHeader {
+        id,
+        size: (!unknown).then_some(size.value),
+        data_pos,
+    })
+}
+
+fn read_payload<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    reader
+        .take(size)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Read error: {}", e))?;
+    if (data.len() as u64) < size {
+        return Err("Truncated element".to_string());
+    }
+    Ok(data)
+}
+
+/// Child elements of an in-memory master element
+fn children(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let id_len = data[0].leading_zeros() as usize + 1;
+        if id_len > 4 || data.len() < id_len {
+            break;
+        }
+        let id = data[..id_len]
+            .iter()
+            .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        data.advance(id_len);
+        let Ok(size) = read_vint(&mut data) else {
+            break;
+        };
+        let size = (size.value as usize).min(data.len());
+        out.push((id, &data[..size]));
+        data.advance(size);
+    }
+    out
+}
+
+fn as_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn as_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Offset of the Segment's data, which Cue and SeekHead positions count from
+pub fn segment_data_offset<R: Read + Seek>(reader: &mut R) -> Result<u64, String> {
+    Ok(read_segment_header(reader)?.data_pos)
+}
+
+fn read_segment_header<R: Read + Seek>(reader: &mut R) -> Result<ElementHeader, String> {
+    reader
+        .seek(SeekFrom::Start(0))
+        .map_err(|e| format!("Seek error: {}", e))?;
+    let header = read_element_header(reader)?;
+    if header.id != EBML {
+        return Err("Not an EBML file".to_string());
+    }
+    let header_size = header.size.ok_or("EBML header has unknown size")?;
+    reader
+        .seek(SeekFrom::Start(header.data_pos + header_size))
+        .map_err(|e| format!("Seek error: {}", e))?;
+    let segment = read_element_header(reader)?;
+    if segment.id != SEGMENT {
+        return Err("No Segment after EBML header".to_string());
+    }
+    Ok(segment)
+}
+
+/// Read Cues, Chapters and Attachments from the Segment of a Matroska file
+pub fn read_segment_index<R: Read + Seek>(reader: &mut R) -> Result<SegmentIndex, String> {
+    let segment = read_segment_header(reader)?;
+    let segment_start = segment.data_pos;
+    let segment_end = segment.size.map_or(u64::MAX, |size| segment_start + size);
+
+    let mut index = SegmentIndex::default();
+    let mut timestamp_scale = 1_000_000u64;
+    let mut seek_targets: Vec<(u32, u64)> = Vec::new();
+    let mut seen: Vec<u32> = Vec::new();
+    let mut pos = segment_start;
+
+    // Top-level walk; with a SeekHead it stops at the first Cluster, without
+    // one it hops over Clusters to reach trailing Cues/Chapters
+    while pos < segment_end {
+        if reader.seek(SeekFrom::Start(pos)).is_err() {
+            break;
+        }
+        let Ok(element) = read_element_header(reader) else {
+            break;
+        };
+        if element.id == CLUSTER && !seek_targets.is_empty() {
+            break;
+        }
+        let Some(size) = element.size else {
+            break;
+        };
+        match element.id {
+            SEEK_HEAD => {
+                let data = read_payload(reader, size)?;
+                for (id, seek) in children(&data) {
+                    if id != SEEK {
+                        continue;
+                    }
+                    let fields = children(seek);
+                    let target = fields.iter().find(|(id, _)| *id == SEEK_ID);
+                    let position = fields.iter().find(|(id, _)| *id == SEEK_POSITION);
+                    if let (Some((_, target)), Some((_, position))) = (target, position) {
+                        seek_targets.push((as_uint(target) as u32, as_uint(position)));
+                    }
+                }
+            }
+            INFO => {
+                let data = read_payload(reader, size)?;
+                if let Some((_, scale)) = children(&data)
+                    .into_iter()
+                    .find(|(id, _)| *id == TIMESTAMP_SCALE)
+                {
+                    timestamp_scale = as_uint(scale).max(1);
+                }
+            }
+            CUES | CHAPTERS | ATTACHMENTS => {
+                read_index_element(reader, &element, timestamp_scale, &mut index)?;
+                seen.push(element.id);
+            }
+            _ => {}
+        }
+        pos = element.data_pos + size;
+    }
+
+    // Everything after the clusters is reached through the SeekHead
+    for (id, position) in seek_targets {
+        if !matches!(id, CUES | CHAPTERS | ATTACHMENTS) || seen.contains(&id) {
+            continue;
+        }
+        reader
+            .seek(SeekFrom::Start(segment_start + position))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        let element = read_element_header(reader)?;
+        if element.id != id {
+            tracing::warn!("SeekHead entry {:#x} points at {:#x}", id, element.id);
+            continue;
+        }
+        read_index_element(reader, &element, timestamp_scale, &mut index)?;
+        seen.push(id);
+    }
+
+    Ok(index)
+}
+
+fn read_index_element<R: Read + Seek>(
+    reader: &mut R,
+    element: &ElementHeader,
+    timestamp_scale: u64,
+    index: &mut SegmentIndex,
+) -> Result<(), String> {
+    let size = element.size.ok_or("Index element has unknown size")?;
+    match element.id {
+        CUES => {
+            let data = read_payload(reader, size)?;
+            index.cues = parse_cues(&data, timestamp_scale);
+        }
+        CHAPTERS => {
+            let data = read_payload(reader, size)?;
+            index.chapters = children(&data)
+                .into_iter()
+                .filter(|(id, _)| *id == EDITION_ENTRY)
+                .flat_map(|(_, edition)| {
+                    children(edition)
+                        .into_iter()
+                        .filter(|(id, _)| *id == CHAPTER_ATOM)
+                        .map(|(_, atom)| parse_chapter(atom))
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+        }
+        ATTACHMENTS => {
+            index.attachments = read_attachments(reader, element.data_pos + size)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_cues(data: &[u8], timestamp_scale: u64) -> Vec<CuePoint> {
+    let to_ms = |ticks: u64| ticks.saturating_mul(timestamp_scale) / 1_000_000;
+    let mut cues = Vec::new();
+    for (id, point) in children(data) {
+        if id != CUE_POINT {
+            continue;
+        }
+        let fields = children(point);
+        let Some(time) = fields
+            .iter()
+            .find(|(id, _)| *id == CUE_TIME)
+            .map(|(_, v)| as_uint(v))
+        else {
+            continue;
+        };
+        for (_, positions) in fields.iter().filter(|(id, _)| *id == CUE_TRACK_POSITIONS) {
+            let mut cue = CuePoint {
+                time_ms: to_ms(time),
+                track: 0,
+                cluster_position: 0,
+                relative_position: None,
+                duration_ms: None,
+            };
+            for (id, value) in children(positions) {
+                match id {
+                    CUE_TRACK => cue.track = as_uint(value),
+                    CUE_CLUSTER_POSITION => cue.cluster_position = as_uint(value),
+                    CUE_RELATIVE_POSITION => cue.relative_position = Some(as_uint(value)),
+                    CUE_DURATION => cue.duration_ms = Some(to_ms(as_uint(value))),
+                    _ => {}
+                }
+            }
+            cues.push(cue);
+        }
+    }
+    cues
+}
+
+fn parse_chapter(atom: &[u8]) -> MkvChapter {
+    let mut chapter = MkvChapter {
+        uid: 0,
+        string_uid: None,
+        title: String::new(),
+        language: "eng".to_string(),
+        start_time_ms: 0,
+        end_time_ms: None,
+        hidden: false,
+        enabled: true,
+        nested: Vec::new(),
+    };
+    let mut has_display = false;
+    for (id, value) in children(atom) {
+        match id {
+            CHAPTER_UID => chapter.uid = as_uint(value),
+            CHAPTER_STRING_UID => chapter.string_uid = Some(as_string(value)),
+            CHAPTER_TIME_START => chapter.start_time_ms = as_uint(value) / 1_000_000,
+            CHAPTER_TIME_END => chapter.end_time_ms = Some(as_uint(value) / 1_000_000),
+            CHAPTER_FLAG_HIDDEN => chapter.hidden = as_uint(value) != 0,
+            CHAPTER_FLAG_ENABLED => chapter.enabled = as_uint(value) != 0,
+            // The first display is the primary title
+            CHAPTER_DISPLAY if !has_display => {
+                has_display = true;
+                for (id, value) in children(value) {
+                    match id {
+                        CHAP_STRING => chapter.title = as_string(value),
+                        CHAP_LANGUAGE => chapter.language = as_string(value),
+                        _ => {}
+                    }
+                }
+            }
+            CHAPTER_ATOM => chapter.nested.push(parse_chapter(value)),
+            _ => {}
+        }
+    }
+    chapter
+}
+
+/// Attachment metadata; file data is left on disk and located by offset
+fn read_attachments<R: Read + Seek>(
+    reader: &mut R,
+    end: u64,
+) -> Result<Vec<MkvAttachment>, String> {
+    let mut attachments = Vec::new();
+    let mut pos = reader
+        .stream_position()
+        .map_err(|e| format!("Position error: {}", e))?;
+
+    while pos < end {
+        reader
+            .seek(SeekFrom::Start(pos))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        let file = read_element_header(reader)?;
+        let size = file.size.ok_or("AttachedFile has unknown size")?;
+        pos = file.data_pos + size;
+        if file.id != ATTACHED_FILE {
+            continue;
+        }
+
+        let mut attachment = MkvAttachment {
+            uid: 0,
+            filename: String::new(),
+            mime_type: String::new(),
+            description: None,
+            size: 0,
+            data_offset: 0,
+        };
+        let mut child_pos = file.data_pos;
+        while child_pos < pos {
+            reader
+                .seek(SeekFrom::Start(child_pos))
+                .map_err(|e| format!("Seek error: {}", e))?;
+            let child = read_element_header(reader)?;
+            let child_size = child.size.ok_or("Attachment field has unknown size")?;
+            child_pos = child.data_pos + child_size;
+            match child.id {
+                FILE_DATA => {
+                    attachment.data_offset = child.data_pos;
+                    attachment.size = child_size;
+                }
+                FILE_NAME => attachment.filename = as_string(&read_payload(reader, child_size)?),
+                FILE_MEDIA_TYPE => {
+                    attachment.mime_type = as_string(&read_payload(reader, child_size)?)
+                }
+                FILE_DESCRIPTION => {
+                    attachment.description = Some(as_string(&read_payload(reader, child_size)?))
+                }
+                FILE_UID => attachment.uid = as_uint(&read_payload(reader, child_size)?),
+                _ => {}
+            }
+        }
+        attachments.push(attachment);
+    }
+    Ok(attachments)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,874 @@
+//! Matroska/WebM writer
+//!
+//! Writes the EBML header, a Segment with a reserved SeekHead, Info, Tracks
+//! and Attachments, then packets as SimpleBlocks grouped into Clusters (a new
+//! one on every video keyframe, or when the block-relative timestamp would
+//! overflow). Cues for every video keyframe and the Chapters follow the last
+//! Cluster. The SeekHead, Segment size and Duration are patched in when the
+//! muxer is finished, so the output must be seekable. Timestamps use the
+//! default 1 ms TimestampScale.
+
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::mkv::{CuePoint, MkvChapter};
+use crate::mux::{MuxPacket, MuxStream, MuxTrackKind};
+
+// EBML / Matroska element IDs
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const VOID: u32 = 0xEC;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const DURATION: u32 = 0x4489;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TITLE: u32 = 0x7BA9;
+
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_ENABLED: u32 = 0xB9;
+const FLAG_DEFAULT: u32 = 0x88;
+const FLAG_FORCED: u32 = 0x55AA;
+const FLAG_LACING: u32 = 0x9C;
+const DEFAULT_DURATION: u32 = 0x23_E383;
+const NAME: u32 = 0x536E;
+const LANGUAGE: u32 = 0x22_B59C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const BIT_DEPTH: u32 = 0x6264;
+
+const CLUSTER: u32 = 0x1F43_B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+const CUE_RELATIVE_POSITION: u32 = 0xF0;
+
+const CHAPTERS: u32 = 0x1043_A770;
+const EDITION_ENTRY: u32 = 0x45B9;
+const EDITION_UID: u32 = 0x45BC;
+const EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+const CHAPTER_ATOM: u32 = 0xB6;
+const CHAPTER_UID: u32 = 0x73C4;
+const CHAPTER_STRING_UID: u32 = 0x5654;
+const CHAPTER_TIME_START: u32 = 0x91;
+const CHAPTER_TIME_END: u32 = 0x92;
+const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+const CHAPTER_DISPLAY: u32 = 0x80;
+const CHAP_STRING: u32 = 0x85;
+const CHAP_LANGUAGE: u32 = 0x437C;
+
+const ATTACHMENTS: u32 = 0x1941_A469;
+const ATTACHED_FILE: u32 = 0x61A7;
+const FILE_DESCRIPTION: u32 = 0x467E;
+const FILE_NAME: u32 = 0x466E;
+const FILE_MEDIA_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465C;
+const FILE_UID: u32 = 0x46AE;
+
+/// "Unknown" 8-byte size, patched on finish
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Room kept at the start of the Segment for the SeekHead
+const SEEK_HEAD_RESERVED: usize = 160;
+
+// ============================================================================
+// EBML Encoding
+// ============================================================================
+
+fn put_id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(3);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// Shortest vint that holds `size` (all-ones is reserved for "unknown")
+fn put_size(out: &mut Vec<u8>, size: u64) {
+    let len = (1..=8usize)
+        .find(|&len| size < (1u64 << (7 * len)) - 1)
+        .unwrap_or(8);
+    let marked = size | (1u64 << (7 * len));
+    out.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+fn put_element(out: &mut Vec<u8>, id: u32, payload: &[u8]) {
+    put_id(out, id);
+    put_size(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
+
+fn put_uint(out: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    put_element(out, id, &bytes[skip..]);
+}
+
+fn put_float(out: &mut Vec<u8>, id: u32, value: f64) {
+    put_element(out, id, &value.to_be_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, id: u32, value: &str) {
+    put_element(out, id, value.as_bytes());
+}
+
+fn put_master(out: &mut Vec<u8>, id: u32, build: impl FnOnce(&mut Vec<u8>)) {
+    let mut body = Vec::new();
+    build(&mut body);
+    put_element(out, id, &body);
+}
+
+/// Void element filling exactly `len` bytes (at least 9)
+fn put_void(out: &mut Vec<u8>, len: usize) {
+    put_id(out, VOID);
+    let payload = len - 9;
+    out.extend_from_slice(&(payload as u64 | (1u64 << 56)).to_be_bytes());
+    out.resize(out.len() + payload, 0);
+}
+
+/// Stable non-zero UID derived from an index
+fn uid(seed: u64) -> u64 {
+    (seed + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1
+}
+
+fn put_chapter(out: &mut Vec<u8>, chapter: &MkvChapter, index: u64) {
+    put_master(out, CHAPTER_ATOM, |atom| {
+        let chapter_uid = if chapter.uid != 0 {
+            chapter.uid
+        } else {
+            uid(index)
+        };
+        put_uint(atom, CHAPTER_UID, chapter_uid);
+        if let Some(string_uid) = &chapter.string_uid {
+            put_string(atom, CHAPTER_STRING_UID, string_uid);
+        }
+        put_uint(atom, CHAPTER_TIME_START, chapter.start_time_ms * 1_000_000);
+        if let Some(end) = chapter.end_time_ms {
+            put_uint(atom, CHAPTER_TIME_END, end * 1_000_000);
+        }
+        put_uint(atom, CHAPTER_FLAG_HIDDEN, chapter.hidden as u64);
+        put_uint(atom, CHAPTER_FLAG_ENABLED, chapter.enabled as u64);
+        put_master(atom, CHAPTER_DISPLAY, |display| {
+            put_string(display, CHAP_STRING, &chapter.title);
+            put_string(display, CHAP_LANGUAGE, &chapter.language);
+        });
+        for (i, nested) in chapter.nested.iter().enumerate() {
+            put_chapter(atom, nested, (index + 1) * 1000 + i as u64);
+        }
+    });
+}
+
+// ============================================================================
+// Muxer
+// ============================================================================
+
+/// A file stored in the Segment (fonts for ASS subtitles, cover art, ...)
+#[derive(Debug, Clone)]
+pub struct MkvMuxAttachment {
+    pub filename: String,
+    pub mime_type: String,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl MkvMuxAttachment {
+    /// Load `path`, guessing the media type from its extension
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("Invalid attachment name: {:?}", path))?
+            .to_string();
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let mime_type = match ext.as_str() {
+            "ttf" => "font/ttf",
+            "otf" => "font/otf",
+            "woff2" => "font/woff2",
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            "webp" => "image/webp",
+            "txt" | "nfo" => "text/plain",
+            _ => "application/octet-stream",
+        };
+        Ok(Self {
+            filename,
+            mime_type: mime_type.to_string(),
+            description: None,
+            data,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MkvMuxConfig {
+    /// DocType "webm" instead of "matroska"
+    pub webm: bool,
+    pub title: Option<String>,
+    /// Longest a cluster may run without a video keyframe
+    pub max_cluster_ms: i64,
+    /// Index video keyframes (or cluster starts for audio-only files)
+    pub cues: bool,
+    /// Written after the last cluster; may be replaced before `finish`
+    pub chapters: Vec<MkvChapter>,
+    /// Written ahead of the clusters (not allowed in WebM)
+    pub attachments: Vec<MkvMuxAttachment>,
+}
+
+impl Default for MkvMuxConfig {
+    fn default() -> Self {
+        Self {
+            webm: false,
+            title: None,
+            max_cluster_ms: 5000,
+            cues: true,
+            chapters: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+}
+
+struct OpenCluster {
+    timestamp_ms: i64,
+    body: Vec<u8>,
+    /// (time, track, offset of the block in the body) to index
+    cues: Vec<(u64, u64, u64)>,
+}
+
+pub struct MkvMuxer<W: Write + Seek> {
+    writer: W,
+    config: MkvMuxConfig,
+    streams: Vec<MuxStream>,
+    /// Where the Segment's size vint and payload start
+    segment_size_pos: u64,
+    segment_data_pos: u64,
+    duration_pos: u64,
+    /// SeekHead entries (element ID, position in the Segment)
+    seek_entries: Vec<(u32, u64)>,
+    has_video: bool,
+    cluster: Option<OpenCluster>,
+    cues: Vec<CuePoint>,
+    end_ms: i64,
+    packets: u64,
+}
+
+impl<W: Write + Seek> MkvMuxer<W> {
+    /// Write the EBML header, Info and Tracks for `streams`
+    pub fn new(
+        mut writer: W,
+        streams: Vec<MuxStream>,
+        config: MkvMuxConfig,
+    ) -> Result<Self, String> {
+        if streams.is_empty() {
+            return Err("MKV muxer needs at least one stream".to_string());
+        }
+        if config.webm {
+            if let Some(stream) = streams.iter().find(|s| {
+                !matches!(
+                    s.codec.mkv_codec_id(),
+                    "V_VP8" | "V_VP9" | "V_AV1" | "A_OPUS" | "A_VORBIS"
+                )
+            }) {
+                return Err(format!("{:?} is not allowed in WebM", stream.codec));
+            }
+            if !config.attachments.is_empty() {
+                return Err("Attachments are not allowed in WebM".to_string());
+            }
+        }
+
+        let mut head = Vec::new();
+        put_master(&mut head, EBML, |e| {
+            put_uint(e, EBML_VERSION, 1);
+            put_uint(e, EBML_READ_VERSION, 1);
+            put_uint(e, EBML_MAX_ID_LENGTH, 4);
+            put_uint(e, EBML_MAX_SIZE_LENGTH, 8);
+            put_string(e, DOC_TYPE, if config.webm { "webm" } else { "matroska" });
+            put_uint(e, DOC_TYPE_VERSION, 4);
+            put_uint(e, DOC_TYPE_READ_VERSION, 2);
+        });
+        put_id(&mut head, SEGMENT);
+
+        let start = writer
+            .stream_position()
+            .map_err(|e| format!("Position error: {}", e))?;
+        let segment_size_pos = start + head.len() as u64;
+        head.extend_from_slice(&UNKNOWN_SIZE);
+        let segment_data_pos = start + head.len() as u64;
+        put_void(&mut head, SEEK_HEAD_RESERVED);
+        let mut seek_entries = Vec::new();
+        let segment_offset = |head: &Vec<u8>| start + head.len() as u64 - segment_data_pos;
+
+        // Info, remembering where the Duration float lands
+        let mut info = Vec::new();
+        put_uint(&mut info, TIMESTAMP_SCALE, 1_000_000);
+        put_string(&mut info, MUXING_APP, "SLAIN");
+        put_string(
+            &mut info,
+            WRITING_APP,
+            &format!("SLAIN {}", env!("CARGO_PKG_VERSION")),
+        );
+        if let Some(title) = &config.title {
+            put_string(&mut info, TITLE, title);
+        }
+        let duration_offset = info.len() + 3;
+        put_float(&mut info, DURATION, 0.0);
+        seek_entries.push((INFO, segment_offset(&head)));
+        put_element(&mut head, INFO, &info);
+        let duration_pos = start + (head.len() - info.len() + duration_offset) as u64;
+
+        seek_entries.push((TRACKS, segment_offset(&head)));
+        put_master(&mut head, TRACKS, |tracks| {
+            for (i, stream) in streams.iter().enumerate() {
+                put_master(tracks, TRACK_ENTRY, |t| write_track_entry(t, i, stream));
+            }
+        });
+
+        if !config.attachments.is_empty() {
+            seek_entries.push((ATTACHMENTS, segment_offset(&head)));
+            put_master(&mut head, ATTACHMENTS, |attachments| {
+                for (i, file) in config.attachments.iter().enumerate() {
+                    put_master(attachments, ATTACHED_FILE, |f| {
+                        if let Some(description) = &file.description {
+                            put_string(f, FILE_DESCRIPTION, description);
+                        }
+                        put_string(f, FILE_NAME, &file.filename);
+                        put_string(f, FILE_MEDIA_TYPE, &file.mime_type);
+                        put_element(f, FILE_DATA, &file.data);
+                        put_uint(f, FILE_UID, uid(i as u64 + 0x100));
+                    });
+                }
+            });
+        }
+
+        writer
+            .write_all(&head)
+            .map_err(|e| format!("Write error: {}", e))?;
+
+        let has_video = streams
+            .iter()
+            .any(|s| matches!(s.kind, MuxTrackKind::Video { .. }));
+        Ok(Self {
+            writer,
+            config,
+            streams,
+            segment_size_pos,
+            segment_data_pos,
+            duration_pos,
+            seek_entries,
+            has_video,
+            cluster: None,
+            cues: Vec::new(),
+            end_ms: 0,
+            packets: 0,
+        })
+    }
+
+    pub fn streams(&self) -> &[MuxStream] {
+        &self.streams
+    }
+
+    /// Replace the chapters written on `finish` (e.g. a recording that only
+    /// learns its programme boundaries as it goes)
+    pub fn set_chapters(&mut self, chapters: Vec<MkvChapter>) {
+        self.config.chapters = chapters;
+    }
+
+    /// Append a packet as a SimpleBlock (packets should arrive in decode order)
+    pub fn write_packet(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        let stream = self
+            .streams
+            .get(packet.stream)
+            .ok_or_else(|| format!("Unknown stream index {}", packet.stream))?;
+        let is_video = matches!(stream.kind, MuxTrackKind::Video { .. });
+        let frame_duration_us = stream.frame_duration_us;
+        let pts_ms = packet.pts_us.div_euclid(1000);
+
+        let split = match &self.cluster {
+            None => true,
+            Some(cluster) => {
+                let relative = pts_ms - cluster.timestamp_ms;
+                (is_video && packet.keyframe && !cluster.body.is_empty())
+                    || relative > i16::MAX as i64
+                    || relative < i16::MIN as i64
+                    || relative >= self.config.max_cluster_ms && (!is_video || packet.keyframe)
+            }
+        };
+        if split {
+            self.flush_cluster()?;
+            self.cluster = Some(OpenCluster {
+                timestamp_ms: pts_ms.max(0),
+                body: Vec::new(),
+                cues: Vec::new(),
+            });
+        }
+        let cluster = self.cluster.as_mut().expect("cluster opened above");
+        if cluster.body.is_empty() {
+            put_uint(
+                &mut cluster.body,
+                CLUSTER_TIMESTAMP,
+                cluster.timestamp_ms as u64,
+            );
+        }
+
+        let mut block = Vec::with_capacity(packet.data.len() + 4);
+        put_size(&mut block, packet.stream as u64 + 1);
+        block.extend_from_slice(&((pts_ms - cluster.timestamp_ms) as i16).to_be_bytes());
+        block.push(if packet.keyframe || !is_video {
+            0x80
+        } else {
+            0x00
+        });
+        block.extend_from_slice(&packet.data);
+
+        let index = if self.has_video {
+            is_video && packet.keyframe
+        } else {
+            cluster.cues.is_empty()
+        };
+        if index && self.config.cues {
+            cluster.cues.push((
+                pts_ms.max(0) as u64,
+                packet.stream as u64 + 1,
+                cluster.body.len() as u64,
+            ));
+        }
+        put_element(&mut cluster.body, SIMPLE_BLOCK, &block);
+
+        let duration_ms = packet
+            .duration_us
+            .map(|d| d.div_euclid(1000))
+            .or_else(|| frame_duration_us.map(|d| (d / 1000) as i64))
+            .unwrap_or(0);
+        self.end_ms = self.end_ms.max(pts_ms + duration_ms);
+        self.packets += 1;
+        Ok(())
+    }
+
+    fn flush_cluster(&mut self) -> Result<(), String> {
+        let Some(cluster) = self.cluster.take() else {
+            return Ok(());
+        };
+        let cluster_position = self
+            .writer
+            .stream_position()
+            .map_err(|e| format!("Position error: {}", e))?
+            - self.segment_data_pos;
+        self.cues.extend(
+            cluster
+                .cues
+                .iter()
+                .map(|&(time_ms, track, relative)| CuePoint {
+                    time_ms,
+                    track,
+                    cluster_position,
+                    relative_position: Some(relative),
+                    duration_ms: None,
+                }),
+        );
+        let mut out = Vec::with_capacity(cluster.body.len() + 12);
+        put_element(&mut out, CLUSTER, &cluster.body);
+        self.writer
+            .write_all(&out)
+            .map_err(|e| format!("Write error: {}", e))
+    }
+
+    /// Close the last cluster, write Cues and Chapters, patch the SeekHead,
+    /// Segment size and Duration, and hand back the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        self.flush_cluster()?;
+
+        let mut tail = Vec::new();
+        let tail_pos = self
+            .writer
+            .stream_position()
+            .map_err(|e| format!("Position error: {}", e))?
+            - self.segment_data_pos;
+        if !self.cues.is_empty() {
+            self.seek_entries.push((CUES, tail_pos));
+            put_master(&mut tail, CUES, |cues| {
+                for cue in &self.cues {
+                    put_master(cues, CUE_POINT, |point| {
+                        put_uint(point, CUE_TIME, cue.time_ms);
+                        put_master(point, CUE_TRACK_POSITIONS, |positions| {
+                            put_uint(positions, CUE_TRACK, cue.track);
+                            put_uint(positions, CUE_CLUSTER_POSITION, cue.cluster_position);
+                            if let Some(relative) = cue.relative_position {
+                                put_uint(positions, CUE_RELATIVE_POSITION, relative);
+                            }
+                        });
+                    });
+                }
+            });
+        }
+        if !self.config.chapters.is_empty() {
+            self.seek_entries
+                .push((CHAPTERS, tail_pos + tail.len() as u64));
+            put_master(&mut tail, CHAPTERS, |chapters| {
+                put_master(chapters, EDITION_ENTRY, |edition| {
+                    put_uint(edition, EDITION_UID, uid(0xED));
+                    put_uint(edition, EDITION_FLAG_DEFAULT, 1);
+                    for (i, chapter) in self.config.chapters.iter().enumerate() {
+                        put_chapter(edition, chapter, i as u64);
+                    }
+                });
+            });
+        }
+        self.writer
+            .write_all(&tail)
+            .map_err(|e| format!("Write error: {}", e))?;
+        let end = self
+            .writer
+            .stream_position()
+            .map_err(|e| format!("Position error: {}", e))?;
+
+        let mut seek_head = Vec::with_capacity(SEEK_HEAD_RESERVED);
+        put_master(&mut seek_head, SEEK_HEAD, |entries| {
+            for &(id, position) in &self.seek_entries {
+                put_master(entries, SEEK, |seek| {
+                    let mut id_bytes = Vec::new();
+                    put_id(&mut id_bytes, id);
+                    put_element(seek, SEEK_ID, &id_bytes);
+                    put_uint(seek, SEEK_POSITION, position);
+                });
+            }
+        });
+        let spare = SEEK_HEAD_RESERVED
+            .checked_sub(seek_head.len())
+            .filter(|spare| *spare == 0 || *spare >= 9)
+            .ok_or("SeekHead does not fit its reserved space")?;
+        if spare > 0 {
+            put_void(&mut seek_head, spare);
+        }
+        self.patch(self.segment_data_pos, &seek_head)?;
+
+        let segment_size = end - self.segment_data_pos;
+        let mut size = [0u8; 8];
+        size.copy_from_slice(&(segment_size | (1u64 << 56)).to_be_bytes());
+        self.patch(self.segment_size_pos, &size)?;
+        self.patch(
+            self.duration_pos,
+            &(self.end_ms.max(0) as f64).to_be_bytes(),
+        )?;
+        self.writer
+            .seek(SeekFrom::Start(end))
+            .map_err(|e| format!("Seek error: {}", e))?;
+
+        tracing::info!(
+            "MKV mux: {} packets, {} ms, {} bytes",
+            self.packets,
+            self.end_ms,
+            end
+        );
+        Ok(self.writer)
+    }
+
+    fn patch(&mut self, pos: u64, bytes: &[u8]) -> Result<(), String> {
+        self.writer
+            .seek(SeekFrom::Start(pos))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        self.writer
+            .write_all(bytes)
+            .map_err(|e| format!("Write error: {}", e))
+    }
+}
+
+fn write_track_entry(t: &mut Vec<u8>, index: usize, stream: &MuxStream) {
+    let number = index as u64 + 1;
+    put_uint(t, TRACK_NUMBER, number);
+    put_uint(t, TRACK_UID, uid(index as u64));
+    let track_type = match stream.kind {
+        MuxTrackKind::Video { .. } => 1,
+        MuxTrackKind::Audio { .. } => 2,
+        MuxTrackKind::Subtitle => 17,
+    };
+    put_uint(t, TRACK_TYPE, track_type);
+    put_uint(t, FLAG_ENABLED, 1);
+    put_uint(t, FLAG_DEFAULT, stream.default as u64);
+    if stream.forced {
+        put_uint(t, FLAG_FORCED, 1);
+    }
+    put_uint(t, FLAG_LACING, 0);
+    if let Some(duration_us) = stream.frame_duration_us {
+        put_uint(t, DEFAULT_DURATION, duration_us * 1000);
+    }
+    if let Some(name) = &stream.name {
+        put_string(t, NAME, name);
+    }
+    put_string(t, LANGUAGE, &stream.language);
+    put_string(t, CODEC_ID, stream.codec.mkv_codec_id());
+    if let Some(private) = &stream.codec_private {
+        put_element(t, CODEC_PRIVATE, private);
+    }
+    match &stream.kind {
+        MuxTrackKind::Video { width, height } => put_master(t, VIDEO, |v| {
+            put_uint(v, PIXEL_WIDTH, *width as u64);
+            put_uint(v, PIXEL_HEIGHT, *height as u64);
+        }),
+        MuxTrackKind::Audio {
+            sample_rate,
+            channels,
+            bit_depth,
+        } => put_master(t, AUDIO, |a| {
+            put_float(a, SAMPLING_FREQUENCY, *sample_rate as f64);
+            put_uint(a, CHANNELS, *channels as u64);
+            if let Some(bits) = bit_depth {
+                put_uint(a, BIT_DEPTH, *bits as u64);
+            }
+        }),
+        MuxTrackKind::Subtitle => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mkv::{MkvDemuxer, MkvParser, MkvTrack};
+    use crate::mux::MuxCodec;
+    use std::fs::File;
+    use std::io::{BufWriter, Read};
+
+    fn chapter(title: &str, start_ms: u64, end_ms: u64) -> MkvChapter {
+        MkvChapter {
+            uid: 0,
+            string_uid: None,
+            title: title.to_string(),
+            language: "eng".to_string(),
+            start_time_ms: start_ms,
+            end_time_ms: Some(end_ms),
+            hidden: false,
+            enabled: true,
+            nested: Vec::new(),
+        }
+    }
+
+    fn write_sample(path: &Path) {
+        let mut video = MuxStream::video(MuxCodec::H264, 320, 240)
+            .with_codec_private(vec![1, 0x64, 0, 0x1f, 0xff, 0xe1, 0, 0])
+            .with_language("jpn");
+        video.name = Some("Main".to_string());
+        video.frame_duration_us = Some(40_000);
+        let mut audio = MuxStream::audio(MuxCodec::Opus, 48000, 2)
+            .with_codec_private(b"OpusHead".to_vec())
+            .with_language("eng");
+        audio.default = false;
+        let mut subtitle = MuxStream::subtitle(MuxCodec::SubRip).with_language("ger");
+        subtitle.forced = true;
+
+        let config = MkvMuxConfig {
+            title: Some("Round trip".to_string()),
+            chapters: vec![chapter("Placeholder", 0, 1)],
+            attachments: vec![MkvMuxAttachment {
+                filename: "font.ttf".to_string(),
+                mime_type: "font/ttf".to_string(),
+                description: Some("Sign font".to_string()),
+                data: (0..=255u8).collect(),
+            }],
+            ..Default::default()
+        };
+        let file = BufWriter::new(File::create(path).unwrap());
+        let mut muxer = MkvMuxer::new(file, vec![video, audio, subtitle], config).unwrap();
+        for i in 0..50i64 {
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: i * 40_000,
+                    dts_us: None,
+                    duration_us: None,
+                    keyframe: i % 25 == 0,
+                    data: vec![i as u8; 16],
+                })
+                .unwrap();
+            if i % 2 == 0 {
+                muxer
+                    .write_packet(&MuxPacket {
+                        stream: 1,
+                        pts_us: i * 40_000,
+                        dts_us: None,
+                        duration_us: Some(80_000),
+                        keyframe: true,
+                        data: vec![0xA0; 8],
+                    })
+                    .unwrap();
+            }
+        }
+        muxer
+            .write_packet(&MuxPacket {
+                stream: 2,
+                pts_us: 1_500_000,
+                dts_us: None,
+                duration_us: Some(500_000),
+                keyframe: true,
+                data: b"Hello".to_vec(),
+            })
+            .unwrap();
+        // Chapters can still change while the packets are being written
+        let mut opening = chapter("Opening", 0, 1000);
+        opening.nested.push(chapter("Cold open", 0, 400));
+        muxer.set_chapters(vec![opening, chapter("Main", 1000, 2000)]);
+        muxer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_through_parser() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.mkv");
+        write_sample(&path);
+
+        let info = MkvParser::new().parse(&path).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Round trip"));
+        assert_eq!(info.duration_ms, 2000);
+        assert_eq!(info.tracks.len(), 3);
+        match &info.tracks[0] {
+            MkvTrack::Video(v) => {
+                assert_eq!(v.codec_id, "V_MPEG4/ISO/AVC");
+                assert_eq!((v.pixel_width, v.pixel_height), (320, 240));
+                assert_eq!(v.language, "jpn");
+                assert_eq!(v.name.as_deref(), Some("Main"));
+                assert!(v.default);
+                assert_eq!(v.codec_private.as_ref().unwrap()[0], 1);
+            }
+            other => panic!("expected video, got {:?}", other),
+        }
+        match &info.tracks[1] {
+            MkvTrack::Audio(a) => {
+                assert_eq!(a.codec_id, "A_OPUS");
+                assert_eq!(a.sample_rate, 48000.0);
+                assert_eq!(a.channels, 2);
+                assert_eq!(a.language, "eng");
+                assert!(!a.default);
+                assert_eq!(a.codec_private.as_deref(), Some(&b"OpusHead"[..]));
+            }
+            other => panic!("expected audio, got {:?}", other),
+        }
+        match &info.tracks[2] {
+            MkvTrack::Subtitle(s) => {
+                assert_eq!(s.codec_id, "S_TEXT/UTF8");
+                assert!(s.forced);
+                assert_eq!(s.language, "ger");
+            }
+            other => panic!("expected subtitle, got {:?}", other),
+        }
+
+        // Cues point at the clusters holding the two keyframes
+        assert!(info.has_cues);
+        let times: Vec<u64> = info.cues.iter().map(|c| c.time_ms).collect();
+        assert_eq!(times, vec![0, 1000]);
+        let mut file = File::open(&path).unwrap();
+        for cue in &info.cues {
+            assert_eq!(cue.track, 1);
+            // EBML header (fixed size here) + Segment ID + 8-byte size
+            let segment_data = 40 + 4 + 8;
+            file.seek(SeekFrom::Start(segment_data + cue.cluster_position))
+                .unwrap();
+            let mut id = [0u8; 4];
+            file.read_exact(&mut id).unwrap();
+            assert_eq!(u32::from_be_bytes(id), CLUSTER);
+        }
+
+        assert_eq!(info.chapters.len(), 2);
+        assert_eq!(info.chapters[0].title, "Opening");
+        assert_eq!(info.chapters[0].nested[0].title, "Cold open");
+        assert_eq!(info.chapters[0].nested[0].end_time_ms, Some(400));
+        assert_eq!(
+            (info.chapters[1].start_time_ms, info.chapters[1].end_time_ms),
+            (1000, Some(2000))
+        );
+
+        assert_eq!(info.attachments.len(), 1);
+        let attachment = &info.attachments[0];
+        assert_eq!(attachment.filename, "font.ttf");
+        assert_eq!(attachment.mime_type, "font/ttf");
+        assert_eq!(attachment.description.as_deref(), Some("Sign font"));
+        let data = MkvParser::new().read_attachment(&path, attachment).unwrap();
+        assert_eq!(data, (0..=255u8).collect::<Vec<_>>());
+
+        // Packets come back in order with their timestamps and flags
+        let mut demuxer = MkvDemuxer::open(&path, info).unwrap();
+        let packets: Vec<_> = std::iter::from_fn(|| demuxer.read_packet()).collect();
+        let video: Vec<_> = packets.iter().filter(|p| p.track_number == 1).collect();
+        assert_eq!(video.len(), 50);
+        assert_eq!(video[26].pts_ms, 26 * 40);
+        assert_eq!(video[26].data, vec![26u8; 16]);
+        assert!(video[25].keyframe && !video[26].keyframe);
+        assert_eq!(packets.iter().filter(|p| p.track_number == 2).count(), 25);
+        let subtitle = packets.iter().find(|p| p.track_number == 3).unwrap();
+        assert_eq!(
+            (subtitle.pts_ms, subtitle.data.as_slice()),
+            (1500, &b"Hello"[..])
+        );
+    }
+
+    #[test]
+    fn test_webm_rejects_foreign_codecs_and_attachments() {
+        let opus = MuxStream::audio(MuxCodec::Opus, 48000, 2);
+        let webm = MkvMuxConfig {
+            webm: true,
+            ..Default::default()
+        };
+        let h264 = MuxStream::video(MuxCodec::H264, 64, 64);
+        assert!(MkvMuxer::new(std::io::Cursor::new(Vec::new()), vec![h264], webm.clone()).is_err());
+
+        let with_attachment = MkvMuxConfig {
+            attachments: vec![MkvMuxAttachment {
+                filename: "a.txt".to_string(),
+                mime_type: "text/plain".to_string(),
+                description: None,
+                data: Vec::new(),
+            }],
+            ..webm.clone()
+        };
+        assert!(MkvMuxer::new(
+            std::io::Cursor::new(Vec::new()),
+            vec![opus.clone()],
+            with_attachment
+        )
+        .is_err());
+        assert!(MkvMuxer::new(std::io::Cursor::new(Vec::new()), vec![opus], webm).is_ok());
+    }
+
+    #[test]
+    fn test_size_vints() {
+        let mut out = Vec::new();
+        put_size(&mut out, 0);
+        put_size(&mut out, 126);
+        put_size(&mut out, 127);
+        put_size(&mut out, 16382);
+        assert_eq!(out, vec![0x80, 0xFE, 0x40, 0x7F, 0x7F, 0xFE]);
+
+        let mut void = Vec::new();
+        put_void(&mut void, 20);
+        assert_eq!(void.len(), 20);
+        assert_eq!(void[0], 0xEC);
+    }
+}
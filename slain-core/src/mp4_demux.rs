@@ -215,6 +215,16 @@ pub mod mp4 {
     const MDAT: u32 = 0x6D646174; // mdat
     const EDTS: u32 = 0x65647473; // edts
     const ELST: u32 = 0x656C7374; // elst
+    const ESDS: u32 = 0x65736473; // esds (MPEG-4 audio config)
+
+    // Movie fragments
+    const MVEX: u32 = 0x6D766578; // mvex
+    const TREX: u32 = 0x74726578; // trex
+    const MOOF: u32 = 0x6D6F6F66; // moof
+    const TRAF: u32 = 0x74726166; // traf
+    const TFHD: u32 = 0x74666864; // tfhd
+    const TFDT: u32 = 0x74666474; // tfdt
+    const TRUN: u32 = 0x7472756E; // trun
 
     // Video codec atoms
     const AVC1: u32 = 0x61766331; // avc1 (H.264)
@@ -238,6 +248,66 @@ pub mod mp4 {
         tracks: Vec<Track>,
         mdat_offset: u64,
         mdat_size: u64,
+        /// Fragment defaults per track ID from `trex` (duration, size, flags)
+        trex: HashMap<u32, (u32, u32, u32)>,
+    }
+
+    /// Child boxes of an in-memory box payload as (type, payload)
+    fn child_boxes(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut boxes = Vec::new();
+        while data.len() >= 8 {
+            let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            let kind = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+            if size < 8 || size > data.len() {
+                break;
+            }
+            boxes.push((kind, &data[8..size]));
+            data = &data[size..];
+        }
+        boxes
+    }
+
+    /// DecoderSpecificInfo (e.g. AudioSpecificConfig) from an `esds` payload
+    fn esds_decoder_config(data: &[u8]) -> Option<Vec<u8>> {
+        // Descriptor: tag, 1-4 byte expandable length, payload
+        fn descriptor(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+            let tag = *data.first()?;
+            let mut len = 0usize;
+            let mut pos = 1;
+            loop {
+                let b = *data.get(pos)?;
+                len = (len << 7) | (b & 0x7F) as usize;
+                pos += 1;
+                if b & 0x80 == 0 || pos == 5 {
+                    break;
+                }
+            }
+            let payload = data.get(pos..pos + len)?;
+            Some((tag, payload, &data[pos + len..]))
+        }
+
+        let (tag, es, _) = descriptor(data.get(4..)?)?; // skip version/flags
+        if tag != 0x03 {
+            return None;
+        }
+        let flags = *es.get(2)?;
+        let mut pos = 3;
+        if flags & 0x80 != 0 {
+            pos += 2; // depends_on_ES_ID
+        }
+        if flags & 0x40 != 0 {
+            pos += 1 + *es.get(pos)? as usize; // URL
+        }
+        if flags & 0x20 != 0 {
+            pos += 2; // OCR_ES_ID
+        }
+        let (tag, decoder_config, _) = descriptor(es.get(pos..)?)?;
+        if tag != 0x04 {
+            return None;
+        }
+        // objectTypeIndication, streamType, bufferSize, max/avg bitrate
+        let (tag, info, _) = descriptor(decoder_config.get(13..)?)?;
+        (tag == 0x05).then(|| info.to_vec())
     }
 
     impl<R: Read + Seek> Mp4Demuxer<R> {
@@ -249,6 +319,7 @@ pub mod mp4 {
                 tracks: Vec::new(),
                 mdat_offset: 0,
                 mdat_size: 0,
+                trex: HashMap::new(),
             };
             demuxer.parse_atoms()?;
             Ok(demuxer)
@@ -278,19 +349,148 @@ pub mod mp4 {
                     MDAT => {
                         self.mdat_offset = pos + 8;
                         self.mdat_size = size - 8;
-                        self.skip_bytes(size - 8)?;
                     }
-                    _ => {
-                        self.skip_bytes(size - 8)?;
+                    MOOF => {
+                        let mut moof = vec![0u8; size.saturating_sub(8) as usize];
+                        self.reader
+                            .read_exact(&mut moof)
+                            .map_err(|e| format!("Read error: {}", e))?;
+                        for (kind, traf) in child_boxes(&moof) {
+                            if kind == TRAF {
+                                self.parse_traf(pos, traf)?;
+                            }
+                        }
                     }
+                    _ => {}
                 }
 
+                // Seek past the box (its header may be 16 bytes)
                 pos += size;
+                self.reader
+                    .seek(SeekFrom::Start(pos))
+                    .map_err(|e| format!("Seek error: {}", e))?;
             }
 
             Ok(())
         }
 
+        /// Append the samples of one track fragment to its sample table
+        fn parse_traf(&mut self, moof_pos: u64, traf: &[u8]) -> Result<(), String> {
+            let boxes = child_boxes(traf);
+            let Some(&(_, mut tfhd)) = boxes.iter().find(|(kind, _)| *kind == TFHD) else {
+                return Ok(());
+            };
+            let flags = read_u32(&mut tfhd)? & 0x00FF_FFFF;
+            let track_id = read_u32(&mut tfhd)?;
+            let Some(track_idx) = self.tracks.iter().position(|t| t.id == track_id) else {
+                return Ok(());
+            };
+            let (mut duration, mut size, mut sample_flags) =
+                self.trex.get(&track_id).copied().unwrap_or_default();
+            // default-base-is-moof and the implicit base both start at the moof
+            let mut base = moof_pos;
+            if flags & 0x01 != 0 {
+                base = read_u64(&mut tfhd)?;
+            }
+            if flags & 0x02 != 0 {
+                read_u32(&mut tfhd)?; // sample_description_index
+            }
+            if flags & 0x08 != 0 {
+                duration = read_u32(&mut tfhd)?;
+            }
+            if flags & 0x10 != 0 {
+                size = read_u32(&mut tfhd)?;
+            }
+            if flags & 0x20 != 0 {
+                sample_flags = read_u32(&mut tfhd)?;
+            }
+
+            let table = &mut self.tracks[track_idx].sample_table;
+            if let Some(&(_, mut tfdt)) = boxes.iter().find(|(kind, _)| *kind == TFDT) {
+                let version = read_u8(&mut tfdt)?;
+                tfdt = tfdt.get(3..).unwrap_or_default();
+                let decode_time = if version == 1 {
+                    read_u64(&mut tfdt)?
+                } else {
+                    read_u32(&mut tfdt)? as u64
+                };
+                let end = table.decode_end();
+                if decode_time > end {
+                    table.extend_last_duration((decode_time - end) as u32);
+                }
+            }
+
+            let mut next_offset = base;
+            for (_, mut trun) in boxes.into_iter().filter(|(kind, _)| *kind == TRUN) {
+                let flags = read_u32(&mut trun)? & 0x00FF_FFFF;
+                let count = read_u32(&mut trun)?;
+                if flags & 0x001 != 0 {
+                    next_offset = base.wrapping_add_signed(read_u32(&mut trun)? as i32 as i64);
+                }
+                let first_flags = if flags & 0x004 != 0 {
+                    Some(read_u32(&mut trun)?)
+                } else {
+                    None
+                };
+                for i in 0..count {
+                    let sample_duration = if flags & 0x100 != 0 {
+                        read_u32(&mut trun)?
+                    } else {
+                        duration
+                    };
+                    let sample_size = if flags & 0x200 != 0 {
+                        read_u32(&mut trun)?
+                    } else {
+                        size
+                    };
+                    let mut this_flags = if flags & 0x400 != 0 {
+                        read_u32(&mut trun)?
+                    } else {
+                        sample_flags
+                    };
+                    if i == 0 {
+                        this_flags = first_flags.unwrap_or(this_flags);
+                    }
+                    let composition_offset = if flags & 0x800 != 0 {
+                        read_u32(&mut trun)? as i32
+                    } else {
+                        0
+                    };
+                    table.push_fragment_sample(
+                        next_offset,
+                        sample_size,
+                        sample_duration,
+                        this_flags & 0x0001_0000 == 0, // sample_is_non_sync_sample
+                        composition_offset,
+                    );
+                    next_offset += sample_size as u64;
+                }
+            }
+
+            Ok(())
+        }
+
+        fn parse_mvex(&mut self, size: u64) -> Result<(), String> {
+            let mut mvex = vec![0u8; size as usize];
+            self.reader
+                .read_exact(&mut mvex)
+                .map_err(|e| format!("Read error: {}", e))?;
+            for (kind, mut trex) in child_boxes(&mvex) {
+                if kind == TREX && trex.len() >= 24 {
+                    trex = &trex[4..]; // version + flags
+                    let track_id = read_u32(&mut trex)?;
+                    read_u32(&mut trex)?; // default_sample_description_index
+                    let defaults = (
+                        read_u32(&mut trex)?,
+                        read_u32(&mut trex)?,
+                        read_u32(&mut trex)?,
+                    );
+                    self.trex.insert(track_id, defaults);
+                }
+            }
+            Ok(())
+        }
+
         fn read_atom_header(&mut self) -> Result<(u64, u32), String> {
             let header: BoxHeader = read_box_header(&mut self.reader)?;
             let atom_type = u32::from_be_bytes(header.box_type);
@@ -351,6 +551,7 @@ pub mod mp4 {
                 match atom_type {
                     MVHD => self.parse_mvhd(atom_size - 8)?,
                     TRAK => self.parse_trak(atom_size - 8)?,
+                    MVEX => self.parse_mvex(atom_size - 8)?,
                     _ => self.skip_bytes(atom_size - 8)?,
                 }
             }
@@ -374,8 +575,8 @@ pub mod mp4 {
                 self.duration = self.read_u32()? as u64;
             }
 
-            // Skip rest
-            let remaining = if version == 1 { size - 28 } else { size - 16 };
+            // Skip rest (version/flags + the fields read above)
+            let remaining = if version == 1 { size - 32 } else { size - 20 };
             self.skip_bytes(remaining)?;
 
             Ok(())
@@ -406,6 +607,7 @@ pub mod mp4 {
                 duration: 0,
                 sample_table: SampleTable::default(),
                 current_sample: 0,
+                media_start: 0,
             };
 
             while self.reader.stream_position().unwrap_or(end_pos) < end_pos {
@@ -414,6 +616,7 @@ pub mod mp4 {
                 match atom_type {
                     TKHD => self.parse_tkhd(&mut track, atom_size - 8)?,
                     MDIA => self.parse_mdia(&mut track, atom_size - 8)?,
+                    EDTS => self.parse_edts(&mut track, atom_size - 8)?,
                     _ => self.skip_bytes(atom_size - 8)?,
                 }
             }
@@ -448,12 +651,42 @@ pub mod mp4 {
                 track.duration = self.read_u32()? as u64;
             }
 
-            let remaining = if version == 1 { size - 32 } else { size - 20 };
+            let remaining = if version == 1 { size - 36 } else { size - 24 };
             self.skip_bytes(remaining)?;
 
             Ok(())
         }
 
+        /// Media time of the first non-empty edit (empty edits are ignored)
+        fn parse_edts(&mut self, track: &mut Track, size: u64) -> Result<(), String> {
+            let mut edts = vec![0u8; size as usize];
+            self.reader
+                .read_exact(&mut edts)
+                .map_err(|e| format!("Read error: {}", e))?;
+            let Some((_, mut elst)) = child_boxes(&edts).into_iter().find(|(k, _)| *k == ELST)
+            else {
+                return Ok(());
+            };
+            let version = read_u8(&mut elst)?;
+            elst = elst.get(3..).unwrap_or_default();
+            let entry_count = read_u32(&mut elst)?;
+            for _ in 0..entry_count {
+                let media_time = if version == 1 {
+                    read_u64(&mut elst)?; // segment_duration
+                    read_u64(&mut elst)? as i64
+                } else {
+                    read_u32(&mut elst)?;
+                    read_u32(&mut elst)? as i32 as i64
+                };
+                read_u32(&mut elst)?; // media_rate
+                if media_time >= 0 {
+                    track.media_start = media_time;
+                    break;
+                }
+            }
+            Ok(())
+        }
+
         fn parse_mdia(&mut self, track: &mut Track, size: u64) -> Result<(), String> {
             let end_pos = self
                 .reader
@@ -499,7 +732,7 @@ pub mod mp4 {
             track.stream_info.language =
                 Some(format!("{}{}{}", c1 as char, c2 as char, c3 as char));
 
-            let remaining = if version == 1 { size - 26 } else { size - 18 };
+            let remaining = if version == 1 { size - 34 } else { size - 22 };
             self.skip_bytes(remaining)?;
 
             Ok(())
@@ -518,7 +751,7 @@ pub mod mp4 {
                 _ => CodecType::Unknown,
             };
 
-            self.skip_bytes(size - 8)?;
+            self.skip_bytes(size - 12)?;
             Ok(())
         }
 
@@ -674,11 +907,33 @@ pub mod mp4 {
                         // 4 bytes sample_rate (16.16 fixed)
                         self.skip_bytes(6)?; // reserved
                         self.skip_bytes(2)?; // data_reference_index
-                        self.skip_bytes(8)?; // reserved
+                        let version = self.read_u16()?; // QuickTime sound version
+                        self.skip_bytes(6)?; // reserved
                         let channels = self.read_u16()?;
                         let bits = self.read_u16()?;
                         self.skip_bytes(4)?; // pre_defined, reserved
                         let sample_rate = self.read_u32()? >> 16;
+                        match version {
+                            1 => self.skip_bytes(16)?,
+                            2 => self.skip_bytes(36)?,
+                            _ => {}
+                        }
+
+                        // AudioSpecificConfig from esds
+                        let current_pos = self.reader.stream_position().unwrap_or(entry_data_end);
+                        if codec_fourcc == MP4A && current_pos < entry_data_end {
+                            let mut ext = vec![0u8; (entry_data_end - current_pos) as usize];
+                            self.reader
+                                .read_exact(&mut ext)
+                                .map_err(|e| format!("Read error: {}", e))?;
+                            if let Some(config) = child_boxes(&ext)
+                                .into_iter()
+                                .find(|(kind, _)| *kind == ESDS)
+                                .and_then(|(_, esds)| esds_decoder_config(esds))
+                            {
+                                track.stream_info.extra_data = config;
+                            }
+                        }
 
                         track.audio_info = Some(AudioInfo {
                             sample_rate,
@@ -859,17 +1114,22 @@ pub mod mp4 {
                 track
                     .sample_table
                     .keyframes
-                    .contains(&(sample_idx as u32 + 1))
+                    .binary_search(&(sample_idx as u32 + 1))
+                    .is_ok()
             };
+            let timescale = track.timescale.max(1) as i64;
+            let media_start_us = track.media_start * 1_000_000 / timescale;
+            let composition_us =
+                track.sample_table.composition_offset(sample_idx) as i64 * 1_000_000 / timescale;
 
             // Read data
             self.reader.seek(SeekFrom::Start(offset)).ok()?;
             let mut data = vec![0u8; size as usize];
             self.reader.read_exact(&mut data).ok()?;
 
-            // Calculate PTS/DTS
-            let pts = best_time;
-            let dts = best_time; // Simplified - should use ctts
+            // DTS from stts, PTS adds ctts; both shifted so the edit starts at 0
+            let dts = best_time - media_start_us;
+            let pts = dts + composition_us;
 
             // Advance to next sample
             self.tracks[track_idx].current_sample += 1;
@@ -885,7 +1145,7 @@ pub mod mp4 {
         }
 
         fn calculate_sample_time(&self, track: &Track, sample_idx: usize) -> i64 {
-            let mut time = 0i64;
+            let mut time = track.sample_table.base_decode_time as i64;
             let mut sample = 0usize;
 
             for &(count, delta) in &track.sample_table.time_to_sample {
@@ -969,7 +1229,10 @@ pub mod mp4 {
         }
 
         fn find_sample_for_time_static(track: &Track, timestamp_us: i64) -> usize {
-            let target_time = timestamp_us * (track.timescale as i64) / 1_000_000;
+            let target_time = (timestamp_us * (track.timescale as i64) / 1_000_000
+                + track.media_start
+                - track.sample_table.base_decode_time as i64)
+                .max(0);
 
             let mut time = 0i64;
             let mut sample = 0usize;
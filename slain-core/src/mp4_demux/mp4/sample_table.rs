@@ -8,4 +8,91 @@ pub struct SampleTable {
     pub time_to_sample: Vec<(u32, u32)>,       // sample_count, sample_delta
     pub keyframes: Vec<u32>,                   // Sample numbers that are keyframes
     pub composition_offsets: Vec<(u32, i32)>,  // sample_count, offset
+    pub base_decode_time: u64,                 // tfdt of the first fragment of an empty moov
+}
+
+fn push_run<T: Copy + PartialEq>(runs: &mut Vec<(u32, T)>, value: T) {
+    match runs.last_mut() {
+        Some((count, last)) if *last == value => *count += 1,
+        _ => runs.push((1, value)),
+    }
+}
+
+impl SampleTable {
+    /// Decode time at the end of the last sample, in the track timescale
+    pub fn decode_end(&self) -> u64 {
+        self.base_decode_time
+            + self
+                .time_to_sample
+                .iter()
+                .map(|&(count, delta)| count as u64 * delta as u64)
+                .sum::<u64>()
+    }
+
+    /// Stretch the last sample so the next one starts `gap` ticks later
+    pub fn extend_last_duration(&mut self, gap: u32) {
+        match self.time_to_sample.last_mut() {
+            Some((1, delta)) => *delta += gap,
+            Some((count, delta)) => {
+                let delta = *delta;
+                *count -= 1;
+                self.time_to_sample.push((1, delta + gap));
+            }
+            None => self.base_decode_time += gap as u64,
+        }
+    }
+
+    /// Append a sample from a movie fragment as a chunk of its own
+    pub fn push_fragment_sample(
+        &mut self,
+        offset: u64,
+        size: u32,
+        duration: u32,
+        sync: bool,
+        composition_offset: i32,
+    ) {
+        let number = self.sample_sizes.len() as u32 + 1;
+        if self
+            .sample_to_chunk
+            .last()
+            .is_none_or(|&(_, per_chunk, _)| per_chunk != 1)
+        {
+            self.sample_to_chunk
+                .push((self.chunk_offsets.len() as u32 + 1, 1, 1));
+        }
+        self.chunk_offsets.push(offset);
+        self.sample_sizes.push(size);
+        push_run(&mut self.time_to_sample, duration);
+
+        // An empty keyframe list means every sample so far was sync
+        if !sync && self.keyframes.is_empty() {
+            self.keyframes = (1..number).collect();
+        } else if sync && !self.keyframes.is_empty() {
+            self.keyframes.push(number);
+        }
+
+        if composition_offset != 0 || !self.composition_offsets.is_empty() {
+            let covered: u32 = self
+                .composition_offsets
+                .iter()
+                .map(|&(count, _)| count)
+                .sum();
+            if covered + 1 < number {
+                self.composition_offsets.push((number - 1 - covered, 0));
+            }
+            push_run(&mut self.composition_offsets, composition_offset);
+        }
+    }
+
+    /// Composition offset of a sample (0-based), in the track timescale
+    pub fn composition_offset(&self, sample_idx: usize) -> i32 {
+        let mut sample = 0usize;
+        for &(count, offset) in &self.composition_offsets {
+            sample += count as usize;
+            if sample > sample_idx {
+                return offset;
+            }
+        }
+        0
+    }
 }
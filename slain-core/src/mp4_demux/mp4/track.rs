@@ -12,4 +12,6 @@ pub struct Track {
     pub duration: u64,
     pub sample_table: SampleTable,
     pub current_sample: usize,
+    /// Media time presented first (edit list), subtracted from timestamps
+    pub media_start: i64,
 }
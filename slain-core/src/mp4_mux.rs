@@ -0,0 +1,1277 @@
+//! MP4 (ISO BMFF) writer
+//!
+//! Progressive layout: `ftyp`, one `mdat` that packets are appended to as they
+//! arrive, and `moov` written at the end with per-track sample tables
+//! (stts/ctts/stss/stsc/stsz/stco). [`faststart`] moves that `moov` in front of
+//! the `mdat` afterwards so players can start before the whole file is read.
+//!
+//! Fragmented layout (`fragment_duration_ms`): `ftyp`, a `moov` with empty
+//! sample tables plus `mvex`, then a `moof`/`mdat` pair per fragment, cut on
+//! video keyframes.
+//!
+//! Codec configuration records from the stream's `codec_private` go into the
+//! sample entry as-is (avcC, hvcC, av1C, vpcC, dOps, dfLa, dac3/dec3); AAC/MP3
+//! get an `esds` wrapped around them. H.264/H.265 given as Annex B (encoder
+//! output) get their avcC/hvcC built from the SPS/PPS and samples rewritten
+//! with length prefixes.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::h264_utils;
+use crate::mux::{MuxCodec, MuxPacket, MuxStream, MuxTrackKind};
+
+const MOVIE_TIMESCALE: u32 = 1000;
+
+// ============================================================================
+// Box Encoding
+// ============================================================================
+
+fn put_box(out: &mut Vec<u8>, kind: &[u8; 4], build: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(kind);
+    build(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn put_full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    build: impl FnOnce(&mut Vec<u8>),
+) {
+    put_box(out, kind, |b| {
+        b.extend_from_slice(&((version as u32) << 24 | (flags & 0x00FF_FFFF)).to_be_bytes());
+        build(b);
+    });
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+/// Identity transformation matrix used by mvhd/tkhd
+fn put_matrix(out: &mut Vec<u8>) {
+    for v in [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        put_u32(out, v);
+    }
+}
+
+/// ISO 639-2/T code packed into 15 bits
+fn packed_language(language: &str) -> u16 {
+    let bytes = language.as_bytes();
+    if bytes.len() != 3 || !bytes.iter().all(|b| b.is_ascii_lowercase()) {
+        return packed_language("und");
+    }
+    bytes
+        .iter()
+        .fold(0u16, |acc, &b| (acc << 5) | (b - 0x60) as u16)
+}
+
+/// MPEG-4 descriptor: tag, 4-byte expandable length, payload
+fn put_descriptor(out: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    out.push(tag);
+    let len = payload.len() as u32;
+    out.extend_from_slice(&[
+        0x80 | ((len >> 21) & 0x7F) as u8,
+        0x80 | ((len >> 14) & 0x7F) as u8,
+        0x80 | ((len >> 7) & 0x7F) as u8,
+        (len & 0x7F) as u8,
+    ]);
+    out.extend_from_slice(payload);
+}
+
+/// `esds` for AAC (object type 0x40) or MP3 (0x6B)
+fn put_esds(out: &mut Vec<u8>, track_id: u32, object_type: u8, config: Option<&[u8]>) {
+    put_full_box(out, b"esds", 0, 0, |b| {
+        let mut decoder_config = vec![object_type, 0x15]; // audio stream
+        decoder_config.extend_from_slice(&[0, 0, 0]); // buffer size
+        decoder_config.extend_from_slice(&0u32.to_be_bytes()); // max bitrate
+        decoder_config.extend_from_slice(&0u32.to_be_bytes()); // avg bitrate
+        if let Some(config) = config {
+            put_descriptor(&mut decoder_config, 0x05, config);
+        }
+
+        let mut es = Vec::new();
+        es.extend_from_slice(&(track_id as u16).to_be_bytes());
+        es.push(0); // no dependency/URL/OCR
+        put_descriptor(&mut es, 0x04, &decoder_config);
+        put_descriptor(&mut es, 0x06, &[0x02]); // SLConfig: predefined MP4
+        put_descriptor(b, 0x03, &es);
+    });
+}
+
+// ============================================================================
+// Muxer
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct Mp4MuxConfig {
+    /// Media timescale for video tracks (audio uses its sample rate)
+    pub video_timescale: u32,
+    /// Write `moof`/`mdat` fragments of at least this length instead of a
+    /// single `mdat` (each fragment starts on a video keyframe)
+    pub fragment_duration_ms: Option<u32>,
+    /// Decode time (µs) that fragment timestamps count from. Separately muxed
+    /// segments of one stream (HLS/DASH) share it; by default it is the first
+    /// fragment's earliest decode time.
+    pub fragment_decode_origin_us: Option<i64>,
+}
+
+impl Default for Mp4MuxConfig {
+    fn default() -> Self {
+        Self {
+            video_timescale: 90_000,
+            fragment_duration_ms: None,
+            fragment_decode_origin_us: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    offset: u64,
+    size: u32,
+    pts_us: i64,
+    dts_us: Option<i64>,
+    duration_us: Option<i64>,
+    keyframe: bool,
+}
+
+struct TrackState {
+    stream: MuxStream,
+    timescale: u32,
+    /// Configuration record for the sample entry
+    config: Option<Vec<u8>>,
+    /// H.264/H.265 packets arrive as Annex B and need length prefixes
+    annexb: bool,
+    /// Samples written so far (progressive) or of the open fragment
+    samples: Vec<Sample>,
+    /// Sample data of the open fragment
+    pending: Vec<u8>,
+}
+
+/// avcC/hvcC built from the parameter sets in Annex B data
+fn parameter_set_config(codec: MuxCodec, annexb: &[u8]) -> Option<Vec<u8>> {
+    match codec {
+        MuxCodec::H264 => h264_utils::build_avcc_extradata(annexb),
+        MuxCodec::H265 => h264_utils::build_hvcc_extradata(annexb),
+        _ => None,
+    }
+}
+
+/// Durations, composition offsets and edit of a track, in its timescale
+#[derive(Debug, Default, PartialEq)]
+struct TrackTiming {
+    /// Movie-relative start of the first presented sample (empty edit)
+    lead: i64,
+    /// Media time of the first presented sample (reorder delay)
+    presentation_start: i64,
+    durations: Vec<u32>,
+    composition_offsets: Vec<i32>,
+}
+
+impl TrackTiming {
+    fn media_duration(&self) -> u64 {
+        self.durations.iter().map(|&d| d as u64).sum()
+    }
+}
+
+fn to_ticks(us: i64, timescale: u32) -> i64 {
+    (us as i128 * timescale as i128).div_euclid(1_000_000) as i64
+}
+
+fn to_movie(ticks: u64, timescale: u32) -> u64 {
+    ticks * MOVIE_TIMESCALE as u64 / timescale as u64
+}
+
+impl TrackState {
+    /// Decode timestamps in µs, derived from the reordered PTS when the
+    /// packets did not carry any (shifted back so no sample decodes after it
+    /// is presented)
+    fn decode_times_us(&self) -> Vec<i64> {
+        if self.samples.iter().all(|s| s.dts_us.is_some()) {
+            return self.samples.iter().map(|s| s.dts_us.unwrap_or(0)).collect();
+        }
+        let mut sorted: Vec<i64> = self.samples.iter().map(|s| s.pts_us).collect();
+        sorted.sort_unstable();
+        let delay = sorted
+            .iter()
+            .zip(&self.samples)
+            .map(|(dts, s)| dts - s.pts_us)
+            .max()
+            .unwrap_or(0)
+            .max(0);
+        sorted.iter().map(|dts| dts - delay).collect()
+    }
+
+    fn first_pts_us(&self) -> Option<i64> {
+        self.samples.iter().map(|s| s.pts_us).min()
+    }
+
+    /// Timing of this track when the movie starts at `origin_us`
+    fn timing(&self, origin_us: i64) -> TrackTiming {
+        let dts_us = self.decode_times_us();
+        let Some(first_pts) = self.first_pts_us() else {
+            return TrackTiming::default();
+        };
+        let dts: Vec<i64> = dts_us
+            .iter()
+            .map(|&t| to_ticks(t, self.timescale))
+            .collect();
+        let mut durations: Vec<u32> = dts
+            .windows(2)
+            .map(|w| (w[1] - w[0]).max(0) as u32)
+            .collect();
+        let last = self
+            .samples
+            .last()
+            .and_then(|s| s.duration_us)
+            .or(self.stream.frame_duration_us.map(|d| d as i64))
+            .map(|d| to_ticks(d, self.timescale) as u32)
+            .or(durations.last().copied())
+            .unwrap_or(0);
+        durations.push(last);
+        let composition_offsets = self
+            .samples
+            .iter()
+            .zip(&dts)
+            .map(|(s, &d)| (to_ticks(s.pts_us, self.timescale) - d) as i32)
+            .collect();
+        TrackTiming {
+            lead: to_ticks(first_pts - origin_us, self.timescale).max(0),
+            presentation_start: to_ticks(first_pts, self.timescale) - dts[0],
+            durations,
+            composition_offsets,
+        }
+    }
+}
+
+pub struct Mp4Muxer<W: Write + Seek> {
+    writer: W,
+    config: Mp4MuxConfig,
+    tracks: Vec<TrackState>,
+    /// Start of the 8-byte `free` placeholder in front of the `mdat` header
+    mdat_pos: u64,
+    /// End of the data written so far
+    mdat_end: u64,
+    /// Decode and presentation origin (µs) of a fragmented file, fixed when
+    /// the first fragment is written together with the `moov`
+    fragment_origin: Option<(i64, i64)>,
+    fragment_start_us: Option<i64>,
+    sequence: u32,
+}
+
+impl<W: Write + Seek> Mp4Muxer<W> {
+    /// Write `ftyp` and open the `mdat`
+    pub fn new(
+        mut writer: W,
+        streams: Vec<MuxStream>,
+        config: Mp4MuxConfig,
+    ) -> Result<Self, String> {
+        if streams.is_empty() {
+            return Err("MP4 muxer needs at least one stream".to_string());
+        }
+        let mut tracks = Vec::with_capacity(streams.len());
+        for stream in streams {
+            let timescale = match (&stream.kind, stream.codec) {
+                (
+                    MuxTrackKind::Video { .. },
+                    MuxCodec::H264 | MuxCodec::H265 | MuxCodec::VP9 | MuxCodec::AV1,
+                ) => config.video_timescale,
+                (
+                    MuxTrackKind::Audio { sample_rate, .. },
+                    MuxCodec::AAC
+                    | MuxCodec::MP3
+                    | MuxCodec::AC3
+                    | MuxCodec::EAC3
+                    | MuxCodec::Opus
+                    | MuxCodec::FLAC,
+                ) => {
+                    if *sample_rate > 0 {
+                        *sample_rate
+                    } else {
+                        48_000
+                    }
+                }
+                _ => return Err(format!("{:?} cannot be muxed into MP4", stream.codec)),
+            };
+            let annexb = matches!(stream.codec, MuxCodec::H264 | MuxCodec::H265)
+                && stream
+                    .codec_private
+                    .as_deref()
+                    .is_none_or(|c| c.first() != Some(&1));
+            let config = if annexb {
+                stream
+                    .codec_private
+                    .as_deref()
+                    .and_then(|c| parameter_set_config(stream.codec, c))
+            } else {
+                stream.codec_private.clone()
+            };
+            tracks.push(TrackState {
+                stream,
+                timescale,
+                config,
+                annexb,
+                samples: Vec::new(),
+                pending: Vec::new(),
+            });
+        }
+
+        let fragmented = config.fragment_duration_ms.is_some();
+        let mut head = Vec::new();
+        put_box(&mut head, b"ftyp", |b| {
+            b.extend_from_slice(b"isom");
+            put_u32(b, 0x200);
+            let brands = if fragmented {
+                [b"isom", b"iso6", b"avc1", b"mp41"]
+            } else {
+                [b"isom", b"iso2", b"avc1", b"mp41"]
+            };
+            for brand in brands {
+                b.extend_from_slice(brand);
+            }
+        });
+        let start = writer
+            .stream_position()
+            .map_err(|e| format!("Position error: {}", e))?;
+        let mdat_pos = start + head.len() as u64;
+        if !fragmented {
+            // Room to turn the mdat header into a 64-bit one if it outgrows 4 GiB
+            put_box(&mut head, b"free", |_| {});
+            head.extend_from_slice(&[0, 0, 0, 0]);
+            head.extend_from_slice(b"mdat");
+        }
+        writer
+            .write_all(&head)
+            .map_err(|e| format!("Write error: {}", e))?;
+
+        Ok(Self {
+            writer,
+            config,
+            tracks,
+            mdat_pos,
+            mdat_end: start + head.len() as u64,
+            fragment_origin: None,
+            fragment_start_us: None,
+            sequence: 0,
+        })
+    }
+
+    /// Append a packet to the `mdat` (packets must arrive in decode order per stream)
+    pub fn write_packet(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        let track = self
+            .tracks
+            .get(packet.stream)
+            .ok_or_else(|| format!("Unknown stream index {}", packet.stream))?;
+        let is_video = matches!(track.stream.kind, MuxTrackKind::Video { .. });
+
+        if let Some(fragment_ms) = self.config.fragment_duration_ms {
+            let time_us = packet.dts_us.unwrap_or(packet.pts_us);
+            let cuts_here = if is_video {
+                packet.keyframe
+            } else {
+                !self
+                    .tracks
+                    .iter()
+                    .any(|t| matches!(t.stream.kind, MuxTrackKind::Video { .. }))
+            };
+            if cuts_here
+                && self
+                    .fragment_start_us
+                    .is_some_and(|start| time_us - start >= fragment_ms as i64 * 1000)
+            {
+                self.flush_fragment()?;
+            }
+            self.fragment_start_us.get_or_insert(time_us);
+        }
+
+        let track = &mut self.tracks[packet.stream];
+        let converted;
+        let data = if track.annexb && h264_utils::is_annexb(&packet.data) {
+            if track.config.is_none() {
+                track.config = parameter_set_config(track.stream.codec, &packet.data);
+            }
+            converted = h264_utils::annexb_to_avcc(&packet.data);
+            &converted
+        } else {
+            &packet.data
+        };
+        let size = u32::try_from(data.len()).map_err(|_| "Sample too large for MP4")?;
+        let offset = if self.config.fragment_duration_ms.is_some() {
+            track.pending.extend_from_slice(data);
+            (track.pending.len() - data.len()) as u64
+        } else {
+            self.writer
+                .write_all(data)
+                .map_err(|e| format!("Write error: {}", e))?;
+            self.mdat_end += size as u64;
+            self.mdat_end - size as u64
+        };
+        track.samples.push(Sample {
+            offset,
+            size,
+            pts_us: packet.pts_us,
+            dts_us: packet.dts_us,
+            duration_us: packet.duration_us,
+            keyframe: packet.keyframe || !is_video,
+        });
+        Ok(())
+    }
+
+    /// Close the `mdat`, append `moov` and hand back the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        if self.config.fragment_duration_ms.is_some() {
+            self.flush_fragment()?;
+            if self.fragment_origin.is_none() {
+                // Nothing was written: still leave a valid (empty) movie
+                self.fragment_origin = Some((0, 0));
+                let moov = self.build_moov();
+                self.write_all(&moov)?;
+            }
+            tracing::info!(
+                "MP4 mux: {} tracks, {} fragments, {} bytes",
+                self.tracks.len(),
+                self.sequence,
+                self.mdat_end
+            );
+            return Ok(self.writer);
+        }
+
+        let mdat_size = self.mdat_end - (self.mdat_pos + 8);
+        let header = if mdat_size <= u32::MAX as u64 {
+            let mut h = vec![0, 0, 0, 8];
+            h.extend_from_slice(b"free");
+            h.extend_from_slice(&(mdat_size as u32).to_be_bytes());
+            h.extend_from_slice(b"mdat");
+            h
+        } else {
+            let mut h = 1u32.to_be_bytes().to_vec();
+            h.extend_from_slice(b"mdat");
+            h.extend_from_slice(&(mdat_size + 8).to_be_bytes());
+            h
+        };
+        self.writer
+            .seek(SeekFrom::Start(self.mdat_pos))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        self.writer
+            .write_all(&header)
+            .map_err(|e| format!("Write error: {}", e))?;
+        self.writer
+            .seek(SeekFrom::Start(self.mdat_end))
+            .map_err(|e| format!("Seek error: {}", e))?;
+
+        let moov = self.build_moov();
+        self.writer
+            .write_all(&moov)
+            .map_err(|e| format!("Write error: {}", e))?;
+
+        tracing::info!(
+            "MP4 mux: {} tracks, {} samples, {} bytes",
+            self.tracks.len(),
+            self.tracks.iter().map(|t| t.samples.len()).sum::<usize>(),
+            self.mdat_end + moov.len() as u64
+        );
+        Ok(self.writer)
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(data)
+            .map_err(|e| format!("Write error: {}", e))?;
+        self.mdat_end += data.len() as u64;
+        Ok(())
+    }
+
+    /// Write the open fragment as `moof` + `mdat` (and the `moov` before the
+    /// first one)
+    fn flush_fragment(&mut self) -> Result<(), String> {
+        self.fragment_start_us = None;
+        if self.tracks.iter().all(|t| t.samples.is_empty()) {
+            return Ok(());
+        }
+
+        let (decode_origin, _) = match self.fragment_origin {
+            Some(origin) => origin,
+            None => {
+                let decode = self.config.fragment_decode_origin_us.unwrap_or_else(|| {
+                    self.tracks
+                        .iter()
+                        .filter_map(|t| t.decode_times_us().first().copied())
+                        .min()
+                        .unwrap_or(0)
+                });
+                let presentation = self
+                    .tracks
+                    .iter()
+                    .filter_map(|t| t.first_pts_us())
+                    .min()
+                    .unwrap_or(0);
+                self.fragment_origin = Some((decode, presentation));
+                let moov = self.build_moov();
+                self.write_all(&moov)?;
+                (decode, presentation)
+            }
+        };
+
+        self.sequence += 1;
+        let trafs: Vec<(usize, TrackTiming, u64)> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !t.samples.is_empty())
+            .map(|(i, t)| {
+                let first_dts = t.decode_times_us()[0];
+                let base = to_ticks(first_dts - decode_origin, t.timescale).max(0) as u64;
+                (i, t.timing(0), base)
+            })
+            .collect();
+        let data_len: u64 = trafs
+            .iter()
+            .map(|(i, _, _)| self.tracks[*i].pending.len() as u64)
+            .sum();
+        let mdat_header: Vec<u8> = if data_len + 8 <= u32::MAX as u64 {
+            let mut h = ((data_len + 8) as u32).to_be_bytes().to_vec();
+            h.extend_from_slice(b"mdat");
+            h
+        } else {
+            let mut h = 1u32.to_be_bytes().to_vec();
+            h.extend_from_slice(b"mdat");
+            h.extend_from_slice(&(data_len + 16).to_be_bytes());
+            h
+        };
+
+        // Sizes don't depend on the data offsets, so measure first
+        let moof_len = self.build_moof(&trafs, 0).len() as u64;
+        let moof = self.build_moof(&trafs, moof_len + mdat_header.len() as u64);
+        self.write_all(&moof)?;
+        self.write_all(&mdat_header)?;
+        for (i, _, _) in &trafs {
+            let data = std::mem::take(&mut self.tracks[*i].pending);
+            self.write_all(&data)?;
+        }
+        for track in &mut self.tracks {
+            track.samples.clear();
+        }
+        Ok(())
+    }
+
+    /// `moof` for the open fragment; `data_start` is the offset of the first
+    /// sample from the start of the `moof`
+    fn build_moof(&self, trafs: &[(usize, TrackTiming, u64)], data_start: u64) -> Vec<u8> {
+        let mut moof = Vec::new();
+        put_box(&mut moof, b"moof", |moof| {
+            put_full_box(moof, b"mfhd", 0, 0, |b| put_u32(b, self.sequence));
+            let mut data_offset = data_start;
+            for (index, timing, base) in trafs {
+                let track = &self.tracks[*index];
+                put_box(moof, b"traf", |traf| {
+                    // default-base-is-moof
+                    put_full_box(traf, b"tfhd", 0, 0x02_0000, |b| {
+                        put_u32(b, *index as u32 + 1)
+                    });
+                    put_full_box(traf, b"tfdt", 1, 0, |b| put_u64(b, *base));
+
+                    let with_offsets = timing.composition_offsets.iter().any(|&o| o != 0);
+                    let version = timing.composition_offsets.iter().any(|&o| o < 0) as u8;
+                    // data offset, duration, size, flags (+ composition offset)
+                    let flags =
+                        0x001 | 0x100 | 0x200 | 0x400 | if with_offsets { 0x800 } else { 0 };
+                    put_full_box(traf, b"trun", version, flags, |b| {
+                        put_u32(b, track.samples.len() as u32);
+                        put_u32(b, data_offset as u32);
+                        for (i, sample) in track.samples.iter().enumerate() {
+                            put_u32(b, timing.durations[i]);
+                            put_u32(b, sample.size);
+                            // sync: depends on nothing; otherwise non-sync, depends on others
+                            put_u32(
+                                b,
+                                if sample.keyframe {
+                                    0x0200_0000
+                                } else {
+                                    0x0101_0000
+                                },
+                            );
+                            if with_offsets {
+                                put_u32(b, timing.composition_offsets[i] as u32);
+                            }
+                        }
+                    });
+                });
+                data_offset += track.pending.len() as u64;
+            }
+        });
+        moof
+    }
+
+    fn build_moov(&self) -> Vec<u8> {
+        let fragment_origin = self.fragment_origin;
+        let origin_us = self
+            .tracks
+            .iter()
+            .filter_map(|t| t.first_pts_us())
+            .min()
+            .unwrap_or(0);
+        let timings: Vec<TrackTiming> = match fragment_origin {
+            // Media times count from the decode origin; skip to the first
+            // presented sample like the progressive edit does
+            Some((decode, presentation)) => self
+                .tracks
+                .iter()
+                .map(|t| TrackTiming {
+                    presentation_start: to_ticks(presentation - decode, t.timescale),
+                    ..Default::default()
+                })
+                .collect(),
+            None => self.tracks.iter().map(|t| t.timing(origin_us)).collect(),
+        };
+        let movie_duration = |track: &TrackState, timing: &TrackTiming| -> u64 {
+            to_movie(
+                timing.lead as u64 + timing.media_duration(),
+                track.timescale,
+            )
+        };
+        let duration = self
+            .tracks
+            .iter()
+            .zip(&timings)
+            .map(|(t, timing)| movie_duration(t, timing))
+            .max()
+            .unwrap_or(0);
+
+        let mut moov = Vec::new();
+        put_box(&mut moov, b"moov", |moov| {
+            put_full_box(moov, b"mvhd", 0, 0, |b| {
+                put_u32(b, 0); // creation_time
+                put_u32(b, 0); // modification_time
+                put_u32(b, MOVIE_TIMESCALE);
+                put_u32(b, duration as u32);
+                put_u32(b, 0x0001_0000); // rate 1.0
+                put_u16(b, 0x0100); // volume 1.0
+                b.extend_from_slice(&[0; 10]);
+                put_matrix(b);
+                b.extend_from_slice(&[0; 24]);
+                put_u32(b, self.tracks.len() as u32 + 1);
+            });
+            for (i, (track, timing)) in self.tracks.iter().zip(&timings).enumerate() {
+                let track_duration = movie_duration(track, timing);
+                self.put_trak(moov, i as u32 + 1, track, timing, track_duration);
+            }
+            if fragment_origin.is_some() {
+                put_box(moov, b"mvex", |mvex| {
+                    for i in 0..self.tracks.len() {
+                        put_full_box(mvex, b"trex", 0, 0, |b| {
+                            put_u32(b, i as u32 + 1);
+                            put_u32(b, 1); // sample description
+                            put_u32(b, 0); // duration
+                            put_u32(b, 0); // size
+                            put_u32(b, 0); // flags
+                        });
+                    }
+                });
+            }
+        });
+        moov
+    }
+
+    fn put_trak(
+        &self,
+        out: &mut Vec<u8>,
+        track_id: u32,
+        track: &TrackState,
+        timing: &TrackTiming,
+        duration: u64,
+    ) {
+        let stream = &track.stream;
+        let (width, height) = match stream.kind {
+            MuxTrackKind::Video { width, height } => (width, height),
+            _ => (0, 0),
+        };
+        let is_audio = matches!(stream.kind, MuxTrackKind::Audio { .. });
+
+        put_box(out, b"trak", |trak| {
+            let flags = if stream.default { 0x3 } else { 0x2 };
+            put_full_box(trak, b"tkhd", 0, flags, |b| {
+                put_u32(b, 0);
+                put_u32(b, 0);
+                put_u32(b, track_id);
+                put_u32(b, 0);
+                put_u32(b, duration as u32);
+                b.extend_from_slice(&[0; 8]);
+                put_u16(b, 0); // layer
+                put_u16(b, 0); // alternate group
+                put_u16(b, if is_audio { 0x0100 } else { 0 });
+                put_u16(b, 0);
+                put_matrix(b);
+                put_u32(b, width << 16);
+                put_u32(b, height << 16);
+            });
+
+            // Delay a late-starting track, and skip the reorder delay
+            let lead = to_movie(timing.lead as u64, track.timescale);
+            if lead > 0 || timing.presentation_start > 0 {
+                put_box(trak, b"edts", |edts| {
+                    put_full_box(edts, b"elst", 0, 0, |b| {
+                        put_u32(b, if lead > 0 { 2 } else { 1 });
+                        if lead > 0 {
+                            put_u32(b, lead as u32);
+                            put_u32(b, u32::MAX); // media_time -1: empty edit
+                            put_u32(b, 0x0001_0000);
+                        }
+                        put_u32(b, duration.saturating_sub(lead) as u32);
+                        put_u32(b, timing.presentation_start.max(0) as u32);
+                        put_u32(b, 0x0001_0000);
+                    });
+                });
+            }
+
+            put_box(trak, b"mdia", |mdia| {
+                put_full_box(mdia, b"mdhd", 0, 0, |b| {
+                    put_u32(b, 0);
+                    put_u32(b, 0);
+                    put_u32(b, track.timescale);
+                    put_u32(b, timing.media_duration() as u32);
+                    put_u16(b, packed_language(&stream.language));
+                    put_u16(b, 0);
+                });
+                put_full_box(mdia, b"hdlr", 0, 0, |b| {
+                    put_u32(b, 0);
+                    b.extend_from_slice(if is_audio { b"soun" } else { b"vide" });
+                    b.extend_from_slice(&[0; 12]);
+                    let name = stream.name.as_deref().unwrap_or(if is_audio {
+                        "SoundHandler"
+                    } else {
+                        "VideoHandler"
+                    });
+                    b.extend_from_slice(name.as_bytes());
+                    b.push(0);
+                });
+                put_box(mdia, b"minf", |minf| {
+                    if is_audio {
+                        put_full_box(minf, b"smhd", 0, 0, |b| put_u32(b, 0));
+                    } else {
+                        put_full_box(minf, b"vmhd", 0, 1, |b| b.extend_from_slice(&[0; 8]));
+                    }
+                    put_box(minf, b"dinf", |dinf| {
+                        put_full_box(dinf, b"dref", 0, 0, |b| {
+                            put_u32(b, 1);
+                            put_full_box(b, b"url ", 0, 1, |_| {});
+                        });
+                    });
+                    put_box(minf, b"stbl", |stbl| {
+                        self.put_stsd(stbl, track_id, track);
+                        // Fragmented samples are described by the moofs
+                        let samples: &[Sample] = if self.fragment_origin.is_some() {
+                            &[]
+                        } else {
+                            &track.samples
+                        };
+                        put_sample_tables(stbl, samples, timing);
+                    });
+                });
+            });
+        });
+    }
+
+    fn put_stsd(&self, out: &mut Vec<u8>, track_id: u32, track: &TrackState) {
+        let stream = &track.stream;
+        let config = track.config.as_deref();
+        put_full_box(out, b"stsd", 0, 0, |stsd| {
+            put_u32(stsd, 1);
+            match stream.kind {
+                MuxTrackKind::Video { width, height } => {
+                    let (entry, config_box): (&[u8; 4], &[u8; 4]) = match stream.codec {
+                        MuxCodec::H264 => (b"avc1", b"avcC"),
+                        MuxCodec::H265 => (b"hvc1", b"hvcC"),
+                        MuxCodec::VP9 => (b"vp09", b"vpcC"),
+                        _ => (b"av01", b"av1C"),
+                    };
+                    put_box(stsd, entry, |b| {
+                        b.extend_from_slice(&[0; 6]);
+                        put_u16(b, 1); // data_reference_index
+                        b.extend_from_slice(&[0; 16]);
+                        put_u16(b, width as u16);
+                        put_u16(b, height as u16);
+                        put_u32(b, 0x0048_0000); // 72 dpi
+                        put_u32(b, 0x0048_0000);
+                        put_u32(b, 0);
+                        put_u16(b, 1); // frame_count
+                        b.extend_from_slice(&[0; 32]); // compressor name
+                        put_u16(b, 0x0018); // depth
+                        put_u16(b, 0xFFFF);
+                        if let Some(config) = config {
+                            put_box(b, config_box, |c| c.extend_from_slice(config));
+                        }
+                    });
+                }
+                MuxTrackKind::Audio {
+                    sample_rate,
+                    channels,
+                    bit_depth,
+                } => {
+                    let entry: &[u8; 4] = match stream.codec {
+                        MuxCodec::AC3 => b"ac-3",
+                        MuxCodec::EAC3 => b"ec-3",
+                        MuxCodec::Opus => b"Opus",
+                        MuxCodec::FLAC => b"fLaC",
+                        _ => b"mp4a",
+                    };
+                    put_box(stsd, entry, |b| {
+                        b.extend_from_slice(&[0; 6]);
+                        put_u16(b, 1);
+                        b.extend_from_slice(&[0; 8]);
+                        put_u16(b, channels);
+                        put_u16(b, bit_depth.unwrap_or(16));
+                        put_u32(b, 0);
+                        put_u32(b, sample_rate.min(0xFFFF) << 16);
+                        match stream.codec {
+                            MuxCodec::AAC => put_esds(b, track_id, 0x40, config),
+                            MuxCodec::MP3 => put_esds(b, track_id, 0x6B, None),
+                            codec => {
+                                let kind: &[u8; 4] = match codec {
+                                    MuxCodec::AC3 => b"dac3",
+                                    MuxCodec::EAC3 => b"dec3",
+                                    MuxCodec::Opus => b"dOps",
+                                    _ => b"dfLa",
+                                };
+                                if let Some(config) = config {
+                                    put_box(b, kind, |c| c.extend_from_slice(config));
+                                }
+                            }
+                        }
+                    });
+                }
+                MuxTrackKind::Subtitle => {}
+            }
+        });
+    }
+}
+
+/// stts, ctts, stss, stsc, stsz and stco/co64 for one track
+fn put_sample_tables(out: &mut Vec<u8>, samples: &[Sample], timing: &TrackTiming) {
+    put_full_box(out, b"stts", 0, 0, |b| {
+        let runs = run_lengths(&timing.durations);
+        put_u32(b, runs.len() as u32);
+        for (count, delta) in runs {
+            put_u32(b, count);
+            put_u32(b, delta);
+        }
+    });
+
+    if timing.composition_offsets.iter().any(|&o| o != 0) {
+        let version = timing.composition_offsets.iter().any(|&o| o < 0) as u8;
+        put_full_box(out, b"ctts", version, 0, |b| {
+            let runs = run_lengths(&timing.composition_offsets);
+            put_u32(b, runs.len() as u32);
+            for (count, offset) in runs {
+                put_u32(b, count);
+                put_u32(b, offset as u32);
+            }
+        });
+    }
+
+    if samples.iter().any(|s| !s.keyframe) {
+        put_full_box(out, b"stss", 0, 0, |b| {
+            let keyframes: Vec<u32> = samples
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.keyframe)
+                .map(|(i, _)| i as u32 + 1)
+                .collect();
+            put_u32(b, keyframes.len() as u32);
+            for k in keyframes {
+                put_u32(b, k);
+            }
+        });
+    }
+
+    // One sample per chunk keeps interleaving trivial to describe
+    put_full_box(out, b"stsc", 0, 0, |b| {
+        if samples.is_empty() {
+            put_u32(b, 0);
+        } else {
+            put_u32(b, 1);
+            put_u32(b, 1);
+            put_u32(b, 1);
+            put_u32(b, 1);
+        }
+    });
+
+    put_full_box(out, b"stsz", 0, 0, |b| {
+        put_u32(b, 0);
+        put_u32(b, samples.len() as u32);
+        for s in samples {
+            put_u32(b, s.size);
+        }
+    });
+
+    if samples.iter().any(|s| s.offset > u32::MAX as u64) {
+        put_full_box(out, b"co64", 0, 0, |b| {
+            put_u32(b, samples.len() as u32);
+            for s in samples {
+                put_u64(b, s.offset);
+            }
+        });
+    } else {
+        put_full_box(out, b"stco", 0, 0, |b| {
+            put_u32(b, samples.len() as u32);
+            for s in samples {
+                put_u32(b, s.offset as u32);
+            }
+        });
+    }
+}
+
+fn run_lengths<T: Copy + PartialEq>(values: &[T]) -> Vec<(u32, T)> {
+    let mut runs: Vec<(u32, T)> = Vec::new();
+    for &v in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == v => *count += 1,
+            _ => runs.push((1, v)),
+        }
+    }
+    runs
+}
+
+// ============================================================================
+// Fast Start
+// ============================================================================
+
+const RELOCATE_CHUNK: u64 = 1 << 20;
+
+/// Top-level boxes as (type, start, size)
+fn top_level_boxes<F: Read + Seek>(file: &mut F) -> Result<Vec<([u8; 4], u64, u64)>, String> {
+    let file_size = file
+        .seek(SeekFrom::End(0))
+        .map_err(|e| format!("Seek error: {}", e))?;
+    let mut boxes = Vec::new();
+    let mut pos = 0u64;
+    while pos + 8 <= file_size {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(pos))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        file.read_exact(&mut header[..8])
+            .map_err(|e| format!("Read error: {}", e))?;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap_or_default();
+        let size = match u32::from_be_bytes(header[..4].try_into().unwrap_or_default()) {
+            0 => file_size - pos,
+            1 => {
+                file.read_exact(&mut header[8..])
+                    .map_err(|e| format!("Read error: {}", e))?;
+                u64::from_be_bytes(header[8..].try_into().unwrap_or_default())
+            }
+            size => size as u64,
+        };
+        if size < 8 || pos + size > file_size {
+            return Err(format!("Truncated MP4 box at offset {}", pos));
+        }
+        boxes.push((kind, pos, size));
+        pos += size;
+    }
+    Ok(boxes)
+}
+
+/// Add `delta` to every stco/co64 entry below `data` (a box payload list)
+fn shift_chunk_offsets(data: &mut [u8], delta: u64) -> Result<(), String> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap_or_default()) as usize;
+        if size < 8 || pos + size > data.len() {
+            return Err("Malformed box inside moov".to_string());
+        }
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap_or_default();
+        let payload = &mut data[pos + 8..pos + size];
+        match &kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => shift_chunk_offsets(payload, delta)?,
+            b"stco" | b"co64" => {
+                let wide = &kind == b"co64";
+                let width = if wide { 8 } else { 4 };
+                let count = payload
+                    .get(4..8)
+                    .map_or(0, |c| u32::from_be_bytes(c.try_into().unwrap_or_default()))
+                    as usize;
+                for i in 0..count {
+                    let at = 8 + i * width;
+                    let entry = payload
+                        .get_mut(at..at + width)
+                        .ok_or("Truncated chunk offset table")?;
+                    if wide {
+                        let v = u64::from_be_bytes(entry.try_into().unwrap_or_default()) + delta;
+                        entry.copy_from_slice(&v.to_be_bytes());
+                    } else {
+                        let v =
+                            u32::from_be_bytes(entry.try_into().unwrap_or_default()) as u64 + delta;
+                        let v = u32::try_from(v).map_err(|_| "stco overflow")?;
+                        entry.copy_from_slice(&v.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+        pos += size;
+    }
+    Ok(())
+}
+
+/// Move a trailing `moov` in front of the first `mdat`, in place.
+///
+/// Returns `Ok(false)` without touching the file when it is already
+/// moov-first or fragmented, or when the move would push a 32-bit chunk
+/// offset past 4 GiB.
+pub fn faststart<F: Read + Write + Seek>(file: &mut F) -> Result<bool, String> {
+    let boxes = top_level_boxes(file)?;
+    if boxes.iter().any(|(kind, _, _)| kind == b"moof") {
+        return Ok(false);
+    }
+    let (Some(mdat), Some(moov)) = (
+        boxes.iter().find(|(kind, _, _)| kind == b"mdat"),
+        boxes.iter().find(|(kind, _, _)| kind == b"moov"),
+    ) else {
+        return Err("MP4 has no moov/mdat".to_string());
+    };
+    let (_, insert_at, _) = *mdat;
+    let (_, moov_pos, moov_size) = *moov;
+    if moov_pos < insert_at {
+        return Ok(false);
+    }
+
+    let mut moov = vec![0u8; moov_size as usize];
+    file.seek(SeekFrom::Start(moov_pos))
+        .map_err(|e| format!("Seek error: {}", e))?;
+    file.read_exact(&mut moov)
+        .map_err(|e| format!("Read error: {}", e))?;
+    if u32::from_be_bytes(moov[..4].try_into().unwrap_or_default()) == 1 {
+        return Ok(false);
+    }
+    if let Err(e) = shift_chunk_offsets(&mut moov[8..], moov_size) {
+        tracing::warn!("MP4 faststart skipped: {}", e);
+        return Ok(false);
+    }
+
+    // Shift everything between the mdat start and the moov up, back to front
+    let mut buf = vec![0u8; RELOCATE_CHUNK as usize];
+    let mut end = moov_pos;
+    while end > insert_at {
+        let len = (end - insert_at).min(RELOCATE_CHUNK);
+        let from = end - len;
+        let chunk = &mut buf[..len as usize];
+        file.seek(SeekFrom::Start(from))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        file.read_exact(chunk)
+            .map_err(|e| format!("Read error: {}", e))?;
+        file.seek(SeekFrom::Start(from + moov_size))
+            .map_err(|e| format!("Seek error: {}", e))?;
+        file.write_all(chunk)
+            .map_err(|e| format!("Write error: {}", e))?;
+        end = from;
+    }
+    file.seek(SeekFrom::Start(insert_at))
+        .map_err(|e| format!("Seek error: {}", e))?;
+    file.write_all(&moov)
+        .map_err(|e| format!("Write error: {}", e))?;
+    file.flush().map_err(|e| format!("Flush error: {}", e))?;
+
+    tracing::info!(
+        "MP4 faststart: moved {} byte moov to offset {}",
+        moov_size,
+        insert_at
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4_demux::mp4::Mp4Demuxer;
+    use crate::mp4_demux::Packet;
+    use std::io::Cursor;
+
+    const SPS: [u8; 9] = [0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f, 0xac];
+    const PPS: [u8; 7] = [0, 0, 0, 1, 0x68, 0xee, 0x3c];
+    const ASC: [u8; 2] = [0x11, 0x90]; // AAC-LC 48 kHz stereo
+
+    fn annexb_frame(keyframe: bool, n: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        if keyframe {
+            data.extend_from_slice(&SPS);
+            data.extend_from_slice(&PPS);
+            data.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88, n]);
+        } else {
+            data.extend_from_slice(&[0, 0, 0, 1, 0x41, 0x9a, n]);
+        }
+        data
+    }
+
+    fn streams(with_config: bool) -> Vec<MuxStream> {
+        let mut video = MuxStream::video(MuxCodec::H264, 320, 240);
+        if with_config {
+            video = video.with_codec_private([&SPS[..], &PPS[..]].concat());
+        }
+        video.frame_duration_us = Some(40_000);
+        vec![
+            video,
+            MuxStream::audio(MuxCodec::AAC, 48_000, 2).with_codec_private(ASC.to_vec()),
+        ]
+    }
+
+    fn audio_packet(i: i64) -> MuxPacket {
+        MuxPacket {
+            stream: 1,
+            pts_us: i * 20_000,
+            dts_us: None,
+            duration_us: Some(20_000),
+            keyframe: true,
+            data: vec![0x21, i as u8, 0x55],
+        }
+    }
+
+    fn demux(file: &[u8]) -> (Mp4Demuxer<Cursor<Vec<u8>>>, Vec<Packet>) {
+        let mut demuxer = Mp4Demuxer::new(Cursor::new(file.to_vec())).unwrap();
+        let packets = std::iter::from_fn(|| demuxer.read_packet()).collect();
+        (demuxer, packets)
+    }
+
+    fn box_order(file: &[u8]) -> Vec<[u8; 4]> {
+        top_level_boxes(&mut Cursor::new(file))
+            .unwrap()
+            .into_iter()
+            .map(|(kind, _, _)| kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_progressive_and_faststart_round_trip() {
+        // I P B B P B B in decode order, no DTS given
+        let order = [0i64, 3, 1, 2, 6, 4, 5];
+        let mut muxer = Mp4Muxer::new(
+            Cursor::new(Vec::new()),
+            streams(true),
+            Mp4MuxConfig::default(),
+        )
+        .unwrap();
+        for (i, &frame) in order.iter().enumerate() {
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: frame * 40_000,
+                    dts_us: None,
+                    duration_us: None,
+                    keyframe: i == 0,
+                    data: annexb_frame(i == 0, i as u8),
+                })
+                .unwrap();
+            muxer.write_packet(&audio_packet(2 * i as i64)).unwrap();
+            muxer.write_packet(&audio_packet(2 * i as i64 + 1)).unwrap();
+        }
+        let mut file = muxer.finish().unwrap();
+        let progressive = file.get_ref().clone();
+        assert_eq!(
+            box_order(&progressive),
+            [*b"ftyp", *b"free", *b"mdat", *b"moov"]
+        );
+
+        let (demuxer, packets) = demux(&progressive);
+        let info = demuxer.streams();
+        let avcc = h264_utils::build_avcc_extradata(&[&SPS[..], &PPS[..]].concat()).unwrap();
+        assert_eq!(info[0].extra_data, avcc);
+        assert_eq!(info[1].extra_data, ASC);
+
+        let video: Vec<&Packet> = packets.iter().filter(|p| p.stream_index == 0).collect();
+        assert_eq!(video.len(), order.len());
+        for (i, (packet, &frame)) in video.iter().zip(&order).enumerate() {
+            assert_eq!(packet.pts, frame * 40_000);
+            assert!(packet.dts <= packet.pts);
+            assert_eq!(packet.keyframe, i == 0);
+            assert_eq!(
+                packet.data,
+                h264_utils::annexb_to_avcc(&annexb_frame(i == 0, i as u8))
+            );
+        }
+        let audio: Vec<i64> = packets
+            .iter()
+            .filter(|p| p.stream_index == 1)
+            .map(|p| p.pts)
+            .collect();
+        assert_eq!(audio, (0..14).map(|i| i * 20_000).collect::<Vec<_>>());
+
+        // Moving the moov up keeps every sample where the tables say it is
+        assert!(faststart(&mut file).unwrap());
+        let faststarted = file.into_inner();
+        assert_eq!(faststarted.len(), progressive.len());
+        assert_eq!(
+            box_order(&faststarted),
+            [*b"ftyp", *b"free", *b"moov", *b"mdat"]
+        );
+        let (_, moved) = demux(&faststarted);
+        assert_eq!(
+            moved.iter().map(|p| (p.pts, &p.data)).collect::<Vec<_>>(),
+            packets.iter().map(|p| (p.pts, &p.data)).collect::<Vec<_>>()
+        );
+        assert!(!faststart(&mut Cursor::new(faststarted)).unwrap());
+    }
+
+    #[test]
+    fn test_fragmented_round_trip() {
+        let config = Mp4MuxConfig {
+            fragment_duration_ms: Some(500),
+            ..Default::default()
+        };
+        let mut muxer = Mp4Muxer::new(Cursor::new(Vec::new()), streams(false), config).unwrap();
+        for i in 0..30i64 {
+            let keyframe = i % 10 == 0;
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: i * 40_000,
+                    dts_us: Some(i * 40_000),
+                    duration_us: None,
+                    keyframe,
+                    data: annexb_frame(keyframe, i as u8),
+                })
+                .unwrap();
+            muxer.write_packet(&audio_packet(2 * i)).unwrap();
+            muxer.write_packet(&audio_packet(2 * i + 1)).unwrap();
+        }
+        let file = muxer.finish().unwrap().into_inner();
+
+        // Cut at the first keyframe 500 ms in (frame 20)
+        assert_eq!(
+            box_order(&file),
+            [*b"ftyp", *b"moov", *b"moof", *b"mdat", *b"moof", *b"mdat"]
+        );
+
+        let (demuxer, packets) = demux(&file);
+        // avcC came from the parameter sets in the first keyframe
+        assert_eq!(demuxer.streams()[0].extra_data[0], 1);
+        let video: Vec<&Packet> = packets.iter().filter(|p| p.stream_index == 0).collect();
+        assert_eq!(video.len(), 30);
+        for (i, packet) in video.iter().enumerate() {
+            assert_eq!(packet.pts, i as i64 * 40_000);
+            assert_eq!(packet.keyframe, i % 10 == 0);
+            assert_eq!(
+                packet.data,
+                h264_utils::annexb_to_avcc(&annexb_frame(i % 10 == 0, i as u8))
+            );
+        }
+        let audio: Vec<i64> = packets
+            .iter()
+            .filter(|p| p.stream_index == 1)
+            .map(|p| p.pts)
+            .collect();
+        assert_eq!(audio, (0..60).map(|i| i * 20_000).collect::<Vec<_>>());
+
+        // A fragmented file is left alone
+        assert!(!faststart(&mut Cursor::new(file)).unwrap());
+    }
+}
@@ -0,0 +1,308 @@
+//! Shared stream/packet types for the container writers, plus a facade that
+//! picks a writer from the output extension (the mirror of `demuxer`).
+//!
+//! Timestamps are microseconds throughout; each writer converts to its own
+//! timebase. Packets arrive in decode order; `dts_us` may be left out when the
+//! source only carries presentation times (Matroska), in which case writers
+//! that need it derive it from the reordered PTS.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::mkv_mux::{MkvMuxConfig, MkvMuxer};
+use crate::mp4_mux::{Mp4MuxConfig, Mp4Muxer};
+use crate::ts_mux::{TsMuxConfig, TsMuxer};
+
+// ============================================================================
+// Stream Description
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MuxCodec {
+    H264,
+    H265,
+    VP8,
+    VP9,
+    AV1,
+    MPEG2,
+    AAC,
+    MP3,
+    AC3,
+    EAC3,
+    Opus,
+    Vorbis,
+    FLAC,
+    SubRip,
+    Ass,
+}
+
+impl MuxCodec {
+    /// Matroska CodecID
+    pub fn mkv_codec_id(&self) -> &'static str {
+        match self {
+            Self::H264 => "V_MPEG4/ISO/AVC",
+            Self::H265 => "V_MPEGH/ISO/HEVC",
+            Self::VP8 => "V_VP8",
+            Self::VP9 => "V_VP9",
+            Self::AV1 => "V_AV1",
+            Self::MPEG2 => "V_MPEG2",
+            Self::AAC => "A_AAC",
+            Self::MP3 => "A_MPEG/L3",
+            Self::AC3 => "A_AC3",
+            Self::EAC3 => "A_EAC3",
+            Self::Opus => "A_OPUS",
+            Self::Vorbis => "A_VORBIS",
+            Self::FLAC => "A_FLAC",
+            Self::SubRip => "S_TEXT/UTF8",
+            Self::Ass => "S_TEXT/ASS",
+        }
+    }
+
+    pub fn from_mkv_codec_id(codec_id: &str) -> Option<Self> {
+        Some(match codec_id {
+            "V_MPEG4/ISO/AVC" => Self::H264,
+            "V_MPEGH/ISO/HEVC" => Self::H265,
+            "V_VP8" => Self::VP8,
+            "V_VP9" => Self::VP9,
+            "V_AV1" => Self::AV1,
+            "V_MPEG2" => Self::MPEG2,
+            id if id.starts_with("A_AAC") => Self::AAC,
+            "A_MPEG/L3" => Self::MP3,
+            "A_AC3" => Self::AC3,
+            "A_EAC3" => Self::EAC3,
+            "A_OPUS" => Self::Opus,
+            "A_VORBIS" => Self::Vorbis,
+            "A_FLAC" => Self::FLAC,
+            "S_TEXT/UTF8" => Self::SubRip,
+            "S_TEXT/ASS" | "S_TEXT/SSA" => Self::Ass,
+            _ => return None,
+        })
+    }
+
+    pub fn from_mp4_codec(codec: &crate::mp4_demux::CodecId) -> Option<Self> {
+        use crate::mp4_demux::{AudioCodec, CodecId, VideoCodec};
+
+        Some(match codec {
+            CodecId::Video(VideoCodec::H264) => Self::H264,
+            CodecId::Video(VideoCodec::H265) => Self::H265,
+            CodecId::Video(VideoCodec::VP8) => Self::VP8,
+            CodecId::Video(VideoCodec::VP9) => Self::VP9,
+            CodecId::Video(VideoCodec::AV1) => Self::AV1,
+            CodecId::Video(VideoCodec::MPEG2) => Self::MPEG2,
+            CodecId::Audio(AudioCodec::AAC) => Self::AAC,
+            CodecId::Audio(AudioCodec::MP3) => Self::MP3,
+            CodecId::Audio(AudioCodec::AC3) => Self::AC3,
+            CodecId::Audio(AudioCodec::EAC3) => Self::EAC3,
+            CodecId::Audio(AudioCodec::Opus) => Self::Opus,
+            CodecId::Audio(AudioCodec::Vorbis) => Self::Vorbis,
+            CodecId::Audio(AudioCodec::FLAC) => Self::FLAC,
+            _ => return None,
+        })
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self,
+            Self::H264 | Self::H265 | Self::VP8 | Self::VP9 | Self::AV1 | Self::MPEG2
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MuxTrackKind {
+    Video {
+        width: u32,
+        height: u32,
+    },
+    Audio {
+        sample_rate: u32,
+        channels: u16,
+        bit_depth: Option<u16>,
+    },
+    Subtitle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MuxStream {
+    pub codec: MuxCodec,
+    pub kind: MuxTrackKind,
+    /// avcC/hvcC record (or Annex B SPS/PPS), AudioSpecificConfig, OpusHead, ...
+    pub codec_private: Option<Vec<u8>>,
+    /// ISO 639-2 code
+    pub language: String,
+    pub name: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+    /// Nominal frame/packet duration, when constant
+    pub frame_duration_us: Option<u64>,
+}
+
+impl MuxStream {
+    pub fn video(codec: MuxCodec, width: u32, height: u32) -> Self {
+        Self::new(codec, MuxTrackKind::Video { width, height })
+    }
+
+    pub fn audio(codec: MuxCodec, sample_rate: u32, channels: u16) -> Self {
+        Self::new(
+            codec,
+            MuxTrackKind::Audio {
+                sample_rate,
+                channels,
+                bit_depth: None,
+            },
+        )
+    }
+
+    pub fn subtitle(codec: MuxCodec) -> Self {
+        Self::new(codec, MuxTrackKind::Subtitle)
+    }
+
+    fn new(codec: MuxCodec, kind: MuxTrackKind) -> Self {
+        Self {
+            codec,
+            kind,
+            codec_private: None,
+            language: "und".to_string(),
+            name: None,
+            default: true,
+            forced: false,
+            frame_duration_us: None,
+        }
+    }
+
+    pub fn with_codec_private(mut self, data: Vec<u8>) -> Self {
+        self.codec_private = (!data.is_empty()).then_some(data);
+        self
+    }
+
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+}
+
+/// One compressed frame for stream `stream` (index into the stream list)
+#[derive(Debug, Clone)]
+pub struct MuxPacket {
+    pub stream: usize,
+    pub pts_us: i64,
+    pub dts_us: Option<i64>,
+    pub duration_us: Option<i64>,
+    pub keyframe: bool,
+    pub data: Vec<u8>,
+}
+
+// ============================================================================
+// Facade
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MuxFormat {
+    Mkv,
+    WebM,
+    Mp4,
+    Ts,
+}
+
+impl MuxFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mkv" | "mka" => Some(Self::Mkv),
+            "webm" => Some(Self::WebM),
+            "mp4" | "m4v" | "mov" => Some(Self::Mp4),
+            "ts" | "m2ts" | "mts" => Some(Self::Ts),
+            _ => None,
+        }
+    }
+
+    /// Whether this container can carry `stream`
+    pub fn supports(&self, stream: &MuxStream) -> bool {
+        use MuxCodec::*;
+
+        match self {
+            Self::Mkv => true,
+            Self::WebM => matches!(stream.codec, VP8 | VP9 | AV1 | Opus | Vorbis),
+            Self::Mp4 => matches!(
+                stream.codec,
+                H264 | H265 | VP9 | AV1 | AAC | MP3 | AC3 | EAC3 | Opus | FLAC
+            ),
+            Self::Ts => matches!(stream.codec, H264 | H265 | AAC | MP3 | AC3 | EAC3),
+        }
+    }
+}
+
+pub enum UniversalMuxer {
+    Mkv(MkvMuxer<BufWriter<File>>),
+    Mp4(Mp4Muxer<BufWriter<File>>),
+    Ts(TsMuxer<BufWriter<File>>),
+}
+
+impl UniversalMuxer {
+    /// Create `path` with a writer chosen by its extension
+    pub fn create(path: &Path, streams: Vec<MuxStream>) -> Result<Self, String> {
+        let format = MuxFormat::from_path(path)
+            .ok_or_else(|| format!("Unsupported output container: {:?}", path))?;
+        // Readable too: the MP4 writer moves its moov to the front at the end
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| format!("Create error: {}", e))?;
+        let writer = BufWriter::new(file);
+        match format {
+            MuxFormat::Mkv | MuxFormat::WebM => {
+                let config = MkvMuxConfig {
+                    webm: format == MuxFormat::WebM,
+                    ..Default::default()
+                };
+                Ok(Self::Mkv(MkvMuxer::new(writer, streams, config)?))
+            }
+            MuxFormat::Mp4 => Ok(Self::Mp4(Mp4Muxer::new(
+                writer,
+                streams,
+                Mp4MuxConfig::default(),
+            )?)),
+            MuxFormat::Ts => Ok(Self::Ts(TsMuxer::new(
+                writer,
+                streams,
+                TsMuxConfig::default(),
+            )?)),
+        }
+    }
+
+    pub fn write_packet(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        match self {
+            Self::Mkv(muxer) => muxer.write_packet(packet),
+            Self::Mp4(muxer) => muxer.write_packet(packet),
+            Self::Ts(muxer) => muxer.write_packet(packet),
+        }
+    }
+
+    /// Write indexes/trailers and flush the file (MP4 is rewritten moov-first)
+    pub fn finish(self) -> Result<(), String> {
+        use std::io::Write;
+
+        match self {
+            Self::Mkv(muxer) => muxer
+                .finish()?
+                .flush()
+                .map_err(|e| format!("Flush error: {}", e)),
+            Self::Mp4(muxer) => {
+                let mut file = muxer
+                    .finish()?
+                    .into_inner()
+                    .map_err(|e| format!("Flush error: {}", e))?;
+                crate::mp4_mux::faststart(&mut file).map(|_| ())
+            }
+            Self::Ts(muxer) => {
+                muxer.finish()?;
+                Ok(())
+            }
+        }
+    }
+}
@@ -22,10 +22,24 @@ pub enum PixelFormat {
     YUV422P,     // Y plane, U plane (half width), V plane (half width)
     YUV444P,     // Y plane, U plane (full size), V plane (full size)
 
+    // High bit depth planar (little-endian, LSB-aligned in 16-bit words)
+    YUV422P10LE,
+    YUV444P10LE,
+    YUV420P12LE,
+    YUV422P12LE,
+    YUV444P12LE,
+
     // YUV semi-planar (GPU decoder output)
     NV12, // Y plane, interleaved UV plane
     P010, // 10-bit NV12
 
+    // High bit depth semi-planar (MSB-aligned in 16-bit words)
+    P012, // 12-bit 4:2:0
+    P210, // 10-bit 4:2:2
+    P212, // 12-bit 4:2:2
+    P410, // 10-bit 4:4:4
+    P412, // 12-bit 4:4:4
+
     // RGB
     RGB24,  // 8-bit per channel, packed
     RGBA32, // 8-bit per channel + alpha, packed
@@ -34,6 +48,7 @@ pub enum PixelFormat {
 
     // GPU textures
     R8G8B8A8, // Vulkan/OpenGL format
+    RGBA16F,  // Half-float per channel, for high bit depth rendering
 }
 
 impl PixelFormat {
@@ -42,42 +57,157 @@ impl PixelFormat {
         match self {
             Self::RGB24 | Self::BGR24 => 3,
             Self::RGBA32 | Self::BGRA32 | Self::R8G8B8A8 => 4,
+            Self::RGBA16F => 8,
             _ => 0, // Planar formats
         }
     }
 
     /// Calculate buffer size needed
     pub fn buffer_size(&self, width: usize, height: usize) -> usize {
-        match self {
-            Self::YUV420P => width * height * 3 / 2,
-            Self::YUV420P10LE => width * height * 3, // 2 bytes per sample
-            Self::YUV422P => width * height * 2,
-            Self::YUV444P => width * height * 3,
-            Self::NV12 => width * height * 3 / 2,
-            Self::P010 => width * height * 3,
-            Self::RGB24 | Self::BGR24 => width * height * 3,
-            Self::RGBA32 | Self::BGRA32 | Self::R8G8B8A8 => width * height * 4,
+        match self.chroma_subsampling() {
+            Some((sub_x, sub_y)) => {
+                let (cw, ch) = chroma_dims(width, height, sub_x, sub_y);
+                (width * height + 2 * cw * ch) * self.bytes_per_sample()
+            }
+            None => width * height * self.bytes_per_pixel(),
         }
     }
 
     pub fn is_yuv(&self) -> bool {
+        self.chroma_subsampling().is_some()
+    }
+
+    pub fn is_rgb(&self) -> bool {
         matches!(
             self,
-            Self::YUV420P
-                | Self::YUV420P10LE
-                | Self::YUV422P
-                | Self::YUV444P
-                | Self::NV12
-                | Self::P010
+            Self::RGB24
+                | Self::RGBA32
+                | Self::BGR24
+                | Self::BGRA32
+                | Self::R8G8B8A8
+                | Self::RGBA16F
         )
     }
 
-    pub fn is_rgb(&self) -> bool {
+    /// Significant bits per YUV sample (8 for RGB formats)
+    pub fn bit_depth(&self) -> u32 {
+        match self {
+            Self::YUV420P10LE
+            | Self::YUV422P10LE
+            | Self::YUV444P10LE
+            | Self::P010
+            | Self::P210
+            | Self::P410 => 10,
+            Self::YUV420P12LE
+            | Self::YUV422P12LE
+            | Self::YUV444P12LE
+            | Self::P012
+            | Self::P212
+            | Self::P412 => 12,
+            Self::RGBA16F => 16,
+            _ => 8,
+        }
+    }
+
+    /// Bytes per YUV sample: 1 for 8-bit, 2 for 16-bit containers
+    pub fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth() > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether chroma is subsampled horizontally / vertically; None for RGB
+    pub fn chroma_subsampling(&self) -> Option<(bool, bool)> {
+        match self {
+            Self::YUV420P
+            | Self::YUV420P10LE
+            | Self::YUV420P12LE
+            | Self::NV12
+            | Self::P010
+            | Self::P012 => Some((true, true)),
+            Self::YUV422P | Self::YUV422P10LE | Self::YUV422P12LE | Self::P210 | Self::P212 => {
+                Some((true, false))
+            }
+            Self::YUV444P | Self::YUV444P10LE | Self::YUV444P12LE | Self::P410 | Self::P412 => {
+                Some((false, false))
+            }
+            _ => None,
+        }
+    }
+
+    /// Interleaved UV plane (NV12 family)
+    pub fn is_semi_planar(&self) -> bool {
         matches!(
             self,
-            Self::RGB24 | Self::RGBA32 | Self::BGR24 | Self::BGRA32 | Self::R8G8B8A8
+            Self::NV12
+                | Self::P010
+                | Self::P012
+                | Self::P210
+                | Self::P212
+                | Self::P410
+                | Self::P412
         )
     }
+
+    /// Same sample layout with 4:4:4 chroma
+    pub fn to_444(&self) -> Option<Self> {
+        match self {
+            Self::YUV420P | Self::YUV422P | Self::YUV444P => Some(Self::YUV444P),
+            Self::YUV420P10LE | Self::YUV422P10LE | Self::YUV444P10LE => Some(Self::YUV444P10LE),
+            Self::YUV420P12LE | Self::YUV422P12LE | Self::YUV444P12LE => Some(Self::YUV444P12LE),
+            Self::P010 | Self::P210 | Self::P410 => Some(Self::P410),
+            Self::P012 | Self::P212 | Self::P412 => Some(Self::P412),
+            _ => None,
+        }
+    }
+
+    /// Parse an FFmpeg-style pixel format name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "yuv420p" | "i420" => Some(Self::YUV420P),
+            "yuv420p10le" | "yuv420p10" => Some(Self::YUV420P10LE),
+            "yuv422p" => Some(Self::YUV422P),
+            "yuv444p" => Some(Self::YUV444P),
+            "yuv422p10le" | "yuv422p10" => Some(Self::YUV422P10LE),
+            "yuv444p10le" | "yuv444p10" => Some(Self::YUV444P10LE),
+            "yuv420p12le" | "yuv420p12" => Some(Self::YUV420P12LE),
+            "yuv422p12le" | "yuv422p12" => Some(Self::YUV422P12LE),
+            "yuv444p12le" | "yuv444p12" => Some(Self::YUV444P12LE),
+            "nv12" => Some(Self::NV12),
+            "p010" | "p010le" => Some(Self::P010),
+            "p012" | "p012le" => Some(Self::P012),
+            "p210" | "p210le" => Some(Self::P210),
+            "p212" | "p212le" => Some(Self::P212),
+            "p410" | "p410le" => Some(Self::P410),
+            "p412" | "p412le" => Some(Self::P412),
+            "rgb24" => Some(Self::RGB24),
+            "rgba" | "rgba32" => Some(Self::RGBA32),
+            "bgr24" => Some(Self::BGR24),
+            "bgra" | "bgra32" => Some(Self::BGRA32),
+            "rgba16f" | "rgbaf16" => Some(Self::RGBA16F),
+            _ => None,
+        }
+    }
+}
+
+/// Chroma plane dimensions for a luma size
+fn chroma_dims(width: usize, height: usize, sub_x: bool, sub_y: bool) -> (usize, usize) {
+    (
+        if sub_x { width.div_ceil(2) } else { width },
+        if sub_y { height.div_ceil(2) } else { height },
+    )
+}
+
+/// Quantization range of YUV samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorRange {
+    /// 16-235 luma / 16-240 chroma at 8-bit (TV / MPEG range)
+    #[default]
+    Limited,
+    /// 0-255 (PC / JPEG range)
+    Full,
 }
 
 // ============================================================================
@@ -141,17 +271,23 @@ pub struct VideoFrame {
     pub data: Vec<u8>,
     pub linesize: Vec<usize>, // Stride for each plane
     pub pts: i64,
+    pub range: ColorRange,
 }
 
 impl VideoFrame {
     pub fn new(width: usize, height: usize, format: PixelFormat) -> Self {
         let size = format.buffer_size(width, height);
-        let linesize = match format {
-            PixelFormat::YUV420P => vec![width, width / 2, width / 2],
-            PixelFormat::NV12 => vec![width, width],
-            PixelFormat::RGB24 | PixelFormat::BGR24 => vec![width * 3],
-            PixelFormat::RGBA32 | PixelFormat::BGRA32 => vec![width * 4],
-            _ => vec![width],
+        let bps = format.bytes_per_sample();
+        let linesize = match format.chroma_subsampling() {
+            Some((sub_x, sub_y)) => {
+                let (cw, _) = chroma_dims(width, height, sub_x, sub_y);
+                if format.is_semi_planar() {
+                    vec![width * bps, cw * 2 * bps]
+                } else {
+                    vec![width * bps, cw * bps, cw * bps]
+                }
+            }
+            None => vec![width * format.bytes_per_pixel()],
         };
 
         Self {
@@ -162,56 +298,45 @@ impl VideoFrame {
             data: vec![0u8; size],
             linesize,
             pts: 0,
+            range: ColorRange::Limited,
+        }
+    }
+
+    /// Byte range of each plane inside `data`
+    fn plane_ranges(&self) -> Vec<(usize, usize)> {
+        let Some((sub_x, sub_y)) = self.format.chroma_subsampling() else {
+            return vec![(0, self.data.len())];
+        };
+        let bps = self.format.bytes_per_sample();
+        let y_size = self.width * self.height * bps;
+        let (cw, ch) = chroma_dims(self.width, self.height, sub_x, sub_y);
+        let c_size = cw * ch * bps;
+        if self.format.is_semi_planar() {
+            vec![(0, y_size), (y_size, c_size * 2)]
+        } else {
+            vec![(0, y_size), (y_size, c_size), (y_size + c_size, c_size)]
         }
     }
 
     /// Get plane data slice
     pub fn plane(&self, index: usize) -> &[u8] {
-        match self.format {
-            PixelFormat::YUV420P => {
-                let y_size = self.width * self.height;
-                let uv_size = y_size / 4;
-                match index {
-                    0 => &self.data[..y_size],
-                    1 => &self.data[y_size..y_size + uv_size],
-                    2 => &self.data[y_size + uv_size..],
-                    _ => &[],
-                }
-            }
-            PixelFormat::NV12 => {
-                let y_size = self.width * self.height;
-                match index {
-                    0 => &self.data[..y_size],
-                    1 => &self.data[y_size..],
-                    _ => &[],
-                }
-            }
-            _ => &self.data,
+        match self.plane_ranges().get(index) {
+            Some(&(offset, len)) => &self.data[offset..(offset + len).min(self.data.len())],
+            None if self.format.is_rgb() => &self.data,
+            None => &[],
         }
     }
 
     /// Get mutable plane data slice
     pub fn plane_mut(&mut self, index: usize) -> &mut [u8] {
-        match self.format {
-            PixelFormat::YUV420P => {
-                let y_size = self.width * self.height;
-                let uv_size = y_size / 4;
-                match index {
-                    0 => &mut self.data[..y_size],
-                    1 => &mut self.data[y_size..y_size + uv_size],
-                    2 => &mut self.data[y_size + uv_size..],
-                    _ => &mut [],
-                }
+        let is_rgb = self.format.is_rgb();
+        match self.plane_ranges().get(index) {
+            Some(&(offset, len)) => {
+                let end = (offset + len).min(self.data.len());
+                &mut self.data[offset..end]
             }
-            PixelFormat::NV12 => {
-                let y_size = self.width * self.height;
-                match index {
-                    0 => &mut self.data[..y_size],
-                    1 => &mut self.data[y_size..],
-                    _ => &mut [],
-                }
-            }
-            _ => &mut self.data,
+            None if is_rgb => &mut self.data,
+            None => &mut [],
         }
     }
 }
@@ -232,8 +357,34 @@ pub struct PixelConverter {
     u_table_b: [i32; 256],
     v_table_r: [i32; 256],
     v_table_g: [i32; 256],
+    // Generic path (any bit depth / range / subsampling)
+    range: ColorRange,
+    dither: bool,
+    tables: ConversionTables,
 }
 
+/// Per-code-value contributions in normalized [0, 1] RGB, sized for the source bit depth
+#[derive(Debug, Clone, Default)]
+struct ConversionTables {
+    y: Vec<f32>,
+    cr_r: Vec<f32>,
+    cb_g: Vec<f32>,
+    cr_g: Vec<f32>,
+    cb_b: Vec<f32>,
+}
+
+/// 8x8 Bayer matrix for ordered dithering down to 8-bit
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
 impl PixelConverter {
     pub fn new(
         src_format: PixelFormat,
@@ -253,11 +404,54 @@ impl PixelConverter {
             u_table_b: [0; 256],
             v_table_r: [0; 256],
             v_table_g: [0; 256],
+            range: ColorRange::Limited,
+            dither: true,
+            tables: ConversionTables::default(),
         };
         converter.build_tables();
+        converter.build_generic_tables();
         converter
     }
 
+    /// Set the source quantization range (default: limited)
+    pub fn with_range(mut self, range: ColorRange) -> Self {
+        self.range = range;
+        self.build_generic_tables();
+        self
+    }
+
+    /// Enable ordered dithering when reducing >8-bit sources to 8-bit RGB (default: on)
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    fn build_generic_tables(&mut self) {
+        if !self.src_format.is_yuv() {
+            return;
+        }
+        let depth = self.src_format.bit_depth();
+        let max = ((1u32 << depth) - 1) as f32;
+        let (y_off, y_scale, c_off, c_scale) = match self.range {
+            ColorRange::Limited => {
+                let k = (1u32 << (depth - 8)) as f32;
+                (16.0 * k, 219.0 * k, 128.0 * k, 224.0 * k)
+            }
+            ColorRange::Full => (0.0, max, (1u32 << (depth - 1)) as f32, max),
+        };
+        let matrix = self.color_space.yuv_to_rgb_matrix();
+
+        let count = 1usize << depth;
+        let chroma = |i: usize| (i as f32 - c_off) / c_scale;
+        self.tables = ConversionTables {
+            y: (0..count).map(|i| (i as f32 - y_off) / y_scale).collect(),
+            cr_r: (0..count).map(|i| chroma(i) * matrix[0][2]).collect(),
+            cb_g: (0..count).map(|i| chroma(i) * matrix[1][1]).collect(),
+            cr_g: (0..count).map(|i| chroma(i) * matrix[1][2]).collect(),
+            cb_b: (0..count).map(|i| chroma(i) * matrix[2][1]).collect(),
+        };
+    }
+
     fn build_tables(&mut self) {
         let matrix = self.color_space.yuv_to_rgb_matrix();
 
@@ -287,19 +481,25 @@ impl PixelConverter {
             return Err("Size mismatch".to_string());
         }
 
+        // The integer LUT fast paths assume 8-bit limited range
+        let fast = self.range == ColorRange::Limited;
+
         match (self.src_format, self.dst_format) {
-            (PixelFormat::NV12, PixelFormat::RGBA32) => {
+            (PixelFormat::NV12, PixelFormat::RGBA32) if fast => {
                 self.nv12_to_rgba(src, dst);
             }
-            (PixelFormat::NV12, PixelFormat::RGB24) => {
+            (PixelFormat::NV12, PixelFormat::RGB24) if fast => {
                 self.nv12_to_rgb(src, dst);
             }
-            (PixelFormat::YUV420P, PixelFormat::RGBA32) => {
+            (PixelFormat::YUV420P, PixelFormat::RGBA32) if fast => {
                 self.yuv420p_to_rgba(src, dst);
             }
-            (PixelFormat::YUV420P, PixelFormat::RGB24) => {
+            (PixelFormat::YUV420P, PixelFormat::RGB24) if fast => {
                 self.yuv420p_to_rgb(src, dst);
             }
+            (src_format, dst_format) if src_format.is_yuv() && dst_format.is_rgb() => {
+                self.yuv_to_rgb_generic(src, dst);
+            }
             _ => {
                 return Err(format!(
                     "Unsupported conversion: {:?} -> {:?}",
@@ -312,6 +512,89 @@ impl PixelConverter {
         Ok(())
     }
 
+    /// Any YUV layout and bit depth to any RGB output. 8-bit outputs are
+    /// ordered-dithered for >8-bit sources, RGBA16F keeps full precision.
+    fn yuv_to_rgb_generic(&self, src: &VideoFrame, dst: &mut VideoFrame) {
+        let format = self.src_format;
+        let Some((sub_x, sub_y)) = format.chroma_subsampling() else {
+            return;
+        };
+        let depth = format.bit_depth();
+        let wide = format.bytes_per_sample() == 2;
+        let semi = format.is_semi_planar();
+        // Semi-planar high bit depth formats are MSB-aligned
+        let shift = if semi && wide { 16 - depth } else { 0 };
+        let mask = ((1u32 << depth) - 1) as u16;
+        let read = |plane: &[u8], idx: usize| -> usize {
+            if wide {
+                ((u16::from_le_bytes([plane[idx * 2], plane[idx * 2 + 1]]) >> shift) & mask)
+                    as usize
+            } else {
+                plane[idx] as usize
+            }
+        };
+
+        let width = self.width;
+        let height = self.height;
+        let (cw, _) = chroma_dims(width, height, sub_x, sub_y);
+        let (cx_shift, cy_shift) = (sub_x as usize, sub_y as usize);
+        let y_plane = src.plane(0);
+        let u_plane = src.plane(1);
+        let v_plane = if semi { src.plane(1) } else { src.plane(2) };
+
+        let out_format = self.dst_format;
+        let bpp = out_format.bytes_per_pixel();
+        let (ri, bi) = match out_format {
+            PixelFormat::BGR24 | PixelFormat::BGRA32 => (2, 0),
+            _ => (0, 2),
+        };
+        let dither = self.dither && depth > 8;
+        let t = &self.tables;
+        let out = &mut dst.data;
+
+        for y in 0..height {
+            let c_row = (y >> cy_shift) * cw;
+            for x in 0..width {
+                let y_val = read(y_plane, y * width + x);
+                let ci = c_row + (x >> cx_shift);
+                let (u_val, v_val) = if semi {
+                    (read(u_plane, ci * 2), read(v_plane, ci * 2 + 1))
+                } else {
+                    (read(u_plane, ci), read(v_plane, ci))
+                };
+
+                let luma = t.y[y_val];
+                let rgb = [
+                    luma + t.cr_r[v_val],
+                    luma + t.cb_g[u_val] + t.cr_g[v_val],
+                    luma + t.cb_b[u_val],
+                ];
+
+                let idx = (y * width + x) * bpp;
+                if out_format == PixelFormat::RGBA16F {
+                    for (c, value) in rgb.iter().chain(std::iter::once(&1.0)).enumerate() {
+                        let bits = f32_to_f16(*value).to_le_bytes();
+                        out[idx + c * 2..idx + c * 2 + 2].copy_from_slice(&bits);
+                    }
+                    continue;
+                }
+
+                let offset = if dither {
+                    (BAYER_8X8[y & 7][x & 7] as f32 + 0.5) / 64.0 - 0.5
+                } else {
+                    0.0
+                };
+                let quantize = |v: f32| (v * 255.0 + 0.5 + offset).floor().clamp(0.0, 255.0) as u8;
+                out[idx + ri] = quantize(rgb[0]);
+                out[idx + 1] = quantize(rgb[1]);
+                out[idx + bi] = quantize(rgb[2]);
+                if bpp == 4 {
+                    out[idx + 3] = 255;
+                }
+            }
+        }
+    }
+
     fn nv12_to_rgba(&self, src: &VideoFrame, dst: &mut VideoFrame) {
         let y_plane = src.plane(0);
         let uv_plane = src.plane(1);
@@ -451,6 +734,60 @@ impl PixelConverter {
     }
 }
 
+// ============================================================================
+// Half Float
+// ============================================================================
+
+/// Convert to IEEE 754 binary16 bits (round to nearest even)
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // Inf / NaN
+        return sign | 0x7c00 | if mant != 0 { 0x200 } else { 0 };
+    }
+
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // Subnormal half (or zero)
+        if e < -10 {
+            return sign;
+        }
+        let m = mant | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = m >> shift;
+        let rem = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+
+    let half = ((e as u32) << 10) | (mant >> 13);
+    let rem = mant & 0x1fff;
+    let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // A mantissa carry rolls into the exponent, which is the correct result
+    sign | (half + round as u32) as u16
+}
+
+/// Convert IEEE 754 binary16 bits to f32
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((bits >> 10) & 0x1f) as i32;
+    let mant = (bits & 0x3ff) as f32;
+    match exp {
+        0 => sign * mant * (2.0f32).powi(-24),
+        0x1f if mant == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mant / 1024.0) * (2.0f32).powi(exp - 15),
+    }
+}
+
 // ============================================================================
 // Scaler (resize)
 // ============================================================================
@@ -548,6 +885,710 @@ impl Scaler {
             }
         }
     }
+    /// Scale RGB24 frame with any [`ScaleFilter`] (bicubic, Lanczos, spline, area)
+    pub fn scale_rgb_filtered(
+        &self,
+        src: &[u8],
+        dst: &mut [u8],
+        filter: ScaleFilter,
+    ) -> Result<(), String> {
+        let scaler = PlaneScaler::new(
+            self.src_width,
+            self.src_height,
+            self.dst_width,
+            self.dst_height,
+            filter,
+        )?;
+        scaler.scale_packed_u8(src, dst, 3);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// High-Quality Scaling
+// ============================================================================
+//
+// Separable two-pass resampler: every output column/row gets a precomputed
+// list of source taps with 14-bit fixed-point weights, so the hot loop is
+// integer multiply-accumulate only. Works on 8-bit and 16-bit containers
+// (10/12-bit video), planar or interleaved channels.
+
+/// Fixed-point precision of precomputed kernel weights
+const WEIGHT_BITS: u32 = 14;
+/// Extra precision carried between the horizontal and vertical pass
+const INTERMEDIATE_BITS: u32 = 6;
+
+/// Resampling kernel
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+    /// Mitchell-Netravali cubic family: (1/3, 1/3) Mitchell, (0, 0.5)
+    /// Catmull-Rom, (1, 0) B-spline
+    Bicubic {
+        b: f32,
+        c: f32,
+    },
+    /// Windowed sinc with 2, 3 or 4 lobes
+    Lanczos {
+        taps: u32,
+    },
+    Spline36,
+    /// Pixel-coverage averaging, the cleanest choice for downscaling
+    Area,
+}
+
+impl Default for ScaleFilter {
+    fn default() -> Self {
+        Self::MITCHELL
+    }
+}
+
+impl ScaleFilter {
+    pub const MITCHELL: Self = Self::Bicubic {
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0,
+    };
+    pub const CATMULL_ROM: Self = Self::Bicubic { b: 0.0, c: 0.5 };
+    pub const LANCZOS3: Self = Self::Lanczos { taps: 3 };
+
+    /// Parse a filter name as used by the API ("lanczos3", "catmull_rom", ...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', ' '], "_").as_str() {
+            "nearest" | "point" => Some(Self::Nearest),
+            "bilinear" | "linear" => Some(Self::Bilinear),
+            "bicubic" | "mitchell" => Some(Self::MITCHELL),
+            "catmull_rom" | "catrom" => Some(Self::CATMULL_ROM),
+            "bspline" | "b_spline" => Some(Self::Bicubic { b: 1.0, c: 0.0 }),
+            "lanczos2" => Some(Self::Lanczos { taps: 2 }),
+            "lanczos" | "lanczos3" => Some(Self::LANCZOS3),
+            "lanczos4" => Some(Self::Lanczos { taps: 4 }),
+            "spline36" => Some(Self::Spline36),
+            "area" | "box" => Some(Self::Area),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Nearest => "nearest".to_string(),
+            Self::Bilinear => "bilinear".to_string(),
+            Self::Bicubic { b, c } => format!("bicubic(b={:.2}, c={:.2})", b, c),
+            Self::Lanczos { taps } => format!("lanczos{}", lanczos_taps(*taps)),
+            Self::Spline36 => "spline36".to_string(),
+            Self::Area => "area".to_string(),
+        }
+    }
+
+    /// Kernel radius in source pixels at 1:1 scale
+    pub fn support(&self) -> f32 {
+        match self {
+            Self::Nearest | Self::Area => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Bicubic { .. } => 2.0,
+            Self::Lanczos { taps } => lanczos_taps(*taps) as f32,
+            Self::Spline36 => 3.0,
+        }
+    }
+
+    /// Evaluate the kernel at distance `x` (in source pixels)
+    pub fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Self::Nearest | Self::Area => {
+                if x < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::Bicubic { b, c } => mitchell_netravali(x, b, c),
+            Self::Lanczos { taps } => {
+                let a = lanczos_taps(taps) as f32;
+                if x < a {
+                    sinc(x) * sinc(x / a)
+                } else {
+                    0.0
+                }
+            }
+            Self::Spline36 => spline36(x),
+        }
+    }
+}
+
+fn lanczos_taps(taps: u32) -> u32 {
+    taps.clamp(2, 4)
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let px = std::f32::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+fn mitchell_netravali(x: f32, b: f32, c: f32) -> f32 {
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn spline36(x: f32) -> f32 {
+    if x < 1.0 {
+        ((13.0 / 11.0 * x - 453.0 / 209.0) * x - 3.0 / 209.0) * x + 1.0
+    } else if x < 2.0 {
+        let x = x - 1.0;
+        ((-6.0 / 11.0 * x + 270.0 / 209.0) * x - 156.0 / 209.0) * x
+    } else if x < 3.0 {
+        let x = x - 2.0;
+        ((1.0 / 11.0 * x - 45.0 / 209.0) * x + 26.0 / 209.0) * x
+    } else {
+        0.0
+    }
+}
+
+/// Precomputed taps for one axis
+#[derive(Debug, Clone)]
+struct FilterBank {
+    taps: usize,
+    /// First source index for each output sample
+    starts: Vec<usize>,
+    /// `taps` fixed-point weights per output sample, each row sums to 1 << WEIGHT_BITS
+    weights: Vec<i32>,
+}
+
+impl FilterBank {
+    /// `offset` shifts the sampling grid in source pixels (used for chroma siting)
+    fn new(src_len: usize, dst_len: usize, filter: ScaleFilter, offset: f32) -> Self {
+        let scale = src_len as f32 / dst_len as f32;
+        let last = src_len as isize - 1;
+
+        // Gather (clamped index, weight) lists per output sample
+        let mut rows: Vec<Vec<(usize, f32)>> = Vec::with_capacity(dst_len);
+        for d in 0..dst_len {
+            let center = (d as f32 + 0.5) * scale - 0.5 + offset;
+            let mut row: Vec<(usize, f32)> = Vec::new();
+            // Taps past the edge fold onto the border pixel
+            let push = |row: &mut Vec<(usize, f32)>, j: isize, w: f32| {
+                let j = j.clamp(0, last) as usize;
+                match row.iter_mut().find(|(idx, _)| *idx == j) {
+                    Some(entry) => entry.1 += w,
+                    None => row.push((j, w)),
+                }
+            };
+
+            match filter {
+                ScaleFilter::Nearest => push(&mut row, (center + 0.5).floor() as isize, 1.0),
+                ScaleFilter::Area => {
+                    // Exact overlap of the destination footprint with source pixels
+                    let lo = center + 0.5 - scale * 0.5;
+                    let hi = lo + scale;
+                    let mut j = lo.floor() as isize;
+                    while (j as f32) < hi {
+                        let overlap = (hi.min(j as f32 + 1.0) - lo.max(j as f32)).max(0.0);
+                        if overlap > 0.0 {
+                            push(&mut row, j, overlap);
+                        }
+                        j += 1;
+                    }
+                }
+                _ => {
+                    // Widen the kernel when downscaling so it also low-passes
+                    let stretch = scale.max(1.0);
+                    let radius = filter.support() * stretch;
+                    let first = (center - radius).floor() as isize;
+                    let end = (center + radius).ceil() as isize;
+                    for j in first..=end {
+                        let w = filter.weight((j as f32 - center) / stretch);
+                        if w != 0.0 {
+                            push(&mut row, j, w);
+                        }
+                    }
+                }
+            }
+            if row.is_empty() {
+                push(&mut row, center.round() as isize, 1.0);
+            }
+            row.sort_by_key(|(j, _)| *j);
+            rows.push(row);
+        }
+
+        let taps = rows
+            .iter()
+            .map(|r| r[r.len() - 1].0 - r[0].0 + 1)
+            .max()
+            .unwrap_or(1)
+            .min(src_len);
+
+        let one = 1i32 << WEIGHT_BITS;
+        let mut starts = Vec::with_capacity(dst_len);
+        let mut weights = vec![0i32; dst_len * taps];
+        for (d, row) in rows.iter().enumerate() {
+            let start = row[0].0.min(src_len - taps);
+            let sum: f32 = row.iter().map(|(_, w)| w).sum();
+            let sum = if sum.abs() < 1e-6 { 1.0 } else { sum };
+            let out = &mut weights[d * taps..(d + 1) * taps];
+            for &(j, w) in row {
+                out[j - start] = (w / sum * one as f32).round() as i32;
+            }
+            // Put the rounding error on the dominant tap so DC is preserved exactly
+            let error = one - out.iter().sum::<i32>();
+            if let Some(max) = out.iter_mut().max_by_key(|w| **w) {
+                *max += error;
+            }
+            starts.push(start);
+        }
+
+        Self {
+            taps,
+            starts,
+            weights,
+        }
+    }
+
+    #[inline]
+    fn taps_for(&self, d: usize) -> (usize, &[i32]) {
+        (
+            self.starts[d],
+            &self.weights[d * self.taps..(d + 1) * self.taps],
+        )
+    }
+}
+
+/// Sample container the scaler can operate on
+pub trait ScaleSample: Copy + Default + Send + Sync {
+    fn to_i32(self) -> i32;
+    fn from_i32(v: i32) -> Self;
+}
+
+impl ScaleSample for u8 {
+    #[inline]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+    #[inline]
+    fn from_i32(v: i32) -> Self {
+        v as u8
+    }
+}
+
+impl ScaleSample for u16 {
+    #[inline]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+    #[inline]
+    fn from_i32(v: i32) -> Self {
+        v as u16
+    }
+}
+
+/// Run `f(row_index, row)` for each `row_len` chunk, in parallel with `rayon`
+fn for_each_row<T: Send>(buf: &mut [T], row_len: usize, f: impl Fn(usize, &mut [T]) + Send + Sync) {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        buf.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        buf.chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    }
+}
+
+/// Chroma sample position relative to luma (H.273 chroma_sample_loc_type)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChromaLocation {
+    /// MPEG-2 / H.264 / HEVC default: co-sited with the left luma sample
+    #[default]
+    Left,
+    /// MPEG-1 / JPEG: centered between luma samples
+    Center,
+    /// BT.2020 / UHD: co-sited with the top-left luma sample
+    TopLeft,
+    Top,
+    BottomLeft,
+    Bottom,
+}
+
+impl ChromaLocation {
+    pub fn from_h273(value: u8) -> Self {
+        match value {
+            1 => Self::Center,
+            2 => Self::TopLeft,
+            3 => Self::Top,
+            4 => Self::BottomLeft,
+            5 => Self::Bottom,
+            _ => Self::Left,
+        }
+    }
+
+    /// Offset of a chroma sample from its pixel center, in chroma pixels,
+    /// for a plane subsampled 2x along the respective axis
+    pub fn shift(&self, subsampled_x: bool, subsampled_y: bool) -> (f32, f32) {
+        let x = match self {
+            Self::Left | Self::TopLeft | Self::BottomLeft => -0.25,
+            _ => 0.0,
+        };
+        let y = match self {
+            Self::TopLeft | Self::Top => -0.25,
+            Self::BottomLeft | Self::Bottom => 0.25,
+            _ => 0.0,
+        };
+        (
+            if subsampled_x { x } else { 0.0 },
+            if subsampled_y { y } else { 0.0 },
+        )
+    }
+}
+
+/// Separable resampler for a single plane (or interleaved channels)
+#[derive(Debug, Clone)]
+pub struct PlaneScaler {
+    pub src_width: usize,
+    pub src_height: usize,
+    pub dst_width: usize,
+    pub dst_height: usize,
+    pub filter: ScaleFilter,
+    horizontal: FilterBank,
+    vertical: FilterBank,
+}
+
+impl PlaneScaler {
+    pub fn new(
+        src_width: usize,
+        src_height: usize,
+        dst_width: usize,
+        dst_height: usize,
+        filter: ScaleFilter,
+    ) -> Result<Self, String> {
+        Self::with_siting(
+            src_width,
+            src_height,
+            dst_width,
+            dst_height,
+            filter,
+            (0.0, 0.0),
+            (0.0, 0.0),
+        )
+    }
+
+    /// Build a scaler whose source and destination samples sit `src_shift` /
+    /// `dst_shift` pixels away from their pixel centers (see [`ChromaLocation::shift`])
+    pub fn with_siting(
+        src_width: usize,
+        src_height: usize,
+        dst_width: usize,
+        dst_height: usize,
+        filter: ScaleFilter,
+        src_shift: (f32, f32),
+        dst_shift: (f32, f32),
+    ) -> Result<Self, String> {
+        if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+            return Err(format!(
+                "Invalid scale {}x{} -> {}x{}",
+                src_width, src_height, dst_width, dst_height
+            ));
+        }
+        let sx = src_width as f32 / dst_width as f32;
+        let sy = src_height as f32 / dst_height as f32;
+
+        Ok(Self {
+            src_width,
+            src_height,
+            dst_width,
+            dst_height,
+            filter,
+            horizontal: FilterBank::new(
+                src_width,
+                dst_width,
+                filter,
+                dst_shift.0 * sx - src_shift.0,
+            ),
+            vertical: FilterBank::new(
+                src_height,
+                dst_height,
+                filter,
+                dst_shift.1 * sy - src_shift.1,
+            ),
+        })
+    }
+
+    /// Scale an 8-bit plane; strides are in samples
+    pub fn scale_u8(&self, src: &[u8], src_stride: usize, dst: &mut [u8], dst_stride: usize) {
+        self.scale(src, src_stride, dst, dst_stride, 1, 255);
+    }
+
+    /// Scale a 16-bit container plane (10/12/16-bit video); strides are in samples
+    pub fn scale_u16(
+        &self,
+        src: &[u16],
+        src_stride: usize,
+        dst: &mut [u16],
+        dst_stride: usize,
+        bit_depth: u32,
+    ) {
+        let max = ((1u32 << bit_depth.clamp(1, 16)) - 1) as i32;
+        self.scale(src, src_stride, dst, dst_stride, 1, max);
+    }
+
+    /// Scale tightly packed interleaved 8-bit pixels (RGB24, RGBA, NV12 UV, ...)
+    pub fn scale_packed_u8(&self, src: &[u8], dst: &mut [u8], channels: usize) {
+        self.scale(
+            src,
+            self.src_width * channels,
+            dst,
+            self.dst_width * channels,
+            channels,
+            255,
+        );
+    }
+
+    /// Scale tightly packed interleaved 16-bit pixels (P010 UV, ...)
+    pub fn scale_packed_u16(&self, src: &[u16], dst: &mut [u16], channels: usize, bit_depth: u32) {
+        let max = ((1u32 << bit_depth.clamp(1, 16)) - 1) as i32;
+        self.scale(
+            src,
+            self.src_width * channels,
+            dst,
+            self.dst_width * channels,
+            channels,
+            max,
+        );
+    }
+
+    /// Generic two-pass scale: horizontal into an i32 buffer, then vertical
+    pub fn scale<T: ScaleSample>(
+        &self,
+        src: &[T],
+        src_stride: usize,
+        dst: &mut [T],
+        dst_stride: usize,
+        channels: usize,
+        max: i32,
+    ) {
+        let channels = channels.max(1);
+        let row_len = self.dst_width * channels;
+        if src.len() < (self.src_height - 1) * src_stride + self.src_width * channels
+            || dst.len() < (self.dst_height - 1) * dst_stride + row_len
+        {
+            tracing::warn!(
+                "PlaneScaler: buffer too small for {}x{} -> {}x{}",
+                self.src_width,
+                self.src_height,
+                self.dst_width,
+                self.dst_height
+            );
+            return;
+        }
+
+        // Horizontal pass: src_height rows of dst_width samples, kept at
+        // INTERMEDIATE_BITS of extra precision
+        let h_shift = WEIGHT_BITS - INTERMEDIATE_BITS;
+        let h_round = 1i64 << (h_shift - 1);
+        let mut tmp = vec![0i32; self.src_height * row_len];
+        for_each_row(&mut tmp, row_len, |y, out| {
+            let line = &src[y * src_stride..];
+            for x in 0..self.dst_width {
+                let (start, weights) = self.horizontal.taps_for(x);
+                for c in 0..channels {
+                    let mut acc = 0i64;
+                    for (k, &w) in weights.iter().enumerate() {
+                        acc += line[(start + k) * channels + c].to_i32() as i64 * w as i64;
+                    }
+                    out[x * channels + c] = ((acc + h_round) >> h_shift) as i32;
+                }
+            }
+        });
+
+        // Vertical pass
+        let v_shift = WEIGHT_BITS + INTERMEDIATE_BITS;
+        let v_round = 1i64 << (v_shift - 1);
+        let dst_len = (self.dst_height - 1) * dst_stride + row_len;
+        let tmp = &tmp;
+        for_each_row(&mut dst[..dst_len], dst_stride, |y, out| {
+            let (start, weights) = self.vertical.taps_for(y);
+            for (i, sample) in out[..row_len].iter_mut().enumerate() {
+                let mut acc = 0i64;
+                for (k, &w) in weights.iter().enumerate() {
+                    acc += tmp[(start + k) * row_len + i] as i64 * w as i64;
+                }
+                *sample = T::from_i32((((acc + v_round) >> v_shift) as i32).clamp(0, max));
+            }
+        });
+    }
+}
+
+fn bytes_to_u16(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+fn write_u16(dst: &mut [u8], samples: &[u16]) {
+    for (out, s) in dst.chunks_exact_mut(2).zip(samples) {
+        out.copy_from_slice(&s.to_le_bytes());
+    }
+}
+
+/// Scale one tightly packed plane of `format` samples with `channels` interleaved components
+fn scale_plane(
+    scaler: &PlaneScaler,
+    format: PixelFormat,
+    src: &[u8],
+    dst: &mut [u8],
+    channels: usize,
+) {
+    if format.bytes_per_sample() == 1 {
+        scaler.scale_packed_u8(src, dst, channels);
+        return;
+    }
+    // Semi-planar formats keep samples in the high bits of each word
+    let depth = if format.is_semi_planar() {
+        16
+    } else {
+        format.bit_depth()
+    };
+    let input = bytes_to_u16(src);
+    let mut output = vec![0u16; scaler.dst_width * scaler.dst_height * channels];
+    scaler.scale_packed_u16(&input, &mut output, channels, depth);
+    write_u16(dst, &output);
+}
+
+/// Resize a frame in its own pixel format. Chroma planes are resampled on
+/// their own grid, keeping the sample positions given by `chroma`.
+pub fn scale_frame(
+    src: &VideoFrame,
+    dst_width: usize,
+    dst_height: usize,
+    filter: ScaleFilter,
+    chroma: ChromaLocation,
+) -> Result<VideoFrame, String> {
+    let mut dst = VideoFrame::new(dst_width, dst_height, src.format);
+    dst.color_space = src.color_space;
+    dst.range = src.range;
+    dst.pts = src.pts;
+
+    if src.format.is_rgb() && src.format.bit_depth() == 8 {
+        let channels = src.format.bytes_per_pixel();
+        let scaler = PlaneScaler::new(src.width, src.height, dst_width, dst_height, filter)?;
+        scaler.scale_packed_u8(&src.data, &mut dst.data, channels);
+        return Ok(dst);
+    }
+
+    let (sub_x, sub_y) = src
+        .format
+        .chroma_subsampling()
+        .ok_or_else(|| format!("Scaling not supported for {:?}", src.format))?;
+    let odd =
+        |w: usize, h: usize| (sub_x && !w.is_multiple_of(2)) || (sub_y && !h.is_multiple_of(2));
+    if odd(src.width, src.height) || odd(dst_width, dst_height) {
+        return Err(format!(
+            "{:?} needs even dimensions ({}x{} -> {}x{})",
+            src.format, src.width, src.height, dst_width, dst_height
+        ));
+    }
+
+    let (src_cw, src_ch) = chroma_dims(src.width, src.height, sub_x, sub_y);
+    let (dst_cw, dst_ch) = chroma_dims(dst_width, dst_height, sub_x, sub_y);
+    let shift = chroma.shift(sub_x, sub_y);
+
+    let luma = PlaneScaler::new(src.width, src.height, dst_width, dst_height, filter)?;
+    let chroma_scaler =
+        PlaneScaler::with_siting(src_cw, src_ch, dst_cw, dst_ch, filter, shift, shift)?;
+
+    scale_plane(&luma, src.format, src.plane(0), dst.plane_mut(0), 1);
+    if src.format.is_semi_planar() {
+        scale_plane(
+            &chroma_scaler,
+            src.format,
+            src.plane(1),
+            dst.plane_mut(1),
+            2,
+        );
+    } else {
+        for p in 1..3 {
+            scale_plane(
+                &chroma_scaler,
+                src.format,
+                src.plane(p),
+                dst.plane_mut(p),
+                1,
+            );
+        }
+    }
+
+    Ok(dst)
+}
+
+/// Upsample 4:2:0 / 4:2:2 chroma to 4:4:4, honoring chroma siting
+pub fn upsample_chroma(
+    src: &VideoFrame,
+    filter: ScaleFilter,
+    chroma: ChromaLocation,
+) -> Result<VideoFrame, String> {
+    let (target, (sub_x, sub_y)) = match (src.format.to_444(), src.format.chroma_subsampling()) {
+        (Some(target), Some(sub)) => (target, sub),
+        _ => {
+            return Err(format!(
+                "Chroma upsampling not supported for {:?}",
+                src.format
+            ))
+        }
+    };
+    if !sub_x && !sub_y {
+        return Ok(src.clone());
+    }
+    if !src.width.is_multiple_of(2) || (sub_y && !src.height.is_multiple_of(2)) {
+        return Err(format!(
+            "{:?} needs even dimensions ({}x{})",
+            src.format, src.width, src.height
+        ));
+    }
+
+    let (cw, ch) = chroma_dims(src.width, src.height, sub_x, sub_y);
+    let scaler = PlaneScaler::with_siting(
+        cw,
+        ch,
+        src.width,
+        src.height,
+        filter,
+        chroma.shift(sub_x, sub_y),
+        (0.0, 0.0),
+    )?;
+
+    let mut dst = VideoFrame::new(src.width, src.height, target);
+    dst.color_space = src.color_space;
+    dst.range = src.range;
+    dst.pts = src.pts;
+    dst.plane_mut(0).copy_from_slice(src.plane(0));
+    if src.format.is_semi_planar() {
+        scale_plane(&scaler, src.format, src.plane(1), dst.plane_mut(1), 2);
+    } else {
+        for p in 1..3 {
+            scale_plane(&scaler, src.format, src.plane(p), dst.plane_mut(p), 1);
+        }
+    }
+    Ok(dst)
 }
 
 // ============================================================================
@@ -555,18 +1596,16 @@ impl Scaler {
 // ============================================================================
 
 pub fn pixel_convert_info(format: String) -> serde_json::Value {
-    let pf = match format.as_str() {
-        "nv12" => PixelFormat::NV12,
-        "yuv420p" => PixelFormat::YUV420P,
-        "rgb24" => PixelFormat::RGB24,
-        "rgba" => PixelFormat::RGBA32,
-        _ => return serde_json::json!({"error": "Unknown format"}),
+    let Some(pf) = PixelFormat::from_name(&format) else {
+        return serde_json::json!({"error": "Unknown format"});
     };
 
     serde_json::json!({
         "format": format,
         "is_yuv": pf.is_yuv(),
         "is_rgb": pf.is_rgb(),
+        "bit_depth": pf.bit_depth(),
+        "semi_planar": pf.is_semi_planar(),
         "bytes_per_pixel": pf.bytes_per_pixel(),
         "buffer_size_1080p": pf.buffer_size(1920, 1080),
     })
@@ -599,6 +1638,18 @@ SUPPORTED CONVERSIONS:
 • NV12 → RGB24
 • YUV420P → RGBA (software decoder output)
 • YUV420P → RGB24
+• Any 8/10/12-bit YUV 4:2:0 / 4:2:2 / 4:4:4, planar or
+  semi-planar (P010/P012/P210/P410...) → RGB24/RGBA/BGR/BGRA
+  with ordered dithering, or → RGBA16F (half float) for the renderer
+• Limited (TV) and full (PC) range
+
+SCALING (separable, precomputed kernels):
+• Nearest, Bilinear
+• Bicubic (B/C: Mitchell, Catmull-Rom, B-spline)
+• Lanczos 2/3/4, Spline36
+• Area averaging for downscale
+• YUV420P/422P/444P, NV12, 10-bit (YUV420P10LE, P010), RGB
+• Chroma-siting-aware 4:2:0/4:2:2 → 4:4:4 upsampling
 
 COLOR SPACES:
 • BT.601 (SD video)
@@ -609,9 +1660,228 @@ OPTIMIZATION:
 • Pre-computed lookup tables
 • Integer-only math in hot path
 • No memory allocations during conversion
+• Row-parallel scaling with the `rayon` feature
 
 For GPU-accelerated conversion, use compute shaders
 (see gpu_orchestrator.rs).
 "#
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [ScaleFilter; 8] = [
+        ScaleFilter::Nearest,
+        ScaleFilter::Bilinear,
+        ScaleFilter::MITCHELL,
+        ScaleFilter::CATMULL_ROM,
+        ScaleFilter::Lanczos { taps: 2 },
+        ScaleFilter::Lanczos { taps: 4 },
+        ScaleFilter::Spline36,
+        ScaleFilter::Area,
+    ];
+
+    #[test]
+    fn identity_scale_is_lossless() {
+        let src: Vec<u8> = (0..64u32).map(|i| (i * 37 % 256) as u8).collect();
+        // Cubics with B > 0 are not interpolating and soften by design
+        let interpolating = FILTERS
+            .into_iter()
+            .filter(|f| !matches!(f, ScaleFilter::Bicubic { b, .. } if *b > 0.0));
+        for filter in interpolating {
+            let scaler = PlaneScaler::new(8, 8, 8, 8, filter).expect("scaler");
+            let mut dst = vec![0u8; 64];
+            scaler.scale_u8(&src, 8, &mut dst, 8);
+            assert_eq!(dst, src, "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn flat_plane_stays_flat() {
+        let src = vec![700u16; 40 * 30];
+        for filter in FILTERS {
+            for (dw, dh) in [(17, 11), (97, 61)] {
+                let scaler = PlaneScaler::new(40, 30, dw, dh, filter).expect("scaler");
+                let mut dst = vec![0u16; dw * dh];
+                scaler.scale_u16(&src, 40, &mut dst, dw, 10);
+                assert!(dst.iter().all(|&v| v == 700), "{}", filter.name());
+            }
+        }
+    }
+
+    #[test]
+    fn area_downscale_averages_blocks() {
+        // 4x2 source, two 2x2 blocks with averages 10 and 150
+        let src = [0u8, 20, 100, 200, 20, 0, 100, 200];
+        let scaler = PlaneScaler::new(4, 2, 2, 1, ScaleFilter::Area).expect("scaler");
+        let mut dst = [0u8; 2];
+        scaler.scale_u8(&src, 4, &mut dst, 2);
+        assert_eq!(dst, [10, 150]);
+    }
+
+    #[test]
+    fn ringing_is_clamped_to_bit_depth() {
+        // Hard edge upscaled with Lanczos overshoots; output must stay in range
+        let src: Vec<u16> = (0..16).map(|i| if i < 8 { 0 } else { 1023 }).collect();
+        let scaler =
+            PlaneScaler::new(16, 1, 64, 1, ScaleFilter::Lanczos { taps: 4 }).expect("scaler");
+        let mut dst = vec![0u16; 64];
+        scaler.scale_u16(&src, 16, &mut dst, 64, 10);
+        assert!(dst.iter().all(|&v| v <= 1023));
+        assert_eq!(dst[0], 0);
+        assert_eq!(dst[63], 1023);
+    }
+
+    #[test]
+    fn left_sited_chroma_upsample_hits_cosited_samples() {
+        let mut frame = VideoFrame::new(8, 2, PixelFormat::YUV422P);
+        frame
+            .plane_mut(1)
+            .copy_from_slice(&[0, 100, 200, 100, 0, 100, 200, 100]);
+        frame.plane_mut(2).fill(128);
+
+        let out =
+            upsample_chroma(&frame, ScaleFilter::Bilinear, ChromaLocation::Left).expect("upsample");
+        let u = out.plane(1);
+        // Even luma columns are co-sited with chroma, odd ones interpolate
+        assert_eq!(&u[..6], &[0, 50, 100, 150, 200, 150]);
+        assert!(out.plane(2).iter().all(|&v| v == 128));
+    }
+
+    #[test]
+    fn scale_frame_keeps_planar_layout() {
+        let mut frame = VideoFrame::new(64, 32, PixelFormat::YUV420P10LE);
+        for chunk in frame.data.chunks_exact_mut(2) {
+            chunk.copy_from_slice(&512u16.to_le_bytes());
+        }
+        let out = scale_frame(
+            &frame,
+            32,
+            16,
+            ScaleFilter::Spline36,
+            ChromaLocation::TopLeft,
+        )
+        .expect("scale");
+        assert_eq!(out.data.len(), PixelFormat::YUV420P10LE.buffer_size(32, 16));
+        assert!(out
+            .data
+            .chunks_exact(2)
+            .all(|c| u16::from_le_bytes([c[0], c[1]]) == 512));
+        assert!(scale_frame(&frame, 31, 16, ScaleFilter::Area, ChromaLocation::Left).is_err());
+    }
+
+    fn fill_u16(frame: &mut VideoFrame, plane: usize, value: u16) {
+        for chunk in frame.plane_mut(plane).chunks_exact_mut(2) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn ten_bit_limited_range_maps_to_full_rgb() {
+        let converter = PixelConverter::new(
+            PixelFormat::YUV420P10LE,
+            PixelFormat::RGB24,
+            4,
+            2,
+            ColorSpace::BT709,
+        )
+        .with_dither(false);
+        for (y, expected) in [(64u16, 0u8), (940, 255), (502, 128)] {
+            let mut src = VideoFrame::new(4, 2, PixelFormat::YUV420P10LE);
+            fill_u16(&mut src, 0, y);
+            fill_u16(&mut src, 1, 512);
+            fill_u16(&mut src, 2, 512);
+            let mut dst = VideoFrame::new(4, 2, PixelFormat::RGB24);
+            converter.convert(&src, &mut dst).expect("convert");
+            assert!(dst.data.iter().all(|&v| v == expected), "Y={}", y);
+        }
+    }
+
+    #[test]
+    fn dithering_preserves_sub_code_levels() {
+        // Y=66 at 10-bit is ~0.58 of an 8-bit code above black
+        let mut src = VideoFrame::new(16, 16, PixelFormat::P010);
+        fill_u16(&mut src, 0, 66 << 6);
+        fill_u16(&mut src, 1, 512 << 6);
+
+        let convert = |dither: bool| {
+            let converter = PixelConverter::new(
+                PixelFormat::P010,
+                PixelFormat::RGB24,
+                16,
+                16,
+                ColorSpace::BT2020,
+            )
+            .with_dither(dither);
+            let mut dst = VideoFrame::new(16, 16, PixelFormat::RGB24);
+            converter.convert(&src, &mut dst).expect("convert");
+            dst.data.iter().map(|&v| v as f32).sum::<f32>() / dst.data.len() as f32
+        };
+
+        let expected = 2.0 / 876.0 * 255.0;
+        assert_eq!(convert(false), 1.0);
+        assert!((convert(true) - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn semi_planar_and_planar_agree_in_half_float() {
+        let (w, h) = (4, 4);
+        let mut planar = VideoFrame::new(w, h, PixelFormat::YUV422P12LE);
+        let mut semi = VideoFrame::new(w, h, PixelFormat::P212);
+        fill_u16(&mut planar, 0, 2000);
+        fill_u16(&mut planar, 1, 1500);
+        fill_u16(&mut planar, 2, 2600);
+        fill_u16(&mut semi, 0, 2000 << 4);
+        for pair in semi.plane_mut(1).chunks_exact_mut(4) {
+            pair[..2].copy_from_slice(&(1500u16 << 4).to_le_bytes());
+            pair[2..].copy_from_slice(&(2600u16 << 4).to_le_bytes());
+        }
+
+        let mut outputs = Vec::new();
+        for src in [&planar, &semi] {
+            let converter =
+                PixelConverter::new(src.format, PixelFormat::RGBA16F, w, h, ColorSpace::BT709);
+            let mut dst = VideoFrame::new(w, h, PixelFormat::RGBA16F);
+            converter.convert(src, &mut dst).expect("convert");
+            outputs.push(dst.data);
+        }
+        assert_eq!(outputs[0], outputs[1]);
+
+        let alpha = f16_to_f32(u16::from_le_bytes([outputs[0][6], outputs[0][7]]));
+        assert_eq!(alpha, 1.0);
+    }
+
+    #[test]
+    fn full_range_uses_whole_code_space() {
+        let converter = PixelConverter::new(
+            PixelFormat::YUV444P,
+            PixelFormat::BGRA32,
+            2,
+            1,
+            ColorSpace::BT601,
+        )
+        .with_range(ColorRange::Full);
+        let mut src = VideoFrame::new(2, 1, PixelFormat::YUV444P);
+        src.data.copy_from_slice(&[0, 255, 128, 128, 128, 128]);
+        let mut dst = VideoFrame::new(2, 1, PixelFormat::BGRA32);
+        converter.convert(&src, &mut dst).expect("convert");
+        assert_eq!(dst.data, [0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn half_float_round_trip() {
+        for v in [0.0f32, 1.0, -2.5, 0.1, 65504.0, 1e-6] {
+            let back = f16_to_f32(f32_to_f16(v));
+            assert!(
+                (back - v).abs() <= v.abs() * 1e-3 + 1e-7,
+                "{} -> {}",
+                v,
+                back
+            );
+        }
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+    }
+}
@@ -1,7 +1,7 @@
 //! GPU Video Rendering via wgpu
 //!
 //! Uploads video frames to GPU textures and renders to screen.
-//! Supports NV12, YUV420, and RGB formats.
+//! Supports NV12, YUV420, RGB and half-float RGBA formats.
 
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -107,6 +107,44 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 }
 "#;
 
+// Half-float frames carry gamma-encoded values. Decode them the same way the
+// Rgba8UnormSrgb texture does in the RGB path so the sRGB surface re-encodes
+// both identically.
+const SHADER_RGB_FLOAT: &str = r#"
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+@group(0) @binding(0) var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1) var s_diffuse: sampler;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let lo = c / 12.92;
+    let hi = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(hi, lo, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let c = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(srgb_to_linear(clamp(c.rgb, vec3<f32>(0.0), vec3<f32>(1.0))), c.a);
+}
+"#;
+
 // ============================================================================
 // Frame Format
 // ============================================================================
@@ -117,6 +155,23 @@ pub enum FrameFormat {
     RGBA32,
     NV12,
     YUV420P,
+    /// RGBA, 16-bit float per channel (little-endian), for 10/12-bit sources
+    RGBA16F,
+}
+
+impl FrameFormat {
+    /// Renderer format for a converted frame, if it can be uploaded directly
+    pub fn from_pixel_format(format: crate::pixel_convert::PixelFormat) -> Option<Self> {
+        use crate::pixel_convert::PixelFormat;
+        match format {
+            PixelFormat::RGB24 => Some(Self::RGB24),
+            PixelFormat::RGBA32 | PixelFormat::R8G8B8A8 => Some(Self::RGBA32),
+            PixelFormat::NV12 => Some(Self::NV12),
+            PixelFormat::YUV420P => Some(Self::YUV420P),
+            PixelFormat::RGBA16F => Some(Self::RGBA16F),
+            _ => None,
+        }
+    }
 }
 
 // ============================================================================
@@ -145,6 +200,9 @@ pub struct GpuRenderer {
     rgb_pipeline: wgpu::RenderPipeline,
     rgb_bind_group_layout: wgpu::BindGroupLayout,
 
+    // Half-float RGB pipeline (shares the RGB bind group layout)
+    float_pipeline: wgpu::RenderPipeline,
+
     // NV12 pipeline
     nv12_pipeline: wgpu::RenderPipeline,
     nv12_bind_group_layout: wgpu::BindGroupLayout,
@@ -358,6 +416,50 @@ impl GpuRenderer {
             cache: None,
         });
 
+        // Half-float RGB pipeline
+        let float_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rgb_float_shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER_RGB_FLOAT.into()),
+        });
+
+        let float_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("rgb_float_pipeline"),
+            layout: Some(&rgb_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &float_shader,
+                entry_point: Some("vs_main"),
+                buffers: std::slice::from_ref(&vertex_layout),
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &float_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
         // NV12 pipeline
         let nv12_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("nv12_shader"),
@@ -430,6 +532,7 @@ impl GpuRenderer {
             surface_config,
             rgb_pipeline,
             rgb_bind_group_layout,
+            float_pipeline,
             nv12_pipeline,
             nv12_bind_group_layout,
             vertex_buffer,
@@ -462,6 +565,9 @@ impl GpuRenderer {
             FrameFormat::NV12 => {
                 self.upload_nv12(frame);
             }
+            FrameFormat::RGBA16F => {
+                self.upload_rgba16f(frame);
+            }
             FrameFormat::YUV420P => {
                 // Convert to RGB first (or implement YUV shader)
                 // For now, skip
@@ -545,6 +651,62 @@ impl GpuRenderer {
         self.current_format = FrameFormat::RGB24;
     }
 
+    fn upload_rgba16f(&mut self, frame: &VideoFrame) {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("video_texture_f16"),
+            size: wgpu::Extent3d {
+                width: frame.width,
+                height: frame.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &frame.data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(8 * frame.width),
+                rows_per_image: Some(frame.height),
+            },
+            wgpu::Extent3d {
+                width: frame.width,
+                height: frame.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.current_bind_group = Some(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("rgb_float_bind_group"),
+            layout: &self.rgb_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        }));
+
+        self.current_format = FrameFormat::RGBA16F;
+    }
+
     fn upload_nv12(&mut self, frame: &VideoFrame) {
         let y_size = (frame.width * frame.height) as usize;
         let uv_width = frame.width / 2;
@@ -679,6 +841,7 @@ impl GpuRenderer {
             if let Some(bind_group) = &self.current_bind_group {
                 let pipeline = match self.current_format {
                     FrameFormat::NV12 => &self.nv12_pipeline,
+                    FrameFormat::RGBA16F => &self.float_pipeline,
                     _ => &self.rgb_pipeline,
                 };
 
@@ -0,0 +1,1682 @@
+//! In-process RTMP publishing
+//!
+//! Publishes H.264/AAC to an RTMP ingest (Twitch, YouTube, nginx-rtmp, ...)
+//! without external tools: simple handshake, chunk stream, the
+//! `connect` / `createStream` / `publish` exchange and FLV tag packaging.
+//! Packets come in as `MuxPacket`s, so demuxed files and the output of the
+//! `encode` encoders can be sent the same way.
+//!
+//! When the connection drops mid-stream the publisher reconnects with
+//! exponential backoff, re-sends metadata and sequence headers and resumes
+//! video at the next keyframe.
+
+use crate::h264_utils;
+use crate::mux::{MuxCodec, MuxPacket, MuxStream, MuxTrackKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Default RTMP port
+pub const RTMP_PORT: u16 = 1935;
+
+const HANDSHAKE_SIZE: usize = 1536;
+/// Chunk size until a Set Chunk Size message says otherwise
+const DEFAULT_CHUNK_SIZE: usize = 128;
+const EXTENDED_TIMESTAMP: u32 = 0xFF_FFFF;
+/// Window for the reported send bitrate
+const BITRATE_WINDOW: Duration = Duration::from_secs(3);
+
+// Message types
+const MSG_SET_CHUNK_SIZE: u8 = 1;
+const MSG_ABORT: u8 = 2;
+const MSG_ACKNOWLEDGEMENT: u8 = 3;
+const MSG_USER_CONTROL: u8 = 4;
+const MSG_WINDOW_ACK_SIZE: u8 = 5;
+const MSG_SET_PEER_BANDWIDTH: u8 = 6;
+const MSG_AUDIO: u8 = 8;
+const MSG_VIDEO: u8 = 9;
+const MSG_DATA_AMF0: u8 = 18;
+const MSG_COMMAND_AMF0: u8 = 20;
+
+// Chunk stream ids
+const CSID_CONTROL: u32 = 2;
+const CSID_COMMAND: u32 = 3;
+const CSID_AUDIO: u32 = 4;
+const CSID_DATA: u32 = 5;
+const CSID_VIDEO: u32 = 6;
+
+// ============================================================================
+// AMF0
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Amf0Value {
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    Object(Vec<(String, Amf0Value)>),
+    Null,
+    Undefined,
+    EcmaArray(Vec<(String, Amf0Value)>),
+    StrictArray(Vec<Amf0Value>),
+}
+
+impl Amf0Value {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Number(n) => {
+                out.push(0x00);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Self::Boolean(b) => out.extend_from_slice(&[0x01, *b as u8]),
+            Self::String(s) if s.len() > u16::MAX as usize => {
+                out.push(0x0C);
+                out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+            Self::String(s) => {
+                out.push(0x02);
+                put_amf_key(out, s);
+            }
+            Self::Object(props) => {
+                out.push(0x03);
+                put_amf_props(out, props);
+            }
+            Self::Null => out.push(0x05),
+            Self::Undefined => out.push(0x06),
+            Self::EcmaArray(props) => {
+                out.push(0x08);
+                out.extend_from_slice(&(props.len() as u32).to_be_bytes());
+                put_amf_props(out, props);
+            }
+            Self::StrictArray(values) => {
+                out.push(0x0A);
+                out.extend_from_slice(&(values.len() as u32).to_be_bytes());
+                for value in values {
+                    value.encode(out);
+                }
+            }
+        }
+    }
+
+    /// Decode one value from the front of `data`, advancing it
+    pub fn decode(data: &mut &[u8]) -> Result<Self, String> {
+        let marker = take(data, 1)?[0];
+        Ok(match marker {
+            0x00 => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(take(data, 8)?);
+                Self::Number(f64::from_be_bytes(bytes))
+            }
+            0x01 => Self::Boolean(take(data, 1)?[0] != 0),
+            0x02 => Self::String(take_amf_key(data)?),
+            0x03 => Self::Object(take_amf_props(data)?),
+            0x05 => Self::Null,
+            0x06 => Self::Undefined,
+            0x08 => {
+                take(data, 4)?;
+                Self::EcmaArray(take_amf_props(data)?)
+            }
+            0x0A => {
+                let count = be_u32(take(data, 4)?);
+                let mut values = Vec::new();
+                for _ in 0..count {
+                    values.push(Self::decode(data)?);
+                }
+                Self::StrictArray(values)
+            }
+            0x0C => {
+                let len = be_u32(take(data, 4)?) as usize;
+                Self::String(String::from_utf8_lossy(take(data, len)?).into_owned())
+            }
+            other => return Err(format!("Unsupported AMF0 marker 0x{:02X}", other)),
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Property of an object or ECMA array
+    pub fn get(&self, key: &str) -> Option<&Amf0Value> {
+        match self {
+            Self::Object(props) | Self::EcmaArray(props) => {
+                props.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn put_amf_key(out: &mut Vec<u8>, key: &str) {
+    let bytes = &key.as_bytes()[..key.len().min(u16::MAX as usize)];
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn put_amf_props(out: &mut Vec<u8>, props: &[(String, Amf0Value)]) {
+    for (key, value) in props {
+        put_amf_key(out, key);
+        value.encode(out);
+    }
+    out.extend_from_slice(&[0, 0, 0x09]);
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if data.len() < len {
+        return Err("Truncated AMF0 data".to_string());
+    }
+    let (head, rest) = data.split_at(len);
+    *data = rest;
+    Ok(head)
+}
+
+fn take_amf_key(data: &mut &[u8]) -> Result<String, String> {
+    let len = u16::from_be_bytes([take(data, 1)?[0], take(data, 1)?[0]]) as usize;
+    Ok(String::from_utf8_lossy(take(data, len)?).into_owned())
+}
+
+fn take_amf_props(data: &mut &[u8]) -> Result<Vec<(String, Amf0Value)>, String> {
+    let mut props = Vec::new();
+    loop {
+        let key = take_amf_key(data)?;
+        if key.is_empty() && data.first() == Some(&0x09) {
+            take(data, 1)?;
+            return Ok(props);
+        }
+        props.push((key, Amf0Value::decode(data)?));
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32)
+}
+
+/// Encode a command or data message body
+pub fn encode_amf0(values: &[Amf0Value]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in values {
+        value.encode(&mut out);
+    }
+    out
+}
+
+/// Decode every value of a command or data message body
+pub fn decode_amf0(mut data: &[u8]) -> Result<Vec<Amf0Value>, String> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        values.push(Amf0Value::decode(&mut data)?);
+    }
+    Ok(values)
+}
+
+fn string(s: &str) -> Amf0Value {
+    Amf0Value::String(s.to_string())
+}
+
+// ============================================================================
+// Chunk Stream
+// ============================================================================
+
+/// A complete (reassembled) RTMP message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtmpMessage {
+    pub type_id: u8,
+    pub stream_id: u32,
+    pub timestamp: u32,
+    pub payload: Vec<u8>,
+}
+
+/// Splits messages into chunks. Every message starts with a full (type 0)
+/// header; its later chunks use type 3.
+pub struct ChunkWriter {
+    chunk_size: usize,
+}
+
+impl Default for ChunkWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkWriter {
+    pub fn new() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Chunk size used from the next message on (announce it first with
+    /// `set_chunk_size_message`)
+    pub fn set_chunk_size(&mut self, size: usize) {
+        self.chunk_size = size.clamp(1, 0x7FFF_FFFF);
+    }
+
+    pub fn encode(&self, csid: u32, message: &RtmpMessage) -> Vec<u8> {
+        let extended = message.timestamp >= EXTENDED_TIMESTAMP;
+        let chunks = message.payload.len().div_ceil(self.chunk_size).max(1);
+        let mut out = Vec::with_capacity(message.payload.len() + 16 + chunks * 5);
+
+        put_basic_header(&mut out, 0, csid);
+        let ts_field = message.timestamp.min(EXTENDED_TIMESTAMP);
+        out.extend_from_slice(&ts_field.to_be_bytes()[1..]);
+        out.extend_from_slice(&(message.payload.len() as u32).to_be_bytes()[1..]);
+        out.push(message.type_id);
+        out.extend_from_slice(&message.stream_id.to_le_bytes());
+        if extended {
+            out.extend_from_slice(&message.timestamp.to_be_bytes());
+        }
+
+        for (i, chunk) in message.payload.chunks(self.chunk_size).enumerate() {
+            if i > 0 {
+                put_basic_header(&mut out, 3, csid);
+                if extended {
+                    out.extend_from_slice(&message.timestamp.to_be_bytes());
+                }
+            }
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+}
+
+fn put_basic_header(out: &mut Vec<u8>, fmt: u8, csid: u32) {
+    match csid {
+        2..=63 => out.push((fmt << 6) | csid as u8),
+        64..=319 => out.extend_from_slice(&[fmt << 6, (csid - 64) as u8]),
+        _ => {
+            let id = csid.saturating_sub(64).min(0xFFFF);
+            out.extend_from_slice(&[(fmt << 6) | 1, id as u8, (id >> 8) as u8]);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct ChunkStreamState {
+    timestamp: u32,
+    delta: u32,
+    length: usize,
+    type_id: u8,
+    stream_id: u32,
+    extended: bool,
+    partial: Vec<u8>,
+}
+
+/// Reassembles messages from received bytes. Bytes are pushed as they
+/// arrive; a chunk is only consumed once it is complete, so this works on
+/// blocking and non-blocking sockets alike.
+pub struct ChunkReader {
+    buffer: Vec<u8>,
+    chunk_size: usize,
+    streams: HashMap<u32, ChunkStreamState>,
+    /// Bytes received so far (for acknowledgements)
+    pub bytes_received: u64,
+}
+
+impl Default for ChunkReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkReader {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            streams: HashMap::new(),
+            bytes_received: 0,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.bytes_received += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Next complete message. Set Chunk Size and Abort are applied here and
+    /// also returned.
+    pub fn next_message(&mut self) -> Result<Option<RtmpMessage>, String> {
+        loop {
+            let Some((consumed, message)) = self.parse_chunk()? else {
+                return Ok(None);
+            };
+            self.buffer.drain(..consumed);
+            if let Some(message) = message {
+                match message.type_id {
+                    MSG_SET_CHUNK_SIZE if message.payload.len() >= 4 => {
+                        let size = be_u32(&message.payload[..4]) & 0x7FFF_FFFF;
+                        self.chunk_size = (size as usize).max(1);
+                    }
+                    MSG_ABORT if message.payload.len() >= 4 => {
+                        if let Some(state) = self.streams.get_mut(&be_u32(&message.payload[..4])) {
+                            state.partial.clear();
+                        }
+                    }
+                    _ => {}
+                }
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    /// Parse one chunk from the buffer: bytes used and the message it
+    /// completed, or `None` when the chunk hasn't fully arrived
+    fn parse_chunk(&mut self) -> Result<Option<(usize, Option<RtmpMessage>)>, String> {
+        let buf = &self.buffer;
+        let Some(&first) = buf.first() else {
+            return Ok(None);
+        };
+        let fmt = first >> 6;
+        let (csid, mut pos) = match first & 0x3F {
+            0 if buf.len() >= 2 => (64 + buf[1] as u32, 2),
+            1 if buf.len() >= 3 => (64 + buf[1] as u32 + ((buf[2] as u32) << 8), 3),
+            0 | 1 => return Ok(None),
+            id => (id as u32, 1),
+        };
+        let header_len = [11, 7, 3, 0][fmt as usize];
+        if buf.len() < pos + header_len {
+            return Ok(None);
+        }
+        let header = &buf[pos..pos + header_len];
+        pos += header_len;
+
+        let mut state = match self.streams.get(&csid) {
+            Some(state) => state.clone(),
+            None if fmt == 0 => ChunkStreamState::default(),
+            None => return Err(format!("Chunk stream {} continues without a header", csid)),
+        };
+        let starting = state.partial.is_empty();
+        let ts_field = (fmt < 3).then(|| be_u32(&header[..3]));
+        if fmt <= 1 {
+            state.length = be_u32(&header[3..6]) as usize;
+            state.type_id = header[6];
+        }
+        if fmt == 0 {
+            state.stream_id = u32::from_le_bytes([header[7], header[8], header[9], header[10]]);
+        }
+        if let Some(field) = ts_field {
+            state.extended = field == EXTENDED_TIMESTAMP;
+        }
+        let extended_value = if state.extended {
+            if buf.len() < pos + 4 {
+                return Ok(None);
+            }
+            let value = be_u32(&buf[pos..pos + 4]);
+            pos += 4;
+            Some(value)
+        } else {
+            None
+        };
+        if starting {
+            match (fmt, ts_field) {
+                (0, Some(field)) => {
+                    state.timestamp = extended_value.unwrap_or(field);
+                    state.delta = 0;
+                }
+                (_, Some(field)) => {
+                    state.delta = extended_value.unwrap_or(field);
+                    state.timestamp = state.timestamp.wrapping_add(state.delta);
+                }
+                _ => state.timestamp = state.timestamp.wrapping_add(state.delta),
+            }
+        }
+
+        let want = (state.length - state.partial.len()).min(self.chunk_size);
+        if buf.len() < pos + want {
+            return Ok(None);
+        }
+        state.partial.extend_from_slice(&buf[pos..pos + want]);
+        pos += want;
+
+        let message = (state.partial.len() >= state.length).then(|| RtmpMessage {
+            type_id: state.type_id,
+            stream_id: state.stream_id,
+            timestamp: state.timestamp,
+            payload: std::mem::take(&mut state.partial),
+        });
+        self.streams.insert(csid, state);
+        Ok(Some((pos, message)))
+    }
+}
+
+/// Set Chunk Size protocol message
+pub fn set_chunk_size_message(size: u32) -> RtmpMessage {
+    RtmpMessage {
+        type_id: MSG_SET_CHUNK_SIZE,
+        stream_id: 0,
+        timestamp: 0,
+        payload: (size & 0x7FFF_FFFF).to_be_bytes().to_vec(),
+    }
+}
+
+/// AMF0 command message
+pub fn command_message(stream_id: u32, values: &[Amf0Value]) -> RtmpMessage {
+    RtmpMessage {
+        type_id: MSG_COMMAND_AMF0,
+        stream_id,
+        timestamp: 0,
+        payload: encode_amf0(values),
+    }
+}
+
+// ============================================================================
+// URL
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtmpUrl {
+    pub host: String,
+    pub port: u16,
+    /// Application path ("live", "app/instance")
+    pub app: String,
+    /// `rtmp://host[:port]/app`, sent as `tcUrl`
+    pub tc_url: String,
+}
+
+/// Parse `rtmp://host[:port]/app[/instance]`
+pub fn parse_rtmp_url(url: &str) -> Result<RtmpUrl, String> {
+    let rest = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("rtmp") => rest,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "Unsupported RTMP scheme {}:// (only rtmp:// is supported)",
+                scheme
+            ))
+        }
+        None => return Err(format!("Not an RTMP URL: {}", url)),
+    };
+    let (authority, app) = rest.split_once('/').unwrap_or((rest, ""));
+    let app = app.trim_end_matches('/');
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(']') || authority.starts_with('[') => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| format!("Invalid RTMP port in {}", url))?,
+        ),
+        _ => (authority, RTMP_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("No host in RTMP URL {}", url));
+    }
+    if app.is_empty() {
+        return Err(format!("No application in RTMP URL {}", url));
+    }
+    Ok(RtmpUrl {
+        host: host.to_string(),
+        port,
+        app: app.to_string(),
+        tc_url: format!("rtmp://{}/{}", authority, app),
+    })
+}
+
+// ============================================================================
+// FLV Tags
+// ============================================================================
+
+/// FLV AVC sequence header (AVCDecoderConfigurationRecord)
+fn avc_sequence_header(avcc: &[u8]) -> Vec<u8> {
+    let mut tag = vec![0x17, 0x00, 0, 0, 0];
+    tag.extend_from_slice(avcc);
+    tag
+}
+
+/// FLV AVC NALU tag; `data` is length-prefixed as the sequence header says
+fn avc_nalu(keyframe: bool, composition_ms: i32, data: &[u8]) -> Vec<u8> {
+    let mut tag = vec![if keyframe { 0x17 } else { 0x27 }, 0x01];
+    tag.extend_from_slice(&composition_ms.to_be_bytes()[1..]);
+    tag.extend_from_slice(data);
+    tag
+}
+
+/// AAC, 44 kHz / 16-bit / stereo flags (fixed for AAC; the real format is in
+/// the AudioSpecificConfig)
+const AAC_TAG_HEADER: u8 = 0xAF;
+
+fn aac_tag(packet_type: u8, data: &[u8]) -> Vec<u8> {
+    let mut tag = vec![AAC_TAG_HEADER, packet_type];
+    tag.extend_from_slice(data);
+    tag
+}
+
+/// Split an ADTS frame into its AudioSpecificConfig and raw payload
+pub(crate) fn split_adts(data: &[u8]) -> Option<([u8; 2], &[u8])> {
+    if data.len() < 7 || data[0] != 0xFF || data[1] & 0xF6 != 0xF0 {
+        return None;
+    }
+    let header_len = if data[1] & 0x01 == 0 { 9 } else { 7 };
+    let profile = ((data[2] >> 6) & 0x03) + 1;
+    let rate_index = (data[2] >> 2) & 0x0F;
+    let channels = ((data[2] & 0x01) << 2) | (data[3] >> 6);
+    let asc = [
+        (profile << 3) | (rate_index >> 1),
+        ((rate_index & 1) << 7) | (channels << 3),
+    ];
+    Some((asc, data.get(header_len..)?))
+}
+
+/// How a stream's packets become FLV tags
+#[derive(Debug, Clone)]
+enum TagFraming {
+    /// H.264: the avcC (once known) and the incoming packet layout
+    Avc { avcc: Option<Vec<u8>>, annexb: bool },
+    /// AAC: the AudioSpecificConfig, taken from ADTS headers when missing
+    Aac { asc: Option<Vec<u8>> },
+}
+
+#[derive(Debug, Clone)]
+struct PublishedStream {
+    framing: TagFraming,
+    /// Sequence header sent on the current connection
+    header_sent: bool,
+}
+
+/// Whether RTMP (classic FLV) can carry a stream
+pub fn supports(stream: &MuxStream) -> bool {
+    matches!(stream.codec, MuxCodec::H264 | MuxCodec::AAC)
+}
+
+// ============================================================================
+// Publisher
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RtmpConfig {
+    /// `rtmp://host[:port]/app`
+    pub url: String,
+    pub stream_key: String,
+    /// Outgoing chunk size
+    pub chunk_size: u32,
+    /// Connect and I/O timeout
+    pub timeout_secs: u64,
+    /// Reconnect attempts after the connection drops mid-stream (0 disables)
+    pub max_reconnects: u32,
+    /// First reconnect delay; doubles per attempt up to 30 s
+    pub reconnect_delay_ms: u64,
+}
+
+impl Default for RtmpConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            stream_key: String::new(),
+            chunk_size: 4096,
+            timeout_secs: 10,
+            max_reconnects: 5,
+            reconnect_delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RtmpStats {
+    pub bytes_sent: u64,
+    pub video_frames: u64,
+    pub audio_frames: u64,
+    /// Frames dropped while waiting for a sequence header or, after a
+    /// reconnect, for the next keyframe
+    pub dropped_frames: u64,
+    pub reconnects: u32,
+    /// Send rate over the last few seconds
+    pub bitrate_bps: u64,
+    pub uptime_secs: f64,
+    /// Timestamp of the last message sent
+    pub stream_time_ms: u64,
+}
+
+/// One live connection after a successful `publish`
+struct Connection {
+    socket: TcpStream,
+    writer: ChunkWriter,
+    reader: ChunkReader,
+    stream_id: u32,
+    /// Acknowledgement window the server asked for, and the last ack sent
+    window_ack_size: u64,
+    acked: u64,
+}
+
+impl Connection {
+    fn open(url: &RtmpUrl, config: &RtmpConfig) -> Result<Self, String> {
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+        let addrs = (url.host.as_str(), url.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve {}: {}", url.host, e))?;
+        let mut last_error = format!("No address for {}", url.host);
+        let mut socket = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(s) => {
+                    socket = Some(s);
+                    break;
+                }
+                Err(e) => last_error = format!("Connect to {} failed: {}", addr, e),
+            }
+        }
+        let socket = socket.ok_or(last_error)?;
+        socket.set_nodelay(true).ok();
+        socket
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| socket.set_write_timeout(Some(timeout)))
+            .map_err(|e| format!("Socket setup failed: {}", e))?;
+
+        let mut connection = Self {
+            socket,
+            writer: ChunkWriter::new(),
+            reader: ChunkReader::new(),
+            stream_id: 0,
+            window_ack_size: 0,
+            acked: 0,
+        };
+        connection.handshake()?;
+        connection.send(
+            CSID_CONTROL,
+            &set_chunk_size_message(config.chunk_size.max(128)),
+        )?;
+        connection
+            .writer
+            .set_chunk_size(config.chunk_size.max(128) as usize);
+        connection.publish(url, &config.stream_key)?;
+        Ok(connection)
+    }
+
+    /// Simple (non-digest) handshake
+    fn handshake(&mut self) -> Result<(), String> {
+        let mut c1 = vec![0u8; HANDSHAKE_SIZE];
+        // time, zero, then filler
+        let mut seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(1)
+            | 1;
+        for byte in &mut c1[8..] {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            *byte = seed as u8;
+        }
+        let mut c0c1 = vec![3u8];
+        c0c1.extend_from_slice(&c1);
+        self.socket
+            .write_all(&c0c1)
+            .map_err(|e| format!("Handshake failed: {}", e))?;
+
+        let mut s0s1 = vec![0u8; 1 + HANDSHAKE_SIZE];
+        self.socket
+            .read_exact(&mut s0s1)
+            .map_err(|e| format!("Handshake failed: {}", e))?;
+        if s0s1[0] != 3 {
+            return Err(format!("Unsupported RTMP version {}", s0s1[0]));
+        }
+        // C2 echoes S1
+        self.socket
+            .write_all(&s0s1[1..])
+            .map_err(|e| format!("Handshake failed: {}", e))?;
+        let mut s2 = vec![0u8; HANDSHAKE_SIZE];
+        self.socket
+            .read_exact(&mut s2)
+            .map_err(|e| format!("Handshake failed: {}", e))
+    }
+
+    fn send(&mut self, csid: u32, message: &RtmpMessage) -> Result<usize, String> {
+        let bytes = self.writer.encode(csid, message);
+        self.socket
+            .write_all(&bytes)
+            .map_err(|e| format!("RTMP send failed: {}", e))?;
+        Ok(bytes.len())
+    }
+
+    fn command(&mut self, values: &[Amf0Value]) -> Result<(), String> {
+        let message = command_message(self.stream_id, values);
+        self.send(CSID_COMMAND, &message).map(|_| ())
+    }
+
+    /// Block until a message arrives, answering protocol messages on the way
+    fn receive(&mut self) -> Result<RtmpMessage, String> {
+        let mut buf = [0u8; 8192];
+        loop {
+            if let Some(message) = self.reader.next_message()? {
+                if self.handle_protocol(&message)? {
+                    continue;
+                }
+                return Ok(message);
+            }
+            let n = self
+                .socket
+                .read(&mut buf)
+                .map_err(|e| format!("RTMP receive failed: {}", e))?;
+            if n == 0 {
+                return Err("Server closed the connection".to_string());
+            }
+            self.reader.push(&buf[..n]);
+            self.acknowledge()?;
+        }
+    }
+
+    /// Read whatever has arrived without blocking; errors come from the
+    /// server (`onStatus` failures) or a dead socket
+    fn poll(&mut self) -> Result<(), String> {
+        let mut buf = [0u8; 8192];
+        self.socket.set_nonblocking(true).ok();
+        let result = loop {
+            match self.socket.read(&mut buf) {
+                Ok(0) => break Err("Server closed the connection".to_string()),
+                Ok(n) => self.reader.push(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => break Err(format!("RTMP receive failed: {}", e)),
+            }
+        };
+        self.socket.set_nonblocking(false).ok();
+        result?;
+        self.acknowledge()?;
+        while let Some(message) = self.reader.next_message()? {
+            if self.handle_protocol(&message)? {
+                continue;
+            }
+            if message.type_id == MSG_COMMAND_AMF0 {
+                let values = decode_amf0(&message.payload)?;
+                if values.first().and_then(Amf0Value::as_str) == Some("onStatus") {
+                    status_result(&values)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn acknowledge(&mut self) -> Result<(), String> {
+        let received = self.reader.bytes_received;
+        if self.window_ack_size > 0 && received - self.acked >= self.window_ack_size {
+            self.acked = received;
+            let message = RtmpMessage {
+                type_id: MSG_ACKNOWLEDGEMENT,
+                stream_id: 0,
+                timestamp: 0,
+                payload: (received as u32).to_be_bytes().to_vec(),
+            };
+            self.send(CSID_CONTROL, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Handle protocol control messages; true when consumed
+    fn handle_protocol(&mut self, message: &RtmpMessage) -> Result<bool, String> {
+        match message.type_id {
+            MSG_SET_CHUNK_SIZE | MSG_ABORT | MSG_ACKNOWLEDGEMENT | MSG_SET_PEER_BANDWIDTH => {
+                Ok(true)
+            }
+            MSG_WINDOW_ACK_SIZE if message.payload.len() >= 4 => {
+                self.window_ack_size = be_u32(&message.payload[..4]) as u64;
+                Ok(true)
+            }
+            MSG_USER_CONTROL if message.payload.len() >= 2 => {
+                // Ping request: answer with a ping response carrying its time
+                if message.payload[..2] == [0, 6] {
+                    let mut payload = vec![0, 7];
+                    payload.extend_from_slice(&message.payload[2..]);
+                    let pong = RtmpMessage {
+                        type_id: MSG_USER_CONTROL,
+                        stream_id: 0,
+                        timestamp: 0,
+                        payload,
+                    };
+                    self.send(CSID_CONTROL, &pong)?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Wait for the `_result` / `_error` of transaction `transaction`
+    fn wait_result(&mut self, transaction: f64) -> Result<Vec<Amf0Value>, String> {
+        loop {
+            let message = self.receive()?;
+            if message.type_id != MSG_COMMAND_AMF0 {
+                continue;
+            }
+            let values = decode_amf0(&message.payload)?;
+            let name = values.first().and_then(Amf0Value::as_str).unwrap_or("");
+            let id = values.get(1).and_then(Amf0Value::as_number);
+            match name {
+                "_result" if id == Some(transaction) => return Ok(values),
+                "_error" if id == Some(transaction) => {
+                    let info = values.get(3).or(values.get(2));
+                    return Err(format!(
+                        "Server refused the request: {}",
+                        describe_status(info)
+                    ));
+                }
+                "onStatus" => {
+                    status_result(&values)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn publish(&mut self, url: &RtmpUrl, stream_key: &str) -> Result<(), String> {
+        self.command(&[
+            string("connect"),
+            Amf0Value::Number(1.0),
+            Amf0Value::Object(vec![
+                ("app".to_string(), string(&url.app)),
+                ("type".to_string(), string("nonprivate")),
+                (
+                    "flashVer".to_string(),
+                    string("FMLE/3.0 (compatible; SLAIN)"),
+                ),
+                ("tcUrl".to_string(), string(&url.tc_url)),
+            ]),
+        ])?;
+        self.wait_result(1.0)?;
+
+        self.command(&[
+            string("releaseStream"),
+            Amf0Value::Number(2.0),
+            Amf0Value::Null,
+            string(stream_key),
+        ])?;
+        self.command(&[
+            string("FCPublish"),
+            Amf0Value::Number(3.0),
+            Amf0Value::Null,
+            string(stream_key),
+        ])?;
+        self.command(&[
+            string("createStream"),
+            Amf0Value::Number(4.0),
+            Amf0Value::Null,
+        ])?;
+        let result = self.wait_result(4.0)?;
+        let stream_id = result
+            .get(3)
+            .and_then(Amf0Value::as_number)
+            .ok_or("createStream returned no stream id")?;
+        self.stream_id = stream_id as u32;
+
+        self.command(&[
+            string("publish"),
+            Amf0Value::Number(5.0),
+            Amf0Value::Null,
+            string(stream_key),
+            string("live"),
+        ])?;
+        loop {
+            let message = self.receive()?;
+            if message.type_id != MSG_COMMAND_AMF0 {
+                continue;
+            }
+            let values = decode_amf0(&message.payload)?;
+            if values.first().and_then(Amf0Value::as_str) != Some("onStatus") {
+                continue;
+            }
+            if status_result(&values)? == "NetStream.Publish.Start" {
+                return Ok(());
+            }
+        }
+    }
+
+    fn close(&mut self, stream_key: &str) {
+        let _ = self.command(&[
+            string("FCUnpublish"),
+            Amf0Value::Number(6.0),
+            Amf0Value::Null,
+            string(stream_key),
+        ]);
+        let _ = self.command(&[
+            string("deleteStream"),
+            Amf0Value::Number(7.0),
+            Amf0Value::Null,
+            Amf0Value::Number(self.stream_id as f64),
+        ]);
+        let _ = self.socket.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+fn describe_status(info: Option<&Amf0Value>) -> String {
+    let field = |key: &str| info.and_then(|i| i.get(key)).and_then(Amf0Value::as_str);
+    match (field("code"), field("description")) {
+        (Some(code), Some(description)) => format!("{} ({})", code, description),
+        (Some(code), None) => code.to_string(),
+        (None, Some(description)) => description.to_string(),
+        (None, None) => "no details".to_string(),
+    }
+}
+
+/// The code of an `onStatus`, or an error for failure levels/codes
+fn status_result(values: &[Amf0Value]) -> Result<String, String> {
+    let info = values.get(3).or(values.get(2));
+    let code = info
+        .and_then(|i| i.get("code"))
+        .and_then(Amf0Value::as_str)
+        .unwrap_or("")
+        .to_string();
+    let level = info
+        .and_then(|i| i.get("level"))
+        .and_then(Amf0Value::as_str)
+        .unwrap_or("");
+    if level == "error"
+        || ["Failed", "BadName", "Rejected", "Error"]
+            .iter()
+            .any(|w| code.contains(w))
+    {
+        return Err(format!("Publish failed: {}", describe_status(info)));
+    }
+    Ok(code)
+}
+
+/// RTMP publisher fed with `MuxPacket`s (microsecond timestamps, decode
+/// order). H.264 may be Annex B or length-prefixed as its avcC says; AAC may
+/// be raw or ADTS. Other streams are left out.
+pub struct RtmpPublisher {
+    config: RtmpConfig,
+    url: RtmpUrl,
+    streams: Vec<MuxStream>,
+    /// Publish state per input stream (None: not sent)
+    published: Vec<Option<PublishedStream>>,
+    connection: Option<Connection>,
+    /// Decode time that message timestamps count from
+    origin_us: Option<i64>,
+    /// After a reconnect, video waits for the next keyframe
+    waiting_for_keyframe: bool,
+    stats: RtmpStats,
+    started: Instant,
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl RtmpPublisher {
+    /// Connect, publish `config.stream_key` and send the stream metadata
+    pub fn connect(config: RtmpConfig, streams: Vec<MuxStream>) -> Result<Self, String> {
+        let url = parse_rtmp_url(&config.url)?;
+        let published: Vec<Option<PublishedStream>> = streams
+            .iter()
+            .map(|stream| {
+                let config = stream.codec_private.clone();
+                let framing = match stream.codec {
+                    MuxCodec::H264 => match config {
+                        Some(c) if h264_utils::is_annexb(&c) => TagFraming::Avc {
+                            avcc: h264_utils::build_avcc_extradata(&c),
+                            annexb: true,
+                        },
+                        Some(c) => TagFraming::Avc {
+                            avcc: Some(c),
+                            annexb: false,
+                        },
+                        None => TagFraming::Avc {
+                            avcc: None,
+                            annexb: true,
+                        },
+                    },
+                    MuxCodec::AAC => TagFraming::Aac { asc: config },
+                    other => {
+                        tracing::warn!("RTMP: leaving out {:?} stream (H.264/AAC only)", other);
+                        return None;
+                    }
+                };
+                Some(PublishedStream {
+                    framing,
+                    header_sent: false,
+                })
+            })
+            .collect();
+        let video_streams = published
+            .iter()
+            .filter(|p| {
+                matches!(
+                    p,
+                    Some(PublishedStream {
+                        framing: TagFraming::Avc { .. },
+                        ..
+                    })
+                )
+            })
+            .count();
+        let audio_streams = published.iter().flatten().count() - video_streams;
+        if video_streams > 1 || audio_streams > 1 {
+            return Err("RTMP carries one video and one audio stream".to_string());
+        }
+        if video_streams + audio_streams == 0 {
+            return Err("No H.264 or AAC stream to publish".to_string());
+        }
+
+        let connection = Connection::open(&url, &config)?;
+        tracing::info!("RTMP: publishing to {}", url.tc_url);
+        let mut publisher = Self {
+            config,
+            url,
+            streams,
+            published,
+            connection: Some(connection),
+            origin_us: None,
+            waiting_for_keyframe: false,
+            stats: RtmpStats::default(),
+            started: Instant::now(),
+            recent: VecDeque::new(),
+        };
+        publisher.send_metadata()?;
+        Ok(publisher)
+    }
+
+    pub fn stats(&self) -> RtmpStats {
+        let mut stats = self.stats.clone();
+        stats.uptime_secs = self.started.elapsed().as_secs_f64();
+        let window: usize = self.recent.iter().map(|(_, n)| n).sum();
+        let span = self
+            .recent
+            .front()
+            .map(|(t, _)| t.elapsed().max(Duration::from_millis(500)))
+            .unwrap_or(BITRATE_WINDOW);
+        stats.bitrate_bps = (window as f64 * 8.0 / span.as_secs_f64()) as u64;
+        stats
+    }
+
+    /// Send one packet, reconnecting if the connection dropped
+    pub fn write_packet(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        match self.try_write(packet) {
+            Ok(()) => Ok(()),
+            Err(e) if self.config.max_reconnects > 0 => {
+                tracing::warn!("RTMP: {}; reconnecting", e);
+                self.reconnect(&e)?;
+                self.try_write(packet)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `FCUnpublish` + `deleteStream` and close; returns the final stats
+    pub fn finish(mut self) -> RtmpStats {
+        if let Some(mut connection) = self.connection.take() {
+            connection.close(&self.config.stream_key);
+        }
+        tracing::info!(
+            "RTMP: {} video / {} audio frames, {} bytes, {} reconnects",
+            self.stats.video_frames,
+            self.stats.audio_frames,
+            self.stats.bytes_sent,
+            self.stats.reconnects
+        );
+        self.stats()
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection, String> {
+        self.connection
+            .as_mut()
+            .ok_or_else(|| "Not connected".to_string())
+    }
+
+    fn send(&mut self, csid: u32, message: &RtmpMessage) -> Result<(), String> {
+        let sent = self.connection()?.send(csid, message)?;
+        self.stats.bytes_sent += sent as u64;
+        let now = Instant::now();
+        self.recent.push_back((now, sent));
+        while self
+            .recent
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > BITRATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self, cause: &str) -> Result<(), String> {
+        self.connection = None;
+        let mut delay = Duration::from_millis(self.config.reconnect_delay_ms);
+        let mut last_error = cause.to_string();
+        for attempt in 1..=self.config.max_reconnects {
+            std::thread::sleep(delay);
+            match Connection::open(&self.url, &self.config) {
+                Ok(connection) => {
+                    tracing::info!("RTMP: reconnected (attempt {})", attempt);
+                    self.connection = Some(connection);
+                    self.stats.reconnects += 1;
+                    for stream in self.published.iter_mut().flatten() {
+                        stream.header_sent = false;
+                    }
+                    self.waiting_for_keyframe = true;
+                    return self.send_metadata();
+                }
+                Err(e) => {
+                    tracing::warn!("RTMP: reconnect attempt {} failed: {}", attempt, e);
+                    last_error = e;
+                }
+            }
+            delay = (delay * 2).min(Duration::from_secs(30));
+        }
+        Err(format!(
+            "RTMP connection lost ({}); gave up after {} reconnect attempts: {}",
+            cause, self.config.max_reconnects, last_error
+        ))
+    }
+
+    fn send_metadata(&mut self) -> Result<(), String> {
+        let mut props = Vec::new();
+        let number = Amf0Value::Number;
+        for (stream, published) in self.streams.iter().zip(&self.published) {
+            let Some(published) = published else {
+                continue;
+            };
+            match (&stream.kind, &published.framing) {
+                (MuxTrackKind::Video { width, height }, TagFraming::Avc { .. }) => {
+                    props.push(("width".to_string(), number(*width as f64)));
+                    props.push(("height".to_string(), number(*height as f64)));
+                    if let Some(d) = stream.frame_duration_us.filter(|&d| d > 0) {
+                        props.push(("framerate".to_string(), number(1_000_000.0 / d as f64)));
+                    }
+                    props.push(("videocodecid".to_string(), number(7.0)));
+                }
+                (
+                    MuxTrackKind::Audio {
+                        sample_rate,
+                        channels,
+                        ..
+                    },
+                    TagFraming::Aac { .. },
+                ) => {
+                    props.push(("audiocodecid".to_string(), number(10.0)));
+                    props.push(("audiosamplerate".to_string(), number(*sample_rate as f64)));
+                    props.push(("audiosamplesize".to_string(), number(16.0)));
+                    props.push(("stereo".to_string(), Amf0Value::Boolean(*channels > 1)));
+                }
+                _ => {}
+            }
+        }
+        props.push((
+            "encoder".to_string(),
+            string(&format!("SLAIN {}", crate::VERSION)),
+        ));
+        let stream_id = self.connection()?.stream_id;
+        let message = RtmpMessage {
+            type_id: MSG_DATA_AMF0,
+            stream_id,
+            timestamp: 0,
+            payload: encode_amf0(&[
+                string("@setDataFrame"),
+                string("onMetaData"),
+                Amf0Value::EcmaArray(props),
+            ]),
+        };
+        self.send(CSID_DATA, &message)
+    }
+
+    fn try_write(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        let Some(Some(published)) = self.published.get(packet.stream).cloned() else {
+            return Ok(());
+        };
+        let dts = packet.dts_us.unwrap_or(packet.pts_us);
+        let origin = *self.origin_us.get_or_insert(dts);
+        let timestamp = ((dts - origin).max(0) / 1000) as u32;
+        let stream_id = self.connection()?.stream_id;
+        self.connection()?.poll()?;
+
+        match published.framing {
+            TagFraming::Avc { avcc, annexb } => {
+                let avcc = match avcc {
+                    Some(avcc) => Some(avcc),
+                    None if packet.keyframe => h264_utils::build_avcc_extradata(&packet.data),
+                    None => None,
+                };
+                let Some(avcc) = avcc else {
+                    self.stats.dropped_frames += 1;
+                    return Ok(());
+                };
+                if self.waiting_for_keyframe && !packet.keyframe {
+                    self.stats.dropped_frames += 1;
+                    return Ok(());
+                }
+                if !published.header_sent {
+                    let message = RtmpMessage {
+                        type_id: MSG_VIDEO,
+                        stream_id,
+                        timestamp,
+                        payload: avc_sequence_header(&avcc),
+                    };
+                    self.send(CSID_VIDEO, &message)?;
+                    if let Some(Some(p)) = self.published.get_mut(packet.stream) {
+                        p.framing = TagFraming::Avc {
+                            avcc: Some(avcc),
+                            annexb,
+                        };
+                        p.header_sent = true;
+                    }
+                }
+                self.waiting_for_keyframe = false;
+
+                let data = if annexb {
+                    h264_utils::annexb_to_avcc(&packet.data)
+                } else {
+                    packet.data.clone()
+                };
+                let composition_ms = ((packet.pts_us - dts) / 1000) as i32;
+                let message = RtmpMessage {
+                    type_id: MSG_VIDEO,
+                    stream_id,
+                    timestamp,
+                    payload: avc_nalu(packet.keyframe, composition_ms, &data),
+                };
+                self.send(CSID_VIDEO, &message)?;
+                self.stats.video_frames += 1;
+            }
+            TagFraming::Aac { asc } => {
+                let adts = split_adts(&packet.data);
+                let raw = adts.map(|(_, raw)| raw).unwrap_or(&packet.data);
+                let asc = asc.or_else(|| adts.map(|(asc, _)| asc.to_vec()));
+                let Some(asc) = asc else {
+                    self.stats.dropped_frames += 1;
+                    return Ok(());
+                };
+                if !published.header_sent {
+                    let message = RtmpMessage {
+                        type_id: MSG_AUDIO,
+                        stream_id,
+                        timestamp,
+                        payload: aac_tag(0, &asc),
+                    };
+                    self.send(CSID_AUDIO, &message)?;
+                    if let Some(Some(p)) = self.published.get_mut(packet.stream) {
+                        p.framing = TagFraming::Aac { asc: Some(asc) };
+                        p.header_sent = true;
+                    }
+                }
+                let message = RtmpMessage {
+                    type_id: MSG_AUDIO,
+                    stream_id,
+                    timestamp,
+                    payload: aac_tag(1, raw),
+                };
+                self.send(CSID_AUDIO, &message)?;
+                self.stats.audio_frames += 1;
+            }
+        }
+        self.stats.stream_time_ms = timestamp as u64;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_amf0_and_chunks() {
+        let values = vec![
+            string("connect"),
+            Amf0Value::Number(1.0),
+            Amf0Value::Object(vec![
+                ("app".to_string(), string("live")),
+                ("fpad".to_string(), Amf0Value::Boolean(false)),
+                ("x".to_string(), Amf0Value::Null),
+            ]),
+            Amf0Value::EcmaArray(vec![("width".to_string(), Amf0Value::Number(1280.0))]),
+            Amf0Value::StrictArray(vec![Amf0Value::Undefined, string("a")]),
+        ];
+        let encoded = encode_amf0(&values);
+        assert_eq!(decode_amf0(&encoded).unwrap(), values);
+        assert!(decode_amf0(&encoded[..encoded.len() - 2]).is_err());
+
+        let url = parse_rtmp_url("rtmp://live.example.com/app/instance").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("live.example.com", 1935));
+        assert_eq!(url.app, "app/instance");
+        let url = parse_rtmp_url("rtmp://127.0.0.1:1940/live/").unwrap();
+        assert_eq!(
+            (url.port, url.tc_url.as_str()),
+            (1940, "rtmp://127.0.0.1:1940/live")
+        );
+        assert!(parse_rtmp_url("rtmps://a/live").is_err());
+        assert!(parse_rtmp_url("rtmp://host").is_err());
+
+        // Interleaved messages, a chunk size change and an extended timestamp
+        let mut writer = ChunkWriter::new();
+        let big = RtmpMessage {
+            type_id: MSG_VIDEO,
+            stream_id: 1,
+            timestamp: 0x0100_0000,
+            payload: (0..1000u32).map(|i| i as u8).collect(),
+        };
+        let small = RtmpMessage {
+            type_id: MSG_AUDIO,
+            stream_id: 1,
+            timestamp: 40,
+            payload: vec![0xAF, 1, 2, 3],
+        };
+        let mut wire = writer.encode(CSID_VIDEO, &big);
+        wire.extend(writer.encode(CSID_CONTROL, &set_chunk_size_message(300)));
+        writer.set_chunk_size(300);
+        wire.extend(writer.encode(CSID_AUDIO, &small));
+        wire.extend(writer.encode(CSID_VIDEO, &big));
+
+        let mut reader = ChunkReader::new();
+        let mut received = Vec::new();
+        // Byte by byte: partial chunks must wait
+        for byte in wire {
+            reader.push(&[byte]);
+            while let Some(message) = reader.next_message().unwrap() {
+                received.push(message);
+            }
+        }
+        assert_eq!(received.len(), 4);
+        assert_eq!(received[0], big);
+        assert_eq!(received[1].type_id, MSG_SET_CHUNK_SIZE);
+        assert_eq!(received[2], small);
+        assert_eq!(received[3], big);
+    }
+
+    /// What the stand-in server saw on one connection
+    #[derive(Debug, Default)]
+    struct Session {
+        app: String,
+        key: String,
+        /// (type, timestamp, payload) of audio, video and data messages
+        media: Vec<(u8, u32, Vec<u8>)>,
+    }
+
+    /// Minimal RTMP ingest: handshake, connect/createStream/publish, then
+    /// record media. Each entry of `drop_after` closes that connection after
+    /// so many media messages.
+    fn stand_in_server(drop_after: Vec<Option<usize>>) -> (u16, mpsc::Receiver<Session>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for limit in drop_after {
+                let Ok((mut socket, _)) = listener.accept() else {
+                    return;
+                };
+                let session = serve_session(&mut socket, limit);
+                if tx.send(session).is_err() {
+                    return;
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    fn serve_session(socket: &mut TcpStream, limit: Option<usize>) -> Session {
+        let mut session = Session::default();
+        let mut c0c1 = vec![0u8; 1 + HANDSHAKE_SIZE];
+        socket.read_exact(&mut c0c1).unwrap();
+        let mut reply = vec![3u8];
+        reply.extend(vec![7u8; HANDSHAKE_SIZE]);
+        reply.extend_from_slice(&c0c1[1..]);
+        socket.write_all(&reply).unwrap();
+        let mut c2 = vec![0u8; HANDSHAKE_SIZE];
+        socket.read_exact(&mut c2).unwrap();
+
+        let writer = ChunkWriter::new();
+        let mut reader = ChunkReader::new();
+        let mut buf = [0u8; 4096];
+        let send = |socket: &mut TcpStream, values: &[Amf0Value], stream_id: u32| {
+            let bytes = writer.encode(CSID_COMMAND, &command_message(stream_id, values));
+            socket.write_all(&bytes).unwrap();
+        };
+        // A window ack size and a ping up front, as real servers do
+        let window = RtmpMessage {
+            type_id: MSG_WINDOW_ACK_SIZE,
+            stream_id: 0,
+            timestamp: 0,
+            payload: 2_500_000u32.to_be_bytes().to_vec(),
+        };
+        let ping = RtmpMessage {
+            type_id: MSG_USER_CONTROL,
+            stream_id: 0,
+            timestamp: 0,
+            payload: vec![0, 6, 0, 0, 0, 9],
+        };
+        socket
+            .write_all(&writer.encode(CSID_CONTROL, &window))
+            .unwrap();
+        socket
+            .write_all(&writer.encode(CSID_CONTROL, &ping))
+            .unwrap();
+
+        loop {
+            let message = match reader.next_message().unwrap() {
+                Some(message) => message,
+                None => match socket.read(&mut buf) {
+                    Ok(0) | Err(_) => return session,
+                    Ok(n) => {
+                        reader.push(&buf[..n]);
+                        continue;
+                    }
+                },
+            };
+            match message.type_id {
+                MSG_COMMAND_AMF0 => {
+                    let values = decode_amf0(&message.payload).unwrap();
+                    let name = values[0].as_str().unwrap().to_string();
+                    let id = values[1].clone();
+                    match name.as_str() {
+                        "connect" => {
+                            session.app = values[2].get("app").unwrap().as_str().unwrap().into();
+                            let info = Amf0Value::Object(vec![(
+                                "code".to_string(),
+                                string("NetConnection.Connect.Success"),
+                            )]);
+                            send(socket, &[string("_result"), id, Amf0Value::Null, info], 0);
+                        }
+                        "createStream" => send(
+                            socket,
+                            &[
+                                string("_result"),
+                                id,
+                                Amf0Value::Null,
+                                Amf0Value::Number(1.0),
+                            ],
+                            0,
+                        ),
+                        "publish" => {
+                            session.key = values[3].as_str().unwrap().into();
+                            let code = if session.key == "bad" {
+                                "NetStream.Publish.BadName"
+                            } else {
+                                "NetStream.Publish.Start"
+                            };
+                            let info = Amf0Value::Object(vec![
+                                ("level".to_string(), string("status")),
+                                ("code".to_string(), string(code)),
+                            ]);
+                            send(
+                                socket,
+                                &[
+                                    string("onStatus"),
+                                    Amf0Value::Number(0.0),
+                                    Amf0Value::Null,
+                                    info,
+                                ],
+                                1,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                MSG_AUDIO | MSG_VIDEO | MSG_DATA_AMF0 => {
+                    session
+                        .media
+                        .push((message.type_id, message.timestamp, message.payload));
+                    if limit.is_some_and(|limit| session.media.len() >= limit) {
+                        return session;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    const SPS: [u8; 6] = [0x67, 0x64, 0x00, 0x1F, 0xAC, 0xD9];
+    const PPS: [u8; 4] = [0x68, 0xEB, 0xE3, 0xCB];
+
+    /// Annex B H.264 (parameter sets in-band, keyframe every 10) and ADTS AAC
+    fn packets(count: i64) -> Vec<MuxPacket> {
+        let mut packets = Vec::new();
+        for i in 0..count {
+            let keyframe = i % 10 == 0;
+            let mut data = Vec::new();
+            if keyframe {
+                for nal in [&SPS[..], &PPS[..]] {
+                    data.extend_from_slice(&[0, 0, 0, 1]);
+                    data.extend_from_slice(nal);
+                }
+            }
+            data.extend_from_slice(&[0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }]);
+            data.extend(std::iter::repeat_n(0x11, 300));
+            packets.push(MuxPacket {
+                stream: 0,
+                // One frame of B-frame delay
+                pts_us: i * 40_000 + 40_000,
+                dts_us: Some(i * 40_000),
+                duration_us: Some(40_000),
+                keyframe,
+                data,
+            });
+            // ADTS: AAC LC, 48 kHz (index 3), stereo, 7-byte header
+            let frame_len = 7 + 50;
+            let mut adts = vec![
+                0xFF,
+                0xF1,
+                (1 << 6) | (3 << 2),
+                (2 << 6) | ((frame_len >> 11) as u8 & 0x03),
+                (frame_len >> 3) as u8,
+                ((frame_len as u8 & 0x07) << 5) | 0x1F,
+                0xFC,
+            ];
+            adts.extend(std::iter::repeat_n(0x22, 50));
+            packets.push(MuxPacket {
+                stream: 1,
+                pts_us: i * 40_000,
+                dts_us: Some(i * 40_000),
+                duration_us: Some(40_000),
+                keyframe: true,
+                data: adts,
+            });
+        }
+        packets
+    }
+
+    fn streams() -> Vec<MuxStream> {
+        let mut video = MuxStream::video(MuxCodec::H264, 1280, 720);
+        video.frame_duration_us = Some(40_000);
+        vec![
+            video,
+            MuxStream::audio(MuxCodec::AAC, 48000, 2),
+            MuxStream::subtitle(MuxCodec::SubRip),
+        ]
+    }
+
+    fn config(port: u16, key: &str) -> RtmpConfig {
+        RtmpConfig {
+            url: format!("rtmp://127.0.0.1:{}/live", port),
+            stream_key: key.to_string(),
+            reconnect_delay_ms: 50,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_publish_flv_tags() {
+        let (port, sessions) = stand_in_server(vec![None]);
+        let mut publisher = RtmpPublisher::connect(config(port, "key123"), streams()).unwrap();
+        for packet in packets(20) {
+            publisher.write_packet(&packet).unwrap();
+        }
+        let stats = publisher.finish();
+        assert_eq!((stats.video_frames, stats.audio_frames), (20, 20));
+        assert_eq!((stats.dropped_frames, stats.reconnects), (0, 0));
+        assert!(stats.bytes_sent > 20 * 300);
+
+        let session = sessions.recv().unwrap();
+        assert_eq!(
+            (session.app.as_str(), session.key.as_str()),
+            ("live", "key123")
+        );
+        // Metadata first
+        let (kind, _, payload) = &session.media[0];
+        assert_eq!(*kind, MSG_DATA_AMF0);
+        let values = decode_amf0(payload).unwrap();
+        assert_eq!(values[1].as_str(), Some("onMetaData"));
+        assert_eq!(
+            values[2].get("width").and_then(Amf0Value::as_number),
+            Some(1280.0)
+        );
+        assert_eq!(
+            values[2].get("framerate").and_then(Amf0Value::as_number),
+            Some(25.0)
+        );
+
+        let video: Vec<_> = session.media.iter().filter(|m| m.0 == MSG_VIDEO).collect();
+        let audio: Vec<_> = session.media.iter().filter(|m| m.0 == MSG_AUDIO).collect();
+        assert_eq!((video.len(), audio.len()), (21, 21));
+        // AVC sequence header built from the in-band SPS/PPS
+        assert_eq!(&video[0].2[..5], &[0x17, 0, 0, 0, 0]);
+        assert_eq!(
+            h264_utils::parse_avcc_extradata(&video[0].2[5..])
+                .unwrap()
+                .1,
+            4
+        );
+        // Keyframe NALU: composition time 40 ms, 4-byte lengths, no start codes
+        let first = &video[1].2;
+        assert_eq!(&first[..5], &[0x17, 0x01, 0, 0, 40]);
+        assert_eq!(
+            h264_utils::avcc_to_annexb(&first[5..], 4).len(),
+            first.len() - 5
+        );
+        assert_eq!(video[2].2[0], 0x27);
+        assert_eq!(video[20].1, 19 * 40);
+        // AAC sequence header from ADTS: LC, 48 kHz, stereo; raw frames after
+        assert_eq!(audio[0].2, vec![0xAF, 0x00, 0x11, 0x90]);
+        assert_eq!(audio[1].2.len(), 2 + 50);
+        assert_eq!(audio[5].1, 4 * 40);
+    }
+
+    #[test]
+    fn test_reconnect_and_refusal() {
+        // The first connection dies after metadata + 12 media messages
+        let (port, sessions) = stand_in_server(vec![Some(13), None]);
+        let mut publisher = RtmpPublisher::connect(config(port, "key"), streams()).unwrap();
+        for packet in packets(30) {
+            publisher.write_packet(&packet).unwrap();
+            // Give the server time to hang up before the next write
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let stats = publisher.finish();
+        assert_eq!(stats.reconnects, 1);
+        assert!(stats.dropped_frames > 0);
+
+        let first = sessions.recv().unwrap();
+        assert_eq!(first.media.len(), 13);
+        let second = sessions.recv().unwrap();
+        // Fresh metadata and headers, video resumes on a keyframe
+        assert_eq!(second.media[0].0, MSG_DATA_AMF0);
+        let video: Vec<_> = second.media.iter().filter(|m| m.0 == MSG_VIDEO).collect();
+        assert_eq!(video[0].2[..2], [0x17, 0x00]);
+        assert_eq!(video[1].2[..2], [0x17, 0x01]);
+        assert_eq!(video[1].1 % 400, 0);
+        let audio: Vec<_> = second.media.iter().filter(|m| m.0 == MSG_AUDIO).collect();
+        assert_eq!(audio[0].2[..2], [0xAF, 0x00]);
+
+        let (port, _sessions) = stand_in_server(vec![None]);
+        let error = RtmpPublisher::connect(config(port, "bad"), streams())
+            .err()
+            .unwrap();
+        assert!(error.contains("NetStream.Publish.BadName"), "{}", error);
+    }
+}
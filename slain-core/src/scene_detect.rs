@@ -0,0 +1,671 @@
+//! # Scene Detection
+//!
+//! Shot boundary detection on decoded frames.
+//!
+//! Each frame is reduced to a small luma thumbnail and a 64-bin histogram.
+//! The cut score between neighbouring frames blends histogram distance (robust
+//! to motion) with mean absolute difference (catches cuts between shots with
+//! similar tones). Hard cuts must also stand out from the recent score average,
+//! so fast action doesn't read as a string of cuts. Gradual transitions are
+//! found by twin comparison: a run of moderate scores whose accumulated change
+//! crosses the cut threshold is a fade (to/from black) or a dissolve.
+//!
+//! `analyze_file` runs the detector as an offline pass over an MKV/MP4 file;
+//! the resulting `SceneList` exports as JSON or as Matroska chapters.
+
+use crate::hw_decode::{decode_file, FileDecodeOptions};
+use crate::mkv::MkvChapter;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// Width of the analysis thumbnail (height follows the aspect ratio)
+const ANALYSIS_WIDTH: usize = 160;
+const HISTOGRAM_BINS: usize = 64;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SceneDetectConfig {
+    /// Score (0-100) above which a frame change is a hard cut
+    pub cut_threshold: f32,
+    /// A cut must also exceed this multiple of the recent average score
+    pub adaptive_ratio: f32,
+    /// Frames in the rolling average
+    pub adaptive_window: usize,
+    /// Score above which a frame may be part of a gradual transition
+    pub gradual_threshold: f32,
+    /// Longest fade/dissolve considered, in frames
+    pub max_transition_frames: usize,
+    /// Mean luma (8-bit) at or below which a frame counts as black
+    pub black_level: f32,
+    /// Minimum scene length in frames; closer boundaries are merged (flashes)
+    pub min_scene_frames: u64,
+    /// Report fades and dissolves as well as hard cuts
+    pub detect_gradual: bool,
+}
+
+impl Default for SceneDetectConfig {
+    fn default() -> Self {
+        Self {
+            cut_threshold: 30.0,
+            adaptive_ratio: 3.0,
+            adaptive_window: 8,
+            gradual_threshold: 4.0,
+            max_transition_frames: 60,
+            black_level: 24.0,
+            min_scene_frames: 8,
+            detect_gradual: true,
+        }
+    }
+}
+
+// ============================================================================
+// Results
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransitionKind {
+    Cut,
+    FadeIn,
+    FadeOut,
+    Dissolve,
+}
+
+/// A shot boundary. `time_ms` is where the new shot starts (the midpoint for
+/// gradual transitions); `start_ms..end_ms` spans the transition itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneCut {
+    pub time_ms: u64,
+    pub frame: u64,
+    pub kind: TransitionKind,
+    pub score: f32,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SceneList {
+    pub source: Option<String>,
+    pub frames_analyzed: u64,
+    pub duration_ms: u64,
+    pub cuts: Vec<SceneCut>,
+}
+
+impl SceneList {
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// Scene spans as (start_ms, end_ms)
+    pub fn scenes(&self) -> Vec<(u64, u64)> {
+        let mut bounds = vec![0];
+        bounds.extend(self.cuts.iter().map(|c| c.time_ms).filter(|t| *t > 0));
+        bounds
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = bounds.get(i + 1).copied().unwrap_or(self.duration_ms);
+                (start, end.max(start))
+            })
+            .collect()
+    }
+
+    /// One chapter per scene, ready for the MKV muxer
+    pub fn to_mkv_chapters(&self) -> Vec<MkvChapter> {
+        self.scenes()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (start, end))| MkvChapter {
+                uid: chapter_uid(i as u64, start),
+                string_uid: None,
+                title: format!("Scene {}", i + 1),
+                language: "eng".to_string(),
+                start_time_ms: start,
+                end_time_ms: Some(end),
+                hidden: false,
+                enabled: true,
+                nested: Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Matroska chapter XML (the format `mkvmerge --chapters` reads)
+    pub fn to_chapters_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE Chapters SYSTEM \"matroskachapters.dtd\">\n<Chapters>\n  <EditionEntry>\n",
+        );
+        for chapter in self.to_mkv_chapters() {
+            xml.push_str("    <ChapterAtom>\n");
+            xml.push_str(&format!("      <ChapterUID>{}</ChapterUID>\n", chapter.uid));
+            xml.push_str(&format!(
+                "      <ChapterTimeStart>{}</ChapterTimeStart>\n",
+                format_chapter_time(chapter.start_time_ms)
+            ));
+            if let Some(end) = chapter.end_time_ms {
+                xml.push_str(&format!(
+                    "      <ChapterTimeEnd>{}</ChapterTimeEnd>\n",
+                    format_chapter_time(end)
+                ));
+            }
+            xml.push_str("      <ChapterDisplay>\n");
+            xml.push_str(&format!(
+                "        <ChapterString>{}</ChapterString>\n",
+                chapter.title
+            ));
+            xml.push_str(&format!(
+                "        <ChapterLanguage>{}</ChapterLanguage>\n",
+                chapter.language
+            ));
+            xml.push_str("      </ChapterDisplay>\n    </ChapterAtom>\n");
+        }
+        xml.push_str("  </EditionEntry>\n</Chapters>\n");
+        xml
+    }
+}
+
+fn chapter_uid(index: u64, start_ms: u64) -> u64 {
+    // Stable, non-zero UIDs so re-running the analysis yields the same chapters
+    (index + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ start_ms
+}
+
+fn format_chapter_time(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}000000",
+        ms / 3_600_000,
+        (ms / 60_000) % 60,
+        (ms / 1000) % 60,
+        ms % 1000
+    )
+}
+
+// ============================================================================
+// Frame Features
+// ============================================================================
+
+#[derive(Clone)]
+struct FrameFeatures {
+    thumb: Vec<u8>,
+    histogram: [f32; HISTOGRAM_BINS],
+    mean: f32,
+    pts_ms: u64,
+}
+
+impl FrameFeatures {
+    /// Box-downscale an 8-bit luma plane (`stride` bytes per row)
+    fn from_luma(luma: &[u8], width: usize, height: usize, stride: usize, pts_ms: u64) -> Self {
+        Self::build(width, height, pts_ms, |x, y| luma[y * stride + x])
+    }
+
+    fn build(
+        width: usize,
+        height: usize,
+        pts_ms: u64,
+        sample: impl Fn(usize, usize) -> u8,
+    ) -> Self {
+        let tw = width.clamp(1, ANALYSIS_WIDTH);
+        let th = (height * tw / width.max(1)).max(1);
+        let mut thumb = Vec::with_capacity(tw * th);
+        let mut histogram = [0f32; HISTOGRAM_BINS];
+        for ty in 0..th {
+            let (y0, y1) = (
+                ty * height / th,
+                ((ty + 1) * height / th).max(ty * height / th + 1),
+            );
+            for tx in 0..tw {
+                let (x0, x1) = (
+                    tx * width / tw,
+                    ((tx + 1) * width / tw).max(tx * width / tw + 1),
+                );
+                let mut sum = 0u32;
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        sum += sample(x, y) as u32;
+                    }
+                }
+                let value = (sum / ((y1 - y0) * (x1 - x0)) as u32) as u8;
+                thumb.push(value);
+                histogram[value as usize * HISTOGRAM_BINS / 256] += 1.0;
+            }
+        }
+        let count = thumb.len() as f32;
+        histogram.iter_mut().for_each(|h| *h /= count);
+        let mean = thumb.iter().map(|&v| v as f32).sum::<f32>() / count;
+        Self {
+            thumb,
+            histogram,
+            mean,
+            pts_ms,
+        }
+    }
+
+    /// Cut score 0-100: half histogram distance, half mean absolute difference
+    fn score(&self, other: &FrameFeatures) -> f32 {
+        let intersection: f32 = self
+            .histogram
+            .iter()
+            .zip(&other.histogram)
+            .map(|(a, b)| a.min(*b))
+            .sum();
+        let hist = (1.0 - intersection).clamp(0.0, 1.0);
+        let sad = if self.thumb.len() == other.thumb.len() {
+            let sum: u64 = self
+                .thumb
+                .iter()
+                .zip(&other.thumb)
+                .map(|(&a, &b)| a.abs_diff(b) as u64)
+                .sum();
+            sum as f32 / self.thumb.len() as f32
+        } else {
+            255.0
+        };
+        // A 64-level mean difference already means different content
+        50.0 * hist + 50.0 * (sad / 64.0).min(1.0)
+    }
+}
+
+// ============================================================================
+// Detector
+// ============================================================================
+
+/// A gradual transition candidate (twin comparison)
+struct Gradual {
+    start: FrameFeatures,
+    start_frame: u64,
+    frames: usize,
+    quiet_frames: usize,
+    peak: f32,
+}
+
+pub struct SceneDetector {
+    config: SceneDetectConfig,
+    prev: Option<FrameFeatures>,
+    recent: VecDeque<f32>,
+    gradual: Option<Gradual>,
+    frame: u64,
+    last_cut_frame: Option<u64>,
+    last_pts_ms: u64,
+    cuts: Vec<SceneCut>,
+}
+
+impl SceneDetector {
+    pub fn new(config: SceneDetectConfig) -> Self {
+        Self {
+            config,
+            prev: None,
+            recent: VecDeque::with_capacity(config.adaptive_window.max(1)),
+            gradual: None,
+            frame: 0,
+            last_cut_frame: None,
+            last_pts_ms: 0,
+            cuts: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SceneDetectConfig {
+        &self.config
+    }
+
+    /// Cuts found so far
+    pub fn cuts(&self) -> &[SceneCut] {
+        &self.cuts
+    }
+
+    /// Feed an 8-bit luma plane. Returns a boundary when one is confirmed;
+    /// gradual transitions are reported once they finish.
+    pub fn push_luma(
+        &mut self,
+        luma: &[u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        pts_ms: u64,
+    ) -> Result<Option<SceneCut>, String> {
+        let stride = if stride == 0 { width } else { stride };
+        if width == 0 || height == 0 || stride < width || luma.len() < stride * (height - 1) + width
+        {
+            return Err("Luma plane is smaller than its dimensions".to_string());
+        }
+        Ok(self.push_features(FrameFeatures::from_luma(
+            luma, width, height, stride, pts_ms,
+        )))
+    }
+
+    /// Feed an RGB24 frame
+    pub fn push_rgb(
+        &mut self,
+        frame: &crate::frame_interpolation::RgbFrame,
+        pts_ms: u64,
+    ) -> Result<Option<SceneCut>, String> {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        if frame.data.len() < width * height * 3 || width == 0 || height == 0 {
+            return Err("RGB frame is smaller than its dimensions".to_string());
+        }
+        let features = FrameFeatures::build(width, height, pts_ms, |x, y| {
+            let i = (y * width + x) * 3;
+            let (r, g, b) = (
+                frame.data[i] as u32,
+                frame.data[i + 1] as u32,
+                frame.data[i + 2] as u32,
+            );
+            ((77 * r + 150 * g + 29 * b) >> 8) as u8
+        });
+        Ok(self.push_features(features))
+    }
+
+    /// Feed a hardware/software decoder frame (luma plane only is read)
+    pub fn push_decoded(
+        &mut self,
+        frame: &crate::hw_decode::DecodedFrame,
+        pts_ms: u64,
+    ) -> Result<Option<SceneCut>, String> {
+        use crate::hw_decode::PixelFormat;
+
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        match frame.format {
+            PixelFormat::NV12 | PixelFormat::YUV420 => {
+                self.push_luma(&frame.data, width, height, frame.pitch as usize, pts_ms)
+            }
+            PixelFormat::P010 | PixelFormat::P016 => {
+                // 16-bit little-endian samples, MSB-aligned: keep the high byte
+                let stride = if frame.pitch == 0 {
+                    width * 2
+                } else {
+                    frame.pitch as usize
+                };
+                if frame.data.len() < stride * (height.max(1) - 1) + width * 2 {
+                    return Err("Luma plane is smaller than its dimensions".to_string());
+                }
+                let features = FrameFeatures::build(width, height, pts_ms, |x, y| {
+                    frame.data[y * stride + x * 2 + 1]
+                });
+                Ok(self.push_features(features))
+            }
+        }
+    }
+
+    fn push_features(&mut self, features: FrameFeatures) -> Option<SceneCut> {
+        let frame = self.frame;
+        self.frame += 1;
+        self.last_pts_ms = self.last_pts_ms.max(features.pts_ms);
+
+        let Some(prev) = self.prev.take() else {
+            self.prev = Some(features);
+            return None;
+        };
+        let score = features.score(&prev);
+
+        let average = if self.recent.is_empty() {
+            0.0
+        } else {
+            self.recent.iter().sum::<f32>() / self.recent.len() as f32
+        };
+        // Histograms of near-black frames jump on tiny changes; leave the
+        // start of a fade up and the end of a fade down to gradual tracking
+        let dark = 2.0 * self.config.black_level;
+        let is_cut = score >= self.config.cut_threshold
+            && score >= average * self.config.adaptive_ratio
+            && (prev.mean > dark || features.mean > dark);
+
+        let mut result = None;
+        if is_cut {
+            // A hard cut ends any gradual candidate without reporting it
+            self.gradual = None;
+            result = self.record(SceneCut {
+                time_ms: features.pts_ms,
+                frame,
+                kind: TransitionKind::Cut,
+                score,
+                start_ms: prev.pts_ms,
+                end_ms: features.pts_ms,
+            });
+        } else if self.config.detect_gradual {
+            result = self.track_gradual(&prev, frame, score);
+        }
+
+        if self.recent.len() >= self.config.adaptive_window.max(1) {
+            self.recent.pop_front();
+        }
+        // Cuts stay out of the average so one doesn't mask the next
+        if !is_cut {
+            self.recent.push_back(score);
+        }
+        self.prev = Some(features);
+        result
+    }
+
+    fn track_gradual(&mut self, prev: &FrameFeatures, frame: u64, score: f32) -> Option<SceneCut> {
+        let moving = score >= self.config.gradual_threshold;
+        let Some(mut gradual) = self.gradual.take() else {
+            if moving {
+                self.gradual = Some(Gradual {
+                    start: prev.clone(),
+                    start_frame: frame.saturating_sub(1),
+                    frames: 1,
+                    quiet_frames: 0,
+                    peak: score,
+                });
+            }
+            return None;
+        };
+
+        gradual.frames += 1;
+        gradual.peak = gradual.peak.max(score);
+        gradual.quiet_frames = if moving { 0 } else { gradual.quiet_frames + 1 };
+
+        // Allow one quiet frame inside a transition (encoder duplicates)
+        if gradual.quiet_frames <= 1 && gradual.frames <= self.config.max_transition_frames {
+            self.gradual = Some(gradual);
+            return None;
+        }
+        if gradual.frames > self.config.max_transition_frames {
+            return None;
+        }
+
+        // Transition ended at `prev`: was the accumulated change a boundary?
+        let end = prev;
+        let total = end.score(&gradual.start);
+        if total < self.config.cut_threshold {
+            return None;
+        }
+        let black = self.config.black_level;
+        let kind = if end.mean <= black && gradual.start.mean > black {
+            TransitionKind::FadeOut
+        } else if gradual.start.mean <= black && end.mean > black {
+            TransitionKind::FadeIn
+        } else {
+            TransitionKind::Dissolve
+        };
+        let start_ms = gradual.start.pts_ms;
+        let end_ms = end.pts_ms;
+        let (time_ms, cut_frame) = match kind {
+            // The new shot starts once the picture is back (fade in) or after
+            // the old one has gone (fade out)
+            TransitionKind::FadeIn => (start_ms, gradual.start_frame),
+            TransitionKind::FadeOut => (end_ms, frame.saturating_sub(1)),
+            _ => (
+                start_ms + (end_ms - start_ms) / 2,
+                gradual.start_frame + (frame.saturating_sub(1) - gradual.start_frame) / 2,
+            ),
+        };
+        self.record(SceneCut {
+            time_ms,
+            frame: cut_frame,
+            kind,
+            score: total,
+            start_ms,
+            end_ms,
+        })
+    }
+
+    fn record(&mut self, cut: SceneCut) -> Option<SceneCut> {
+        // A fade up straight after a fade down bounds a short black gap, not
+        // a flash, so it is kept
+        let through_black = cut.kind == TransitionKind::FadeIn
+            && self.cuts.last().map(|c| c.kind) == Some(TransitionKind::FadeOut);
+        if let Some(last) = self.last_cut_frame {
+            if cut.frame < last + self.config.min_scene_frames && !through_black {
+                return None;
+            }
+        }
+        self.last_cut_frame = Some(cut.frame);
+        self.cuts.push(cut.clone());
+        Some(cut)
+    }
+
+    /// Finish the analysis and return the cut list
+    pub fn finish(mut self, source: Option<String>) -> SceneList {
+        self.cuts.sort_by_key(|c| c.time_ms);
+        SceneList {
+            source,
+            frames_analyzed: self.frame,
+            duration_ms: self.last_pts_ms,
+            cuts: self.cuts,
+        }
+    }
+}
+
+// ============================================================================
+// Offline Analysis
+// ============================================================================
+
+/// Decode every video frame of an MKV/MP4 file and detect its shot boundaries
+pub fn analyze_file(path: &Path, config: SceneDetectConfig) -> Result<SceneList, String> {
+    let mut detector = SceneDetector::new(config);
+    decode_file(path, FileDecodeOptions::default(), |frame| {
+        detector.push_decoded(&frame, frame.pts.max(0) as u64)?;
+        Ok(true)
+    })?;
+    let list = detector.finish(Some(path.display().to_string()));
+    tracing::info!(
+        "Scene detection: {} frames, {} cuts in {:?}",
+        list.frames_analyzed,
+        list.cuts.len(),
+        path
+    );
+    Ok(list)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 64;
+    const H: usize = 36;
+
+    /// Shot `seed`: a textured gradient, drifting by `t` pixels
+    fn shot(seed: u32, t: usize) -> Vec<u8> {
+        let mut luma = vec![0u8; W * H];
+        for y in 0..H {
+            for x in 0..W {
+                let xs = x + t;
+                let base = match seed {
+                    0 => 40 + xs * 2,
+                    1 => 220 - y * 4,
+                    _ => 90 + ((xs / 8 + y / 8) % 2) * 100,
+                };
+                let grain = (xs * 7 + y * 13 + seed as usize * 31) % 9;
+                luma[y * W + x] = (base + grain).min(255) as u8;
+            }
+        }
+        luma
+    }
+
+    fn run(frames: &[Vec<u8>]) -> SceneList {
+        let mut detector = SceneDetector::new(SceneDetectConfig::default());
+        for (i, frame) in frames.iter().enumerate() {
+            detector
+                .push_luma(frame, W, H, W, i as u64 * 40)
+                .expect("push");
+        }
+        detector.finish(None)
+    }
+
+    #[test]
+    fn detects_hard_cuts_and_ignores_motion() {
+        let mut frames: Vec<Vec<u8>> = (0..20).map(|t| shot(0, t)).collect();
+        frames.extend((0..20).map(|t| shot(1, t)));
+        frames.extend((0..20).map(|t| shot(2, t)));
+        let list = run(&frames);
+
+        let times: Vec<u64> = list.cuts.iter().map(|c| c.time_ms).collect();
+        assert_eq!(times, vec![800, 1600]);
+        assert!(list.cuts.iter().all(|c| c.kind == TransitionKind::Cut));
+        assert_eq!(list.scenes(), vec![(0, 800), (800, 1600), (1600, 2360)]);
+    }
+
+    #[test]
+    fn detects_fade_out_and_in() {
+        let a = shot(0, 0);
+        let b = shot(2, 0);
+        let mut frames: Vec<Vec<u8>> = (0..12).map(|_| a.clone()).collect();
+        // 10-frame fade to black, 4 black frames, 10-frame fade up
+        for k in 1..=10 {
+            frames.push(
+                a.iter()
+                    .map(|&v| (v as u32 * (10 - k) / 10) as u8)
+                    .collect(),
+            );
+        }
+        frames.extend((0..4).map(|_| vec![0u8; W * H]));
+        for k in 1..=10 {
+            frames.push(b.iter().map(|&v| (v as u32 * k / 10) as u8).collect());
+        }
+        frames.extend((0..12).map(|_| b.clone()));
+        let list = run(&frames);
+
+        let kinds: Vec<TransitionKind> = list.cuts.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![TransitionKind::FadeOut, TransitionKind::FadeIn]);
+        assert!(list.cuts[0].start_ms < list.cuts[0].end_ms);
+    }
+
+    #[test]
+    fn detects_dissolve() {
+        let a = shot(0, 0);
+        let b = shot(1, 0);
+        let mut frames: Vec<Vec<u8>> = (0..12).map(|_| a.clone()).collect();
+        for k in 1..16u32 {
+            frames.push(
+                a.iter()
+                    .zip(&b)
+                    .map(|(&x, &y)| ((x as u32 * (16 - k) + y as u32 * k) / 16) as u8)
+                    .collect(),
+            );
+        }
+        frames.extend((0..12).map(|_| b.clone()));
+        let list = run(&frames);
+
+        assert_eq!(list.cuts.len(), 1);
+        assert_eq!(list.cuts[0].kind, TransitionKind::Dissolve);
+        // Midpoint of the 16-frame dissolve starting at frame 11
+        let time = list.cuts[0].time_ms;
+        assert!((560..=840).contains(&time), "dissolve at {}", time);
+    }
+
+    #[test]
+    fn exports_json_and_chapters() {
+        let mut frames: Vec<Vec<u8>> = (0..10).map(|t| shot(0, t)).collect();
+        frames.extend((0..10).map(|t| shot(1, t)));
+        let list = run(&frames);
+
+        let json = list.to_json().expect("json");
+        let parsed: SceneList = serde_json::from_str(&json).expect("parse");
+        assert_eq!(parsed.cuts.len(), 1);
+
+        let chapters = list.to_mkv_chapters();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].start_time_ms, 400);
+        assert_eq!(chapters[0].end_time_ms, Some(400));
+        assert_ne!(chapters[0].uid, chapters[1].uid);
+
+        let xml = list.to_chapters_xml();
+        assert!(xml.contains("<ChapterTimeStart>00:00:00.400000000</ChapterTimeStart>"));
+        assert!(xml.contains("<ChapterString>Scene 2</ChapterString>"));
+    }
+}
@@ -2,9 +2,9 @@
 //!
 //! Features:
 //! - DLNA/UPnP discovery via SSDP (real UDP multicast)
-//! - Chromecast discovery via mDNS
-//! - RTMP streaming (broadcast to Twitch/YouTube)
-//! - Local network streaming server
+//! - Chromecast discovery via mDNS and control over Cast v2 (`cast`)
+//! - RTMP streaming (broadcast to Twitch/YouTube) through the native `rtmp` publisher
+//! - Local network streaming server, with on-the-fly HLS (`hls_packager`)
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -279,19 +279,18 @@ impl DeviceDiscovery {
         }
     }
 
-    /// Discover Chromecast devices using mDNS
+    /// Discover Chromecast devices using mDNS (`_googlecast._tcp.local`)
     pub async fn discover_chromecast(&self) -> Result<Vec<CastDevice>, String> {
-        // mDNS query for _googlecast._tcp.local would be ideal, but we reuse SSDP/DIAL.
-        let devices = self.discover_dlna().await?;
-        Ok(devices
-            .into_iter()
-            .filter(|d| {
-                matches!(
-                    d.device_type,
-                    CastDeviceType::Chromecast | CastDeviceType::ChromecastAudio
-                )
-            })
-            .collect())
+        // The mDNS browse blocks for its whole window
+        let found = tokio::task::spawn_blocking(|| cast::browse_googlecast(Duration::from_secs(3)))
+            .await
+            .map_err(|e| format!("mDNS browse task failed: {}", e))??;
+
+        let mut devices = self.devices.write().await;
+        for device in &found {
+            devices.insert(device.id.clone(), device.clone());
+        }
+        Ok(found)
     }
 
     /// Get all discovered devices
@@ -300,6 +299,169 @@ impl DeviceDiscovery {
     }
 }
 
+// ============================================================================
+// SSDP Advertisement (our own UPnP devices)
+// ============================================================================
+
+use std::time::Instant;
+
+/// What one of our UPnP devices announces on the multicast group
+#[derive(Debug, Clone)]
+pub(crate) struct SsdpAdvertisement {
+    pub uuid: String,
+    pub device_type: &'static str,
+    pub services: Vec<&'static str>,
+    /// Device description URL
+    pub location: String,
+    pub max_age: u32,
+}
+
+/// `SERVER` / `Server` header for SSDP and UPnP HTTP responses
+pub(crate) fn upnp_server_header() -> String {
+    format!(
+        "{}/1.0 UPnP/1.0 SLAIN/{}",
+        std::env::consts::OS,
+        crate::VERSION
+    )
+}
+
+impl SsdpAdvertisement {
+    /// Notification types with their USNs
+    pub fn targets(&self) -> Vec<(String, String)> {
+        let udn = format!("uuid:{}", self.uuid);
+        let mut targets = vec![
+            (
+                "upnp:rootdevice".to_string(),
+                format!("{}::upnp:rootdevice", udn),
+            ),
+            (udn.clone(), udn.clone()),
+        ];
+        for nt in std::iter::once(self.device_type).chain(self.services.iter().copied()) {
+            targets.push((nt.to_string(), format!("{}::{}", udn, nt)));
+        }
+        targets
+    }
+
+    /// Responses to an M-SEARCH packet (empty when it isn't one for us)
+    pub fn search_responses(&self, request: &str) -> Vec<String> {
+        let mut lines = request.lines();
+        if !lines
+            .next()
+            .is_some_and(|l| l.trim().eq_ignore_ascii_case("M-SEARCH * HTTP/1.1"))
+        {
+            return Vec::new();
+        }
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim().to_string()))
+            .collect();
+        if headers.get("MAN").map(|m| m.trim_matches('"')) != Some("ssdp:discover") {
+            return Vec::new();
+        }
+        let Some(st) = headers.get("ST") else {
+            return Vec::new();
+        };
+        self.targets()
+            .into_iter()
+            .filter(|(nt, _)| st == "ssdp:all" || st.eq_ignore_ascii_case(nt))
+            .map(|(nt, usn)| {
+                format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\nContent-Length: 0\r\n\r\n",
+                    self.max_age,
+                    self.location,
+                    upnp_server_header(),
+                    nt,
+                    usn
+                )
+            })
+            .collect()
+    }
+
+    /// NOTIFY ssdp:alive / ssdp:byebye
+    pub fn notify(&self, nt: &str, usn: &str, alive: bool) -> String {
+        if alive {
+            format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nCACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nNT: {}\r\nNTS: ssdp:alive\r\nSERVER: {}\r\nUSN: {}\r\n\r\n",
+                SSDP_MULTICAST_ADDR,
+                SSDP_PORT,
+                self.max_age,
+                self.location,
+                nt,
+                upnp_server_header(),
+                usn
+            )
+        } else {
+            format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nNT: {}\r\nNTS: ssdp:byebye\r\nUSN: {}\r\n\r\n",
+                SSDP_MULTICAST_ADDR, SSDP_PORT, nt, usn
+            )
+        }
+    }
+
+    /// Announce and answer searches on a background thread until `shutdown`
+    /// is set; says byebye on the way out
+    pub fn spawn(self, shutdown: Arc<AtomicBool>) -> Result<thread::JoinHandle<()>, String> {
+        let socket = Self::socket()?;
+        Ok(thread::spawn(move || self.run(socket, &shutdown)))
+    }
+
+    fn socket() -> Result<UdpSocket, String> {
+        use socket2::{Domain, Protocol, Socket, Type};
+        let group: Ipv4Addr = SSDP_MULTICAST_ADDR
+            .parse()
+            .map_err(|e| format!("Invalid multicast address: {}", e))?;
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+            .map_err(|e| format!("SSDP socket failed: {}", e))?;
+        // Other UPnP stacks on this machine listen on 1900 too
+        socket.set_reuse_address(true).ok();
+        socket
+            .bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, SSDP_PORT)).into())
+            .map_err(|e| format!("SSDP bind failed: {}", e))?;
+        socket
+            .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|e| format!("SSDP multicast join failed: {}", e))?;
+        socket.set_multicast_ttl_v4(4).ok();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .map_err(|e| format!("Failed to set timeout: {}", e))?;
+        Ok(socket.into())
+    }
+
+    fn run(&self, socket: UdpSocket, shutdown: &AtomicBool) {
+        let Ok(group) = format!("{}:{}", SSDP_MULTICAST_ADDR, SSDP_PORT).parse::<SocketAddr>()
+        else {
+            return;
+        };
+        let targets = self.targets();
+        let notify = |alive: bool| {
+            for (nt, usn) in &targets {
+                let message = self.notify(nt, usn, alive);
+                if let Err(e) = socket.send_to(message.as_bytes(), group) {
+                    tracing::debug!("SSDP NOTIFY failed: {}", e);
+                }
+            }
+        };
+
+        let interval = Duration::from_secs((self.max_age as u64 / 2).max(30));
+        let mut last_notify: Option<Instant> = None;
+        let mut buf = [0u8; 2048];
+        while !shutdown.load(Ordering::Relaxed) {
+            if last_notify.is_none_or(|t| t.elapsed() >= interval) {
+                notify(true);
+                last_notify = Some(Instant::now());
+            }
+            let Ok((len, from)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            let request = String::from_utf8_lossy(&buf[..len]);
+            for response in self.search_responses(&request) {
+                socket.send_to(response.as_bytes(), from).ok();
+            }
+        }
+        notify(false);
+    }
+}
+
 // ============================================================================
 // DLNA Casting (Real Implementation)
 // ============================================================================
@@ -560,6 +722,135 @@ fn extract_xml_value(xml: &str, element: &str) -> Option<String> {
     Some(xml[content_start..end].trim().to_string())
 }
 
+// ============================================================================
+// Chromecast Casting (Cast v2)
+// ============================================================================
+
+/// Controls a Chromecast through the Default Media Receiver. Local files are
+/// served from a `LocalStreamServer` owned by the caster, so it keeps running
+/// for the whole session: formats the receiver plays natively as they are,
+/// everything else as on-the-fly HLS.
+pub struct ChromecastCaster {
+    client: Arc<Mutex<CastClient>>,
+    server: LocalStreamServer,
+}
+
+impl ChromecastCaster {
+    pub async fn connect(device: &CastDevice) -> Result<Self, String> {
+        let device = device.clone();
+        let client = tokio::task::spawn_blocking(move || CastClient::connect_device(&device))
+            .await
+            .map_err(|e| format!("Cast connect task failed: {}", e))??;
+        Ok(Self {
+            client: Arc::new(Mutex::new(client)),
+            server: LocalStreamServer::new(0),
+        })
+    }
+
+    /// Replace a dropped connection, keeping the server the device may
+    /// still be streaming from
+    pub async fn reconnect(self, device: &CastDevice) -> Result<Self, String> {
+        let server = self.server;
+        Ok(Self {
+            server,
+            ..Self::connect(device).await?
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.lock().is_connected()
+    }
+
+    /// Cast requests block until the receiver answers
+    async fn request<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut CastClient) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let client = self.client.clone();
+        tokio::task::spawn_blocking(move || f(&mut client.lock()))
+            .await
+            .map_err(|e| format!("Cast request task failed: {}", e))?
+    }
+
+    /// Cast a URL; the content type is guessed from its extension when
+    /// `content_type` is None
+    pub async fn cast_url(
+        &mut self,
+        url: &str,
+        content_type: Option<&str>,
+        title: &str,
+    ) -> Result<MediaStatus, String> {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        let content_type = content_type
+            .or_else(|| cast_content_type(path))
+            .unwrap_or("video/mp4");
+        let mut media = CastMedia::new(url, content_type);
+        media.title = Some(title.to_string());
+        self.request(move |client| client.load(&media)).await
+    }
+
+    /// Serve a local file on the network and cast it
+    pub async fn cast_file(&mut self, path: PathBuf) -> Result<MediaStatus, String> {
+        let title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "SLAIN Video".to_string());
+        let (url, content_type) = match cast_content_type(&path.to_string_lossy()) {
+            Some(content_type) => (self.server.start(path).await?, content_type),
+            None => (
+                self.server
+                    .start_hls(path, HlsPackagerConfig::default())
+                    .await?,
+                "application/x-mpegURL",
+            ),
+        };
+        self.cast_url(&url, Some(content_type), &title).await
+    }
+
+    pub async fn play(&self) -> Result<MediaStatus, String> {
+        self.request(|client| client.play()).await
+    }
+
+    pub async fn pause(&self) -> Result<MediaStatus, String> {
+        self.request(|client| client.pause()).await
+    }
+
+    pub async fn stop(&self) -> Result<MediaStatus, String> {
+        self.request(|client| client.stop()).await
+    }
+
+    pub async fn seek(&self, position_seconds: f64) -> Result<MediaStatus, String> {
+        self.request(move |client| client.seek(position_seconds))
+            .await
+    }
+
+    /// Volume 0.0 - 1.0
+    pub async fn set_volume(&self, level: f32) -> Result<ReceiverStatus, String> {
+        self.request(move |client| client.set_volume(level)).await
+    }
+
+    pub async fn status(&self) -> Result<Option<MediaStatus>, String> {
+        self.request(|client| client.media_status()).await
+    }
+}
+
+/// Content types the Default Media Receiver plays directly
+fn cast_content_type(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "mp4" | "m4v" | "mov" => "video/mp4",
+        "webm" => "video/webm",
+        "m3u8" => "application/x-mpegURL",
+        "mpd" => "application/dash+xml",
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "flac" => "audio/flac",
+        "ogg" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        _ => return None,
+    })
+}
+
 // ============================================================================
 // RTMP Streaming (Broadcast to Twitch/YouTube)
 // ============================================================================
@@ -610,11 +901,39 @@ impl StreamConfig {
 }
 
 use parking_lot::Mutex;
-use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use once_cell::sync::Lazy;
 
-static RTMP_PROCESS: Lazy<Mutex<Option<Child>>> = Lazy::new(|| Mutex::new(None));
+use crate::capture::ClipSource;
+use crate::encode::{self, EncoderConfig};
+use crate::mux::{MuxCodec, MuxPacket, MuxStream};
+use crate::pixel_convert::{scale_frame, ChromaLocation, PixelFormat, ScaleFilter, VideoFrame};
+use crate::rtmp::{self, RtmpConfig, RtmpPublisher, RtmpStats};
+
+static RTMP_SESSION: Lazy<Mutex<Option<RtmpSlot>>> = Lazy::new(|| Mutex::new(None));
+
+/// What an `RtmpStreamer` broadcasts
+pub enum StreamSource {
+    /// A media file, paced in real time; its H.264/AAC streams are sent as-is
+    File(PathBuf),
+    /// Frames from our own pipeline (`pts` in microseconds), encoded to H.264
+    /// by `config.encoder` at the configured resolution and bitrate. The
+    /// stream ends when the sender is dropped.
+    Frames(std::sync::mpsc::Receiver<VideoFrame>),
+}
+
+/// The one broadcast slot; `Starting` holds it while a stream connects
+enum RtmpSlot {
+    Starting,
+    Running(RtmpSession),
+}
+
+struct RtmpSession {
+    stop: Arc<AtomicBool>,
+    stats: Arc<Mutex<RtmpStats>>,
+    worker: thread::JoinHandle<Result<RtmpStats, String>>,
+}
 
 pub struct RtmpStreamer {
     config: StreamConfig,
@@ -625,124 +944,420 @@ impl RtmpStreamer {
         Self { config }
     }
 
-    /// Start streaming using FFmpeg
-    pub async fn start(&mut self) -> Result<(), String> {
-        let encoder_args: Vec<&str> = match self.config.encoder {
-            VideoEncoder::Nvenc => vec!["-c:v", "h264_nvenc", "-preset", "llhq", "-rc", "cbr"],
-            VideoEncoder::X264 => vec![
-                "-c:v",
-                "libx264",
-                "-preset",
-                "veryfast",
-                "-tune",
-                "zerolatency",
-            ],
-            VideoEncoder::Qsv => vec!["-c:v", "h264_qsv", "-preset", "veryfast"],
-            VideoEncoder::Amf => vec!["-c:v", "h264_amf", "-quality", "speed"],
+    /// Connect and start publishing `source` in the background. Connection
+    /// and publish errors are returned here; later errors come from `stop`.
+    ///
+    /// Frames are encoded with `config.encoder` where SLAIN has that backend
+    /// (AMF, or OpenH264 for X264); NVENC and QSV fall back to the best
+    /// encoder available.
+    pub async fn start(&mut self, source: StreamSource) -> Result<(), String> {
+        {
+            let mut slot = RTMP_SESSION.lock();
+            if slot.is_some() {
+                return Err("Already streaming".to_string());
+            }
+            *slot = Some(RtmpSlot::Starting);
+        }
+        let _starting = StartingGuard;
+        // Probing the source and the RTMP handshake both block
+        let config = self.config.clone();
+        let (publisher, feed) = tokio::task::spawn_blocking(move || Feed::open(&config, source))
+            .await
+            .map_err(|e| format!("RTMP connect task failed: {}", e))??;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Mutex::new(publisher.stats()));
+        let worker = {
+            let stop = stop.clone();
+            let stats = stats.clone();
+            thread::spawn(move || feed.run(publisher, &stop, &stats))
         };
+        *RTMP_SESSION.lock() = Some(RtmpSlot::Running(RtmpSession {
+            stop,
+            stats,
+            worker,
+        }));
+        tracing::info!("RTMP streaming to {}", self.config.server_url);
+        Ok(())
+    }
 
-        let rtmp_url = format!("{}/{}", self.config.server_url, self.config.stream_key);
-        let fps_str = self.config.fps.to_string();
+    /// Stop streaming; returns the final stats, or the error that ended the
+    /// stream early
+    pub async fn stop(&mut self) -> Result<RtmpStats, String> {
+        stop_rtmp_session()
+    }
 
-        #[cfg(target_os = "windows")]
-        let capture_args = vec!["-f", "gdigrab", "-framerate", &fps_str, "-i", "desktop"];
+    /// Whether a stream is running (false once a file has been sent)
+    pub fn is_streaming(&self) -> bool {
+        matches!(
+            &*RTMP_SESSION.lock(),
+            Some(RtmpSlot::Running(session)) if !session.worker.is_finished()
+        )
+    }
 
-        #[cfg(target_os = "linux")]
-        let capture_args = vec!["-f", "x11grab", "-framerate", &fps_str, "-i", ":0.0"];
+    pub fn stats(&self) -> Option<RtmpStats> {
+        match &*RTMP_SESSION.lock() {
+            Some(RtmpSlot::Running(session)) => Some(session.stats.lock().clone()),
+            _ => None,
+        }
+    }
+}
 
-        #[cfg(target_os = "macos")]
-        let capture_args = vec!["-f", "avfoundation", "-framerate", &fps_str, "-i", "1:0"];
+/// Frees a `Starting` slot when `start` fails or is dropped mid-connect
+struct StartingGuard;
 
-        let bitrate_str = format!("{}k", self.config.bitrate_kbps);
-        let audio_bitrate_str = format!("{}k", self.config.audio_bitrate);
+impl Drop for StartingGuard {
+    fn drop(&mut self) {
+        let mut slot = RTMP_SESSION.lock();
+        if matches!(*slot, Some(RtmpSlot::Starting)) {
+            *slot = None;
+        }
+    }
+}
 
-        let mut cmd = Command::new("ffmpeg");
-        cmd.args(&capture_args)
-            .args(&encoder_args)
-            .args(&[
-                "-b:v",
-                &bitrate_str,
-                "-maxrate",
-                &bitrate_str,
-                "-bufsize",
-                &format!("{}k", self.config.bitrate_kbps * 2),
-            ])
-            .args(&["-c:a", "aac", "-b:a", &audio_bitrate_str])
-            .args(&["-f", "flv", &rtmp_url])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
+fn stop_rtmp_session() -> Result<RtmpStats, String> {
+    let session = {
+        let mut slot = RTMP_SESSION.lock();
+        match slot.take() {
+            Some(RtmpSlot::Running(session)) => session,
+            Some(RtmpSlot::Starting) => {
+                *slot = Some(RtmpSlot::Starting);
+                return Err("Stream is still connecting".to_string());
+            }
+            None => return Err("Not streaming".to_string()),
+        }
+    };
+    session.stop.store(true, Ordering::Relaxed);
+    session
+        .worker
+        .join()
+        .map_err(|_| "RTMP streaming thread panicked".to_string())?
+}
 
-        let child = cmd
-            .spawn()
-            .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+/// Packet source of a running stream
+enum Feed {
+    File(ClipSource),
+    Frames {
+        frames: std::sync::mpsc::Receiver<VideoFrame>,
+        encoder: Box<dyn encode::Encoder>,
+        width: u32,
+        height: u32,
+    },
+}
 
-        *RTMP_PROCESS.lock() = Some(child);
+impl Feed {
+    /// Set up `source` and connect a publisher for its streams
+    fn open(config: &StreamConfig, source: StreamSource) -> Result<(RtmpPublisher, Self), String> {
+        let rtmp_config = RtmpConfig {
+            url: config.server_url.clone(),
+            stream_key: config.stream_key.clone(),
+            ..Default::default()
+        };
+        match source {
+            StreamSource::File(path) => {
+                let (clip, streams) = ClipSource::open(&path, rtmp::supports)?;
+                let publisher = RtmpPublisher::connect(rtmp_config, streams)?;
+                Ok((publisher, Feed::File(clip)))
+            }
+            StreamSource::Frames(frames) => {
+                let (width, height) = config.resolution;
+                let fps = config.fps.max(1);
+                let encoder_config = EncoderConfig {
+                    codec: MuxCodec::H264,
+                    width,
+                    height,
+                    frame_rate: fps as f64,
+                    bitrate_bps: config.bitrate_kbps as u64 * 1000,
+                    // Keyframe every 2 s, as ingest servers recommend
+                    gop_frames: fps * 2,
+                };
+                let encoder: Box<dyn encode::Encoder> = match config.encoder {
+                    VideoEncoder::Amf => Box::new(encode::AmfVideoEncoder::new(&encoder_config)?),
+                    VideoEncoder::X264 => {
+                        Box::new(encode::OpenH264VideoEncoder::new(&encoder_config)?)
+                    }
+                    other => {
+                        let encoder = encode::create_encoder(&encoder_config)?;
+                        tracing::info!(
+                            "No {:?} encoder built in; streaming with {}",
+                            other,
+                            encoder.name()
+                        );
+                        encoder
+                    }
+                };
+                let mut stream = MuxStream::video(MuxCodec::H264, width, height);
+                stream.frame_duration_us = Some(1_000_000 / fps as u64);
+                let publisher = RtmpPublisher::connect(rtmp_config, vec![stream])?;
+                Ok((
+                    publisher,
+                    Feed::Frames {
+                        frames,
+                        encoder,
+                        width,
+                        height,
+                    },
+                ))
+            }
+        }
+    }
 
-        Ok(())
+    fn run(
+        self,
+        mut publisher: RtmpPublisher,
+        stop: &AtomicBool,
+        stats: &Mutex<RtmpStats>,
+    ) -> Result<RtmpStats, String> {
+        let result = match self {
+            Feed::File(clip) => Self::send_file(clip, &mut publisher, stop, stats),
+            Feed::Frames {
+                frames,
+                encoder,
+                width,
+                height,
+            } => Self::send_frames(
+                frames,
+                encoder,
+                (width, height),
+                &mut publisher,
+                stop,
+                stats,
+            ),
+        };
+        let final_stats = publisher.finish();
+        *stats.lock() = final_stats.clone();
+        result.map(|_| final_stats)
     }
 
-    pub async fn stop(&mut self) -> Result<(), String> {
-        let mut process = RTMP_PROCESS.lock();
-        if let Some(mut child) = process.take() {
-            child.kill().ok();
-            child.wait().ok();
+    fn send_file(
+        mut clip: ClipSource,
+        publisher: &mut RtmpPublisher,
+        stop: &AtomicBool,
+        stats: &Mutex<RtmpStats>,
+    ) -> Result<(), String> {
+        let started = std::time::Instant::now();
+        let mut origin_us = None;
+        while let Some(packet) = clip.next_packet() {
+            // Real-time pacing on decode time
+            let dts = packet.dts_us.unwrap_or(packet.pts_us);
+            let due = Duration::from_micros((dts - *origin_us.get_or_insert(dts)).max(0) as u64);
+            while started.elapsed() < due {
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                thread::sleep((due - started.elapsed()).min(Duration::from_millis(50)));
+            }
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            publisher.write_packet(&packet)?;
+            *stats.lock() = publisher.stats();
         }
         Ok(())
     }
 
-    pub fn is_streaming(&self) -> bool {
-        RTMP_PROCESS.lock().is_some()
+    fn send_frames(
+        frames: std::sync::mpsc::Receiver<VideoFrame>,
+        mut encoder: Box<dyn encode::Encoder>,
+        (width, height): (u32, u32),
+        publisher: &mut RtmpPublisher,
+        stop: &AtomicBool,
+        stats: &Mutex<RtmpStats>,
+    ) -> Result<(), String> {
+        let mut send = |packets: Vec<encode::EncodedPacket>| -> Result<(), String> {
+            for encoded in packets {
+                publisher.write_packet(&MuxPacket {
+                    stream: 0,
+                    pts_us: encoded.pts_us,
+                    dts_us: None,
+                    duration_us: None,
+                    keyframe: encoded.keyframe,
+                    data: encoded.data,
+                })?;
+            }
+            *stats.lock() = publisher.stats();
+            Ok(())
+        };
+        while !stop.load(Ordering::Relaxed) {
+            let mut frame = match frames.recv_timeout(Duration::from_millis(100)) {
+                Ok(frame) => frame,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if frame.format != PixelFormat::NV12 {
+                return Err(format!("RTMP expects NV12 frames, got {:?}", frame.format));
+            }
+            if frame.width != width as usize || frame.height != height as usize {
+                frame = scale_frame(
+                    &frame,
+                    width as usize,
+                    height as usize,
+                    ScaleFilter::Bilinear,
+                    ChromaLocation::Left,
+                )?;
+            }
+            send(encoder.encode(&frame)?)?;
+        }
+        send(encoder.flush()?)
     }
 }
 
+/// Grab the desktop as NV12 frames at `config`'s resolution and frame rate
+///
+/// The platform grabber (gdigrab, x11grab or avfoundation) runs under ffmpeg,
+/// which only captures and scales; encoding and publishing stay with
+/// `RtmpStreamer`. Capture stops once the receiver is dropped.
+pub fn capture_desktop(
+    config: &StreamConfig,
+) -> Result<std::sync::mpsc::Receiver<VideoFrame>, String> {
+    let (width, height) = config.resolution;
+    let fps = config.fps.max(1);
+    let rate = fps.to_string();
+
+    #[cfg(target_os = "windows")]
+    let input = ["-f", "gdigrab", "-framerate", &rate, "-i", "desktop"];
+    #[cfg(target_os = "linux")]
+    let input = ["-f", "x11grab", "-framerate", &rate, "-i", ":0.0"];
+    #[cfg(target_os = "macos")]
+    let input = ["-f", "avfoundation", "-framerate", &rate, "-i", "1:0"];
+
+    let mut child = std::process::Command::new("ffmpeg")
+        .args(["-loglevel", "error"])
+        .args(input)
+        .args(["-vf", &format!("scale={}:{}", width, height)])
+        .args(["-pix_fmt", "nv12", "-f", "rawvideo", "-"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start desktop capture: {}", e))?;
+    let mut output = child.stdout.take().ok_or("Desktop capture has no output")?;
+
+    let (sender, frames) = std::sync::mpsc::sync_channel(2);
+    thread::spawn(move || {
+        let frame_us = 1_000_000 / fps as i64;
+        for index in 0.. {
+            let mut frame = VideoFrame::new(width as usize, height as usize, PixelFormat::NV12);
+            if output.read_exact(&mut frame.data).is_err() {
+                break;
+            }
+            frame.pts = index * frame_us;
+            if sender.send(frame).is_err() {
+                break;
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+    Ok(frames)
+}
+
 // ============================================================================
 // Local Network Streaming Server
 // ============================================================================
 
+use crate::cast::{self, CastClient, CastMedia, MediaStatus, ReceiverStatus};
+use crate::hls_packager::{HlsPackager, HlsPackagerConfig};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::thread;
 
-static LOCAL_SERVER: Lazy<Mutex<Option<LocalServerHandle>>> = Lazy::new(|| Mutex::new(None));
+/// What a running server hands out; swapped when the server is reused
+struct ServedMedia {
+    video_path: PathBuf,
+    packager: Option<Arc<HlsPackager>>,
+}
 
-struct LocalServerHandle {
-    port: u16,
-    shutdown: Arc<std::sync::atomic::AtomicBool>,
+struct RunningServer {
+    shutdown: Arc<AtomicBool>,
+    media: Arc<Mutex<ServedMedia>>,
 }
 
+/// HTTP server for one local file. Starting it again serves the new file
+/// from the same port; it stops on `stop` or when dropped.
 pub struct LocalStreamServer {
     port: u16,
+    running: Option<RunningServer>,
 }
 
 impl LocalStreamServer {
     pub fn new(port: u16) -> Self {
-        Self { port }
+        Self {
+            port,
+            running: None,
+        }
     }
 
     /// Start HTTP streaming server
     pub async fn start(&mut self, video_path: PathBuf) -> Result<String, String> {
         let local_ip =
             local_ip_address::local_ip().map_err(|e| format!("Failed to get local IP: {}", e))?;
+        let port = self.serve(video_path, None)?;
+
+        Ok(format!("http://{}:{}/stream", local_ip, port))
+    }
+
+    /// Start the server with on-the-fly HLS of `video_path` under `/hls/`
+    /// (next to the plain `/stream`); returns the master playlist URL
+    pub async fn start_hls(
+        &mut self,
+        video_path: PathBuf,
+        config: HlsPackagerConfig,
+    ) -> Result<String, String> {
+        let local_ip =
+            local_ip_address::local_ip().map_err(|e| format!("Failed to get local IP: {}", e))?;
+        let packager = Arc::new(HlsPackager::open(&video_path, config)?);
+        let port = self.serve(video_path, Some(packager))?;
+
+        Ok(format!("http://{}:{}/hls/master.m3u8", local_ip, port))
+    }
+
+    /// Bind and spawn the accept loop, or point the running one at the new
+    /// media; returns the bound port (port 0 picks one)
+    fn serve(
+        &mut self,
+        video_path: PathBuf,
+        packager: Option<Arc<HlsPackager>>,
+    ) -> Result<u16, String> {
+        let media = ServedMedia {
+            video_path,
+            packager,
+        };
+        if let Some(running) = &self.running {
+            *running.media.lock() = media;
+            return Ok(self.port);
+        }
 
         let addr = format!("0.0.0.0:{}", self.port);
         let listener =
             std::net::TcpListener::bind(&addr).map_err(|e| format!("Failed to bind: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map(|a| a.port())
+            .map_err(|e| format!("Failed to bind: {}", e))?;
+        self.port = port;
 
-        let shutdown = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let shutdown = Arc::new(AtomicBool::new(false));
         let shutdown_clone = shutdown.clone();
-        let port = self.port;
+        let media = Arc::new(Mutex::new(media));
+        let media_clone = media.clone();
 
         // Spawn server thread
         thread::spawn(move || {
             listener.set_nonblocking(true).ok();
 
-            while !shutdown_clone.load(std::sync::atomic::Ordering::Relaxed) {
+            while !shutdown_clone.load(Ordering::Relaxed) {
                 match listener.accept() {
                     Ok((mut stream, _addr)) => {
-                        let video_path = video_path.clone();
+                        // Accepted sockets inherit non-blocking mode on some platforms
+                        stream.set_nonblocking(false).ok();
+                        let (video_path, packager) = {
+                            let media = media_clone.lock();
+                            (media.video_path.clone(), media.packager.clone())
+                        };
                         thread::spawn(move || {
-                            Self::handle_request(&mut stream, &video_path);
+                            Self::handle_request(&mut stream, &video_path, packager.as_deref());
                         });
                     }
                     Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
@@ -753,12 +1368,16 @@ impl LocalStreamServer {
             }
         });
 
-        *LOCAL_SERVER.lock() = Some(LocalServerHandle { port, shutdown });
+        self.running = Some(RunningServer { shutdown, media });
 
-        Ok(format!("http://{}:{}/stream", local_ip, port))
+        Ok(port)
     }
 
-    fn handle_request(stream: &mut std::net::TcpStream, video_path: &PathBuf) {
+    fn handle_request(
+        stream: &mut std::net::TcpStream,
+        video_path: &PathBuf,
+        packager: Option<&HlsPackager>,
+    ) {
         use std::io::Write;
 
         let mut buf = [0u8; 4096];
@@ -768,6 +1387,16 @@ impl LocalStreamServer {
 
         let request = String::from_utf8_lossy(&buf);
 
+        let path = request
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or("/");
+        if let (Some(packager), Some(hls_path)) = (packager, path.strip_prefix("/hls/")) {
+            Self::respond_hls(stream, packager, hls_path);
+            return;
+        }
+
         // Parse Range header for seeking
         let range_start = request
             .find("Range: bytes=")
@@ -839,16 +1468,53 @@ impl LocalStreamServer {
         }
     }
 
+    fn respond_hls(stream: &mut std::net::TcpStream, packager: &HlsPackager, path: &str) {
+        use std::io::Write;
+
+        let (status, content_type, body) = match packager.respond(path) {
+            Ok(Some((content_type, body))) => ("200 OK", content_type, body),
+            Ok(None) => ("404 Not Found", "text/plain", Arc::new(Vec::new())),
+            Err(e) => {
+                tracing::warn!("HLS request {} failed: {}", path, e);
+                (
+                    "500 Internal Server Error",
+                    "text/plain",
+                    Arc::new(e.into_bytes()),
+                )
+            }
+        };
+        let headers = format!(
+            "HTTP/1.1 {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Connection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        );
+        if stream.write_all(headers.as_bytes()).is_ok() {
+            stream.write_all(&body).ok();
+        }
+    }
+
     pub async fn stop(&mut self) {
-        let mut server = LOCAL_SERVER.lock();
-        if let Some(handle) = server.take() {
-            handle
-                .shutdown
-                .store(true, std::sync::atomic::Ordering::Relaxed);
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.shutdown.store(true, Ordering::Relaxed);
         }
     }
 }
 
+impl Drop for LocalStreamServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
 // ============================================================================
 // Public Rust API
 // ============================================================================
@@ -863,9 +1529,18 @@ pub async fn discover_cast_devices() -> Result<Vec<CastDevice>, String> {
         devices.extend(dlna);
     }
 
-    // Discover Chromecast (via DIAL in SSDP)
+    // Discover Chromecast (mDNS)
     if let Ok(cc) = discovery.discover_chromecast().await {
-        devices.extend(cc);
+        for device in cc {
+            // Chromecasts answering DIAL over SSDP too
+            devices.retain(|d| d.address != device.address);
+            devices.push(device);
+        }
+    }
+
+    let mut known = CAST_DEVICES.lock();
+    for device in &devices {
+        known.insert(device.id.clone(), device.clone());
     }
 
     Ok(devices)
@@ -874,15 +1549,75 @@ pub async fn discover_cast_devices() -> Result<Vec<CastDevice>, String> {
 static CAST_DEVICES: Lazy<Mutex<HashMap<String, CastDevice>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Open Cast connections by device id, kept so later casts reuse them
+static CHROMECAST_SESSIONS: Lazy<Mutex<HashMap<String, ChromecastCaster>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn known_device(device_id: &str) -> Result<CastDevice, String> {
+    CAST_DEVICES
+        .lock()
+        .get(device_id)
+        .cloned()
+        .ok_or_else(|| "Device not found".to_string())
+}
+
+/// Local file servers for DLNA renderers by device id, kept running while
+/// the renderer may still be reading from them
+static DLNA_SERVERS: Lazy<Mutex<HashMap<String, LocalStreamServer>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Connected caster for a Chromecast, reusing a live connection
+async fn chromecast_caster(device: &CastDevice) -> Result<ChromecastCaster, String> {
+    let session = CHROMECAST_SESSIONS.lock().remove(&device.id);
+    match session {
+        Some(caster) if caster.is_connected() => Ok(caster),
+        Some(caster) => caster.reconnect(device).await,
+        None => ChromecastCaster::connect(device).await,
+    }
+}
+
+fn is_chromecast(device: &CastDevice) -> bool {
+    matches!(
+        device.device_type,
+        CastDeviceType::Chromecast | CastDeviceType::ChromecastAudio
+    )
+}
+
 pub async fn cast_to_device(device_id: String, video_url: String) -> Result<(), String> {
-    let devices = CAST_DEVICES.lock();
-    let device = devices.get(&device_id).ok_or("Device not found")?.clone();
-    drop(devices);
+    let device = known_device(&device_id)?;
+
+    if is_chromecast(&device) {
+        let mut caster = chromecast_caster(&device).await?;
+        caster.cast_url(&video_url, None, "SLAIN Video").await?;
+        CHROMECAST_SESSIONS.lock().insert(device_id, caster);
+        return Ok(());
+    }
 
     let caster = DlnaCaster::new(device);
     caster.cast_url(&video_url, "SLAIN Video").await
 }
 
+/// Cast a local file, serving it with `LocalStreamServer`
+pub async fn cast_file_to_device(device_id: String, path: PathBuf) -> Result<(), String> {
+    let device = known_device(&device_id)?;
+
+    if is_chromecast(&device) {
+        let mut caster = chromecast_caster(&device).await?;
+        caster.cast_file(path).await?;
+        CHROMECAST_SESSIONS.lock().insert(device_id, caster);
+        return Ok(());
+    }
+
+    let mut server = DLNA_SERVERS
+        .lock()
+        .remove(&device_id)
+        .unwrap_or_else(|| LocalStreamServer::new(0));
+    let url = server.start(path).await;
+    DLNA_SERVERS.lock().insert(device_id, server);
+    DlnaCaster::new(device).cast_url(&url?, "SLAIN Video").await
+}
+
+/// Broadcast the desktop to `server_url`
 pub async fn start_rtmp_stream(
     server_url: String,
     stream_key: String,
@@ -898,17 +1633,36 @@ pub async fn start_rtmp_stream(
         audio_bitrate: 160,
     };
 
+    let frames = capture_desktop(&config)?;
     let mut streamer = RtmpStreamer::new(config);
-    streamer.start().await
+    streamer.start(StreamSource::Frames(frames)).await
 }
 
-pub async fn stop_rtmp_stream() -> Result<(), String> {
-    let mut process = RTMP_PROCESS.lock();
-    if let Some(mut child) = process.take() {
-        child.kill().ok();
-        child.wait().ok();
-    }
-    Ok(())
+/// Broadcast a media file's H.264/AAC streams to `server_url`
+pub async fn start_rtmp_file_stream(
+    server_url: String,
+    stream_key: String,
+    bitrate: u32,
+    input_path: String,
+) -> Result<(), String> {
+    let config = StreamConfig {
+        server_url,
+        stream_key,
+        bitrate_kbps: bitrate,
+        resolution: (1920, 1080),
+        fps: 60,
+        encoder: VideoEncoder::Nvenc,
+        audio_bitrate: 160,
+    };
+
+    let mut streamer = RtmpStreamer::new(config);
+    streamer
+        .start(StreamSource::File(PathBuf::from(input_path)))
+        .await
+}
+
+pub async fn stop_rtmp_stream() -> Result<RtmpStats, String> {
+    stop_rtmp_session()
 }
 
 pub async fn start_local_server(port: u16) -> Result<String, String> {
@@ -945,6 +1699,102 @@ mod tests {
         assert_eq!(youtube.audio_bitrate, 128);
     }
 
+    #[test]
+    fn rtmp_frames_source() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+        // The NVENC preset falls back to a built-in encoder and gets as far
+        // as connecting
+        let (_sender, frames) = std::sync::mpsc::channel();
+        let mut config = StreamConfig::twitch_1080p60("key");
+        config.server_url = "rtmp://127.0.0.1:1/app".to_string();
+        let mut streamer = RtmpStreamer::new(config);
+        let err = runtime
+            .block_on(streamer.start(StreamSource::Frames(frames)))
+            .unwrap_err();
+        assert!(!err.contains("encod"), "{}", err);
+        assert!(!streamer.is_streaming());
+
+        // A second start while the first is still handshaking is refused
+        // instead of replacing it
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = StreamConfig::twitch_1080p60("key");
+        config.server_url = format!("rtmp://{}/app", listener.local_addr().unwrap());
+        let (_first_sender, first_frames) = std::sync::mpsc::channel();
+        let first = {
+            let mut streamer = RtmpStreamer::new(config.clone());
+            runtime.spawn(async move { streamer.start(StreamSource::Frames(first_frames)).await })
+        };
+        let (socket, _) = listener.accept().unwrap();
+        let (_second_sender, second_frames) = std::sync::mpsc::channel();
+        let second =
+            runtime.block_on(RtmpStreamer::new(config).start(StreamSource::Frames(second_frames)));
+        assert_eq!(second.unwrap_err(), "Already streaming");
+        assert!(stop_rtmp_session().is_err());
+        drop(socket);
+        assert!(runtime.block_on(first).unwrap().is_err());
+        assert!(RTMP_SESSION.lock().is_none());
+
+        // The desktop feeds the same path and leaves the slot free on failure
+        let desktop = runtime.block_on(start_rtmp_stream(
+            "rtmp://127.0.0.1:1/app".to_string(),
+            "key".to_string(),
+            6000,
+        ));
+        assert!(desktop.is_err());
+        assert!(RTMP_SESSION.lock().is_none());
+    }
+
+    #[test]
+    fn cast_content_types() {
+        assert_eq!(cast_content_type("/media/Movie.MP4"), Some("video/mp4"));
+        assert_eq!(
+            cast_content_type("http://host/hls/master.m3u8"),
+            Some("application/x-mpegURL")
+        );
+        assert_eq!(cast_content_type("song.flac"), Some("audio/flac"));
+        // Containers the receiver can't play go through HLS
+        assert_eq!(cast_content_type("/media/show.mkv"), None);
+        assert_eq!(cast_content_type("no_extension"), None);
+    }
+
+    #[test]
+    fn local_server_reuse() {
+        use std::io::Write;
+
+        fn fetch(port: u16) -> String {
+            let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream
+                .write_all(b"GET /stream HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.mp4");
+        let second = dir.path().join("second.mp4");
+        std::fs::write(&first, b"first file").unwrap();
+        std::fs::write(&second, b"second file").unwrap();
+
+        // Casting another file reuses the running server and its port
+        let mut server = LocalStreamServer::new(0);
+        let url = pollster::block_on(server.start(first)).unwrap();
+        assert!(fetch(server.port).ends_with("first file"));
+        assert_eq!(pollster::block_on(server.start(second)).unwrap(), url);
+        assert!(fetch(server.port).ends_with("second file"));
+
+        // Dropping it shuts the accept loop down and frees the port
+        let port = server.port;
+        drop(server);
+        thread::sleep(Duration::from_millis(300));
+        assert!(std::net::TcpListener::bind(("0.0.0.0", port)).is_ok());
+    }
+
     #[test]
     fn extract_xml_helpers() {
         let discovery = DeviceDiscovery::new();
@@ -0,0 +1,204 @@
+//! Fixtures shared by the unit tests of several modules
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::dlna_server::HttpRequest;
+use crate::h264_utils::{annexb_has_idr, annexb_to_avcc, build_avcc_extradata};
+use crate::mkv_mux::{MkvMuxConfig, MkvMuxer};
+use crate::mux::{MuxCodec, MuxPacket, MuxStream};
+use crate::ts_mux::{TsMuxConfig, TsMuxer};
+
+pub const H264_WIDTH: usize = 64;
+pub const H264_HEIGHT: usize = 48;
+pub const H264_FRAME_MS: i64 = 40;
+const AUDIO_MS: i64 = 20;
+
+/// Luma of every pixel in frame `index` of `write_h264_mkv`
+pub fn h264_frame_luma(index: usize) -> u8 {
+    16 + (index * 4).min(219) as u8
+}
+
+/// Encode `frames` flat grey pictures (brightness rising with the frame
+/// index, see `h264_frame_luma`) at 25 fps with an IDR every `gop` frames,
+/// and store them as AVCC H.264 in an MKV alongside an AAC-sized audio track
+pub fn write_h264_mkv(path: &Path, frames: usize, gop: usize) {
+    use openh264::encoder::{Encoder, EncoderConfig, RateControlMode};
+    use openh264::formats::YUVBuffer;
+    use openh264::OpenH264API;
+
+    let config = EncoderConfig::new()
+        .rate_control_mode(RateControlMode::Off)
+        .enable_skip_frame(false)
+        .max_frame_rate(25.0);
+    let mut encoder = Encoder::with_api_config(OpenH264API::from_source(), config).unwrap();
+    let chroma = H264_WIDTH * H264_HEIGHT / 4;
+    let access_units: Vec<Vec<u8>> = (0..frames)
+        .map(|i| {
+            let mut yuv = vec![h264_frame_luma(i); H264_WIDTH * H264_HEIGHT];
+            yuv.resize(yuv.len() + 2 * chroma, 128);
+            if i % gop == 0 {
+                encoder.force_intra_frame();
+            }
+            let source = YUVBuffer::from_vec(yuv, H264_WIDTH, H264_HEIGHT);
+            encoder.encode(&source).unwrap().to_vec()
+        })
+        .collect();
+
+    let avcc = build_avcc_extradata(&access_units[0]).expect("SPS/PPS in first access unit");
+    let streams = vec![
+        MuxStream::video(MuxCodec::H264, H264_WIDTH as u32, H264_HEIGHT as u32)
+            .with_codec_private(avcc),
+        MuxStream::audio(MuxCodec::AAC, 48000, 2).with_codec_private(vec![0x11, 0x90]),
+    ];
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut muxer = MkvMuxer::new(file, streams, MkvMuxConfig::default()).unwrap();
+    let mut audio_ms = 0i64;
+    for (i, access_unit) in access_units.iter().enumerate() {
+        let pts_ms = i as i64 * H264_FRAME_MS;
+        muxer
+            .write_packet(&MuxPacket {
+                stream: 0,
+                pts_us: pts_ms * 1000,
+                dts_us: None,
+                duration_us: Some(H264_FRAME_MS * 1000),
+                keyframe: annexb_has_idr(access_unit, false),
+                data: annexb_to_avcc(access_unit),
+            })
+            .unwrap();
+        while audio_ms < pts_ms + H264_FRAME_MS {
+            muxer
+                .write_packet(&MuxPacket {
+                    stream: 1,
+                    pts_us: audio_ms * 1000,
+                    dts_us: None,
+                    duration_us: Some(AUDIO_MS * 1000),
+                    keyframe: true,
+                    data: vec![0xAA; 8],
+                })
+                .unwrap();
+            audio_ms += AUDIO_MS;
+        }
+    }
+    muxer.finish().unwrap();
+}
+
+/// 1920x1080 High profile SPS and its PPS
+const SPS: [u8; 27] = [
+    0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00, 0x00, 0x03,
+    0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x3c, 0x60, 0xc6, 0x58,
+];
+const PPS: [u8; 4] = [0x68, 0xEB, 0xE3, 0xCB];
+
+/// `frames` of 25 fps Annex B H.264 and ADTS AAC in MPEG-TS; with
+/// `keyframes`, every 25th frame is an IDR led by SPS/PPS, otherwise none is
+pub fn ts_stream(frames: i64, keyframes: bool) -> Vec<u8> {
+    let streams = vec![
+        MuxStream::video(MuxCodec::H264, 1920, 1080),
+        MuxStream::audio(MuxCodec::AAC, 48000, 2),
+    ];
+    let mut muxer = TsMuxer::new(Vec::new(), streams, TsMuxConfig::default()).unwrap();
+    for i in 0..frames {
+        let keyframe = keyframes && i % 25 == 0;
+        let mut data = Vec::new();
+        if keyframe {
+            for nal in [&SPS[..], &PPS[..]] {
+                data.extend_from_slice(&[0, 0, 0, 1]);
+                data.extend_from_slice(nal);
+            }
+        }
+        data.extend_from_slice(&[0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }]);
+        data.extend(std::iter::repeat_n(0x11, 400));
+        muxer
+            .write_packet(&MuxPacket {
+                stream: 0,
+                pts_us: i * 40_000,
+                dts_us: Some(i * 40_000),
+                duration_us: Some(40_000),
+                keyframe,
+                data,
+            })
+            .unwrap();
+        // AAC LC, 48 kHz, stereo
+        let frame_len = 7 + 60;
+        let mut adts = vec![
+            0xFF,
+            0xF1,
+            (1 << 6) | (3 << 2),
+            (2 << 6) | ((frame_len >> 11) as u8 & 0x03),
+            (frame_len >> 3) as u8,
+            ((frame_len as u8 & 0x07) << 5) | 0x1F,
+            0xFC,
+        ];
+        adts.extend(std::iter::repeat_n(0x22, 60));
+        muxer
+            .write_packet(&MuxPacket {
+                stream: 1,
+                pts_us: i * 40_000,
+                dts_us: Some(i * 40_000),
+                duration_us: Some(21_333),
+                keyframe: true,
+                data: adts,
+            })
+            .unwrap();
+    }
+    muxer.finish().unwrap()
+}
+
+// ============================================================================
+// HTTP Stand-in
+// ============================================================================
+
+/// Local HTTP/1.1 server for client tests. `handle` answers each request
+/// (usually with `respond`) on the connection's own thread; returns the base
+/// URL, `http://127.0.0.1:port`.
+pub fn http_server<F>(handle: F) -> String
+where
+    F: Fn(&HttpRequest, &mut TcpStream) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let handle = Arc::new(handle);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                if let Ok(request) = HttpRequest::read(&mut stream) {
+                    handle(&request, &mut stream);
+                }
+            });
+        }
+    });
+    base
+}
+
+/// Write a response head (`status` like "200 OK"); without `content_length`
+/// the body runs until the connection closes
+pub fn write_head(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    content_length: Option<usize>,
+) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    if let Some(length) = content_length {
+        let _ = write!(head, "Content-Length: {}\r\n", length);
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+/// Write a complete response; a client that already hung up is ignored
+pub fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, String)], body: &[u8]) {
+    if write_head(stream, status, headers, Some(body.len())).is_ok() {
+        let _ = stream.write_all(body);
+    }
+}
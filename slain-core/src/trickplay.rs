@@ -0,0 +1,605 @@
+//! Seekbar thumbnail previews (trickplay)
+//!
+//! Keyframes are decoded at a fixed interval, shrunk with
+//! `imaging::create_thumbnail` and packed row-major into sprite sheets. Each
+//! sheet is described by a JSON index and a WebVTT track (`#xywh=` cues, the
+//! format web players understand), and the whole set is cached on disk under
+//! the file's `history::generate_video_id` so later opens are instant.
+//!
+//! `TrickplayGenerator` runs a pass on a background thread and exposes its
+//! progress; the player looks tiles up by hover time with `TrickplayIndex::lookup`.
+
+use crate::capture::decoded_to_rgb;
+use crate::hw_decode::{decode_file, DecodedFrame, FileDecodeOptions};
+use crate::imaging::{create_thumbnail, save_image, ThumbnailOptions};
+use image::{imageops, DynamicImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const INDEX_FILE: &str = "index.json";
+const WEBVTT_FILE: &str = "thumbnails.vtt";
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrickplayConfig {
+    /// Minimum spacing between thumbnails (ms)
+    pub interval_ms: u64,
+    /// Bounding box each thumbnail is shrunk into (aspect is preserved)
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Sprite sheet grid
+    pub columns: u32,
+    pub rows: u32,
+    /// JPEG quality for the sheets
+    pub quality: u8,
+}
+
+impl Default for TrickplayConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 10_000,
+            tile_width: 160,
+            tile_height: 90,
+            columns: 10,
+            rows: 10,
+            quality: 80,
+        }
+    }
+}
+
+impl TrickplayConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_ms == 0 {
+            return Err("Trickplay interval must be non-zero".to_string());
+        }
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err("Trickplay tile size must be non-zero".to_string());
+        }
+        if self.columns == 0 || self.rows == 0 {
+            return Err("Trickplay sheet grid must be non-empty".to_string());
+        }
+        Ok(())
+    }
+
+    fn tiles_per_sheet(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// Sheet number and top-left pixel of the n-th tile
+    fn tile_position(&self, n: usize) -> (usize, u32, u32) {
+        let per_sheet = self.tiles_per_sheet();
+        let slot = (n % per_sheet) as u32;
+        (
+            n / per_sheet,
+            (slot % self.columns) * self.tile_width,
+            (slot / self.columns) * self.tile_height,
+        )
+    }
+}
+
+// ============================================================================
+// Index
+// ============================================================================
+
+/// One thumbnail inside a sprite sheet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrickplayTile {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub sheet: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrickplayIndex {
+    pub video_id: String,
+    pub source: Option<String>,
+    /// Span covered by the tiles (last tile runs for one interval)
+    pub duration_ms: u64,
+    pub config: TrickplayConfig,
+    /// Sheet file names, relative to the index
+    pub sheets: Vec<String>,
+    pub tiles: Vec<TrickplayTile>,
+}
+
+impl TrickplayIndex {
+    /// Tile to show when hovering at `time_ms`
+    pub fn lookup(&self, time_ms: u64) -> Option<&TrickplayTile> {
+        let after = self.tiles.partition_point(|t| t.start_ms <= time_ms);
+        self.tiles.get(after.saturating_sub(1))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("JSON error: {}", e))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid trickplay index: {}", e))
+    }
+
+    /// WebVTT thumbnail track with media-fragment cues into the sheets
+    pub fn to_webvtt(&self) -> String {
+        let mut out = String::from("WEBVTT\n");
+        for tile in &self.tiles {
+            out.push_str(&format!(
+                "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+                vtt_timestamp(tile.start_ms),
+                vtt_timestamp(tile.end_ms),
+                self.sheets[tile.sheet],
+                tile.x,
+                tile.y,
+                tile.width,
+                tile.height
+            ));
+        }
+        out
+    }
+}
+
+fn vtt_timestamp(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        (ms / 60_000) % 60,
+        (ms / 1000) % 60,
+        ms % 1000
+    )
+}
+
+// ============================================================================
+// Sprite Sheets
+// ============================================================================
+
+/// Packs thumbnails into sheets and writes each one out as it fills up
+struct SheetWriter {
+    dir: PathBuf,
+    config: TrickplayConfig,
+    canvas: Option<RgbImage>,
+    sheets: Vec<String>,
+    tiles: Vec<TrickplayTile>,
+}
+
+impl SheetWriter {
+    fn new(dir: &Path, config: TrickplayConfig) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            config,
+            canvas: None,
+            sheets: Vec::new(),
+            tiles: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, thumbnail: &RgbImage, time_ms: u64) -> Result<(), String> {
+        // Decoders may hand back a frame twice around flushes
+        if self.tiles.last().is_some_and(|t| time_ms <= t.start_ms) {
+            return Ok(());
+        }
+        let (sheet, x, y) = self.config.tile_position(self.tiles.len());
+        let canvas = self.canvas.get_or_insert_with(|| {
+            RgbImage::new(
+                self.config.columns * self.config.tile_width,
+                self.config.rows * self.config.tile_height,
+            )
+        });
+        let width = thumbnail.width().min(self.config.tile_width);
+        let height = thumbnail.height().min(self.config.tile_height);
+        imageops::replace(canvas, thumbnail, x as i64, y as i64);
+
+        if let Some(prev) = self.tiles.last_mut() {
+            prev.end_ms = time_ms;
+        }
+        self.tiles.push(TrickplayTile {
+            start_ms: time_ms,
+            end_ms: time_ms + self.config.interval_ms,
+            sheet,
+            x,
+            y,
+            width,
+            height,
+        });
+        if self
+            .tiles
+            .len()
+            .is_multiple_of(self.config.tiles_per_sheet())
+        {
+            self.flush_sheet()?;
+        }
+        Ok(())
+    }
+
+    fn flush_sheet(&mut self) -> Result<(), String> {
+        let Some(canvas) = self.canvas.take() else {
+            return Ok(());
+        };
+        let name = format!("sheet_{:03}.jpg", self.sheets.len());
+        // Trim the unused rows of a partial last sheet
+        let used_rows = self
+            .tiles
+            .iter()
+            .filter(|t| t.sheet == self.sheets.len())
+            .map(|t| t.y + self.config.tile_height)
+            .max()
+            .unwrap_or(canvas.height());
+        let image = DynamicImage::ImageRgb8(canvas).crop_imm(
+            0,
+            0,
+            self.config.columns * self.config.tile_width,
+            used_rows,
+        );
+        save_image(&image, self.dir.join(&name), Some(self.config.quality))?;
+        self.sheets.push(name);
+        Ok(())
+    }
+
+    fn finish(
+        mut self,
+        video_id: String,
+        source: Option<String>,
+    ) -> Result<TrickplayIndex, String> {
+        self.flush_sheet()?;
+        let index = TrickplayIndex {
+            video_id,
+            source,
+            duration_ms: self.tiles.last().map(|t| t.end_ms).unwrap_or(0),
+            config: self.config,
+            sheets: self.sheets,
+            tiles: self.tiles,
+        };
+        std::fs::write(self.dir.join(INDEX_FILE), index.to_json()?)
+            .map_err(|e| format!("Failed to write trickplay index: {}", e))?;
+        std::fs::write(self.dir.join(WEBVTT_FILE), index.to_webvtt())
+            .map_err(|e| format!("Failed to write trickplay WebVTT: {}", e))?;
+        Ok(index)
+    }
+}
+
+/// Convert a decoded frame to RGB and shrink it into the tile box
+fn thumbnail_from_frame(frame: DecodedFrame, config: &TrickplayConfig) -> Result<RgbImage, String> {
+    let frame = decoded_to_rgb(frame)?;
+    let rgb = RgbImage::from_raw(frame.width, frame.height, frame.data)
+        .ok_or("RGB buffer does not match frame size")?;
+    let options = ThumbnailOptions {
+        max_width: config.tile_width,
+        max_height: config.tile_height,
+        quality: config.quality,
+        format: "jpeg".to_string(),
+    };
+    Ok(create_thumbnail(&DynamicImage::ImageRgb8(rgb), &options)?.into_rgb8())
+}
+
+// ============================================================================
+// Cache
+// ============================================================================
+
+/// Cache directory for one video
+pub fn cache_dir(video_id: &str) -> PathBuf {
+    let mut path = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("SLAIN");
+    path.push("trickplay");
+    path.push(video_id);
+    path
+}
+
+/// Previously generated index for `path`, if it was built with the same config
+pub fn load_cached(path: &Path, config: &TrickplayConfig) -> Option<TrickplayIndex> {
+    let video_id = crate::history::generate_video_id(&path.to_string_lossy());
+    let dir = cache_dir(&video_id);
+    let json = std::fs::read_to_string(dir.join(INDEX_FILE)).ok()?;
+    let index = TrickplayIndex::from_json(&json).ok()?;
+    let complete = index.sheets.iter().all(|s| dir.join(s).exists());
+    (index.config == *config && complete).then_some(index)
+}
+
+/// Generate (or reuse) the trickplay set for `path` in the cache
+pub fn generate(path: &Path, config: &TrickplayConfig) -> Result<TrickplayIndex, String> {
+    if let Some(index) = load_cached(path, config) {
+        return Ok(index);
+    }
+    let video_id = crate::history::generate_video_id(&path.to_string_lossy());
+    generate_into(
+        path,
+        &cache_dir(&video_id),
+        config,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+}
+
+/// Generate the trickplay set for `path` into `dir`, reporting the time of
+/// each tile written. Stops early (with an error) when `cancel` is set.
+pub fn generate_into<F>(
+    path: &Path,
+    dir: &Path,
+    config: &TrickplayConfig,
+    cancel: &AtomicBool,
+    mut progress: F,
+) -> Result<TrickplayIndex, String>
+where
+    F: FnMut(u64),
+{
+    config.validate()?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+
+    let mut writer = SheetWriter::new(dir, config.clone());
+    let options = FileDecodeOptions {
+        keyframes_only: true,
+        min_interval_ms: config.interval_ms,
+        ..Default::default()
+    };
+    decode_file(path, options, |frame| {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Trickplay generation cancelled".to_string());
+        }
+        let time_ms = frame.pts.max(0) as u64;
+        let thumbnail = thumbnail_from_frame(frame, config)?;
+        writer.push(&thumbnail, time_ms)?;
+        progress(time_ms);
+        Ok(true)
+    })?;
+
+    let video_id = crate::history::generate_video_id(&path.to_string_lossy());
+    let index = writer.finish(video_id, Some(path.display().to_string()))?;
+    tracing::info!(
+        "Trickplay: {} tiles in {} sheets for {:?}",
+        index.tiles.len(),
+        index.sheets.len(),
+        path
+    );
+    Ok(index)
+}
+
+// ============================================================================
+// Background Generator
+// ============================================================================
+
+/// Runs `generate` on a worker thread; poll `result` from the UI
+pub struct TrickplayGenerator {
+    dir: PathBuf,
+    cancel: Arc<AtomicBool>,
+    progress_ms: Arc<AtomicU64>,
+    result: Arc<Mutex<Option<Result<TrickplayIndex, String>>>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl TrickplayGenerator {
+    pub fn spawn(path: PathBuf, config: TrickplayConfig) -> Self {
+        let video_id = crate::history::generate_video_id(&path.to_string_lossy());
+        let dir = cache_dir(&video_id);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let progress_ms = Arc::new(AtomicU64::new(0));
+        let result = Arc::new(Mutex::new(None));
+
+        let handle = {
+            let (dir, cancel, progress_ms, result) = (
+                dir.clone(),
+                cancel.clone(),
+                progress_ms.clone(),
+                result.clone(),
+            );
+            thread::spawn(move || {
+                let outcome = match load_cached(&path, &config) {
+                    Some(index) => Ok(index),
+                    None => generate_into(&path, &dir, &config, &cancel, |ms| {
+                        progress_ms.store(ms, Ordering::Relaxed)
+                    }),
+                };
+                if let Err(e) = &outcome {
+                    tracing::warn!("Trickplay generation failed for {:?}: {}", path, e);
+                }
+                *result.lock().unwrap() = Some(outcome);
+            })
+        };
+
+        Self {
+            dir,
+            cancel,
+            progress_ms,
+            result,
+            handle: Some(handle),
+        }
+    }
+
+    /// Directory the sheets are written to
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Media time of the last tile written
+    pub fn progress_ms(&self) -> u64 {
+        self.progress_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.lock().unwrap().is_some()
+    }
+
+    /// Take the finished index (or error); `None` while still running
+    pub fn take_result(&mut self) -> Option<Result<TrickplayIndex, String>> {
+        let result = self.result.lock().unwrap().take();
+        if result.is_some() {
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+        result
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for TrickplayGenerator {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// ============================================================================
+// Hover Preview
+// ============================================================================
+
+/// A finished index with its sheets loaded on demand, for seekbar hover
+pub struct TrickplayPreview {
+    index: TrickplayIndex,
+    dir: PathBuf,
+    sheets: HashMap<usize, RgbImage>,
+}
+
+impl TrickplayPreview {
+    pub fn new(index: TrickplayIndex, dir: PathBuf) -> Self {
+        Self {
+            index,
+            dir,
+            sheets: HashMap::new(),
+        }
+    }
+
+    pub fn index(&self) -> &TrickplayIndex {
+        &self.index
+    }
+
+    /// Tile under `time_ms` and its RGB24 pixels
+    pub fn thumbnail(&mut self, time_ms: u64) -> Option<(TrickplayTile, Vec<u8>)> {
+        let tile = self.index.lookup(time_ms)?.clone();
+        if !self.sheets.contains_key(&tile.sheet) {
+            let path = self.dir.join(self.index.sheets.get(tile.sheet)?);
+            match image::open(&path) {
+                Ok(sheet) => {
+                    self.sheets.insert(tile.sheet, sheet.into_rgb8());
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to load trickplay sheet {:?}: {}", path, e);
+                    return None;
+                }
+            }
+        }
+        let sheet = self.sheets.get(&tile.sheet)?;
+        if tile.x + tile.width > sheet.width() || tile.y + tile.height > sheet.height() {
+            return None;
+        }
+        let pixels = imageops::crop_imm(sheet, tile.x, tile.y, tile.width, tile.height)
+            .to_image()
+            .into_raw();
+        Some((tile, pixels))
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> TrickplayConfig {
+        TrickplayConfig {
+            interval_ms: 1000,
+            tile_width: 16,
+            tile_height: 9,
+            columns: 3,
+            rows: 2,
+            quality: 80,
+        }
+    }
+
+    #[test]
+    fn test_sheet_packing_and_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = small_config();
+        let mut writer = SheetWriter::new(dir.path(), config.clone());
+        for i in 0..8u64 {
+            let thumb = RgbImage::from_pixel(16, 9, image::Rgb([(i * 30) as u8, 0, 0]));
+            writer.push(&thumb, i * 1000).unwrap();
+        }
+        // Repeated timestamps are ignored
+        writer.push(&RgbImage::new(16, 9), 7000).unwrap();
+        let index = writer.finish("abc".to_string(), None).unwrap();
+
+        assert_eq!(index.tiles.len(), 8);
+        assert_eq!(index.sheets, vec!["sheet_000.jpg", "sheet_001.jpg"]);
+        assert_eq!(
+            (index.tiles[4].sheet, index.tiles[4].x, index.tiles[4].y),
+            (0, 16, 9)
+        );
+        assert_eq!(
+            (index.tiles[6].sheet, index.tiles[6].x, index.tiles[6].y),
+            (1, 0, 0)
+        );
+        assert_eq!(index.duration_ms, 8000);
+
+        // Partial last sheet is trimmed to its single used row
+        let last = image::open(dir.path().join("sheet_001.jpg")).unwrap();
+        assert_eq!((last.width(), last.height()), (48, 9));
+
+        assert_eq!(index.lookup(0).unwrap().start_ms, 0);
+        assert_eq!(index.lookup(2500).unwrap().start_ms, 2000);
+        assert_eq!(index.lookup(60_000).unwrap().start_ms, 7000);
+
+        let mut preview = TrickplayPreview::new(index.clone(), dir.path().to_path_buf());
+        let (tile, pixels) = preview.thumbnail(6500).unwrap();
+        assert_eq!((tile.sheet, tile.start_ms), (1, 6000));
+        assert_eq!(pixels.len(), 16 * 9 * 3);
+        assert!((pixels[0] as i32 - 180).abs() < 20);
+
+        let json = std::fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap();
+        let loaded = TrickplayIndex::from_json(&json).unwrap();
+        assert_eq!(loaded.tiles, index.tiles);
+        assert_eq!(loaded.config, config);
+    }
+
+    #[test]
+    fn test_webvtt_cues() {
+        let index = TrickplayIndex {
+            video_id: "id".to_string(),
+            source: None,
+            duration_ms: 3_725_500,
+            config: TrickplayConfig::default(),
+            sheets: vec!["sheet_000.jpg".to_string()],
+            tiles: vec![TrickplayTile {
+                start_ms: 3_715_500,
+                end_ms: 3_725_500,
+                sheet: 0,
+                x: 320,
+                y: 90,
+                width: 160,
+                height: 67,
+            }],
+        };
+        assert_eq!(
+            index.to_webvtt(),
+            "WEBVTT\n\n01:01:55.500 --> 01:02:05.500\nsheet_000.jpg#xywh=320,90,160,67\n"
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_from_frame_keeps_aspect() {
+        let (width, height) = (64u32, 36u32);
+        let mut data = vec![128u8; (width * height * 3 / 2) as usize];
+        data[..(width * height) as usize].fill(200);
+        let frame = DecodedFrame {
+            pts: 0,
+            width,
+            height,
+            pitch: width,
+            format: crate::hw_decode::PixelFormat::NV12,
+            data,
+            progressive: true,
+        };
+        let thumb = thumbnail_from_frame(frame, &small_config()).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (16, 9));
+        assert!(thumb.get_pixel(8, 4)[1] > 180);
+    }
+}
@@ -206,12 +206,13 @@ fn parse_pes_header(data: &[u8]) -> Option<(PesPacket, usize)> {
     let stream_id = data[3];
     let pes_length = ((data[4] as usize) << 8) | data[5] as usize;
 
-    // Video/audio streams have extended header
-    let (pts, dts, header_len) = if stream_id >= 0xE0 || (stream_id >= 0xC0 && stream_id < 0xE0) {
-        if data.len() < 9 {
-            return None;
-        }
-
+    // Everything but padding, private_stream_2 and the system streams has
+    // the optional header (private_stream_1 carries AC-3, DTS, subtitles)
+    let has_header = !matches!(
+        stream_id,
+        0xBC | 0xBE | 0xBF | 0xF0 | 0xF1 | 0xF2 | 0xF8 | 0xFF
+    );
+    let (pts, dts, header_len) = if has_header {
         let pts_dts_flags = (data[7] >> 6) & 0x03;
         let header_data_length = data[8] as usize;
 
@@ -270,7 +271,8 @@ pub struct TsDemuxer<R: Read + Seek> {
     info: TsInfo,
     pid_to_stream: HashMap<u16, usize>,
     pes_buffers: HashMap<u16, Vec<u8>>,
-    pes_pts: HashMap<u16, Option<i64>>,
+    /// PTS and DTS from each PES header
+    pes_times: HashMap<u16, (Option<i64>, Option<i64>)>,
     pes_keyframe: HashMap<u16, bool>,
     packet_size: usize,
 }
@@ -292,7 +294,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
             },
             pid_to_stream: HashMap::new(),
             pes_buffers: HashMap::new(),
-            pes_pts: HashMap::new(),
+            pes_times: HashMap::new(),
             pes_keyframe: HashMap::new(),
             packet_size,
         };
@@ -448,6 +450,12 @@ impl<R: Read + Seek> TsDemuxer<R> {
             let es_info_length =
                 (((section[pos + 3] as usize) & 0x0F) << 8) | section[pos + 4] as usize;
 
+            // PMTs repeat several times a second
+            if self.pid_to_stream.contains_key(&pid) {
+                pos += 5 + es_info_length;
+                continue;
+            }
+
             // Parse ES descriptors for language
             let mut language = None;
             if es_info_length > 0 && pos + 5 + es_info_length <= section.len() {
@@ -549,7 +557,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
 
                     // Parse PES header for PTS
                     if let Some((pes, _)) = parse_pes_header(payload) {
-                        self.pes_pts.insert(header.pid, pes.pts);
+                        self.pes_times.insert(header.pid, (pes.pts, pes.dts));
                     }
 
                     return Some(packet);
@@ -559,7 +567,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
                     self.pes_keyframe.insert(header.pid, keyframe);
 
                     if let Some((pes, _)) = parse_pes_header(payload) {
-                        self.pes_pts.insert(header.pid, pes.pts);
+                        self.pes_times.insert(header.pid, (pes.pts, pes.dts));
                     }
                 }
             } else {
@@ -576,7 +584,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
 
     fn emit_pes(&mut self, pid: u16) -> Option<TsPacket> {
         let buffer = self.pes_buffers.remove(&pid)?;
-        let pts = self.pes_pts.remove(&pid).flatten();
+        let (pts, dts) = self.pes_times.remove(&pid).unwrap_or_default();
         let keyframe = self.pes_keyframe.remove(&pid).unwrap_or(false);
 
         // Parse PES to get actual payload
@@ -589,7 +597,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
         Some(TsPacket {
             pid,
             pts,
-            dts: pts,
+            dts: dts.or(pts),
             keyframe,
             data,
         })
@@ -608,7 +616,7 @@ impl<R: Read + Seek> TsDemuxer<R> {
             .seek(SeekFrom::Start(0))
             .map_err(|e| format!("Seek error: {}", e))?;
         self.pes_buffers.clear();
-        self.pes_pts.clear();
+        self.pes_times.clear();
         self.pes_keyframe.clear();
         Ok(())
     }
@@ -0,0 +1,858 @@
+//! MPEG Transport Stream writer
+//!
+//! One program: PAT and PMT at the start, again every `psi_interval_ms` and in
+//! front of every video keyframe (so HLS segments cut there are decodable on
+//! their own), then each packet as one PES split over 188-byte TS packets.
+//! PCR rides in the adaptation field of the PCR stream (the first video
+//! stream) at least every `pcr_interval_ms`. Only needs `Write`, so it can
+//! feed a socket or pipe as well as a file.
+//!
+//! Timestamps are shifted by `mux_delay_ms` so B-frame DTS stay positive and
+//! the PCR runs that far ahead of decoding. With `mux_rate_bps` set the output
+//! is constant bitrate: the PCR follows the byte position and null packets
+//! are stuffed in whenever data would otherwise arrive early.
+//!
+//! H.264/H.265 leave as Annex B (avcC input is rewritten) with SPS/PPS in
+//! front of every keyframe; raw AAC gets ADTS headers from its
+//! AudioSpecificConfig.
+
+use std::io::Write;
+
+use crate::h264_utils;
+use crate::mux::{MuxCodec, MuxPacket, MuxStream, MuxTrackKind};
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0x0000;
+const NULL_PID: u16 = 0x1FFF;
+
+const STREAM_TYPE_MPEG1_AUDIO: u8 = 0x03;
+const STREAM_TYPE_AAC: u8 = 0x0F;
+const STREAM_TYPE_H264: u8 = 0x1B;
+const STREAM_TYPE_H265: u8 = 0x24;
+const STREAM_TYPE_AC3: u8 = 0x81;
+const STREAM_TYPE_EAC3: u8 = 0x87;
+
+/// 27 MHz system clock ticks per microsecond
+const PCR_PER_US: u64 = 27;
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct TsMuxConfig {
+    pub transport_stream_id: u16,
+    pub program_number: u16,
+    pub pmt_pid: u16,
+    /// PID of the first elementary stream; the rest follow in order
+    pub first_stream_pid: u16,
+    /// Maximum gap between PCRs (ISO 13818-1 allows up to 100 ms)
+    pub pcr_interval_ms: u32,
+    /// Maximum gap between PAT/PMT repeats
+    pub psi_interval_ms: u32,
+    /// Added to every PTS/DTS; the PCR leads decoding by this much
+    pub mux_delay_ms: u32,
+    /// Constant output bitrate, padded with null packets (None = VBR)
+    pub mux_rate_bps: Option<u64>,
+}
+
+impl Default for TsMuxConfig {
+    fn default() -> Self {
+        Self {
+            transport_stream_id: 1,
+            program_number: 1,
+            pmt_pid: 0x1000,
+            first_stream_pid: 0x100,
+            pcr_interval_ms: 40,
+            psi_interval_ms: 100,
+            mux_delay_ms: 700,
+            mux_rate_bps: None,
+        }
+    }
+}
+
+// ============================================================================
+// Encoding Helpers
+// ============================================================================
+
+/// CRC-32/MPEG-2 over a PSI section
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// 33-bit PES timestamp with its 4-bit prefix and marker bits
+fn put_timestamp(out: &mut Vec<u8>, prefix: u8, ts: u64) {
+    let ts = ts & 0x1_FFFF_FFFF;
+    out.push((prefix << 4) | (((ts >> 30) as u8 & 0x07) << 1) | 1);
+    out.push((ts >> 22) as u8);
+    out.push((((ts >> 15) as u8) << 1) | 1);
+    out.push((ts >> 7) as u8);
+    out.push(((ts as u8) << 1) | 1);
+}
+
+/// 6-byte PCR: 33-bit base (90 kHz), 6 reserved bits, 9-bit extension
+fn encode_pcr(pcr: u64) -> [u8; 6] {
+    let base = (pcr / 300) & 0x1_FFFF_FFFF;
+    let ext = pcr % 300;
+    [
+        (base >> 25) as u8,
+        (base >> 17) as u8,
+        (base >> 9) as u8,
+        (base >> 1) as u8,
+        (((base & 1) as u8) << 7) | 0x7E | (ext >> 8) as u8,
+        ext as u8,
+    ]
+}
+
+/// ADTS header for one raw AAC frame described by an AudioSpecificConfig
+fn adts_header(config: &[u8], frame_len: usize) -> Option<[u8; 7]> {
+    if config.len() < 2 {
+        return None;
+    }
+    let object_type = config[0] >> 3;
+    let frequency_index = ((config[0] & 0x07) << 1) | (config[1] >> 7);
+    let channels = (config[1] >> 3) & 0x0F;
+    if object_type == 0 || object_type > 4 || frequency_index > 12 {
+        return None;
+    }
+    let len = frame_len + 7;
+    Some([
+        0xFF,
+        0xF1, // MPEG-4, layer 0, no CRC
+        ((object_type - 1) << 6) | (frequency_index << 2) | (channels >> 2),
+        ((channels & 0x03) << 6) | ((len >> 11) as u8 & 0x03),
+        (len >> 3) as u8,
+        (((len & 0x07) as u8) << 5) | 0x1F,
+        0xFC,
+    ])
+}
+
+/// avcC/hvcC NAL units to Annex B. Unlike `h264_utils::avcc_to_annexb` this
+/// doesn't sniff for start codes: a 256-511 byte NAL's length prefix
+/// (00 00 01 xx) looks like one.
+pub(crate) fn length_prefixed_to_annexb(data: &[u8], nal_length_size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    let mut pos = 0;
+    while pos + nal_length_size <= data.len() {
+        let len = data[pos..pos + nal_length_size]
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        pos += nal_length_size;
+        let end = (pos + len).min(data.len());
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(&data[pos..end]);
+        pos = end;
+    }
+    out
+}
+
+/// Whether an Annex B access unit already carries its SPS
+fn has_sps(annexb: &[u8], hevc: bool) -> bool {
+    h264_utils::split_annexb(annexb)
+        .iter()
+        .any(|nal| match nal.first() {
+            Some(&header) if hevc => (header >> 1) & 0x3F == 33,
+            Some(&header) => header & 0x1F == 7,
+            None => false,
+        })
+}
+
+/// How a stream's packets are rewritten into what TS expects
+enum Framing {
+    AsIs,
+    /// H.264/H.265: Annex B parameter sets to repeat on keyframes, and the
+    /// NAL length size when packets are length-prefixed (avcC/hvcC)
+    Video {
+        nal_length_size: Option<usize>,
+        parameter_sets: Vec<u8>,
+        hevc: bool,
+    },
+    /// Raw AAC frames: AudioSpecificConfig for the ADTS header
+    Adts(Vec<u8>),
+}
+
+struct TsStreamState {
+    pid: u16,
+    stream_id: u8,
+    stream_type: u8,
+    is_video: bool,
+    language: String,
+    framing: Framing,
+    continuity: u8,
+}
+
+impl TsStreamState {
+    fn new(index: usize, pid: u16, stream: &MuxStream) -> Result<Self, String> {
+        let (stream_type, stream_id) = match stream.codec {
+            MuxCodec::H264 => (STREAM_TYPE_H264, 0xE0),
+            MuxCodec::H265 => (STREAM_TYPE_H265, 0xE0),
+            MuxCodec::AAC => (STREAM_TYPE_AAC, 0xC0),
+            MuxCodec::MP3 => (STREAM_TYPE_MPEG1_AUDIO, 0xC0),
+            // private_stream_1, as ATSC/Blu-ray carry Dolby audio
+            MuxCodec::AC3 => (STREAM_TYPE_AC3, 0xBD),
+            MuxCodec::EAC3 => (STREAM_TYPE_EAC3, 0xBD),
+            codec => return Err(format!("{:?} cannot be muxed into MPEG-TS", codec)),
+        };
+        let is_video = matches!(stream.kind, MuxTrackKind::Video { .. });
+        let stream_id = if stream_id == 0xBD {
+            stream_id
+        } else {
+            stream_id + (index as u8 & 0x0F)
+        };
+
+        let config = stream.codec_private.as_deref();
+        let hevc = stream.codec == MuxCodec::H265;
+        let framing = match (stream.codec, config) {
+            (MuxCodec::H264 | MuxCodec::H265, Some(config)) if h264_utils::is_annexb(config) => {
+                Framing::Video {
+                    nal_length_size: None,
+                    parameter_sets: config.to_vec(),
+                    hevc,
+                }
+            }
+            (MuxCodec::H264 | MuxCodec::H265, Some(config)) => {
+                let parsed = if hevc {
+                    h264_utils::parse_hvcc_extradata(config)
+                } else {
+                    h264_utils::parse_avcc_extradata(config)
+                };
+                let (parameter_sets, nal_length_size) =
+                    parsed.ok_or("Invalid avcC/hvcC codec private data")?;
+                Framing::Video {
+                    nal_length_size: Some(nal_length_size),
+                    parameter_sets,
+                    hevc,
+                }
+            }
+            (MuxCodec::AAC, Some(config)) => Framing::Adts(config.to_vec()),
+            _ => Framing::AsIs,
+        };
+
+        Ok(Self {
+            pid,
+            stream_id,
+            stream_type,
+            is_video,
+            language: stream.language.clone(),
+            framing,
+            continuity: 0,
+        })
+    }
+
+    /// Elementary stream bytes for one packet
+    fn frame(&self, packet: &MuxPacket) -> Vec<u8> {
+        match &self.framing {
+            Framing::AsIs => packet.data.clone(),
+            Framing::Video {
+                nal_length_size,
+                parameter_sets,
+                hevc,
+            } => {
+                let annexb = match nal_length_size {
+                    Some(size) => length_prefixed_to_annexb(&packet.data, *size),
+                    None => packet.data.clone(),
+                };
+                if packet.keyframe && !has_sps(&annexb, *hevc) {
+                    [parameter_sets.as_slice(), &annexb].concat()
+                } else {
+                    annexb
+                }
+            }
+            Framing::Adts(config) => {
+                // Already ADTS (sync word) or unknown config: pass through
+                let already_adts = packet.data.len() > 1
+                    && packet.data[0] == 0xFF
+                    && packet.data[1] & 0xF0 == 0xF0;
+                match adts_header(config, packet.data.len()) {
+                    Some(header) if !already_adts => [&header[..], &packet.data].concat(),
+                    _ => packet.data.clone(),
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Muxer
+// ============================================================================
+
+pub struct TsMuxer<W: Write> {
+    writer: W,
+    config: TsMuxConfig,
+    streams: Vec<TsStreamState>,
+    pcr_pid: u16,
+    pat_continuity: u8,
+    pmt_continuity: u8,
+    packets_written: u64,
+    /// Input DTS (µs) the CBR byte clock starts at
+    clock_origin_us: Option<i64>,
+    last_pcr_us: Option<i64>,
+    last_psi_us: Option<i64>,
+    underflow_warned: bool,
+}
+
+impl<W: Write> TsMuxer<W> {
+    pub fn new(writer: W, streams: Vec<MuxStream>, config: TsMuxConfig) -> Result<Self, String> {
+        if streams.is_empty() {
+            return Err("TS muxer needs at least one stream".to_string());
+        }
+        if config.mux_rate_bps.is_some_and(|rate| rate < 10_000) {
+            return Err("TS mux rate is too low".to_string());
+        }
+        let states = streams
+            .iter()
+            .enumerate()
+            .map(|(i, s)| TsStreamState::new(i, config.first_stream_pid + i as u16, s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pcr_pid = states.iter().find(|s| s.is_video).unwrap_or(&states[0]).pid;
+
+        Ok(Self {
+            writer,
+            config,
+            streams: states,
+            pcr_pid,
+            pat_continuity: 0,
+            pmt_continuity: 0,
+            packets_written: 0,
+            clock_origin_us: None,
+            last_pcr_us: None,
+            last_psi_us: None,
+            underflow_warned: false,
+        })
+    }
+
+    /// TS packets (188 bytes each) written so far, stuffing included
+    pub fn packets_written(&self) -> u64 {
+        self.packets_written
+    }
+
+    /// Write one compressed frame as a PES
+    pub fn write_packet(&mut self, packet: &MuxPacket) -> Result<(), String> {
+        let index = packet.stream;
+        let stream = self
+            .streams
+            .get(index)
+            .ok_or_else(|| format!("Unknown stream index {}", index))?;
+        let es = stream.frame(packet);
+        let is_video = stream.is_video;
+        let dts_us = packet.dts_us.unwrap_or(packet.pts_us);
+        let origin = *self.clock_origin_us.get_or_insert(dts_us);
+
+        if self.config.mux_rate_bps.is_some() {
+            // Hold data back until the byte clock reaches its DTS
+            while self.clock_us(origin) < dts_us {
+                self.write_null_packet()?;
+            }
+            let late_us = self.clock_us(origin) - dts_us;
+            if late_us > self.config.mux_delay_ms as i64 * 1000 && !self.underflow_warned {
+                tracing::warn!(
+                    "TS mux rate too low: data running {} ms behind its DTS",
+                    late_us / 1000
+                );
+                self.underflow_warned = true;
+            }
+        }
+
+        let now = self.now_us(origin, dts_us);
+        let psi_due = self
+            .last_psi_us
+            .is_none_or(|last| now - last >= self.config.psi_interval_ms as i64 * 1000);
+        if psi_due || (is_video && packet.keyframe) {
+            self.write_tables()?;
+            self.last_psi_us = Some(now);
+        }
+
+        let pes = self.build_pes(index, packet, &es);
+        let mut offset = 0;
+        let mut first = true;
+        while offset < pes.len() || first {
+            let now = self.now_us(origin, dts_us);
+            let pcr_due = self
+                .last_pcr_us
+                .is_none_or(|last| now - last >= self.config.pcr_interval_ms as i64 * 1000);
+            let pid = self.streams[index].pid;
+            let pcr = if pcr_due {
+                self.last_pcr_us = Some(now);
+                Some(self.pcr_at(now))
+            } else {
+                None
+            };
+            let pcr_here = pcr.filter(|_| pid == self.pcr_pid);
+            if let Some(pcr) = pcr.filter(|_| pid != self.pcr_pid) {
+                // The PCR stream has nothing to send now: PCR-only packet
+                let continuity = self
+                    .streams
+                    .iter()
+                    .find(|s| s.pid == self.pcr_pid)
+                    .map_or(0, |s| s.continuity.wrapping_sub(1) & 0x0F);
+                let packet =
+                    build_ts_packet(self.pcr_pid, false, continuity, false, Some(pcr), &[]).0;
+                self.emit(&packet)?;
+            }
+
+            let stream = &mut self.streams[index];
+            let (ts_packet, used) = build_ts_packet(
+                pid,
+                first,
+                stream.continuity,
+                first && packet.keyframe,
+                pcr_here,
+                &pes[offset..],
+            );
+            stream.continuity = (stream.continuity + 1) & 0x0F;
+            self.emit(&ts_packet)?;
+            offset += used;
+            first = false;
+        }
+        Ok(())
+    }
+
+    /// Write PAT and PMT now (e.g. at the start of a new HLS segment)
+    pub fn write_tables(&mut self) -> Result<(), String> {
+        let pat = self.pat_section();
+        let pmt = self.pmt_section();
+        let pat_packet = build_psi_packet(PAT_PID, self.pat_continuity, &pat);
+        self.pat_continuity = (self.pat_continuity + 1) & 0x0F;
+        let pmt_packet = build_psi_packet(self.config.pmt_pid, self.pmt_continuity, &pmt);
+        self.pmt_continuity = (self.pmt_continuity + 1) & 0x0F;
+        self.emit(&pat_packet)?;
+        self.emit(&pmt_packet)
+    }
+
+    /// Flush and hand back the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Flush error: {}", e))?;
+        tracing::info!(
+            "TS mux: {} streams, {} packets ({} bytes)",
+            self.streams.len(),
+            self.packets_written,
+            self.packets_written * TS_PACKET_SIZE as u64
+        );
+        Ok(self.writer)
+    }
+
+    /// Byte clock of the next packet in CBR mode, in input µs
+    fn clock_us(&self, origin_us: i64) -> i64 {
+        let rate = self.config.mux_rate_bps.unwrap_or(1).max(1) as i128;
+        let bits = self.packets_written as i128 * TS_PACKET_SIZE as i128 * 8;
+        origin_us + (bits * 1_000_000 / rate) as i64
+    }
+
+    /// Transport time of the next packet: byte clock (CBR) or the DTS (VBR)
+    fn now_us(&self, origin_us: i64, dts_us: i64) -> i64 {
+        if self.config.mux_rate_bps.is_some() {
+            self.clock_us(origin_us)
+        } else {
+            self.last_pcr_us.map_or(dts_us, |last| last.max(dts_us))
+        }
+    }
+
+    /// PCR for an input time; the PES timestamps carry the mux delay instead
+    fn pcr_at(&self, time_us: i64) -> u64 {
+        time_us.max(0) as u64 * PCR_PER_US
+    }
+
+    fn ts_time(&self, us: i64) -> u64 {
+        let us = us + self.config.mux_delay_ms as i64 * 1000;
+        (us.max(0) as u64 * 9 / 100) & 0x1_FFFF_FFFF
+    }
+
+    fn build_pes(&self, index: usize, packet: &MuxPacket, es: &[u8]) -> Vec<u8> {
+        let stream = &self.streams[index];
+        let pts = self.ts_time(packet.pts_us);
+        let dts = packet.dts_us.map(|d| self.ts_time(d)).filter(|&d| d != pts);
+
+        let header_len = if dts.is_some() { 10 } else { 5 };
+        let mut pes = Vec::with_capacity(es.len() + 19);
+        pes.extend_from_slice(&[0x00, 0x00, 0x01, stream.stream_id]);
+        let length = 3 + header_len + es.len();
+        // Unbounded (0) is only allowed for video
+        let length = if length > u16::MAX as usize && stream.is_video {
+            0
+        } else {
+            length.min(u16::MAX as usize)
+        };
+        pes.extend_from_slice(&(length as u16).to_be_bytes());
+        pes.push(0x84); // marker bits, data_alignment_indicator
+        pes.push(if dts.is_some() { 0xC0 } else { 0x80 });
+        pes.push(header_len as u8);
+        match dts {
+            Some(dts) => {
+                put_timestamp(&mut pes, 0x3, pts);
+                put_timestamp(&mut pes, 0x1, dts);
+            }
+            None => put_timestamp(&mut pes, 0x2, pts),
+        }
+        pes.extend_from_slice(es);
+        pes
+    }
+
+    fn pat_section(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.config.program_number.to_be_bytes());
+        body.extend_from_slice(&(0xE000 | self.config.pmt_pid).to_be_bytes());
+        psi_section(0x00, self.config.transport_stream_id, &body)
+    }
+
+    fn pmt_section(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(0xE000 | self.pcr_pid).to_be_bytes());
+        body.extend_from_slice(&0xF000u16.to_be_bytes()); // no program descriptors
+        for stream in &self.streams {
+            let mut descriptors = Vec::new();
+            let language = stream.language.as_bytes();
+            if language.len() == 3 && stream.language != "und" {
+                // ISO 639 language descriptor
+                descriptors.extend_from_slice(&[0x0A, 4]);
+                descriptors.extend_from_slice(language);
+                descriptors.push(0);
+            }
+            body.push(stream.stream_type);
+            body.extend_from_slice(&(0xE000 | stream.pid).to_be_bytes());
+            body.extend_from_slice(&(0xF000 | descriptors.len() as u16).to_be_bytes());
+            body.extend_from_slice(&descriptors);
+        }
+        psi_section(0x02, self.config.program_number, &body)
+    }
+
+    fn write_null_packet(&mut self) -> Result<(), String> {
+        let mut packet = [0xFFu8; TS_PACKET_SIZE];
+        packet[..4].copy_from_slice(&[TS_SYNC_BYTE, (NULL_PID >> 8) as u8, NULL_PID as u8, 0x10]);
+        self.emit(&packet)
+    }
+
+    fn emit(&mut self, packet: &[u8; TS_PACKET_SIZE]) -> Result<(), String> {
+        self.writer
+            .write_all(packet)
+            .map_err(|e| format!("Write error: {}", e))?;
+        self.packets_written += 1;
+        Ok(())
+    }
+}
+
+/// Long-form PSI section (version 0, single section) with its CRC
+fn psi_section(table_id: u8, id: u16, body: &[u8]) -> Vec<u8> {
+    let section_length = 5 + body.len() + 4;
+    let mut section = vec![
+        table_id,
+        0xB0 | (section_length >> 8) as u8, // syntax indicator, reserved
+        section_length as u8,
+    ];
+    section.extend_from_slice(&id.to_be_bytes());
+    section.extend_from_slice(&[0xC1, 0x00, 0x00]); // version 0, current, section 0 of 0
+    section.extend_from_slice(body);
+    let crc = crc32_mpeg2(&section);
+    section.extend_from_slice(&crc.to_be_bytes());
+    section
+}
+
+/// A PSI section in one packet: pointer field, section, 0xFF stuffing
+fn build_psi_packet(pid: u16, continuity: u8, section: &[u8]) -> [u8; TS_PACKET_SIZE] {
+    let mut packet = [0xFFu8; TS_PACKET_SIZE];
+    packet[0] = TS_SYNC_BYTE;
+    packet[1] = 0x40 | (pid >> 8) as u8;
+    packet[2] = pid as u8;
+    packet[3] = 0x10 | continuity;
+    packet[4] = 0; // pointer_field
+    let len = section.len().min(TS_PACKET_SIZE - 5);
+    packet[5..5 + len].copy_from_slice(&section[..len]);
+    packet
+}
+
+/// One TS packet carrying as much of `payload` as fits; short payloads are
+/// padded through the adaptation field. Returns the packet and bytes used.
+fn build_ts_packet(
+    pid: u16,
+    unit_start: bool,
+    continuity: u8,
+    random_access: bool,
+    pcr: Option<u64>,
+    payload: &[u8],
+) -> ([u8; TS_PACKET_SIZE], usize) {
+    // Adaptation field after its length byte
+    let mut adaptation = Vec::new();
+    if random_access || pcr.is_some() {
+        adaptation
+            .push(if random_access { 0x40 } else { 0 } | if pcr.is_some() { 0x10 } else { 0 });
+        if let Some(pcr) = pcr {
+            adaptation.extend_from_slice(&encode_pcr(pcr));
+        }
+    }
+    let mut adaptation_len = if adaptation.is_empty() {
+        0
+    } else {
+        1 + adaptation.len()
+    };
+    let room = TS_PACKET_SIZE - 4 - adaptation_len;
+    let used = payload.len().min(room);
+    let stuffing = room - used;
+    if stuffing > 0 {
+        if adaptation_len == 0 {
+            // A bare length byte covers one byte; more needs the flags byte
+            if stuffing > 1 {
+                adaptation.push(0);
+                adaptation.resize(stuffing - 1, 0xFF);
+            }
+        } else {
+            adaptation.resize(adaptation.len() + stuffing, 0xFF);
+        }
+        adaptation_len += stuffing;
+    }
+
+    let mut packet = [0u8; TS_PACKET_SIZE];
+    let control = match (adaptation_len > 0, used > 0) {
+        (true, true) => 0x30,
+        (true, false) => 0x20,
+        _ => 0x10,
+    };
+    packet[0] = TS_SYNC_BYTE;
+    packet[1] = if unit_start { 0x40 } else { 0 } | (pid >> 8) as u8 & 0x1F;
+    packet[2] = pid as u8;
+    packet[3] = control | (continuity & 0x0F);
+    let mut pos = 4;
+    if adaptation_len > 0 {
+        packet[pos] = (adaptation_len - 1) as u8;
+        packet[pos + 1..pos + adaptation_len].copy_from_slice(&adaptation);
+        pos += adaptation_len;
+    }
+    packet[pos..pos + used].copy_from_slice(&payload[..used]);
+    (packet, used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ts_demux::{StreamCodec, TsDemuxer};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    const SPS: [u8; 6] = [0x67, 0x64, 0x00, 0x1F, 0xAC, 0xD9];
+    const PPS: [u8; 4] = [0x68, 0xEB, 0xE3, 0xCB];
+
+    fn avcc() -> Vec<u8> {
+        let mut config = vec![1, 0x64, 0x00, 0x1F, 0xFF, 0xE1];
+        config.extend_from_slice(&(SPS.len() as u16).to_be_bytes());
+        config.extend_from_slice(&SPS);
+        config.push(1);
+        config.extend_from_slice(&(PPS.len() as u16).to_be_bytes());
+        config.extend_from_slice(&PPS);
+        config
+    }
+
+    /// 2 s of 25 fps H.264 (IPBB..., keyframe every 10) and 50 fps AAC
+    fn packets() -> Vec<MuxPacket> {
+        let mut packets = Vec::new();
+        for i in 0..50i64 {
+            let keyframe = i % 10 == 0;
+            // Decode order I P B, presentation order I B P
+            let pts = match i % 10 {
+                0 => i,
+                n if n % 2 == 1 => i + 1,
+                _ => i - 1,
+            } * 40_000
+                + 40_000;
+            let size = if keyframe { 3000 } else { 400 + i as usize * 7 };
+            let nal_type = if keyframe { 0x65 } else { 0x41 };
+            let mut data = (size as u32 + 1).to_be_bytes().to_vec();
+            data.push(nal_type);
+            data.extend((0..size).map(|b| (b as u8) | 0x10));
+            packets.push(MuxPacket {
+                stream: 0,
+                pts_us: pts,
+                dts_us: Some(i * 40_000),
+                duration_us: Some(40_000),
+                keyframe,
+                data,
+            });
+            for a in 0..2 {
+                let t = i * 40_000 + a * 20_000;
+                packets.push(MuxPacket {
+                    stream: 1,
+                    pts_us: t,
+                    dts_us: Some(t),
+                    duration_us: Some(20_000),
+                    keyframe: true,
+                    data: vec![0x21; 100 + (i * 2 + a) as usize],
+                });
+            }
+        }
+        packets
+    }
+
+    fn mux(config: TsMuxConfig) -> Vec<u8> {
+        let streams = vec![
+            MuxStream::video(MuxCodec::H264, 1280, 720).with_codec_private(avcc()),
+            MuxStream::audio(MuxCodec::AAC, 48000, 2)
+                .with_codec_private(vec![0x11, 0x90])
+                .with_language("eng"),
+        ];
+        let mut muxer = TsMuxer::new(Vec::new(), streams, config).unwrap();
+        for packet in packets() {
+            muxer.write_packet(&packet).unwrap();
+        }
+        muxer.finish().unwrap()
+    }
+
+    /// (pid, continuity counter, has payload, PCR) per TS packet
+    fn scan(data: &[u8]) -> Vec<(u16, u8, bool, Option<u64>)> {
+        data.chunks(TS_PACKET_SIZE)
+            .map(|p| {
+                assert_eq!(p.len(), TS_PACKET_SIZE);
+                assert_eq!(p[0], TS_SYNC_BYTE);
+                let pid = ((p[1] as u16 & 0x1F) << 8) | p[2] as u16;
+                let pcr = (p[3] & 0x20 != 0 && p[4] >= 7 && p[5] & 0x10 != 0).then(|| {
+                    let base = ((p[6] as u64) << 25)
+                        | ((p[7] as u64) << 17)
+                        | ((p[8] as u64) << 9)
+                        | ((p[9] as u64) << 1)
+                        | ((p[10] as u64) >> 7);
+                    base * 300 + (((p[10] as u64) & 1) << 8 | p[11] as u64)
+                });
+                (pid, p[3] & 0x0F, p[3] & 0x10 != 0, pcr)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_through_demuxer() {
+        let data = mux(TsMuxConfig::default());
+        let mut demuxer = TsDemuxer::new(Cursor::new(data)).unwrap();
+
+        let info = demuxer.info().clone();
+        assert_eq!(info.streams.len(), 2);
+        assert_eq!(info.streams[0].pid, 0x100);
+        assert_eq!(info.streams[0].codec, StreamCodec::H264);
+        assert_eq!(info.streams[1].codec, StreamCodec::AAC);
+        assert_eq!(info.streams[1].language.as_deref(), Some("eng"));
+
+        let mut by_pid: HashMap<u16, Vec<_>> = HashMap::new();
+        while let Some(packet) = demuxer.read_packet() {
+            by_pid.entry(packet.pid).or_default().push(packet);
+        }
+        let delay = 700_000;
+        let source = packets();
+        let video: Vec<_> = source.iter().filter(|p| p.stream == 0).collect();
+        let audio: Vec<_> = source.iter().filter(|p| p.stream == 1).collect();
+        let out_video = &by_pid[&0x100];
+        let out_audio = &by_pid[&0x101];
+        assert_eq!(out_video.len(), video.len());
+        assert_eq!(out_audio.len(), audio.len());
+
+        let parameter_sets = [&[0, 0, 0, 1][..], &SPS, &[0, 0, 0, 1], &PPS].concat();
+        for (src, out) in video.iter().zip(out_video) {
+            assert_eq!(out.pts, Some(src.pts_us + delay));
+            assert_eq!(out.dts, Some(src.dts_us.unwrap() + delay));
+            assert_eq!(out.keyframe, src.keyframe);
+            let annexb = [&[0, 0, 0, 1][..], &src.data[4..]].concat();
+            let expected = if src.keyframe {
+                [parameter_sets.as_slice(), &annexb].concat()
+            } else {
+                annexb
+            };
+            assert_eq!(out.data, expected);
+        }
+        for (src, out) in audio.iter().zip(out_audio) {
+            assert_eq!(out.pts, Some(src.pts_us + delay));
+            // ADTS: AAC LC, 48 kHz, stereo, length including the header
+            assert_eq!(&out.data[..3], &[0xFF, 0xF1, 0x4C]);
+            let len = ((out.data[3] as usize & 3) << 11)
+                | ((out.data[4] as usize) << 3)
+                | (out.data[5] as usize >> 5);
+            assert_eq!(len, out.data.len());
+            assert_eq!(&out.data[7..], &src.data[..]);
+        }
+    }
+
+    #[test]
+    fn test_packet_structure() {
+        let data = mux(TsMuxConfig::default());
+        let packets = scan(&data);
+        assert_eq!(packets[0].0, PAT_PID);
+        assert_eq!(packets[1].0, 0x1000);
+        assert!(packets.iter().all(|p| p.0 != NULL_PID));
+
+        // Continuity counters step by one on every payload-carrying packet
+        let mut last: HashMap<u16, u8> = HashMap::new();
+        for &(pid, cc, payload, _) in &packets {
+            if let Some(&prev) = last.get(&pid) {
+                let expected = if payload { (prev + 1) & 0x0F } else { prev };
+                assert_eq!(cc, expected, "pid {:#x}", pid);
+            }
+            last.insert(pid, cc);
+        }
+
+        // PCR only on the video PID, rising, never more than 40 ms apart
+        let pcrs: Vec<u64> = packets
+            .iter()
+            .filter_map(|p| p.3.inspect(|_| assert_eq!(p.0, 0x100)))
+            .collect();
+        assert!(pcrs.len() >= 50);
+        for pair in pcrs.windows(2) {
+            assert!(pair[1] > pair[0]);
+            assert!(pair[1] - pair[0] <= 40 * 27_000);
+        }
+
+        // PSI at least every 100 ms of PCR time
+        let mut last_psi = 0;
+        let mut last_pcr = 0;
+        for &(pid, _, _, pcr) in &packets {
+            if let Some(pcr) = pcr {
+                last_pcr = pcr;
+                assert!(last_pcr - last_psi <= 140 * 27_000);
+            }
+            if pid == PAT_PID {
+                last_psi = last_pcr;
+            }
+        }
+        // A section followed by its own CRC checks out to zero
+        assert_eq!(crc32_mpeg2(&data[5..21]), 0);
+    }
+
+    #[test]
+    fn test_constant_bitrate() {
+        let rate = 2_000_000;
+        let data = mux(TsMuxConfig {
+            mux_rate_bps: Some(rate),
+            ..Default::default()
+        });
+        let packets = scan(&data);
+        assert!(packets.iter().any(|p| p.0 == NULL_PID));
+
+        // PCR tracks the byte position at the configured rate
+        let pcrs: Vec<(usize, u64)> = packets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.3.map(|pcr| (i, pcr)))
+            .collect();
+        let (first_index, first_pcr) = pcrs[0];
+        for &(index, pcr) in &pcrs[1..] {
+            let bits = ((index - first_index) * TS_PACKET_SIZE * 8) as u64;
+            let expected = first_pcr + bits * 27_000_000 / rate;
+            assert!(pcr.abs_diff(expected) <= 2 * 27, "{} vs {}", pcr, expected);
+        }
+        // Roughly 2 s of content at 2 Mbit/s
+        let seconds = data.len() as f64 * 8.0 / rate as f64;
+        assert!((1.9..2.2).contains(&seconds), "{} s", seconds);
+
+        let mut demuxer = TsDemuxer::new(Cursor::new(data)).unwrap();
+        let mut count = 0;
+        while demuxer.read_packet().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 150);
+    }
+}
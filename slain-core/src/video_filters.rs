@@ -39,6 +39,8 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
 
+use crate::lut::{self, LutInterpolation, LutKind};
+
 // ============================================================================
 // Filter Parameters
 // ============================================================================
@@ -280,8 +282,13 @@ pub enum Filter {
     Vignette(VignetteParams),
     /// Film grain
     Grain(GrainParams),
-    /// 3D LUT (lookup table path)
-    Lut3D { path: String, strength: f32 },
+    /// 3D LUT (lookup table path, `.cube` or `.3dl`)
+    Lut3D {
+        path: String,
+        strength: f32,
+        #[serde(default)]
+        interpolation: LutInterpolation,
+    },
     /// Scale (resize)
     Scale {
         width: u32,
@@ -311,6 +318,17 @@ pub enum ScaleAlgorithm {
     Lanczos,
 }
 
+impl From<ScaleAlgorithm> for crate::pixel_convert::ScaleFilter {
+    fn from(algorithm: ScaleAlgorithm) -> Self {
+        match algorithm {
+            ScaleAlgorithm::Nearest => Self::Nearest,
+            ScaleAlgorithm::Bilinear => Self::Bilinear,
+            ScaleAlgorithm::Bicubic => Self::MITCHELL,
+            ScaleAlgorithm::Lanczos => Self::LANCZOS3,
+        }
+    }
+}
+
 impl Filter {
     /// Get filter name
     pub fn name(&self) -> &'static str {
@@ -347,6 +365,7 @@ impl Filter {
             Self::Blur(p) => p.radius < 0.001,
             Self::Vignette(p) => p.intensity < 0.001,
             Self::Grain(p) => p.intensity < 0.001,
+            Self::Lut3D { strength, .. } => *strength < 0.001,
             Self::Flip {
                 horizontal,
                 vertical,
@@ -583,6 +602,8 @@ pub struct FilterProcessor {
     output_texture: Option<wgpu::Texture>,
     /// Ping-pong buffers for chaining
     ping_pong: [Option<wgpu::Texture>; 2],
+    /// Uploaded LUTs keyed by file path
+    luts: HashMap<String, LoadedLut>,
     /// Current dimensions
     width: u32,
     height: u32,
@@ -593,6 +614,17 @@ struct CompiledPipeline {
     bind_group_layout: wgpu::BindGroupLayout,
 }
 
+/// 3D LUT resident on the GPU
+struct LoadedLut {
+    texture: wgpu::Texture,
+    size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+/// Lattice size used when baking 1D LUTs for the GPU
+const GPU_LUT_BAKE_SIZE: usize = 33;
+
 impl FilterProcessor {
     /// Create a new filter processor
     pub async fn new() -> Result<Self, String> {
@@ -633,6 +665,7 @@ impl FilterProcessor {
             input_texture: None,
             output_texture: None,
             ping_pong: [None, None],
+            luts: HashMap::new(),
             width: 0,
             height: 0,
         };
@@ -640,6 +673,7 @@ impl FilterProcessor {
         // Compile core pipelines
         processor.compile_color_pipeline();
         processor.compile_sharpen_pipeline();
+        processor.compile_lut_pipeline();
 
         Ok(processor)
     }
@@ -797,6 +831,152 @@ impl FilterProcessor {
         );
     }
 
+    /// Compile 3D LUT pipeline
+    fn compile_lut_pipeline(&mut self) {
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("lut3d_filter"),
+                source: wgpu::ShaderSource::Wgsl(SHADER_LUT3D.into()),
+            });
+
+        let bind_group_layout =
+            self.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("lut3d_bind_group_layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::StorageTexture {
+                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                format: wgpu::TextureFormat::Rgba8Unorm,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        // LUT lattice (Rgba32Float isn't filterable, interpolation is done in the shader)
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D3,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        let pipeline_layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("lut3d_pipeline_layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("lut3d_pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        self.pipelines.insert(
+            "LUT3D",
+            CompiledPipeline {
+                pipeline,
+                bind_group_layout,
+            },
+        );
+    }
+
+    /// Load a LUT from disk and upload it as a 3D texture (cached by path)
+    pub fn load_lut(&mut self, path: &str) -> Result<(), String> {
+        if self.luts.contains_key(path) {
+            return Ok(());
+        }
+
+        let mut lut = lut::load_lut(path)?;
+        if lut.kind == LutKind::OneD {
+            lut = lut.to_3d(GPU_LUT_BAKE_SIZE, LutInterpolation::Trilinear);
+        }
+        let size = lut.size as u32;
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lut3d_texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let texels = lut.to_rgba_f32();
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * size),
+                rows_per_image: Some(size),
+            },
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+        );
+
+        self.luts.insert(
+            path.to_string(),
+            LoadedLut {
+                texture,
+                size,
+                domain_min: lut.domain_min,
+                domain_max: lut.domain_max,
+            },
+        );
+        Ok(())
+    }
+
     /// Resize processing buffers
     pub fn resize(&mut self, width: u32, height: u32) {
         if self.width == width && self.height == height {
@@ -844,6 +1024,15 @@ impl FilterProcessor {
             return;
         }
 
+        // Make sure every LUT in the chain is resident before encoding
+        for filter in chain.filters() {
+            if let Filter::Lut3D { path, .. } = filter {
+                if let Err(e) = self.load_lut(path) {
+                    tracing::warn!("Skipping LUT {}: {}", path, e);
+                }
+            }
+        }
+
         // Upload input to GPU
         let input_tex = self.input_texture.as_ref().unwrap();
         self.queue.write_texture(
@@ -891,6 +1080,15 @@ impl FilterProcessor {
 
             let pipeline_name = filter.name();
 
+            // LUT filters need their lattice texture; skip the ones that failed to load
+            let lut_texture = match filter {
+                Filter::Lut3D { path, .. } => match self.luts.get(path) {
+                    Some(loaded) => Some(loaded),
+                    None => continue,
+                },
+                _ => None,
+            };
+
             // Get the compiled pipeline for this filter type
             if let Some(compiled) = self.pipelines.get(pipeline_name) {
                 // Get the output texture (ping-pong buffer)
@@ -933,6 +1131,35 @@ impl FilterProcessor {
                                 usage: wgpu::BufferUsages::UNIFORM,
                             })
                     }
+                    Filter::Lut3D {
+                        strength,
+                        interpolation,
+                        ..
+                    } => {
+                        let (size, lo, hi) = lut_texture
+                            .map(|l| (l.size as f32, l.domain_min, l.domain_max))
+                            .unwrap_or((2.0, [0.0; 3], [1.0; 3]));
+                        let data = [
+                            strength.clamp(0.0, 1.0),
+                            size,
+                            *interpolation as u32 as f32,
+                            0.0f32, // padding
+                            lo[0],
+                            lo[1],
+                            lo[2],
+                            0.0,
+                            hi[0],
+                            hi[1],
+                            hi[2],
+                            0.0,
+                        ];
+                        self.device
+                            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                label: Some("lut3d_params"),
+                                contents: bytemuck::cast_slice(&data),
+                                usage: wgpu::BufferUsages::UNIFORM,
+                            })
+                    }
                     _ => {
                         // Default empty params for other filters
                         let data = [0.0f32; 4];
@@ -949,24 +1176,36 @@ impl FilterProcessor {
                 let input_view = current_input.create_view(&wgpu::TextureViewDescriptor::default());
                 let output_view = output_tex.create_view(&wgpu::TextureViewDescriptor::default());
 
+                let lut_view = lut_texture.map(|l| {
+                    l.texture
+                        .create_view(&wgpu::TextureViewDescriptor::default())
+                });
+
                 // Create bind group
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&input_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&output_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: param_buffer.as_entire_binding(),
+                    },
+                ];
+                if let Some(view) = lut_view.as_ref() {
+                    entries.push(wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    });
+                }
                 let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
                     label: Some("filter_bind_group"),
                     layout: &compiled.bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&input_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::TextureView(&output_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: param_buffer.as_entire_binding(),
-                        },
-                    ],
+                    entries: &entries,
                 });
 
                 // Create compute pass and dispatch
@@ -1221,6 +1460,98 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
 }
 "#;
 
+// Trilinear / tetrahedral lookup into an Rgba32Float lattice
+const SHADER_LUT3D: &str = r#"
+struct LutParams {
+    strength: f32,
+    size: f32,
+    interpolation: f32, // 0 = trilinear, 1 = tetrahedral
+    _padding: f32,
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+}
+
+@group(0) @binding(0) var input_tex: texture_2d<f32>;
+@group(0) @binding(1) var output_tex: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2) var<uniform> params: LutParams;
+@group(0) @binding(3) var lut_tex: texture_3d<f32>;
+
+fn lattice(p: vec3<i32>) -> vec3<f32> {
+    return textureLoad(lut_tex, p, 0).rgb;
+}
+
+fn sample_trilinear(base: vec3<i32>, next: vec3<i32>, f: vec3<f32>) -> vec3<f32> {
+    let c00 = mix(lattice(base), lattice(vec3<i32>(next.x, base.y, base.z)), f.x);
+    let c10 = mix(lattice(vec3<i32>(base.x, next.y, base.z)), lattice(vec3<i32>(next.x, next.y, base.z)), f.x);
+    let c01 = mix(lattice(vec3<i32>(base.x, base.y, next.z)), lattice(vec3<i32>(next.x, base.y, next.z)), f.x);
+    let c11 = mix(lattice(vec3<i32>(base.x, next.y, next.z)), lattice(next), f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+fn sample_tetrahedral(base: vec3<i32>, next: vec3<i32>, f: vec3<f32>) -> vec3<f32> {
+    let c000 = lattice(base);
+    let c111 = lattice(next);
+    var v1: vec3<f32>;
+    var v2: vec3<f32>;
+    var w: vec4<f32>;
+    if f.x > f.y {
+        if f.y > f.z {
+            v1 = lattice(vec3<i32>(next.x, base.y, base.z));
+            v2 = lattice(vec3<i32>(next.x, next.y, base.z));
+            w = vec4<f32>(1.0 - f.x, f.x - f.y, f.y - f.z, f.z);
+        } else if f.x > f.z {
+            v1 = lattice(vec3<i32>(next.x, base.y, base.z));
+            v2 = lattice(vec3<i32>(next.x, base.y, next.z));
+            w = vec4<f32>(1.0 - f.x, f.x - f.z, f.z - f.y, f.y);
+        } else {
+            v1 = lattice(vec3<i32>(base.x, base.y, next.z));
+            v2 = lattice(vec3<i32>(next.x, base.y, next.z));
+            w = vec4<f32>(1.0 - f.z, f.z - f.x, f.x - f.y, f.y);
+        }
+    } else if f.z > f.y {
+        v1 = lattice(vec3<i32>(base.x, base.y, next.z));
+        v2 = lattice(vec3<i32>(base.x, next.y, next.z));
+        w = vec4<f32>(1.0 - f.z, f.z - f.y, f.y - f.x, f.x);
+    } else if f.z > f.x {
+        v1 = lattice(vec3<i32>(base.x, next.y, base.z));
+        v2 = lattice(vec3<i32>(base.x, next.y, next.z));
+        w = vec4<f32>(1.0 - f.y, f.y - f.z, f.z - f.x, f.x);
+    } else {
+        v1 = lattice(vec3<i32>(base.x, next.y, base.z));
+        v2 = lattice(vec3<i32>(next.x, next.y, base.z));
+        w = vec4<f32>(1.0 - f.y, f.y - f.x, f.x - f.z, f.z);
+    }
+    return w.x * c000 + w.y * v1 + w.z * v2 + w.w * c111;
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let dims = textureDimensions(input_tex);
+    if gid.x >= dims.x || gid.y >= dims.y {
+        return;
+    }
+
+    let color = textureLoad(input_tex, vec2<i32>(gid.xy), 0);
+    let max_idx = params.size - 1.0;
+    let normalized = (color.rgb - params.domain_min.xyz) / (params.domain_max.xyz - params.domain_min.xyz);
+    let idx = clamp(normalized, vec3<f32>(0.0), vec3<f32>(1.0)) * max_idx;
+    let base_f = floor(idx);
+    let f = idx - base_f;
+    let base = vec3<i32>(base_f);
+    let next = min(base + vec3<i32>(1), vec3<i32>(i32(max_idx)));
+
+    var graded: vec3<f32>;
+    if params.interpolation > 0.5 {
+        graded = sample_tetrahedral(base, next, f);
+    } else {
+        graded = sample_trilinear(base, next, f);
+    }
+
+    let result = mix(color.rgb, graded, params.strength);
+    textureStore(output_tex, vec2<i32>(gid.xy), vec4<f32>(clamp(result, vec3<f32>(0.0), vec3<f32>(1.0)), color.a));
+}
+"#;
+
 // ============================================================================
 // Preset Manager
 // ============================================================================
@@ -1,18 +1,60 @@
 //! Unified video processing pipeline glue.
 
-use crate::deinterlace::{DeinterlaceConfig, Deinterlacer};
+use crate::deinterlace::{
+    DeinterlaceConfig, DeinterlaceMode, Deinterlacer, FieldOrder, InterlaceDetector,
+    YuvDeinterlacer, YuvPicture,
+};
 use crate::filter_pipeline::{FilterChain, FilterChainSpec, Frame, PixelFormat};
+use crate::frame_queue::{self, FrameQueue};
+use crate::ivtc::{Cadence, Ivtc, IvtcConfig};
 
 pub struct VideoPipeline {
     chain: FilterChain,
     deinterlacer: Option<Deinterlacer>,
+    yuv_deinterlacer: Option<YuvDeinterlacer>,
+    yuv_format: Option<PixelFormat>,
+    /// Spots telecined film in YUV input (auto deinterlacing only)
+    detector: InterlaceDetector,
+    ivtc: Option<Ivtc>,
+    /// Pulldown was detected: pictures go through IVTC instead of the
+    /// deinterlacer until it stops finding film
+    film: bool,
 }
 
 impl VideoPipeline {
-    pub fn new(spec: FilterChainSpec, width: u32, height: u32, deinterlace: Option<DeinterlaceConfig>) -> Self {
+    pub fn new(
+        spec: FilterChainSpec,
+        width: u32,
+        height: u32,
+        deinterlace: Option<DeinterlaceConfig>,
+    ) -> Self {
         let chain = FilterChain::new(spec);
         let deinterlacer = deinterlace.map(|config| Deinterlacer::new(config, width, height));
-        Self { chain, deinterlacer }
+        let yuv_deinterlacer = deinterlace.map(YuvDeinterlacer::new);
+        let ivtc = deinterlace
+            .filter(|config| config.mode == DeinterlaceMode::Auto)
+            .map(|config| {
+                Ivtc::new(IvtcConfig {
+                    field_order: config.field_order,
+                    ..Default::default()
+                })
+            });
+        Self {
+            chain,
+            deinterlacer,
+            yuv_deinterlacer,
+            yuv_format: None,
+            detector: InterlaceDetector::new(),
+            ivtc,
+            film: false,
+        }
+    }
+
+    /// Field order from container/codec headers (e.g. MPEG-TS / H.264 picture structure)
+    pub fn set_stream_field_order(&mut self, order: Option<FieldOrder>) {
+        if let Some(deinterlacer) = self.yuv_deinterlacer.as_mut() {
+            deinterlacer.set_stream_field_order(order);
+        }
     }
 
     pub fn process_frame(&mut self, mut frame: Frame) -> Frame {
@@ -28,6 +70,160 @@ impl VideoPipeline {
         self.chain.process_frame(frame)
     }
 
+    /// Process a frame, deinterlacing YUV input (NV12, P010, I420) with
+    /// Yadif/Bwdif. YUV output lags one frame behind and yields two frames
+    /// per input in double-rate mode, with timestamps split between fields.
+    ///
+    /// With auto deinterlacing, input the detector recognises as 3:2 or 2:2
+    /// pulldown is field-matched by IVTC instead; its output comes a cycle at
+    /// a time, decimated and retimed to the film rate.
+    pub fn push_frame(&mut self, frame: Frame) -> Vec<Frame> {
+        self.push_timed(frame)
+            .into_iter()
+            .map(|(frame, _)| frame)
+            .collect()
+    }
+
+    /// Drain the frames held back by the YUV deinterlacer or IVTC (end of
+    /// stream / seek)
+    pub fn flush(&mut self) -> Vec<Frame> {
+        self.flush_timed()
+            .into_iter()
+            .map(|(frame, _)| frame)
+            .collect()
+    }
+
+    /// `push_frame`, handing the output to `queue` with each frame's
+    /// duration so IVTC's retimed film cadence reaches the renderer. Returns
+    /// how many frames the queue accepted.
+    pub fn push_to_queue(&mut self, frame: Frame, queue: &FrameQueue) -> usize {
+        let frames = self.push_timed(frame);
+        enqueue(frames, queue)
+    }
+
+    /// `flush` into `queue`
+    pub fn flush_to_queue(&mut self, queue: &FrameQueue) -> usize {
+        let frames = self.flush_timed();
+        enqueue(frames, queue)
+    }
+
+    /// Output frames with their durations in microseconds (0 when unknown)
+    fn push_timed(&mut self, frame: Frame) -> Vec<(Frame, i64)> {
+        let Some(px_format) = yuv_pixel_format(&frame.format) else {
+            return vec![(self.process_frame(frame), 0)];
+        };
+        if self.yuv_deinterlacer.is_none() {
+            return vec![(self.chain.process_frame(frame), 0)];
+        }
+
+        let mut px = crate::pixel_convert::VideoFrame::new(
+            frame.width as usize,
+            frame.height as usize,
+            px_format,
+        );
+        let len = px.data.len();
+        if frame.data.len() < len {
+            return vec![(self.chain.process_frame(frame), 0)];
+        }
+        px.data.copy_from_slice(&frame.data[..len]);
+        px.pts = frame.pts_us;
+
+        let pictures = match YuvPicture::from_pixel_frame(&px) {
+            Ok(picture) => {
+                self.yuv_format = Some(frame.format.clone());
+                self.route(picture)
+            }
+            Err(e) => {
+                tracing::warn!("Deinterlace skipped: {}", e);
+                return vec![(self.chain.process_frame(frame), 0)];
+            }
+        };
+        self.finish_pictures(pictures)
+    }
+
+    fn flush_timed(&mut self) -> Vec<(Frame, i64)> {
+        let pictures = match (
+            self.film,
+            self.ivtc.as_mut(),
+            self.yuv_deinterlacer.as_mut(),
+        ) {
+            (true, Some(ivtc), _) => ivtc.flush(),
+            (_, _, Some(deinterlacer)) => deinterlacer.flush(),
+            _ => Vec::new(),
+        };
+        self.finish_pictures(pictures)
+    }
+
+    /// Send a picture to IVTC or the deinterlacer, handing over (and
+    /// draining the stage left behind) when the detected cadence changes
+    fn route(&mut self, picture: YuvPicture) -> Vec<YuvPicture> {
+        let Some(deinterlacer) = self.yuv_deinterlacer.as_mut() else {
+            return vec![picture];
+        };
+        let Some(ivtc) = self.ivtc.as_mut() else {
+            return deinterlacer.push(picture);
+        };
+
+        let shift = picture.bit_depth.saturating_sub(8);
+        let luma8: Vec<u16> = picture.planes[0].iter().map(|&v| v >> shift).collect();
+        let info = self
+            .detector
+            .analyze_luma(&luma8, picture.width, picture.height);
+
+        let mut output = Vec::new();
+        if !self.film && info.pulldown.is_some() {
+            tracing::debug!("{:?} pulldown detected, switching to IVTC", info.pulldown);
+            output.extend(deinterlacer.flush());
+            deinterlacer.reset();
+            self.film = true;
+        } else if self.film
+            && info.pulldown.is_none()
+            && matches!(ivtc.cadence(), Cadence::Progressive | Cadence::Interlaced)
+        {
+            tracing::debug!("Film cadence lost ({:?}), leaving IVTC", ivtc.cadence());
+            output.extend(ivtc.flush());
+            ivtc.reset();
+            self.film = false;
+        }
+
+        if self.film {
+            output.extend(ivtc.push(picture));
+        } else {
+            output.extend(deinterlacer.push(picture));
+        }
+        output
+    }
+
+    fn finish_pictures(&mut self, pictures: Vec<YuvPicture>) -> Vec<(Frame, i64)> {
+        let Some(format) = self.yuv_format.clone() else {
+            return Vec::new();
+        };
+        let Some(px_format) = yuv_pixel_format(&format) else {
+            return Vec::new();
+        };
+
+        pictures
+            .into_iter()
+            .filter_map(|picture| match picture.to_pixel_frame(px_format) {
+                Ok(px) => Some((
+                    Frame {
+                        data: px.data,
+                        width: picture.width,
+                        height: picture.height,
+                        format: format.clone(),
+                        pts_us: picture.pts,
+                    },
+                    picture.duration,
+                )),
+                Err(e) => {
+                    tracing::warn!("Deinterlace output dropped: {}", e);
+                    None
+                }
+            })
+            .map(|(frame, duration)| (self.chain.process_frame(frame), duration))
+            .collect()
+    }
+
     pub fn chain(&self) -> &FilterChain {
         &self.chain
     }
@@ -36,3 +232,124 @@ impl VideoPipeline {
         &mut self.chain
     }
 }
+
+/// Push pipeline output into a frame queue; formats it has no layout for
+/// are dropped
+fn enqueue(frames: Vec<(Frame, i64)>, queue: &FrameQueue) -> usize {
+    frames
+        .into_iter()
+        .filter_map(|(frame, duration)| match to_queue_frame(&frame, duration) {
+            Ok(frame) => Some(frame),
+            Err(e) => {
+                tracing::warn!("Frame not queued: {}", e);
+                None
+            }
+        })
+        .map(|frame| queue.push(frame))
+        .filter(|pushed| *pushed)
+        .count()
+}
+
+/// Copy a pipeline frame into a frame-queue frame carrying its duration
+fn to_queue_frame(frame: &Frame, duration_us: i64) -> Result<frame_queue::Frame, String> {
+    let format = match &frame.format {
+        PixelFormat::Nv12 => frame_queue::PixelFormat::NV12,
+        PixelFormat::I420 => frame_queue::PixelFormat::YUV420P,
+        PixelFormat::P010 => frame_queue::PixelFormat::P010,
+        PixelFormat::Rgba => frame_queue::PixelFormat::RGBA32,
+        other => return Err(format!("{:?} has no frame queue layout", other)),
+    };
+    let mut queued = frame_queue::Frame::new(frame.width, frame.height, format);
+    queued.copy_from(&frame.data, frame.pts_us, duration_us, false);
+    Ok(queued)
+}
+
+fn yuv_pixel_format(format: &PixelFormat) -> Option<crate::pixel_convert::PixelFormat> {
+    match format {
+        PixelFormat::Nv12 => Some(crate::pixel_convert::PixelFormat::NV12),
+        PixelFormat::P010 => Some(crate::pixel_convert::PixelFormat::P010),
+        PixelFormat::I420 => Some(crate::pixel_convert::PixelFormat::YUV420P),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_queue::{FramePool, QueueConfig};
+    use std::sync::Arc;
+
+    const W: u32 = 32;
+    const H: u32 = 32;
+
+    /// Luma of progressive film frame `n`: a bright bar moving 5 px per frame
+    fn film_luma(n: usize, x: usize) -> u8 {
+        let width = W as usize;
+        if (x + width - (n * 5) % width) % width < 8 {
+            220
+        } else {
+            30
+        }
+    }
+
+    /// NV12 frame woven from film frames `top` (even lines) and `bottom`
+    fn woven(top: usize, bottom: usize, pts_us: i64) -> Frame {
+        let mut data = Vec::new();
+        for y in 0..H as usize {
+            let n = if y % 2 == 0 { top } else { bottom };
+            data.extend((0..W as usize).map(|x| film_luma(n, x)));
+        }
+        data.resize((W * H * 3 / 2) as usize, 128);
+        Frame {
+            data,
+            width: W,
+            height: H,
+            format: PixelFormat::Nv12,
+            pts_us,
+        }
+    }
+
+    #[test]
+    fn telecined_film_is_retimed_into_the_queue() {
+        let mut pipeline =
+            VideoPipeline::new(FilterChainSpec::empty(None), W, H, Some(Default::default()));
+        let queue = FrameQueue::new(
+            QueueConfig {
+                max_frames: 128,
+                reorder: false,
+                ..Default::default()
+            },
+            Arc::new(FramePool::new(W, H, frame_queue::PixelFormat::NV12, 0)),
+        );
+
+        // 3:2 pulldown, top field first: AA BB BC CD DD
+        let pattern = [(0, 0), (1, 1), (1, 2), (2, 3), (3, 3)];
+        let mut queued = 0;
+        for i in 0..60 {
+            let (top, bottom) = pattern[i % 5];
+            let film = i / 5 * 4;
+            let frame = woven(film + top, film + bottom, i as i64 * 33_367);
+            queued += pipeline.push_to_queue(frame, &queue);
+        }
+        queued += pipeline.flush_to_queue(&queue);
+        assert!(pipeline.film);
+
+        let frames: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(frames.len(), queued);
+        // Decimated once IVTC took over
+        assert!(frames.len() < 56, "{} frames", frames.len());
+
+        // Film before the flushed partial cycle: 23.976p timing, no combing
+        let tail = &frames[frames.len() - 9..frames.len() - 1];
+        for pair in tail.windows(2) {
+            assert!((pair[1].pts_us - pair[0].pts_us - 41_709).abs() <= 1);
+        }
+        for frame in tail {
+            assert!((frame.duration_us - 41_709).abs() <= 1);
+            assert_eq!(
+                frame.data[..W as usize],
+                frame.data[W as usize..2 * W as usize]
+            );
+        }
+    }
+}
@@ -399,6 +399,79 @@ impl McpServer {
                 }),
             },
             // ============================================================
+            // Download Manager Tools
+            // ============================================================
+            Tool {
+                name: "download_add".into(),
+                description: "Queue a download; hoster links are resolved through the configured debrid service first".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "url": { "type": "string", "description": "Hoster link or direct download URL" },
+                        "filename": { "type": "string", "description": "Override the saved file name" },
+                        "checksum": { "type": "string", "description": "Expected checksum, e.g. sha256:<hex> or md5:<hex>" },
+                        "resolve": { "type": "boolean", "description": "Resolve through debrid (default true when a debrid service is configured)" }
+                    },
+                    "required": ["url"]
+                }),
+            },
+            Tool {
+                name: "download_list".into(),
+                description: "List queued, active and finished downloads with progress".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                }),
+            },
+            Tool {
+                name: "download_pause".into(),
+                description: "Pause a download (resumable later, also after restart)".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string", "description": "Download ID" }
+                    },
+                    "required": ["id"]
+                }),
+            },
+            Tool {
+                name: "download_resume".into(),
+                description: "Resume a paused or failed download".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string", "description": "Download ID" }
+                    },
+                    "required": ["id"]
+                }),
+            },
+            Tool {
+                name: "download_cancel".into(),
+                description: "Remove a download from the queue and delete its partial data".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string", "description": "Download ID" }
+                    },
+                    "required": ["id"]
+                }),
+            },
+            Tool {
+                name: "download_set_limits".into(),
+                description: "Set concurrent downloads, connections and bandwidth cap; omitted values are unchanged".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "max_active": { "type": "integer", "description": "Downloads running at once" },
+                        "connections_per_download": { "type": "integer", "description": "Segments per download" },
+                        "per_host_connections": { "type": "integer", "description": "Connections to one host" },
+                        "bandwidth_limit_kb": { "type": "integer", "description": "KiB per second, 0 = unlimited" }
+                    },
+                    "required": []
+                }),
+            },
+            // ============================================================
             // Video Player Control Tools
             // ============================================================
             Tool {
@@ -444,6 +517,39 @@ impl McpServer {
                     "required": ["pipeline"]
                 }),
             },
+            Tool {
+                name: "player_screenshot".into(),
+                description: "Save the frame at a position as PNG/JPEG/WebP at native resolution".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path to video file" },
+                        "time": { "type": "number", "description": "Position (seconds)" },
+                        "output": { "type": "string", "description": "Image path (.png, .jpg or .webp)" },
+                        "stage": {
+                            "type": "string",
+                            "enum": ["pre_filter", "post_filter"],
+                            "description": "Capture before or after the filter chain (default post_filter)"
+                        },
+                        "quality": { "type": "integer", "description": "JPEG/WebP quality 1-100 (default 90)" }
+                    },
+                    "required": ["path", "time", "output"]
+                }),
+            },
+            Tool {
+                name: "player_export_clip".into(),
+                description: "Copy an A-B range to MKV/MP4/WebM without re-encoding (start snaps to the previous keyframe)".into(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path to video file" },
+                        "start": { "type": "number", "description": "Clip start (seconds)" },
+                        "end": { "type": "number", "description": "Clip end (seconds)" },
+                        "output": { "type": "string", "description": "Output path (.mkv, .mp4, .webm or .ts)" }
+                    },
+                    "required": ["path", "start", "end", "output"]
+                }),
+            },
         ];
 
         Ok(json!({ "tools": tools }))
@@ -475,10 +581,19 @@ impl McpServer {
             // Bandwidth
             "bandwidth_status" => self.tool_bandwidth_status(),
             "bandwidth_stats" => self.tool_bandwidth_stats(),
+            // Downloads
+            "download_add" => self.tool_download_add(args),
+            "download_list" => self.tool_download_list(),
+            "download_pause" => self.tool_download_pause(args),
+            "download_resume" => self.tool_download_resume(args),
+            "download_cancel" => self.tool_download_cancel(args),
+            "download_set_limits" => self.tool_download_set_limits(args),
             // Player
             "player_open" => self.tool_player_open(args),
             "player_control" => self.tool_player_control(args),
             "player_pipeline" => self.tool_player_pipeline(args),
+            "player_screenshot" => self.tool_player_screenshot(args),
+            "player_export_clip" => self.tool_player_export_clip(args),
             _ => Err(format!("Unknown tool: {}", name)),
         };
 
@@ -737,6 +852,135 @@ impl McpServer {
             .into())
     }
 
+    // ========================================================================
+    // Download Manager Tools
+    // ========================================================================
+
+    fn tool_download_add(&self, args: &Value) -> Result<String, String> {
+        use slain_core::debrid_download::{self as downloads, Checksum, DownloadRequest};
+
+        let url = args["url"].as_str().ok_or("url is required")?;
+        let resolve = args["resolve"]
+            .as_bool()
+            .unwrap_or_else(|| !slain_core::debrid::debrid_available_services().is_empty());
+
+        let mut request = if resolve {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let link = runtime.block_on(slain_core::debrid::debrid_resolve(url.to_string()))?;
+            DownloadRequest::from_resolved(&link)
+        } else {
+            DownloadRequest::new(url)
+        };
+        if let Some(filename) = args["filename"].as_str() {
+            request.filename = Some(filename.to_string());
+        }
+        if let Some(checksum) = args["checksum"].as_str() {
+            request.checksum = Some(Checksum::parse(checksum)?);
+        }
+
+        let id = downloads::download_add(request)?;
+        let progress = downloads::download_get(id.clone()).ok_or("Download was not queued")?;
+        Ok(format!(
+            "Download queued.\n\nID: {}\nFile: {}\nSource: {}",
+            id,
+            progress.path.display(),
+            progress.url
+        ))
+    }
+
+    fn tool_download_list(&self) -> Result<String, String> {
+        let downloads = slain_core::debrid_download::download_list();
+        if downloads.is_empty() {
+            return Ok("No downloads.".into());
+        }
+
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        let mut output = format!("Downloads ({}):\n", downloads.len());
+        for d in downloads {
+            output.push_str(&format!("\n[{}] {}\n", d.id, d.filename));
+            output.push_str(&format!("  State: {:?}", d.state));
+            match d.total_size {
+                Some(total) => output.push_str(&format!(
+                    " - {:.1} / {:.1} MiB ({:.1}%)",
+                    mib(d.downloaded),
+                    mib(total),
+                    d.percent.unwrap_or(0.0)
+                )),
+                None => output.push_str(&format!(" - {:.1} MiB", mib(d.downloaded))),
+            }
+            if d.speed_bps > 0 {
+                output.push_str(&format!(", {:.2} MiB/s", mib(d.speed_bps)));
+            }
+            output.push('\n');
+            if let Some(verified) = d.checksum_verified {
+                output.push_str(&format!(
+                    "  Checksum: {}\n",
+                    if verified { "verified" } else { "MISMATCH" }
+                ));
+            }
+            if let Some(error) = &d.error {
+                output.push_str(&format!("  Error: {}\n", error));
+            }
+        }
+        Ok(output)
+    }
+
+    fn tool_download_pause(&self, args: &Value) -> Result<String, String> {
+        let id = args["id"].as_str().ok_or("id is required")?;
+        slain_core::debrid_download::download_pause(id.to_string())?;
+        Ok(format!("Download {} paused.", id))
+    }
+
+    fn tool_download_resume(&self, args: &Value) -> Result<String, String> {
+        let id = args["id"].as_str().ok_or("id is required")?;
+        slain_core::debrid_download::download_resume(id.to_string())?;
+        Ok(format!("Download {} resumed.", id))
+    }
+
+    fn tool_download_cancel(&self, args: &Value) -> Result<String, String> {
+        let id = args["id"].as_str().ok_or("id is required")?;
+        slain_core::debrid_download::download_cancel(id.to_string())?;
+        Ok(format!("Download {} cancelled.", id))
+    }
+
+    fn tool_download_set_limits(&self, args: &Value) -> Result<String, String> {
+        use slain_core::debrid_download::{download_get_limits, download_set_limits};
+
+        let mut limits = download_get_limits();
+        if let Some(n) = args["max_active"].as_u64() {
+            limits.max_active = n as usize;
+        }
+        if let Some(n) = args["connections_per_download"].as_u64() {
+            limits.connections_per_download = n as usize;
+        }
+        if let Some(n) = args["per_host_connections"].as_u64() {
+            limits.per_host_connections = n as usize;
+        }
+        if let Some(kb) = args["bandwidth_limit_kb"].as_u64() {
+            limits.bandwidth_limit = kb * 1024;
+        }
+        download_set_limits(limits.clone())?;
+
+        let bandwidth = match limits.bandwidth_limit {
+            0 => "unlimited".to_string(),
+            bytes => format!("{} KiB/s", bytes / 1024),
+        };
+        Ok(format!(
+            "Download limits updated.\n\n\
+            Max active: {}\n\
+            Connections per download: {}\n\
+            Connections per host: {}\n\
+            Bandwidth: {}",
+            limits.max_active,
+            limits.connections_per_download,
+            limits.per_host_connections,
+            bandwidth
+        ))
+    }
+
     // ========================================================================
     // Player Control Tools
     // ========================================================================
@@ -795,6 +1039,64 @@ impl McpServer {
 
         Ok(output)
     }
+
+    fn tool_player_screenshot(&self, args: &Value) -> Result<String, String> {
+        use slain_core::capture::{screenshot_file, ScreenshotOptions, ScreenshotStage};
+
+        let path = args["path"].as_str().ok_or("path is required")?;
+        let time = args["time"].as_f64().ok_or("time is required")?;
+        let output = args["output"].as_str().ok_or("output is required")?;
+        let stage = match args["stage"].as_str().unwrap_or("post_filter") {
+            "pre_filter" => ScreenshotStage::PreFilter,
+            "post_filter" => ScreenshotStage::PostFilter,
+            other => return Err(format!("Unknown stage: {}", other)),
+        };
+        let quality = args["quality"].as_u64().unwrap_or(90).clamp(1, 100) as u8;
+
+        let time_ms = (time.max(0.0) * 1000.0) as u64;
+        let options = ScreenshotOptions { stage, quality };
+        screenshot_file(
+            std::path::Path::new(path),
+            time_ms,
+            std::path::Path::new(output),
+            &options,
+        )?;
+        Ok(format!(
+            "Screenshot of {} at {:.3}s saved to {} ({:?})",
+            path,
+            time_ms as f64 / 1000.0,
+            output,
+            stage
+        ))
+    }
+
+    fn tool_player_export_clip(&self, args: &Value) -> Result<String, String> {
+        let path = args["path"].as_str().ok_or("path is required")?;
+        let start = args["start"].as_f64().ok_or("start is required")?;
+        let end = args["end"].as_f64().ok_or("end is required")?;
+        let output = args["output"].as_str().ok_or("output is required")?;
+
+        let report = slain_core::capture::export_clip(
+            std::path::Path::new(path),
+            std::path::Path::new(output),
+            (start.max(0.0) * 1000.0) as u64,
+            (end.max(0.0) * 1000.0) as u64,
+        )?;
+        Ok(format!(
+            "Clip exported to {}\n\n\
+            Requested start: {:.3}s\n\
+            Keyframe start: {:.3}s\n\
+            End: {:.3}s\n\
+            Streams: {}\n\
+            Packets: {}",
+            output,
+            report.requested_start_ms as f64 / 1000.0,
+            report.start_ms as f64 / 1000.0,
+            report.end_ms as f64 / 1000.0,
+            report.streams,
+            report.packets
+        ))
+    }
 }
 
 // ============================================================================
@@ -8,29 +8,47 @@ use parking_lot::Mutex;
 use serde::Deserialize;
 use std::collections::VecDeque;
 use std::io::{ErrorKind, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStdout, Command, Stdio};
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
 // Import from our core library - NOT rewriting
+use slain_core::adaptive_stream::{
+    is_manifest_url, AdaptiveClient, AdaptiveConfig, MediaKind, SegmentData, SegmentFormat,
+};
 use slain_core::audio::{audio_set_volume, AudioPlayer};
 use slain_core::avi_demux::AviDemuxer;
 use slain_core::bandwidth::window_monitor;
+use slain_core::capture::{self, ScreenshotOptions, ScreenshotStage};
+use slain_core::deinterlace::{DeinterlaceConfig, FieldOrder};
+use slain_core::dlna_renderer::{
+    AvTransportState, DlnaRenderer, DlnaRendererConfig, MediaFetch, RendererCommand,
+    RendererPlayback,
+};
 use slain_core::filter_pipeline::{
-    ContainerFormat, FilterChainSpec, FilterRegistry, PipelineProfile, PipelineProfileSelector,
-    ProfileScope,
+    ContainerFormat, FilterChainSpec, FilterRegistry, Frame as PipelineFrame, PipelineProfile,
+    PipelineProfileSelector, PixelFormat as PipelinePixelFormat, ProfileScope,
 };
-use slain_core::h264_utils::{avcc_to_annexb, is_annexb, parse_avcc_extradata};
+use slain_core::frame_interpolation::{FrameRateConverter, MemcConfig, RgbFrame as MemcFrame};
+use slain_core::frame_queue::{
+    FramePool, FrameQueue, PixelFormat as QueuePixelFormat, QueueConfig,
+};
+use slain_core::h264_utils::{avcc_to_annexb, is_annexb, parse_avcc_extradata, H264FieldParser};
+use slain_core::hdr::{HdrSourceInfo, ToneMapConfig, ToneMapper};
 use slain_core::hw_decode::{
-    available_decoders, find_best_decoder, DecoderConfig, HwCodec, HwDecoder, HwDecoderType,
+    available_decoders, find_best_decoder, DecodedFrame, DecoderConfig, HwCodec, HwDecoder,
+    HwDecoderType, PixelFormat as HwPixelFormat,
 };
 use slain_core::mkv::{MkvDemuxer, MkvInfo, MkvParser, MkvTrack};
 use slain_core::mp4_demux::mp4::Mp4Demuxer;
 use slain_core::pipeline::{PipelineKind, PipelineManager};
 use slain_core::pixel_convert::{ColorSpace, PixelConverter, PixelFormat as PxFormat, VideoFrame as PxVideoFrame};
+use slain_core::trickplay::{TrickplayConfig, TrickplayGenerator, TrickplayPreview};
+use slain_core::video_pipeline::VideoPipeline;
 use slain_core::ts_demux::{TsDemuxer, StreamCodec as TsStreamCodec};
 
 // ============================================================================
@@ -64,6 +82,9 @@ struct PlaybackShared {
     seek_requested: AtomicBool,
     seek_target_ms: AtomicU64,
     frame_queue: Mutex<VecDeque<RgbFrame>>,
+    /// Motion interpolation output rate (0 = off)
+    memc_fps: AtomicU32,
+    memc: Mutex<Option<FrameRateConverter>>,
 }
 
 impl PlaybackShared {
@@ -75,8 +96,78 @@ impl PlaybackShared {
             seek_requested: AtomicBool::new(false),
             seek_target_ms: AtomicU64::new(0),
             frame_queue: Mutex::new(VecDeque::with_capacity(8)),
+            memc_fps: AtomicU32::new(0),
+            memc: Mutex::new(None),
         })
     }
+
+    /// Queue a decoded frame, resampling it through MEMC when enabled
+    fn push_frame(&self, frame: RgbFrame) {
+        let target_fps = self.memc_fps.load(Ordering::Relaxed);
+        let mut memc = self.memc.lock();
+        if target_fps == 0 {
+            *memc = None;
+            self.frame_queue.lock().push_back(frame);
+            return;
+        }
+
+        let converter = match memc.take() {
+            Some(converter) if converter.target_fps() == target_fps as f64 => converter,
+            _ => match FrameRateConverter::new(target_fps as f64, MemcConfig::default()) {
+                Ok(converter) => converter.with_gpu(true),
+                Err(e) => {
+                    tracing::warn!("MEMC disabled: {}", e);
+                    self.frame_queue.lock().push_back(frame);
+                    return;
+                }
+            },
+        };
+        let converter = memc.insert(converter);
+
+        // Checked here rather than by MemcFrame::new, which would consume the
+        // pixels: a frame MEMC can't take is still shown as decoded
+        if frame.data.len() != frame.width as usize * frame.height as usize * 3 {
+            tracing::warn!(
+                "MEMC input error: {} bytes for a {}x{} frame",
+                frame.data.len(),
+                frame.width,
+                frame.height
+            );
+            self.frame_queue.lock().push_back(frame);
+            return;
+        }
+        let pts_us = frame.pts_ms as i64 * 1000;
+        let source = MemcFrame {
+            width: frame.width,
+            height: frame.height,
+            data: frame.data,
+        };
+        match converter.push(source, pts_us) {
+            Ok(frames) => {
+                let mut queue = self.frame_queue.lock();
+                for (output, pts_us) in frames {
+                    queue.push_back(RgbFrame {
+                        data: output.data,
+                        width: output.width,
+                        height: output.height,
+                        pts_ms: (pts_us / 1000).max(0) as u64,
+                    });
+                }
+            }
+            Err(e) => {
+                tracing::warn!("MEMC error: {}", e);
+                converter.reset();
+            }
+        }
+    }
+
+    /// Drop queued frames and any frame held by MEMC (seek/stop)
+    fn clear_frames(&self) {
+        self.frame_queue.lock().clear();
+        if let Some(converter) = self.memc.lock().as_mut() {
+            converter.reset();
+        }
+    }
 }
 
 /// RGB frame ready for display
@@ -84,19 +175,24 @@ struct RgbFrame {
     data: Vec<u8>, // RGB24
     width: u32,
     height: u32,
-    #[allow(dead_code)] // Used for frame pacing in future
     pts_ms: u64,
 }
 
 #[derive(Clone, Copy)]
 struct AppOptions {
     use_ffmpeg: bool,
+    /// Act as a DLNA renderer so phones can cast to the player
+    dlna_renderer: bool,
 }
 
 impl AppOptions {
     fn from_args(args: &[String]) -> Self {
         let use_ffmpeg = args.iter().any(|arg| arg == "--ffmpeg");
-        Self { use_ffmpeg }
+        let dlna_renderer = args.iter().any(|arg| arg == "--dlna-renderer");
+        Self {
+            use_ffmpeg,
+            dlna_renderer,
+        }
     }
 }
 
@@ -141,6 +237,7 @@ fn main() -> Result<()> {
         );
     }
 
+    let app_options = AppOptions::from_args(&args);
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_title("SLAIN Player")
@@ -153,7 +250,7 @@ fn main() -> Result<()> {
     eframe::run_native(
         "SLAIN Player",
         options,
-        Box::new(|cc| Ok(Box::new(SlainApp::new(cc)))),
+        Box::new(move |cc| Ok(Box::new(SlainApp::new(cc, app_options)))),
     )
     .map_err(|e| anyhow::anyhow!("eframe error: {}", e))?;
 
@@ -212,6 +309,39 @@ struct SlainApp {
     // Frame pacing
     playback_start_time: Option<Instant>,
     last_displayed_pts: u64,
+    /// Motion interpolation output rate (0 = off)
+    memc_fps: u32,
+    /// Wall clock / pts pair that MEMC output is presented against
+    pace_origin: Option<(Instant, u64)>,
+
+    // Seekbar thumbnails
+    trickplay: Option<TrickplayGenerator>,
+    trickplay_preview: Option<TrickplayPreview>,
+    /// Hover texture and the tile start it was made for
+    trickplay_texture: Option<(u64, TextureHandle)>,
+
+    // Screenshots and clip export
+    screenshot_stage: ScreenshotStage,
+    screenshot_format: &'static str,
+    clip_start_ms: Option<u64>,
+    clip_end_ms: Option<u64>,
+    capture_job: Option<thread::JoinHandle<Result<String, String>>>,
+    capture_status: Option<String>,
+
+    // DLNA renderer (cast target)
+    renderer: Option<(DlnaRenderer, mpsc::Receiver<RendererCommand>)>,
+    /// Remote media being downloaded for the renderer
+    renderer_fetch: Option<MediaFetch>,
+    /// URI and MIME type the control point last set
+    renderer_uri: Option<(String, Option<String>)>,
+    /// Stop cancelled the fetch; Play starts it again
+    renderer_cancelled: bool,
+    /// Play arrived before the media was open
+    renderer_play_pending: bool,
+    /// Stop leaves the file open and paused at 0; report it as STOPPED
+    renderer_stopped: bool,
+    renderer_reported_volume: (f32, bool),
+    muted: bool,
 
     // UI state
     show_osd: bool,
@@ -219,6 +349,8 @@ struct SlainApp {
     #[allow(dead_code)]
     show_settings: bool,
     show_controls: bool,
+    /// URL being typed into the Open URL prompt
+    url_prompt: Option<String>,
 
     // Stats
     fps: f32,
@@ -229,7 +361,7 @@ struct SlainApp {
 }
 
 impl SlainApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, app_options: AppOptions) -> Self {
         // Configure dark theme with custom colors
         let mut visuals = egui::Visuals::dark();
         visuals.panel_fill = egui::Color32::from_rgb(25, 25, 30);
@@ -290,6 +422,18 @@ impl SlainApp {
             .map(|s| s.success())
             .unwrap_or(false);
 
+        let renderer = if app_options.dlna_renderer {
+            match DlnaRenderer::start(DlnaRendererConfig::default()) {
+                Ok(renderer) => Some(renderer),
+                Err(e) => {
+                    tracing::warn!("DLNA renderer failed to start: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Self {
             playback_state: PlaybackState::Idle,
             media_info: None,
@@ -314,10 +458,30 @@ impl SlainApp {
             last_frame_time: Instant::now(),
             playback_start_time: None,
             last_displayed_pts: 0,
+            memc_fps: 0,
+            pace_origin: None,
+            trickplay: None,
+            trickplay_preview: None,
+            trickplay_texture: None,
+            screenshot_stage: ScreenshotStage::PostFilter,
+            screenshot_format: "png",
+            clip_start_ms: None,
+            clip_end_ms: None,
+            capture_job: None,
+            capture_status: None,
+            renderer,
+            renderer_fetch: None,
+            renderer_uri: None,
+            renderer_cancelled: false,
+            renderer_play_pending: false,
+            renderer_stopped: false,
+            renderer_reported_volume: (1.0, false),
+            muted: false,
             show_osd: true,
             is_fullscreen: false,
             show_settings: false,
             show_controls: false,
+            url_prompt: None,
             fps: 0.0,
             frame_count: 0,
             dropped_frames: 0,
@@ -392,7 +556,8 @@ impl SlainApp {
             .to_lowercase();
 
         self.current_container = ContainerFormat::from_extension(&ext);
-        
+        self.start_trickplay(&path, &ext);
+
         match ext.as_str() {
             "mkv" | "webm" => self.open_mkv(&path),
             "mp4" | "m4v" | "mov" => self.open_mp4(&path),
@@ -408,6 +573,42 @@ impl SlainApp {
         self.video_path = Some(path);
     }
 
+    /// Open an HLS playlist or DASH MPD; `AdaptiveClient` fetches and picks
+    /// renditions on the decode thread. Video only: the audio player plays
+    /// local files.
+    fn open_url(&mut self, url: String) {
+        tracing::info!("Opening stream: {}", url);
+
+        self.playback_start_time = None;
+        self.last_displayed_pts = 0;
+        self.current_time_ms = 0;
+        self.duration_ms = 0;
+        self.audio_started = false;
+        self.media_info = None;
+        self.video_path = None;
+        self.current_container = None;
+        self.trickplay = None;
+        self.trickplay_preview = None;
+        self.trickplay_texture = None;
+        self.apply_pipeline_profile(None);
+
+        self.stop_decode_thread();
+        let shared = self.shared.clone();
+        let width = self.frame_width;
+        let height = self.frame_height;
+        self.decode_thread = Some(thread::spawn(move || {
+            if let Err(e) = decode_adaptive(shared, &url, width, height) {
+                tracing::error!("Stream decode failed: {}", e);
+            }
+            tracing::info!("Decode thread finished");
+        }));
+
+        self.shared.is_playing.store(true, Ordering::SeqCst);
+        self.playback_state = PlaybackState::Playing;
+        self.playback_start_time = Some(Instant::now());
+        window_monitor().set_playing(true);
+    }
+
     fn open_mkv(&mut self, path: &PathBuf) {
         self.playback_state = PlaybackState::Loading;
 
@@ -474,13 +675,140 @@ impl SlainApp {
         }
     }
 
+    /// Build (or load cached) seekbar thumbnails in the background
+    fn start_trickplay(&mut self, path: &Path, ext: &str) {
+        self.trickplay_preview = None;
+        self.trickplay_texture = None;
+        self.trickplay = matches!(ext, "mkv" | "webm" | "mp4" | "m4v" | "mov")
+            .then(|| TrickplayGenerator::spawn(path.to_path_buf(), TrickplayConfig::default()));
+    }
+
+    /// Pick up a finished trickplay pass
+    fn poll_trickplay(&mut self) {
+        let Some(generator) = self.trickplay.as_mut() else {
+            return;
+        };
+        match generator.take_result() {
+            Some(Ok(index)) => {
+                let dir = generator.dir().to_path_buf();
+                self.trickplay_preview = Some(TrickplayPreview::new(index, dir));
+                self.trickplay = None;
+            }
+            Some(Err(_)) => self.trickplay = None,
+            None => {}
+        }
+    }
+
+    /// Thumbnail texture for the seekbar position under the pointer
+    fn trickplay_texture(&mut self, ctx: &egui::Context, time_ms: u64) -> Option<TextureHandle> {
+        let (tile, pixels) = self.trickplay_preview.as_mut()?.thumbnail(time_ms)?;
+        match &self.trickplay_texture {
+            Some((start_ms, texture)) if *start_ms == tile.start_ms => Some(texture.clone()),
+            _ => {
+                let image =
+                    ColorImage::from_rgb([tile.width as usize, tile.height as usize], &pixels);
+                let texture = ctx.load_texture("trickplay", image, TextureOptions::LINEAR);
+                self.trickplay_texture = Some((tile.start_ms, texture.clone()));
+                Some(texture)
+            }
+        }
+    }
+
+    /// Save the frame at the current position next to the user's pictures
+    fn take_screenshot(&mut self) {
+        let Some(path) = self.video_path.clone() else {
+            return;
+        };
+        let time_ms = self.current_time_ms;
+        let output = capture::screenshot_path(
+            &capture::default_screenshot_dir(),
+            &path,
+            time_ms,
+            self.screenshot_format,
+        );
+        let options = ScreenshotOptions {
+            stage: self.screenshot_stage,
+            ..Default::default()
+        };
+        self.start_capture_job(move || {
+            capture::screenshot_file(&path, time_ms, &output, &options)?;
+            Ok(format!("Screenshot saved to {}", output.display()))
+        });
+    }
+
+    /// Ask where to save the A-B range and copy it in the background
+    fn export_clip(&mut self) {
+        let (Some(path), Some(start_ms), Some(end_ms)) = (
+            self.video_path.clone(),
+            self.clip_start_ms,
+            self.clip_end_ms,
+        ) else {
+            return;
+        };
+        if end_ms <= start_ms {
+            self.capture_status = Some("Clip end must be after its start".to_string());
+            return;
+        }
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("clip");
+        let Some(output) = rfd::FileDialog::new()
+            .set_file_name(format!("{}_clip.mkv", stem))
+            .add_filter("Matroska", &["mkv"])
+            .add_filter("MP4", &["mp4"])
+            .add_filter("WebM", &["webm"])
+            .add_filter("MPEG-TS", &["ts"])
+            .save_file()
+        else {
+            return;
+        };
+        self.start_capture_job(move || {
+            let report = capture::export_clip(&path, &output, start_ms, end_ms)?;
+            Ok(format!(
+                "Clip {} - {} saved to {}",
+                format_time(report.start_ms),
+                format_time(report.end_ms),
+                output.display()
+            ))
+        });
+    }
+
+    fn start_capture_job<F>(&mut self, job: F)
+    where
+        F: FnOnce() -> Result<String, String> + Send + 'static,
+    {
+        if self.capture_job.is_some() {
+            self.capture_status = Some("Still busy with the previous capture".to_string());
+            return;
+        }
+        self.capture_status = Some("Working...".to_string());
+        self.capture_job = Some(thread::spawn(job));
+    }
+
+    /// Pick up a finished screenshot/clip job
+    fn poll_capture(&mut self) {
+        if !self.capture_job.as_ref().is_some_and(|j| j.is_finished()) {
+            return;
+        }
+        let Some(job) = self.capture_job.take() else {
+            return;
+        };
+        let message = match job.join() {
+            Ok(Ok(message)) => message,
+            Ok(Err(e)) => {
+                tracing::warn!("Capture failed: {}", e);
+                format!("Capture failed: {}", e)
+            }
+            Err(_) => "Capture failed".to_string(),
+        };
+        self.capture_status = Some(message);
+    }
+
     fn stop_decode_thread(&mut self) {
         self.shared.should_stop.store(true, Ordering::SeqCst);
         if let Some(handle) = self.decode_thread.take() {
             let _ = handle.join();
         }
         self.shared.should_stop.store(false, Ordering::SeqCst);
-        self.shared.frame_queue.lock().clear();
+        self.shared.clear_frames();
     }
 
     fn open_mp4(&mut self, path: &PathBuf) {
@@ -701,7 +1029,186 @@ impl SlainApp {
 
     fn set_volume(&mut self, vol: f32) {
         self.volume = vol.clamp(0.0, 1.0);
-        let _ = audio_set_volume(self.volume);
+        let _ = audio_set_volume(if self.muted { 0.0 } else { self.volume });
+    }
+
+    fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.set_volume(self.volume);
+    }
+
+    // ========================================================================
+    // DLNA Renderer
+    // ========================================================================
+
+    /// Apply control point commands and report playback back to them
+    fn poll_renderer(&mut self) {
+        let Some((_, commands)) = &self.renderer else {
+            return;
+        };
+        let pending: Vec<RendererCommand> = commands.try_iter().collect();
+        for command in pending {
+            self.apply_renderer_command(command);
+        }
+
+        if self
+            .renderer_fetch
+            .as_ref()
+            .is_some_and(|fetch| fetch.is_finished())
+        {
+            let result = self.renderer_fetch.take().map(MediaFetch::join);
+            match result {
+                Some(Ok(path)) => self.open_for_renderer(|app| app.open_file(path)),
+                Some(Err(e)) => self.renderer_error(&e),
+                None => {}
+            }
+        }
+
+        let state = match self.playback_state {
+            _ if self.renderer_fetch.is_some() => AvTransportState::Transitioning,
+            PlaybackState::Loading => AvTransportState::Transitioning,
+            PlaybackState::Playing => AvTransportState::Playing,
+            PlaybackState::Paused if !self.renderer_stopped => AvTransportState::PausedPlayback,
+            _ => AvTransportState::Stopped,
+        };
+        let volume = (self.volume, self.muted);
+        let Some((renderer, _)) = &self.renderer else {
+            return;
+        };
+        renderer.update_playback(RendererPlayback {
+            state,
+            position_ms: self.current_time_ms,
+            duration_ms: self.duration_ms,
+        });
+        if volume != self.renderer_reported_volume {
+            renderer.update_volume(volume.0, volume.1);
+            self.renderer_reported_volume = volume;
+        }
+    }
+
+    fn apply_renderer_command(&mut self, command: RendererCommand) {
+        tracing::debug!("DLNA renderer: {:?}", command);
+        match command {
+            RendererCommand::Load { uri, mime, .. } => {
+                if self.is_playing() {
+                    self.toggle_play();
+                }
+                self.renderer_play_pending = false;
+                self.renderer_stopped = true;
+                self.renderer_cancelled = false;
+                if let Some(fetch) = self.renderer_fetch.take() {
+                    fetch.cancel();
+                }
+                self.renderer_uri = Some((uri.clone(), mime.clone()));
+                if is_manifest_url(&uri, mime.as_deref()) {
+                    self.open_for_renderer(|app| app.open_url(uri));
+                } else {
+                    self.renderer_fetch = Some(MediaFetch::start(uri, mime));
+                }
+            }
+            RendererCommand::Play => {
+                self.renderer_stopped = false;
+                if std::mem::take(&mut self.renderer_cancelled) {
+                    if let Some((uri, mime)) = self.renderer_uri.clone() {
+                        self.renderer_fetch = Some(MediaFetch::start(uri, mime));
+                    }
+                }
+                if self.renderer_fetch.is_some() {
+                    self.renderer_play_pending = true;
+                } else if !self.is_playing() {
+                    self.toggle_play();
+                }
+            }
+            RendererCommand::Pause => {
+                self.renderer_play_pending = false;
+                if self.is_playing() {
+                    self.toggle_play();
+                }
+            }
+            RendererCommand::Stop => {
+                self.renderer_play_pending = false;
+                self.renderer_stopped = true;
+                if let Some(fetch) = self.renderer_fetch.take() {
+                    fetch.cancel();
+                    self.renderer_cancelled = true;
+                }
+                if self.is_playing() {
+                    self.toggle_play();
+                }
+                if self.is_ready() {
+                    self.seek(0);
+                }
+            }
+            RendererCommand::Seek { position_ms } => {
+                if self.is_ready() {
+                    self.seek(position_ms);
+                }
+            }
+            RendererCommand::SetVolume(volume) => {
+                self.set_volume(volume);
+                self.renderer_reported_volume = (self.volume, self.muted);
+            }
+            RendererCommand::SetMute(muted) => {
+                self.set_muted(muted);
+                self.renderer_reported_volume = (self.volume, self.muted);
+            }
+        }
+    }
+
+    /// Open fetched media; the open_* paths start playback, so pause unless
+    /// the control point already asked to play
+    fn open_for_renderer(&mut self, open: impl FnOnce(&mut Self)) {
+        open(self);
+        if let PlaybackState::Error(e) = &self.playback_state {
+            let message = e.clone();
+            self.renderer_error(&message);
+            return;
+        }
+        if !self.renderer_play_pending && self.is_playing() {
+            self.toggle_play();
+        }
+        self.renderer_play_pending = false;
+    }
+
+    fn renderer_error(&mut self, message: &str) {
+        self.renderer_play_pending = false;
+        if let Some((renderer, _)) = &self.renderer {
+            renderer.report_error(message);
+        }
+    }
+
+    /// Pop the newest frame whose pts is due. MEMC output runs at a fixed rate
+    /// independent of the display, so it is presented by timestamp rather
+    /// than one frame per repaint.
+    fn next_paced_frame(&mut self) -> Option<RgbFrame> {
+        let mut queue = self.shared.frame_queue.lock();
+        let front_pts = queue.front()?.pts_ms;
+        let now = Instant::now();
+        let elapsed = |origin: Instant| now.duration_since(origin).as_millis() as u64;
+        let clock = match self.pace_origin {
+            Some((origin, origin_pts))
+                if front_pts >= origin_pts && front_pts <= origin_pts + elapsed(origin) + 1000 =>
+            {
+                origin_pts + elapsed(origin)
+            }
+            // First frame, seek or discontinuity: restart the clock here
+            _ => {
+                self.pace_origin = Some((now, front_pts));
+                front_pts
+            }
+        };
+
+        let mut due = None;
+        while queue.front().is_some_and(|frame| frame.pts_ms <= clock) {
+            due = queue.pop_front();
+        }
+        due
+    }
+
+    fn set_memc_fps(&mut self, fps: u32) {
+        self.memc_fps = fps;
+        self.pace_origin = None;
+        self.shared.memc_fps.store(fps, Ordering::SeqCst);
     }
 
     fn toggle_fullscreen(&mut self, ctx: &egui::Context) {
@@ -721,9 +1228,26 @@ impl eframe::App for SlainApp {
             ctx.request_repaint();
             self.current_time_ms = self.shared.current_time_ms.load(Ordering::Relaxed);
         }
+        self.poll_trickplay();
+        self.poll_capture();
+        if self.renderer.is_some() {
+            self.poll_renderer();
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+        if self.capture_job.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
 
         // Pull frame from queue and upload to texture
-        if let Some(frame) = self.shared.frame_queue.lock().pop_front() {
+        if !self.is_playing() {
+            self.pace_origin = None;
+        }
+        let next_frame = if self.memc_fps > 0 {
+            self.next_paced_frame()
+        } else {
+            self.shared.frame_queue.lock().pop_front()
+        };
+        if let Some(frame) = next_frame {
             let now = Instant::now();
             let delta = now.duration_since(self.last_frame_time);
             if delta.as_secs_f32() > 0.0 {
@@ -758,6 +1282,10 @@ impl eframe::App for SlainApp {
                         }
                         ui.close_menu();
                     }
+                    if ui.button("Open URL...").clicked() {
+                        self.url_prompt = Some(String::new());
+                        ui.close_menu();
+                    }
                     ui.separator();
                     if ui.button("Exit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
@@ -789,6 +1317,15 @@ impl eframe::App for SlainApp {
                         ui.label("FFmpeg not found on PATH.");
                     }
 
+                    ui.separator();
+                    ui.label("Motion interpolation:");
+                    for (fps, label) in [(0, "Off"), (60, "60 fps"), (120, "120 fps")] {
+                        if ui.radio(self.memc_fps == fps, label).clicked() {
+                            self.set_memc_fps(fps);
+                            ui.close_menu();
+                        }
+                    }
+
                     ui.separator();
                     ui.label(format!(
                         "Backend: {}",
@@ -922,6 +1459,68 @@ impl eframe::App for SlainApp {
                     }
                 });
                 
+                ui.menu_button("Capture", |ui| {
+                    let ready = self.is_ready();
+                    if ui
+                        .add_enabled(ready, egui::Button::new("Screenshot (S)"))
+                        .clicked()
+                    {
+                        self.take_screenshot();
+                        ui.close_menu();
+                    }
+                    ui.radio_value(
+                        &mut self.screenshot_stage,
+                        ScreenshotStage::PostFilter,
+                        "After filters",
+                    );
+                    ui.radio_value(
+                        &mut self.screenshot_stage,
+                        ScreenshotStage::PreFilter,
+                        "Before filters",
+                    );
+                    ui.horizontal(|ui| {
+                        for format in ["png", "jpg", "webp"] {
+                            ui.radio_value(
+                                &mut self.screenshot_format,
+                                format,
+                                format.to_uppercase(),
+                            );
+                        }
+                    });
+                    ui.separator();
+                    let start = self.clip_start_ms.map(format_time).unwrap_or_default();
+                    if ui
+                        .add_enabled(
+                            ready,
+                            egui::Button::new(format!("Set clip start (A) {}", start)),
+                        )
+                        .clicked()
+                    {
+                        self.clip_start_ms = Some(self.current_time_ms);
+                    }
+                    let end = self.clip_end_ms.map(format_time).unwrap_or_default();
+                    if ui
+                        .add_enabled(
+                            ready,
+                            egui::Button::new(format!("Set clip end (B) {}", end)),
+                        )
+                        .clicked()
+                    {
+                        self.clip_end_ms = Some(self.current_time_ms);
+                    }
+                    let can_export = ready
+                        && self.capture_job.is_none()
+                        && self.clip_start_ms.is_some()
+                        && self.clip_end_ms.is_some();
+                    if ui
+                        .add_enabled(can_export, egui::Button::new("Export clip..."))
+                        .clicked()
+                    {
+                        ui.close_menu();
+                        self.export_clip();
+                    }
+                });
+
                 ui.menu_button("Audio", |ui| {
                     ui.label("Volume:");
                     ui.add(egui::Slider::new(&mut self.volume, 0.0..=1.0).show_value(false));
@@ -1060,10 +1659,25 @@ impl eframe::App for SlainApp {
                     .show_value(false)
                     .trailing_fill(true);
 
-                if ui.add(slider).changed() {
+                let response = ui.add(slider);
+                if response.changed() {
                     self.seek((time_sec * 1000.0) as u64);
                 }
 
+                // Thumbnail preview of the hovered position
+                if let Some(pointer) = response.hover_pos() {
+                    let rect = response.rect;
+                    let fraction = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+                    let hover_ms = (fraction as f64 * self.duration_ms as f64) as u64;
+                    let texture = self.trickplay_texture(ctx, hover_ms);
+                    response.on_hover_ui_at_pointer(|ui| {
+                        if let Some(texture) = &texture {
+                            ui.image((texture.id(), texture.size_vec2()));
+                        }
+                        ui.label(format_time(hover_ms));
+                    });
+                }
+
                 ui.horizontal(|ui| {
                     let icon = if self.is_playing() { "⏸" } else { "▶" };
                     if ui.button(egui::RichText::new(icon).size(20.0)).clicked() {
@@ -1081,6 +1695,9 @@ impl eframe::App for SlainApp {
                         format_time(self.current_time_ms),
                         format_time(self.duration_ms)
                     ));
+                    if let Some(status) = &self.capture_status {
+                        ui.label(egui::RichText::new(status).color(egui::Color32::GRAY));
+                    }
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         if ui.button(egui::RichText::new("⛶").size(18.0)).clicked() {
@@ -1121,12 +1738,38 @@ impl eframe::App for SlainApp {
                     ui.label("F or Alt+Enter: Toggle fullscreen");
                     ui.label("Tab: Toggle OSD");
                     ui.label("Esc: Exit fullscreen");
+                    ui.label("S: Screenshot");
+                    ui.label("A / B: Set clip start / end");
                     ui.separator();
                     ui.label("Mouse");
                     ui.label("Drag & drop: Open media");
                 });
         }
 
+        if let Some(mut url) = self.url_prompt.take() {
+            let mut keep = true;
+            let mut submit = false;
+            egui::Window::new("Open URL")
+                .open(&mut keep)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("HLS playlist (.m3u8) or DASH manifest (.mpd)");
+                    let field = ui.text_edit_singleline(&mut url);
+                    field.request_focus();
+                    let valid = is_manifest_url(url.trim(), None);
+                    let entered =
+                        field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let open = ui.add_enabled(valid, egui::Button::new("Open")).clicked();
+                    submit = valid && (open || entered);
+                });
+            if submit {
+                self.open_url(url.trim().to_string());
+            } else if keep {
+                self.url_prompt = Some(url);
+            }
+        }
+
         // Handle drag & drop
         ctx.input(|i| {
             for file in &i.raw.dropped_files {
@@ -1136,32 +1779,45 @@ impl eframe::App for SlainApp {
             }
         });
 
-        // Keyboard shortcuts
+        // Keyboard shortcuts, unless a text field has focus
+        let typing = ctx.wants_keyboard_input();
         ctx.input(|i| {
-            if i.key_pressed(egui::Key::Space) {
+            let key_pressed = |key| !typing && i.key_pressed(key);
+            if key_pressed(egui::Key::Space) {
                 self.toggle_play();
             }
-            if i.key_pressed(egui::Key::Tab) {
+            if key_pressed(egui::Key::Tab) {
                 self.show_osd = !self.show_osd;
             }
-            if i.key_pressed(egui::Key::F) || (i.key_pressed(egui::Key::Enter) && i.modifiers.alt) {
+            if key_pressed(egui::Key::F) || (key_pressed(egui::Key::Enter) && i.modifiers.alt) {
                 self.toggle_fullscreen(ctx);
             }
-            if i.key_pressed(egui::Key::Escape) && self.is_fullscreen {
+            if key_pressed(egui::Key::Escape) && self.is_fullscreen {
                 self.toggle_fullscreen(ctx);
             }
-            if i.key_pressed(egui::Key::ArrowRight) {
+            if key_pressed(egui::Key::ArrowRight) {
                 self.seek(self.current_time_ms.saturating_add(5000));
             }
-            if i.key_pressed(egui::Key::ArrowLeft) {
+            if key_pressed(egui::Key::ArrowLeft) {
                 self.seek(self.current_time_ms.saturating_sub(5000));
             }
-            if i.key_pressed(egui::Key::ArrowUp) {
+            if key_pressed(egui::Key::ArrowUp) {
                 self.set_volume(self.volume + 0.05);
             }
-            if i.key_pressed(egui::Key::ArrowDown) {
+            if key_pressed(egui::Key::ArrowDown) {
                 self.set_volume(self.volume - 0.05);
             }
+            if self.is_ready() {
+                if key_pressed(egui::Key::S) {
+                    self.take_screenshot();
+                }
+                if key_pressed(egui::Key::A) {
+                    self.clip_start_ms = Some(self.current_time_ms);
+                }
+                if key_pressed(egui::Key::B) {
+                    self.clip_end_ms = Some(self.current_time_ms);
+                }
+            }
 
             // Mouse wheel: volume (default) or seek (with Shift)
             // Try both scroll_delta and smooth_scroll_delta for compatibility
@@ -1231,6 +1887,15 @@ fn mp4_codec_to_hwcodec(codec: &slain_core::mp4_demux::CodecId) -> Result<HwCode
     }
 }
 
+fn ts_codec_to_hwcodec(codec: TsStreamCodec) -> Result<HwCodec, String> {
+    match codec {
+        TsStreamCodec::H264 => Ok(HwCodec::H264),
+        TsStreamCodec::H265 => Ok(HwCodec::H265),
+        TsStreamCodec::MPEG2Video | TsStreamCodec::MPEG1Video => Ok(HwCodec::MPEG2),
+        other => Err(format!("Unsupported TS codec: {:?}", other)),
+    }
+}
+
 fn mkv_codec_to_hwcodec(codec_id: &str) -> Result<HwCodec, String> {
     match codec_id {
         "V_MPEG4/ISO/AVC" => Ok(HwCodec::H264),
@@ -1248,11 +1913,22 @@ struct HeadlessOptions {
     input: PathBuf,
     frames: u64,
     interpolate_alpha: Option<f32>,
+    /// Save the frame at this time (seconds) instead of playing
+    screenshot_at: Option<f64>,
+    /// Copy this A-B range (seconds) instead of playing
+    clip: Option<(f64, f64)>,
+    output: Option<PathBuf>,
+    pre_filter: bool,
+    quality: u8,
 }
 
 fn run_headless(args: &[String]) -> Result<()> {
     let options = parse_headless_args(args)?;
 
+    if options.screenshot_at.is_some() || options.clip.is_some() {
+        return run_headless_capture(&options);
+    }
+
     tracing::info!(
         "Headless playback starting: input={:?}, frames={}",
         options.input,
@@ -1306,10 +1982,74 @@ fn run_headless(args: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Screenshot or clip export requested on the command line
+fn run_headless_capture(options: &HeadlessOptions) -> Result<()> {
+    let output = options
+        .output
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("--screenshot or --clip needs --output <file>"))?;
+
+    if let Some(time_s) = options.screenshot_at {
+        let time_ms = (time_s * 1000.0) as u64;
+        let stage = if options.pre_filter {
+            ScreenshotStage::PreFilter
+        } else {
+            ScreenshotStage::PostFilter
+        };
+        let screenshot = ScreenshotOptions {
+            stage,
+            quality: options.quality,
+        };
+        capture::screenshot_file(&options.input, time_ms, &output, &screenshot)
+            .map_err(|e| anyhow::anyhow!(e))?;
+        println!(
+            "Saved screenshot at {} to {}",
+            format_time(time_ms),
+            output.display()
+        );
+    }
+
+    if let Some((start_s, end_s)) = options.clip {
+        let report = capture::export_clip(
+            &options.input,
+            &output,
+            (start_s * 1000.0) as u64,
+            (end_s * 1000.0) as u64,
+        )
+        .map_err(|e| anyhow::anyhow!(e))?;
+        println!(
+            "Exported {} - {} ({} packets, {} streams) to {}",
+            format_time(report.start_ms),
+            format_time(report.end_ms),
+            report.packets,
+            report.streams,
+            output.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_seconds(value: Option<&String>, flag: &str) -> Result<f64> {
+    let value = value.ok_or_else(|| anyhow::anyhow!("Missing value for {}", flag))?;
+    let seconds = value
+        .parse::<f64>()
+        .map_err(|e| anyhow::anyhow!("Invalid time {} for {}: {}", value, flag, e))?;
+    if seconds < 0.0 {
+        return Err(anyhow::anyhow!("{} must not be negative", flag));
+    }
+    Ok(seconds)
+}
+
 fn parse_headless_args(args: &[String]) -> Result<HeadlessOptions> {
     let mut input: Option<PathBuf> = None;
     let mut frames: u64 = 120;
     let mut interpolate_alpha: Option<f32> = None;
+    let mut screenshot_at: Option<f64> = None;
+    let mut clip: Option<(f64, f64)> = None;
+    let mut output: Option<PathBuf> = None;
+    let mut pre_filter = false;
+    let mut quality: u8 = ScreenshotOptions::default().quality;
 
     let mut i = 1;
     while i < args.len() {
@@ -1352,6 +2092,38 @@ fn parse_headless_args(args: &[String]) -> Result<HeadlessOptions> {
                 interpolate_alpha = Some(alpha);
                 i += 2;
             }
+            "--screenshot" => {
+                screenshot_at = Some(parse_seconds(args.get(i + 1), "--screenshot")?);
+                i += 2;
+            }
+            "--clip" => {
+                let start = parse_seconds(args.get(i + 1), "--clip")?;
+                let end = parse_seconds(args.get(i + 2), "--clip")?;
+                clip = Some((start, end));
+                i += 3;
+            }
+            "--output" | "-o" => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| anyhow::anyhow!("Missing value for --output"))?;
+                output = Some(PathBuf::from(value));
+                i += 2;
+            }
+            "--pre-filter" => {
+                pre_filter = true;
+                i += 1;
+            }
+            "--quality" => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| anyhow::anyhow!("Missing value for --quality"))?;
+                quality = value
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|q| (1..=100).contains(q))
+                    .ok_or_else(|| anyhow::anyhow!("Quality must be 1-100, got {}", value))?;
+                i += 2;
+            }
             "--help" | "-h" => {
                 print_headless_usage();
                 std::process::exit(0);
@@ -1366,17 +2138,28 @@ fn parse_headless_args(args: &[String]) -> Result<HeadlessOptions> {
         print_headless_usage();
         anyhow::anyhow!("Missing required --input for headless playback")
     })?;
+    // Both would write the one --output file
+    if screenshot_at.is_some() && clip.is_some() {
+        return Err(anyhow::anyhow!(
+            "--screenshot and --clip can't be combined; run them separately"
+        ));
+    }
 
     Ok(HeadlessOptions {
         input,
         frames,
         interpolate_alpha,
+        screenshot_at,
+        clip,
+        output,
+        pre_filter,
+        quality,
     })
 }
 
 fn print_headless_usage() {
     eprintln!(
-        "\nHeadless playback usage:\n  slain --headless --input <file> [--frames <n>] [--interpolate] [--interpolate-alpha <0-1>]\n"
+        "\nHeadless playback usage:\n  slain --headless --input <file> [--frames <n>] [--interpolate] [--interpolate-alpha <0-1>]\n  slain --headless --input <file> --screenshot <seconds> --output <image.png|jpg|webp> [--pre-filter] [--quality <1-100>]\n  slain --headless --input <file> --clip <start-seconds> <end-seconds> --output <clip.mkv|mp4|webm|ts>\n"
     );
 }
 
@@ -1453,6 +2236,7 @@ fn decode_mp4_headless(
                 let src_format = match decoded.format {
                     slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
                     slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
+                    slain_core::hw_decode::PixelFormat::P016 => PxFormat::P012,
                     _ => PxFormat::YUV420P,
                 };
 
@@ -1593,6 +2377,7 @@ fn decode_mkv_headless(
                 let src_format = match decoded.format {
                     slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
                     slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
+                    slain_core::hw_decode::PixelFormat::P016 => PxFormat::P012,
                     _ => PxFormat::YUV420P,
                 };
 
@@ -1659,6 +2444,190 @@ fn decode_mkv_headless(
     })
 }
 
+/// Tone mapper for a stream whose container/codec metadata says it is HDR
+fn hdr_tone_mapper(source: Option<HdrSourceInfo>) -> Option<ToneMapper> {
+    let source = source?;
+    tracing::info!(
+        "HDR source ({:?}, {:.0} nit peak): tone mapping to SDR",
+        source.transfer,
+        source.content_peak_nits()
+    );
+    Some(ToneMapper::new(source, ToneMapConfig::default()))
+}
+
+/// Convert a YUV picture to RGB24, tone mapping it when the stream is HDR
+fn yuv_to_rgb24(
+    src_format: PxFormat,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    tone_mapper: Option<&mut ToneMapper>,
+) -> Result<Vec<u8>, String> {
+    let (width, height) = (width as usize, height as usize);
+    let mut src_frame = PxVideoFrame::new(width, height, src_format);
+    src_frame.data = data;
+
+    if let Some(mapper) = tone_mapper {
+        return mapper.process_yuv(&src_frame);
+    }
+    let converter = PixelConverter::new(
+        src_format,
+        PxFormat::RGB24,
+        width,
+        height,
+        ColorSpace::BT709,
+    );
+    let mut dst_frame = PxVideoFrame::new(width, height, PxFormat::RGB24);
+    converter.convert(&src_frame, &mut dst_frame)?;
+    Ok(dst_frame.data)
+}
+
+/// Decoded pictures on their way to the display queue. YUV goes through the
+/// core `VideoPipeline` (auto deinterlacing, IVTC for telecined film) and
+/// its `FrameQueue`, so film comes out decimated and retimed, then to RGB.
+struct VideoStage {
+    pipeline: VideoPipeline,
+    queue: FrameQueue,
+    tone_mapper: Option<ToneMapper>,
+    fields: H264FieldParser,
+}
+
+impl VideoStage {
+    fn new(width: u32, height: u32, tone_mapper: Option<ToneMapper>) -> Self {
+        let pipeline = VideoPipeline::new(
+            FilterChainSpec::empty(None),
+            width,
+            height,
+            Some(DeinterlaceConfig::default()),
+        );
+        // Decoders hand pictures over in display order
+        let queue = FrameQueue::new(
+            QueueConfig {
+                reorder: false,
+                ..Default::default()
+            },
+            Arc::new(FramePool::new(width, height, QueuePixelFormat::NV12, 0)),
+        );
+        Self {
+            pipeline,
+            queue,
+            tone_mapper,
+            fields: H264FieldParser::new(),
+        }
+    }
+
+    /// Take the field order from an H.264 access unit or parameter sets
+    /// (Annex B), for pictures the deinterlacer finds interlaced
+    fn scan_h264(&mut self, annexb: &[u8]) {
+        if let Some(info) = self.fields.parse(annexb) {
+            self.pipeline
+                .set_stream_field_order(FieldOrder::from_stream_flags(
+                    info.interlaced,
+                    info.top_field_first,
+                ));
+        }
+    }
+
+    /// Run a decoded picture through the pipeline and queue whatever it
+    /// releases for display; returns the pts (ms) of the last frame queued
+    fn push(
+        &mut self,
+        shared: &PlaybackShared,
+        decoded: &mut DecodedFrame,
+        pts_us: i64,
+    ) -> Option<u64> {
+        let data = std::mem::take(&mut decoded.data);
+        let format = match decoded.format {
+            HwPixelFormat::NV12 => PipelinePixelFormat::Nv12,
+            HwPixelFormat::P010 => PipelinePixelFormat::P010,
+            HwPixelFormat::YUV420 => PipelinePixelFormat::I420,
+            // No pipeline layout for 16-bit; MSB-aligned, so read as P012
+            HwPixelFormat::P016 => {
+                let pts_ms = (pts_us / 1000).max(0) as u64;
+                return self
+                    .show(
+                        shared,
+                        PxFormat::P012,
+                        decoded.width,
+                        decoded.height,
+                        data,
+                        pts_ms,
+                    )
+                    .then_some(pts_ms);
+            }
+        };
+        self.pipeline.push_to_queue(
+            PipelineFrame {
+                data,
+                width: decoded.width,
+                height: decoded.height,
+                format,
+                pts_us,
+            },
+            &self.queue,
+        );
+        self.drain(shared)
+    }
+
+    /// Drop pictures held for deinterlacing/IVTC (seek)
+    fn reset(&mut self) {
+        self.pipeline.flush();
+        self.queue.flush();
+        if let Some(mapper) = self.tone_mapper.as_mut() {
+            mapper.reset();
+        }
+    }
+
+    /// Show the pictures still held at end of stream
+    fn finish(&mut self, shared: &PlaybackShared) -> Option<u64> {
+        self.pipeline.flush_to_queue(&self.queue);
+        self.drain(shared)
+    }
+
+    fn drain(&mut self, shared: &PlaybackShared) -> Option<u64> {
+        let mut last = None;
+        while let Some(mut frame) = self.queue.pop() {
+            let format = match frame.format {
+                QueuePixelFormat::NV12 => PxFormat::NV12,
+                QueuePixelFormat::P010 => PxFormat::P010,
+                _ => PxFormat::YUV420P,
+            };
+            let pts_ms = (frame.pts_us / 1000).max(0) as u64;
+            let data = std::mem::take(&mut frame.data);
+            if self.show(shared, format, frame.width, frame.height, data, pts_ms) {
+                last = Some(pts_ms);
+            }
+        }
+        last
+    }
+
+    fn show(
+        &mut self,
+        shared: &PlaybackShared,
+        format: PxFormat,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        pts_ms: u64,
+    ) -> bool {
+        match yuv_to_rgb24(format, width, height, data, self.tone_mapper.as_mut()) {
+            Ok(rgb) => {
+                shared.push_frame(RgbFrame {
+                    data: rgb,
+                    width,
+                    height,
+                    pts_ms,
+                });
+                true
+            }
+            Err(e) => {
+                tracing::warn!("Pixel convert error: {}", e);
+                false
+            }
+        }
+    }
+}
+
 /// Main decode loop - runs in separate thread
 fn decode_loop(shared: Arc<PlaybackShared>, path: PathBuf, width: u32, height: u32) {
     tracing::info!("Decode thread started for {:?}", path);
@@ -1773,11 +2742,19 @@ fn decode_mkv(
     };
 
     let mut decoder = HwDecoder::new(config)?;
+    let tone_mapper = hdr_tone_mapper(info.tracks.iter().find_map(|t| match t {
+        MkvTrack::Video(v) => HdrSourceInfo::from_mkv_track(v),
+        _ => None,
+    }));
+    let mut stage = VideoStage::new(vid_w, vid_h, tone_mapper);
     tracing::info!("MKV decoder created: backend={:?}", decoder.backend());
 
     // Feed SPS/PPS first if we have it
     let mut sps_pps_sent = false;
     if let Some(ref data) = sps_pps_data {
+        if codec == HwCodec::H264 {
+            stage.scan_h264(data);
+        }
         tracing::info!("Feeding SPS/PPS ({} bytes) to decoder", data.len());
         match decoder.decode(data, 0) {
             Ok(_) => {
@@ -1806,7 +2783,8 @@ fn decode_mkv(
             let target = shared.seek_target_ms.load(Ordering::SeqCst);
             let _ = demuxer.seek(target);
             shared.seek_requested.store(false, Ordering::SeqCst);
-            shared.frame_queue.lock().clear();
+            shared.clear_frames();
+            stage.reset();
             // Re-send SPS/PPS after seek
             if let Some(ref data) = sps_pps_data {
                 let _ = decoder.decode(data, 0);
@@ -1829,6 +2807,9 @@ fn decode_mkv(
                     // Convert from AVCC
                     avcc_to_annexb(&packet.data, nal_length_size)
                 };
+                if codec == HwCodec::H264 {
+                    stage.scan_h264(&decode_data);
+                }
 
                 if packets_fed <= 5 || packets_fed % 100 == 0 {
                     tracing::info!(
@@ -1841,49 +2822,12 @@ fn decode_mkv(
                 }
 
                 match decoder.decode(&decode_data, packet.pts_ms) {
-                    Ok(Some(decoded)) => {
-                        let src_format = match decoded.format {
-                            slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
-                            slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
-                            _ => PxFormat::YUV420P,
-                        };
-
-                        let converter = PixelConverter::new(
-                            src_format,
-                            PxFormat::RGB24,
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            ColorSpace::BT709,
-                        );
-
-                        let mut src_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            src_format,
-                        );
-                        src_frame.data = decoded.data;
-
-                        let mut dst_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            PxFormat::RGB24,
-                        );
-
-                        if let Err(e) = converter.convert(&src_frame, &mut dst_frame) {
-                            tracing::warn!("Pixel convert error: {}", e);
-                            continue;
+                    Ok(Some(mut decoded)) => {
+                        let pts_us = packet.pts_ms.max(0) * 1000;
+                        if let Some(pts_ms) = stage.push(&shared, &mut decoded, pts_us) {
+                            shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
                         }
 
-                        let pts_ms = packet.pts_ms.max(0) as u64;
-                        shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
-
-                        shared.frame_queue.lock().push_back(RgbFrame {
-                            data: dst_frame.data,
-                            width: decoded.width,
-                            height: decoded.height,
-                            pts_ms,
-                        });
-
                         frame_number += 1;
                         if frame_number <= 5 || frame_number % 100 == 0 {
                             tracing::info!(
@@ -1911,6 +2855,7 @@ fn decode_mkv(
             }
             None => {
                 tracing::info!("End of MKV file");
+                stage.finish(&shared);
                 break;
             }
         }
@@ -1964,6 +2909,11 @@ fn decode_avi(
     };
 
     let mut decoder = HwDecoder::new(config)?;
+    let mut stage = VideoStage::new(
+        vid_w,
+        vid_h,
+        hdr_tone_mapper(HdrSourceInfo::probe_file(path)),
+    );
     let mut frame_number: u64 = 0;
 
     while !shared.should_stop.load(Ordering::SeqCst) {
@@ -1981,7 +2931,8 @@ fn decode_avi(
             let target = shared.seek_target_ms.load(Ordering::SeqCst);
             let _ = demuxer.seek((target as i64) * 1000);
             shared.seek_requested.store(false, Ordering::SeqCst);
-            shared.frame_queue.lock().clear();
+            shared.clear_frames();
+            stage.reset();
         }
 
         match demuxer.read_packet() {
@@ -1989,54 +2940,20 @@ fn decode_avi(
                 if packet.stream_index != video_stream_index {
                     continue;
                 }
+                if is_annexb(&packet.data) {
+                    stage.scan_h264(&packet.data);
+                }
 
                 match decoder.decode(&packet.data, packet.pts) {
-                    Ok(Some(decoded)) => {
-                        let src_format = match decoded.format {
-                            slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
-                            slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
-                            _ => PxFormat::YUV420P,
-                        };
-
-                        let converter = PixelConverter::new(
-                            src_format,
-                            PxFormat::RGB24,
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            ColorSpace::BT709,
-                        );
-
-                        let mut src_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            src_format,
-                        );
-                        src_frame.data = decoded.data;
-
-                        let mut dst_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            PxFormat::RGB24,
-                        );
-
-                        if let Err(e) = converter.convert(&src_frame, &mut dst_frame) {
-                            tracing::warn!("Pixel convert error: {}", e);
-                            continue;
-                        }
-
-                        let pts_ms = if packet.pts > 0 {
-                            (packet.pts as u64) / 1000
+                    Ok(Some(mut decoded)) => {
+                        let pts_us = if packet.pts > 0 {
+                            packet.pts
                         } else {
-                            frame_number * 33
+                            frame_number as i64 * 33_000
                         };
-                        shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
-
-                        shared.frame_queue.lock().push_back(RgbFrame {
-                            data: dst_frame.data,
-                            width: decoded.width,
-                            height: decoded.height,
-                            pts_ms,
-                        });
+                        if let Some(pts_ms) = stage.push(&shared, &mut decoded, pts_us) {
+                            shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
+                        }
 
                         frame_number += 1;
                     }
@@ -2048,6 +2965,7 @@ fn decode_avi(
             }
             None => {
                 tracing::info!("End of AVI file");
+                stage.finish(&shared);
                 break;
             }
         }
@@ -2088,15 +3006,7 @@ fn decode_ts(
             })
             .ok_or_else(|| "No video stream found in TS".to_string())?;
 
-        let codec = match video_stream.codec {
-            TsStreamCodec::H264 => HwCodec::H264,
-            TsStreamCodec::H265 => HwCodec::H265,
-            TsStreamCodec::MPEG2Video => HwCodec::MPEG2,
-            TsStreamCodec::MPEG1Video => HwCodec::MPEG2,
-            other => {
-                return Err(format!("Unsupported TS codec: {:?}", other));
-            }
-        };
+        let codec = ts_codec_to_hwcodec(video_stream.codec)?;
         (video_stream.pid, codec)
     };
 
@@ -2110,6 +3020,11 @@ fn decode_ts(
     };
 
     let mut decoder = HwDecoder::new(config)?;
+    let mut stage = VideoStage::new(
+        width,
+        height,
+        hdr_tone_mapper(HdrSourceInfo::probe_file(path)),
+    );
     let mut frame_number: u64 = 0;
 
     while !shared.should_stop.load(Ordering::SeqCst) {
@@ -2125,7 +3040,8 @@ fn decode_ts(
 
         if shared.seek_requested.load(Ordering::SeqCst) {
             shared.seek_requested.store(false, Ordering::SeqCst);
-            shared.frame_queue.lock().clear();
+            shared.clear_frames();
+            stage.reset();
         }
 
         match demuxer.read_packet() {
@@ -2133,55 +3049,21 @@ fn decode_ts(
                 if packet.pid != video_pid {
                     continue;
                 }
+                if codec == HwCodec::H264 {
+                    stage.scan_h264(&packet.data);
+                }
 
                 let pts = packet.pts.unwrap_or(0);
                 match decoder.decode(&packet.data, pts) {
-                    Ok(Some(decoded)) => {
-                        let src_format = match decoded.format {
-                            slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
-                            slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
-                            _ => PxFormat::YUV420P,
-                        };
-
-                        let converter = PixelConverter::new(
-                            src_format,
-                            PxFormat::RGB24,
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            ColorSpace::BT709,
-                        );
-
-                        let mut src_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            src_format,
-                        );
-                        src_frame.data = decoded.data;
-
-                        let mut dst_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            PxFormat::RGB24,
-                        );
-
-                        if let Err(e) = converter.convert(&src_frame, &mut dst_frame) {
-                            tracing::warn!("Pixel convert error: {}", e);
-                            continue;
-                        }
-
-                        let pts_ms = if pts > 0 {
-                            (pts as u64) / 1000
+                    Ok(Some(mut decoded)) => {
+                        let pts_us = if pts > 0 {
+                            pts
                         } else {
-                            frame_number * 33
+                            frame_number as i64 * 33_000
                         };
-                        shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
-
-                        shared.frame_queue.lock().push_back(RgbFrame {
-                            data: dst_frame.data,
-                            width: decoded.width,
-                            height: decoded.height,
-                            pts_ms,
-                        });
+                        if let Some(pts_ms) = stage.push(&shared, &mut decoded, pts_us) {
+                            shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
+                        }
 
                         frame_number += 1;
                     }
@@ -2193,6 +3075,7 @@ fn decode_ts(
             }
             None => {
                 tracing::info!("End of TS file");
+                stage.finish(&shared);
                 break;
             }
         }
@@ -2254,6 +3137,19 @@ fn decode_mp4(
     };
 
     let mut decoder = HwDecoder::new(config)?;
+    let mut stage = VideoStage::new(
+        vid_w,
+        vid_h,
+        hdr_tone_mapper(HdrSourceInfo::probe_file(path)),
+    );
+    // avcC samples are rewritten to Annex B only for the field-order scan
+    let avcc = match codec {
+        HwCodec::H264 => parse_avcc_extradata(&video_info.extra_data),
+        _ => None,
+    };
+    if let Some((sps_pps, _)) = &avcc {
+        stage.scan_h264(sps_pps);
+    }
 
     tracing::info!(
         "MP4 decode ready: {}x{}, backend={:?}",
@@ -2279,7 +3175,8 @@ fn decode_mp4(
         if shared.seek_requested.load(Ordering::SeqCst) {
             let _target = shared.seek_target_ms.load(Ordering::SeqCst);
             shared.seek_requested.store(false, Ordering::SeqCst);
-            shared.frame_queue.lock().clear();
+            shared.clear_frames();
+            stage.reset();
         }
 
         match demuxer.read_packet() {
@@ -2297,55 +3194,22 @@ fn decode_mp4(
                         packet.pts
                     );
                 }
+                match &avcc {
+                    Some(_) if is_annexb(&packet.data) => stage.scan_h264(&packet.data),
+                    Some((_, size)) => stage.scan_h264(&avcc_to_annexb(&packet.data, *size)),
+                    None => {}
+                }
 
                 match decoder.decode(&packet.data, packet.pts) {
-                    Ok(Some(decoded)) => {
-                        let src_format = match decoded.format {
-                            slain_core::hw_decode::PixelFormat::NV12 => PxFormat::NV12,
-                            slain_core::hw_decode::PixelFormat::P010 => PxFormat::P010,
-                            _ => PxFormat::YUV420P,
-                        };
-
-                        let converter = PixelConverter::new(
-                            src_format,
-                            PxFormat::RGB24,
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            ColorSpace::BT709,
-                        );
-
-                        let mut src_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            src_format,
-                        );
-                        src_frame.data = decoded.data;
-
-                        let mut dst_frame = PxVideoFrame::new(
-                            decoded.width as usize,
-                            decoded.height as usize,
-                            PxFormat::RGB24,
-                        );
-
-                        if let Err(e) = converter.convert(&src_frame, &mut dst_frame) {
-                            tracing::warn!("Pixel convert error: {}", e);
-                            continue;
-                        }
-
-                        let pts_ms = if packet.pts > 0 {
-                            (packet.pts as u64) / 1000
+                    Ok(Some(mut decoded)) => {
+                        let pts_us = if packet.pts > 0 {
+                            packet.pts
                         } else {
-                            frame_number * 33
+                            frame_number as i64 * 33_000
                         };
-
-                        shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
-
-                        shared.frame_queue.lock().push_back(RgbFrame {
-                            data: dst_frame.data,
-                            width: decoded.width,
-                            height: decoded.height,
-                            pts_ms,
-                        });
+                        if let Some(pts_ms) = stage.push(&shared, &mut decoded, pts_us) {
+                            shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
+                        }
 
                         frame_number += 1;
                         if frame_number <= 5 || frame_number % 100 == 0 {
@@ -2374,6 +3238,7 @@ fn decode_mp4(
             }
             None => {
                 tracing::info!("End of file");
+                stage.finish(&shared);
                 break;
             }
         }
@@ -2381,3 +3246,196 @@ fn decode_mp4(
 
     Ok(())
 }
+
+/// Decoder for the rendition an adaptive stream is currently on
+struct StreamDecoder {
+    config: DecoderConfig,
+    decoder: HwDecoder,
+    stage: VideoStage,
+    /// SPS/PPS and NAL length size when samples are avcC (fMP4 H.264)
+    avcc: Option<(Vec<u8>, usize)>,
+}
+
+impl StreamDecoder {
+    fn new(config: DecoderConfig) -> Result<Self, String> {
+        let decoder = HwDecoder::new(config.clone())?;
+        let mut stage = VideoStage::new(config.width, config.height, None);
+        let avcc = match config.codec {
+            HwCodec::H264 => config.extra_data.as_deref().and_then(parse_avcc_extradata),
+            _ => None,
+        };
+        if let Some((sps_pps, _)) = &avcc {
+            stage.scan_h264(sps_pps);
+        }
+        tracing::info!(
+            "Stream decode: {:?} {}x{}, backend={:?}",
+            config.codec,
+            config.width,
+            config.height,
+            decoder.backend()
+        );
+        Ok(Self {
+            config,
+            decoder,
+            stage,
+            avcc,
+        })
+    }
+
+    /// Whether segments with `config` can go on through this decoder
+    fn fits(&self, config: &DecoderConfig) -> bool {
+        self.config.codec == config.codec
+            && (self.config.width, self.config.height) == (config.width, config.height)
+            && self.config.extra_data == config.extra_data
+    }
+}
+
+/// (pts µs, sample) pairs of one video stream
+type VideoSamples = Vec<(i64, Vec<u8>)>;
+
+/// Decoder setup and samples of the video in one HLS/DASH segment; `size`
+/// is used when the container doesn't say (TS)
+fn segment_video(
+    segment: &SegmentData,
+    size: (u32, u32),
+) -> Result<Option<(DecoderConfig, VideoSamples)>, String> {
+    let config = |codec, (width, height), extra_data| DecoderConfig {
+        codec,
+        width,
+        height,
+        preferred_backend: None,
+        allow_software_fallback: true,
+        extra_data,
+    };
+    let mut samples = Vec::new();
+    match segment.format {
+        SegmentFormat::Ts => {
+            let mut demuxer = TsDemuxer::new(std::io::Cursor::new(&segment.data[..]))?;
+            let video = demuxer
+                .info()
+                .streams
+                .iter()
+                .find_map(|stream| Some((stream.pid, ts_codec_to_hwcodec(stream.codec).ok()?)));
+            let Some((pid, codec)) = video else {
+                return Ok(None);
+            };
+            while let Some(packet) = demuxer.read_packet() {
+                if packet.pid == pid {
+                    samples.push((packet.pts.unwrap_or(0), packet.data));
+                }
+            }
+            Ok(Some((config(codec, size, None), samples)))
+        }
+        SegmentFormat::Fmp4 => {
+            let mut buffer = segment.init.clone().unwrap_or_default();
+            buffer.extend_from_slice(&segment.data);
+            let mut demuxer = Mp4Demuxer::new(std::io::Cursor::new(buffer))
+                .map_err(|e| format!("Segment demux init: {}", e))?;
+            let video = demuxer
+                .streams()
+                .into_iter()
+                .enumerate()
+                .find(|(_, s)| matches!(s.codec_type, slain_core::mp4_demux::CodecType::Video));
+            let Some((index, stream)) = video else {
+                return Ok(None);
+            };
+            let codec = mp4_codec_to_hwcodec(&stream.codec)?;
+            let size = demuxer
+                .video_info(index)
+                .map_or(size, |info| (info.width, info.height));
+            while let Some(packet) = demuxer.read_packet() {
+                if packet.stream_index == index as u32 {
+                    samples.push((packet.pts, packet.data));
+                }
+            }
+            Ok(Some((
+                config(codec, size, Some(stream.extra_data)),
+                samples,
+            )))
+        }
+    }
+}
+
+/// HLS/DASH decoding: `AdaptiveClient` segments, demuxed one at a time, into
+/// hw_decode. The decoder is rebuilt when a rendition switch changes codec,
+/// size or parameter sets.
+fn decode_adaptive(
+    shared: Arc<PlaybackShared>,
+    url: &str,
+    width: u32,
+    height: u32,
+) -> Result<(), String> {
+    let mut client = AdaptiveClient::open(url, AdaptiveConfig::default())?;
+    let mut active: Option<StreamDecoder> = None;
+
+    while !shared.should_stop.load(Ordering::SeqCst) {
+        let Some(segment) = client.next_segment()? else {
+            tracing::info!("End of stream");
+            break;
+        };
+        if segment.track != MediaKind::Main {
+            continue;
+        }
+        let size = client
+            .renditions()
+            .get(segment.rendition)
+            .and_then(|rendition| rendition.width.zip(rendition.height))
+            .unwrap_or((width, height));
+        let Some((config, samples)) = segment_video(&segment, size)? else {
+            continue;
+        };
+        let stream = match &mut active {
+            Some(stream) if stream.fits(&config) => stream,
+            _ => {
+                if let Some(mut previous) = active.take() {
+                    previous.stage.finish(&shared);
+                }
+                active.insert(StreamDecoder::new(config)?)
+            }
+        };
+
+        for (pts_us, data) in samples {
+            // Streams don't seek; a request only drops what is queued
+            loop {
+                if shared.should_stop.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+                if shared.seek_requested.swap(false, Ordering::SeqCst) {
+                    shared.clear_frames();
+                    stream.stage.reset();
+                }
+                if !shared.is_playing.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(10));
+                } else if shared.frame_queue.lock().len() >= 4 {
+                    thread::sleep(Duration::from_millis(5));
+                } else {
+                    break;
+                }
+            }
+
+            match &stream.avcc {
+                Some(_) if is_annexb(&data) => stream.stage.scan_h264(&data),
+                Some((_, size)) => stream.stage.scan_h264(&avcc_to_annexb(&data, *size)),
+                None if stream.config.codec == HwCodec::H264 => stream.stage.scan_h264(&data),
+                None => {}
+            }
+
+            match stream.decoder.decode(&data, pts_us) {
+                Ok(Some(mut decoded)) => {
+                    if let Some(pts_ms) = stream.stage.push(&shared, &mut decoded, pts_us) {
+                        shared.current_time_ms.store(pts_ms, Ordering::SeqCst);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Decode error: {}", e);
+                }
+            }
+        }
+    }
+
+    if let Some(mut stream) = active {
+        stream.stage.finish(&shared);
+    }
+    Ok(())
+}